@@ -11,6 +11,7 @@ pub use error::CertgenError;
 use rcgen::{CertificateParams, DistinguishedName, DnType, KeyPair};
 
 /// A generated certificate and private key pair.
+#[derive(Clone)]
 pub struct GeneratedCert {
     /// PEM-encoded certificate.
     pub cert_pem: String,
@@ -53,7 +54,7 @@ pub fn generate_certificate(hostname: &str) -> Result<GeneratedCert, CertgenErro
 
     let cert_pem = cert.pem();
     let key_pem = key_pair.serialize_pem();
-    let fingerprint = sha256_fingerprint(cert.der());
+    let fingerprint = fingerprint_from_der(cert.der());
 
     Ok(GeneratedCert {
         cert_pem,
@@ -74,11 +75,11 @@ pub fn fingerprint_from_pem(pem: &str) -> Result<String, CertgenError> {
     let der_bytes = base64::engine::general_purpose::STANDARD
         .decode(&der)
         .map_err(|e| CertgenError::Generation(format!("invalid PEM data: {e}")))?;
-    Ok(sha256_fingerprint(&der_bytes))
+    Ok(fingerprint_from_der(&der_bytes))
 }
 
-/// Compute SHA-256 fingerprint of DER-encoded certificate bytes.
-fn sha256_fingerprint(der: &[u8]) -> String {
+/// Compute the SHA-256 fingerprint of DER-encoded certificate bytes.
+pub fn fingerprint_from_der(der: &[u8]) -> String {
     use std::fmt::Write;
     let digest = ring::digest::digest(&ring::digest::SHA256, der);
     let mut fingerprint = String::from("SHA256:");
@@ -0,0 +1,438 @@
+//! Loopback demo (`cross-control demo`).
+//!
+//! Runs two daemons in this process, connected over QUIC on 127.0.0.1: this
+//! machine and a virtual screen to its right. Nothing is installed or
+//! configured and no second machine is needed. By default a scripted tour
+//! drives mock input through a crossing, some typing and scrolling, the
+//! release hotkey and a crossing back, printing what the virtual screen
+//! receives along the way. With `--live` (Linux) your own keyboard and mouse
+//! drive this machine's side instead.
+//!
+//! The setup below is also the smallest complete use of the library API:
+//! generate a certificate per machine, bind a `QuicTransport`, build a
+//! `Config` that places and pins the peer, hand `Daemon::new` capture and
+//! emulation backends, and `run()` it.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use cross_control_certgen::GeneratedCert;
+use cross_control_daemon::config::{
+    Config, DaemonConfig, IdentityConfig, PointerScale, ScreenConfig, ScreenMode,
+};
+use cross_control_daemon::{Daemon, DaemonEvent, DaemonStatus};
+use cross_control_input::mock::{MockCapture, MockEmulation, MockEmulationHandle};
+use cross_control_input::{InputCapture, InputEmulation};
+use cross_control_protocol::{QuicTransport, Transport};
+use cross_control_types::{
+    ButtonState, CapturedEvent, DeviceCapability, DeviceId, DeviceInfo, InputEvent, KeyCode,
+    MachineId, Position, ScrollAxis, ScrollDirection,
+};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+
+const LOCAL: &str = "this-machine";
+const VIRTUAL: &str = "virtual-screen";
+
+/// How long to wait for the daemons to connect or react to a step.
+const STEP_TIMEOUT: Duration = Duration::from_secs(5);
+
+const KEYBOARD: DeviceId = DeviceId(1);
+const MOUSE: DeviceId = DeviceId(2);
+
+/// Parameters of a demo run.
+pub struct DemoOptions {
+    /// Drive this machine's side with the real keyboard and mouse.
+    pub live: bool,
+    /// Pause between steps of the scripted tour.
+    pub pace: Duration,
+}
+
+/// One in-process daemon.
+struct Screen {
+    status: watch::Receiver<DaemonStatus>,
+    events: mpsc::Sender<DaemonEvent>,
+    task: JoinHandle<()>,
+}
+
+impl Screen {
+    fn spawn(
+        name: &'static str,
+        config: Config,
+        transport: QuicTransport,
+        capture: Box<dyn InputCapture>,
+        emulation: Box<dyn InputEmulation>,
+        devices: Vec<DeviceInfo>,
+    ) -> Self {
+        let mut daemon = Daemon::new(config, MachineId::new(), transport, capture, emulation);
+        // Devices announced to the peer, which creates a virtual twin of each.
+        daemon.set_local_devices(devices);
+        let status = daemon.status_receiver();
+        let events = daemon.event_sender();
+        let task = tokio::spawn(async move {
+            if let Err(e) = daemon.run().await {
+                eprintln!("{name}: daemon stopped: {e}");
+            }
+        });
+        Self {
+            status,
+            events,
+            task,
+        }
+    }
+
+    async fn wait_for(
+        &mut self,
+        what: &str,
+        pred: impl Fn(&DaemonStatus) -> bool,
+    ) -> anyhow::Result<()> {
+        let status = &mut self.status;
+        let reached = tokio::time::timeout(STEP_TIMEOUT, async {
+            loop {
+                if pred(&status.borrow_and_update()) {
+                    return true;
+                }
+                if status.changed().await.is_err() {
+                    return false;
+                }
+            }
+        })
+        .await;
+        match reached {
+            Ok(true) => Ok(()),
+            Ok(false) => bail!("daemon exited while waiting for {what}"),
+            Err(_) => bail!("{what} did not happen within {STEP_TIMEOUT:?}"),
+        }
+    }
+
+    async fn stop(self) {
+        let _ = self.events.send(DaemonEvent::Shutdown).await;
+        let _ = tokio::time::timeout(STEP_TIMEOUT, self.task).await;
+    }
+}
+
+/// Run the demo until the tour finishes or, with `--live`, until Ctrl+C.
+pub async fn run(options: DemoOptions) -> anyhow::Result<()> {
+    let local_cert = cross_control_certgen::generate_certificate("localhost")?;
+    let virtual_cert = cross_control_certgen::generate_certificate("localhost")?;
+    let loopback: SocketAddr = "127.0.0.1:0".parse()?;
+
+    // The virtual screen listens and never captures input of its own; what
+    // it would inject into its desktop is recorded by `MockEmulation`.
+    let virtual_transport =
+        QuicTransport::bind(loopback, &virtual_cert.cert_pem, &virtual_cert.key_pem)?;
+    let virtual_addr = virtual_transport.local_addr()?;
+    let (virtual_capture, _virtual_feed) = MockCapture::new();
+    let virtual_emulation = MockEmulation::new();
+    let received = virtual_emulation.handle();
+    let mut virtual_screen = Screen::spawn(
+        VIRTUAL,
+        config(VIRTUAL, LOCAL, Position::Left, None, &local_cert),
+        virtual_transport,
+        Box::new(virtual_capture),
+        Box::new(virtual_emulation),
+        Vec::new(),
+    );
+
+    // This machine dials the virtual screen, which sits to its right.
+    let local_transport = QuicTransport::bind(loopback, &local_cert.cert_pem, &local_cert.key_pem)?;
+    let local_config = config(
+        LOCAL,
+        VIRTUAL,
+        Position::Right,
+        Some(virtual_addr),
+        &virtual_cert,
+    );
+    let input = local_input(options.live)?;
+    let mut local = Screen::spawn(
+        LOCAL,
+        local_config,
+        local_transport,
+        input.capture,
+        Box::new(MockEmulation::new()),
+        input.devices,
+    );
+
+    let result = async {
+        local
+            .wait_for("connecting to the virtual screen", |s| s.session_count == 1)
+            .await?;
+        virtual_screen
+            .wait_for("accepting this machine", |s| s.session_count == 1)
+            .await?;
+        println!("{LOCAL} is connected to {VIRTUAL} (to its right) over QUIC on {virtual_addr}.");
+        match input.feed {
+            Some(feed) => tour(&mut local, &feed, &received, options.pace).await,
+            None => live(&mut local, &received).await,
+        }
+    }
+    .await;
+
+    local.stop().await;
+    virtual_screen.stop().await;
+    result
+}
+
+/// This machine's side of the demo.
+struct LocalInput {
+    capture: Box<dyn InputCapture>,
+    devices: Vec<DeviceInfo>,
+    /// Feeds the mock capture; `None` when real devices are captured.
+    feed: Option<mpsc::Sender<CapturedEvent>>,
+}
+
+/// Capture backend for this machine's side: the real devices with `--live`,
+/// otherwise a mock fed by the scripted tour.
+fn local_input(live: bool) -> anyhow::Result<LocalInput> {
+    if live {
+        #[cfg(feature = "linux")]
+        {
+            use cross_control_input::linux::capture::EvdevCapture;
+            use cross_control_input::DeviceFilter;
+
+            let devices: Vec<_> = EvdevCapture::enumerate_devices(&DeviceFilter::default())
+                .into_iter()
+                .map(|(_, info)| info)
+                .collect();
+            if devices.is_empty() {
+                bail!(
+                    "no readable input devices; `--live` needs access to /dev/input \
+                     (see `cross-control doctor`)"
+                );
+            }
+            return Ok(LocalInput {
+                capture: Box::new(EvdevCapture::new()),
+                devices,
+                feed: None,
+            });
+        }
+        #[cfg(not(feature = "linux"))]
+        bail!("`--live` needs the Linux input backend");
+    }
+    let (capture, feed) = MockCapture::new();
+    Ok(LocalInput {
+        capture: Box::new(capture),
+        devices: demo_devices(),
+        feed: Some(feed),
+    })
+}
+
+/// The scripted tour: cross, type, scroll, release with the hotkey, then
+/// cross again and come back by moving left.
+async fn tour(
+    local: &mut Screen,
+    feed: &mpsc::Sender<CapturedEvent>,
+    received: &MockEmulationHandle,
+    pace: Duration,
+) -> anyhow::Result<()> {
+    let send = |device_id, event| async move {
+        feed.send(CapturedEvent {
+            device_id,
+            timestamp_us: 0,
+            event,
+        })
+        .await
+        .context("this machine's daemon stopped reading input")
+    };
+    let show = || async {
+        tokio::time::sleep(pace).await;
+        print_received(received);
+    };
+
+    println!("\n1. The pointer starts in the middle of a 1920x1080 screen and moves right.");
+    for _ in 0..3 {
+        send(MOUSE, InputEvent::MouseMove { dx: 400, dy: 0 }).await?;
+    }
+    local
+        .wait_for("crossing into the virtual screen", |s| {
+            s.controlling.is_some()
+        })
+        .await?;
+    println!("   It hit the right edge: {LOCAL} now controls {VIRTUAL}.");
+    println!("   Local input is grabbed and forwarded instead of reaching this desktop.");
+    show().await;
+
+    println!("\n2. Scrolling down and typing \"hi\" reaches the virtual screen.");
+    send(
+        MOUSE,
+        InputEvent::Scroll {
+            axis: ScrollAxis::Vertical,
+            direction: ScrollDirection::Negative,
+            amount: -1.0,
+        },
+    )
+    .await?;
+    for code in [KeyCode::KeyH, KeyCode::KeyI] {
+        send(KEYBOARD, key(code, ButtonState::Pressed)).await?;
+        send(KEYBOARD, key(code, ButtonState::Released)).await?;
+    }
+    show().await;
+
+    println!("\n3. The release hotkey (LeftCtrl+LeftShift+Escape) brings control back.");
+    let hotkey = [KeyCode::LeftCtrl, KeyCode::LeftShift, KeyCode::Escape];
+    for code in hotkey {
+        send(KEYBOARD, key(code, ButtonState::Pressed)).await?;
+    }
+    local
+        .wait_for("releasing with the hotkey", |s| s.controlling.is_none())
+        .await?;
+    for code in hotkey.into_iter().rev() {
+        send(KEYBOARD, key(code, ButtonState::Released)).await?;
+    }
+    println!("   {LOCAL} is in control of its own desktop again.");
+    show().await;
+
+    println!("\n4. Crossing again, then moving left back over the shared edge.");
+    for _ in 0..3 {
+        send(MOUSE, InputEvent::MouseMove { dx: 400, dy: 0 }).await?;
+    }
+    local
+        .wait_for("crossing into the virtual screen", |s| {
+            s.controlling.is_some()
+        })
+        .await?;
+    println!("   Crossed into {VIRTUAL}, entering at its left edge.");
+    // Leaving over the entry edge only counts once the pointer has moved
+    // away from it, so a crossing never bounces straight back.
+    send(MOUSE, InputEvent::MouseMove { dx: 200, dy: 0 }).await?;
+    show().await;
+    for _ in 0..3 {
+        send(MOUSE, InputEvent::MouseMove { dx: -400, dy: 0 }).await?;
+    }
+    local
+        .wait_for("returning to this machine", |s| s.controlling.is_none())
+        .await?;
+    println!("   {VIRTUAL} reached its left edge and handed control back.");
+    show().await;
+
+    println!(
+        "\nThat's the whole loop. Run `cross-control demo --live` to drive it with your own \
+         keyboard and mouse, or `cross-control setup` to pair with a real machine."
+    );
+    Ok(())
+}
+
+/// Live mode: real input on this machine's side until Ctrl+C.
+async fn live(local: &mut Screen, received: &MockEmulationHandle) -> anyhow::Result<()> {
+    println!(
+        "\nMove your mouse to the right edge of the screen to cross into {VIRTUAL}.\n\
+         While there, your keyboard and mouse are grabbed and shown below instead of\n\
+         reaching this desktop. Move back left, or press LeftCtrl+LeftShift+Escape, to\n\
+         return. Press Ctrl+C (on this machine) to stop."
+    );
+    let mut poll = tokio::time::interval(Duration::from_millis(100));
+    let mut controlling = false;
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            changed = local.status.changed() => {
+                if changed.is_err() {
+                    bail!("{LOCAL}'s daemon stopped");
+                }
+                let now = local.status.borrow_and_update().controlling.is_some();
+                if now != controlling {
+                    controlling = now;
+                    if now {
+                        println!("-> crossed into {VIRTUAL}");
+                    } else {
+                        println!("<- back on {LOCAL}");
+                    }
+                }
+            }
+            _ = poll.tick() => print_received(received),
+        }
+    }
+    Ok(())
+}
+
+/// Print and clear what the virtual screen has injected, merging runs of
+/// pointer motion into one line.
+fn print_received(received: &MockEmulationHandle) {
+    let mut motion = (0, 0);
+    let flush = |motion: &mut (i32, i32)| {
+        if *motion != (0, 0) {
+            println!(
+                "   {VIRTUAL} <- pointer moved by ({}, {})",
+                motion.0, motion.1
+            );
+            *motion = (0, 0);
+        }
+    };
+    for injected in received.take_injected_events() {
+        match injected.event {
+            InputEvent::MouseMove { dx, dy } => {
+                motion.0 += dx;
+                motion.1 += dy;
+            }
+            InputEvent::Key { code, state } => {
+                flush(&mut motion);
+                println!("   {VIRTUAL} <- key {code:?} {state:?}");
+            }
+            other => {
+                flush(&mut motion);
+                println!("   {VIRTUAL} <- {other:?}");
+            }
+        }
+    }
+    flush(&mut motion);
+}
+
+fn key(code: KeyCode, state: ButtonState) -> InputEvent {
+    InputEvent::Key { code, state }
+}
+
+/// A config placing `peer` at `position` and pinning its certificate, the
+/// same as `cross-control setup` would write.
+fn config(
+    name: &str,
+    peer: &str,
+    position: Position,
+    address: Option<SocketAddr>,
+    peer_cert: &GeneratedCert,
+) -> Config {
+    Config {
+        daemon: DaemonConfig {
+            screen_width: 1920,
+            screen_height: 1080,
+            ..DaemonConfig::default()
+        },
+        identity: IdentityConfig {
+            name: name.to_string(),
+        },
+        screens: vec![ScreenConfig {
+            name: peer.to_string(),
+            group: None,
+            address: address.map(|a| a.to_string()),
+            position,
+            fingerprint: Some(peer_cert.fingerprint.clone()),
+            mode: ScreenMode::Control,
+            wake_on_enter: false,
+            locate_on_enter: false,
+            pointer_scale: PointerScale::default(),
+            share_devices: None,
+            clipboard: true,
+            wait_on_start: false,
+            commands: Vec::new(),
+            release_hotkey: None,
+        }],
+        ..Config::default()
+    }
+}
+
+fn demo_devices() -> Vec<DeviceInfo> {
+    vec![
+        DeviceInfo {
+            id: KEYBOARD,
+            name: "Demo Keyboard".to_string(),
+            capabilities: vec![DeviceCapability::Keyboard],
+            keys: Vec::new(),
+        },
+        DeviceInfo {
+            id: MOUSE,
+            name: "Demo Mouse".to_string(),
+            capabilities: vec![DeviceCapability::RelativeMouse, DeviceCapability::Scroll],
+            keys: Vec::new(),
+        },
+    ]
+}
@@ -0,0 +1,106 @@
+//! Folded-stack span timings for flamegraphs (`--features flame`).
+//!
+//! Each closed span writes one line of the form `root;child;leaf <self_us>`,
+//! where `self_us` is the time spent inside the span minus the time spent in
+//! its child spans. The output can be fed directly to `inferno-flamegraph`
+//! or `flamegraph.pl`.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tracing::span::{Attributes, Id};
+use tracing::Subscriber;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Busy-time bookkeeping stored in each span's extensions.
+#[derive(Default)]
+struct Timing {
+    entered_at: Option<Instant>,
+    busy: Duration,
+    children: Duration,
+}
+
+/// A [`Layer`] that writes folded stacks of span self-time to a file.
+pub struct FoldedLayer {
+    out: Mutex<BufWriter<File>>,
+}
+
+impl FoldedLayer {
+    /// Create the layer, truncating `path`.
+    pub fn create(path: &Path) -> std::io::Result<Self> {
+        Ok(Self {
+            out: Mutex::new(BufWriter::new(File::create(path)?)),
+        })
+    }
+}
+
+impl<S> Layer<S> for FoldedLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, _attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(Timing::default());
+        }
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(timing) = span.extensions_mut().get_mut::<Timing>() {
+                timing.entered_at = Some(Instant::now());
+            }
+        }
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(timing) = span.extensions_mut().get_mut::<Timing>() {
+                if let Some(entered_at) = timing.entered_at.take() {
+                    timing.busy += entered_at.elapsed();
+                }
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some((busy, children)) = span
+            .extensions()
+            .get::<Timing>()
+            .map(|t| (t.busy, t.children))
+        else {
+            return;
+        };
+
+        if let Some(parent) = span.parent() {
+            if let Some(timing) = parent.extensions_mut().get_mut::<Timing>() {
+                timing.children += busy;
+            }
+        }
+
+        let stack = span
+            .scope()
+            .from_root()
+            .map(|s| s.name())
+            .collect::<Vec<_>>()
+            .join(";");
+        let self_us = busy.saturating_sub(children).as_micros();
+        if let Ok(mut out) = self.out.lock() {
+            if self_us > 0 {
+                let _ = writeln!(out, "{stack} {self_us}");
+            }
+            // The global subscriber is never dropped, so flush whenever a
+            // root span completes.
+            if span.parent().is_none() {
+                let _ = out.flush();
+            }
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! One-JSON-object-per-line log output (`daemon.log_format = "json"`).
+//!
+//! Each line has `timestamp`, `level`, `target`, `span` (the innermost span
+//! name, if any) and `message`, plus the fields of every enclosing span and
+//! the event's own fields, all at the top level with keys in sorted order.
+//! Inner values win over outer ones. Daemon logs name peers with `peer` and
+//! machine IDs with `machine_id`, and the event loop's span adds the event
+//! `kind`.
+//!
+//! The layer is installed at startup but stays silent until [`enable`] is
+//! called, because the format is only known once the config is loaded.
+
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::time::{FormatTime, SystemTime};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Switch log output from text to JSON.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Whether JSON output is on.
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Recorded fields of a span, stored in its extensions.
+struct SpanFields(Map<String, Value>);
+
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{value:?}").into());
+    }
+}
+
+/// A [`Layer`] that writes events to stdout as JSON lines while enabled.
+pub struct JsonLayer;
+
+impl<S> Layer<S> for JsonLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if !enabled() {
+            return;
+        }
+        if let Some(span) = ctx.span(id) {
+            let mut fields = Map::new();
+            attrs.record(&mut JsonVisitor(&mut fields));
+            span.extensions_mut().insert(SpanFields(fields));
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(fields) = span.extensions_mut().get_mut::<SpanFields>() {
+                values.record(&mut JsonVisitor(&mut fields.0));
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if !enabled() {
+            return;
+        }
+        let mut line = Map::new();
+        let mut timestamp = String::new();
+        if SystemTime
+            .format_time(&mut Writer::new(&mut timestamp))
+            .is_ok()
+        {
+            line.insert("timestamp".to_string(), timestamp.into());
+        }
+        let meta = event.metadata();
+        line.insert("level".to_string(), meta.level().as_str().into());
+        line.insert("target".to_string(), meta.target().into());
+
+        let mut fields = Map::new();
+        if let Some(scope) = ctx.event_scope(event) {
+            let mut innermost = None;
+            for span in scope.from_root() {
+                innermost = Some(span.name());
+                if let Some(span_fields) = span.extensions().get::<SpanFields>() {
+                    fields.extend(span_fields.0.clone());
+                }
+            }
+            if let Some(name) = innermost {
+                line.insert("span".to_string(), name.into());
+            }
+        }
+        event.record(&mut JsonVisitor(&mut fields));
+        if let Some(message) = fields.remove("message") {
+            line.insert("message".to_string(), message);
+        }
+        line.extend(fields);
+
+        let mut out = std::io::stdout().lock();
+        let _ = serde_json::to_writer(&mut out, &line);
+        let _ = writeln!(out);
+    }
+}
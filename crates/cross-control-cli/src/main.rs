@@ -1,6 +1,16 @@
 //! cross-control CLI — user-facing binary for the cross-control virtual KVM.
 
 use clap::{Parser, Subcommand};
+use cross_control_types::SetupMessage;
+
+#[cfg(feature = "demo")]
+mod demo;
+#[cfg(feature = "flame")]
+mod flame;
+mod json_log;
+mod report;
+#[cfg(feature = "soak")]
+mod soak;
 
 #[derive(Parser)]
 #[command(
@@ -12,6 +22,16 @@ use clap::{Parser, Subcommand};
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// Run as a named instance with its own config, PID file, IPC socket
+    /// and machine name, e.g. to join a work and a personal mesh at once.
+    #[arg(long, global = true)]
+    instance: Option<String>,
+
+    /// Write folded-stack span timings to this file (for flamegraphs).
+    #[cfg(feature = "flame")]
+    #[arg(long, global = true)]
+    flame: Option<std::path::PathBuf>,
 }
 
 #[derive(Subcommand)]
@@ -29,6 +49,37 @@ enum Commands {
     /// Show daemon status and connected machines.
     Status,
 
+    /// List the input devices being shared and the virtual devices created
+    /// for each connected machine.
+    Devices,
+
+    /// Show recent state transitions and messages of a peer's session.
+    History {
+        /// Peer name or machine ID.
+        peer: String,
+    },
+
+    /// Show traffic counters and round-trip times of each session, e.g. to
+    /// compare settings without restarting the daemons.
+    Stats {
+        /// Only this peer (name or machine ID).
+        peer: Option<String>,
+        /// Start the counters again from zero after showing them.
+        #[arg(long, conflicts_with = "since")]
+        reset: bool,
+        /// Instead, show daily usage over this many days (e.g. `7d`, `4w`):
+        /// time spent controlling each peer, crossings and mean round trip.
+        #[arg(long, value_parser = parse_days)]
+        since: Option<u64>,
+    },
+
+    /// Check the configuration, routes to peers and firewall rules.
+    Doctor {
+        /// Path to configuration file.
+        #[arg(short, long)]
+        config: Option<String>,
+    },
+
     /// Generate a TLS certificate for this machine.
     GenerateCert {
         /// Output directory for certificate files.
@@ -36,33 +87,279 @@ enum Commands {
         output: String,
     },
 
-    /// Pair with a remote machine.
+    /// Guided first-time setup between this machine and one other.
+    ///
+    /// Run `cross-control setup` on one machine, then
+    /// `cross-control setup --with <address>` on the other. Both show a
+    /// session code to confirm, the initiator picks which side the other
+    /// screen is on, and both configs are written before the daemon starts.
+    Setup {
+        /// Address of the machine waiting in `cross-control setup` (host or host:port).
+        #[arg(long)]
+        with: Option<String>,
+
+        /// Path to configuration file.
+        #[arg(short, long)]
+        config: Option<String>,
+    },
+
+    /// Pair with a remote machine through the running daemon.
+    ///
+    /// The daemon dials the machine from its own endpoint, then this command
+    /// shows the peer's fingerprint and session code for confirmation.
     Pair {
         /// Address of the remote machine (host:port).
         address: String,
+        /// Which side of this screen the remote machine is on
+        /// (left/right/above/below). Asked for if the machine is new.
+        #[arg(long)]
+        position: Option<String>,
+    },
+
+    /// Show session verification codes for connected machines.
+    ///
+    /// Compare the code shown here with the one shown on the other machine;
+    /// if they match, both ends share the same encrypted session.
+    Verify,
+
+    /// Pause all cross-control activity without disconnecting peers.
+    ///
+    /// Releases any control, stops edge switching and suspends clipboard sync.
+    Pause,
+
+    /// Resume after `pause`.
+    Resume,
+
+    /// Ask a connected machine to let this one take control of it.
+    ///
+    /// The machine decides by its `daemon.control_requests` setting. If it
+    /// agrees, it gives up control of any peer and the cursor crosses into it.
+    RequestControl {
+        /// Screen name or machine ID.
+        screen: String,
+    },
+
+    /// Measure the round trip to a connected machine through both daemons.
+    ///
+    /// Unlike ICMP ping this goes over the peer connection, so a machine
+    /// that answers here is reachable by cross-control itself, and slow
+    /// answers point at the daemons or the session rather than the network.
+    Ping {
+        /// Screen name or machine ID.
+        screen: String,
+        /// Number of pings to send.
+        #[arg(short, long, default_value_t = 5)]
+        count: u32,
+        /// Milliseconds between pings.
+        #[arg(long, default_value_t = 1000)]
+        interval: u64,
+        /// Milliseconds to wait for each answer before counting it lost.
+        #[arg(long, default_value_t = 2000)]
+        timeout: u64,
+    },
+
+    /// Move the cursor to a pixel position on a screen.
+    ///
+    /// The screen is this machine's name or a connected peer's name or
+    /// machine ID. Control of a peer is taken first if needed.
+    Teleport {
+        /// Screen name or machine ID.
+        screen: String,
+        /// Horizontal position in pixels from the left edge.
+        x: u32,
+        /// Vertical position in pixels from the top edge.
+        y: u32,
+    },
+
+    /// Type text into a connected machine as key presses.
+    ///
+    /// For prompts that don't take a paste, such as a login screen. Only
+    /// characters on a US keyboard can be typed, and the remote machine
+    /// should use a US layout too.
+    Type {
+        /// Peer name or machine ID.
+        #[arg(long)]
+        screen: String,
+        /// The text to type; a newline presses Enter.
+        text: String,
+    },
+
+    /// Tell connected machines which keyboard layout this one types with.
+    ///
+    /// For layout switches the daemon can't see itself, such as the input
+    /// source switcher of a desktop; call it from the desktop's
+    /// layout-change hook.
+    KeyboardLayout {
+        /// XKB layout name, e.g. `gr`.
+        layout: String,
+        /// XKB variant, e.g. `dvorak`.
+        #[arg(long, default_value = "")]
+        variant: String,
+    },
+
+    /// Ask connected machines to run a command from their `[commands]`
+    /// allowlist, such as `lock-screen`.
+    ///
+    /// Each machine runs only commands its own config defines and allows
+    /// this machine to run.
+    Run {
+        /// Command name, as defined under `[commands]` on the peer.
+        command: String,
+        /// Peer names or machine IDs.
+        #[arg(required_unless_present = "all")]
+        peers: Vec<String>,
+        /// Send to every connected peer.
+        #[arg(long, conflicts_with = "peers")]
+        all: bool,
+    },
+
+    /// Lock this machine and every trusted, connected peer, reporting which
+    /// ones did.
+    ///
+    /// Runs the `lock-screen` command (or `suspend`) from each machine's
+    /// `[commands]`; this machine goes last.
+    LockAll {
+        /// Run `suspend` instead of `lock-screen`.
+        #[arg(long)]
+        suspend: bool,
+    },
+
+    /// Collect logs, redacted config, status and version details into a
+    /// `.tar.gz` to attach to a bug report.
+    Report {
+        /// Path to configuration file.
+        #[arg(short, long)]
+        config: Option<String>,
+        /// Where to write the bundle (default: a timestamped file in the
+        /// current directory).
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Don't ask before collecting.
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Try cross-control on this machine alone: a virtual second screen on
+    /// loopback, driven by a scripted tour or, with `--live`, by your own
+    /// keyboard and mouse.
+    #[cfg(feature = "demo")]
+    Demo {
+        /// Drive the demo with the real keyboard and mouse (Linux; needs
+        /// access to /dev/input).
+        #[arg(long)]
+        live: bool,
+        /// Milliseconds to pause between steps of the scripted tour.
+        #[arg(long, default_value_t = 800)]
+        pace: u64,
+    },
+
+    /// Manage trusted peers.
+    Peers {
+        #[command(subcommand)]
+        command: PeersCommand,
+    },
+
+    /// Inspect the screen layout.
+    Layout {
+        #[command(subcommand)]
+        command: LayoutCommand,
+    },
+
+    /// Manage configuration on peers.
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+
+    /// Switch between layout profiles.
+    Profile {
+        #[command(subcommand)]
+        command: ProfileCommand,
+    },
+
+    /// Run two in-process daemons with mock backends under randomized
+    /// traffic, checking for leaked sessions, stuck control and memory growth.
+    #[cfg(feature = "soak")]
+    #[command(hide = true)]
+    Soak {
+        /// How long to run, in seconds.
+        #[arg(long, default_value_t = 3600)]
+        duration: u64,
+
+        /// Seed for the traffic generator; printed on failure for replay.
+        #[arg(long)]
+        seed: Option<u64>,
+
+        /// Seconds between progress reports and memory checks.
+        #[arg(long, default_value_t = 60)]
+        report_every: u64,
+
+        /// Allowed resident memory growth over the first report, in MiB.
+        #[arg(long, default_value_t = 64)]
+        max_rss_growth_mb: u64,
+    },
+}
+
+#[derive(Subcommand)]
+enum PeersCommand {
+    /// Revoke a peer: close its session and refuse its certificate from now on.
+    Ban {
+        /// Peer name or machine ID.
+        peer: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum LayoutCommand {
+    /// Suggest `[[screens]]` position fixes based on how edges are used.
+    Suggest,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Push `[[screens]]` and `[[screen_adjacency]]` from a TOML file to a
+    /// connected peer. The peer must set `allow_remote_config = true` and pin
+    /// this machine's fingerprint.
+    Push {
+        /// Peer name or machine ID.
+        peer: String,
+        /// TOML file with the peer's layout (other sections are ignored).
+        file: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProfileCommand {
+    /// Switch the running daemon to a `[profiles.<name>]` layout, closing
+    /// sessions with peers it doesn't list. Use `default` for the top-level
+    /// `[[screens]]`.
+    Switch {
+        /// Profile name.
+        name: String,
     },
 }
 
 #[tokio::main]
+#[allow(clippy::too_many_lines)]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
-        )
-        .init();
-
     let cli = Cli::parse();
+    init_logging(&cli)?;
+    if let Some(instance) = &cli.instance {
+        cross_control_daemon::setup::set_instance(instance)?;
+    }
 
     match cli.command {
         Commands::Start { config } => {
-            start_daemon(config.as_deref()).await?;
+            start_daemon(config.as_deref(), None).await?;
         }
         Commands::Stop => {
             stop_daemon()?;
         }
         Commands::Status => {
-            show_status()?;
+            show_status().await?;
+        }
+        Commands::Doctor { config } => {
+            doctor(config.as_deref())?;
         }
         Commands::GenerateCert { output } => {
             let hostname = hostname::get()
@@ -84,36 +381,211 @@ async fn main() -> anyhow::Result<()> {
             println!("Private key: {key_path}");
             println!("Fingerprint: {}", cert.fingerprint);
         }
-        Commands::Pair { address } => {
-            tracing::info!(address = %address, "pairing with remote machine");
-            // TODO: Phase 2 — connect, exchange fingerprints, pin
-            eprintln!("cross-control pairing not yet implemented (Phase 2)");
+        Commands::Setup { with, config } => {
+            guided_setup(with.as_deref(), config.as_deref()).await?;
+        }
+        Commands::Pair { address, position } => {
+            let position = position
+                .map(|p| parse_position(&p).ok_or_else(|| anyhow::anyhow!("invalid position: {p}")))
+                .transpose()?;
+            pair(&address, position).await?;
+        }
+        Commands::Verify => {
+            show_session_codes().await?;
+        }
+        Commands::Devices => {
+            show_devices().await?;
+        }
+        Commands::History { peer } => {
+            show_history(peer).await?;
+        }
+        Commands::Stats { peer, reset, since } => {
+            if let Some(days) = since {
+                show_trends(peer, days).await?;
+            } else {
+                show_stats(peer, reset).await?;
+            }
+        }
+        Commands::Pause => {
+            set_paused(true).await?;
         }
+        Commands::Resume => {
+            set_paused(false).await?;
+        }
+        Commands::RequestControl { screen } => {
+            request_control(screen).await?;
+        }
+        Commands::Ping {
+            screen,
+            count,
+            interval,
+            timeout,
+        } => {
+            ping(screen, count, interval, timeout).await?;
+        }
+        Commands::Teleport { screen, x, y } => {
+            teleport(screen, x, y).await?;
+        }
+        Commands::Type { screen, text } => {
+            type_text(screen, text).await?;
+        }
+        Commands::Run {
+            command,
+            peers,
+            all,
+        } => {
+            run_command(&command, peers, all).await?;
+        }
+        Commands::LockAll { suspend } => {
+            lock_all(if suspend { "suspend" } else { "lock-screen" }).await?;
+        }
+        Commands::Report {
+            config,
+            output,
+            yes,
+        } => {
+            create_report(config.as_deref(), output.as_deref(), yes).await?;
+        }
+        #[cfg(feature = "demo")]
+        Commands::Demo { live, pace } => {
+            demo::run(demo::DemoOptions {
+                live,
+                pace: std::time::Duration::from_millis(pace),
+            })
+            .await?;
+        }
+        Commands::Peers {
+            command: PeersCommand::Ban { peer },
+        } => {
+            ban_peer(&peer).await?;
+        }
+        Commands::Layout {
+            command: LayoutCommand::Suggest,
+        } => {
+            suggest_layout().await?;
+        }
+        Commands::Config {
+            command: ConfigCommand::Push { peer, file },
+        } => {
+            push_config(&peer, &file).await?;
+        }
+        Commands::Profile {
+            command: ProfileCommand::Switch { name },
+        } => {
+            switch_profile(&name).await?;
+        }
+        Commands::KeyboardLayout { layout, variant } => {
+            set_keyboard_layout(layout, variant).await?;
+        }
+        #[cfg(feature = "soak")]
+        Commands::Soak {
+            duration,
+            seed,
+            report_every,
+            max_rss_growth_mb,
+        } => {
+            let seed = seed.unwrap_or_else(|| {
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map_or(1, |d| d.as_secs())
+            });
+            soak::run(soak::SoakOptions {
+                duration: std::time::Duration::from_secs(duration),
+                seed,
+                report_every: std::time::Duration::from_secs(report_every.max(1)),
+                max_rss_growth_kb: max_rss_growth_mb * 1024,
+            })
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg_attr(
+    not(feature = "flame"),
+    allow(unused_variables, clippy::unnecessary_wraps)
+)]
+fn init_logging(cli: &Cli) -> anyhow::Result<()> {
+    use tracing_subscriber::filter::{filter_fn, FilterExt};
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::util::SubscriberInitExt;
+    use tracing_subscriber::Layer;
+
+    // The demo narrates on stdout; daemon logs would drown it out.
+    #[cfg(feature = "demo")]
+    let level = if matches!(cli.command, Commands::Demo { .. }) {
+        "error"
+    } else {
+        "info"
+    };
+    #[cfg(not(feature = "demo"))]
+    let level = "info";
+    let env_filter = || {
+        tracing_subscriber::EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(level))
+    };
+    // Both formats are installed; the config picks one once it is loaded.
+    let text = env_filter().and(filter_fn(|_| !json_log::enabled()));
+    let registry = tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_filter(text))
+        .with(json_log::JsonLayer.with_filter(env_filter()));
+
+    #[cfg(feature = "flame")]
+    if let Some(path) = &cli.flame {
+        // Hot-path spans are trace level; record them regardless of RUST_LOG.
+        let layer = flame::FoldedLayer::create(path)?
+            .with_filter(tracing_subscriber::filter::LevelFilter::TRACE);
+        registry.with(layer).init();
+        return Ok(());
     }
 
+    registry.init();
     Ok(())
 }
 
-async fn start_daemon(config_path: Option<&str>) -> anyhow::Result<()> {
+/// Run the daemon, reusing `transport` if one is already bound to the
+/// configured address.
+async fn start_daemon(
+    config_path: Option<&str>,
+    transport: Option<cross_control_protocol::QuicTransport>,
+) -> anyhow::Result<()> {
+    use cross_control_daemon::config::LogFormat;
     use cross_control_daemon::{daemon::Daemon, setup};
-    use std::net::SocketAddr;
+    use cross_control_protocol::Transport;
 
     let config = setup::load_config(config_path)?;
+    if config.daemon.log_format == LogFormat::Json {
+        json_log::enable();
+    }
     let config_dir = setup::config_dir();
     let (cert_pem, key_pem) = setup::load_or_generate_certs(&config_dir)?;
     let machine_id = setup::load_or_create_machine_id(&config_dir)?;
 
     // Write PID file
+    setup::create_runtime_dir()?;
     let pid_path = setup::pid_file_path();
     std::fs::write(&pid_path, std::process::id().to_string())?;
     tracing::info!(pid_file = %pid_path.display(), "wrote PID file");
 
     // Bind transport
-    let bind_addr: SocketAddr = format!("{}:{}", config.daemon.bind, config.daemon.port)
-        .parse()
-        .map_err(|e| anyhow::anyhow!("invalid bind address: {e}"))?;
+    let bind_addr = setup::listen_address(&config, config.daemon.port)?;
 
-    let transport = cross_control_protocol::QuicTransport::bind(bind_addr, &cert_pem, &key_pem)?;
+    let transport = match transport {
+        Some(transport) => transport,
+        None => cross_control_protocol::QuicTransport::bind(bind_addr, &cert_pem, &key_pem)?,
+    };
+    for fingerprint in setup::load_denylist(&config_dir)? {
+        transport.denylist().insert(fingerprint);
+    }
+    // With `daemon.port = 0` only the bound address says where we listen.
+    let bind_addr = transport.local_addr()?;
+    let info_path = setup::runtime_info_path();
+    setup::RuntimeInfo {
+        pid: std::process::id(),
+        address: bind_addr,
+    }
+    .write(&info_path)?;
 
     // Create input backends
     #[cfg(feature = "linux")]
@@ -121,12 +593,13 @@ async fn start_daemon(config_path: Option<&str>) -> anyhow::Result<()> {
         use cross_control_input::linux::capture::EvdevCapture;
         use cross_control_input::linux::emulation::UinputEmulation;
 
-        let capture = EvdevCapture::new();
-        let emulation = UinputEmulation::new();
-        let devices: Vec<_> = EvdevCapture::enumerate_devices()
+        let filter = config.input.device_filter();
+        let devices: Vec<_> = EvdevCapture::enumerate_devices(&filter)
             .into_iter()
             .map(|(_, info)| info)
             .collect();
+        let capture = EvdevCapture::with_filter(filter);
+        let emulation = UinputEmulation::new();
         (
             Box::new(capture) as Box<dyn cross_control_input::InputCapture>,
             Box::new(emulation) as Box<dyn cross_control_input::InputEmulation>,
@@ -144,32 +617,35 @@ async fn start_daemon(config_path: Option<&str>) -> anyhow::Result<()> {
     }
 
     // Create and run daemon
+    let discovery = setup::discovery_backend(&config, &cert_pem, &key_pem);
+    let clipboard = setup::clipboard_backend(&config);
     let mut daemon = Daemon::new(config, machine_id, transport, capture, emulation);
     daemon.set_local_devices(local_devices);
+    daemon.set_config_path(setup::config_file_path(config_path));
+    daemon.set_denylist_dir(config_dir.clone());
+    daemon.set_metrics_path(setup::metrics_path());
+    if let Some(discovery) = discovery {
+        daemon.set_discovery(discovery);
+    }
+    if let Some(clipboard) = clipboard {
+        daemon.set_clipboard(clipboard);
+    }
 
     let event_tx = daemon.event_sender();
 
-    // Signal handling
-    let shutdown_tx = event_tx.clone();
-    tokio::spawn(async move {
-        let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
-            .expect("failed to register SIGTERM handler");
-        let mut sigint = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::interrupt())
-            .expect("failed to register SIGINT handler");
-
-        tokio::select! {
-            _ = sigterm.recv() => {
-                tracing::info!("received SIGTERM");
-            }
-            _ = sigint.recv() => {
-                tracing::info!("received SIGINT");
+    // IPC socket for CLI commands
+    let socket_path = setup::socket_path();
+    {
+        let socket_path = socket_path.clone();
+        let event_tx = event_tx.clone();
+        tokio::spawn(async move {
+            if let Err(e) = cross_control_daemon::ipc::serve(&socket_path, event_tx).await {
+                tracing::warn!(error = %e, "IPC server stopped");
             }
-        }
+        });
+    }
 
-        let _ = shutdown_tx
-            .send(cross_control_daemon::daemon::DaemonEvent::Shutdown)
-            .await;
-    });
+    spawn_signal_handler(event_tx.clone());
 
     tracing::info!(
         machine_id = %machine_id,
@@ -179,14 +655,164 @@ async fn start_daemon(config_path: Option<&str>) -> anyhow::Result<()> {
 
     daemon.run().await?;
 
-    // Clean up PID file
+    // Clean up PID file, runtime info and IPC socket
     let _ = std::fs::remove_file(&pid_path);
+    let _ = std::fs::remove_file(&info_path);
+    let _ = std::fs::remove_file(&socket_path);
     tracing::info!("daemon stopped");
 
     Ok(())
 }
 
-fn show_status() -> anyhow::Result<()> {
+/// Ask the daemon to shut down on SIGTERM or SIGINT. A second signal exits
+/// immediately: the kernel drops our device grabs when the process exits,
+/// and the grab marker left behind makes the next start release them too.
+fn spawn_signal_handler(
+    event_tx: tokio::sync::mpsc::Sender<cross_control_daemon::daemon::DaemonEvent>,
+) {
+    tokio::spawn(async move {
+        let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to register SIGTERM handler");
+        let mut sigint = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::interrupt())
+            .expect("failed to register SIGINT handler");
+
+        for signals in 0.. {
+            tokio::select! {
+                _ = sigterm.recv() => {
+                    tracing::info!("received SIGTERM");
+                }
+                _ = sigint.recv() => {
+                    tracing::info!("received SIGINT");
+                }
+            }
+            if signals > 0 {
+                tracing::warn!("second signal, exiting without a clean shutdown");
+                std::process::exit(1);
+            }
+            let _ = event_tx.try_send(cross_control_daemon::daemon::DaemonEvent::Shutdown);
+        }
+    });
+}
+
+fn doctor(config_path: Option<&str>) -> anyhow::Result<()> {
+    use cross_control_daemon::config::DiscoveryMethod;
+    use cross_control_daemon::firewall::{self, PortStatus};
+    use cross_control_daemon::{network, setup};
+
+    let config = setup::load_config(config_path)?;
+    println!(
+        "Config:   {}",
+        setup::config_file_path(config_path).display()
+    );
+
+    let listen = setup::listen_address(&config, config.daemon.port)?;
+    println!("Listen:   {listen} (bind = {:?})", config.daemon.bind);
+    let routes = network::peer_routes(&config);
+    for route in &routes {
+        match (route.address, route.local) {
+            (Some(address), Some(local)) => {
+                println!("  {:<20} {address} via {local}", route.name);
+                if !listen.ip().is_unspecified() && listen.ip() != local {
+                    println!(
+                        "  WARNING: {} is not reachable from {}",
+                        route.name,
+                        listen.ip()
+                    );
+                }
+            }
+            (Some(address), None) => println!("  {:<20} {address}: no route", route.name),
+            (None, _) => println!("  {:<20} address does not resolve", route.name),
+        }
+    }
+    if config.daemon.bind != network::AUTO_BIND {
+        if let Some(ip) = network::auto_listen_address(&routes) {
+            println!("  All peers are reached through {ip}; bind = \"auto\" listens only there.");
+        }
+    }
+
+    // An ephemeral port is only known while the daemon runs.
+    let running = setup::RuntimeInfo::load(&setup::runtime_info_path());
+    let mut ports = Vec::new();
+    match (config.daemon.port, running) {
+        (0, Some(info)) => {
+            println!("  Running daemon is bound to {}", info.address);
+            ports.push(info.address.port());
+        }
+        (0, None) => println!("  daemon.port = 0: the port is chosen when the daemon starts"),
+        (port, _) => ports.push(port),
+    }
+    if config.daemon.discovery && config.daemon.discovery_method == DiscoveryMethod::Broadcast {
+        ports.push(config.daemon.broadcast_port());
+    }
+    let mut checked = false;
+    for port in ports {
+        for check in firewall::check_port(port) {
+            checked = true;
+            match check.status {
+                PortStatus::Open => println!("Firewall: {} allows UDP {port}", check.firewall),
+                PortStatus::Blocked => {
+                    println!(
+                        "Firewall: {} blocks UDP {port}. Open it with:",
+                        check.firewall
+                    );
+                    println!("    {}", check.firewall.allow_rule(port));
+                }
+                PortStatus::Unknown(reason) => {
+                    println!(
+                        "Firewall: {} is active but UDP {port} can't be checked: {reason}",
+                        check.firewall
+                    );
+                    println!("    If it is blocked: {}", check.firewall.allow_rule(port));
+                }
+            }
+        }
+    }
+    if !checked {
+        println!("Firewall: no active ufw, firewalld or Windows Firewall found");
+    }
+    Ok(())
+}
+
+/// Warn about each peer on another version, e.g. "peer desk-pc runs 0.3.1,
+/// you run 0.4.0 — control requests disabled".
+fn print_version_skew(skews: &[cross_control_daemon::ipc::VersionSkewReport], ours: &str) {
+    use cross_control_types::PROTOCOL_VERSION;
+
+    for skew in skews {
+        let versions = match &skew.version {
+            Some(theirs) => format!("runs {theirs}, you run {ours}"),
+            None => format!(
+                "runs protocol {}, you run {PROTOCOL_VERSION}",
+                skew.protocol
+            ),
+        };
+        if skew.disabled.is_empty() {
+            println!("WARNING: peer {} {versions}", skew.peer);
+        } else {
+            println!(
+                "WARNING: peer {} {versions} — {} disabled",
+                skew.peer,
+                skew.disabled.join(", ")
+            );
+        }
+    }
+}
+
+/// Where input to the controlled peer `name` is landing.
+fn print_remote_focus(name: &str, focus: cross_control_types::FocusHint) {
+    use cross_control_types::ScreenState;
+
+    println!("Remote:  cursor at {},{}", focus.cursor_x, focus.cursor_y);
+    match focus.screen {
+        ScreenState::Active => {}
+        ScreenState::Locked => println!("WARNING: {name} is locked; input goes to its lock screen"),
+        ScreenState::Asleep => println!("WARNING: {name}'s displays are asleep"),
+    }
+}
+
+#[allow(clippy::too_many_lines)]
+async fn show_status() -> anyhow::Result<()> {
+    use cross_control_daemon::ipc::{InputGrab, IpcRequest, IpcResponse};
     use cross_control_daemon::setup;
 
     let pid_path = setup::pid_file_path();
@@ -212,6 +838,94 @@ fn show_status() -> anyhow::Result<()> {
     if alive {
         println!("Status:  running");
         println!("PID:     {pid}");
+        if let Some(info) = setup::RuntimeInfo::load(&setup::runtime_info_path()) {
+            println!("Listen:  {}", info.address);
+        }
+        if let Ok(IpcResponse::Status(report)) = daemon_request(&IpcRequest::Status).await {
+            println!("Version: {} ({})", report.version, report.commit);
+            println!(
+                "Uptime:  {} (since {} UTC)",
+                format_uptime(report.uptime_secs),
+                format_utc(report.started_at)
+            );
+            println!("Paused:  {}", if report.paused { "yes" } else { "no" });
+            if report.input_grab == InputGrab::Retrying {
+                println!("WARNING: failed to grab local input, retrying");
+            }
+            if !report.waiting_on.is_empty() {
+                println!("Startup: waiting for {}", report.waiting_on.join(", "));
+            }
+            if report.dry_run {
+                println!("Dry run: input from peers is logged, not injected");
+            }
+            if report.private_input {
+                println!("Private: keyboard input is not shared");
+            }
+            println!("Peers:   {}", report.session_count);
+            if let Some(layout) = &report.keyboard_layout {
+                println!("Keyboard: {layout}");
+            }
+            for peer in &report.peer_keyboard_layouts {
+                println!("Keyboard: {} types with {}", peer.peer, peer.layout);
+            }
+            if let Some(profile) = report.profile {
+                println!("Profile: {profile}");
+            }
+            for conflict in report.conflicts {
+                println!("WARNING: identity conflict: {conflict}");
+            }
+            print_version_skew(&report.version_skew, &report.version);
+            for error in report.peer_errors {
+                println!(
+                    "Peer error: {}: {} [{}]",
+                    error.peer, error.detail, error.code
+                );
+            }
+            for alarm in report.link_alarms {
+                println!("WARNING: link to {} degraded: {}", alarm.peer, alarm.reason);
+            }
+            let counters = &report.counters;
+            println!(
+                "Activity: {} crossings, {} events forwarded, {} events injected, {} reconnects",
+                counters.crossings,
+                counters.events_forwarded,
+                counters.events_injected,
+                counters.reconnects
+            );
+            if counters.clock_jumps > 0 {
+                println!(
+                    "Clock:   {} jumps of the system clock, timers re-baselined",
+                    counters.clock_jumps
+                );
+            }
+            let evictions = &report.evictions;
+            if evictions.total() > 0 {
+                println!(
+                    "Evicted: {} input batches, {} peer errors, {} discovered peers",
+                    evictions.input_batches, evictions.peer_errors, evictions.discovered_peers
+                );
+            }
+            if counters.local_input_conflicts > 0 {
+                println!(
+                    "Local:   used {} times while controlled by a peer",
+                    counters.local_input_conflicts
+                );
+            }
+            if let Some(name) = report.controlling {
+                println!("Control: controlling {name}");
+                if let Some(focus) = report.remote_focus {
+                    print_remote_focus(&name, focus);
+                }
+                if report.remote_user_active {
+                    println!("WARNING: someone is using {name}'s own keyboard or pointer");
+                }
+            } else if let Some(name) = report.controlled_by {
+                println!("Control: controlled by {name}");
+                if report.local_user_active {
+                    println!("WARNING: local input while {name} has control");
+                }
+            }
+        }
     } else {
         println!("Status:  stopped (stale PID file)");
         // Clean up stale PID file
@@ -223,7 +937,7 @@ fn show_status() -> anyhow::Result<()> {
     // Show machine name from config if available
     if config_path.exists() {
         if let Ok(content) = std::fs::read_to_string(&config_path) {
-            if let Ok(config) = toml::from_str::<cross_control_daemon::config::Config>(&content) {
+            if let Ok(config) = cross_control_daemon::config::Config::from_toml(&content) {
                 println!("Name:    {}", config.identity.name);
             }
         }
@@ -242,6 +956,837 @@ fn show_status() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Format a duration in seconds as e.g. `3d 4h 5m`.
+fn format_uptime(secs: u64) -> String {
+    let (days, hours, minutes) = (secs / 86_400, secs / 3600 % 24, secs / 60 % 60);
+    match (days, hours) {
+        (0, 0) => format!("{minutes}m {}s", secs % 60),
+        (0, _) => format!("{hours}h {minutes}m"),
+        _ => format!("{days}d {hours}h {minutes}m"),
+    }
+}
+
+/// Format seconds since the Unix epoch as `YYYY-MM-DD HH:MM:SS` in UTC.
+fn format_utc(epoch_secs: u64) -> String {
+    // Days to civil date, from Howard Hinnant's `civil_from_days`.
+    let days = epoch_secs / 86_400 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    let secs = epoch_secs % 86_400;
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+#[allow(clippy::too_many_lines)]
+async fn guided_setup(remote: Option<&str>, config_path: Option<&str>) -> anyhow::Result<()> {
+    use cross_control_daemon::setup;
+    use cross_control_protocol::Transport;
+    use cross_control_types::ScreenGeometry;
+    use std::net::SocketAddr;
+
+    let mut config = setup::load_config(config_path)?;
+    let config_dir = setup::config_dir();
+    let (cert_pem, key_pem) = setup::load_or_generate_certs(&config_dir)?;
+    let machine_id = setup::load_or_create_machine_id(&config_dir)?;
+
+    let screen_size = setup::detect_screen_size();
+    let (width, height) =
+        screen_size.unwrap_or((config.daemon.screen_width, config.daemon.screen_height));
+    match screen_size {
+        Some(_) => println!("This screen: {width}x{height} (detected)"),
+        None => println!("This screen: {width}x{height} (from config; detection unavailable)"),
+    }
+    let screen = ScreenGeometry::new(width, height).with_scale(config.daemon.screen_scale);
+
+    // The waiting side listens where its daemon will; the dialling side
+    // only needs an ephemeral port.
+    let port = match remote {
+        Some(_) => 0,
+        None => config.daemon.port,
+    };
+    let bind_addr = setup::listen_address(&config, port)?;
+    let transport = cross_control_protocol::QuicTransport::bind(bind_addr, &cert_pem, &key_pem)?;
+    transport
+        .allowlist()
+        .set(config.security.allowed_subnets.clone());
+
+    let mut session = if let Some(address) = remote {
+        let addr = resolve_address(address, config.daemon.peer_port()).await?;
+        println!("Connecting to {addr}...");
+        cross_control_daemon::pairing::connect(
+            &transport,
+            addr,
+            machine_id,
+            &config.identity.name,
+            &screen,
+        )
+        .await?
+    } else {
+        println!(
+            "Waiting on port {}. On the other machine run:\n    \
+             cross-control setup --with <this machine's address>",
+            transport.local_addr()?.port()
+        );
+        cross_control_daemon::pairing::accept(
+            &transport,
+            machine_id,
+            &config.identity.name,
+            &screen,
+        )
+        .await?
+    };
+
+    let peer_name = session.peer_name().to_string();
+    let peer_fingerprint = session.peer_fingerprint().map(str::to_string);
+    println!();
+    println!(
+        "Connected to {peer_name} ({}x{})",
+        session.peer_screen().width,
+        session.peer_screen().height
+    );
+    if let Some(fingerprint) = &peer_fingerprint {
+        println!("Fingerprint:  {fingerprint}");
+    }
+    println!("Session code: {}", session.verification_code()?);
+
+    let answer = session
+        .keep_alive_while(prompt("Does the other machine show the same code? [y/N] "))
+        .await?;
+    if !answer.eq_ignore_ascii_case("y") {
+        let _ = session.send(SetupMessage::Declined).await;
+        session.close().await;
+        anyhow::bail!("setup cancelled: session codes not confirmed");
+    }
+
+    let (position, address) = if let Some(address) = remote {
+        let position = loop {
+            let question =
+                format!("Which side of this screen is {peer_name}? [left/right/above/below] ");
+            let answer = session.keep_alive_while(prompt(&question)).await?;
+            if let Some(position) = parse_position(&answer) {
+                break position;
+            }
+        };
+        session
+            .send(SetupMessage::Layout {
+                position: position.opposite(),
+                port: config.daemon.peer_port(),
+            })
+            .await?;
+        println!("Waiting for {peer_name} to confirm...");
+        match session.recv().await? {
+            SetupMessage::Accepted => {}
+            other => anyhow::bail!("{peer_name} did not accept the layout ({other:?})"),
+        }
+        (
+            position,
+            resolve_address(address, config.daemon.peer_port()).await?,
+        )
+    } else {
+        println!("Waiting for {peer_name} to choose the layout...");
+        let (position, port) = match session.recv().await? {
+            SetupMessage::Layout { position, port } => (position, port),
+            other => anyhow::bail!("{peer_name} cancelled setup ({other:?})"),
+        };
+        println!("{peer_name} is {position:?} of this screen");
+        session.send(SetupMessage::Accepted).await?;
+        let address = SocketAddr::new(session.peer_address().ip(), port);
+        // Let the initiator read the reply and hang up first.
+        session.wait_closed().await;
+        (position, address)
+    };
+
+    cross_control_daemon::pairing::apply_pairing(
+        &mut config,
+        &peer_name,
+        address.to_string(),
+        position,
+        peer_fingerprint,
+        screen_size,
+    );
+    let path = setup::save_config(config_path, &config)?;
+    println!("Saved {}", path.display());
+
+    println!("Starting cross-control...");
+    if remote.is_some() {
+        session.close().await;
+        start_daemon(config_path, None).await
+    } else {
+        // Already listening on the daemon's port.
+        start_daemon(config_path, Some(transport)).await
+    }
+}
+
+/// Resolve `host` or `host:port`, using `default_port` when none is given.
+async fn resolve_address(address: &str, default_port: u16) -> anyhow::Result<std::net::SocketAddr> {
+    let with_port = if address.contains(':') {
+        address.to_string()
+    } else {
+        format!("{address}:{default_port}")
+    };
+    let mut addrs = tokio::net::lookup_host(&with_port).await?;
+    addrs
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("cannot resolve {address}"))
+}
+
+fn parse_position(answer: &str) -> Option<cross_control_types::Position> {
+    use cross_control_types::Position;
+
+    match answer.to_ascii_lowercase().as_str() {
+        "l" | "left" => Some(Position::Left),
+        "r" | "right" => Some(Position::Right),
+        "a" | "above" | "up" => Some(Position::Above),
+        "b" | "below" | "down" => Some(Position::Below),
+        _ => None,
+    }
+}
+
+/// Ask a question on the terminal without blocking the runtime.
+async fn prompt(question: &str) -> anyhow::Result<String> {
+    let question = question.to_string();
+    tokio::task::spawn_blocking(move || {
+        use std::io::Write;
+
+        print!("{question}");
+        std::io::stdout().flush()?;
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line)?;
+        Ok(line.trim().to_string())
+    })
+    .await?
+}
+
+/// Send one request to the running daemon over the IPC socket.
+async fn daemon_request(
+    request: &cross_control_daemon::ipc::IpcRequest,
+) -> anyhow::Result<cross_control_daemon::ipc::IpcResponse> {
+    use cross_control_daemon::{ipc, setup};
+
+    ipc::request(&setup::socket_path(), request)
+        .await
+        .map_err(|e| anyhow::anyhow!("cannot reach daemon: {e}"))
+}
+
+async fn pair(
+    address: &str,
+    mut position: Option<cross_control_types::Position>,
+) -> anyhow::Result<()> {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+    use cross_control_daemon::setup;
+
+    let port = setup::load_config(None)?.daemon.peer_port();
+    let addr = resolve_address(address, port).await?;
+    println!("Connecting to {addr}...");
+    let request = IpcRequest::PairConnect {
+        address: addr.to_string(),
+    };
+    let (name, fingerprint) = match daemon_request(&request).await? {
+        IpcResponse::PairPending {
+            name,
+            fingerprint,
+            code,
+        } => {
+            println!("Connected to {name}");
+            println!("Fingerprint:  {fingerprint}");
+            println!("Session code: {code}");
+            (name, fingerprint)
+        }
+        IpcResponse::Error { code, message } => {
+            anyhow::bail!("daemon error: {message} [{code}]")
+        }
+        other => {
+            anyhow::bail!("unexpected daemon response: {other:?}")
+        }
+    };
+
+    let answer = prompt("Does the other machine show the same code? [y/N] ").await?;
+    let accept = answer.eq_ignore_ascii_case("y");
+    loop {
+        let request = IpcRequest::PairConfirm {
+            fingerprint: fingerprint.clone(),
+            accept,
+            position,
+        };
+        match daemon_request(&request).await? {
+            IpcResponse::Paired { name, position } => {
+                println!("Paired with {name} ({position:?} of this screen)");
+                return Ok(());
+            }
+            IpcResponse::PairCancelled => {
+                anyhow::bail!("pairing cancelled: session codes not confirmed")
+            }
+            // New machines need a position before they can be pinned.
+            IpcResponse::Error {
+                code: cross_control_types::ErrorCode::InvalidRequest,
+                ..
+            } if position.is_none() => {
+                let question =
+                    format!("Which side of this screen is {name}? [left/right/above/below] ");
+                position = parse_position(&prompt(&question).await?);
+            }
+            IpcResponse::Error { code, message } => {
+                anyhow::bail!("daemon error: {message} [{code}]")
+            }
+            other => {
+                anyhow::bail!("unexpected daemon response: {other:?}")
+            }
+        }
+    }
+}
+
+async fn set_paused(paused: bool) -> anyhow::Result<()> {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+
+    match daemon_request(&IpcRequest::SetPaused(paused)).await? {
+        IpcResponse::Paused(true) => println!("cross-control paused"),
+        IpcResponse::Paused(false) => println!("cross-control resumed"),
+        IpcResponse::Error { code, message } => {
+            anyhow::bail!("daemon error: {message} [{code}]")
+        }
+        other => anyhow::bail!("unexpected daemon response: {other:?}"),
+    }
+    Ok(())
+}
+
+async fn request_control(screen: String) -> anyhow::Result<()> {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+
+    match daemon_request(&IpcRequest::RequestControl { screen }).await? {
+        IpcResponse::ControlGranted { screen } => println!("{screen} agreed, taking control"),
+        IpcResponse::Error { code, message } => {
+            anyhow::bail!("daemon error: {message} [{code}]")
+        }
+        other => anyhow::bail!("unexpected daemon response: {other:?}"),
+    }
+    Ok(())
+}
+
+async fn ping(screen: String, count: u32, interval: u64, timeout: u64) -> anyhow::Result<()> {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+    use std::time::Duration;
+
+    let request = IpcRequest::Ping {
+        screen: screen.clone(),
+    };
+    let mut rtts = Vec::new();
+    for seq in 1..=count {
+        if seq > 1 {
+            tokio::time::sleep(Duration::from_millis(interval)).await;
+        }
+        let answer =
+            tokio::time::timeout(Duration::from_millis(timeout), daemon_request(&request)).await;
+        match answer {
+            Ok(Ok(IpcResponse::Pong { screen, rtt_us })) => {
+                println!("{screen}: seq={seq} time={:.1} ms", ms(rtt_us));
+                rtts.push(rtt_us);
+            }
+            Ok(Ok(IpcResponse::Error { code, message })) => {
+                anyhow::bail!("daemon error: {message} [{code}]")
+            }
+            Ok(Ok(other)) => anyhow::bail!("unexpected daemon response: {other:?}"),
+            Ok(Err(e)) => return Err(e),
+            Err(_) => println!("{screen}: seq={seq} timed out"),
+        }
+    }
+
+    let received = rtts.len() as u64;
+    let lost = u64::from(count) - received;
+    println!();
+    println!(
+        "{count} sent, {received} received, {}% lost",
+        lost * 100 / u64::from(count.max(1))
+    );
+    if let (Some(min), Some(max)) = (rtts.iter().min(), rtts.iter().max()) {
+        let mean = rtts.iter().sum::<u64>() / received;
+        println!(
+            "rtt min {:.1} ms, mean {:.1} ms, max {:.1} ms",
+            ms(*min),
+            ms(mean),
+            ms(*max)
+        );
+    }
+    if rtts.is_empty() && count > 0 {
+        anyhow::bail!("no answer from {screen}");
+    }
+    Ok(())
+}
+
+async fn teleport(screen: String, x: u32, y: u32) -> anyhow::Result<()> {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+
+    match daemon_request(&IpcRequest::Teleport { screen, x, y }).await? {
+        IpcResponse::Teleported { screen, x, y } => println!("Cursor on {screen} at {x},{y}"),
+        IpcResponse::Error { code, message } => {
+            anyhow::bail!("daemon error: {message} [{code}]")
+        }
+        other => anyhow::bail!("unexpected daemon response: {other:?}"),
+    }
+    Ok(())
+}
+
+async fn type_text(screen: String, text: String) -> anyhow::Result<()> {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+
+    match daemon_request(&IpcRequest::TypeText { screen, text }).await? {
+        IpcResponse::Typed { screen, chars } => println!("Typed {chars} characters on {screen}"),
+        IpcResponse::Error { code, message } => {
+            anyhow::bail!("daemon error: {message} [{code}]")
+        }
+        other => anyhow::bail!("unexpected daemon response: {other:?}"),
+    }
+    Ok(())
+}
+
+async fn show_session_codes() -> anyhow::Result<()> {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+
+    let response = daemon_request(&IpcRequest::SessionCodes).await?;
+    match response {
+        IpcResponse::SessionCodes(codes) if codes.is_empty() => {
+            println!("No connected machines.");
+        }
+        IpcResponse::SessionCodes(codes) => {
+            for entry in codes {
+                println!(
+                    "{:<20} {}  session {}  ({})",
+                    entry.name, entry.code, entry.session, entry.machine_id
+                );
+            }
+        }
+        IpcResponse::Error { code, message } => {
+            anyhow::bail!("daemon error: {message} [{code}]")
+        }
+        other => {
+            anyhow::bail!("unexpected daemon response: {other:?}")
+        }
+    }
+    Ok(())
+}
+
+async fn show_devices() -> anyhow::Result<()> {
+    use cross_control_daemon::ipc::{DeviceEntry, IpcRequest, IpcResponse};
+
+    fn print_device(device: &DeviceEntry) {
+        let capabilities: Vec<String> = device
+            .capabilities
+            .iter()
+            .map(|c| format!("{c:?}"))
+            .collect();
+        println!(
+            "  {:>3}  {:<32} {:<28} {} events",
+            device.id.0,
+            device.name,
+            capabilities.join(", "),
+            device.events
+        );
+    }
+
+    let report = match daemon_request(&IpcRequest::Devices).await? {
+        IpcResponse::Devices(report) => report,
+        IpcResponse::Error { code, message } => {
+            anyhow::bail!("daemon error: {message} [{code}]")
+        }
+        other => {
+            anyhow::bail!("unexpected daemon response: {other:?}")
+        }
+    };
+
+    println!("Captured devices:");
+    if report.captured.is_empty() {
+        println!("  (none)");
+    }
+    for device in &report.captured {
+        print_device(device);
+    }
+    for peer in &report.remote {
+        println!();
+        println!("Virtual devices for {}:", peer.peer);
+        for device in &peer.devices {
+            print_device(device);
+        }
+        if peer.rejected > 0 {
+            println!(
+                "  {} announcements rejected (malformed or over limits.peer_devices)",
+                peer.rejected
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn show_history(peer: String) -> anyhow::Result<()> {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+
+    let history = match daemon_request(&IpcRequest::History { peer }).await? {
+        IpcResponse::History(history) => history,
+        IpcResponse::Error { code, message } => {
+            anyhow::bail!("daemon error: {message} [{code}]")
+        }
+        other => {
+            anyhow::bail!("unexpected daemon response: {other:?}")
+        }
+    };
+
+    println!(
+        "Session {} with {} ({}), now {}:",
+        history.session, history.name, history.machine_id, history.state
+    );
+    for event in &history.events {
+        println!(
+            "  {}.{:03} UTC  {:<12} {}",
+            format_utc(event.at_ms / 1000),
+            event.at_ms % 1000,
+            event.state,
+            event.event
+        );
+    }
+    Ok(())
+}
+
+async fn show_stats(peer: Option<String>, reset: bool) -> anyhow::Result<()> {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+
+    let request = if reset {
+        IpcRequest::ResetStats { peer }
+    } else {
+        IpcRequest::Stats { peer }
+    };
+    let reports = match daemon_request(&request).await? {
+        IpcResponse::Stats(reports) => reports,
+        IpcResponse::Error { code, message } => {
+            anyhow::bail!("daemon error: {message} [{code}]")
+        }
+        other => {
+            anyhow::bail!("unexpected daemon response: {other:?}")
+        }
+    };
+
+    if reports.is_empty() {
+        println!("No connected machines.");
+    }
+    for report in &reports {
+        println!(
+            "{} (session {}), over {} since {} UTC:",
+            report.name,
+            report.session,
+            format_uptime(report.elapsed_ms / 1000),
+            format_utc(report.since_ms / 1000)
+        );
+        println!(
+            "  events  {} sent, {} received, {} dropped",
+            report.events_sent, report.events_received, report.events_dropped
+        );
+        println!(
+            "  bytes   {} sent, {} received",
+            report.bytes_sent, report.bytes_received
+        );
+        let rtt = &report.rtt_us;
+        if let (Some(min), Some(max)) = (rtt.iter().min(), rtt.iter().max()) {
+            let mean = rtt.iter().sum::<u64>() / rtt.len() as u64;
+            println!(
+                "  rtt     min {:.1} ms, mean {:.1} ms, max {:.1} ms over {} pings",
+                ms(*min),
+                ms(mean),
+                ms(*max),
+                rtt.len()
+            );
+        } else {
+            println!("  rtt     no pings answered yet");
+        }
+    }
+    if reset && !reports.is_empty() {
+        println!("Counters reset.");
+    }
+    Ok(())
+}
+
+/// A number of days, as `7d`, `4w` or plain `7`.
+fn parse_days(s: &str) -> Result<u64, String> {
+    let (number, unit) = match s.strip_suffix('d') {
+        Some(days) => (days, 1),
+        None => s.strip_suffix('w').map_or((s, 1), |weeks| (weeks, 7)),
+    };
+    match number.parse::<u64>() {
+        Ok(n) if n > 0 => Ok(n * unit),
+        _ => Err(format!(
+            "expected a number of days like `7d` or `4w`, got {s:?}"
+        )),
+    }
+}
+
+async fn show_trends(peer: Option<String>, days: u64) -> anyhow::Result<()> {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+    use cross_control_daemon::metrics::PeerDay;
+
+    const BAR_WIDTH: u64 = 20;
+
+    let rows = match daemon_request(&IpcRequest::Trends { days, peer }).await? {
+        IpcResponse::Trends(rows) => rows,
+        IpcResponse::Error { code, message } => {
+            anyhow::bail!("daemon error: {message} [{code}]")
+        }
+        other => anyhow::bail!("unexpected daemon response: {other:?}"),
+    };
+    if rows.is_empty() {
+        println!("No usage recorded in the last {days} days.");
+        return Ok(());
+    }
+
+    let longest = rows
+        .iter()
+        .map(|r| r.controlled_ms)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let rtt = |day: &PeerDay| {
+        day.mean_rtt_us()
+            .map_or_else(|| "-".to_string(), |us| format!("{:.1} ms", ms(us)))
+    };
+    for (i, day) in rows.iter().enumerate() {
+        if i == 0 || rows[i - 1].peer != day.peer {
+            println!("{}, last {days} days:", day.peer);
+        }
+        let filled = usize::try_from(day.controlled_ms * BAR_WIDTH / longest).unwrap_or(0);
+        println!(
+            "  {}  {:>10}  {:<20}  {:>5} crossings, rtt {}",
+            &format_utc(day.day * 86_400)[..10],
+            format_uptime(day.controlled_ms / 1000),
+            "#".repeat(filled),
+            day.crossings,
+            rtt(day)
+        );
+        if rows.get(i + 1).map_or(true, |next| next.peer != day.peer) {
+            let mut total = PeerDay::default();
+            for row in rows.iter().filter(|r| r.peer == day.peer) {
+                total.add(row);
+            }
+            println!(
+                "  {:<10}  {:>10}  {:<20}  {:>5} crossings, rtt {}",
+                "total",
+                format_uptime(total.controlled_ms / 1000),
+                "",
+                total.crossings,
+                rtt(&total)
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Microseconds as fractional milliseconds, for display.
+#[allow(clippy::cast_precision_loss)]
+fn ms(us: u64) -> f64 {
+    us as f64 / 1000.0
+}
+
+async fn create_report(
+    config_path: Option<&str>,
+    output: Option<&str>,
+    yes: bool,
+) -> anyhow::Result<()> {
+    println!("The report will contain:");
+    for item in report::CONTENTS {
+        println!("  - {item}");
+    }
+    if !yes {
+        let answer = prompt("Create it? [y/N] ").await?;
+        if !answer.eq_ignore_ascii_case("y") {
+            println!("Cancelled.");
+            return Ok(());
+        }
+    }
+    let path = report::create(config_path, output).await?;
+    println!("Wrote {}", path.display());
+    println!("Look it over, then attach it to your issue.");
+    Ok(())
+}
+
+async fn ban_peer(peer: &str) -> anyhow::Result<()> {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+
+    let request = IpcRequest::Ban {
+        peer: peer.to_string(),
+    };
+    let response = daemon_request(&request).await?;
+    match response {
+        IpcResponse::Banned { name, fingerprint } => {
+            println!("Banned {name} ({fingerprint})");
+        }
+        IpcResponse::Error { code, message } => {
+            anyhow::bail!("daemon error: {message} [{code}]")
+        }
+        other => {
+            anyhow::bail!("unexpected daemon response: {other:?}")
+        }
+    }
+    Ok(())
+}
+
+async fn suggest_layout() -> anyhow::Result<()> {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+
+    match daemon_request(&IpcRequest::LayoutSuggest).await? {
+        IpcResponse::LayoutSuggestions(suggestions) if suggestions.is_empty() => {
+            println!("No layout changes suggested.");
+        }
+        IpcResponse::LayoutSuggestions(suggestions) => {
+            for suggestion in suggestions {
+                println!("{}: {}", suggestion.screen, suggestion.reason);
+                if let Some(position) = suggestion.suggested {
+                    println!("    position = \"{position:?}\"");
+                }
+            }
+        }
+        IpcResponse::Error { code, message } => {
+            anyhow::bail!("daemon error: {message} [{code}]")
+        }
+        other => anyhow::bail!("unexpected daemon response: {other:?}"),
+    }
+    Ok(())
+}
+
+async fn push_config(peer: &str, file: &str) -> anyhow::Result<()> {
+    use cross_control_daemon::config::LayoutConfig;
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+
+    let content = std::fs::read_to_string(file)?;
+    let layout: LayoutConfig =
+        toml::from_str(&content).map_err(|e| anyhow::anyhow!("invalid layout in {file}: {e}"))?;
+    let request = IpcRequest::PushConfig {
+        peer: peer.to_string(),
+        layout: toml::to_string(&layout)?,
+    };
+    // The daemon answers once the peer has applied or refused the layout.
+    let response =
+        tokio::time::timeout(std::time::Duration::from_secs(10), daemon_request(&request))
+            .await
+            .map_err(|_| anyhow::anyhow!("{peer} did not answer the push"))??;
+    match response {
+        IpcResponse::ConfigPushed { name } => {
+            println!(
+                "{name} applied the layout ({} screens, {} adjacency entries)",
+                layout.screens.len(),
+                layout.screen_adjacency.len()
+            );
+        }
+        IpcResponse::Error { code, message } => anyhow::bail!("{message} [{code}]"),
+        other => anyhow::bail!("unexpected daemon response: {other:?}"),
+    }
+    Ok(())
+}
+
+async fn run_command(command: &str, mut peers: Vec<String>, all: bool) -> anyhow::Result<()> {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+
+    if all {
+        peers = match daemon_request(&IpcRequest::SessionCodes).await? {
+            IpcResponse::SessionCodes(codes) => codes.into_iter().map(|c| c.name).collect(),
+            IpcResponse::Error { code, message } => {
+                anyhow::bail!("daemon error: {message} [{code}]")
+            }
+            other => anyhow::bail!("unexpected daemon response: {other:?}"),
+        };
+    }
+    let mut failed = 0;
+    for peer in peers {
+        let request = IpcRequest::RunCommand {
+            peer: peer.clone(),
+            command: command.to_string(),
+        };
+        // The daemon answers once the peer has started or refused it.
+        let response =
+            tokio::time::timeout(std::time::Duration::from_secs(10), daemon_request(&request))
+                .await
+                .map_err(|_| anyhow::anyhow!("{peer} did not answer"))?;
+        match response? {
+            IpcResponse::CommandStarted { name, command } => println!("{name}: started {command}"),
+            IpcResponse::Error { code, message } => {
+                eprintln!("{peer}: {message} [{code}]");
+                failed += 1;
+            }
+            other => anyhow::bail!("unexpected daemon response: {other:?}"),
+        }
+    }
+    if failed > 0 {
+        anyhow::bail!("{failed} peer(s) did not run {command}");
+    }
+    Ok(())
+}
+
+async fn lock_all(command: &str) -> anyhow::Result<()> {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+
+    let request = IpcRequest::RunEverywhere {
+        command: command.to_string(),
+    };
+    let outcomes = match daemon_request(&request).await? {
+        IpcResponse::CommandsRun(outcomes) => outcomes,
+        IpcResponse::Error { code, message } => anyhow::bail!("daemon error: {message} [{code}]"),
+        other => anyhow::bail!("unexpected daemon response: {other:?}"),
+    };
+    let mut failed = 0;
+    for outcome in outcomes {
+        match outcome.error {
+            None => println!("{}: started {command}", outcome.name),
+            Some(e) => {
+                eprintln!("{}: {e}", outcome.name);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        anyhow::bail!("{failed} machine(s) did not run {command}");
+    }
+    Ok(())
+}
+
+async fn switch_profile(name: &str) -> anyhow::Result<()> {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+
+    let request = IpcRequest::SwitchProfile {
+        name: name.to_string(),
+    };
+    match daemon_request(&request).await? {
+        IpcResponse::ProfileSwitched { name, screens } => {
+            println!("Switched to profile {name} ({screens} screens)");
+        }
+        IpcResponse::Error { code, message } => {
+            anyhow::bail!("daemon error: {message} [{code}]")
+        }
+        other => anyhow::bail!("unexpected daemon response: {other:?}"),
+    }
+    Ok(())
+}
+
+async fn set_keyboard_layout(layout: String, variant: String) -> anyhow::Result<()> {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+    use cross_control_types::KeyboardLayout;
+
+    let layout = KeyboardLayout { layout, variant };
+    match daemon_request(&IpcRequest::SetKeyboardLayout(layout.clone())).await? {
+        IpcResponse::KeyboardLayoutSet { peers } => {
+            println!("Keyboard layout {layout}, sent to {peers} peers");
+        }
+        IpcResponse::Error { code, message } => {
+            anyhow::bail!("daemon error: {message} [{code}]")
+        }
+        other => anyhow::bail!("unexpected daemon response: {other:?}"),
+    }
+    Ok(())
+}
+
 fn stop_daemon() -> anyhow::Result<()> {
     use cross_control_daemon::setup;
 
@@ -0,0 +1,154 @@
+//! Bug report bundles (`cross-control report`).
+//!
+//! Collects what is usually needed to reproduce a problem into one
+//! `.tar.gz`: version and platform details, the config with addresses,
+//! fingerprints, network names and command lines redacted, a status and
+//! device snapshot from the running daemon, and the daemon's recent journal.
+//! Nothing is sent anywhere; the user attaches the file to an issue
+//! themselves.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use cross_control_daemon::ipc::{self, IpcRequest, IpcResponse};
+use cross_control_daemon::setup;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+/// Journal lines included from the daemon's systemd user service.
+const LOG_LINES: &str = "2000";
+
+/// What the bundle contains, shown before asking for consent.
+pub const CONTENTS: [&str; 4] = [
+    "cross-control version and commit, OS, kernel and desktop session type",
+    "your config, with addresses, fingerprints, Wi-Fi names and commands redacted",
+    "the running daemon's status and device list (device names included)",
+    "the last 2000 lines of the daemon's journal, if it runs as a systemd user \
+     service (log lines may mention machine names and addresses)",
+];
+
+/// Write a report bundle to `output`, or to a timestamped file in the
+/// current directory, and return its path.
+pub async fn create(config_path: Option<&str>, output: Option<&str>) -> anyhow::Result<PathBuf> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let path = output.map_or_else(
+        || PathBuf::from(format!("cross-control-report-{now}.tar.gz")),
+        PathBuf::from,
+    );
+
+    let files = [
+        ("version.txt", version()),
+        ("platform.txt", platform()),
+        ("config.toml", redacted_config(config_path)),
+        ("status.json", daemon_snapshot().await),
+        ("daemon.log", journal()),
+    ];
+    write_bundle(&path, now, &files)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+fn write_bundle(path: &Path, mtime: u64, files: &[(&str, String)]) -> anyhow::Result<()> {
+    let encoder = GzEncoder::new(File::create(path)?, Compression::default());
+    let mut archive = tar::Builder::new(encoder);
+    for (name, content) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(mtime);
+        header.set_cksum();
+        archive.append_data(
+            &mut header,
+            format!("cross-control-report/{name}"),
+            content.as_bytes(),
+        )?;
+    }
+    archive.into_inner()?.finish()?;
+    Ok(())
+}
+
+fn version() -> String {
+    format!(
+        "cross-control {}\ncommit {}\n",
+        cross_control_daemon::VERSION,
+        cross_control_daemon::COMMIT
+    )
+}
+
+fn platform() -> String {
+    let read = |path: &str| {
+        std::fs::read_to_string(path).unwrap_or_else(|e| format!("(unavailable: {e})\n"))
+    };
+    let env = |name: &str| std::env::var(name).unwrap_or_else(|_| "(unset)".to_string());
+    format!(
+        "os: {} {}\nkernel: {}session type: {}\ndesktop: {}\n\n{}",
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        read("/proc/sys/kernel/osrelease"),
+        env("XDG_SESSION_TYPE"),
+        env("XDG_CURRENT_DESKTOP"),
+        read("/etc/os-release"),
+    )
+}
+
+fn redacted_config(config_path: Option<&str>) -> String {
+    let path = setup::config_file_path(config_path);
+    if !path.exists() {
+        return format!("# no config at {}; defaults in use\n", path.display());
+    }
+    setup::load_config(config_path)
+        .map_err(anyhow::Error::from)
+        .and_then(|config| Ok(config.redacted_toml()?))
+        .unwrap_or_else(|e| format!("# config could not be read: {e}\n"))
+}
+
+/// Status and devices from the running daemon, as JSON.
+async fn daemon_snapshot() -> String {
+    let socket = setup::socket_path();
+    let mut snapshot = serde_json::Map::new();
+    for (key, request) in [
+        ("status", IpcRequest::Status),
+        ("devices", IpcRequest::Devices),
+    ] {
+        let value = match ipc::request(&socket, &request).await {
+            Ok(IpcResponse::Status(report)) => serde_json::to_value(report),
+            Ok(IpcResponse::Devices(report)) => serde_json::to_value(report),
+            Ok(other) => Ok(format!("unexpected response: {other:?}").into()),
+            Err(e) => Ok(format!("daemon not reachable: {e}").into()),
+        };
+        snapshot.insert(
+            key.to_string(),
+            value.unwrap_or_else(|e| e.to_string().into()),
+        );
+    }
+    serde_json::to_string_pretty(&snapshot).unwrap_or_default()
+}
+
+/// Recent daemon log lines from the systemd user journal.
+fn journal() -> String {
+    let output = Command::new("journalctl")
+        .args([
+            "--user",
+            "-u",
+            "cross-control",
+            "--no-pager",
+            "-o",
+            "short-iso",
+            "-n",
+        ])
+        .arg(LOG_LINES)
+        .output();
+    match output {
+        Ok(output) if output.status.success() && !output.stdout.is_empty() => {
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        }
+        _ => "No journal entries for the cross-control user service. If you run the daemon \
+              another way, attach its output separately.\n"
+            .to_string(),
+    }
+}
@@ -0,0 +1,490 @@
+//! Long-running soak test with synthetic traffic (`--features soak`).
+//!
+//! `cross-control soak` runs two daemons in-process on loopback with mock
+//! input and clipboard backends and drives them with randomized steps:
+//! crossings followed by input bursts, clipboard copies on either side,
+//! cursor movement that stays local, and reconnects (the dialing daemon is
+//! restarted). After every step both daemons must settle back to exactly one
+//! session each with nobody in control, and the process's resident set must
+//! not keep growing. The run stops at the first broken invariant and prints
+//! the seed so it can be replayed.
+
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context};
+use cross_control_certgen::GeneratedCert;
+use cross_control_clipboard::mock::{MockClipboard, MockClipboardHandle};
+use cross_control_daemon::config::{
+    ClipboardScope, Config, DaemonConfig, IdentityConfig, PointerScale, ScreenConfig, ScreenMode,
+};
+use cross_control_daemon::{Daemon, DaemonEvent, DaemonStatus};
+use cross_control_input::mock::{MockCapture, MockEmulation, MockEmulationHandle};
+use cross_control_protocol::Transport;
+use cross_control_types::{
+    ButtonState, CapturedEvent, ClipboardContent, DeviceCapability, DeviceId, DeviceInfo,
+    InputEvent, KeyCode, MachineId, MouseButton, Position,
+};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tracing::info;
+
+/// How long a step may take to settle before the daemons count as stuck.
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often to poll mock backends while waiting for a step to land.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+const KEYBOARD: DeviceId = DeviceId(1);
+const MOUSE: DeviceId = DeviceId(2);
+
+const KEYS: &[KeyCode] = &[
+    KeyCode::KeyA,
+    KeyCode::KeyE,
+    KeyCode::KeyI,
+    KeyCode::KeyO,
+    KeyCode::KeyS,
+    KeyCode::KeyT,
+];
+
+/// Parameters of a soak run.
+pub struct SoakOptions {
+    pub duration: Duration,
+    pub seed: u64,
+    pub report_every: Duration,
+    /// Allowed resident set growth over the first report, in KiB.
+    pub max_rss_growth_kb: u64,
+}
+
+/// What the run has done so far.
+#[derive(Debug, Default)]
+struct Counters {
+    steps: u64,
+    crossings: u64,
+    injected: u64,
+    clipboard_syncs: u64,
+    reconnects: u64,
+}
+
+/// One daemon and the handles used to drive and observe it.
+struct Node {
+    name: &'static str,
+    feed: mpsc::Sender<CapturedEvent>,
+    emulation: MockEmulationHandle,
+    clipboard: MockClipboardHandle,
+    status: watch::Receiver<DaemonStatus>,
+    events: mpsc::Sender<DaemonEvent>,
+    task: JoinHandle<()>,
+}
+
+impl Node {
+    fn spawn(
+        name: &'static str,
+        config: Config,
+        machine_id: MachineId,
+        cert: &GeneratedCert,
+    ) -> anyhow::Result<(Self, SocketAddr)> {
+        let bind: SocketAddr = "127.0.0.1:0".parse()?;
+        let transport =
+            cross_control_protocol::QuicTransport::bind(bind, &cert.cert_pem, &cert.key_pem)?;
+        let addr = transport.local_addr()?;
+
+        let (capture, feed) = MockCapture::new();
+        let emulation = MockEmulation::new();
+        let emulation_handle = emulation.handle();
+        let clipboard = MockClipboard::new();
+        let clipboard_handle = clipboard.handle();
+
+        let mut daemon = Daemon::new(
+            config,
+            machine_id,
+            transport,
+            Box::new(capture),
+            Box::new(emulation),
+        );
+        daemon.set_local_devices(devices());
+        daemon.set_clipboard(Box::new(clipboard));
+        let status = daemon.status_receiver();
+        let events = daemon.event_sender();
+        let task = tokio::spawn(async move {
+            if let Err(e) = daemon.run().await {
+                tracing::error!(node = name, error = %e, "daemon exited with an error");
+            }
+        });
+
+        let node = Self {
+            name,
+            feed,
+            emulation: emulation_handle,
+            clipboard: clipboard_handle,
+            status,
+            events,
+            task,
+        };
+        Ok((node, addr))
+    }
+
+    async fn stop(&mut self) -> anyhow::Result<()> {
+        let _ = self.events.send(DaemonEvent::Shutdown).await;
+        tokio::time::timeout(SETTLE_TIMEOUT, &mut self.task)
+            .await
+            .with_context(|| format!("daemon {} did not shut down", self.name))?
+            .with_context(|| format!("daemon {} panicked", self.name))
+    }
+
+    async fn send(&self, device_id: DeviceId, event: InputEvent) -> anyhow::Result<()> {
+        self.feed
+            .send(CapturedEvent {
+                device_id,
+                timestamp_us: 0,
+                event,
+            })
+            .await
+            .with_context(|| format!("daemon {} stopped reading input", self.name))
+    }
+
+    async fn wait_for(
+        &mut self,
+        what: &str,
+        pred: impl Fn(&DaemonStatus) -> bool,
+    ) -> anyhow::Result<()> {
+        let name = self.name;
+        let status = &mut self.status;
+        let waited = tokio::time::timeout(SETTLE_TIMEOUT, async {
+            loop {
+                if pred(&status.borrow_and_update()) {
+                    return true;
+                }
+                if status.changed().await.is_err() {
+                    return false;
+                }
+            }
+        })
+        .await;
+        match waited {
+            Ok(true) => Ok(()),
+            Ok(false) => bail!("daemon {name} exited while waiting for {what}"),
+            Err(_) => bail!(
+                "daemon {name}: {what} not reached within {SETTLE_TIMEOUT:?} (status: {:?})",
+                *status.borrow()
+            ),
+        }
+    }
+
+    /// Both invariants that must hold between steps.
+    async fn settle(&mut self) -> anyhow::Result<()> {
+        self.wait_for("exactly one session, nobody in control", |s| {
+            s.session_count == 1 && s.controlling.is_none() && s.controlled_by.is_none()
+        })
+        .await
+    }
+}
+
+/// Small deterministic generator, so a failing run can be replayed by seed.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        // xorshift64
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    /// Uniform in `-range..=range`.
+    fn delta(&mut self, range: i32) -> i32 {
+        let span = u64::try_from(2 * range + 1).unwrap_or(1);
+        i32::try_from(self.below(span)).unwrap_or(0) - range
+    }
+
+    fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[usize::try_from(self.below(items.len() as u64)).unwrap_or(0)]
+    }
+}
+
+/// Run the soak test until `options.duration` has passed or an invariant
+/// breaks.
+pub async fn run(options: SoakOptions) -> anyhow::Result<()> {
+    let mut rng = Rng::new(options.seed);
+    info!(seed = options.seed, duration = ?options.duration, "starting soak test");
+
+    let cert_a = cross_control_certgen::generate_certificate("localhost")?;
+    let cert_b = cross_control_certgen::generate_certificate("localhost")?;
+    let id_a = MachineId::new();
+    let id_b = MachineId::new();
+
+    let config_b = config("soak-b", "soak-a", Position::Left, None, &cert_a);
+    let (mut b, addr_b) = Node::spawn("b", config_b, id_b, &cert_b)?;
+    let config_a = config("soak-a", "soak-b", Position::Right, Some(addr_b), &cert_b);
+    let (mut a, _) = Node::spawn("a", config_a.clone(), id_a, &cert_a)?;
+
+    let mut counters = Counters::default();
+    let started = Instant::now();
+    let mut next_report = started + options.report_every;
+    let mut baseline_rss = None;
+
+    let result: anyhow::Result<()> = async {
+        a.settle().await?;
+        b.settle().await?;
+
+        while started.elapsed() < options.duration {
+            counters.steps += 1;
+            match rng.below(10) {
+                0..=3 => crossing(&mut a, &mut b, &mut rng, &mut counters).await?,
+                4..=6 => clipboard(&a, &b, &mut rng, &mut counters).await?,
+                7 | 8 => wander(&a, &mut rng).await?,
+                _ => {
+                    a.stop().await?;
+                    a = Node::spawn("a", config_a.clone(), id_a, &cert_a)?.0;
+                    counters.reconnects += 1;
+                }
+            }
+            a.settle().await?;
+            b.settle().await?;
+
+            if Instant::now() >= next_report {
+                next_report += options.report_every;
+                let rss = resident_kb();
+                info!(
+                    elapsed_s = started.elapsed().as_secs(),
+                    steps = counters.steps,
+                    crossings = counters.crossings,
+                    injected = counters.injected,
+                    clipboard_syncs = counters.clipboard_syncs,
+                    reconnects = counters.reconnects,
+                    rss_kb = rss,
+                    "soak progress"
+                );
+                if let Some(rss) = rss {
+                    let baseline = *baseline_rss.get_or_insert(rss);
+                    if rss.saturating_sub(baseline) > options.max_rss_growth_kb {
+                        bail!(
+                            "resident set grew from {baseline} KiB to {rss} KiB \
+                             (limit {} KiB)",
+                            options.max_rss_growth_kb
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+    .await;
+
+    let stopped = match (a.stop().await, b.stop().await) {
+        (Err(e), _) | (_, Err(e)) => Err(e),
+        _ => Ok(()),
+    };
+    result.and(stopped).with_context(|| {
+        format!(
+            "soak failed at step {} (seed {})",
+            counters.steps, options.seed
+        )
+    })?;
+
+    println!(
+        "Soak passed: {} steps in {}s (seed {})",
+        counters.steps,
+        started.elapsed().as_secs(),
+        options.seed
+    );
+    println!(
+        "  crossings: {}, injected events: {}, clipboard syncs: {}, reconnects: {}",
+        counters.crossings, counters.injected, counters.clipboard_syncs, counters.reconnects
+    );
+    Ok(())
+}
+
+/// Cross from A to B, send a burst of input, then release with the hotkey.
+async fn crossing(
+    a: &mut Node,
+    b: &mut Node,
+    rng: &mut Rng,
+    counters: &mut Counters,
+) -> anyhow::Result<()> {
+    for _ in 0..5 {
+        a.send(MOUSE, InputEvent::MouseMove { dx: 500, dy: 0 })
+            .await?;
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+    a.wait_for("control of b", |s| s.controlling.is_some())
+        .await?;
+    b.wait_for("being controlled by a", |s| s.controlled_by.is_some())
+        .await?;
+    counters.crossings += 1;
+
+    for _ in 0..=rng.below(200) {
+        let (device, event) = random_input(rng);
+        a.send(device, event).await?;
+    }
+    let deadline = Instant::now() + SETTLE_TIMEOUT;
+    loop {
+        let injected = b.emulation.take_injected_events().len() as u64;
+        counters.injected += injected;
+        if injected > 0 {
+            break;
+        }
+        if Instant::now() >= deadline {
+            bail!("input sent while controlling b was never injected");
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+
+    for state in [ButtonState::Pressed, ButtonState::Released] {
+        for code in [KeyCode::LeftCtrl, KeyCode::LeftShift, KeyCode::Escape] {
+            a.send(KEYBOARD, InputEvent::Key { code, state }).await?;
+        }
+    }
+    // Anything still in flight is dropped so the mock doesn't grow.
+    tokio::time::sleep(POLL_INTERVAL).await;
+    counters.injected += b.emulation.take_injected_events().len() as u64;
+    Ok(())
+}
+
+/// Copy random text on one side and wait for it to reach the other.
+async fn clipboard(
+    a: &Node,
+    b: &Node,
+    rng: &mut Rng,
+    counters: &mut Counters,
+) -> anyhow::Result<()> {
+    let (from, to) = if rng.below(2) == 0 { (a, b) } else { (b, a) };
+    let len = usize::try_from(1 + rng.below(4096)).unwrap_or(1);
+    let text = format!("{:016x}", rng.next()).repeat(len / 16 + 1);
+    let content = ClipboardContent::text(&text[..len]);
+
+    from.clipboard.copy(content.clone());
+    let deadline = Instant::now() + SETTLE_TIMEOUT;
+    while to.clipboard.contents().as_ref() != Some(&content) {
+        if Instant::now() >= deadline {
+            bail!(
+                "clipboard copied on {} never reached {}",
+                from.name,
+                to.name
+            );
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+    counters.clipboard_syncs += 1;
+    Ok(())
+}
+
+/// Move the cursor around on A without reaching an edge, then back to where
+/// it started.
+async fn wander(a: &Node, rng: &mut Rng) -> anyhow::Result<()> {
+    let moves: Vec<(i32, i32)> = (0..10).map(|_| (rng.delta(40), rng.delta(40))).collect();
+    for &(dx, dy) in &moves {
+        a.send(MOUSE, InputEvent::MouseMove { dx, dy }).await?;
+    }
+    for &(dx, dy) in moves.iter().rev() {
+        a.send(MOUSE, InputEvent::MouseMove { dx: -dx, dy: -dy })
+            .await?;
+    }
+    Ok(())
+}
+
+/// A random event that keeps the cursor away from B's left edge, so the
+/// burst doesn't cross back before the hotkey.
+fn random_input(rng: &mut Rng) -> (DeviceId, InputEvent) {
+    let state = if rng.below(2) == 0 {
+        ButtonState::Pressed
+    } else {
+        ButtonState::Released
+    };
+    match rng.below(3) {
+        0 => (
+            KEYBOARD,
+            InputEvent::Key {
+                code: rng.pick(KEYS),
+                state,
+            },
+        ),
+        1 => (
+            MOUSE,
+            InputEvent::MouseButton {
+                button: rng.pick(&[MouseButton::Left, MouseButton::Right]),
+                state,
+            },
+        ),
+        _ => (
+            MOUSE,
+            InputEvent::MouseMove {
+                dx: rng.delta(20).abs(),
+                dy: rng.delta(20),
+            },
+        ),
+    }
+}
+
+fn config(
+    name: &str,
+    peer: &str,
+    position: Position,
+    address: Option<SocketAddr>,
+    peer_cert: &GeneratedCert,
+) -> Config {
+    let mut config = Config {
+        daemon: DaemonConfig {
+            screen_width: 1920,
+            screen_height: 1080,
+            ..DaemonConfig::default()
+        },
+        identity: IdentityConfig {
+            name: name.to_string(),
+        },
+        screens: vec![ScreenConfig {
+            name: peer.to_string(),
+            group: None,
+            address: address.map(|a| a.to_string()),
+            position,
+            fingerprint: Some(peer_cert.fingerprint.clone()),
+            mode: ScreenMode::Control,
+            wake_on_enter: true,
+            locate_on_enter: false,
+            pointer_scale: PointerScale::default(),
+            share_devices: None,
+            clipboard: true,
+            wait_on_start: false,
+            commands: Vec::new(),
+            release_hotkey: None,
+        }],
+        ..Config::default()
+    };
+    config.clipboard.scope = ClipboardScope::AllPeers;
+    config
+}
+
+fn devices() -> Vec<DeviceInfo> {
+    vec![
+        DeviceInfo {
+            id: KEYBOARD,
+            name: "Soak Keyboard".to_string(),
+            capabilities: vec![DeviceCapability::Keyboard],
+            keys: Vec::new(),
+        },
+        DeviceInfo {
+            id: MOUSE,
+            name: "Soak Mouse".to_string(),
+            capabilities: vec![DeviceCapability::RelativeMouse, DeviceCapability::Scroll],
+            keys: Vec::new(),
+        },
+    ]
+}
+
+/// Resident set size of this process in KiB, where the platform reports it.
+fn resident_kb() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))
+        .and_then(|rest| rest.trim().trim_end_matches("kB").trim().parse().ok())
+}
@@ -13,6 +13,9 @@ pub enum ClipboardError {
     #[error("clipboard content too large: {size} bytes (max {max} bytes)")]
     TooLarge { size: usize, max: usize },
 
+    #[error("malformed clipboard data: {0}")]
+    Malformed(String),
+
     #[error("backend not available on this platform")]
     Unavailable,
 
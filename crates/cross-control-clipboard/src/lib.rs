@@ -1,12 +1,21 @@
 //! Clipboard synchronisation for cross-control.
 //!
 //! Defines the [`ClipboardProvider`] trait for platform clipboard access.
-//! Backends (arboard, wl-clipboard-rs) will be added in later phases.
+//! The Windows and macOS backends are behind the `windows` and `macos`
+//! features; Linux backends (wl-clipboard-rs) will be added in a later phase.
 
 use async_trait::async_trait;
 use cross_control_types::{ClipboardContent, ClipboardFormat};
 
 pub mod error;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+
+#[cfg(all(target_os = "macos", feature = "macos"))]
+pub mod macos;
+
+#[cfg(any(test, feature = "windows"))]
+pub mod windows;
 
 pub use error::ClipboardError;
 
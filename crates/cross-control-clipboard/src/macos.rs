@@ -0,0 +1,168 @@
+//! macOS clipboard backend using `NSPasteboard`.
+//!
+//! | Pasteboard type          | [`ClipboardFormat`] |
+//! |--------------------------|---------------------|
+//! | `public.utf8-plain-text` | `PlainText`         |
+//! | `public.html`            | `Html`              |
+//! | `public.png`             | `Png`               |
+//!
+//! The pasteboard has no change notifications, so `watch` polls its
+//! `changeCount`. Reads prefer an image, then text, then HTML: every
+//! application that copies HTML also copies text, and text is what most
+//! paste targets on the other machine can use.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use cross_control_types::{ClipboardContent, ClipboardFormat};
+use objc2::rc::autoreleasepool;
+use objc2_app_kit::NSPasteboard;
+use objc2_foundation::{NSData, NSString};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::debug;
+
+use crate::error::ClipboardError;
+use crate::ClipboardProvider;
+
+/// How often `watch` checks the pasteboard's change count.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Pasteboard types in read preference order.
+const TYPES: [(&str, ClipboardFormat); 3] = [
+    ("public.png", ClipboardFormat::Png),
+    ("public.utf8-plain-text", ClipboardFormat::PlainText),
+    ("public.html", ClipboardFormat::Html),
+];
+
+fn pasteboard_type(format: ClipboardFormat) -> &'static str {
+    match format {
+        ClipboardFormat::PlainText => "public.utf8-plain-text",
+        ClipboardFormat::Html => "public.html",
+        ClipboardFormat::Png => "public.png",
+    }
+}
+
+fn change_count() -> isize {
+    autoreleasepool(|_| NSPasteboard::generalPasteboard().changeCount())
+}
+
+/// Read the pasteboard in the preferred format.
+fn read_content() -> Result<ClipboardContent, ClipboardError> {
+    autoreleasepool(|_| {
+        let pasteboard = NSPasteboard::generalPasteboard();
+        TYPES
+            .iter()
+            .find_map(|&(name, format)| {
+                let data = pasteboard.dataForType(&NSString::from_str(name))?;
+                Some(ClipboardContent {
+                    format,
+                    data: data.to_vec(),
+                })
+            })
+            .ok_or(ClipboardError::FormatUnavailable)
+    })
+}
+
+/// Replace the pasteboard contents.
+fn write_content(content: &ClipboardContent) -> Result<(), ClipboardError> {
+    if content.format != ClipboardFormat::Png && std::str::from_utf8(&content.data).is_err() {
+        return Err(ClipboardError::Malformed(format!(
+            "{:?} is not UTF-8",
+            content.format
+        )));
+    }
+    autoreleasepool(|_| {
+        let pasteboard = NSPasteboard::generalPasteboard();
+        pasteboard.clearContents();
+        let name = NSString::from_str(pasteboard_type(content.format));
+        let data = NSData::with_bytes(&content.data);
+        if pasteboard.setData_forType(Some(&*data), &name) {
+            Ok(())
+        } else {
+            Err(ClipboardError::AccessDenied)
+        }
+    })
+}
+
+/// macOS clipboard backend.
+pub struct MacosClipboard {
+    /// Task polling the change count, started by `watch`.
+    poller: Option<JoinHandle<()>>,
+}
+
+impl Default for MacosClipboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MacosClipboard {
+    pub fn new() -> Self {
+        Self { poller: None }
+    }
+}
+
+impl Drop for MacosClipboard {
+    fn drop(&mut self) {
+        if let Some(poller) = self.poller.take() {
+            poller.abort();
+        }
+    }
+}
+
+#[async_trait]
+impl ClipboardProvider for MacosClipboard {
+    async fn get(&self) -> Result<ClipboardContent, ClipboardError> {
+        read_content()
+    }
+
+    async fn set(&mut self, content: ClipboardContent) -> Result<(), ClipboardError> {
+        write_content(&content)
+    }
+
+    async fn available_formats(&self) -> Result<Vec<ClipboardFormat>, ClipboardError> {
+        let types: Vec<String> = autoreleasepool(|_| {
+            NSPasteboard::generalPasteboard()
+                .types()
+                .map(|types| types.iter().map(ToString::to_string).collect())
+                .unwrap_or_default()
+        });
+        Ok(TYPES
+            .iter()
+            .filter(|(name, _)| types.iter().any(|t| t == *name))
+            .map(|&(_, format)| format)
+            .collect())
+    }
+
+    async fn watch(&mut self) -> Result<mpsc::Receiver<ClipboardContent>, ClipboardError> {
+        if let Some(poller) = self.poller.take() {
+            poller.abort();
+        }
+        let (tx, rx) = mpsc::channel(16);
+        let mut last = change_count();
+        self.poller = Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(POLL_INTERVAL);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            loop {
+                interval.tick().await;
+                let count = change_count();
+                if count == last {
+                    continue;
+                }
+                last = count;
+                match read_content() {
+                    Ok(content) => {
+                        if tx.send(content).await.is_err() {
+                            break;
+                        }
+                    }
+                    // Emptied, or holding only types we don't share.
+                    Err(ClipboardError::FormatUnavailable) => {}
+                    Err(e) => debug!(error = %e, "failed to read changed pasteboard"),
+                }
+            }
+        }));
+        Ok(rx)
+    }
+}
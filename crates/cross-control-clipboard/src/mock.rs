@@ -0,0 +1,129 @@
+//! Mock clipboard backend for testing.
+
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use cross_control_types::{ClipboardContent, ClipboardFormat};
+use tokio::sync::mpsc;
+
+use crate::error::ClipboardError;
+use crate::ClipboardProvider;
+
+/// Shared state for observing and driving a `MockClipboard`.
+#[derive(Debug, Default)]
+struct MockClipboardState {
+    content: Option<ClipboardContent>,
+    watcher: Option<mpsc::Sender<ClipboardContent>>,
+}
+
+impl MockClipboardState {
+    /// Store content and notify the watcher, as a real clipboard would for
+    /// both local copies and remote `set` calls.
+    fn store(&mut self, content: ClipboardContent) {
+        if let Some(watcher) = &self.watcher {
+            let _ = watcher.try_send(content.clone());
+        }
+        self.content = Some(content);
+    }
+}
+
+/// Mock clipboard backend for testing.
+pub struct MockClipboard {
+    state: Arc<Mutex<MockClipboardState>>,
+}
+
+impl Default for MockClipboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockClipboard {
+    /// Create an empty mock clipboard.
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(MockClipboardState::default())),
+        }
+    }
+
+    /// Get a clonable handle for driving and observing the clipboard from tests.
+    pub fn handle(&self) -> MockClipboardHandle {
+        MockClipboardHandle {
+            state: Arc::clone(&self.state),
+        }
+    }
+}
+
+/// Clonable handle for `MockClipboard`.
+///
+/// Tests use this to simulate a local copy and to inspect the clipboard.
+#[derive(Clone)]
+pub struct MockClipboardHandle {
+    state: Arc<Mutex<MockClipboardState>>,
+}
+
+impl MockClipboardHandle {
+    /// Simulate the user copying `content` on this machine.
+    pub fn copy(&self, content: ClipboardContent) {
+        self.state.lock().unwrap().store(content);
+    }
+
+    /// Get the current clipboard content.
+    pub fn contents(&self) -> Option<ClipboardContent> {
+        self.state.lock().unwrap().content.clone()
+    }
+}
+
+#[async_trait]
+impl ClipboardProvider for MockClipboard {
+    async fn get(&self) -> Result<ClipboardContent, ClipboardError> {
+        self.state
+            .lock()
+            .unwrap()
+            .content
+            .clone()
+            .ok_or(ClipboardError::FormatUnavailable)
+    }
+
+    async fn set(&mut self, content: ClipboardContent) -> Result<(), ClipboardError> {
+        self.state.lock().unwrap().store(content);
+        Ok(())
+    }
+
+    async fn available_formats(&self) -> Result<Vec<ClipboardFormat>, ClipboardError> {
+        Ok(self
+            .state
+            .lock()
+            .unwrap()
+            .content
+            .iter()
+            .map(|c| c.format)
+            .collect())
+    }
+
+    async fn watch(&mut self) -> Result<mpsc::Receiver<ClipboardContent>, ClipboardError> {
+        let (tx, rx) = mpsc::channel(16);
+        self.state.lock().unwrap().watcher = Some(tx);
+        Ok(rx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn copy_notifies_watcher() {
+        let mut clipboard = MockClipboard::new();
+        let handle = clipboard.handle();
+        let mut rx = clipboard.watch().await.unwrap();
+
+        handle.copy(ClipboardContent::text("hello"));
+        assert_eq!(rx.recv().await.unwrap().as_text(), Some("hello"));
+        assert_eq!(clipboard.get().await.unwrap().as_text(), Some("hello"));
+        assert_eq!(
+            clipboard.available_formats().await.unwrap(),
+            vec![ClipboardFormat::PlainText]
+        );
+    }
+}
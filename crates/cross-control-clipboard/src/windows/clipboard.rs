@@ -0,0 +1,267 @@
+//! Win32 clipboard access and change notifications, through the safe
+//! wrappers in `clipboard-win`.
+//!
+//! Reads prefer an image (`PNG`, then `CF_DIB`), then `CF_UNICODETEXT`, then
+//! `HTML Format`: every application that copies HTML also copies text, and
+//! text is what most paste targets on the other machine can use. Writes put
+//! an image on the clipboard in both image formats, so applications that
+//! only understand bitmaps can paste it too.
+//!
+//! Changes are reported by a thread that owns a `clipboard_win::Monitor`,
+//! a message-only window registered with `AddClipboardFormatListener`.
+
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use clipboard_win::formats::{CF_DIB, CF_UNICODETEXT};
+use clipboard_win::monitor::Shutdown;
+use clipboard_win::{raw, Clipboard, Monitor};
+use cross_control_types::{ClipboardContent, ClipboardFormat};
+use tokio::sync::{mpsc, oneshot};
+use tracing::debug;
+
+use super::format;
+use crate::error::ClipboardError;
+use crate::ClipboardProvider;
+
+/// Attempts to open the clipboard while another application holds it.
+const OPEN_ATTEMPTS: u32 = 10;
+
+/// Delay between attempts to open the clipboard.
+const OPEN_RETRY: Duration = Duration::from_millis(10);
+
+/// IDs of the registered clipboard formats, assigned by the window station.
+#[derive(Debug, Clone, Copy)]
+struct Formats {
+    html: u32,
+    png: u32,
+}
+
+impl Formats {
+    fn register() -> Result<Self, ClipboardError> {
+        let (Some(html), Some(png)) = (
+            raw::register_format("HTML Format"),
+            raw::register_format("PNG"),
+        ) else {
+            return Err(ClipboardError::Unavailable);
+        };
+        Ok(Self {
+            html: html.get(),
+            png: png.get(),
+        })
+    }
+}
+
+/// The clipboard, opened by this thread and closed on drop.
+struct OpenClipboardGuard(Clipboard);
+
+// `read` and `write` take `&self` as proof that the clipboard is open.
+#[allow(clippy::unused_self)]
+impl OpenClipboardGuard {
+    /// Open the clipboard, retrying briefly while another application has
+    /// it open.
+    fn open() -> Result<Self, ClipboardError> {
+        for _ in 0..OPEN_ATTEMPTS {
+            if let Ok(clipboard) = Clipboard::new() {
+                return Ok(Self(clipboard));
+            }
+            std::thread::sleep(OPEN_RETRY);
+        }
+        Err(ClipboardError::AccessDenied)
+    }
+
+    /// The clipboard data in `format`, if there is any.
+    fn read(&self, format: u32) -> Option<Vec<u8>> {
+        let mut data = Vec::new();
+        raw::get_vec(format, &mut data).ok()?;
+        Some(data)
+    }
+
+    /// Empty the clipboard, taking ownership of it.
+    fn empty(&self) -> Result<(), ClipboardError> {
+        raw::empty().map_err(|_| ClipboardError::AccessDenied)
+    }
+
+    /// Put `data` on the clipboard as `format`, next to what is there.
+    fn write(&self, format: u32, data: &[u8]) -> Result<(), ClipboardError> {
+        raw::set_without_clear(format, data).map_err(|_| ClipboardError::AccessDenied)
+    }
+}
+
+/// Read the clipboard in the preferred format.
+fn read_content(formats: Formats) -> Result<ClipboardContent, ClipboardError> {
+    let clipboard = OpenClipboardGuard::open()?;
+    if let Some(data) = clipboard.read(formats.png) {
+        return Ok(ClipboardContent {
+            format: ClipboardFormat::Png,
+            data,
+        });
+    }
+    if let Some(dib) = clipboard.read(CF_DIB) {
+        return Ok(ClipboardContent {
+            format: ClipboardFormat::Png,
+            data: format::dib_to_png(&dib)?,
+        });
+    }
+    if let Some(bytes) = clipboard.read(CF_UNICODETEXT) {
+        let wide: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        return Ok(ClipboardContent::text(&format::utf16_to_text(&wide)));
+    }
+    if let Some(cf_html) = clipboard.read(formats.html) {
+        return Ok(ClipboardContent {
+            format: ClipboardFormat::Html,
+            data: format::cf_html_fragment(&cf_html)?.into_bytes(),
+        });
+    }
+    Err(ClipboardError::FormatUnavailable)
+}
+
+/// Replace the clipboard contents.
+fn write_content(formats: Formats, content: &ClipboardContent) -> Result<(), ClipboardError> {
+    let utf8 = |data: &[u8]| {
+        std::str::from_utf8(data)
+            .map(str::to_owned)
+            .map_err(|_| ClipboardError::Malformed(format!("{:?} is not UTF-8", content.format)))
+    };
+    // Convert before opening the clipboard, so bad content leaves it as is.
+    let entries = match content.format {
+        ClipboardFormat::PlainText => {
+            let wide = format::text_to_utf16(&utf8(&content.data)?);
+            let bytes = wide.iter().flat_map(|c| c.to_le_bytes()).collect();
+            vec![(CF_UNICODETEXT, bytes)]
+        }
+        ClipboardFormat::Html => {
+            vec![(formats.html, format::html_to_cf_html(&utf8(&content.data)?))]
+        }
+        ClipboardFormat::Png => vec![
+            (formats.png, content.data.clone()),
+            (CF_DIB, format::png_to_dib(&content.data)?),
+        ],
+    };
+
+    let clipboard = OpenClipboardGuard::open()?;
+    clipboard.empty()?;
+    for (format, data) in entries {
+        clipboard.write(format, &data)?;
+    }
+    Ok(())
+}
+
+/// Read the clipboard on every change, until the receiver goes away or
+/// the [`Shutdown`] handed back through `ready` is dropped.
+fn listen(
+    formats: Formats,
+    tx: &mpsc::Sender<ClipboardContent>,
+    ready: oneshot::Sender<Result<Shutdown, ClipboardError>>,
+) {
+    // The monitor's message-only window belongs to this thread.
+    let mut monitor = match Monitor::new() {
+        Ok(monitor) => monitor,
+        Err(e) => {
+            debug!(error = %e, "failed to listen for clipboard changes");
+            let _ = ready.send(Err(ClipboardError::Unavailable));
+            return;
+        }
+    };
+    if ready.send(Ok(monitor.shutdown_channel())).is_err() {
+        return;
+    }
+
+    while let Ok(true) = monitor.recv() {
+        match read_content(formats) {
+            Ok(content) => {
+                if tx.blocking_send(content).is_err() {
+                    break;
+                }
+            }
+            // Emptied, or holding only formats we don't share.
+            Err(ClipboardError::FormatUnavailable) => {}
+            Err(e) => debug!(error = %e, "failed to read changed clipboard"),
+        }
+    }
+}
+
+/// Windows clipboard backend.
+///
+/// Shares text (`CF_UNICODETEXT`), HTML (`HTML Format`) and images (`PNG`
+/// and `CF_DIB`).
+pub struct WindowsClipboard {
+    formats: Formats,
+    /// Stops the change listener started by `watch` when dropped. Behind
+    /// a mutex only because the handle isn't `Sync`.
+    listener: Mutex<Option<Shutdown>>,
+}
+
+impl WindowsClipboard {
+    /// Register the clipboard formats this backend uses.
+    pub fn new() -> Result<Self, ClipboardError> {
+        Ok(Self {
+            formats: Formats::register()?,
+            listener: Mutex::new(None),
+        })
+    }
+
+    fn stop_listener(&mut self) {
+        // Dropping the handle wakes the listener and tells it to stop; a
+        // listener that has already exited ignores it.
+        self.set_listener(None);
+    }
+
+    fn set_listener(&mut self, shutdown: Option<Shutdown>) {
+        *self
+            .listener
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner) = shutdown;
+    }
+}
+
+impl Drop for WindowsClipboard {
+    fn drop(&mut self) {
+        self.stop_listener();
+    }
+}
+
+#[async_trait]
+impl ClipboardProvider for WindowsClipboard {
+    async fn get(&self) -> Result<ClipboardContent, ClipboardError> {
+        read_content(self.formats)
+    }
+
+    async fn set(&mut self, content: ClipboardContent) -> Result<(), ClipboardError> {
+        write_content(self.formats, &content)
+    }
+
+    async fn available_formats(&self) -> Result<Vec<ClipboardFormat>, ClipboardError> {
+        // Querying availability needs no open clipboard.
+        let available = raw::is_format_avail;
+        let mut formats = Vec::new();
+        if available(CF_UNICODETEXT) {
+            formats.push(ClipboardFormat::PlainText);
+        }
+        if available(self.formats.html) {
+            formats.push(ClipboardFormat::Html);
+        }
+        if available(self.formats.png) || available(CF_DIB) {
+            formats.push(ClipboardFormat::Png);
+        }
+        Ok(formats)
+    }
+
+    async fn watch(&mut self) -> Result<mpsc::Receiver<ClipboardContent>, ClipboardError> {
+        self.stop_listener();
+        let (tx, rx) = mpsc::channel(16);
+        let (ready_tx, ready_rx) = oneshot::channel();
+        let formats = self.formats;
+        std::thread::Builder::new()
+            .name("clipboard-listener".to_string())
+            .spawn(move || listen(formats, &tx, ready_tx))
+            .map_err(anyhow::Error::from)?;
+        let shutdown = ready_rx.await.map_err(|_| ClipboardError::Unavailable)??;
+        self.set_listener(Some(shutdown));
+        Ok(rx)
+    }
+}
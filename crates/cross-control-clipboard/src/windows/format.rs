@@ -0,0 +1,328 @@
+//! Conversions between Windows clipboard formats and [`ClipboardFormat`]s.
+//!
+//! | Windows format              | [`ClipboardFormat`] |
+//! |-----------------------------|---------------------|
+//! | `CF_UNICODETEXT`            | `PlainText`         |
+//! | `HTML Format` (`CF_HTML`)   | `Html`              |
+//! | `PNG`, `CF_DIB`             | `Png`               |
+//!
+//! These are plain byte conversions, kept apart from the Win32 calls so they
+//! can be tested on any platform.
+//!
+//! [`ClipboardFormat`]: cross_control_types::ClipboardFormat
+
+use crate::error::ClipboardError;
+
+/// `BI_RGB`: uncompressed bitmap.
+const BI_RGB: u32 = 0;
+/// `BI_BITFIELDS`: uncompressed, with explicit colour masks.
+const BI_BITFIELDS: u32 = 3;
+/// Size of a `BITMAPINFOHEADER`.
+const INFO_HEADER_SIZE: u32 = 40;
+
+/// `CF_UNICODETEXT` payload: UTF-16 with a terminating NUL.
+pub fn text_to_utf16(text: &str) -> Vec<u16> {
+    text.encode_utf16().chain(std::iter::once(0)).collect()
+}
+
+/// Text from a `CF_UNICODETEXT` payload, up to the first NUL.
+pub fn utf16_to_text(wide: &[u16]) -> String {
+    let end = wide.iter().position(|&c| c == 0).unwrap_or(wide.len());
+    String::from_utf16_lossy(&wide[..end])
+}
+
+/// Wrap an HTML fragment in the `CF_HTML` envelope: a header of byte offsets
+/// followed by a minimal document, NUL-terminated.
+pub fn html_to_cf_html(fragment: &str) -> Vec<u8> {
+    const HEADER: &str = "Version:0.9\r\n\
+                          StartHTML:0000000000\r\n\
+                          EndHTML:0000000000\r\n\
+                          StartFragment:0000000000\r\n\
+                          EndFragment:0000000000\r\n";
+    const PREFIX: &str = "<html><body>\r\n<!--StartFragment-->";
+    const SUFFIX: &str = "<!--EndFragment-->\r\n</body></html>";
+
+    let start_html = HEADER.len();
+    let start_fragment = start_html + PREFIX.len();
+    let end_fragment = start_fragment + fragment.len();
+    let end_html = end_fragment + SUFFIX.len();
+    let mut out = format!(
+        "Version:0.9\r\n\
+         StartHTML:{start_html:010}\r\n\
+         EndHTML:{end_html:010}\r\n\
+         StartFragment:{start_fragment:010}\r\n\
+         EndFragment:{end_fragment:010}\r\n\
+         {PREFIX}{fragment}{SUFFIX}"
+    )
+    .into_bytes();
+    out.push(0);
+    out
+}
+
+/// The fragment of a `CF_HTML` payload, located by its header offsets.
+pub fn cf_html_fragment(data: &[u8]) -> Result<String, ClipboardError> {
+    let malformed = |reason: &str| ClipboardError::Malformed(format!("CF_HTML: {reason}"));
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    let data = &data[..end];
+    let offset = |key: &str| {
+        data.split(|&b| b == b'\n')
+            .take_while(|line| !line.starts_with(b"<"))
+            .find_map(|line| {
+                let line = std::str::from_utf8(line).ok()?.trim_end();
+                line.strip_prefix(key)?
+                    .strip_prefix(':')?
+                    .parse::<usize>()
+                    .ok()
+            })
+    };
+    let start = offset("StartFragment").ok_or_else(|| malformed("no StartFragment"))?;
+    let end = offset("EndFragment").ok_or_else(|| malformed("no EndFragment"))?;
+    let fragment = data
+        .get(start..end)
+        .ok_or_else(|| malformed("fragment offsets out of range"))?;
+    String::from_utf8(fragment.to_vec()).map_err(|_| malformed("fragment is not UTF-8"))
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+/// Encode a `CF_DIB` payload (a `BITMAPINFO` followed by pixels) as PNG.
+///
+/// Handles the uncompressed 24- and 32-bit bitmaps that applications put on
+/// the clipboard. A 32-bit bitmap whose alpha bytes are all zero is treated
+/// as opaque, since most applications leave them unset.
+pub fn dib_to_png(dib: &[u8]) -> Result<Vec<u8>, ClipboardError> {
+    let malformed = |reason: &str| ClipboardError::Malformed(format!("CF_DIB: {reason}"));
+    let truncated = || malformed("truncated header");
+
+    let header_size = u32_at(dib, 0).ok_or_else(truncated)?;
+    if header_size < INFO_HEADER_SIZE {
+        return Err(malformed("unsupported header"));
+    }
+    let width = u32_at(dib, 4).ok_or_else(truncated)?;
+    let height = u32_at(dib, 8).ok_or_else(truncated)?;
+    let bit_count = u16_at(dib, 14).ok_or_else(truncated)?;
+    let compression = u32_at(dib, 16).ok_or_else(truncated)?;
+
+    // Width is signed in the header but never negative; a negative height
+    // means rows are stored top-down.
+    let width = i32::from_le_bytes(width.to_le_bytes());
+    let height = i32::from_le_bytes(height.to_le_bytes());
+    let top_down = height < 0;
+    let width = usize::try_from(width).map_err(|_| malformed("negative width"))?;
+    let height = height.unsigned_abs() as usize;
+
+    let mut pixels_at = header_size as usize;
+    match (bit_count, compression) {
+        (24 | 32, BI_RGB) => {}
+        (32, BI_BITFIELDS) => {
+            // A plain BITMAPINFOHEADER is followed by the three masks; the
+            // larger headers carry them inside.
+            let masks_at = INFO_HEADER_SIZE as usize;
+            let masks = (0..3)
+                .map(|i| u32_at(dib, masks_at + 4 * i))
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(truncated)?;
+            if masks != [0x00FF_0000, 0x0000_FF00, 0x0000_00FF] {
+                return Err(malformed("unsupported colour masks"));
+            }
+            if header_size == INFO_HEADER_SIZE {
+                pixels_at += 12;
+            }
+        }
+        _ => {
+            return Err(malformed(&format!(
+                "unsupported {bit_count}-bit bitmap (compression {compression})"
+            )))
+        }
+    }
+
+    let bytes_per_pixel = usize::from(bit_count / 8);
+    let stride = (width * bytes_per_pixel).div_ceil(4) * 4;
+    let pixels = dib
+        .get(pixels_at..)
+        .filter(|p| p.len() >= stride * height)
+        .ok_or_else(|| malformed("truncated pixel data"))?;
+
+    let opaque = bytes_per_pixel == 3
+        || (0..height).all(|y| {
+            pixels[y * stride..y * stride + width * 4]
+                .chunks_exact(4)
+                .all(|px| px[3] == 0)
+        });
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    for y in 0..height {
+        let row = if top_down { y } else { height - 1 - y };
+        let row = &pixels[row * stride..row * stride + width * bytes_per_pixel];
+        for px in row.chunks_exact(bytes_per_pixel) {
+            let alpha = if opaque { 0xFF } else { px[3] };
+            rgba.extend_from_slice(&[px[2], px[1], px[0], alpha]);
+        }
+    }
+
+    let mut out = Vec::new();
+    let dimension = |n: usize| u32::try_from(n).map_err(|_| malformed("bitmap too large"));
+    let mut encoder = png::Encoder::new(&mut out, dimension(width)?, dimension(height)?);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&rgba))
+        .map_err(|e| ClipboardError::Other(e.into()))?;
+    Ok(out)
+}
+
+/// Decode a PNG into a `CF_DIB` payload: a 32-bit bottom-up `BI_RGB`
+/// bitmap.
+pub fn png_to_dib(data: &[u8]) -> Result<Vec<u8>, ClipboardError> {
+    let malformed = |reason: String| ClipboardError::Malformed(format!("PNG: {reason}"));
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| malformed(e.to_string()))?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let frame = reader
+        .next_frame(&mut buf)
+        .map_err(|e| malformed(e.to_string()))?;
+
+    let width = frame.width as usize;
+    let height = frame.height as usize;
+    let channels = frame.color_type.samples();
+    let to_i32 = |n: u32| i32::try_from(n).map_err(|_| malformed("image too large".to_string()));
+    let size_image =
+        u32::try_from(width * height * 4).map_err(|_| malformed("image too large".to_string()))?;
+
+    let mut dib = Vec::with_capacity(INFO_HEADER_SIZE as usize + width * height * 4);
+    dib.extend_from_slice(&INFO_HEADER_SIZE.to_le_bytes());
+    dib.extend_from_slice(&to_i32(frame.width)?.to_le_bytes());
+    dib.extend_from_slice(&to_i32(frame.height)?.to_le_bytes());
+    dib.extend_from_slice(&1u16.to_le_bytes()); // planes
+    dib.extend_from_slice(&32u16.to_le_bytes()); // bits per pixel
+    dib.extend_from_slice(&BI_RGB.to_le_bytes());
+    dib.extend_from_slice(&size_image.to_le_bytes());
+    dib.extend_from_slice(&[0; 16]); // resolution and palette counts
+
+    // Rows are stored bottom-up; 32-bit rows need no padding.
+    for row in buf[..frame.line_size * height]
+        .chunks_exact(frame.line_size)
+        .rev()
+    {
+        for px in row.chunks_exact(channels).take(width) {
+            let (r, g, b, a) = match *px {
+                [v] => (v, v, v, 0xFF),
+                [v, a] => (v, v, v, a),
+                [r, g, b] => (r, g, b, 0xFF),
+                [r, g, b, a] => (r, g, b, a),
+                _ => return Err(malformed("unexpected colour type".to_string())),
+            };
+            dib.extend_from_slice(&[b, g, r, a]);
+        }
+    }
+    Ok(dib)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utf16_roundtrip_stops_at_nul() {
+        let wide = text_to_utf16("héllo ✓");
+        assert_eq!(wide.last(), Some(&0));
+        assert_eq!(utf16_to_text(&wide), "héllo ✓");
+        assert_eq!(utf16_to_text(&[u16::from(b'a'), 0, u16::from(b'b')]), "a");
+    }
+
+    #[test]
+    fn cf_html_offsets_point_at_fragment() {
+        let fragment = "<b>bold</b> & <i>ünïcode</i>";
+        let cf_html = html_to_cf_html(fragment);
+        assert_eq!(cf_html.last(), Some(&0));
+        assert_eq!(cf_html_fragment(&cf_html).unwrap(), fragment);
+
+        let text = std::str::from_utf8(&cf_html[..cf_html.len() - 1]).unwrap();
+        let start_html: usize = text[text.find("StartHTML:").unwrap() + 10..][..10]
+            .parse()
+            .unwrap();
+        assert!(text[start_html..].starts_with("<html>"));
+        assert!(text.ends_with("</html>"));
+    }
+
+    #[test]
+    fn cf_html_without_offsets_is_rejected() {
+        assert!(cf_html_fragment(b"<html><body>hi</body></html>").is_err());
+        assert!(
+            cf_html_fragment(b"Version:0.9\r\nStartFragment:500\r\nEndFragment:600\r\n").is_err()
+        );
+    }
+
+    /// A 2x2 24-bit bottom-up DIB: red, green on the top row; blue, white
+    /// below.
+    fn dib_24() -> Vec<u8> {
+        let mut dib = Vec::new();
+        dib.extend_from_slice(&40u32.to_le_bytes());
+        dib.extend_from_slice(&2i32.to_le_bytes());
+        dib.extend_from_slice(&2i32.to_le_bytes());
+        dib.extend_from_slice(&1u16.to_le_bytes());
+        dib.extend_from_slice(&24u16.to_le_bytes());
+        dib.extend_from_slice(&BI_RGB.to_le_bytes());
+        dib.extend_from_slice(&[0; 20]);
+        // Bottom row first, BGR, padded to 8 bytes.
+        dib.extend_from_slice(&[0xFF, 0, 0, 0xFF, 0xFF, 0xFF, 0, 0]);
+        dib.extend_from_slice(&[0, 0, 0xFF, 0, 0xFF, 0, 0, 0]);
+        dib
+    }
+
+    fn decode_rgba(png: &[u8]) -> (u32, u32, Vec<u8>) {
+        let mut reader = png::Decoder::new(png).read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).unwrap();
+        assert_eq!(info.color_type, png::ColorType::Rgba);
+        (info.width, info.height, buf)
+    }
+
+    #[test]
+    fn dib_converts_to_png_top_row_first() {
+        let (width, height, rgba) = decode_rgba(&dib_to_png(&dib_24()).unwrap());
+        assert_eq!((width, height), (2, 2));
+        assert_eq!(
+            rgba,
+            [
+                [0xFF, 0, 0, 0xFF],
+                [0, 0xFF, 0, 0xFF],
+                [0, 0, 0xFF, 0xFF],
+                [0xFF, 0xFF, 0xFF, 0xFF]
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn png_and_dib_roundtrip() {
+        let png = dib_to_png(&dib_24()).unwrap();
+        let dib = png_to_dib(&png).unwrap();
+        assert_eq!(u16_at(&dib, 14), Some(32));
+        assert_eq!(dib.len(), 40 + 2 * 2 * 4);
+        assert_eq!(decode_rgba(&dib_to_png(&dib).unwrap()), decode_rgba(&png));
+    }
+
+    #[test]
+    fn unsupported_bitmaps_are_rejected() {
+        let mut dib = dib_24();
+        dib[14] = 8;
+        assert!(matches!(
+            dib_to_png(&dib),
+            Err(ClipboardError::Malformed(_))
+        ));
+        assert!(dib_to_png(&dib_24()[..50]).is_err());
+        assert!(png_to_dib(b"not a png").is_err());
+    }
+}
@@ -0,0 +1,9 @@
+//! Windows clipboard backend using the Win32 clipboard API.
+
+pub mod format;
+
+#[cfg(windows)]
+mod clipboard;
+
+#[cfg(windows)]
+pub use clipboard::WindowsClipboard;
@@ -0,0 +1,33 @@
+//! Embeds the git commit the daemon is built from, reported by `status`.
+
+use std::path::Path;
+use std::process::Command;
+
+fn main() {
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|commit| commit.trim().to_string())
+        .filter(|commit| !commit.is_empty())
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=CROSS_CONTROL_COMMIT={commit}");
+
+    // Rebuild when HEAD moves: HEAD itself changes on checkout, the branch
+    // ref it points to on commit.
+    let git = Path::new("../../.git");
+    let head = git.join("HEAD");
+    if head.exists() {
+        println!("cargo:rerun-if-changed={}", head.display());
+        if let Some(branch) = std::fs::read_to_string(&head)
+            .ok()
+            .and_then(|head| head.strip_prefix("ref: ").map(|r| r.trim().to_string()))
+        {
+            println!("cargo:rerun-if-changed={}", git.join(branch).display());
+        }
+    } else {
+        println!("cargo:rerun-if-changed=build.rs");
+    }
+}
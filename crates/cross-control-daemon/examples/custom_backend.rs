@@ -0,0 +1,270 @@
+//! Embedding the daemon with your own input backends.
+//!
+//! `ScriptedCapture` plays back a fixed list of input events instead of
+//! reading real devices, and `PrintingEmulation` prints what a peer sends
+//! instead of creating virtual devices. Any type implementing
+//! [`InputCapture`] and [`InputEmulation`] can be handed to
+//! [`Daemon::new`] the same way.
+//!
+//! ```bash
+//! cargo run -p cross-control-daemon --example custom_backend
+//! ```
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use cross_control_daemon::config::{
+    DaemonConfig, IdentityConfig, PointerScale, ScreenConfig, ScreenMode,
+};
+use cross_control_daemon::{Config, Daemon, DaemonEvent};
+use cross_control_input::{InputCapture, InputEmulation, InputError};
+use cross_control_protocol::{QuicTransport, Transport};
+use cross_control_types::{
+    Barrier, BarrierId, ButtonState, CapturedEvent, DeviceCapability, DeviceId, DeviceInfo,
+    InputEvent, KeyCode, MachineId, Position, VirtualDeviceId,
+};
+use tokio::sync::{mpsc, oneshot};
+
+/// Plays back `script` once `go` fires, one event every `interval`, then
+/// reports `done`.
+#[derive(Default)]
+struct ScriptedCapture {
+    script: Vec<CapturedEvent>,
+    interval: Duration,
+    go: Option<oneshot::Receiver<()>>,
+    done: Option<oneshot::Sender<()>>,
+    next_barrier: u32,
+}
+
+#[async_trait]
+impl InputCapture for ScriptedCapture {
+    async fn start(&mut self, tx: mpsc::Sender<CapturedEvent>) -> Result<(), InputError> {
+        let script = std::mem::take(&mut self.script);
+        let interval = self.interval;
+        let go = self.go.take();
+        let done = self.done.take();
+        tokio::spawn(async move {
+            if let Some(go) = go {
+                let _ = go.await;
+            }
+            for event in script {
+                tokio::time::sleep(interval).await;
+                if tx.send(event).await.is_err() {
+                    return;
+                }
+            }
+            if let Some(done) = done {
+                let _ = done.send(());
+            }
+        });
+        Ok(())
+    }
+
+    // The daemon tracks the cursor itself; a real backend would use
+    // barriers to confine the pointer.
+    async fn add_barrier(&mut self, _barrier: Barrier) -> Result<BarrierId, InputError> {
+        self.next_barrier += 1;
+        Ok(BarrierId(self.next_barrier))
+    }
+
+    async fn remove_barrier(&mut self, _id: BarrierId) -> Result<(), InputError> {
+        Ok(())
+    }
+
+    async fn grab(&mut self) -> Result<(), InputError> {
+        Ok(())
+    }
+
+    async fn release(&mut self) -> Result<(), InputError> {
+        Ok(())
+    }
+
+    async fn shutdown(&mut self) -> Result<(), InputError> {
+        Ok(())
+    }
+}
+
+/// Prints every device and event a peer sends.
+#[derive(Default)]
+struct PrintingEmulation {
+    devices: Vec<String>,
+}
+
+#[async_trait]
+impl InputEmulation for PrintingEmulation {
+    async fn create_device(&mut self, info: &DeviceInfo) -> Result<VirtualDeviceId, InputError> {
+        println!("virtual device {}: {}", self.devices.len(), info.name);
+        self.devices.push(info.name.clone());
+        let id = u32::try_from(self.devices.len() - 1).expect("too many devices");
+        Ok(VirtualDeviceId(id))
+    }
+
+    async fn inject(
+        &mut self,
+        device: VirtualDeviceId,
+        event: InputEvent,
+    ) -> Result<(), InputError> {
+        let name = usize::try_from(device.0)
+            .ok()
+            .and_then(|i| self.devices.get(i))
+            .ok_or_else(|| InputError::Inject(format!("unknown device {device:?}")))?;
+        println!("{name}: {event:?}");
+        Ok(())
+    }
+
+    async fn destroy_device(&mut self, _device: VirtualDeviceId) -> Result<(), InputError> {
+        Ok(())
+    }
+
+    async fn shutdown(&mut self) -> Result<(), InputError> {
+        Ok(())
+    }
+}
+
+/// No-op emulation for the machine that only sends input.
+struct NoEmulation;
+
+#[async_trait]
+impl InputEmulation for NoEmulation {
+    async fn create_device(&mut self, _info: &DeviceInfo) -> Result<VirtualDeviceId, InputError> {
+        Ok(VirtualDeviceId(0))
+    }
+
+    async fn inject(
+        &mut self,
+        _device: VirtualDeviceId,
+        _event: InputEvent,
+    ) -> Result<(), InputError> {
+        Ok(())
+    }
+
+    async fn destroy_device(&mut self, _device: VirtualDeviceId) -> Result<(), InputError> {
+        Ok(())
+    }
+
+    async fn shutdown(&mut self) -> Result<(), InputError> {
+        Ok(())
+    }
+}
+
+fn config(name: &str, neighbour: &str, position: Position, address: Option<SocketAddr>) -> Config {
+    Config {
+        daemon: DaemonConfig {
+            screen_width: 1920,
+            screen_height: 1080,
+            ..DaemonConfig::default()
+        },
+        identity: IdentityConfig {
+            name: name.to_string(),
+        },
+        screens: vec![ScreenConfig {
+            name: neighbour.to_string(),
+            group: None,
+            address: address.map(|a| a.to_string()),
+            position,
+            fingerprint: None,
+            mode: ScreenMode::Control,
+            wake_on_enter: false,
+            locate_on_enter: false,
+            pointer_scale: PointerScale::default(),
+            share_devices: None,
+            clipboard: true,
+            wait_on_start: false,
+            commands: Vec::new(),
+            release_hotkey: None,
+        }],
+        ..Config::default()
+    }
+}
+
+/// Move right until the edge is crossed, then type "ok".
+fn script() -> Vec<CapturedEvent> {
+    let captured = |device_id, event| CapturedEvent {
+        device_id: DeviceId(device_id),
+        timestamp_us: 0,
+        event,
+    };
+    let mut script: Vec<_> = (0..3)
+        .map(|_| captured(2, InputEvent::MouseMove { dx: 600, dy: 0 }))
+        .collect();
+    for code in [KeyCode::KeyO, KeyCode::KeyK] {
+        for state in [ButtonState::Pressed, ButtonState::Released] {
+            script.push(captured(1, InputEvent::Key { code, state }));
+        }
+    }
+    script
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let loopback: SocketAddr = "127.0.0.1:0".parse()?;
+    let sender_cert = cross_control_certgen::generate_certificate("localhost")?;
+    let printer_cert = cross_control_certgen::generate_certificate("localhost")?;
+    let printer_transport =
+        QuicTransport::bind(loopback, &printer_cert.cert_pem, &printer_cert.key_pem)?;
+    let printer_addr = printer_transport.local_addr()?;
+    let sender_transport =
+        QuicTransport::bind(loopback, &sender_cert.cert_pem, &sender_cert.key_pem)?;
+
+    // The printer captures nothing: its script is empty.
+    let mut printer = Daemon::new(
+        config("printer", "sender", Position::Left, None),
+        MachineId::new(),
+        printer_transport,
+        Box::new(ScriptedCapture::default()),
+        Box::new(PrintingEmulation::default()),
+    );
+    let printer_events = printer.event_sender();
+    let printer_task = tokio::spawn(async move { printer.run().await });
+
+    let (go, start_script) = oneshot::channel();
+    let (done, script_done) = oneshot::channel();
+    let mut sender = Daemon::new(
+        config("sender", "printer", Position::Right, Some(printer_addr)),
+        MachineId::new(),
+        sender_transport,
+        Box::new(ScriptedCapture {
+            script: script(),
+            interval: Duration::from_millis(100),
+            go: Some(start_script),
+            done: Some(done),
+            next_barrier: 0,
+        }),
+        Box::new(NoEmulation),
+    );
+    sender.set_local_devices(vec![
+        DeviceInfo {
+            id: DeviceId(1),
+            name: "Scripted Keyboard".to_string(),
+            capabilities: vec![DeviceCapability::Keyboard],
+            keys: Vec::new(),
+        },
+        DeviceInfo {
+            id: DeviceId(2),
+            name: "Scripted Mouse".to_string(),
+            capabilities: vec![DeviceCapability::RelativeMouse],
+            keys: Vec::new(),
+        },
+    ]);
+    let mut status = sender.status_receiver();
+    let sender_events = sender.event_sender();
+    let sender_task = tokio::spawn(async move { sender.run().await });
+
+    // Start the script once the two machines are connected.
+    tokio::time::timeout(
+        Duration::from_secs(5),
+        status.wait_for(|s| s.session_count == 1),
+    )
+    .await??;
+    let _ = go.send(());
+
+    tokio::time::timeout(Duration::from_secs(10), script_done).await??;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let _ = sender_events.send(DaemonEvent::Shutdown).await;
+    let _ = printer_events.send(DaemonEvent::Shutdown).await;
+    sender_task.await??;
+    printer_task.await??;
+    Ok(())
+}
@@ -0,0 +1,196 @@
+//! Two daemons on loopback, driven from code.
+//!
+//! Machine "left" dials machine "right" over QUIC, its mock mouse is pushed
+//! against the right edge so control crosses over, and a few key presses
+//! are injected on "right". Finally "left" is asked for its status over the
+//! same request channel the CLI uses.
+//!
+//! ```bash
+//! cargo run -p cross-control-daemon --example two_peer_loopback
+//! ```
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use cross_control_daemon::config::{
+    DaemonConfig, IdentityConfig, PointerScale, ScreenConfig, ScreenMode,
+};
+use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+use cross_control_daemon::{Config, Daemon, DaemonEvent, DaemonStatus};
+use cross_control_input::mock::{MockCapture, MockEmulation};
+use cross_control_protocol::{QuicTransport, Transport};
+use cross_control_types::{
+    ButtonState, CapturedEvent, DeviceCapability, DeviceId, DeviceInfo, InputEvent, KeyCode,
+    MachineId, Position,
+};
+use tokio::sync::{oneshot, watch};
+
+/// A config for `name` with one neighbour, pinned to its certificate.
+fn config(
+    name: &str,
+    neighbour: &str,
+    position: Position,
+    address: Option<SocketAddr>,
+    fingerprint: &str,
+) -> Config {
+    Config {
+        daemon: DaemonConfig {
+            screen_width: 1920,
+            screen_height: 1080,
+            ..DaemonConfig::default()
+        },
+        identity: IdentityConfig {
+            name: name.to_string(),
+        },
+        screens: vec![ScreenConfig {
+            name: neighbour.to_string(),
+            group: None,
+            address: address.map(|a| a.to_string()),
+            position,
+            fingerprint: Some(fingerprint.to_string()),
+            mode: ScreenMode::Control,
+            wake_on_enter: false,
+            locate_on_enter: false,
+            pointer_scale: PointerScale::default(),
+            share_devices: None,
+            clipboard: true,
+            wait_on_start: false,
+            commands: Vec::new(),
+            release_hotkey: None,
+        }],
+        ..Config::default()
+    }
+}
+
+fn devices() -> Vec<DeviceInfo> {
+    vec![
+        DeviceInfo {
+            id: DeviceId(1),
+            name: "Example Keyboard".to_string(),
+            capabilities: vec![DeviceCapability::Keyboard],
+            keys: Vec::new(),
+        },
+        DeviceInfo {
+            id: DeviceId(2),
+            name: "Example Mouse".to_string(),
+            capabilities: vec![DeviceCapability::RelativeMouse],
+            keys: Vec::new(),
+        },
+    ]
+}
+
+async fn wait_until(
+    status: &mut watch::Receiver<DaemonStatus>,
+    what: &str,
+    done: impl Fn(&DaemonStatus) -> bool,
+) -> anyhow::Result<()> {
+    tokio::time::timeout(Duration::from_secs(5), status.wait_for(done))
+        .await
+        .map_err(|_| anyhow::anyhow!("timed out waiting for {what}"))??;
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let loopback: SocketAddr = "127.0.0.1:0".parse()?;
+    let left_cert = cross_control_certgen::generate_certificate("localhost")?;
+    let right_cert = cross_control_certgen::generate_certificate("localhost")?;
+    let left_transport = QuicTransport::bind(loopback, &left_cert.cert_pem, &left_cert.key_pem)?;
+    let right_transport = QuicTransport::bind(loopback, &right_cert.cert_pem, &right_cert.key_pem)?;
+    let right_addr = right_transport.local_addr()?;
+
+    // "right" only listens; what it would type and click is recorded by
+    // `MockEmulation`.
+    let (right_capture, _) = MockCapture::new();
+    let right_emulation = MockEmulation::new();
+    let injected = right_emulation.handle();
+    let mut right = Daemon::new(
+        config(
+            "right",
+            "left",
+            Position::Left,
+            None,
+            &left_cert.fingerprint,
+        ),
+        MachineId::new(),
+        right_transport,
+        Box::new(right_capture),
+        Box::new(right_emulation),
+    );
+    right.set_local_devices(devices());
+    let right_events = right.event_sender();
+    let right_task = tokio::spawn(async move { right.run().await });
+
+    // "left" dials "right", and its input is fed through `feed`.
+    let (left_capture, feed) = MockCapture::new();
+    let mut left = Daemon::new(
+        config(
+            "left",
+            "right",
+            Position::Right,
+            Some(right_addr),
+            &right_cert.fingerprint,
+        ),
+        MachineId::new(),
+        left_transport,
+        Box::new(left_capture),
+        Box::new(MockEmulation::new()),
+    );
+    left.set_local_devices(devices());
+    let mut status = left.status_receiver();
+    let left_events = left.event_sender();
+    let left_task = tokio::spawn(async move { left.run().await });
+
+    wait_until(&mut status, "the session", |s| s.session_count == 1).await?;
+    println!("left and right are connected");
+
+    // The cursor starts in the middle; large moves push it past the edge.
+    for _ in 0..3 {
+        let event = InputEvent::MouseMove { dx: 600, dy: 0 };
+        feed.send(CapturedEvent {
+            device_id: DeviceId(2),
+            timestamp_us: 0,
+            event,
+        })
+        .await?;
+    }
+    wait_until(&mut status, "the crossing", |s| s.controlling.is_some()).await?;
+    println!("left now controls right");
+
+    for code in [KeyCode::KeyH, KeyCode::KeyI] {
+        for state in [ButtonState::Pressed, ButtonState::Released] {
+            let event = InputEvent::Key { code, state };
+            feed.send(CapturedEvent {
+                device_id: DeviceId(1),
+                timestamp_us: 0,
+                event,
+            })
+            .await?;
+        }
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    for injected in injected.injected_events() {
+        println!("right injected {:?}", injected.event);
+    }
+
+    let (reply, response) = oneshot::channel();
+    left_events
+        .send(DaemonEvent::Ipc {
+            request: IpcRequest::Status,
+            reply,
+        })
+        .await
+        .map_err(|_| anyhow::anyhow!("left has stopped"))?;
+    if let IpcResponse::Status(report) = response.await? {
+        println!(
+            "left forwarded {} events over {} crossing(s)",
+            report.counters.events_forwarded, report.counters.crossings
+        );
+    }
+
+    let _ = left_events.send(DaemonEvent::Shutdown).await;
+    let _ = right_events.send(DaemonEvent::Shutdown).await;
+    left_task.await??;
+    right_task.await??;
+    Ok(())
+}
@@ -0,0 +1,254 @@
+//! Link quality alarms.
+//!
+//! Each tick, every session's latest keepalive round-trip time and recent
+//! keepalive loss are checked against the `[alarms]` thresholds. A link that
+//! stays over a threshold for `alarms.for_secs` raises an alarm, which is
+//! logged, listed by `status`, and announced with a desktop notification and
+//! `alarms.command`. The alarm clears as soon as the link is back under its
+//! thresholds.
+
+use std::time::{Duration, Instant};
+
+use tracing::{debug, warn};
+
+use crate::config::AlarmThresholds;
+use crate::supervisor;
+
+/// A raised or cleared alarm.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlarmChange {
+    /// The link has been over a threshold for the hold time; carries what
+    /// is over.
+    Raised(String),
+    Cleared,
+}
+
+/// Alarm state for one session.
+#[derive(Debug, Default)]
+pub struct LinkAlarm {
+    /// When the link first went over a threshold, while it still is.
+    breached_since: Option<Instant>,
+    /// What is over threshold, once the alarm is raised.
+    raised: Option<String>,
+}
+
+impl LinkAlarm {
+    /// What the raised alarm is about, if there is one.
+    pub fn raised(&self) -> Option<&str> {
+        self.raised.as_deref()
+    }
+
+    /// Record the link's `breach` (from [`breach`]) at `now`, and report
+    /// whether the alarm was raised or cleared by it.
+    pub fn update(
+        &mut self,
+        breach: Option<String>,
+        hold: Duration,
+        now: Instant,
+    ) -> Option<AlarmChange> {
+        let Some(breach) = breach else {
+            self.breached_since = None;
+            return self.raised.take().map(|_| AlarmChange::Cleared);
+        };
+        let since = *self.breached_since.get_or_insert(now);
+        if self.raised.is_some() {
+            self.raised = Some(breach);
+            None
+        } else if now.duration_since(since) >= hold {
+            self.raised = Some(breach.clone());
+            Some(AlarmChange::Raised(breach))
+        } else {
+            None
+        }
+    }
+
+    /// Start timing a breach over, as after a clock jump; a raised alarm
+    /// stays raised.
+    pub fn restart_hold(&mut self) {
+        self.breached_since = None;
+    }
+}
+
+/// Describe what is over `thresholds` for a link with the given latest
+/// round-trip time and loss, or `None` if nothing is.
+pub fn breach(
+    thresholds: &AlarmThresholds,
+    rtt: Option<Duration>,
+    loss_percent: Option<f64>,
+) -> Option<String> {
+    let mut over = Vec::new();
+    if let (Some(limit), Some(rtt)) = (thresholds.rtt_ms, rtt) {
+        if rtt > Duration::from_millis(limit) {
+            over.push(format!("RTT {}ms > {limit}ms", rtt.as_millis()));
+        }
+    }
+    if let (Some(limit), Some(loss)) = (thresholds.loss_percent, loss_percent) {
+        if loss > limit {
+            over.push(format!("loss {loss:.1}% > {limit}%"));
+        }
+    }
+    (!over.is_empty()).then(|| over.join(", "))
+}
+
+/// Show `change` for `peer` as a desktop notification. Without a
+/// notification service this only logs at debug level.
+pub fn notify(peer: &str, change: &AlarmChange) {
+    let (summary, body) = match change {
+        AlarmChange::Raised(reason) => (format!("Link to {peer} degraded"), reason.clone()),
+        AlarmChange::Cleared => (
+            format!("Link to {peer} recovered"),
+            "Back under the alarm thresholds".to_string(),
+        ),
+    };
+    supervisor::spawn("alarm-notify", async move {
+        #[cfg(all(feature = "linux", target_os = "linux"))]
+        if let Err(e) = desktop::notify(&summary, &body).await {
+            debug!(error = %e, "failed to show alarm notification");
+        }
+        #[cfg(not(all(feature = "linux", target_os = "linux")))]
+        debug!(%summary, %body, "no desktop notification service");
+    });
+}
+
+/// Run `alarms.command` for `change` on the link to `peer`.
+///
+/// The program (started without a shell, output discarded) finds the peer
+/// in `CROSS_CONTROL_PEER_NAME`, `raised` or `cleared` in
+/// `CROSS_CONTROL_ALARM`, and what is over threshold in
+/// `CROSS_CONTROL_ALARM_REASON` (empty when cleared).
+pub fn run_command(run: &[String], peer: &str, change: &AlarmChange) {
+    let Some((program, args)) = run.split_first() else {
+        return;
+    };
+    let (state, reason) = match change {
+        AlarmChange::Raised(reason) => ("raised", reason.as_str()),
+        AlarmChange::Cleared => ("cleared", ""),
+    };
+    let child = tokio::process::Command::new(program)
+        .args(args)
+        .env("CROSS_CONTROL_PEER_NAME", peer)
+        .env("CROSS_CONTROL_ALARM", state)
+        .env("CROSS_CONTROL_ALARM_REASON", reason)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            warn!(error = %e, "failed to start the alarm command");
+            return;
+        }
+    };
+    supervisor::spawn("alarm-command", async move {
+        match child.wait().await {
+            Ok(status) if status.success() => {}
+            Ok(status) => warn!(%status, "alarm command failed"),
+            Err(e) => warn!(error = %e, "failed to wait for the alarm command"),
+        }
+    });
+}
+
+#[cfg(all(feature = "linux", target_os = "linux"))]
+mod desktop {
+    use std::collections::HashMap;
+
+    use zbus::zvariant::Value;
+
+    #[zbus::proxy(
+        interface = "org.freedesktop.Notifications",
+        default_service = "org.freedesktop.Notifications",
+        default_path = "/org/freedesktop/Notifications"
+    )]
+    trait Notifications {
+        #[allow(clippy::too_many_arguments)]
+        fn notify(
+            &self,
+            app_name: &str,
+            replaces_id: u32,
+            app_icon: &str,
+            summary: &str,
+            body: &str,
+            actions: &[&str],
+            hints: HashMap<&str, Value<'_>>,
+            expire_timeout: i32,
+        ) -> zbus::Result<u32>;
+    }
+
+    pub async fn notify(summary: &str, body: &str) -> zbus::Result<()> {
+        let connection = zbus::Connection::session().await?;
+        let notifications = NotificationsProxy::new(&connection).await?;
+        notifications
+            .notify(
+                "cross-control",
+                0,
+                "",
+                summary,
+                body,
+                &[],
+                HashMap::new(),
+                -1,
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn thresholds() -> AlarmThresholds {
+        AlarmThresholds {
+            rtt_ms: Some(40),
+            loss_percent: Some(2.0),
+        }
+    }
+
+    #[test]
+    fn breach_names_what_is_over() {
+        let ms = Duration::from_millis;
+        assert_eq!(breach(&thresholds(), Some(ms(40)), Some(0.0)), None);
+        assert_eq!(breach(&thresholds(), None, None), None);
+        assert_eq!(
+            breach(&thresholds(), Some(ms(55)), Some(12.5)).as_deref(),
+            Some("RTT 55ms > 40ms, loss 12.5% > 2%")
+        );
+        assert_eq!(
+            breach(&AlarmThresholds::default(), Some(ms(500)), Some(50.0)),
+            None
+        );
+    }
+
+    #[test]
+    fn raised_after_hold_and_cleared_on_recovery() {
+        let hold = Duration::from_secs(10);
+        let start = Instant::now();
+        let over = || Some("RTT 55ms > 40ms".to_string());
+        let mut alarm = LinkAlarm::default();
+
+        assert_eq!(alarm.update(over(), hold, start), None);
+        assert_eq!(
+            alarm.update(over(), hold, start + Duration::from_secs(9)),
+            None
+        );
+        assert_eq!(
+            alarm.update(over(), hold, start + hold),
+            Some(AlarmChange::Raised("RTT 55ms > 40ms".to_string()))
+        );
+        assert_eq!(alarm.raised(), Some("RTT 55ms > 40ms"));
+        assert_eq!(alarm.update(over(), hold, start + 2 * hold), None);
+
+        let later = start + 3 * hold;
+        assert_eq!(alarm.update(None, hold, later), Some(AlarmChange::Cleared));
+        assert_eq!(alarm.raised(), None);
+        assert_eq!(alarm.update(None, hold, later), None);
+        // A brief spike doesn't raise it again.
+        assert_eq!(alarm.update(over(), hold, later), None);
+        assert_eq!(
+            alarm.update(None, hold, later + Duration::from_secs(1)),
+            None
+        );
+        assert_eq!(alarm.update(over(), hold, later + hold), None);
+    }
+}
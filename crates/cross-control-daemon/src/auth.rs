@@ -0,0 +1,162 @@
+//! Authentication hooks for inbound peers.
+//!
+//! Before answering an inbound `Hello`, the daemon can ask a hook whether
+//! to admit the peer, given the identity it claims, the fingerprint of its
+//! certificate and the address it connected from. Sites can then check
+//! peers against their own inventory or PKI. `security.auth_command` runs an
+//! external program as the hook; programs embedding the daemon can install
+//! their own with [`Daemon::set_auth_hook`](crate::Daemon::set_auth_hook).
+
+use std::net::SocketAddr;
+use std::process::Stdio;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use cross_control_types::MachineId;
+use tokio::io::AsyncReadExt;
+
+/// An inbound peer waiting to be admitted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerCandidate {
+    pub machine_id: MachineId,
+    pub name: String,
+    /// Fingerprint of the certificate the peer presented during TLS.
+    pub fingerprint: Option<String>,
+    pub address: SocketAddr,
+}
+
+/// Decides whether an inbound peer may complete its handshake.
+#[async_trait]
+pub trait AuthHook: Send + Sync {
+    /// `Ok` admits the peer. `Err` refuses it, and the reason is logged and
+    /// sent to the peer.
+    async fn authorize(&self, peer: &PeerCandidate) -> Result<(), String>;
+}
+
+/// Runs an external program for each inbound peer.
+///
+/// The peer is described in `CROSS_CONTROL_PEER_ID`,
+/// `CROSS_CONTROL_PEER_NAME`, `CROSS_CONTROL_PEER_FINGERPRINT` (empty if
+/// there is none) and `CROSS_CONTROL_PEER_ADDRESS`. Exit status 0 admits
+/// it; anything else refuses it, with the first line of standard output as
+/// the reason. A program that can't be started or runs past the timeout
+/// refuses the peer too.
+#[derive(Debug, Clone)]
+pub struct CommandHook {
+    run: Vec<String>,
+    timeout: Duration,
+}
+
+impl CommandHook {
+    /// Run `run` (program and arguments, without a shell), giving it
+    /// `timeout` to decide.
+    pub fn new(run: Vec<String>, timeout: Duration) -> Self {
+        Self { run, timeout }
+    }
+}
+
+#[async_trait]
+impl AuthHook for CommandHook {
+    async fn authorize(&self, peer: &PeerCandidate) -> Result<(), String> {
+        let (program, args) = self
+            .run
+            .split_first()
+            .ok_or_else(|| "the authentication command is empty".to_string())?;
+        let mut child = tokio::process::Command::new(program)
+            .args(args)
+            .env("CROSS_CONTROL_PEER_ID", peer.machine_id.to_string())
+            .env("CROSS_CONTROL_PEER_NAME", &peer.name)
+            .env(
+                "CROSS_CONTROL_PEER_FINGERPRINT",
+                peer.fingerprint.as_deref().unwrap_or_default(),
+            )
+            .env("CROSS_CONTROL_PEER_ADDRESS", peer.address.to_string())
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("failed to start the authentication command: {e}"))?;
+
+        let mut stdout = child.stdout.take();
+        let run = async {
+            let mut output = String::new();
+            if let Some(stdout) = &mut stdout {
+                let _ = stdout.read_to_string(&mut output).await;
+            }
+            let status = child.wait().await?;
+            Ok::<_, std::io::Error>((status, output))
+        };
+        let (status, output) = match tokio::time::timeout(self.timeout, run).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(e)) => return Err(format!("authentication command failed: {e}")),
+            Err(_) => {
+                return Err(format!(
+                    "authentication command gave no answer within {:?}",
+                    self.timeout
+                ))
+            }
+        };
+        if status.success() {
+            return Ok(());
+        }
+        let reason = output.lines().next().map(str::trim).unwrap_or_default();
+        if reason.is_empty() {
+            Err(format!("refused by the authentication command ({status})"))
+        } else {
+            Err(reason.to_string())
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn candidate() -> PeerCandidate {
+        PeerCandidate {
+            machine_id: MachineId::new(),
+            name: "laptop".to_string(),
+            fingerprint: Some("SHA256:ab:cd".to_string()),
+            address: "192.168.1.20:24800".parse().unwrap(),
+        }
+    }
+
+    fn sh(script: &str) -> CommandHook {
+        CommandHook::new(
+            vec!["sh".to_string(), "-c".to_string(), script.to_string()],
+            Duration::from_secs(5),
+        )
+    }
+
+    #[tokio::test]
+    async fn command_sees_peer_and_admits_on_success() {
+        let hook = sh(
+            r#"[ "$CROSS_CONTROL_PEER_NAME" = laptop ] && [ "$CROSS_CONTROL_PEER_FINGERPRINT" = SHA256:ab:cd ] && [ "$CROSS_CONTROL_PEER_ADDRESS" = 192.168.1.20:24800 ]"#,
+        );
+        assert_eq!(hook.authorize(&candidate()).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn command_refuses_with_first_output_line() {
+        let hook = sh("echo 'not in inventory'; echo more; exit 1");
+        assert_eq!(
+            hook.authorize(&candidate()).await,
+            Err("not in inventory".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn slow_or_missing_command_refuses() {
+        let slow = CommandHook::new(
+            vec!["sleep".to_string(), "5".to_string()],
+            Duration::from_millis(100),
+        );
+        assert!(slow.authorize(&candidate()).await.is_err());
+        let missing = CommandHook::new(
+            vec!["/nonexistent/auth-hook".to_string()],
+            Duration::from_secs(1),
+        );
+        assert!(missing.authorize(&candidate()).await.is_err());
+    }
+}
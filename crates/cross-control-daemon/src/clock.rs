@@ -0,0 +1,115 @@
+//! Wall clock jump detection.
+//!
+//! Timeouts and round trips are measured with the monotonic clock, while
+//! history, statistics and `status` report wall-clock times. The two drift
+//! apart when the wall clock is stepped (NTP, a manual change) or when the
+//! machine sleeps without the daemon hearing about it. Each tick compares
+//! how far both clocks moved; a difference over [`JUMP_THRESHOLD`] is a
+//! jump, after which the daemon re-baselines its session timers instead of
+//! timing keepalives across it.
+
+use std::time::{Duration, Instant, SystemTime};
+
+/// Smallest difference between the clocks treated as a jump. Ticks are
+/// well under this, so scheduling delays don't count.
+pub const JUMP_THRESHOLD: Duration = Duration::from_secs(2);
+
+/// A wall clock change against the monotonic clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockJump {
+    /// The wall clock moved ahead by this much more than the monotonic one.
+    Forward(Duration),
+    /// The wall clock moved back by this much against the monotonic one.
+    Backward(Duration),
+}
+
+impl ClockJump {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Forward(_) => "forward",
+            Self::Backward(_) => "backward",
+        }
+    }
+
+    /// How far the clocks drifted apart.
+    pub fn amount(self) -> Duration {
+        match self {
+            Self::Forward(d) | Self::Backward(d) => d,
+        }
+    }
+}
+
+/// Both clocks as last read.
+#[derive(Debug, Clone, Copy)]
+pub struct ClockWatch {
+    mono: Instant,
+    wall: SystemTime,
+}
+
+impl ClockWatch {
+    pub fn new() -> Self {
+        Self::at(Instant::now(), SystemTime::now())
+    }
+
+    fn at(mono: Instant, wall: SystemTime) -> Self {
+        Self { mono, wall }
+    }
+
+    /// Read both clocks and report a jump since the last check.
+    pub fn check(&mut self) -> Option<ClockJump> {
+        self.check_at(Instant::now(), SystemTime::now())
+    }
+
+    fn check_at(&mut self, mono: Instant, wall: SystemTime) -> Option<ClockJump> {
+        let mono_elapsed = mono.duration_since(self.mono);
+        let jump = match wall.duration_since(self.wall) {
+            Ok(wall_elapsed) if wall_elapsed > mono_elapsed => Some(ClockJump::Forward(
+                wall_elapsed.saturating_sub(mono_elapsed),
+            )),
+            Ok(wall_elapsed) => Some(ClockJump::Backward(
+                mono_elapsed.saturating_sub(wall_elapsed),
+            )),
+            Err(e) => Some(ClockJump::Backward(mono_elapsed + e.duration())),
+        };
+        *self = Self::at(mono, wall);
+        jump.filter(|j| j.amount() >= JUMP_THRESHOLD)
+    }
+}
+
+impl Default for ClockWatch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_steps_in_either_direction() {
+        let mono = Instant::now();
+        let wall = SystemTime::now();
+        let secs = Duration::from_secs;
+        let mut watch = ClockWatch::at(mono, wall);
+
+        // Both clocks move together: no jump.
+        assert_eq!(watch.check_at(mono + secs(1), wall + secs(1)), None);
+        // Small scheduling skew is not a jump.
+        let skew = Duration::from_millis(300);
+        assert_eq!(watch.check_at(mono + secs(2), wall + secs(2) + skew), None);
+
+        // NTP steps the wall clock an hour ahead.
+        assert_eq!(
+            watch.check_at(mono + secs(3), wall + secs(3) + skew + secs(3600)),
+            Some(ClockJump::Forward(secs(3600)))
+        );
+        // ...and back again, past where it started.
+        assert_eq!(
+            watch.check_at(mono + secs(4), wall),
+            Some(ClockJump::Backward(secs(3604) + skew))
+        );
+        // Checks after a jump measure from the new baseline.
+        assert_eq!(watch.check_at(mono + secs(5), wall + secs(1)), None);
+    }
+}
@@ -1,6 +1,12 @@
 //! Daemon configuration loaded from TOML.
 
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use cross_control_input::{wildcard_match, DeviceFilter};
+use cross_control_protocol::Subnet;
 use cross_control_types::screen::Position;
+use cross_control_types::{MouseButton, ScreenEdge};
 use serde::{Deserialize, Serialize};
 
 /// Top-level configuration.
@@ -13,13 +19,222 @@ pub struct Config {
     #[serde(default)]
     pub input: InputConfig,
     #[serde(default)]
+    pub controlled: ControlledConfig,
+    #[serde(default)]
     pub clipboard: ClipboardConfig,
+    #[serde(default)]
+    pub power: PowerConfig,
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    #[serde(default)]
+    pub performance: PerformanceConfig,
+    #[serde(default)]
+    pub security: SecurityConfig,
+    #[serde(default)]
+    pub alarms: AlarmConfig,
+    #[serde(default)]
+    pub screens: Vec<ScreenConfig>,
+    #[serde(default)]
+    pub screen_adjacency: Vec<ScreenAdjacency>,
+    /// Named layouts that replace `screens` and `screen_adjacency` while
+    /// active.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, ProfileConfig>,
+    /// Commands peers may ask this machine to run, by name.
+    #[serde(default)]
+    pub commands: BTreeMap<String, CommandConfig>,
+    /// Shared screen settings under `[group.<name>]`, inherited by screens
+    /// that name the group.
+    #[serde(default, rename = "group")]
+    pub groups: BTreeMap<String, GroupConfig>,
+}
+
+impl Config {
+    /// Parse a config file. Each screen that names a `group` takes the
+    /// group's settings for any it does not set itself.
+    pub fn from_toml(content: &str) -> Result<Self, toml::de::Error> {
+        let mut table: toml::Table = toml::from_str(content)?;
+        let groups = match table.get("group") {
+            Some(toml::Value::Table(groups)) => groups.clone(),
+            _ => toml::Table::new(),
+        };
+        if let Some(screens) = table.get_mut("screens") {
+            inherit_group_settings(screens, &groups)?;
+        }
+        if let Some(toml::Value::Table(profiles)) = table.get_mut("profiles") {
+            for (_, profile) in profiles.iter_mut() {
+                if let Some(screens) = profile.get_mut("screens") {
+                    inherit_group_settings(screens, &groups)?;
+                }
+            }
+        }
+        toml::Value::Table(table).try_into()
+    }
+
+    /// The config as TOML, for saving. A screen's settings that match its
+    /// group's are left out, so the group keeps supplying them and later
+    /// edits to the group still apply.
+    pub fn to_toml(&self) -> Result<String, toml::ser::Error> {
+        let mut table = toml::Table::try_from(self)?;
+        let groups = match table.get("group") {
+            Some(toml::Value::Table(groups)) => groups.clone(),
+            _ => toml::Table::new(),
+        };
+        if let Some(screens) = table.get_mut("screens") {
+            strip_group_settings(screens, &groups);
+        }
+        if let Some(toml::Value::Table(profiles)) = table.get_mut("profiles") {
+            for (_, profile) in profiles.iter_mut() {
+                if let Some(screens) = profile.get_mut("screens") {
+                    strip_group_settings(screens, &groups);
+                }
+            }
+        }
+        toml::to_string_pretty(&table)
+    }
+
+    /// The layout of a named profile.
+    pub fn profile_layout(&self, name: &str) -> Option<LayoutConfig> {
+        self.profiles.get(name).map(|profile| LayoutConfig {
+            screens: profile.screens.clone(),
+            screen_adjacency: profile.screen_adjacency.clone(),
+        })
+    }
+
+    /// The first profile (by name) that lists `ssid`.
+    pub fn profile_for_ssid(&self, ssid: &str) -> Option<&str> {
+        self.profiles
+            .iter()
+            .find(|(_, profile)| profile.ssids.iter().any(|s| s == ssid))
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// The config as TOML with addresses, fingerprints and network names
+    /// replaced, for attaching to bug reports.
+    pub fn redacted_toml(&self) -> Result<String, toml::ser::Error> {
+        fn redact(value: &mut toml::Value) {
+            match value {
+                toml::Value::Table(table) => {
+                    for (key, value) in table.iter_mut() {
+                        if REDACTED_KEYS.contains(&key.as_str()) {
+                            *value = toml::Value::String("<redacted>".to_string());
+                        } else {
+                            redact(value);
+                        }
+                    }
+                }
+                toml::Value::Array(values) => values.iter_mut().for_each(redact),
+                _ => {}
+            }
+        }
+
+        let mut value = toml::Value::try_from(self)?;
+        redact(&mut value);
+        toml::to_string(&value)
+    }
+}
+
+/// Remove each screen's keys whose value is the one the `[group.<name>]`
+/// it names would fill in. Undoes [`inherit_group_settings`].
+fn strip_group_settings(screens: &mut toml::Value, groups: &toml::Table) {
+    let toml::Value::Array(screens) = screens else {
+        return;
+    };
+    for screen in screens {
+        let toml::Value::Table(screen) = screen else {
+            continue;
+        };
+        let Some(toml::Value::Table(settings)) = screen
+            .get("group")
+            .and_then(toml::Value::as_str)
+            .and_then(|group| groups.get(group))
+        else {
+            continue;
+        };
+        screen.retain(|key, value| settings.get(key) != Some(value));
+    }
+}
+
+/// Fill in each screen's unset keys from the `[group.<name>]` it names.
+fn inherit_group_settings(
+    screens: &mut toml::Value,
+    groups: &toml::Table,
+) -> Result<(), toml::de::Error> {
+    let toml::Value::Array(screens) = screens else {
+        return Ok(());
+    };
+    for screen in screens {
+        let toml::Value::Table(screen) = screen else {
+            continue;
+        };
+        let Some(toml::Value::String(group)) = screen.get("group") else {
+            continue;
+        };
+        let Some(toml::Value::Table(settings)) = groups.get(group) else {
+            return Err(serde::de::Error::custom(format!(
+                "screen group `{group}` is not defined"
+            )));
+        };
+        for (key, value) in settings {
+            screen.entry(key.as_str()).or_insert_with(|| value.clone());
+        }
+    }
+    Ok(())
+}
+
+/// Keys whose values locate or identify machines and networks, or are
+/// command lines, which may carry tokens, hosts or user names.
+const REDACTED_KEYS: [&str; 8] = [
+    "address",
+    "fingerprint",
+    "bind",
+    "allowed_subnets",
+    "ssids",
+    "auth_command",
+    "run",
+    "command",
+];
+
+/// A named layout under `[profiles.<name>]`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProfileConfig {
+    /// Wi-Fi networks on which this profile is selected automatically.
+    #[serde(default)]
+    pub ssids: Vec<String>,
+    #[serde(default)]
+    pub screens: Vec<ScreenConfig>,
+    #[serde(default)]
+    pub screen_adjacency: Vec<ScreenAdjacency>,
+}
+
+/// The layout sections of a config, as pushed to a peer with
+/// `cross-control config push`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LayoutConfig {
     #[serde(default)]
     pub screens: Vec<ScreenConfig>,
     #[serde(default)]
     pub screen_adjacency: Vec<ScreenAdjacency>,
 }
 
+impl LayoutConfig {
+    /// Limit a layout pushed by a peer to what a peer may decide. Trust
+    /// stays local: each screen keeps the fingerprint it has in `local`,
+    /// and a screen that is new gets none. A push can make a screen a
+    /// viewer but never lift a local viewer to control.
+    #[must_use]
+    pub fn restricted_to(mut self, local: &[ScreenConfig]) -> Self {
+        for screen in &mut self.screens {
+            let known = local.iter().find(|sc| sc.name == screen.name);
+            screen.fingerprint = known.and_then(|sc| sc.fingerprint.clone());
+            if known.is_some_and(|sc| sc.mode == ScreenMode::Viewer) {
+                screen.mode = ScreenMode::Viewer;
+            }
+        }
+        self
+    }
+}
+
 /// An adjacency edge between two screens in the full screen graph.
 ///
 /// Used by the server to know where to route the cursor when it leaves
@@ -34,21 +249,86 @@ pub struct ScreenAdjacency {
     pub position: Position,
 }
 
+/// The QUIC port used when `daemon.port` is not set.
+pub const DEFAULT_PORT: u16 = 24800;
+
 /// Daemon network and runtime settings.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DaemonConfig {
+    /// QUIC port to listen on; 0 picks a free port at startup, which is
+    /// advertised through discovery and written to the runtime info file.
     #[serde(default = "default_port")]
     pub port: u16,
     #[serde(default = "default_bind")]
     pub bind: String,
     #[serde(default = "default_true")]
     pub discovery: bool,
+    /// How peers are discovered when `discovery` is on.
+    #[serde(default)]
+    pub discovery_method: DiscoveryMethod,
+    /// UDP port for broadcast discovery; defaults to `port + 1`, or the
+    /// one after [`DEFAULT_PORT`] when `port` is 0.
+    #[serde(default)]
+    pub broadcast_port: Option<u16>,
     #[serde(default = "default_log_level")]
     pub log_level: String,
+    /// Log output format.
+    #[serde(default)]
+    pub log_format: LogFormat,
     #[serde(default = "default_screen_width")]
     pub screen_width: u32,
     #[serde(default = "default_screen_height")]
     pub screen_height: u32,
+    /// Display scale factor in percent (e.g. 200 for a 2x high-DPI panel).
+    #[serde(default = "default_screen_scale")]
+    pub screen_scale: u32,
+    /// Interval between keepalive pings to each peer, in milliseconds.
+    #[serde(default = "default_keepalive_ms")]
+    pub keepalive_ms: u64,
+    /// Accept `[[screens]]` and `[[screen_adjacency]]` pushed by a peer whose
+    /// certificate fingerprint is pinned in `[[screens]]`.
+    #[serde(default)]
+    pub allow_remote_config: bool,
+    /// Which peers may ask to take control of this machine, handing them
+    /// the cursor even if this machine controls another peer.
+    #[serde(default)]
+    pub control_requests: ControlRequestPolicy,
+    /// Discovery group. Machines only discover peers advertising the same
+    /// group, so separate clusters on one LAN stay apart.
+    #[serde(default)]
+    pub group: Option<String>,
+    /// Profile to start with; `None` uses the top-level layout.
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// How often the Wi-Fi network is checked when profiles list `ssids`.
+    #[serde(default = "default_ssid_poll_secs")]
+    pub ssid_poll_secs: u64,
+    /// Release grabbed devices if the event loop spends longer than this
+    /// on one event; 0 disables the watchdog.
+    #[serde(default = "default_watchdog_secs")]
+    pub watchdog_secs: u64,
+    /// How long to wait for sessions with `wait_on_start` screens before
+    /// reporting readiness anyway.
+    #[serde(default = "default_wait_on_start_secs")]
+    pub wait_on_start_secs: u64,
+}
+
+impl DaemonConfig {
+    /// The port assumed for peer addresses written without one: `port`, or
+    /// [`DEFAULT_PORT`] when this machine listens on an ephemeral port.
+    pub fn peer_port(&self) -> u16 {
+        if self.port == 0 {
+            DEFAULT_PORT
+        } else {
+            self.port
+        }
+    }
+
+    /// The UDP port broadcast discovery uses.
+    pub fn broadcast_port(&self) -> u16 {
+        self.broadcast_port
+            .unwrap_or_else(|| self.peer_port().wrapping_add(1))
+    }
 }
 
 impl Default for DaemonConfig {
@@ -57,13 +337,47 @@ impl Default for DaemonConfig {
             port: default_port(),
             bind: default_bind(),
             discovery: true,
+            discovery_method: DiscoveryMethod::default(),
+            broadcast_port: None,
             log_level: default_log_level(),
+            log_format: LogFormat::default(),
             screen_width: default_screen_width(),
             screen_height: default_screen_height(),
+            screen_scale: default_screen_scale(),
+            keepalive_ms: default_keepalive_ms(),
+            allow_remote_config: false,
+            control_requests: ControlRequestPolicy::default(),
+            group: None,
+            profile: None,
+            ssid_poll_secs: default_ssid_poll_secs(),
+            watchdog_secs: default_watchdog_secs(),
+            wait_on_start_secs: default_wait_on_start_secs(),
         }
     }
 }
 
+/// Discovery mechanism.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiscoveryMethod {
+    /// mDNS/DNS-SD.
+    #[default]
+    Mdns,
+    /// Signed UDP broadcasts, for networks that block multicast.
+    Broadcast,
+}
+
+/// Log output format.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogFormat {
+    /// Human-readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line, for log shippers.
+    Json,
+}
+
 /// Machine identity.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IdentityConfig {
@@ -84,16 +398,197 @@ impl Default for IdentityConfig {
 pub struct InputConfig {
     #[serde(default = "default_release_hotkey")]
     pub release_hotkey: Vec<String>,
+    /// Toggles pause: releases control, stops barrier detection and
+    /// suspends clipboard sync while keeping sessions open.
+    #[serde(default = "default_pause_hotkey")]
+    pub pause_hotkey: Vec<String>,
+    /// Toggles private input: keyboard events stay on this machine, and are
+    /// neither forwarded to a peer nor counted.
+    #[serde(default = "default_private_hotkey")]
+    pub private_hotkey: Vec<String>,
+    /// Runs the `lock-screen` command on this machine and every trusted,
+    /// connected peer, as `cross-control lock-all` does. Empty (the
+    /// default) disables it.
+    #[serde(default)]
+    pub lock_all_hotkey: Vec<String>,
+    /// Swaps roles with the other end of the current control session: a
+    /// peer we control takes control of us, and a peer controlling us is
+    /// taken control of. Empty (the default) disables it.
+    #[serde(default)]
+    pub swap_hotkey: Vec<String>,
+    /// Grab this machine's own keyboard and mouse while a peer controls
+    /// it, so local input can't fight the remote one. The daemon still
+    /// reads the grabbed devices: the release hotkey hands the cursor back
+    /// to the peer, the pause hotkey pauses, and other input is dropped.
+    #[serde(default)]
+    pub suppress_local_while_controlled: bool,
+    /// After accepting an Enter, inject a zero-length pointer move to check
+    /// that input from the controller can be injected, and tell the
+    /// controller with an `Error` if it can't.
+    #[serde(default)]
+    pub verify_on_enter: bool,
+    /// Accept control from peers but only log the input they send, without
+    /// creating virtual devices or injecting anything.
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Rules turning pointer motion into scrolling while a button is held,
+    /// applied to input forwarded to a peer.
+    #[serde(default)]
+    pub drag_scroll: Vec<DragScrollRule>,
+    /// Return to idle when the peer controlling us has sent neither input
+    /// nor a keepalive for this many seconds (0 never does). Keep it above
+    /// the peer's keepalive interval, including `power.battery_keepalive_ms`.
+    #[serde(default = "default_controlled_timeout_secs")]
+    pub controlled_timeout_secs: u64,
+    /// Actions run when the cursor reaches a corner of this screen.
+    #[serde(default)]
+    pub corners: Vec<CornerRule>,
+    /// Actions run by keys pressed one after another, e.g. `F9` then
+    /// `Digit1` to take control of a screen.
+    #[serde(default)]
+    pub sequences: Vec<HotkeySequence>,
+    /// How long a sequence waits for its next step, in milliseconds.
+    #[serde(default = "default_chord_timeout_ms")]
+    pub chord_timeout_ms: u64,
+    /// Devices to capture even though they look virtual or are neither a
+    /// keyboard nor a mouse, as name patterns like `share_devices`.
+    #[serde(default)]
+    pub include_devices: Vec<String>,
+    /// Devices never to capture, as name patterns. Wins over
+    /// `include_devices`.
+    #[serde(default)]
+    pub exclude_devices: Vec<String>,
+}
+
+impl InputConfig {
+    /// The capture overrides set by `include_devices` and `exclude_devices`.
+    pub fn device_filter(&self) -> DeviceFilter {
+        DeviceFilter {
+            include: self.include_devices.clone(),
+            exclude: self.exclude_devices.clone(),
+        }
+    }
 }
 
 impl Default for InputConfig {
     fn default() -> Self {
         Self {
             release_hotkey: default_release_hotkey(),
+            pause_hotkey: default_pause_hotkey(),
+            private_hotkey: default_private_hotkey(),
+            lock_all_hotkey: Vec::new(),
+            swap_hotkey: Vec::new(),
+            suppress_local_while_controlled: false,
+            verify_on_enter: false,
+            dry_run: false,
+            drag_scroll: Vec::new(),
+            controlled_timeout_secs: default_controlled_timeout_secs(),
+            corners: Vec::new(),
+            sequences: Vec::new(),
+            chord_timeout_ms: default_chord_timeout_ms(),
+            include_devices: Vec::new(),
+            exclude_devices: Vec::new(),
         }
     }
 }
 
+/// Scroll by moving the pointer while `button` is held.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DragScrollRule {
+    pub button: MouseButton,
+    /// Case-insensitive substrings of the device names the rule applies to.
+    /// Empty applies it to every pointer.
+    #[serde(default)]
+    pub devices: Vec<String>,
+    /// Pixels of motion per scroll step.
+    #[serde(default = "default_drag_scroll_step")]
+    pub step: u32,
+    /// Scroll the content along with the pointer, like a touchpad, instead
+    /// of the way a wheel turns.
+    #[serde(default)]
+    pub natural: bool,
+}
+
+impl DragScrollRule {
+    /// Whether the rule applies to the device called `name`.
+    pub fn matches(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        self.devices.is_empty()
+            || self
+                .devices
+                .iter()
+                .any(|device| name.contains(&device.to_lowercase()))
+    }
+}
+
+/// Run `action` when the cursor reaches `corner` of the local screen.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CornerRule {
+    pub corner: ScreenCorner,
+    pub action: CornerAction,
+}
+
+/// Run `action` when `steps` are pressed in order. Each step is a key name
+/// or a `+`-joined combination such as `LeftCtrl+F9`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HotkeySequence {
+    pub steps: Vec<String>,
+    pub action: CornerAction,
+}
+
+/// A corner of a screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScreenCorner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl ScreenCorner {
+    pub const ALL: [ScreenCorner; 4] = [
+        ScreenCorner::TopLeft,
+        ScreenCorner::TopRight,
+        ScreenCorner::BottomLeft,
+        ScreenCorner::BottomRight,
+    ];
+
+    /// The two edges that meet at the corner.
+    pub fn edges(self) -> [ScreenEdge; 2] {
+        match self {
+            ScreenCorner::TopLeft => [ScreenEdge::Top, ScreenEdge::Left],
+            ScreenCorner::TopRight => [ScreenEdge::Top, ScreenEdge::Right],
+            ScreenCorner::BottomLeft => [ScreenEdge::Bottom, ScreenEdge::Left],
+            ScreenCorner::BottomRight => [ScreenEdge::Bottom, ScreenEdge::Right],
+        }
+    }
+}
+
+/// What reaching a screen corner or finishing a hotkey sequence does.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CornerAction {
+    /// Hand control back to the peer controlling this machine, or bring the
+    /// cursor home from the peer this machine controls.
+    Release,
+    /// Toggle pause, as the pause hotkey does.
+    Pause,
+    /// Take control of the named screen.
+    Switch(String),
+}
+
+/// A command peers may run on this machine with `cross-control run`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommandConfig {
+    /// Program and arguments, run directly rather than through a shell.
+    pub run: Vec<String>,
+    /// Peers allowed to run it, by screen name. Each needs a pinned
+    /// fingerprint. Empty allows no one.
+    #[serde(default)]
+    pub peers: Vec<String>,
+}
+
 /// Clipboard subsystem settings.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClipboardConfig {
@@ -101,6 +596,8 @@ pub struct ClipboardConfig {
     pub enabled: bool,
     #[serde(default = "default_max_clipboard_size")]
     pub max_size: usize,
+    #[serde(default)]
+    pub scope: ClipboardScope,
 }
 
 impl Default for ClipboardConfig {
@@ -108,23 +605,489 @@ impl Default for ClipboardConfig {
         Self {
             enabled: true,
             max_size: default_max_clipboard_size(),
+            scope: ClipboardScope::default(),
         }
     }
 }
 
+/// Power-saving behaviour towards peers running on battery.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PowerConfig {
+    /// Keepalive interval used when either end is on battery, in milliseconds.
+    #[serde(default = "default_battery_keepalive_ms")]
+    pub battery_keepalive_ms: u64,
+    /// Window for merging pointer motion sent to a battery peer, in
+    /// milliseconds (0 disables coalescing).
+    #[serde(default = "default_battery_coalesce_ms")]
+    pub battery_coalesce_ms: u64,
+    /// Battery charge (percent) at or below which a peer counts as low.
+    #[serde(default = "default_low_battery_percent")]
+    pub low_battery_percent: u8,
+    /// Log a warning when crossing into a peer with a low battery.
+    #[serde(default = "default_true")]
+    pub warn_low_battery: bool,
+    /// How often to re-read the local power state, in seconds.
+    #[serde(default = "default_power_poll_secs")]
+    pub poll_secs: u64,
+}
+
+impl Default for PowerConfig {
+    fn default() -> Self {
+        Self {
+            battery_keepalive_ms: default_battery_keepalive_ms(),
+            battery_coalesce_ms: default_battery_coalesce_ms(),
+            low_battery_percent: default_low_battery_percent(),
+            warn_low_battery: true,
+            poll_secs: default_power_poll_secs(),
+        }
+    }
+}
+
+/// Who may connect to this machine.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecurityConfig {
+    /// Subnets (CIDR, e.g. `"192.168.1.0/24"`) inbound connections are
+    /// accepted from. Connections from anywhere else are dropped before the
+    /// TLS handshake. Empty accepts every address.
+    #[serde(default)]
+    pub allowed_subnets: Vec<Subnet>,
+    /// Program (and arguments, without a shell) asked whether to admit each
+    /// inbound peer before its handshake completes; see
+    /// [`CommandHook`](crate::auth::CommandHook). Empty admits every peer
+    /// that passes the other checks.
+    #[serde(default)]
+    pub auth_command: Vec<String>,
+    /// How long `auth_command` may take before the peer is refused.
+    #[serde(default = "default_auth_timeout_ms")]
+    pub auth_timeout_ms: u64,
+}
+
+impl Default for SecurityConfig {
+    fn default() -> Self {
+        Self {
+            allowed_subnets: Vec::new(),
+            auth_command: Vec::new(),
+            auth_timeout_ms: default_auth_timeout_ms(),
+        }
+    }
+}
+
+/// Link quality thresholds that raise an alarm when a peer's link stays
+/// over one of them for `for_secs`. Unset thresholds are not checked.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AlarmThresholds {
+    /// Keepalive round-trip time, in milliseconds.
+    #[serde(default)]
+    pub rtt_ms: Option<u64>,
+    /// Share of recent keepalives left unanswered, in percent.
+    #[serde(default)]
+    pub loss_percent: Option<f64>,
+}
+
+impl AlarmThresholds {
+    /// Whether no threshold is set.
+    pub fn is_empty(&self) -> bool {
+        self.rtt_ms.is_none() && self.loss_percent.is_none()
+    }
+}
+
+/// Alarms for degraded links to peers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlarmConfig {
+    /// Thresholds for every peer.
+    #[serde(default, flatten)]
+    pub thresholds: AlarmThresholds,
+    /// How long a link must stay over a threshold before the alarm is
+    /// raised, in seconds.
+    #[serde(default = "default_alarm_for_secs")]
+    pub for_secs: u64,
+    /// Show a desktop notification when an alarm is raised or cleared.
+    #[serde(default = "default_true")]
+    pub notify: bool,
+    /// Program (and arguments, without a shell) run when an alarm is raised
+    /// or cleared; see [`alarms::run_command`](crate::alarms::run_command).
+    #[serde(default)]
+    pub command: Vec<String>,
+    /// Per-peer thresholds under `[alarms.peers.<name>]`, replacing the
+    /// shared ones they set.
+    #[serde(default)]
+    pub peers: BTreeMap<String, AlarmThresholds>,
+}
+
+impl AlarmConfig {
+    /// The thresholds that apply to `peer`.
+    pub fn thresholds_for(&self, peer: &str) -> AlarmThresholds {
+        let shared = &self.thresholds;
+        match self.peers.get(peer) {
+            Some(own) => AlarmThresholds {
+                rtt_ms: own.rtt_ms.or(shared.rtt_ms),
+                loss_percent: own.loss_percent.or(shared.loss_percent),
+            },
+            None => shared.clone(),
+        }
+    }
+}
+
+impl Default for AlarmConfig {
+    fn default() -> Self {
+        Self {
+            thresholds: AlarmThresholds::default(),
+            for_secs: default_alarm_for_secs(),
+            notify: true,
+            command: Vec::new(),
+            peers: BTreeMap::new(),
+        }
+    }
+}
+
+/// Ceilings on what the daemon keeps in memory. When one is reached the
+/// oldest entries are dropped and counted in `status`. Lower them on small
+/// machines; the clipboard is already bounded by `clipboard.max_size`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LimitsConfig {
+    /// Input batches waiting to be injected per remote device.
+    #[serde(default = "default_device_queue")]
+    pub device_queue: usize,
+    /// Errors reported by peers, kept for `status`.
+    #[serde(default = "default_peer_errors")]
+    pub peer_errors: usize,
+    /// Peers remembered from discovery.
+    #[serde(default = "default_discovered_peers")]
+    pub discovered_peers: usize,
+    /// State transitions and messages kept per session for
+    /// `cross-control history`.
+    #[serde(default = "default_session_history")]
+    pub session_history: usize,
+    /// Devices a single peer may announce; virtual devices are created
+    /// for at most this many, and further announcements are rejected.
+    #[serde(default = "default_peer_devices")]
+    pub peer_devices: usize,
+    /// Days of usage totals kept for `cross-control stats --since`.
+    #[serde(default = "default_metrics_days")]
+    pub metrics_days: u64,
+    /// Largest control message accepted from a peer, in bytes. Clipboard
+    /// contents travel as control messages, so keep this above
+    /// `clipboard.max_size`. Peers never send more than the smaller of
+    /// both ends' limits.
+    #[serde(default = "default_control_message_size")]
+    pub control_message_size: u32,
+    /// Largest input batch accepted from a peer, in bytes.
+    #[serde(default = "default_input_message_size")]
+    pub input_message_size: u32,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            device_queue: default_device_queue(),
+            peer_errors: default_peer_errors(),
+            discovered_peers: default_discovered_peers(),
+            session_history: default_session_history(),
+            peer_devices: default_peer_devices(),
+            metrics_days: default_metrics_days(),
+            control_message_size: default_control_message_size(),
+            input_message_size: default_input_message_size(),
+        }
+    }
+}
+
+/// Timer rates and queue sizes of the event pipeline.
+///
+/// Each setting left out takes its value from `preset`. The `low-power`
+/// preset wakes up less often and uses smaller queues, for Raspberry Pi
+/// class machines where the defaults cost noticeable power or heat.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PerformanceConfig {
+    #[serde(default)]
+    pub preset: PerformancePreset,
+    /// Housekeeping timer (keepalives, liveness probes), in milliseconds.
+    #[serde(default)]
+    pub tick_ms: Option<u64>,
+    /// Minimum gap between status updates that only move the cursor, in
+    /// milliseconds (0 sends every update).
+    #[serde(default)]
+    pub status_interval_ms: Option<u64>,
+    /// Keepalive interval on mains power; overrides `daemon.keepalive_ms`.
+    #[serde(default)]
+    pub keepalive_ms: Option<u64>,
+    /// Motion coalescing window for every peer, in milliseconds. With 0,
+    /// only motion to battery peers is coalesced (`power.battery_coalesce_ms`).
+    #[serde(default)]
+    pub coalesce_ms: Option<u64>,
+    /// Capacity of the daemon's event queue.
+    #[serde(default)]
+    pub event_queue: Option<usize>,
+    /// Capacity of the captured input queue.
+    #[serde(default)]
+    pub capture_queue: Option<usize>,
+}
+
+/// Starting point for `[performance]`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PerformancePreset {
+    #[default]
+    Default,
+    /// Fewer wakeups and smaller queues.
+    LowPower,
+}
+
+impl PerformanceConfig {
+    fn low_power(&self) -> bool {
+        self.preset == PerformancePreset::LowPower
+    }
+
+    pub fn tick(&self) -> Duration {
+        let preset = if self.low_power() { 2000 } else { 1000 };
+        Duration::from_millis(self.tick_ms.unwrap_or(preset).max(1))
+    }
+
+    pub fn status_interval(&self) -> Duration {
+        let preset = if self.low_power() { 250 } else { 0 };
+        Duration::from_millis(self.status_interval_ms.unwrap_or(preset))
+    }
+
+    /// Keepalive interval on mains power.
+    pub fn keepalive(&self, daemon: &DaemonConfig) -> Duration {
+        let preset = if self.low_power() {
+            daemon.keepalive_ms.max(15_000)
+        } else {
+            daemon.keepalive_ms
+        };
+        Duration::from_millis(self.keepalive_ms.unwrap_or(preset))
+    }
+
+    /// Coalescing window applied to motion for every peer, if any.
+    pub fn coalesce(&self) -> Option<Duration> {
+        let preset = if self.low_power() { 16 } else { 0 };
+        let ms = self.coalesce_ms.unwrap_or(preset);
+        (ms > 0).then(|| Duration::from_millis(ms))
+    }
+
+    pub fn event_queue(&self) -> usize {
+        let preset = if self.low_power() { 256 } else { 1024 };
+        self.event_queue.unwrap_or(preset).max(1)
+    }
+
+    pub fn capture_queue(&self) -> usize {
+        let preset = if self.low_power() { 256 } else { 1024 };
+        self.capture_queue.unwrap_or(preset).max(1)
+    }
+}
+
+/// Behaviour while a peer controls this machine.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ControlledConfig {
+    /// What local keyboard or pointer activity does while a peer controls
+    /// us. Has no effect while `input.suppress_local_while_controlled`
+    /// keeps local input from arriving.
+    #[serde(default)]
+    pub local_input_policy: LocalInputPolicy,
+    /// Seconds without local input after which the local user counts as
+    /// gone again, for the `notify` and `hold` policies.
+    #[serde(default = "default_local_input_secs")]
+    pub local_input_secs: u64,
+}
+
+impl Default for ControlledConfig {
+    fn default() -> Self {
+        Self {
+            local_input_policy: LocalInputPolicy::default(),
+            local_input_secs: default_local_input_secs(),
+        }
+    }
+}
+
+/// Who may take control of this machine by asking for it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ControlRequestPolicy {
+    /// Refuse every request.
+    #[default]
+    Deny,
+    /// Accept requests from peers whose certificate is pinned in
+    /// `[[screens]]`.
+    Pinned,
+    /// Accept requests from any connected peer.
+    Accept,
+}
+
+/// Response to someone using this machine while a peer controls it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LocalInputPolicy {
+    /// Both inputs act on the machine at once.
+    #[default]
+    Ignore,
+    /// Both inputs act on the machine at once, but both machines are told
+    /// that the local user is active.
+    Notify,
+    /// Like `Notify`, and the controller's input is held back until the
+    /// local user has been idle for `controlled.local_input_secs`.
+    Hold,
+    /// Hand the cursor back to the controller; it may cross in again.
+    Release,
+    /// Hand the cursor back and pause cross-control, so no peer can take
+    /// over again until the local user resumes it.
+    PauseRemote,
+}
+
+/// Which peers share the clipboard.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClipboardScope {
+    /// Only the peer we control or are controlled by (plus viewer screens).
+    #[default]
+    ControlledOnly,
+    /// Every connected peer, even while all machines are idle.
+    AllPeers,
+}
+
+/// Screen settings shared by every screen in a group. Each is optional; a
+/// screen's own setting wins over its group's.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GroupConfig {
+    #[serde(default)]
+    pub mode: Option<ScreenMode>,
+    #[serde(default)]
+    pub wake_on_enter: Option<bool>,
+    #[serde(default)]
+    pub locate_on_enter: Option<bool>,
+    #[serde(default)]
+    pub pointer_scale: Option<PointerScale>,
+    #[serde(default)]
+    pub share_devices: Option<Vec<String>>,
+    #[serde(default)]
+    pub clipboard: Option<bool>,
+    #[serde(default)]
+    pub wait_on_start: Option<bool>,
+    #[serde(default)]
+    pub commands: Option<Vec<String>>,
+    #[serde(default)]
+    pub release_hotkey: Option<Vec<String>>,
+}
+
 /// A remote screen definition.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct ScreenConfig {
     pub name: String,
+    /// The `[group.<name>]` this screen takes unset settings from. Applied
+    /// by [`Config::from_toml`].
+    #[serde(default)]
+    pub group: Option<String>,
     #[serde(default)]
     pub address: Option<String>,
     pub position: Position,
     #[serde(default)]
     pub fingerprint: Option<String>,
+    #[serde(default)]
+    pub mode: ScreenMode,
+    /// Nudge the pointer when control crosses to this screen, so a display
+    /// in power-saving sleep wakes before the user starts typing.
+    #[serde(default = "default_true")]
+    pub wake_on_enter: bool,
+    /// Shake the pointer briefly along the entry edge when control crosses
+    /// to this screen, so it is easy to spot among several monitors.
+    #[serde(default)]
+    pub locate_on_enter: bool,
+    /// Scaling applied to pointer motion sent to this screen, per axis.
+    #[serde(default)]
+    pub pointer_scale: PointerScale,
+    /// Names of the local devices shared with this screen, as
+    /// case-insensitive patterns where `*` matches any text (e.g.
+    /// `"*Keyboard*"`). Unset shares every device.
+    #[serde(default)]
+    pub share_devices: Option<Vec<String>>,
+    /// Whether clipboard content is exchanged with this screen, subject to
+    /// `clipboard.enabled` and `clipboard.scope`.
+    #[serde(default = "default_true")]
+    pub clipboard: bool,
+    /// Hold back startup readiness until a session with this screen is
+    /// established, or `daemon.wait_on_start_secs` passes.
+    #[serde(default)]
+    pub wait_on_start: bool,
+    /// `[commands]` this peer may run, besides those whose `peers` list
+    /// it. The peer still needs a pinned fingerprint.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub commands: Vec<String>,
+    /// Key combination that brings the cursor back from this screen, in
+    /// place of `input.release_hotkey`.
+    #[serde(default)]
+    pub release_hotkey: Option<Vec<String>>,
+}
+
+impl ScreenConfig {
+    /// Whether the local device called `name` is shared with this screen.
+    pub fn shares_device(&self, name: &str) -> bool {
+        self.share_devices.as_ref().map_or(true, |patterns| {
+            patterns.iter().any(|pattern| wildcard_match(pattern, name))
+        })
+    }
+}
+
+/// Per-axis multipliers for forwarded pointer motion, e.g.
+/// `{ x = 1.5, y = 1.0 }` to cross an ultrawide screen at the same effort
+/// as a narrower one. Factors that are not positive count as 1.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PointerScale {
+    #[serde(default = "default_pointer_scale")]
+    pub x: f64,
+    #[serde(default = "default_pointer_scale")]
+    pub y: f64,
+}
+
+impl Default for PointerScale {
+    fn default() -> Self {
+        Self { x: 1.0, y: 1.0 }
+    }
+}
+
+impl PointerScale {
+    pub fn is_identity(&self) -> bool {
+        (factor(self.x) - 1.0).abs() < f64::EPSILON && (factor(self.y) - 1.0).abs() < f64::EPSILON
+    }
+
+    /// Scale a motion delta. The fractions lost to rounding are kept in
+    /// `carry` and added to the next delta, so slow movement still gets
+    /// through and travel stays proportional.
+    pub fn apply(&self, dx: i32, dy: i32, carry: &mut (f64, f64)) -> (i32, i32) {
+        let axis = |delta: i32, scale: f64, carry: &mut f64| {
+            let exact = f64::from(delta) * factor(scale) + *carry;
+            // Saturates for absurd scales, which only clips the motion.
+            #[allow(clippy::cast_possible_truncation)]
+            let whole = exact.trunc() as i32;
+            *carry = exact - f64::from(whole);
+            whole
+        };
+        (
+            axis(dx, self.x, &mut carry.0),
+            axis(dy, self.y, &mut carry.1),
+        )
+    }
+}
+
+fn factor(scale: f64) -> f64 {
+    if scale.is_finite() && scale > 0.0 {
+        scale
+    } else {
+        1.0
+    }
+}
+
+/// How a configured screen may be used.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScreenMode {
+    /// Full control in both directions.
+    #[default]
+    Control,
+    /// Session for clipboard and presence only; input control is never
+    /// allowed in either direction.
+    Viewer,
 }
 
 fn default_port() -> u16 {
-    24800
+    DEFAULT_PORT
 }
 
 fn default_bind() -> String {
@@ -139,11 +1102,14 @@ fn default_log_level() -> String {
     "info".to_string()
 }
 
+/// The host name, suffixed with the instance so each instance advertises
+/// itself under its own name.
 fn default_name() -> String {
-    hostname::get()
+    let hostname = hostname::get()
         .ok()
         .and_then(|h| h.into_string().ok())
-        .unwrap_or_else(|| "cross-control".to_string())
+        .unwrap_or_else(|| "cross-control".to_string());
+    crate::setup::namespaced(&hostname, crate::setup::instance())
 }
 
 fn default_release_hotkey() -> Vec<String> {
@@ -154,6 +1120,38 @@ fn default_release_hotkey() -> Vec<String> {
     ]
 }
 
+fn default_private_hotkey() -> Vec<String> {
+    vec![
+        "LeftCtrl".to_string(),
+        "LeftShift".to_string(),
+        "F12".to_string(),
+    ]
+}
+
+fn default_pause_hotkey() -> Vec<String> {
+    vec![
+        "LeftCtrl".to_string(),
+        "LeftShift".to_string(),
+        "Pause".to_string(),
+    ]
+}
+
+fn default_controlled_timeout_secs() -> u64 {
+    60
+}
+
+fn default_chord_timeout_ms() -> u64 {
+    1000
+}
+
+fn default_drag_scroll_step() -> u32 {
+    15
+}
+
+fn default_pointer_scale() -> f64 {
+    1.0
+}
+
 fn default_max_clipboard_size() -> usize {
     10 * 1024 * 1024 // 10 MiB
 }
@@ -166,10 +1164,145 @@ fn default_screen_height() -> u32 {
     1080
 }
 
+fn default_screen_scale() -> u32 {
+    100
+}
+
+fn default_keepalive_ms() -> u64 {
+    5000
+}
+
+fn default_battery_keepalive_ms() -> u64 {
+    20_000
+}
+
+fn default_battery_coalesce_ms() -> u64 {
+    16
+}
+
+fn default_low_battery_percent() -> u8 {
+    15
+}
+
+fn default_power_poll_secs() -> u64 {
+    60
+}
+
+fn default_ssid_poll_secs() -> u64 {
+    30
+}
+
+fn default_watchdog_secs() -> u64 {
+    5
+}
+
+fn default_wait_on_start_secs() -> u64 {
+    30
+}
+
+fn default_auth_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_alarm_for_secs() -> u64 {
+    10
+}
+
+fn default_device_queue() -> usize {
+    1024
+}
+
+fn default_peer_errors() -> usize {
+    32
+}
+
+fn default_discovered_peers() -> usize {
+    256
+}
+
+fn default_session_history() -> usize {
+    64
+}
+
+fn default_local_input_secs() -> u64 {
+    3
+}
+
+fn default_peer_devices() -> usize {
+    32
+}
+
+fn default_metrics_days() -> u64 {
+    90
+}
+
+fn default_control_message_size() -> u32 {
+    16 * 1024 * 1024
+}
+
+fn default_input_message_size() -> u32 {
+    1024 * 1024
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn pushed_layout_keeps_local_trust() {
+        let local: Config = toml::from_str(
+            r#"
+[[screens]]
+name = "desk"
+position = "Left"
+fingerprint = "SHA256:desk"
+
+[[screens]]
+name = "kiosk"
+position = "Right"
+fingerprint = "SHA256:kiosk"
+mode = "Viewer"
+"#,
+        )
+        .unwrap();
+        let pushed: LayoutConfig = toml::from_str(
+            r#"
+[[screens]]
+name = "desk"
+position = "Above"
+fingerprint = "SHA256:attacker"
+mode = "Viewer"
+
+[[screens]]
+name = "kiosk"
+position = "Right"
+mode = "Control"
+
+[[screens]]
+name = "new"
+position = "Below"
+fingerprint = "SHA256:new"
+"#,
+        )
+        .unwrap();
+
+        let layout = pushed.restricted_to(&local.screens);
+        let trust: Vec<_> = layout
+            .screens
+            .iter()
+            .map(|sc| (sc.name.as_str(), sc.fingerprint.as_deref(), sc.mode))
+            .collect();
+        assert_eq!(
+            trust,
+            vec![
+                ("desk", Some("SHA256:desk"), ScreenMode::Viewer),
+                ("kiosk", Some("SHA256:kiosk"), ScreenMode::Viewer),
+                ("new", None, ScreenMode::Control),
+            ]
+        );
+        assert_eq!(layout.screens[0].position, Position::Above);
+    }
+
     #[test]
     fn default_config_serializes() {
         let config = Config::default();
@@ -208,5 +1341,530 @@ fingerprint = "SHA256:abc123"
         assert_eq!(config.screens.len(), 1);
         assert_eq!(config.screens[0].name, "laptop-right");
         assert_eq!(config.screens[0].position, Position::Right);
+        assert_eq!(config.screens[0].mode, ScreenMode::Control);
+        assert!(config.screens[0].wake_on_enter);
+        assert!(!config.screens[0].locate_on_enter);
+        assert_eq!(config.input.controlled_timeout_secs, 60);
+    }
+
+    #[test]
+    fn parse_clipboard_scope() {
+        let config: Config = toml::from_str("").unwrap();
+        assert_eq!(config.clipboard.scope, ClipboardScope::ControlledOnly);
+
+        let toml_str = r#"
+[clipboard]
+scope = "all-peers"
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.clipboard.scope, ClipboardScope::AllPeers);
+    }
+
+    #[test]
+    fn layout_config_ignores_other_sections() {
+        let toml_str = r#"
+[daemon]
+port = 1234
+
+[[screens]]
+name = "laptop"
+position = "Left"
+
+[[screen_adjacency]]
+screen = "laptop"
+neighbor = "tablet"
+position = "Below"
+"#;
+        let layout: LayoutConfig = toml::from_str(toml_str).unwrap();
+        assert_eq!(layout.screens.len(), 1);
+        assert_eq!(layout.screen_adjacency[0].neighbor, "tablet");
+
+        let config: Config = toml::from_str("").unwrap();
+        assert!(!config.daemon.allow_remote_config);
+        assert_eq!(config.daemon.control_requests, ControlRequestPolicy::Deny);
+        let config: Config = toml::from_str("[daemon]\ncontrol_requests = \"pinned\"").unwrap();
+        assert_eq!(config.daemon.control_requests, ControlRequestPolicy::Pinned);
+    }
+
+    #[test]
+    fn parse_discovery_method() {
+        let config: Config = toml::from_str("").unwrap();
+        assert_eq!(config.daemon.discovery_method, DiscoveryMethod::Mdns);
+
+        let toml_str = r#"
+[daemon]
+discovery_method = "broadcast"
+broadcast_port = 24900
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.daemon.discovery_method, DiscoveryMethod::Broadcast);
+        assert_eq!(config.daemon.broadcast_port, Some(24900));
+    }
+
+    #[test]
+    fn parse_limits() {
+        let config: Config = toml::from_str("").unwrap();
+        assert_eq!(config.limits.device_queue, 1024);
+
+        let toml_str = "
+[limits]
+device_queue = 64
+discovered_peers = 16
+session_history = 8
+peer_devices = 4
+metrics_days = 7
+control_message_size = 65536
+";
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.limits.device_queue, 64);
+        assert_eq!(config.limits.peer_errors, 32);
+        assert_eq!(config.limits.discovered_peers, 16);
+        assert_eq!(config.limits.session_history, 8);
+        assert_eq!(config.limits.peer_devices, 4);
+        assert_eq!(config.limits.metrics_days, 7);
+        assert_eq!(config.limits.control_message_size, 65536);
+        assert_eq!(config.limits.input_message_size, 1024 * 1024);
+    }
+
+    #[test]
+    fn performance_preset_with_overrides() {
+        let config: Config = toml::from_str("").unwrap();
+        assert_eq!(config.performance.tick(), Duration::from_secs(1));
+        assert_eq!(config.performance.coalesce(), None);
+        assert_eq!(
+            config.performance.keepalive(&config.daemon),
+            Duration::from_secs(5)
+        );
+
+        let toml_str = r#"
+[performance]
+preset = "low-power"
+tick_ms = 500
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.performance.tick(), Duration::from_millis(500));
+        assert_eq!(
+            config.performance.coalesce(),
+            Some(Duration::from_millis(16))
+        );
+        assert_eq!(
+            config.performance.keepalive(&config.daemon),
+            Duration::from_secs(15)
+        );
+        assert_eq!(config.performance.event_queue(), 256);
+    }
+
+    #[test]
+    fn redacted_toml_hides_addresses() {
+        let toml_str = r#"
+[daemon]
+bind = "192.168.1.10"
+
+[security]
+allowed_subnets = ["192.168.1.0/24"]
+auth_command = ["check-peer", "--token", "s3cret-auth"]
+
+[alarms]
+command = ["notify", "--webhook", "https://hooks.example/s3cret-alarm"]
+
+[commands.backup]
+run = ["rsync", "-a", "/home/alice", "backup.example:"]
+peers = ["laptop"]
+
+[[screens]]
+name = "laptop"
+address = "192.168.1.20:24800"
+position = "Right"
+fingerprint = "SHA256:abc"
+
+[profiles.home]
+ssids = ["HomeWifi"]
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let redacted = config.redacted_toml().unwrap();
+        for secret in [
+            "192.168.1",
+            "SHA256:abc",
+            "HomeWifi",
+            "s3cret-auth",
+            "s3cret-alarm",
+            "alice",
+        ] {
+            assert!(!redacted.contains(secret), "{secret} in {redacted}");
+        }
+        assert!(redacted.contains("laptop"));
+
+        let parsed: toml::Value = toml::from_str(&redacted).unwrap();
+        assert_eq!(parsed["screens"][0]["position"].as_str(), Some("Right"));
+    }
+
+    #[test]
+    fn parse_allowed_subnets() {
+        let toml_str = r#"
+[security]
+allowed_subnets = ["192.168.1.0/24", "10.8.0.0/24"]
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.security.allowed_subnets.len(), 2);
+        assert_eq!(
+            config.security.allowed_subnets[1].to_string(),
+            "10.8.0.0/24"
+        );
+
+        let toml_str = r#"
+[security]
+allowed_subnets = ["192.168.1.0/40"]
+"#;
+        let err = toml::from_str::<Config>(toml_str).unwrap_err();
+        assert!(err.to_string().contains("prefix must be 0-32"), "{err}");
+    }
+
+    #[test]
+    fn parse_drag_scroll() {
+        let toml_str = r#"
+[[input.drag_scroll]]
+button = "Middle"
+devices = ["Trackball"]
+
+[[input.drag_scroll]]
+button = { Other = 8 }
+step = 40
+natural = true
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let rules = &config.input.drag_scroll;
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].button, MouseButton::Middle);
+        assert_eq!(rules[0].step, 15);
+        assert!(rules[0].matches("Logitech USB trackball"));
+        assert!(!rules[0].matches("Office Mouse"));
+        assert_eq!(rules[1].button, MouseButton::Other(8));
+        assert!(rules[1].natural);
+        assert!(rules[1].matches("Office Mouse"));
+    }
+
+    #[test]
+    fn parse_corners() {
+        let toml_str = r#"
+[[input.corners]]
+corner = "bottom-right"
+action = "release"
+
+[[input.corners]]
+corner = "top-left"
+action = { switch = "laptop" }
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(
+            config.input.corners,
+            vec![
+                CornerRule {
+                    corner: ScreenCorner::BottomRight,
+                    action: CornerAction::Release,
+                },
+                CornerRule {
+                    corner: ScreenCorner::TopLeft,
+                    action: CornerAction::Switch("laptop".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_hotkey_sequences() {
+        let toml_str = r#"
+[input]
+chord_timeout_ms = 1500
+
+[[input.sequences]]
+steps = ["F9", "Digit1"]
+action = { switch = "laptop" }
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.input.chord_timeout_ms, 1500);
+        assert_eq!(
+            config.input.sequences,
+            vec![HotkeySequence {
+                steps: vec!["F9".to_string(), "Digit1".to_string()],
+                action: CornerAction::Switch("laptop".to_string()),
+            }]
+        );
+        assert_eq!(Config::default().input.chord_timeout_ms, 1000);
+    }
+
+    #[test]
+    fn parse_local_input_policy() {
+        let config: Config = toml::from_str("").unwrap();
+        assert_eq!(
+            config.controlled.local_input_policy,
+            LocalInputPolicy::Ignore
+        );
+        assert_eq!(config.controlled.local_input_secs, 3);
+        let toml_str = r#"
+[controlled]
+local_input_policy = "pause-remote"
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(
+            config.controlled.local_input_policy,
+            LocalInputPolicy::PauseRemote
+        );
+        let toml_str = r#"
+[controlled]
+local_input_policy = "hold"
+local_input_secs = 5
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.controlled.local_input_policy, LocalInputPolicy::Hold);
+        assert_eq!(config.controlled.local_input_secs, 5);
+    }
+
+    #[test]
+    fn parse_commands() {
+        let toml_str = r#"
+[commands.lock-screen]
+run = ["loginctl", "lock-session"]
+peers = ["desktop", "laptop"]
+
+[commands.suspend]
+run = ["systemctl", "suspend"]
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let lock = &config.commands["lock-screen"];
+        assert_eq!(lock.run, ["loginctl", "lock-session"]);
+        assert_eq!(lock.peers, ["desktop", "laptop"]);
+        assert!(config.commands["suspend"].peers.is_empty());
+    }
+
+    #[test]
+    fn parse_alarms() {
+        let toml_str = r#"
+[alarms]
+rtt_ms = 40
+loss_percent = 2.0
+command = ["notify-ops"]
+
+[alarms.peers.laptop]
+rtt_ms = 120
+"#;
+        let config = Config::from_toml(toml_str).unwrap();
+        let alarms = &config.alarms;
+        assert_eq!(alarms.for_secs, 10);
+        assert!(alarms.notify);
+        assert_eq!(alarms.command, ["notify-ops"]);
+        assert_eq!(
+            alarms.thresholds_for("laptop"),
+            AlarmThresholds {
+                rtt_ms: Some(120),
+                loss_percent: Some(2.0),
+            }
+        );
+        assert_eq!(alarms.thresholds_for("desktop").rtt_ms, Some(40));
+        assert!(Config::default().alarms.thresholds_for("laptop").is_empty());
+    }
+
+    #[test]
+    fn parse_profiles() {
+        let toml_str = r#"
+[daemon]
+profile = "docked"
+
+[[screens]]
+name = "laptop"
+position = "Left"
+
+[profiles.docked]
+ssids = ["office"]
+
+[[profiles.docked.screens]]
+name = "desk-left"
+position = "Left"
+
+[[profiles.docked.screens]]
+name = "desk-right"
+position = "Right"
+
+[profiles.travel]
+ssids = ["home", "phone"]
+
+[[profiles.travel.screens]]
+name = "home-pc"
+address = "10.8.0.2"
+position = "Right"
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.daemon.profile.as_deref(), Some("docked"));
+        assert_eq!(config.screens.len(), 1);
+        assert_eq!(config.profile_layout("docked").unwrap().screens.len(), 2);
+        assert!(config.profile_layout("missing").is_none());
+        assert_eq!(config.profile_for_ssid("phone"), Some("travel"));
+        assert_eq!(config.profile_for_ssid("cafe"), None);
+
+        let round_trip: Config = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(round_trip.profiles.len(), 2);
+    }
+
+    #[test]
+    fn parse_viewer_screen() {
+        let toml_str = r#"
+[[screens]]
+name = "tablet"
+position = "Left"
+mode = "Viewer"
+wake_on_enter = false
+locate_on_enter = true
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.screens[0].mode, ScreenMode::Viewer);
+        assert!(!config.screens[0].wake_on_enter);
+        assert!(config.screens[0].locate_on_enter);
+        assert!(config.screens[0].pointer_scale.is_identity());
+        assert!(config.screens[0].shares_device("anything"));
+    }
+
+    #[test]
+    fn parse_share_devices() {
+        let toml_str = r#"
+[[screens]]
+name = "htpc"
+position = "Below"
+share_devices = ["*keyboard*", "MX Master ?"]
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let screen = &config.screens[0];
+        assert!(screen.shares_device("Logitech USB Keyboard"));
+        assert!(screen.shares_device("KEYBOARD"));
+        assert!(!screen.shares_device("Macro Pad"));
+        // Only `*` is special.
+        assert!(screen.shares_device("MX Master ?"));
+        assert!(!screen.shares_device("MX Master 3"));
+    }
+
+    #[test]
+    fn parse_device_overrides() {
+        let toml_str = r#"
+[input]
+include_devices = ["keyd*"]
+exclude_devices = ["*yubikey*"]
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let filter = config.input.device_filter();
+        assert!(filter.includes("keyd virtual keyboard"));
+        assert!(filter.excludes("Yubico YubiKey OTP+FIDO+CCID"));
+        assert_eq!(
+            Config::default().input.device_filter(),
+            DeviceFilter::default()
+        );
+    }
+
+    #[test]
+    fn parse_pointer_scale() {
+        let toml_str = r#"
+[[screens]]
+name = "ultrawide"
+position = "Right"
+pointer_scale = { x = 1.5 }
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let scale = config.screens[0].pointer_scale;
+        assert_eq!(scale, PointerScale { x: 1.5, y: 1.0 });
+
+        let mut carry = (0.0, 0.0);
+        assert_eq!(scale.apply(3, -3, &mut carry), (4, -3));
+        assert_eq!(scale.apply(1, 0, &mut carry), (2, 0));
+        assert_eq!(carry, (0.0, 0.0));
+    }
+
+    #[test]
+    fn screens_inherit_group_settings() {
+        let toml_str = r#"
+[group.work]
+mode = "Viewer"
+clipboard = false
+share_devices = ["*Keyboard*"]
+
+[[screens]]
+name = "build-box"
+position = "Right"
+group = "work"
+
+[[screens]]
+name = "work-laptop"
+position = "Left"
+group = "work"
+clipboard = true
+
+[[screens]]
+name = "home-pc"
+position = "Above"
+
+[[profiles.travel.screens]]
+name = "work-tablet"
+position = "Below"
+group = "work"
+"#;
+        let config = Config::from_toml(toml_str).unwrap();
+        let build_box = &config.screens[0];
+        assert_eq!(build_box.mode, ScreenMode::Viewer);
+        assert!(!build_box.clipboard);
+        assert!(!build_box.shares_device("Mouse"));
+        assert!(build_box.wake_on_enter);
+
+        let laptop = &config.screens[1];
+        assert_eq!(laptop.mode, ScreenMode::Viewer);
+        assert!(laptop.clipboard);
+
+        let home = &config.screens[2];
+        assert_eq!(home.mode, ScreenMode::Control);
+        assert!(home.clipboard);
+
+        let travel = config.profile_layout("travel").unwrap();
+        assert_eq!(travel.screens[0].mode, ScreenMode::Viewer);
+
+        let err =
+            Config::from_toml("[[screens]]\nname = \"x\"\nposition = \"Left\"\ngroup = \"home\"")
+                .unwrap_err();
+        assert!(err.to_string().contains("`home` is not defined"), "{err}");
+    }
+
+    #[test]
+    fn saved_screens_leave_group_settings_to_the_group() {
+        let toml_str = r#"
+[group.work]
+clipboard = false
+commands = ["lock"]
+release_hotkey = ["F12"]
+
+[[screens]]
+name = "build-box"
+position = "Right"
+group = "work"
+
+[[screens]]
+name = "work-laptop"
+position = "Left"
+group = "work"
+clipboard = true
+"#;
+        let config = Config::from_toml(toml_str).unwrap();
+        assert_eq!(config.screens[0].commands, vec!["lock"]);
+        assert_eq!(
+            config.screens[0].release_hotkey.as_deref(),
+            Some(&["F12".to_string()][..])
+        );
+
+        let saved = config.to_toml().unwrap();
+        let table: toml::Table = toml::from_str(&saved).unwrap();
+        let screens = table["screens"].as_array().unwrap();
+        let build_box = screens[0].as_table().unwrap();
+        for key in ["clipboard", "commands", "release_hotkey"] {
+            assert!(!build_box.contains_key(key), "{key} saved in {saved}");
+        }
+        assert_eq!(screens[1]["clipboard"].as_bool(), Some(true));
+
+        // An edit to the group still reaches the screens after a save.
+        let edited = saved.replace("clipboard = false", "clipboard = true");
+        let config = Config::from_toml(&edited).unwrap();
+        assert!(config.screens[0].clipboard);
     }
 }
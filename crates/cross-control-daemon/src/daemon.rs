@@ -1,20 +1,50 @@
 //! Core daemon orchestration.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
-use cross_control_input::{InputCapture, InputEmulation};
-use cross_control_protocol::QuicTransport;
+use cross_control_clipboard::ClipboardProvider;
+use cross_control_discovery::{Discovery, DiscoveryEvent, ServiceCapability, TxtRecord};
+use cross_control_input::{DryRunEmulation, InputCapture, InputEmulation, InputError};
+use cross_control_protocol::{ProtocolError, Transport};
 use cross_control_types::{
-    CapturedEvent, ControlMessage, DeviceInfo, InputEvent, InputMessage, KeyCode, MachineId,
-    ScreenEdge, ScreenGeometry,
+    warn_throttled, ButtonState, CapturedEvent, ClipboardContent, ClipboardFormat,
+    ClipboardMessage, ControlMessage, DeviceCapability, DeviceId, DeviceInfo, EnterDeniedReason,
+    ErrorCode, FocusHint, InputEvent, InputMessage, KeyCode, KeyboardLayout, MachineId, Position,
+    PowerState, ProtocolFeature, ScreenEdge, ScreenGeometry, ScreenState, PROTOCOL_VERSION,
 };
-use tokio::sync::{mpsc, watch};
-use tracing::{debug, info, warn};
+use tokio::sync::{mpsc, oneshot, watch};
+use tracing::{debug, field, info, info_span, instrument, warn, Instrument};
 
-use crate::config::Config;
+use crate::alarms::AlarmChange;
+use crate::auth::{AuthHook, CommandHook};
+use crate::clock::ClockWatch;
+use crate::config::{
+    ClipboardScope, Config, ControlRequestPolicy, CornerAction, LayoutConfig, LocalInputPolicy,
+    ScreenAdjacency, ScreenConfig, ScreenCorner, ScreenMode,
+};
+use crate::drag_scroll::DragScroll;
+use crate::edges::EdgeTable;
 use crate::error::DaemonError;
-use crate::session::PeerSession;
+use crate::failsafe::ReleaseOnDrop;
+use crate::handshake::{Identity, Role};
+use crate::hotkey::{SequenceKey, SequenceMatcher};
+use crate::injector::{is_release, Injector};
+use crate::ipc::{
+    CommandOutcome, Counters, DeviceEntry, DeviceReport, Evictions, HistoryEvent, InputGrab,
+    IpcRequest, IpcResponse, LinkAlarmReport, PeerDevices, PeerErrorReport, PeerKeyboardLayout,
+    SessionCode, SessionHistory, SessionStatsReport, StatusReport, VersionSkewReport,
+};
+use crate::layout::LayoutStats;
+use crate::metrics::MetricsHistory;
+use crate::pairing;
+use crate::presence::{PeerDirectory, Sighting};
+use crate::session::{MessageLimits, PeerSession};
+use crate::supervisor;
+use crate::watchdog::Heartbeat;
 
 /// Events processed by the daemon's main loop.
 pub enum DaemonEvent {
@@ -27,27 +57,143 @@ pub enum DaemonEvent {
         machine_id: MachineId,
         msg: ControlMessage,
     },
-    /// An input message from a peer.
+    /// An input message from a peer, read from the input stream of control
+    /// episode `episode`.
     PeerInput {
         machine_id: MachineId,
+        episode: u64,
         msg: InputMessage,
     },
+    /// A peer's input stream for `episode` ended, or a Leave stopped waiting
+    /// for it to end.
+    PeerInputEnded { machine_id: MachineId, episode: u64 },
     /// A peer disconnected.
     PeerDisconnected(MachineId),
+    /// The local clipboard changed.
+    ClipboardChanged(ClipboardContent),
+    /// The local power source changed.
+    PowerChanged(PowerState),
+    /// The local keyboard layout was read or changed.
+    KeyboardLayoutChanged(KeyboardLayout),
+    /// The local screen was locked or unlocked, or its displays slept or
+    /// woke.
+    ScreenStateChanged(ScreenState),
+    /// Periodic timer for keepalives.
+    Tick,
+    /// Send pointer motion coalesced for a battery peer.
+    FlushMotion,
+    /// Send the next move of a `locate_on_enter` shake to a peer.
+    LocateStep { peer: MachineId, step: usize },
+    /// The machine is about to sleep; `done` is signalled once peers have
+    /// been told.
+    PrepareForSleep { done: oneshot::Sender<()> },
+    /// The machine woke from sleep.
+    Resumed,
+    /// The active Wi-Fi network changed.
+    NetworkChanged { ssid: Option<String> },
+    /// Local interface addresses changed.
+    InterfacesChanged,
+    /// A peer appeared on or vanished from the network.
+    Discovery(DiscoveryEvent),
+    /// The discovery browse channel closed or its forwarder panicked.
+    DiscoveryStopped,
     /// A fully handshaked session is ready (from a background task).
     SessionReady { session: PeerSession },
+    /// A peer rejected our handshake and said why.
+    PeerError {
+        peer: String,
+        code: ErrorCode,
+        detail: String,
+    },
+    /// A connection dialled for `pair` finished its handshake (or failed).
+    PairConnected {
+        address: SocketAddr,
+        result: Result<PeerSession, DaemonError>,
+        reply: oneshot::Sender<IpcResponse>,
+    },
+    /// A request from the CLI over the IPC socket.
+    Ipc {
+        request: IpcRequest,
+        reply: oneshot::Sender<IpcResponse>,
+    },
+    /// The watchdog saw the event loop stall and released input devices;
+    /// the loop is running again.
+    WatchdogRecovered,
+    /// Input from the peer controlling us in `episode` failed its check
+    /// after Enter (`input.verify_on_enter`).
+    InputCheckFailed {
+        machine_id: MachineId,
+        episode: u64,
+        code: ErrorCode,
+        detail: String,
+    },
     /// Shutdown signal.
     Shutdown,
 }
 
+impl DaemonEvent {
+    /// Whether this is a per-keystroke or per-motion input event.
+    fn is_input(&self) -> bool {
+        matches!(self, Self::CapturedInput(_) | Self::PeerInput { .. })
+    }
+
+    /// Variant name, for diagnostics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::IncomingConnection(_) => "IncomingConnection",
+            Self::CapturedInput(_) => "CapturedInput",
+            Self::PeerControl { .. } => "PeerControl",
+            Self::PeerInput { .. } => "PeerInput",
+            Self::PeerInputEnded { .. } => "PeerInputEnded",
+            Self::PeerDisconnected(_) => "PeerDisconnected",
+            Self::ClipboardChanged(_) => "ClipboardChanged",
+            Self::PowerChanged(_) => "PowerChanged",
+            Self::KeyboardLayoutChanged(_) => "KeyboardLayoutChanged",
+            Self::ScreenStateChanged(_) => "ScreenStateChanged",
+            Self::Tick => "Tick",
+            Self::FlushMotion => "FlushMotion",
+            Self::LocateStep { .. } => "LocateStep",
+            Self::PrepareForSleep { .. } => "PrepareForSleep",
+            Self::Resumed => "Resumed",
+            Self::NetworkChanged { .. } => "NetworkChanged",
+            Self::InterfacesChanged => "InterfacesChanged",
+            Self::Discovery(_) => "Discovery",
+            Self::DiscoveryStopped => "DiscoveryStopped",
+            Self::SessionReady { .. } => "SessionReady",
+            Self::PeerError { .. } => "PeerError",
+            Self::PairConnected { .. } => "PairConnected",
+            Self::Ipc { .. } => "Ipc",
+            Self::WatchdogRecovered => "WatchdogRecovered",
+            Self::InputCheckFailed { .. } => "InputCheckFailed",
+            Self::Shutdown => "Shutdown",
+        }
+    }
+}
+
 /// Observable daemon status (via watch channel).
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct DaemonStatus {
     pub controlling: Option<MachineId>,
     pub controlled_by: Option<MachineId>,
     pub session_count: usize,
     pub cursor_x: i32,
     pub cursor_y: i32,
+    /// Whether the input emulation backend works. When `false`, peers are
+    /// refused with `EnterDenied` and this machine cannot be controlled.
+    pub emulation_available: bool,
+    /// Whether cross-control activity is paused (sessions stay open).
+    pub paused: bool,
+    /// Whether keyboard input is kept from peers by the private hotkey.
+    pub private_input: bool,
+    /// Where input to `controlling` is landing, as last hinted by it.
+    pub remote_focus: Option<FocusHint>,
+    /// Whether local input devices are grabbed. They should be while
+    /// `controlling` is set.
+    pub input_grab: InputGrab,
+    /// Whether startup readiness has been reported: sessions with every
+    /// `wait_on_start` screen are up, or `daemon.wait_on_start_secs` passed.
+    pub ready: bool,
 }
 
 impl Default for DaemonStatus {
@@ -58,53 +204,291 @@ impl Default for DaemonStatus {
             session_count: 0,
             cursor_x: 960,
             cursor_y: 540,
+            emulation_available: true,
+            paused: false,
+            private_input: false,
+            remote_focus: None,
+            input_grab: InputGrab::Released,
+            ready: false,
         }
     }
 }
 
+/// Delay before retrying a failed grab of local input devices, doubled on
+/// each further failure up to `GRAB_RETRY_MAX`.
+const GRAB_RETRY_MIN: Duration = Duration::from_millis(250);
+const GRAB_RETRY_MAX: Duration = Duration::from_secs(8);
+
+/// How often the local screen's lock and display state is checked.
+const SCREEN_STATE_POLL: Duration = Duration::from_secs(2);
+
+/// A crossing that returns within this window counts as a bounce for
+/// layout suggestions.
+const BOUNCE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Sessions that drop this soon after waking or a network change are
+/// redialled: they most likely timed out on the old path.
+const RESUME_RECONNECT_WINDOW: Duration = Duration::from_secs(60);
+
+/// A peer that discovery reported gone must answer a ping within this long,
+/// or its session is dropped without waiting for the QUIC idle timeout.
+const LIVENESS_PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long the check after Enter (`input.verify_on_enter`) may take,
+/// including waiting for virtual devices still being created.
+const INPUT_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long a Leave from the peer controlling us waits for the rest of its
+/// input stream before control is released anyway.
+const INPUT_DRAIN_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Profile name that selects the top-level `[[screens]]` layout.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// How long a `pair` connection waits for the user to confirm the peer.
+const PAIR_CONFIRM_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// The `[commands]` entry run everywhere by `input.lock_all_hotkey`.
+const LOCK_SCREEN_COMMAND: &str = "lock-screen";
+
+/// How long a `RunEverywhere` waits for the peers' `CommandResult`s.
+const COMMAND_ANSWER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often the daily usage totals are written to disk.
+const METRICS_SAVE_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Pointer moves of a `locate_on_enter` shake, along the entry edge. They
+/// add up to zero, so the cursor ends where it entered.
+const LOCATE_SHAKE: [i32; 8] = [24, -48, 48, -48, 48, -48, 48, -24];
+
+/// Time between the moves of a `locate_on_enter` shake.
+const LOCATE_STEP: Duration = Duration::from_millis(40);
+
+/// A controller that went silent mid-episode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SilentController {
+    peer: MachineId,
+    episode: u64,
+    /// Restored on resume, so the cursor doesn't bounce straight back.
+    entry_edge: Option<ScreenEdge>,
+}
+
+/// Something a CLI request does on a peer once we control it.
+enum PeerAction {
+    /// Move its cursor to `(x, y)` on its screen.
+    Warp { x: u32, y: u32 },
+    /// Type the text with its keyboard.
+    Type(String),
+}
+
+/// A [`PeerAction`] waiting for the peer's `EnterAck`.
+struct PendingEntry {
+    peer: MachineId,
+    action: PeerAction,
+    reply: oneshot::Sender<IpcResponse>,
+}
+
+/// A CLI `Ping` waiting for the peer's `Pong`.
+struct PendingPing {
+    peer: MachineId,
+    sent: Instant,
+    reply: oneshot::Sender<IpcResponse>,
+}
+
+/// A connection made for `pair`, waiting for the user to confirm it.
+struct PendingPair {
+    session: PeerSession,
+    address: SocketAddr,
+    since: Instant,
+}
+
+/// Relative pointer motion accumulated for one peer.
+struct PendingMotion {
+    peer: MachineId,
+    device_id: DeviceId,
+    timestamp_us: u64,
+    dx: i32,
+    dy: i32,
+}
+
 /// The core cross-control daemon.
 pub struct Daemon {
     config: Config,
     machine_id: MachineId,
     screen: ScreenGeometry,
-    transport: QuicTransport,
+    transport: Arc<dyn Transport>,
+    /// Asked whether to admit each inbound peer during its handshake.
+    auth: Option<Arc<dyn AuthHook>>,
     capture: Box<dyn InputCapture>,
-    emulation: Box<dyn InputEmulation>,
+    /// Per-device injection queues wrapping the emulation backend.
+    injector: Injector,
+    /// Result of the startup emulation probe.
+    emulation_available: bool,
     sessions: HashMap<MachineId, PeerSession>,
     local_devices: Vec<DeviceInfo>,
+    /// Events captured from each local device since startup.
+    captured_events: HashMap<DeviceId, u64>,
     event_tx: mpsc::Sender<DaemonEvent>,
     event_rx: mpsc::Receiver<DaemonEvent>,
     /// Virtual cursor position for barrier detection.
     cursor_x: i32,
     cursor_y: i32,
+    /// The screen corner the cursor is in, so its action runs once per visit.
+    cursor_corner: Option<ScreenCorner>,
     /// Which peer we are currently controlling, if any.
     controlling: Option<MachineId>,
+    /// When control of `controlling` began.
+    controlling_since: Option<Instant>,
     /// Which peer is currently controlling us, if any.
     controlled_by: Option<MachineId>,
+    /// Until when the local user counts as active while `controlled_by`
+    /// drives us, under the `notify` and `hold` local input policies.
+    local_user_until: Option<Instant>,
     /// The edge the cursor entered from when we are being controlled.
     /// Suppresses Leave checks on this edge until the cursor moves away,
     /// preventing an immediate bounce-back when the cursor starts AT the
     /// entry edge.
     entry_edge: Option<ScreenEdge>,
+    /// Counts the control episodes we accepted. Input read from an earlier
+    /// episode's stream is never injected into a later one.
+    input_episode: u64,
+    /// Latest episode whose input stream has ended.
+    input_ended: Option<u64>,
+    /// Set while a Leave from the peer controlling us waits for the end of
+    /// its input stream, so every event sent before the Leave is injected
+    /// before control is released.
+    draining: Option<(MachineId, u64)>,
+    /// When the peer controlling us last sent input or a control message.
+    controller_seen: Instant,
+    /// The controller we stopped following after it went silent; input on
+    /// the same episode resumes it.
+    silent_controller: Option<SilentController>,
     /// Hotkey state tracking: set of currently pressed keys.
     hotkey_pressed: Vec<KeyCode>,
+    /// Progress through `[[input.sequences]]`.
+    hotkey_sequences: SequenceMatcher,
+    /// Paused: no control in either direction and no clipboard sync.
+    paused: bool,
+    /// Set while private input is on: keyboard events are neither forwarded
+    /// nor counted. Holds the keys pressed before it was turned on, whose
+    /// releases are still forwarded so they don't stick on the peer.
+    private_input: Option<Vec<KeyCode>>,
+    /// Discovery backend, if one is available.
+    discovery: Option<Box<dyn Discovery>>,
+    /// Peers currently advertised on the network.
+    directory: PeerDirectory,
+    /// Local clipboard backend, if one is available on this platform.
+    clipboard: Option<Box<dyn ClipboardProvider>>,
+    /// Latest local clipboard content, served to peers that request it.
+    clipboard_local: Option<ClipboardContent>,
+    /// Content last written from a peer, so its change notification is not
+    /// offered straight back.
+    clipboard_applied: Option<ClipboardContent>,
+    /// Local power source, if the platform reports one.
+    power: Option<PowerState>,
+    /// Local keyboard layout, once known.
+    keyboard_layout: Option<KeyboardLayout>,
+    /// Whether the local screen is locked or asleep.
+    screen_state: ScreenState,
+    /// The focus hint last sent to our controller, and to whom.
+    focus_hint_sent: Option<(MachineId, FocusHint)>,
+    /// Pointer motion held back for a battery peer until the coalescing
+    /// window closes.
+    pending_motion: Option<PendingMotion>,
+    /// Button-held scrolling applied to forwarded input.
+    drag_scroll: DragScroll,
+    /// Fractional motion left over from `pointer_scale`, carried into the
+    /// next forwarded move.
+    pointer_carry: (f64, f64),
+    /// Sequence number for keepalive and CLI pings.
+    ping_seq: u64,
+    /// When the machine last woke from sleep.
+    resumed_at: Option<Instant>,
+    /// When local interface addresses last changed.
+    network_changed_at: Option<Instant>,
+    /// Edge and crossing usage for `layout suggest`.
+    layout: LayoutStats,
+    /// Daily usage totals for `stats --since`.
+    metrics: MetricsHistory,
+    /// Where `metrics` is saved; `None` keeps it in memory only.
+    metrics_path: Option<PathBuf>,
+    /// When control time was last added to `metrics`, and when it was last
+    /// saved.
+    metrics_tick: Instant,
+    metrics_saved: Instant,
+    /// Where the config was loaded from, so pushed layouts persist.
+    config_path: Option<PathBuf>,
+    /// Directory holding the persistent `denylist`, so bans persist.
+    denylist_dir: Option<PathBuf>,
+    /// Active profile; `None` while the top-level layout is in use.
+    profile: Option<String>,
+    /// The top-level layout, restored by switching to [`DEFAULT_PROFILE`].
+    base_layout: LayoutConfig,
+    /// Last error each peer reported to us, by peer name (or address, if
+    /// the handshake never got far enough to learn the name), with the
+    /// sequence number used to forget the oldest past `limits.peer_errors`.
+    peer_errors: BTreeMap<String, (ErrorCode, String, u64)>,
+    /// Sequence number of the last recorded peer error.
+    peer_error_seq: u64,
+    /// When the daemon was created, for `status`.
+    started: (Instant, SystemTime),
+    /// Wall and monotonic clocks at the last tick, to notice clock jumps.
+    clock: ClockWatch,
+    /// Activity counts reported by `status`.
+    counters: Counters,
+    /// Every machine a session has been established with, to count
+    /// reconnects.
+    seen_peers: HashSet<MachineId>,
+    /// Peer errors forgotten because of `limits.peer_errors`.
+    peer_errors_evicted: u64,
+    /// CLI requests waiting for a peer to answer a `ConfigPush`.
+    pending_pushes: HashMap<MachineId, oneshot::Sender<IpcResponse>>,
+    /// CLI requests waiting for a peer to answer a `RunCommand`.
+    pending_commands: HashMap<MachineId, oneshot::Sender<IpcResponse>>,
+    /// CLI requests waiting for a peer to answer a `RequestControl`, with
+    /// the edge to enter it through once it agrees.
+    pending_control_requests: HashMap<MachineId, (oneshot::Sender<IpcResponse>, ScreenEdge)>,
+    /// CLI requests waiting for a `Pong`, by ping sequence number.
+    pending_pings: HashMap<u64, PendingPing>,
+    /// Connections made for `pair`, by peer certificate fingerprint.
+    pending_pairs: HashMap<String, PendingPair>,
+    /// CLI request acting on a peer we are still entering.
+    pending_entry: Option<PendingEntry>,
+    /// Event loop progress, watched for stalls.
+    heartbeat: Arc<Heartbeat>,
+    /// The peer whose control has local devices grabbed, when
+    /// `input.suppress_local_while_controlled` is set.
+    local_input_grabbed: Option<MachineId>,
+    /// Whether the capture backend has local devices grabbed.
+    input_grab: InputGrab,
+    /// When to try again after failed grabs, and how many failed in a row.
+    grab_retry: Option<(Instant, u32)>,
+    /// Set until startup readiness is reported: when to stop waiting for
+    /// `wait_on_start` screens.
+    startup_deadline: Option<Instant>,
     /// Status broadcast channel.
     status_tx: watch::Sender<DaemonStatus>,
-    /// Full screen adjacency graph: `(screen_name, edge) → neighbor_name`.
-    adjacency: HashMap<(String, ScreenEdge), String>,
+    /// When the status was last published.
+    status_sent_at: Instant,
+    /// A cursor-only status update is waiting for `status_interval`.
+    status_held: bool,
+    /// Where the cursor goes from each edge, resolved to connected peers.
+    edges: EdgeTable,
 }
 
 impl Daemon {
     /// Create a new daemon instance.
+    #[allow(clippy::too_many_lines)]
     pub fn new(
-        config: Config,
+        mut config: Config,
         machine_id: MachineId,
-        transport: QuicTransport,
+        transport: impl Transport,
         capture: Box<dyn InputCapture>,
         emulation: Box<dyn InputEmulation>,
     ) -> Self {
-        let screen = ScreenGeometry::new(config.daemon.screen_width, config.daemon.screen_height);
-        let (event_tx, event_rx) = mpsc::channel(1024);
+        let screen = ScreenGeometry::new(config.daemon.screen_width, config.daemon.screen_height)
+            .with_scale(config.daemon.screen_scale);
+        let (event_tx, event_rx) = mpsc::channel(config.performance.event_queue());
         let cursor_x = i32::try_from(screen.width / 2).unwrap_or(960);
         let cursor_y = i32::try_from(screen.height / 2).unwrap_or(540);
         let (status_tx, _) = watch::channel(DaemonStatus {
@@ -113,43 +497,119 @@ impl Daemon {
             ..DaemonStatus::default()
         });
 
-        // Build the full adjacency map.
-        // 1) From config.screens: our own direct neighbors.
-        let my_name = config.identity.name.clone();
-        let mut adjacency: HashMap<(String, ScreenEdge), String> = HashMap::new();
-        for sc in &config.screens {
-            let edge = sc.position.local_edge();
-            adjacency.insert((my_name.clone(), edge), sc.name.clone());
-            // Auto-generate inverse: neighbor → opposite edge → us
-            adjacency.insert((sc.name.clone(), edge.opposite()), my_name.clone());
-        }
-        // 2) From config.screen_adjacency: remote edges.
-        for adj in &config.screen_adjacency {
-            let edge = adj.position.local_edge();
-            adjacency.insert((adj.screen.clone(), edge), adj.neighbor.clone());
-            // Auto-generate inverse
-            adjacency.insert((adj.neighbor.clone(), edge.opposite()), adj.screen.clone());
+        let base_layout = LayoutConfig {
+            screens: config.screens.clone(),
+            screen_adjacency: config.screen_adjacency.clone(),
+        };
+        let profile = config.daemon.profile.clone().filter(|name| {
+            let found = config.profiles.contains_key(name);
+            if !found && name != DEFAULT_PROFILE {
+                warn!(profile = %name, "unknown profile, using the top-level layout");
+            }
+            found
+        });
+        if let Some(layout) = profile.as_deref().and_then(|p| config.profile_layout(p)) {
+            config.screens = layout.screens;
+            config.screen_adjacency = layout.screen_adjacency;
         }
+        let edges = EdgeTable::build(&config, []);
+        transport
+            .allowlist()
+            .set(config.security.allowed_subnets.clone());
+        let directory = PeerDirectory::new(machine_id, config.identity.name.clone())
+            .with_fingerprint(transport.fingerprint().map(str::to_string))
+            .with_limit(config.limits.discovered_peers);
+        let emulation: Box<dyn InputEmulation> = if config.input.dry_run {
+            warn!("input.dry_run is set: input from peers is logged, not injected");
+            Box::new(DryRunEmulation::new())
+        } else {
+            emulation
+        };
+        let injector = Injector::new(emulation, config.limits.device_queue);
+        let auth = (!config.security.auth_command.is_empty()).then(|| {
+            Arc::new(CommandHook::new(
+                config.security.auth_command.clone(),
+                Duration::from_millis(config.security.auth_timeout_ms),
+            )) as Arc<dyn AuthHook>
+        });
 
         Self {
             cursor_x,
             cursor_y,
+            cursor_corner: None,
             config,
             machine_id,
             screen,
-            transport,
+            transport: Arc::new(transport),
+            auth,
             capture,
-            emulation,
+            injector,
+            emulation_available: true,
             sessions: HashMap::new(),
             local_devices: Vec::new(),
+            captured_events: HashMap::new(),
             event_tx,
             event_rx,
             controlling: None,
+            controlling_since: None,
             controlled_by: None,
+            local_user_until: None,
             entry_edge: None,
+            input_episode: 0,
+            input_ended: None,
+            draining: None,
+            controller_seen: Instant::now(),
+            silent_controller: None,
             hotkey_pressed: Vec::new(),
+            hotkey_sequences: SequenceMatcher::default(),
+            paused: false,
+            private_input: None,
+            discovery: None,
+            directory,
+            clipboard: None,
+            clipboard_local: None,
+            clipboard_applied: None,
+            power: None,
+            keyboard_layout: None,
+            screen_state: ScreenState::Active,
+            focus_hint_sent: None,
+            pending_motion: None,
+            drag_scroll: DragScroll::default(),
+            pointer_carry: (0.0, 0.0),
+            ping_seq: 0,
+            resumed_at: None,
+            network_changed_at: None,
+            layout: LayoutStats::default(),
+            metrics: MetricsHistory::default(),
+            metrics_path: None,
+            metrics_tick: Instant::now(),
+            metrics_saved: Instant::now(),
+            config_path: None,
+            denylist_dir: None,
+            profile,
+            base_layout,
+            peer_errors: BTreeMap::new(),
+            peer_error_seq: 0,
+            started: (Instant::now(), SystemTime::now()),
+            clock: ClockWatch::new(),
+            counters: Counters::default(),
+            seen_peers: HashSet::new(),
+            peer_errors_evicted: 0,
+            pending_pushes: HashMap::new(),
+            pending_commands: HashMap::new(),
+            pending_control_requests: HashMap::new(),
+            pending_pings: HashMap::new(),
+            pending_pairs: HashMap::new(),
+            pending_entry: None,
+            heartbeat: Arc::default(),
+            local_input_grabbed: None,
+            input_grab: InputGrab::Released,
+            grab_retry: None,
+            startup_deadline: None,
             status_tx,
-            adjacency,
+            status_sent_at: Instant::now(),
+            status_held: false,
+            edges,
         }
     }
 
@@ -166,14 +626,37 @@ impl Daemon {
     /// Run the daemon event loop.
     #[allow(clippy::too_many_lines)]
     pub async fn run(&mut self) -> Result<(), DaemonError> {
+        // Probe emulation up front so peers are refused instead of silently
+        // controlling a machine that cannot inject input.
+        if let Err(e) = self.injector.probe().await {
+            self.emulation_available = false;
+            warn!(
+                error = %e,
+                "input emulation unavailable — this machine cannot be controlled by peers"
+            );
+        }
+
         // Start input capture
         let capture_tx = self.event_tx.clone();
-        let (input_tx, mut input_rx) = mpsc::channel::<CapturedEvent>(1024);
+        let (input_tx, mut input_rx) =
+            mpsc::channel::<CapturedEvent>(self.config.performance.capture_queue());
         self.capture.start(input_tx).await?;
 
+        let release = self.capture.release_handle();
+        let _release_guard = ReleaseOnDrop::new(release.clone());
+
+        if self.config.daemon.watchdog_secs > 0 {
+            crate::watchdog::spawn(
+                Arc::clone(&self.heartbeat),
+                Duration::from_secs(self.config.daemon.watchdog_secs),
+                release,
+                self.event_tx.clone(),
+            );
+        }
+
         // Forward captured input to daemon events
         let capture_event_tx = capture_tx.clone();
-        tokio::spawn(async move {
+        supervisor::spawn("capture-forwarder", async move {
             while let Some(event) = input_rx.recv().await {
                 if capture_event_tx
                     .send(DaemonEvent::CapturedInput(event))
@@ -185,136 +668,314 @@ impl Daemon {
             }
         });
 
+        // Watch the local clipboard
+        if self.config.clipboard.enabled {
+            if let Some(clipboard) = &mut self.clipboard {
+                match clipboard.watch().await {
+                    Ok(mut clipboard_rx) => {
+                        let clipboard_tx = self.event_tx.clone();
+                        supervisor::spawn("clipboard-watch", async move {
+                            while let Some(content) = clipboard_rx.recv().await {
+                                if clipboard_tx
+                                    .send(DaemonEvent::ClipboardChanged(content))
+                                    .await
+                                    .is_err()
+                                {
+                                    break;
+                                }
+                            }
+                        });
+                    }
+                    Err(e) => warn!(error = %e, "clipboard watch unavailable"),
+                }
+            }
+        }
+
+        // Housekeeping timer
+        {
+            let tick_tx = self.event_tx.clone();
+            let tick = self.config.performance.tick();
+            supervisor::spawn_restarting("tick", move || {
+                let tick_tx = tick_tx.clone();
+                async move {
+                    let mut interval = tokio::time::interval(tick);
+                    loop {
+                        interval.tick().await;
+                        if tick_tx.send(DaemonEvent::Tick).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+            });
+        }
+
+        // Poll the local power source
+        self.power = crate::power::read_power_state();
+        {
+            let power_tx = self.event_tx.clone();
+            let poll = Duration::from_secs(self.config.power.poll_secs.max(1));
+            let mut last = self.power;
+            supervisor::spawn("power-poll", async move {
+                loop {
+                    tokio::time::sleep(poll).await;
+                    let Ok(current) =
+                        tokio::task::spawn_blocking(crate::power::read_power_state).await
+                    else {
+                        break;
+                    };
+                    if current == last {
+                        continue;
+                    }
+                    last = current;
+                    if let Some(state) = current {
+                        if power_tx
+                            .send(DaemonEvent::PowerChanged(state))
+                            .await
+                            .is_err()
+                        {
+                            break;
+                        }
+                    }
+                }
+            });
+        }
+
+        // Tell our controller when the screen locks or sleeps
+        supervisor::spawn(
+            "screen-state",
+            crate::screen_state::watch(self.event_tx.clone(), SCREEN_STATE_POLL),
+        );
+
+        // Tell peers which keyboard layout we type with
+        supervisor::spawn(
+            "keyboard-layout",
+            crate::keyboard_layout::watch(self.event_tx.clone()),
+        );
+
+        // Tell peers before the machine sleeps
+        supervisor::spawn("sleep-watch", crate::sleep::watch(self.event_tx.clone()));
+
+        // Re-evaluate peers when interfaces come and go
+        supervisor::spawn(
+            "interface-watch",
+            crate::network::watch_interfaces(self.event_tx.clone()),
+        );
+
         let transport_local = self.transport.local_addr()?;
         info!(addr = %transport_local, "daemon listening");
 
-        // Spawn accept loop as a background task. Each accepted connection
-        // gets its own handshake task so the event loop never blocks.
+        // Advertise ourselves and browse for peers
+        if self.config.daemon.discovery {
+            let mut capabilities = Vec::new();
+            if self.emulation_available {
+                capabilities.push(ServiceCapability::Control);
+            }
+            if self.config.clipboard.enabled && self.clipboard.is_some() {
+                capabilities.push(ServiceCapability::Clipboard);
+            }
+            let record = TxtRecord {
+                version: PROTOCOL_VERSION,
+                machine_id: self.machine_id,
+                name: self.config.identity.name.clone(),
+                port: transport_local.port(),
+                fingerprint: self.transport.fingerprint().map(str::to_string),
+                capabilities,
+                group: self.config.daemon.group.clone(),
+            };
+            if let Some(discovery) = &mut self.discovery {
+                if let Err(e) = discovery.advertise(&record).await {
+                    warn!(error = %e, "discovery advertising unavailable");
+                }
+            }
+            self.start_browsing().await;
+        }
+
+        // Accept inbound connections; restarted if it panics.
         {
             let transport = self.transport.clone();
             let event_tx = self.event_tx.clone();
-            let our_id = self.machine_id;
-            let our_name = self.config.identity.name.clone();
-            let our_screen = self.screen.clone();
-            let local_devices = self.local_devices.clone();
-            tokio::spawn(async move {
+            let ours = self.identity();
+            let auth = self.auth.clone();
+            supervisor::spawn_restarting("accept", move || {
+                accept_loop(
+                    transport.clone(),
+                    event_tx.clone(),
+                    ours.clone(),
+                    auth.clone(),
+                )
+            });
+        }
+
+        // Pick a profile for the current network before dialling anyone.
+        let auto_profile = self.config.profiles.values().any(|p| !p.ssids.is_empty());
+        if auto_profile {
+            let ssid = tokio::task::spawn_blocking(crate::network::current_ssid)
+                .await
+                .unwrap_or_default();
+            if let Some(name) = ssid
+                .as_deref()
+                .and_then(|s| self.config.profile_for_ssid(s))
+            {
+                if self.profile.as_deref() != Some(name) {
+                    let name = name.to_string();
+                    if let Some(layout) = self.config.profile_layout(&name) {
+                        info!(profile = %name, "selected profile for the current network");
+                        self.config.screens = layout.screens;
+                        self.config.screen_adjacency = layout.screen_adjacency;
+                        self.rebuild_edges();
+                        self.profile = Some(name);
+                    }
+                }
+            }
+            let network_tx = self.event_tx.clone();
+            let poll = Duration::from_secs(self.config.daemon.ssid_poll_secs.max(1));
+            supervisor::spawn("ssid-poll", async move {
+                let mut last = ssid;
                 loop {
-                    match transport.accept().await {
-                        Ok(conn) => {
-                            let tx = event_tx.clone();
-                            let name = our_name.clone();
-                            let screen = our_screen.clone();
-                            let devs = local_devices.clone();
-                            tokio::spawn(async move {
-                                let remote = conn.remote_address();
-                                match perform_handshake_responder(
-                                    conn, our_id, &name, &screen, &devs,
-                                )
-                                .await
-                                {
-                                    Ok(session) => {
-                                        info!(
-                                            peer = %session.name,
-                                            remote = %remote,
-                                            "inbound handshake complete"
-                                        );
-                                        let _ =
-                                            tx.send(DaemonEvent::SessionReady { session }).await;
-                                    }
-                                    Err(e) => {
-                                        warn!(
-                                            remote = %remote,
-                                            error = %e,
-                                            "inbound handshake failed"
-                                        );
-                                    }
-                                }
-                            });
-                        }
-                        Err(e) => {
-                            debug!(error = %e, "accept loop ending");
-                            break;
-                        }
+                    tokio::time::sleep(poll).await;
+                    let Ok(current) =
+                        tokio::task::spawn_blocking(crate::network::current_ssid).await
+                    else {
+                        break;
+                    };
+                    if current == last {
+                        continue;
+                    }
+                    last.clone_from(&current);
+                    if network_tx
+                        .send(DaemonEvent::NetworkChanged { ssid: current })
+                        .await
+                        .is_err()
+                    {
+                        break;
                     }
                 }
             });
         }
 
-        // Spawn outbound connection + handshake tasks. Each task connects,
-        // completes the handshake, then sends the ready session back.
+        // Spawn outbound connection + handshake tasks.
         for sc in &self.config.screens {
-            if let Some(addr_str) = &sc.address {
-                let addr: Option<SocketAddr> = addr_str
-                    .parse()
-                    .or_else(|_| format!("{addr_str}:{}", self.config.daemon.port).parse())
-                    .ok();
-                if let Some(addr) = addr {
-                    let transport = self.transport.clone();
-                    let event_tx = self.event_tx.clone();
-                    let peer_name = sc.name.clone();
-                    let our_id = self.machine_id;
-                    let our_name = self.config.identity.name.clone();
-                    let our_screen = self.screen.clone();
-                    let local_devices = self.local_devices.clone();
-                    tokio::spawn(async move {
-                        match transport.connect(addr, "cross-control").await {
-                            Ok(conn) => {
-                                match perform_handshake_initiator(
-                                    conn,
-                                    our_id,
-                                    &our_name,
-                                    &our_screen,
-                                    &local_devices,
-                                )
-                                .await
-                                {
-                                    Ok(session) => {
-                                        info!(
-                                            peer = %session.name,
-                                            address = %addr,
-                                            "outbound handshake complete"
-                                        );
-                                        let _ = event_tx
-                                            .send(DaemonEvent::SessionReady { session })
-                                            .await;
-                                    }
-                                    Err(e) => {
-                                        warn!(
-                                            peer = %peer_name,
-                                            address = %addr,
-                                            error = %e,
-                                            "outbound handshake failed"
-                                        );
-                                    }
-                                }
-                            }
-                            Err(e) => {
-                                warn!(
-                                    address = %addr,
-                                    error = %e,
-                                    "failed to connect to peer"
-                                );
-                            }
-                        }
-                    });
-                }
-            }
+            self.connect_peer(sc);
         }
 
         info!("daemon running");
+        self.startup_deadline =
+            Some(Instant::now() + Duration::from_secs(self.config.daemon.wait_on_start_secs));
+        self.check_startup();
         self.broadcast_status();
 
         // Main event loop — purely event-driven, never blocks on I/O.
         while let Some(event) = self.event_rx.recv().await {
-            if self.handle_event(event).await {
+            let kind = event.kind();
+            self.heartbeat.begin(kind);
+            // Input events are too frequent for a span of their own; they
+            // get trace-level spans further down.
+            let stop = if event.is_input() {
+                self.handle_event(event).await
+            } else {
+                let span = info_span!("event", kind, session = field::Empty);
+                if let Some(id) = self.event_session(&event) {
+                    span.record("session", id);
+                }
+                self.handle_event(event).instrument(span).await
+            };
+            self.heartbeat.end();
+            self.sync_grab().await;
+            if stop {
                 break;
             }
         }
+        self.heartbeat.stop();
 
         self.shutdown().await
     }
 
+    /// ID of the session an event belongs to, if any.
+    fn event_session<'a>(&'a self, event: &'a DaemonEvent) -> Option<&'a str> {
+        match event {
+            DaemonEvent::PeerControl { machine_id, .. }
+            | DaemonEvent::PeerDisconnected(machine_id) => {
+                self.sessions.get(machine_id).map(|s| s.id.as_str())
+            }
+            DaemonEvent::SessionReady { session }
+            | DaemonEvent::PairConnected {
+                result: Ok(session),
+                ..
+            } => Some(&session.id),
+            _ => None,
+        }
+    }
+
+    /// What we tell peers about ourselves in the handshake.
+    fn identity(&self) -> Identity {
+        Identity {
+            machine_id: self.machine_id,
+            name: self.config.identity.name.clone(),
+            screen: self.screen.clone(),
+            version: PROTOCOL_VERSION,
+        }
+    }
+
+    /// Dial a configured screen in a background task. The task connects,
+    /// completes the handshake, then sends the ready session back.
+    fn connect_peer(&self, sc: &ScreenConfig) {
+        let Some(addr_str) = &sc.address else {
+            return;
+        };
+        let addr: Option<SocketAddr> = addr_str
+            .parse()
+            .or_else(|_| format!("{addr_str}:{}", self.config.daemon.peer_port()).parse())
+            .ok();
+        let Some(addr) = addr else {
+            return;
+        };
+        let transport = self.transport.clone();
+        let event_tx = self.event_tx.clone();
+        let peer_name = sc.name.clone();
+        let ours = self.identity();
+        supervisor::spawn("outbound-handshake", async move {
+            match transport.connect(addr, "cross-control").await {
+                Ok(conn) => match PeerSession::establish(conn, Role::Initiator, ours, None).await {
+                    Ok(session) => {
+                        info!(
+                            peer = %session.name,
+                            session = %session.id,
+                            address = %addr,
+                            "outbound handshake complete"
+                        );
+                        let _ = event_tx.send(DaemonEvent::SessionReady { session }).await;
+                    }
+                    Err(e) => {
+                        warn!(
+                            peer = %peer_name,
+                            address = %addr,
+                            error = %e,
+                            "outbound handshake failed"
+                        );
+                        if let Some((code, detail)) = remote_error(&e) {
+                            let _ = event_tx
+                                .send(DaemonEvent::PeerError {
+                                    peer: peer_name,
+                                    code,
+                                    detail,
+                                })
+                                .await;
+                        }
+                    }
+                },
+                Err(e) => {
+                    warn!(
+                        address = %addr,
+                        error = %e,
+                        "failed to connect to peer"
+                    );
+                }
+            }
+        });
+    }
+
     /// Handle a single daemon event. Returns `true` if the daemon should shut down.
+    #[allow(clippy::too_many_lines)]
     async fn handle_event(&mut self, event: DaemonEvent) -> bool {
         match event {
             DaemonEvent::CapturedInput(captured) => {
@@ -323,14 +984,176 @@ impl Daemon {
             DaemonEvent::PeerControl { machine_id, msg } => {
                 self.handle_peer_control(machine_id, msg).await;
             }
-            DaemonEvent::PeerInput { machine_id, msg } => {
-                self.handle_peer_input(machine_id, msg).await;
+            DaemonEvent::PeerInput {
+                machine_id,
+                episode,
+                msg,
+            } => {
+                self.handle_peer_input(machine_id, episode, msg).await;
+            }
+            DaemonEvent::PeerInputEnded {
+                machine_id,
+                episode,
+            } => {
+                self.handle_peer_input_ended(machine_id, episode);
             }
             DaemonEvent::PeerDisconnected(machine_id) => {
                 self.handle_peer_disconnected(machine_id).await;
             }
+            DaemonEvent::ClipboardChanged(content) => {
+                self.handle_clipboard_changed(content).await;
+            }
+            DaemonEvent::PowerChanged(state) => {
+                self.handle_power_changed(state).await;
+            }
+            DaemonEvent::KeyboardLayoutChanged(layout) => {
+                self.handle_keyboard_layout_changed(layout).await;
+            }
+            DaemonEvent::ScreenStateChanged(state) => {
+                info!(state = state.as_str(), "local screen state changed");
+                self.screen_state = state;
+                self.send_focus_hint().await;
+            }
+            DaemonEvent::Tick => {
+                self.check_clock();
+                self.update_metrics();
+                self.send_keepalives().await;
+                self.send_focus_hint().await;
+                self.expire_local_user().await;
+                self.expire_pending_pairs().await;
+                // The CLI gives up on a ping whose pong never comes.
+                self.pending_pings.retain(|_, p| !p.reply.is_closed());
+                self.check_link_alarms();
+                // Report throttled warnings whose burst has ended, in case
+                // they never fire again to report it themselves.
+                for (site, suppressed) in cross_control_types::throttle::take_suppressed() {
+                    warn!(site, suppressed, "suppressed {suppressed} similar messages");
+                }
+                if self.status_held {
+                    self.broadcast_status();
+                }
+                // Timer ticks don't change observable state unless a peer
+                // failed its liveness probe or the controller went silent.
+                let expired = self.expire_liveness_probes().await;
+                let ready = self.check_startup();
+                if !self.expire_silent_controller() && !expired && !ready {
+                    return false;
+                }
+            }
+            DaemonEvent::FlushMotion => {
+                self.flush_motion().await;
+            }
+            DaemonEvent::LocateStep { peer, step } => {
+                self.locate_step(peer, step).await;
+            }
+            DaemonEvent::PrepareForSleep { done } => {
+                self.prepare_for_sleep().await;
+                let _ = done.send(());
+            }
+            DaemonEvent::Resumed => {
+                self.handle_resumed().await;
+            }
+            DaemonEvent::NetworkChanged { ssid } => {
+                self.handle_network_changed(ssid.as_deref()).await;
+            }
+            DaemonEvent::InterfacesChanged => {
+                self.handle_interfaces_changed();
+            }
+            DaemonEvent::WatchdogRecovered => {
+                // The watchdog released our devices; stop controlling so
+                // the daemon's state matches.
+                self.release_control().await;
+                self.broadcast_status();
+            }
+            DaemonEvent::InputCheckFailed {
+                machine_id,
+                episode,
+                code,
+                detail,
+            } => {
+                self.report_input_check_failure(machine_id, episode, code, detail)
+                    .await;
+            }
+            DaemonEvent::DiscoveryStopped => {
+                warn!("discovery browsing stopped, restarting it");
+                self.start_browsing().await;
+            }
+            DaemonEvent::Discovery(event) => {
+                self.handle_discovery(event).await;
+            }
             DaemonEvent::SessionReady { session } => {
-                self.handle_session_ready(session);
+                self.handle_session_ready(session).await;
+            }
+            DaemonEvent::PeerError { peer, code, detail } => {
+                self.record_peer_error(peer, code, detail);
+            }
+            DaemonEvent::Ipc {
+                request: IpcRequest::PushConfig { peer, layout },
+                reply,
+            } => {
+                self.push_config(&peer, layout, reply).await;
+            }
+            DaemonEvent::Ipc {
+                request: IpcRequest::RunCommand { peer, command },
+                reply,
+            } => {
+                self.request_command(&peer, command, reply).await;
+            }
+            DaemonEvent::Ipc {
+                request: IpcRequest::RequestControl { screen },
+                reply,
+            } => {
+                self.request_control(&screen, reply).await;
+            }
+            DaemonEvent::Ipc {
+                request: IpcRequest::Ping { screen },
+                reply,
+            } => {
+                self.ping_peer(&screen, reply).await;
+            }
+            DaemonEvent::Ipc {
+                request: IpcRequest::RunEverywhere { command },
+                reply,
+            } => {
+                self.run_everywhere(command, reply).await;
+            }
+            DaemonEvent::Ipc {
+                request: IpcRequest::PairConnect { address },
+                reply,
+            } => {
+                self.pair_connect(&address, reply);
+            }
+            DaemonEvent::Ipc {
+                request: IpcRequest::Teleport { screen, x, y },
+                reply,
+            } => {
+                self.teleport(&screen, x, y, reply).await;
+            }
+            DaemonEvent::Ipc {
+                request: IpcRequest::TypeText { screen, text },
+                reply,
+            } => {
+                if let Some(c) = text.chars().find(|c| KeyCode::for_char(*c).is_none()) {
+                    let _ = reply.send(IpcResponse::error(
+                        ErrorCode::InvalidRequest,
+                        format!("no key types {c:?}"),
+                    ));
+                } else {
+                    self.act_on_peer(&screen, PeerAction::Type(text), reply)
+                        .await;
+                }
+            }
+            DaemonEvent::PairConnected {
+                address,
+                result,
+                reply,
+            } => {
+                let response = self.hold_pair(address, result);
+                let _ = reply.send(response);
+            }
+            DaemonEvent::Ipc { request, reply } => {
+                let response = self.handle_ipc(&request).await;
+                let _ = reply.send(response);
             }
             DaemonEvent::Shutdown => {
                 info!("shutting down");
@@ -339,19 +1162,10 @@ impl Daemon {
             DaemonEvent::IncomingConnection(conn) => {
                 // Spawn handshake in background so we don't block the event loop.
                 let tx = self.event_tx.clone();
-                let our_id = self.machine_id;
-                let our_name = self.config.identity.name.clone();
-                let our_screen = self.screen.clone();
-                let local_devices = self.local_devices.clone();
-                tokio::spawn(async move {
-                    match perform_handshake_responder(
-                        conn,
-                        our_id,
-                        &our_name,
-                        &our_screen,
-                        &local_devices,
-                    )
-                    .await
+                let ours = self.identity();
+                let auth = self.auth.clone();
+                supervisor::spawn("inbound-handshake", async move {
+                    match PeerSession::establish(conn, Role::Responder, ours, auth.as_deref()).await
                     {
                         Ok(session) => {
                             let _ = tx.send(DaemonEvent::SessionReady { session }).await;
@@ -367,214 +1181,2548 @@ impl Daemon {
         false
     }
 
-    fn broadcast_status(&self) {
-        let _ = self.status_tx.send(DaemonStatus {
-            controlling: self.controlling,
-            controlled_by: self.controlled_by,
-            session_count: self.sessions.len(),
-            cursor_x: self.cursor_x,
-            cursor_y: self.cursor_y,
-        });
-    }
-
-    fn handle_session_ready(&mut self, session: PeerSession) {
-        let peer_id = session.machine_id;
-        let peer_name = session.name.clone();
-        self.sessions.insert(peer_id, session);
-        self.spawn_control_reader(peer_id);
-        info!(peer = %peer_name, id = %peer_id, "session established");
-    }
-
-    fn spawn_control_reader(&mut self, peer_id: MachineId) {
-        let mut control_rx = self
-            .sessions
-            .get_mut(&peer_id)
-            .and_then(PeerSession::take_control_rx)
-            .expect("control_rx should exist after handshake");
-        let event_tx = self.event_tx.clone();
-        tokio::spawn(async move {
-            loop {
-                match control_rx.recv::<ControlMessage>().await {
-                    Ok(Some(msg)) => {
-                        if event_tx
-                            .send(DaemonEvent::PeerControl {
-                                machine_id: peer_id,
-                                msg,
-                            })
-                            .await
-                            .is_err()
-                        {
-                            break;
-                        }
-                    }
-                    Ok(None) => {
-                        // Stream closed cleanly
-                        let _ = event_tx.send(DaemonEvent::PeerDisconnected(peer_id)).await;
-                        break;
-                    }
-                    Err(e) => {
-                        debug!(peer = %peer_id, error = %e, "control reader error");
-                        let _ = event_tx.send(DaemonEvent::PeerDisconnected(peer_id)).await;
-                        break;
+    async fn handle_ipc(&mut self, request: &IpcRequest) -> IpcResponse {
+        match request {
+            IpcRequest::SessionCodes => {
+                let mut codes = Vec::new();
+                for session in self.sessions.values() {
+                    match session.verification_code() {
+                        Ok(code) => codes.push(SessionCode {
+                            name: session.name.clone(),
+                            machine_id: session.machine_id,
+                            session: session.id.clone(),
+                            code,
+                        }),
+                        Err(e) => return e.into(),
                     }
                 }
+                codes.sort_by(|a, b| a.name.cmp(&b.name));
+                IpcResponse::SessionCodes(codes)
             }
-        });
+            IpcRequest::Ban { peer } => self.ban_peer(peer).await,
+            IpcRequest::SetPaused(paused) => {
+                self.set_paused(*paused).await;
+                IpcResponse::Paused(self.paused)
+            }
+            IpcRequest::Status => IpcResponse::Status(Box::new(self.status_report())),
+            IpcRequest::SetKeyboardLayout(layout) => {
+                self.handle_keyboard_layout_changed(layout.clone()).await;
+                IpcResponse::KeyboardLayoutSet {
+                    peers: self
+                        .sessions
+                        .values()
+                        .filter(|s| s.supports(ProtocolFeature::KeyboardLayout))
+                        .count(),
+                }
+            }
+            IpcRequest::Devices => IpcResponse::Devices(self.device_report()),
+            IpcRequest::History { peer } => self.session_history(peer),
+            IpcRequest::Stats { peer } => self.session_stats(peer.as_deref(), false),
+            IpcRequest::ResetStats { peer } => self.session_stats(peer.as_deref(), true),
+            IpcRequest::Trends { days, peer } => {
+                self.record_control_time();
+                IpcResponse::Trends(
+                    self.metrics
+                        .since(*days, peer.as_deref(), SystemTime::now()),
+                )
+            }
+            IpcRequest::LayoutSuggest => {
+                IpcResponse::LayoutSuggestions(self.layout.suggest(&self.config.screens))
+            }
+            IpcRequest::PushConfig { .. } => IpcResponse::error(
+                ErrorCode::InvalidRequest,
+                "config push must be answered by the peer",
+            ),
+            IpcRequest::PairConnect { .. } => IpcResponse::error(
+                ErrorCode::InvalidRequest,
+                "pair connect must be answered by the peer",
+            ),
+            IpcRequest::RunCommand { .. } | IpcRequest::RunEverywhere { .. } => IpcResponse::error(
+                ErrorCode::InvalidRequest,
+                "run command must be answered by the peer",
+            ),
+            IpcRequest::RequestControl { .. } => IpcResponse::error(
+                ErrorCode::InvalidRequest,
+                "control request must be answered by the peer",
+            ),
+            IpcRequest::Ping { .. } => IpcResponse::error(
+                ErrorCode::InvalidRequest,
+                "ping must be answered by the peer",
+            ),
+            IpcRequest::Teleport { .. } | IpcRequest::TypeText { .. } => IpcResponse::error(
+                ErrorCode::InvalidRequest,
+                "teleport and type must be answered by the event loop",
+            ),
+            IpcRequest::PairConfirm {
+                fingerprint,
+                accept,
+                position,
+            } => self.pair_confirm(fingerprint, *accept, *position).await,
+            IpcRequest::SwitchProfile { name } => match self.switch_profile(name).await {
+                Ok(()) => IpcResponse::ProfileSwitched {
+                    name: name.clone(),
+                    screens: self.config.screens.len(),
+                },
+                Err(e) => IpcResponse::error(ErrorCode::Config, e),
+            },
+        }
     }
 
-    /// Accept the unidirectional input stream from the remote peer, then start
-    /// reading input messages from it. This runs as a spawned task because the
-    /// QUIC stream may not be visible to `accept_uni` until the remote sends
-    /// data on it.
-    fn spawn_accept_input_stream(&self, peer_id: MachineId) {
-        let Some(session) = self.sessions.get(&peer_id) else {
-            return;
+    /// Ban a peer by name or machine ID: denylist its certificate
+    /// fingerprint, drop its screen and adjacency entries from the config
+    /// file and close any live session.
+    async fn ban_peer(&mut self, peer: &str) -> IpcResponse {
+        let session_id = self
+            .sessions
+            .values()
+            .find(|s| s.name == peer || s.machine_id.to_string() == peer)
+            .map(|s| s.machine_id);
+        let name = session_id
+            .and_then(|id| self.sessions.get(&id))
+            .map_or_else(|| peer.to_string(), |s| s.name.clone());
+        let screen = self.config.screens.iter().find(|sc| sc.name == name);
+
+        if session_id.is_none() && screen.is_none() {
+            return IpcResponse::error(ErrorCode::UnknownPeer, format!("unknown peer: {peer}"));
+        }
+        let fingerprint = session_id
+            .and_then(|id| self.sessions.get(&id))
+            .and_then(|s| s.fingerprint.clone())
+            .or_else(|| screen.and_then(|sc| sc.fingerprint.clone()));
+        let Some(fingerprint) = fingerprint else {
+            return IpcResponse::error(
+                ErrorCode::InvalidRequest,
+                format!("no certificate fingerprint known for {name}"),
+            );
         };
-        let connection = session.connection.clone();
-        let event_tx = self.event_tx.clone();
-        tokio::spawn(async move {
-            match connection.accept_input_stream().await {
-                Ok(input_rx) => {
-                    debug!(peer = %peer_id, "accepted input stream from controller");
-                    Self::spawn_input_reader_task(event_tx, input_rx, peer_id);
-                }
-                Err(e) => {
-                    warn!(peer = %peer_id, error = %e, "failed to accept input stream");
-                }
+
+        // Persist the ban before acting on it, so it can't silently
+        // disappear on the next restart.
+        if let Some(dir) = &self.denylist_dir {
+            if let Err(e) = crate::setup::append_denylist(dir, &fingerprint) {
+                return IpcResponse::error(ErrorCode::Config, e.to_string());
             }
-        });
+        }
+        self.transport.denylist().insert(fingerprint.clone());
+        // Drop the screen from every layout, so switching profile doesn't
+        // bring it back.
+        remove_screen(
+            &mut self.config.screens,
+            &mut self.config.screen_adjacency,
+            &name,
+        );
+        remove_screen(
+            &mut self.base_layout.screens,
+            &mut self.base_layout.screen_adjacency,
+            &name,
+        );
+        for profile in self.config.profiles.values_mut() {
+            remove_screen(&mut profile.screens, &mut profile.screen_adjacency, &name);
+        }
+        self.rebuild_edges();
+        if let Err(e) = self.save_layout() {
+            warn!(error = %e, "failed to save layout without banned peer");
+        }
+        if let Some(id) = session_id {
+            if let Some(session) = self.sessions.get_mut(&id) {
+                let _ = session.disconnect().await;
+            }
+            self.handle_peer_disconnected(id).await;
+        }
+        warn!(peer = %name, fingerprint = %fingerprint, "peer banned");
+        IpcResponse::Banned { name, fingerprint }
     }
 
-    fn spawn_input_reader_task(
-        event_tx: mpsc::Sender<DaemonEvent>,
-        mut input_rx: cross_control_protocol::MessageReceiver,
-        peer_id: MachineId,
+    /// Send a layout to a peer; `reply` is answered when the peer responds.
+    async fn push_config(
+        &mut self,
+        peer: &str,
+        layout: String,
+        reply: oneshot::Sender<IpcResponse>,
     ) {
-        tokio::spawn(async move {
-            loop {
-                match input_rx.recv::<InputMessage>().await {
-                    Ok(Some(msg)) => {
-                        if event_tx
-                            .send(DaemonEvent::PeerInput {
-                                machine_id: peer_id,
-                                msg,
-                            })
-                            .await
-                            .is_err()
-                        {
+        let Some(session) = self
+            .sessions
+            .values_mut()
+            .find(|s| s.name == peer || s.machine_id.to_string() == peer)
+        else {
+            let _ = reply.send(IpcResponse::error(
+                ErrorCode::NotConnected,
+                format!("not connected to {peer}"),
+            ));
+            return;
+        };
+        let msg = ControlMessage::ConfigPush { layout };
+        if let Err(e) = session.control_tx.send_tagged(&msg).await {
+            let _ = reply.send(DaemonError::from(e).into());
+            return;
+        }
+        info!(peer = %session.name, "pushed layout");
+        if let Some(previous) = self.pending_pushes.insert(session.machine_id, reply) {
+            let _ = previous.send(IpcResponse::error(
+                ErrorCode::Superseded,
+                "superseded by a newer push",
+            ));
+        }
+    }
+
+    /// Ask a peer to run a command from its allowlist; the reply waits for
+    /// its `CommandResult`.
+    async fn request_command(
+        &mut self,
+        peer: &str,
+        command: String,
+        reply: oneshot::Sender<IpcResponse>,
+    ) {
+        let Some(session) = self
+            .sessions
+            .values_mut()
+            .find(|s| s.name == peer || s.machine_id.to_string() == peer)
+        else {
+            let _ = reply.send(IpcResponse::error(
+                ErrorCode::NotConnected,
+                format!("not connected to {peer}"),
+            ));
+            return;
+        };
+        if !session.supports(ProtocolFeature::RunCommand) {
+            let _ = reply.send(unsupported(session, ProtocolFeature::RunCommand));
+            return;
+        }
+        info!(peer = %session.name, %command, "asking peer to run command");
+        session.record(format!("sent RunCommand {command}"));
+        let msg = ControlMessage::RunCommand { name: command };
+        if let Err(e) = session.control_tx.send_tagged(&msg).await {
+            let _ = reply.send(DaemonError::from(e).into());
+            return;
+        }
+        if let Some(previous) = self.pending_commands.insert(session.machine_id, reply) {
+            let _ = previous.send(IpcResponse::error(
+                ErrorCode::Superseded,
+                "superseded by a newer command",
+            ));
+        }
+    }
+
+    /// Send a peer a `Ping` for the CLI; the reply waits for its `Pong`.
+    async fn ping_peer(&mut self, screen: &str, reply: oneshot::Sender<IpcResponse>) {
+        let Some(session) = self
+            .sessions
+            .values_mut()
+            .find(|s| s.name == screen || s.machine_id.to_string() == screen)
+        else {
+            let _ = reply.send(IpcResponse::error(
+                ErrorCode::NotConnected,
+                format!("not connected to {screen}"),
+            ));
+            return;
+        };
+        self.ping_seq += 1;
+        let seq = self.ping_seq;
+        if let Err(e) = session
+            .control_tx
+            .send_tagged(&ControlMessage::Ping { seq })
+            .await
+        {
+            let _ = reply.send(DaemonError::from(e).into());
+            return;
+        }
+        self.pending_pings.insert(
+            seq,
+            PendingPing {
+                peer: session.machine_id,
+                sent: Instant::now(),
+                reply,
+            },
+        );
+    }
+
+    /// Ask a peer to let us take control of it; the reply waits for its
+    /// `ControlRequestResult`.
+    async fn request_control(&mut self, screen: &str, reply: oneshot::Sender<IpcResponse>) {
+        let Some(session) = self
+            .sessions
+            .values()
+            .find(|s| s.name == screen || s.machine_id.to_string() == screen)
+        else {
+            let _ = reply.send(IpcResponse::error(
+                ErrorCode::NotConnected,
+                format!("not connected to {screen}"),
+            ));
+            return;
+        };
+        let peer_id = session.machine_id;
+        if self.controlling == Some(peer_id) {
+            let _ = reply.send(IpcResponse::error(
+                ErrorCode::InvalidRequest,
+                format!("already controlling {}", session.name),
+            ));
+            return;
+        }
+        if !session.supports(ProtocolFeature::RequestControl) {
+            let _ = reply.send(unsupported(session, ProtocolFeature::RequestControl));
+            return;
+        }
+        // Enter through the edge the peer lies beyond: from the layout, or
+        // the edge its cursor came in through.
+        let edge = self
+            .config
+            .screens
+            .iter()
+            .find(|sc| sc.name == session.name)
+            .map(|sc| sc.position.local_edge())
+            .or(self
+                .entry_edge
+                .filter(|_| self.controlled_by == Some(peer_id)))
+            .unwrap_or(ScreenEdge::Right);
+        let Some(session) = self.sessions.get_mut(&peer_id) else {
+            return;
+        };
+        info!(peer = %session.name, "asking peer for control");
+        session.record("sent RequestControl");
+        if let Err(e) = session
+            .control_tx
+            .send_tagged(&ControlMessage::RequestControl)
+            .await
+        {
+            let _ = reply.send(DaemonError::from(e).into());
+            return;
+        }
+        if let Some((previous, _)) = self.pending_control_requests.insert(peer_id, (reply, edge)) {
+            let _ = previous.send(IpcResponse::error(
+                ErrorCode::Superseded,
+                "superseded by a newer control request",
+            ));
+        }
+    }
+
+    /// Whether `daemon.control_requests` lets `peer_id` take control of us.
+    fn control_request_allowed(&self, peer_id: MachineId) -> Result<(), String> {
+        let session = self
+            .sessions
+            .get(&peer_id)
+            .ok_or_else(|| "unknown session".to_string())?;
+        match self.config.daemon.control_requests {
+            ControlRequestPolicy::Deny => {
+                return Err("control requests are not accepted".to_string());
+            }
+            ControlRequestPolicy::Pinned if !self.is_pinned(session) => {
+                return Err("control requests are only accepted from pinned peers".to_string());
+            }
+            ControlRequestPolicy::Pinned | ControlRequestPolicy::Accept => {}
+        }
+        if self.controlled_by.is_some_and(|id| id != peer_id) {
+            return Err("controlled by another machine".to_string());
+        }
+        Ok(())
+    }
+
+    /// Run `command` on every trusted, connected peer that isn't asleep,
+    /// then on this machine. The reply lists each machine's outcome once
+    /// every peer has answered or timed out; this machine goes last so that
+    /// a `suspend` doesn't strand the requests still in flight.
+    async fn run_everywhere(&mut self, command: String, reply: oneshot::Sender<IpcResponse>) {
+        let peers: Vec<(MachineId, String)> = self
+            .sessions
+            .values()
+            .filter(|s| !s.suspended && self.is_pinned(s))
+            .map(|s| (s.machine_id, s.name.clone()))
+            .collect();
+        let mut answers = Vec::with_capacity(peers.len());
+        for (machine_id, name) in peers {
+            let (tx, rx) = oneshot::channel();
+            self.request_command(&machine_id.to_string(), command.clone(), tx)
+                .await;
+            answers.push((name, rx));
+        }
+        let local = self.config.commands.get(&command).map(|c| c.run.clone());
+        let ours = self.config.identity.name.clone();
+        supervisor::spawn("run-everywhere", async move {
+            let deadline = tokio::time::Instant::now() + COMMAND_ANSWER_TIMEOUT;
+            let mut outcomes = Vec::with_capacity(answers.len() + 1);
+            for (name, rx) in answers {
+                let error = match tokio::time::timeout_at(deadline, rx).await {
+                    Ok(Ok(IpcResponse::CommandStarted { .. })) => None,
+                    Ok(Ok(IpcResponse::Error { message, .. })) => Some(message),
+                    Ok(Ok(other)) => Some(format!("unexpected answer: {other:?}")),
+                    Ok(Err(_)) => Some("no answer".to_string()),
+                    Err(_) => Some("timed out".to_string()),
+                };
+                outcomes.push(CommandOutcome { name, error });
+            }
+            let error = match local {
+                Some(run) => spawn_command(&command, &run).err(),
+                None => Some(format!("{command} is not defined under [commands]")),
+            };
+            outcomes.push(CommandOutcome { name: ours, error });
+            for outcome in &outcomes {
+                match &outcome.error {
+                    None => info!(machine = %outcome.name, %command, "command started"),
+                    Some(e) => {
+                        warn!(machine = %outcome.name, %command, error = %e, "command not started");
+                    }
+                }
+            }
+            let _ = reply.send(IpcResponse::CommandsRun(outcomes));
+        });
+    }
+
+    /// Start the command `name` for a peer, if our `[commands]` entry for it
+    /// lists the peer, or the peer's screen lists the command, and the
+    /// peer's certificate is pinned in our config.
+    /// The command runs in the background; only failure to start it is
+    /// reported.
+    fn run_allowed_command(&self, machine_id: MachineId, name: &str) -> Result<(), String> {
+        let session = self.sessions.get(&machine_id).ok_or("unknown session")?;
+        let command =
+            self.config
+                .commands
+                .get(name)
+                .filter(|command| {
+                    command.peers.contains(&session.name)
+                        || self.config.screens.iter().any(|sc| {
+                            sc.name == session.name && sc.commands.iter().any(|c| c == name)
+                        })
+                })
+                .ok_or_else(|| format!("{} may not run {name}", session.name))?;
+        if !self.is_pinned(session) {
+            return Err(format!(
+                "{} is not a trusted peer (its fingerprint is not pinned)",
+                session.name
+            ));
+        }
+        spawn_command(name, &command.run)?;
+        info!(peer = %session.name, command = %name, "running command for peer");
+        Ok(())
+    }
+
+    /// Whether the peer's certificate fingerprint is pinned in our config.
+    fn is_pinned(&self, session: &PeerSession) -> bool {
+        session.fingerprint.as_ref().is_some_and(|fingerprint| {
+            self.config
+                .screens
+                .iter()
+                .any(|sc| sc.name == session.name && sc.fingerprint.as_ref() == Some(fingerprint))
+        })
+    }
+
+    /// Apply a layout pushed by a peer, if remote config is allowed and the
+    /// peer's certificate is pinned in our config. Fingerprints and viewer
+    /// modes stay as we have them; see [`LayoutConfig::restricted_to`].
+    fn apply_pushed_config(&mut self, machine_id: MachineId, layout: &str) -> Result<(), String> {
+        if !self.config.daemon.allow_remote_config {
+            return Err("remote config is disabled (allow_remote_config = false)".to_string());
+        }
+        let session = self.sessions.get(&machine_id).ok_or("unknown session")?;
+        if !self.is_pinned(session) {
+            return Err(format!(
+                "{} is not a trusted peer (its fingerprint is not pinned)",
+                session.name
+            ));
+        }
+        let layout: LayoutConfig =
+            toml::from_str(layout).map_err(|e| format!("invalid layout: {e}"))?;
+        let layout = layout.restricted_to(&self.config.screens);
+
+        info!(
+            peer = %session.name,
+            screens = layout.screens.len(),
+            "applying pushed layout"
+        );
+        self.apply_layout(layout);
+        self.save_layout()
+    }
+
+    /// Store the current screens and adjacency as the active profile's
+    /// layout, or as the top-level one when no profile is active, then
+    /// write every layout back to the config file, if the config was
+    /// loaded from one.
+    fn save_layout(&mut self) -> Result<(), String> {
+        let screens = self.config.screens.clone();
+        let screen_adjacency = self.config.screen_adjacency.clone();
+        match self
+            .profile
+            .as_ref()
+            .and_then(|name| self.config.profiles.get_mut(name))
+        {
+            Some(profile) => {
+                profile.screens = screens;
+                profile.screen_adjacency = screen_adjacency;
+            }
+            None => {
+                self.base_layout = LayoutConfig {
+                    screens,
+                    screen_adjacency,
+                };
+            }
+        }
+
+        let Some(path) = &self.config_path else {
+            return Ok(());
+        };
+        let path = path.to_string_lossy();
+        let mut on_disk = crate::setup::load_config(Some(&path)).map_err(|e| e.to_string())?;
+        on_disk.screens.clone_from(&self.base_layout.screens);
+        on_disk
+            .screen_adjacency
+            .clone_from(&self.base_layout.screen_adjacency);
+        for (name, profile) in &self.config.profiles {
+            let entry = on_disk.profiles.entry(name.clone()).or_default();
+            entry.screens.clone_from(&profile.screens);
+            entry.screen_adjacency.clone_from(&profile.screen_adjacency);
+        }
+        crate::setup::save_config(Some(&path), &on_disk).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Dial a machine for `pair` in a background task; `reply` is answered
+    /// once the handshake finishes.
+    fn pair_connect(&self, address: &str, reply: oneshot::Sender<IpcResponse>) {
+        let addr: Option<SocketAddr> = address
+            .parse()
+            .or_else(|_| format!("{address}:{}", self.config.daemon.peer_port()).parse())
+            .ok();
+        let Some(addr) = addr else {
+            let _ = reply.send(IpcResponse::error(
+                ErrorCode::InvalidRequest,
+                format!("invalid address: {address}"),
+            ));
+            return;
+        };
+        let transport = self.transport.clone();
+        let event_tx = self.event_tx.clone();
+        let ours = self.identity();
+        supervisor::spawn("pair-handshake", async move {
+            let result = match transport.connect(addr, "cross-control").await {
+                Ok(conn) => PeerSession::establish(conn, Role::Initiator, ours, None).await,
+                Err(e) => Err(e.into()),
+            };
+            let _ = event_tx
+                .send(DaemonEvent::PairConnected {
+                    address: addr,
+                    result,
+                    reply,
+                })
+                .await;
+        });
+    }
+
+    /// Hold a connection made for `pair` until the user confirms the peer.
+    fn hold_pair(
+        &mut self,
+        address: SocketAddr,
+        result: Result<PeerSession, DaemonError>,
+    ) -> IpcResponse {
+        let mut session = match result {
+            Ok(session) => session,
+            Err(e) => {
+                warn!(address = %address, error = %e, "pairing connection failed");
+                return e.into();
+            }
+        };
+        let Some(fingerprint) = session.fingerprint.clone() else {
+            supervisor::spawn("pair-close", async move {
+                let _ = session.disconnect().await;
+            });
+            return IpcResponse::error(
+                ErrorCode::Tls,
+                format!("{address} presented no certificate"),
+            );
+        };
+        let code = match session.verification_code() {
+            Ok(code) => code,
+            Err(e) => return e.into(),
+        };
+        info!(peer = %session.name, address = %address, "pairing connection waiting for confirmation");
+        let name = session.name.clone();
+        let pending = PendingPair {
+            session,
+            address,
+            since: Instant::now(),
+        };
+        if let Some(mut previous) = self.pending_pairs.insert(fingerprint.clone(), pending) {
+            supervisor::spawn("pair-close", async move {
+                let _ = previous.session.disconnect().await;
+            });
+        }
+        IpcResponse::PairPending {
+            name,
+            fingerprint,
+            code,
+        }
+    }
+
+    /// Pin or drop a peer held by `pair`. Accepting records the screen,
+    /// saves the layout and adopts the session.
+    async fn pair_confirm(
+        &mut self,
+        fingerprint: &str,
+        accept: bool,
+        position: Option<Position>,
+    ) -> IpcResponse {
+        let Some(name) = self
+            .pending_pairs
+            .get(fingerprint)
+            .map(|p| p.session.name.clone())
+        else {
+            return IpcResponse::error(
+                ErrorCode::InvalidRequest,
+                format!("no pairing pending for {fingerprint}"),
+            );
+        };
+        if !accept {
+            if let Some(mut pending) = self.pending_pairs.remove(fingerprint) {
+                info!(peer = %name, "pairing rejected");
+                let _ = pending.session.disconnect().await;
+            }
+            return IpcResponse::PairCancelled;
+        }
+        let position = position.or_else(|| {
+            self.config
+                .screens
+                .iter()
+                .find(|sc| sc.name == name)
+                .map(|sc| sc.position)
+        });
+        let Some(position) = position else {
+            return IpcResponse::error(
+                ErrorCode::InvalidRequest,
+                format!("{name} is not in the layout yet; a position is required"),
+            );
+        };
+        let Some(pending) = self.pending_pairs.remove(fingerprint) else {
+            return IpcResponse::error(ErrorCode::Internal, "pairing vanished");
+        };
+
+        pairing::apply_pairing(
+            &mut self.config,
+            &name,
+            pending.address.to_string(),
+            position,
+            Some(fingerprint.to_string()),
+            None,
+        );
+        self.rebuild_edges();
+        if let Err(e) = self.save_layout() {
+            warn!(error = %e, "failed to save paired screen");
+        }
+        info!(peer = %name, fingerprint = %fingerprint, "paired");
+        self.handle_session_ready(pending.session).await;
+        IpcResponse::Paired { name, position }
+    }
+
+    /// Close `pair` connections nobody confirmed in time.
+    async fn expire_pending_pairs(&mut self) {
+        let expired: Vec<String> = self
+            .pending_pairs
+            .iter()
+            .filter(|(_, p)| p.since.elapsed() >= PAIR_CONFIRM_TIMEOUT)
+            .map(|(fingerprint, _)| fingerprint.clone())
+            .collect();
+        for fingerprint in expired {
+            if let Some(mut pending) = self.pending_pairs.remove(&fingerprint) {
+                info!(peer = %pending.session.name, "pairing not confirmed, closing connection");
+                let _ = pending.session.disconnect().await;
+            }
+        }
+    }
+
+    /// Replace the screens and adjacency, and dial screens we have no
+    /// session with.
+    fn apply_layout(&mut self, layout: LayoutConfig) {
+        self.config.screens = layout.screens;
+        self.config.screen_adjacency = layout.screen_adjacency;
+        self.rebuild_edges();
+        self.connect_missing_peers();
+    }
+
+    /// Switch to a named profile: release control, close sessions with
+    /// screens the profile doesn't list, and dial the ones it adds.
+    async fn switch_profile(&mut self, name: &str) -> Result<(), String> {
+        let layout = match self.config.profile_layout(name) {
+            Some(layout) => layout,
+            None if name == DEFAULT_PROFILE => self.base_layout.clone(),
+            None => return Err(format!("unknown profile: {name}")),
+        };
+        info!(profile = %name, screens = layout.screens.len(), "switching profile");
+
+        self.flush_motion().await;
+        self.release_control().await;
+        self.return_control().await;
+        self.apply_layout(layout);
+        self.profile = (name != DEFAULT_PROFILE).then(|| name.to_string());
+
+        let dropped: Vec<MachineId> = self
+            .sessions
+            .values()
+            .filter(|s| !self.config.screens.iter().any(|sc| sc.name == s.name))
+            .map(|s| s.machine_id)
+            .collect();
+        for id in dropped {
+            if let Some(session) = self.sessions.get_mut(&id) {
+                let _ = session.disconnect().await;
+            }
+            self.handle_peer_disconnected(id).await;
+        }
+        Ok(())
+    }
+
+    /// Switch to the profile listing the new Wi-Fi network, if any.
+    async fn handle_network_changed(&mut self, ssid: Option<&str>) {
+        debug!(ssid = ?ssid, "network changed");
+        let Some(name) = ssid.and_then(|s| self.config.profile_for_ssid(s)) else {
+            return;
+        };
+        if self.profile.as_deref() == Some(name) {
+            return;
+        }
+        let name = name.to_string();
+        if let Err(e) = self.switch_profile(&name).await {
+            warn!(profile = %name, error = %e, "automatic profile switch failed");
+        }
+    }
+
+    /// The state reported to `cross-control status`.
+    fn status_report(&self) -> StatusReport {
+        let name_of = |id: Option<MachineId>| {
+            id.and_then(|id| self.sessions.get(&id))
+                .map(|s| s.name.clone())
+        };
+        StatusReport {
+            paused: self.paused,
+            dry_run: self.config.input.dry_run,
+            private_input: self.private_input.is_some(),
+            session_count: self.sessions.len(),
+            controlling: name_of(self.controlling),
+            remote_focus: self
+                .controlling
+                .and_then(|id| self.sessions.get(&id))
+                .and_then(|session| session.focus),
+            controlled_by: name_of(self.controlled_by),
+            local_user_active: self.local_user_until.is_some(),
+            remote_user_active: self
+                .controlling
+                .and_then(|id| self.sessions.get(&id))
+                .is_some_and(|session| session.local_user_active),
+            input_grab: self.input_grab,
+            waiting_on: self.awaited_screens(),
+            version_skew: self.version_skew(),
+            profile: self.profile.clone(),
+            conflicts: self.directory.conflicts(),
+            peer_errors: self
+                .peer_errors
+                .iter()
+                .map(|(peer, (code, detail, _))| PeerErrorReport {
+                    peer: peer.clone(),
+                    code: *code,
+                    detail: detail.clone(),
+                })
+                .collect(),
+            link_alarms: self.link_alarms(),
+            keyboard_layout: self.keyboard_layout.clone(),
+            peer_keyboard_layouts: self.peer_keyboard_layouts(),
+            evictions: Evictions {
+                input_batches: self.injector.evicted(),
+                peer_errors: self.peer_errors_evicted,
+                discovered_peers: self.directory.evicted(),
+            },
+            started_at: self
+                .started
+                .1
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            uptime_secs: self.started.0.elapsed().as_secs(),
+            counters: self.counters.clone(),
+            version: crate::VERSION.to_string(),
+            commit: crate::COMMIT.to_string(),
+        }
+    }
+
+    /// Keyboard layouts peers reported, by peer name.
+    fn peer_keyboard_layouts(&self) -> Vec<PeerKeyboardLayout> {
+        let mut layouts: Vec<_> = self
+            .sessions
+            .values()
+            .filter_map(|session| {
+                Some(PeerKeyboardLayout {
+                    peer: session.name.clone(),
+                    layout: session.keyboard_layout.clone()?,
+                })
+            })
+            .collect();
+        layouts.sort_by(|a, b| a.peer.cmp(&b.peer));
+        layouts
+    }
+
+    /// Raised link alarms, by peer name.
+    fn link_alarms(&self) -> Vec<LinkAlarmReport> {
+        let mut alarms: Vec<_> = self
+            .sessions
+            .values()
+            .filter_map(|session| {
+                Some(LinkAlarmReport {
+                    peer: session.name.clone(),
+                    reason: session.alarm.raised()?.to_string(),
+                })
+            })
+            .collect();
+        alarms.sort_by(|a, b| a.peer.cmp(&b.peer));
+        alarms
+    }
+
+    /// Connected peers whose protocol or software version differs from ours.
+    fn version_skew(&self) -> Vec<VersionSkewReport> {
+        let mut skew: Vec<_> = self
+            .sessions
+            .values()
+            .filter(|s| {
+                s.version != PROTOCOL_VERSION
+                    || s.software.as_deref().is_some_and(|v| v != crate::VERSION)
+            })
+            .map(|s| VersionSkewReport {
+                peer: s.name.clone(),
+                version: s.software.clone(),
+                protocol: s.version.to_string(),
+                disabled: PROTOCOL_VERSION
+                    .missing_from(s.version)
+                    .into_iter()
+                    .map(|feature| feature.description().to_string())
+                    .collect(),
+            })
+            .collect();
+        skew.sort_by(|a, b| a.peer.cmp(&b.peer));
+        skew
+    }
+
+    /// Publish the current status. With `performance.status_interval` set,
+    /// updates that only move the cursor are held back until the interval
+    /// has passed, or until the next tick.
+    fn broadcast_status(&mut self) {
+        let status = DaemonStatus {
+            controlling: self.controlling,
+            controlled_by: self.controlled_by,
+            session_count: self.sessions.len(),
+            cursor_x: self.cursor_x,
+            cursor_y: self.cursor_y,
+            emulation_available: self.emulation_available,
+            paused: self.paused,
+            private_input: self.private_input.is_some(),
+            remote_focus: self
+                .controlling
+                .and_then(|id| self.sessions.get(&id))
+                .and_then(|session| session.focus),
+            input_grab: self.input_grab,
+            ready: self.startup_deadline.is_none(),
+        };
+        let interval = self.config.performance.status_interval();
+        if !interval.is_zero() && !self.status_held {
+            let previous = self.status_tx.borrow().clone();
+            let cursor_only = DaemonStatus {
+                cursor_x: previous.cursor_x,
+                cursor_y: previous.cursor_y,
+                ..status.clone()
+            } == previous;
+            if cursor_only && self.status_sent_at.elapsed() < interval {
+                self.status_held = true;
+                return;
+            }
+        }
+        self.status_held = false;
+        self.status_sent_at = Instant::now();
+        let _ = self.status_tx.send(status);
+    }
+
+    async fn handle_session_ready(&mut self, mut session: PeerSession) {
+        let peer_id = session.machine_id;
+        let peer_name = session.name.clone();
+        // A handshake may have been in flight when the peer was banned.
+        if let Some(fingerprint) = &session.fingerprint {
+            if self.transport.denylist().contains(fingerprint) {
+                warn!(peer = %peer_name, "dropping session from banned peer");
+                let detail = format!(
+                    "{} no longer trusts this machine's certificate",
+                    self.config.identity.name
+                );
+                supervisor::spawn("reject-peer", async move {
+                    session.fail(ErrorCode::Untrusted, detail).await;
+                });
+                return;
+            }
+        }
+        self.peer_errors.remove(&peer_name);
+        session.set_history_limit(self.config.limits.session_history);
+        let session_id = session.id.clone();
+        let limits = MessageLimits {
+            control: self.config.limits.control_message_size,
+            input: self.config.limits.input_message_size,
+        };
+        if let Err(e) = session.announce_limits(limits).await {
+            warn!(peer = %peer_name, session = %session_id, error = %e, "failed to announce limits");
+            return;
+        }
+        if let Err(e) = session.announce_build().await {
+            warn!(peer = %peer_name, session = %session_id, error = %e, "failed to announce build");
+            return;
+        }
+        if session.version != PROTOCOL_VERSION {
+            let disabled = PROTOCOL_VERSION.missing_from(session.version);
+            warn!(
+                peer = %peer_name,
+                peer_protocol = %session.version,
+                protocol = %PROTOCOL_VERSION,
+                disabled = %describe_features(&disabled),
+                "peer speaks a different protocol version"
+            );
+        }
+        let screen = self.config.screens.iter().find(|sc| sc.name == peer_name);
+        let shared = session
+            .announce_devices(&self.local_devices, |device| {
+                screen.map_or(true, |sc| sc.shares_device(&device.name))
+            })
+            .await;
+        if let Err(e) = shared {
+            warn!(peer = %peer_name, session = %session_id, error = %e, "failed to announce devices");
+            return;
+        }
+        if let Some(state) = self.power {
+            let _ = session
+                .control_tx
+                .send_tagged(&ControlMessage::PowerState(state))
+                .await;
+        }
+        if let Some(layout) = &self.keyboard_layout {
+            let _ = session.announce_keyboard_layout(layout).await;
+        }
+        if !self.seen_peers.insert(peer_id) {
+            self.counters.reconnects += 1;
+        }
+        self.sessions.insert(peer_id, session);
+        self.rebuild_edges();
+        self.spawn_control_reader(peer_id);
+        info!(peer = %peer_name, machine_id = %peer_id, session = %session_id, "session established");
+        self.check_startup();
+    }
+
+    /// Names of the `wait_on_start` screens startup is still waiting for.
+    fn awaited_screens(&self) -> Vec<String> {
+        if self.startup_deadline.is_none() {
+            return Vec::new();
+        }
+        self.config
+            .screens
+            .iter()
+            .filter(|sc| sc.wait_on_start && !self.sessions.values().any(|s| s.name == sc.name))
+            .map(|sc| sc.name.clone())
+            .collect()
+    }
+
+    /// Report readiness once every `wait_on_start` screen has a session, or
+    /// the wait times out. Returns whether readiness was reported now.
+    fn check_startup(&mut self) -> bool {
+        let Some(deadline) = self.startup_deadline else {
+            return false;
+        };
+        let waiting = self.awaited_screens();
+        if !waiting.is_empty() {
+            if Instant::now() < deadline {
+                return false;
+            }
+            warn!(
+                peers = %waiting.join(", "),
+                "gave up waiting for peers at startup, reporting ready"
+            );
+        }
+        self.startup_deadline = None;
+        crate::readiness::notify_ready();
+        info!("daemon ready");
+        true
+    }
+
+    /// Browse for peers, forwarding discovery events to the event loop. If
+    /// the backend's channel closes, browsing is started again.
+    async fn start_browsing(&mut self) {
+        let Some(discovery) = &mut self.discovery else {
+            return;
+        };
+        let mut discovery_rx = match discovery.browse(self.config.daemon.group.as_deref()).await {
+            Ok(rx) => rx,
+            Err(e) => {
+                warn!(error = %e, "discovery browsing unavailable");
+                return;
+            }
+        };
+        let discovery_tx = self.event_tx.clone();
+        let cleanup_tx = self.event_tx.clone();
+        let cleanup = async move {
+            let _ = cleanup_tx.send(DaemonEvent::DiscoveryStopped).await;
+        };
+        supervisor::spawn_with_cleanup(
+            "discovery",
+            async move {
+                while let Some(event) = discovery_rx.recv().await {
+                    if discovery_tx
+                        .send(DaemonEvent::Discovery(event))
+                        .await
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+                tokio::time::sleep(supervisor::RESTART_DELAY).await;
+                let _ = discovery_tx.send(DaemonEvent::DiscoveryStopped).await;
+            },
+            cleanup,
+        );
+    }
+
+    fn spawn_control_reader(&mut self, peer_id: MachineId) {
+        let mut control_rx = self
+            .sessions
+            .get_mut(&peer_id)
+            .and_then(PeerSession::take_control_rx)
+            .expect("control_rx should exist after handshake");
+        let event_tx = self.event_tx.clone();
+        // If the reader dies, the session can't receive anything; drop it.
+        let cleanup_tx = self.event_tx.clone();
+        let cleanup = async move {
+            let _ = cleanup_tx
+                .send(DaemonEvent::PeerDisconnected(peer_id))
+                .await;
+        };
+        supervisor::spawn_with_cleanup(
+            "control-reader",
+            async move {
+                loop {
+                    match control_rx.recv_tagged::<ControlMessage>().await {
+                        Ok(Some(msg)) => {
+                            if event_tx
+                                .send(DaemonEvent::PeerControl {
+                                    machine_id: peer_id,
+                                    msg,
+                                })
+                                .await
+                                .is_err()
+                            {
+                                break;
+                            }
+                        }
+                        Ok(None) => {
+                            // Stream closed cleanly
+                            let _ = event_tx.send(DaemonEvent::PeerDisconnected(peer_id)).await;
+                            break;
+                        }
+                        Err(e) => {
+                            debug!(machine_id = %peer_id, error = %e, "control reader error");
+                            let _ = event_tx.send(DaemonEvent::PeerDisconnected(peer_id)).await;
                             break;
                         }
                     }
-                    Ok(None) => break,
-                    Err(e) => {
-                        debug!(peer = %peer_id, error = %e, "input reader error");
-                        break;
-                    }
                 }
+            },
+            cleanup,
+        );
+    }
+
+    /// Accept the unidirectional input stream from the remote peer, then start
+    /// reading input messages from it. This runs as a spawned task because the
+    /// QUIC stream may not be visible to `accept_uni` until the remote sends
+    /// data on it.
+    fn spawn_accept_input_stream(&self, peer_id: MachineId, episode: u64) {
+        let Some(session) = self.sessions.get(&peer_id) else {
+            return;
+        };
+        let connection = session.connection.clone();
+        let max_size = session.limits().input;
+        let counter = session.stats.received_counter();
+        let event_tx = self.event_tx.clone();
+        supervisor::spawn("accept-input-stream", async move {
+            match connection.accept_input_stream().await {
+                Ok(mut input_rx) => {
+                    input_rx.set_max_size(max_size);
+                    input_rx.count_bytes(counter);
+                    debug!(machine_id = %peer_id, "accepted input stream from controller");
+                    Self::spawn_input_reader_task(event_tx, input_rx, peer_id, episode);
+                }
+                Err(e) => {
+                    warn!(machine_id = %peer_id, error = %e, "failed to accept input stream");
+                }
+            }
+        });
+    }
+
+    /// Check in the background that input from the controller in `episode`
+    /// can be injected, reporting a failure through the event loop.
+    fn spawn_input_check(&self, peer_id: MachineId, episode: u64) {
+        let check = self.injector.smoke_test(peer_id, INPUT_CHECK_TIMEOUT);
+        let event_tx = self.event_tx.clone();
+        supervisor::spawn("input-check", async move {
+            let result = tokio::time::timeout(INPUT_CHECK_TIMEOUT, check)
+                .await
+                .unwrap_or_else(|_| {
+                    Err(InputError::Inject(format!(
+                        "no injection within {INPUT_CHECK_TIMEOUT:?}"
+                    )))
+                });
+            match result {
+                Ok(()) => debug!(machine_id = %peer_id, "input check passed"),
+                Err(e) => {
+                    let _ = event_tx
+                        .send(DaemonEvent::InputCheckFailed {
+                            machine_id: peer_id,
+                            episode,
+                            code: e.code(),
+                            detail: e.to_string(),
+                        })
+                        .await;
+                }
+            }
+        });
+    }
+
+    /// Tell the controller that its input can't be injected here, if it
+    /// still controls us in the episode that was checked.
+    async fn report_input_check_failure(
+        &mut self,
+        peer_id: MachineId,
+        episode: u64,
+        code: ErrorCode,
+        detail: String,
+    ) {
+        if self.controlled_by != Some(peer_id) || self.input_episode != episode {
+            return;
+        }
+        let Some(session) = self.sessions.get_mut(&peer_id) else {
+            return;
+        };
+        warn!(peer = %session.name, error = %detail, "input from controller cannot be injected");
+        let detail = format!(
+            "{} cannot inject input: {detail}",
+            self.config.identity.name
+        );
+        session.report_error(code, detail).await;
+    }
+
+    fn spawn_input_reader_task(
+        event_tx: mpsc::Sender<DaemonEvent>,
+        mut input_rx: cross_control_protocol::MessageReceiver,
+        peer_id: MachineId,
+        episode: u64,
+    ) {
+        supervisor::spawn("input-reader", async move {
+            loop {
+                match input_rx.recv::<InputMessage>().await {
+                    Ok(Some(msg)) => {
+                        if event_tx
+                            .send(DaemonEvent::PeerInput {
+                                machine_id: peer_id,
+                                episode,
+                                msg,
+                            })
+                            .await
+                            .is_err()
+                        {
+                            break;
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        debug!(machine_id = %peer_id, error = %e, "input reader error");
+                        break;
+                    }
+                }
+            }
+            // Queued behind every input message read above.
+            let _ = event_tx
+                .send(DaemonEvent::PeerInputEnded {
+                    machine_id: peer_id,
+                    episode,
+                })
+                .await;
+        });
+    }
+
+    #[instrument(level = "trace", name = "route", skip_all)]
+    async fn handle_captured_input(&mut self, captured: CapturedEvent) {
+        // Private keyboard input isn't counted either.
+        if self.private_input.is_none() || !matches!(captured.event, InputEvent::Key { .. }) {
+            *self.captured_events.entry(captured.device_id).or_default() += 1;
+        }
+
+        // Track hotkey state
+        self.update_hotkey_state(&captured.event);
+
+        if self.handle_action_hotkeys(&captured.event).await {
+            return;
+        }
+        if self.handle_hotkey_sequences(&captured.event).await {
+            return;
+        }
+
+        // Check release hotkey
+        if self.controlling.is_some() && self.is_hotkey_pressed(self.release_hotkey()) {
+            self.release_control().await;
+            return;
+        }
+
+        // While suppressed, local input past the action hotkeys above only
+        // serves to take the cursor back.
+        if self.local_input_grabbed.is_some() {
+            if self.is_hotkey_pressed(&self.config.input.release_hotkey) {
+                info!("release hotkey pressed while controlled, returning the cursor");
+                self.return_control().await;
+            }
+            return;
+        }
+
+        self.apply_local_input_policy(&captured.event).await;
+
+        if self.handle_private_input(&captured.event) {
+            return;
+        }
+
+        // If we're controlling a remote, forward the event
+        if let Some(peer_id) = self.controlling {
+            let shared = self
+                .sessions
+                .get(&peer_id)
+                .is_some_and(|s| s.shared_devices.contains(&captured.device_id));
+            if !shared {
+                return;
+            }
+            let mut events = self.drag_scroll(&captured);
+            self.scale_pointer(peer_id, &mut events);
+            if events.is_empty() {
+                return;
+            }
+            if let [InputEvent::MouseMove { dx, dy }] = events[..] {
+                if let Some(window) = self.coalesce_window(peer_id) {
+                    let other_batch = self
+                        .pending_motion
+                        .as_ref()
+                        .is_some_and(|p| p.peer != peer_id || p.device_id != captured.device_id);
+                    if other_batch {
+                        self.flush_motion().await;
+                    }
+                    self.coalesce_motion(peer_id, &captured, dx, dy, window);
+                    return;
+                }
+            }
+            self.flush_motion().await;
+            if let Some(session) = self.sessions.get_mut(&peer_id) {
+                let msg = InputMessage {
+                    device_id: captured.device_id,
+                    timestamp_us: captured.timestamp_us,
+                    events,
+                };
+                debug!(machine_id = %peer_id, device = ?msg.device_id, "forwarding input to peer");
+                if let Err(e) = session.send_input(&msg).await {
+                    warn!(error = %e, "failed to send input to peer");
+                    self.controlling = None;
+                    self.release_input().await;
+                } else {
+                    self.counters.events_forwarded += msg.events.len() as u64;
+                }
+            }
+            return;
+        }
+
+        // Track cursor position for barrier detection
+        if let InputEvent::MouseMove { dx, dy } = &captured.event {
+            self.cursor_x += dx;
+            self.cursor_y += dy;
+
+            // Clamp to screen bounds
+            let width = i32::try_from(self.screen.width).unwrap_or(i32::MAX);
+            let height = i32::try_from(self.screen.height).unwrap_or(i32::MAX);
+            self.cursor_x = self.cursor_x.clamp(0, width - 1);
+            self.cursor_y = self.cursor_y.clamp(0, height - 1);
+
+            let configured: Vec<ScreenEdge> = self
+                .config
+                .screens
+                .iter()
+                .map(|sc| sc.position.local_edge())
+                .collect();
+            self.layout
+                .record_cursor(&self.screen, self.cursor_x, self.cursor_y, &configured);
+
+            // A corner with an action never crosses to a neighbour.
+            if self.check_corner_action().await {
+                return;
+            }
+
+            // Check barrier crossings
+            if self.paused {
+                return;
+            }
+            if let Some((peer_id, edge, position)) = self.check_barrier_crossing() {
+                self.initiate_control(peer_id, edge, position).await;
+            }
+        }
+    }
+
+    /// Apply `controlled.local_input_policy` to a press or movement made
+    /// on this machine while a peer controls it.
+    async fn apply_local_input_policy(&mut self, event: &InputEvent) {
+        if self.controlled_by.is_none() || is_release(event) {
+            return;
+        }
+        match self.config.controlled.local_input_policy {
+            LocalInputPolicy::Ignore => {}
+            LocalInputPolicy::Notify | LocalInputPolicy::Hold => {
+                self.local_user_seen().await;
+            }
+            LocalInputPolicy::Release => {
+                info!("local input while controlled, returning the cursor");
+                self.return_control().await;
+            }
+            LocalInputPolicy::PauseRemote => {
+                info!("local input while controlled, pausing cross-control");
+                self.set_paused(true).await;
+            }
+        }
+    }
+
+    /// Note local input while controlled. The first input after an idle
+    /// spell is logged and announced to the controller.
+    async fn local_user_seen(&mut self) {
+        let until = Instant::now() + Duration::from_secs(self.config.controlled.local_input_secs);
+        if self.local_user_until.replace(until).is_some() {
+            return;
+        }
+        self.counters.local_input_conflicts += 1;
+        let hold = self.config.controlled.local_input_policy == LocalInputPolicy::Hold;
+        let Some(session) = self.controlled_by.and_then(|id| self.sessions.get_mut(&id)) else {
+            return;
+        };
+        warn!(peer = %session.name, hold, "local input while controlled by a peer");
+        session.record("local user active");
+        if let Err(e) = session.announce_local_input(true).await {
+            debug!(peer = %session.name, error = %e, "failed to announce local input");
+        }
+    }
+
+    /// Once the local user has been idle for `controlled.local_input_secs`,
+    /// or control has ended, stop counting them as active.
+    async fn expire_local_user(&mut self) {
+        let Some(until) = self.local_user_until else {
+            return;
+        };
+        if self.controlled_by.is_some() && Instant::now() < until {
+            return;
+        }
+        self.local_user_until = None;
+        let Some(session) = self.controlled_by.and_then(|id| self.sessions.get_mut(&id)) else {
+            return;
+        };
+        info!(peer = %session.name, "local input idle, controller has the machine to itself");
+        session.record("local user idle");
+        if let Err(e) = session.announce_local_input(false).await {
+            debug!(peer = %session.name, error = %e, "failed to announce local input");
+        }
+    }
+
+    /// Whether the controller's input is held back for the local user.
+    fn holding_remote_input(&self) -> bool {
+        self.config.controlled.local_input_policy == LocalInputPolicy::Hold
+            && self
+                .local_user_until
+                .is_some_and(|until| Instant::now() < until)
+    }
+
+    /// While the local user is active under the `hold` policy, drop all
+    /// but releases from the controller's input, so nothing it pressed
+    /// stays stuck down. Returns whether any input is left to inject.
+    fn hold_back_remote_input(&mut self, machine_id: MachineId, msg: &mut InputMessage) -> bool {
+        if !self.holding_remote_input() {
+            return true;
+        }
+        let received = msg.events.len();
+        msg.events.retain(is_release);
+        if let Some(session) = self.sessions.get_mut(&machine_id) {
+            session.stats.events_dropped += (received - msg.events.len()) as u64;
+        }
+        !msg.events.is_empty()
+    }
+
+    /// Check the pause and lock-all hotkeys, which act on the key press
+    /// that completes them. Returns whether one fired.
+    async fn handle_action_hotkeys(&mut self, event: &InputEvent) -> bool {
+        let InputEvent::Key {
+            code,
+            state: ButtonState::Pressed,
+        } = event
+        else {
+            return false;
+        };
+        let completes = |hotkey: &[String]| hotkey.iter().any(|k| *k == format!("{code:?}"));
+        let hotkey = &self.config.input.pause_hotkey;
+        if completes(hotkey) && self.is_hotkey_pressed(hotkey) {
+            self.set_paused(!self.paused).await;
+            return true;
+        }
+        let hotkey = &self.config.input.lock_all_hotkey;
+        if completes(hotkey) && self.is_hotkey_pressed(hotkey) {
+            // Outcomes are logged; nobody waits for the reply.
+            let (reply, _) = oneshot::channel();
+            self.run_everywhere(LOCK_SCREEN_COMMAND.to_string(), reply)
+                .await;
+            return true;
+        }
+        let hotkey = &self.config.input.swap_hotkey;
+        if completes(hotkey) && self.is_hotkey_pressed(hotkey) {
+            self.swap_control().await;
+            return true;
+        }
+        false
+    }
+
+    /// Swap roles with the other end of the current control session. A peer
+    /// we control is asked to take control of us; a peer controlling us is
+    /// handed back its cursor and then entered.
+    async fn swap_control(&mut self) {
+        if let Some(peer_id) = self.controlling {
+            self.flush_motion().await;
+            if let Some(session) = self.sessions.get_mut(&peer_id) {
+                if !session.supports(ProtocolFeature::TakeControl) {
+                    warn!(
+                        peer = %session.name,
+                        peer_protocol = %session.version,
+                        "peer predates the swap hotkey"
+                    );
+                    return;
+                }
+                info!(peer = %session.name, "asking peer to take control");
+                session.record("asked peer to take control");
+                let _ = session
+                    .control_tx
+                    .send_tagged(&ControlMessage::TakeControl)
+                    .await;
+            }
+        } else if let Some(controller_id) = self.controlled_by {
+            if self.is_viewer(controller_id) {
+                debug!(machine_id = %controller_id, "not taking control of viewer screen");
+                return;
+            }
+            // The controller is beyond the edge its cursor came in through.
+            let edge = self.entry_edge.unwrap_or(ScreenEdge::Left);
+            if let Some(session) = self.sessions.get_mut(&controller_id) {
+                info!(peer = %session.name, "taking control of our controller");
+                session.record("took control of our controller");
+            }
+            self.return_control().await;
+            let position = self.edge_position(edge);
+            self.initiate_control(controller_id, edge, position).await;
+        }
+    }
+
+    /// Run the action bound to the corner the cursor is in, once per visit.
+    /// Returns whether the corner has an action.
+    async fn check_corner_action(&mut self) -> bool {
+        let corner = ScreenCorner::ALL.into_iter().find(|corner| {
+            corner
+                .edges()
+                .iter()
+                .all(|edge| self.screen.is_at_edge(self.cursor_x, self.cursor_y, *edge))
+        });
+        let arrived = corner != self.cursor_corner;
+        self.cursor_corner = corner;
+        let Some(action) = corner.and_then(|corner| {
+            self.config
+                .input
+                .corners
+                .iter()
+                .find(|rule| rule.corner == corner)
+                .map(|rule| rule.action.clone())
+        }) else {
+            return false;
+        };
+        if !arrived {
+            return true;
+        }
+        info!(?corner, ?action, "cursor reached a screen corner");
+        self.run_action(action).await;
+        true
+    }
+
+    /// Feed key events to `[[input.sequences]]` and run the action of one
+    /// that completes. Returns whether the event belonged to a sequence.
+    async fn handle_hotkey_sequences(&mut self, event: &InputEvent) -> bool {
+        let InputEvent::Key { code, state } = *event else {
+            return false;
+        };
+        let timeout = Duration::from_millis(self.config.input.chord_timeout_ms);
+        let outcome = self.hotkey_sequences.key(
+            &self.config.input.sequences,
+            timeout,
+            &self.hotkey_pressed,
+            code,
+            state,
+            Instant::now(),
+        );
+        match outcome {
+            SequenceKey::Pass => false,
+            SequenceKey::Consumed => true,
+            SequenceKey::Fired(action) => {
+                info!(?action, "hotkey sequence completed");
+                self.run_action(action).await;
+                true
+            }
+        }
+    }
+
+    /// Run a corner or hotkey sequence action.
+    async fn run_action(&mut self, action: CornerAction) {
+        match action {
+            CornerAction::Release if self.controlling.is_some() => self.release_control().await,
+            CornerAction::Release => self.return_control().await,
+            CornerAction::Pause => self.set_paused(!self.paused).await,
+            CornerAction::Switch(name) if name == self.config.identity.name => {
+                self.flush_motion().await;
+                self.release_control().await;
+            }
+            CornerAction::Switch(name) => {
+                let peer = self
+                    .sessions
+                    .values()
+                    .find(|s| s.name == name)
+                    .map(|s| s.machine_id);
+                match peer {
+                    Some(_) if self.paused || self.controlled_by.is_some() => {}
+                    Some(peer_id) if self.controlling == Some(peer_id) => {}
+                    Some(peer_id) => {
+                        self.flush_motion().await;
+                        self.release_control().await;
+                        let edge = self
+                            .config
+                            .screens
+                            .iter()
+                            .find(|sc| sc.name == name)
+                            .map_or(ScreenEdge::Right, |sc| sc.position.local_edge());
+                        let position = self.edge_position(edge);
+                        self.initiate_control(peer_id, edge, position).await;
+                    }
+                    None => {
+                        warn!(screen = %name, "action names a screen that isn't connected");
+                    }
+                }
+            }
+        }
+    }
+
+    /// Toggle private input on its hotkey (on the key press that completes
+    /// it), and keep keyboard events here while it is on. Releases of keys
+    /// pressed before it was turned on still go out, so they don't stick on
+    /// the peer. Returns whether the event stops here.
+    fn handle_private_input(&mut self, event: &InputEvent) -> bool {
+        let InputEvent::Key { code, state } = event else {
+            return false;
+        };
+        let hotkey = &self.config.input.private_hotkey;
+        if *state == ButtonState::Pressed
+            && hotkey.iter().any(|k| *k == format!("{code:?}"))
+            && self.is_hotkey_pressed(hotkey)
+        {
+            self.private_input = match self.private_input {
+                Some(_) => None,
+                // The key completing the hotkey was never forwarded.
+                None => Some(
+                    self.hotkey_pressed
+                        .iter()
+                        .copied()
+                        .filter(|k| k != code)
+                        .collect(),
+                ),
+            };
+            info!(on = self.private_input.is_some(), "private input toggled");
+            return true;
+        }
+        let Some(held) = &mut self.private_input else {
+            return false;
+        };
+        let was_held = held.contains(code);
+        held.retain(|k| k != code);
+        !(was_held && *state == ButtonState::Released)
+    }
+
+    fn check_barrier_crossing(&self) -> Option<(MachineId, ScreenEdge, u32)> {
+        self.edges.crossings().find_map(|(edge, peer_id)| {
+            let session = self.sessions.get(&peer_id)?;
+            if session.enter_denied.is_some() || session.suspended {
+                return None;
+            }
+            self.screen
+                .is_at_edge(self.cursor_x, self.cursor_y, edge)
+                .then(|| (peer_id, edge, self.edge_position(edge)))
+        })
+    }
+
+    /// Resolve the layout against the current sessions again. Called
+    /// whenever either changes.
+    fn rebuild_edges(&mut self) {
+        let peers = self
+            .sessions
+            .iter()
+            .map(|(id, session)| (*id, session.name.as_str()));
+        self.edges = EdgeTable::build(&self.config, peers);
+    }
+
+    /// Position of the cursor along `edge` in logical pixels, as carried by
+    /// `Enter` and `Leave`.
+    fn edge_position(&self, edge: ScreenEdge) -> u32 {
+        let physical = match edge {
+            ScreenEdge::Left | ScreenEdge::Right => u32::try_from(self.cursor_y).unwrap_or(0),
+            ScreenEdge::Top | ScreenEdge::Bottom => u32::try_from(self.cursor_x).unwrap_or(0),
+        };
+        self.screen.to_logical(physical)
+    }
+
+    /// Place the cursor on `edge` at a logical `position` received from a peer,
+    /// scaled to this screen's physical pixels.
+    fn place_cursor_at_edge(&mut self, edge: ScreenEdge, position: u32) {
+        let width = i32::try_from(self.screen.width).unwrap_or(1920);
+        let height = i32::try_from(self.screen.height).unwrap_or(1080);
+        let pos = i32::try_from(self.screen.to_physical(position)).unwrap_or(0);
+        match edge {
+            ScreenEdge::Left => {
+                self.cursor_x = 0;
+                self.cursor_y = pos.clamp(0, height - 1);
+            }
+            ScreenEdge::Right => {
+                self.cursor_x = width - 1;
+                self.cursor_y = pos.clamp(0, height - 1);
+            }
+            ScreenEdge::Top => {
+                self.cursor_x = pos.clamp(0, width - 1);
+                self.cursor_y = 0;
+            }
+            ScreenEdge::Bottom => {
+                self.cursor_x = pos.clamp(0, width - 1);
+                self.cursor_y = height - 1;
+            }
+        }
+    }
+
+    /// Whether a peer is configured as a viewer screen (never controls or is controlled).
+    fn is_viewer(&self, peer_id: MachineId) -> bool {
+        self.sessions.get(&peer_id).is_some_and(|session| {
+            self.config
+                .screens
+                .iter()
+                .any(|sc| sc.name == session.name && sc.mode == ScreenMode::Viewer)
+        })
+    }
+
+    async fn initiate_control(&mut self, peer_id: MachineId, edge: ScreenEdge, position: u32) {
+        if self.is_viewer(peer_id) {
+            debug!(machine_id = %peer_id, "not taking control of viewer screen");
+            return;
+        }
+        info!(machine_id = %peer_id, ?edge, position, "initiating control");
+
+        if let Some(session) = self.sessions.get(&peer_id) {
+            let threshold = self.config.power.low_battery_percent;
+            if self.config.power.warn_low_battery && session.power.is_low(threshold) {
+                warn!(
+                    peer = %session.name,
+                    battery = ?session.power.battery_percent,
+                    "crossing into a peer with a low battery; it may sleep soon"
+                );
+            }
+        }
+
+        if let Some(session) = self.sessions.get_mut(&peer_id) {
+            match session.send_enter(edge, position).await {
+                Ok(()) => {
+                    // Don't set controlling yet — wait for EnterAck via event loop
+                    info!(machine_id = %peer_id, "Enter sent, awaiting EnterAck");
+                }
+                Err(e) => {
+                    warn!(error = %e, "failed to initiate control");
+                }
+            }
+        }
+    }
+
+    /// Warp the cursor to `(x, y)` on `screen`, this machine or a peer. A
+    /// peer we aren't controlling is entered first, and the reply waits for
+    /// its `EnterAck`.
+    async fn teleport(
+        &mut self,
+        screen: &str,
+        x: u32,
+        y: u32,
+        reply: oneshot::Sender<IpcResponse>,
+    ) {
+        if screen == self.config.identity.name {
+            self.flush_motion().await;
+            self.release_control().await;
+            let x = x.min(self.screen.width.saturating_sub(1));
+            let y = y.min(self.screen.height.saturating_sub(1));
+            self.cursor_x = i32::try_from(x).unwrap_or(i32::MAX);
+            self.cursor_y = i32::try_from(y).unwrap_or(i32::MAX);
+            info!(x, y, "teleported cursor to local screen");
+            let _ = reply.send(IpcResponse::Teleported {
+                screen: screen.to_string(),
+                x,
+                y,
+            });
+            return;
+        }
+        self.act_on_peer(screen, PeerAction::Warp { x, y }, reply)
+            .await;
+    }
+
+    /// Do `action` on the peer named `screen`. A peer we aren't controlling
+    /// is entered first, and the reply waits for its `EnterAck`.
+    async fn act_on_peer(
+        &mut self,
+        screen: &str,
+        action: PeerAction,
+        reply: oneshot::Sender<IpcResponse>,
+    ) {
+        let Some(session) = self
+            .sessions
+            .values()
+            .find(|s| s.name == screen || s.machine_id.to_string() == screen)
+        else {
+            let _ = reply.send(IpcResponse::error(
+                ErrorCode::NotConnected,
+                format!("not connected to {screen}"),
+            ));
+            return;
+        };
+        let peer_id = session.machine_id;
+        let refused = if self.paused {
+            Some("cross-control is paused")
+        } else if self.controlled_by.is_some() {
+            Some("this machine is being controlled")
+        } else if self.is_viewer(peer_id) {
+            Some("viewer screens can't be controlled")
+        } else if session.enter_denied.is_some() || session.suspended {
+            Some("peer can't be controlled right now")
+        } else {
+            None
+        };
+        if let Some(reason) = refused {
+            let _ = reply.send(IpcResponse::error(ErrorCode::InvalidRequest, reason));
+            return;
+        }
+        let edge = self
+            .config
+            .screens
+            .iter()
+            .find(|sc| sc.name == session.name)
+            .map_or(ScreenEdge::Right, |sc| sc.position.local_edge());
+
+        if self.controlling == Some(peer_id) {
+            let response = self.perform(peer_id, action).await;
+            let _ = reply.send(response);
+            return;
+        }
+        self.flush_motion().await;
+        self.release_control().await;
+        let position = self.edge_position(edge);
+        let pending = PendingEntry {
+            peer: peer_id,
+            action,
+            reply,
+        };
+        if let Some(previous) = self.pending_entry.replace(pending) {
+            let _ = previous.reply.send(IpcResponse::error(
+                ErrorCode::Superseded,
+                "superseded by a newer request",
+            ));
+        }
+        self.initiate_control(peer_id, edge, position).await;
+    }
+
+    /// The pending entry into `peer`, if any.
+    fn take_pending_entry(&mut self, peer: MachineId) -> Option<PendingEntry> {
+        if self.pending_entry.as_ref()?.peer == peer {
+            self.pending_entry.take()
+        } else {
+            None
+        }
+    }
+
+    /// Do `action` on a peer we control.
+    async fn perform(&mut self, peer_id: MachineId, action: PeerAction) -> IpcResponse {
+        match action {
+            PeerAction::Warp { x, y } => self.warp_peer(peer_id, x, y).await,
+            PeerAction::Type(text) => self.type_into_peer(peer_id, &text).await,
+        }
+    }
+
+    /// Move the cursor of a peer we control to `(x, y)` on its screen with
+    /// an absolute move. The peer falls back to relative motion if its
+    /// virtual pointer has no absolute axes.
+    async fn warp_peer(&mut self, peer_id: MachineId, x: u32, y: u32) -> IpcResponse {
+        self.flush_motion().await;
+        let Some(session) = self.sessions.get_mut(&peer_id) else {
+            return IpcResponse::error(ErrorCode::NotConnected, "peer disconnected");
+        };
+        let Some(pointer) = self.local_devices.iter().find(|d| {
+            (d.capabilities.contains(&DeviceCapability::RelativeMouse)
+                || d.capabilities.contains(&DeviceCapability::AbsoluteMouse))
+                && session.shared_devices.contains(&d.id)
+        }) else {
+            return IpcResponse::error(
+                ErrorCode::InvalidRequest,
+                format!("no pointer is shared with {}", session.name),
+            );
+        };
+        let width = session.remote_screen.width.max(1);
+        let height = session.remote_screen.height.max(1);
+        let x = x.min(width - 1);
+        let y = y.min(height - 1);
+        let msg = InputMessage {
+            device_id: pointer.id,
+            timestamp_us: 0,
+            events: vec![InputEvent::MouseMoveAbsolute {
+                x: f64::from(x) / f64::from(width),
+                y: f64::from(y) / f64::from(height),
+            }],
+        };
+        if let Err(e) = session.send_input(&msg).await {
+            return e.into();
+        }
+        info!(peer = %session.name, x, y, "teleported cursor to peer");
+        IpcResponse::Teleported {
+            screen: session.name.clone(),
+            x,
+            y,
+        }
+    }
+
+    /// Type `text` into a peer we control with a shared keyboard, one batch
+    /// of key strokes per character. Characters are mapped with a US layout.
+    async fn type_into_peer(&mut self, peer_id: MachineId, text: &str) -> IpcResponse {
+        self.flush_motion().await;
+        let Some(session) = self.sessions.get_mut(&peer_id) else {
+            return IpcResponse::error(ErrorCode::NotConnected, "peer disconnected");
+        };
+        let Some(keyboard) = self.local_devices.iter().find(|d| {
+            d.capabilities.contains(&DeviceCapability::Keyboard)
+                && session.shared_devices.contains(&d.id)
+        }) else {
+            return IpcResponse::error(
+                ErrorCode::InvalidRequest,
+                format!("no keyboard is shared with {}", session.name),
+            );
+        };
+        let key = |code, state| InputEvent::Key { code, state };
+        let mut chars = 0;
+        for (code, shift) in text.chars().filter_map(KeyCode::for_char) {
+            let mut events = vec![
+                key(code, ButtonState::Pressed),
+                key(code, ButtonState::Released),
+            ];
+            if shift {
+                events.insert(0, key(KeyCode::LeftShift, ButtonState::Pressed));
+                events.push(key(KeyCode::LeftShift, ButtonState::Released));
+            }
+            let msg = InputMessage {
+                device_id: keyboard.id,
+                timestamp_us: 0,
+                events,
+            };
+            if let Err(e) = session.send_input(&msg).await {
+                return e.into();
+            }
+            chars += 1;
+        }
+        info!(peer = %session.name, chars, "typed text into peer");
+        IpcResponse::Typed {
+            screen: session.name.clone(),
+            chars,
+        }
+    }
+
+    /// Send a net-zero pointer nudge to a newly controlled peer, if its
+    /// screen asks for it. The injected motion wakes displays in DPMS sleep.
+    async fn wake_display(&mut self, peer_id: MachineId) {
+        let Some(session) = self.sessions.get_mut(&peer_id) else {
+            return;
+        };
+        let wake = self
+            .config
+            .screens
+            .iter()
+            .any(|sc| sc.name == session.name && sc.wake_on_enter);
+        let pointer = self.local_devices.iter().find(|d| {
+            d.capabilities.contains(&DeviceCapability::RelativeMouse)
+                && session.shared_devices.contains(&d.id)
+        });
+        let (true, Some(pointer)) = (wake, pointer) else {
+            return;
+        };
+        // Both moves travel in one batch, so the peer's edge tracking sees
+        // no net motion and the cursor stays where it entered.
+        let msg = InputMessage {
+            device_id: pointer.id,
+            timestamp_us: 0,
+            events: vec![
+                InputEvent::MouseMove { dx: 1, dy: 0 },
+                InputEvent::MouseMove { dx: -1, dy: 0 },
+            ],
+        };
+        if let Err(e) = session.send_input(&msg).await {
+            debug!(peer = %session.name, error = %e, "failed to send display wake");
+        }
+    }
+
+    /// Start shaking the pointer on a newly controlled peer, if its screen
+    /// asks for it, so the user can spot where the cursor landed.
+    fn locate_cursor(&self, peer_id: MachineId) {
+        let Some(session) = self.sessions.get(&peer_id) else {
+            return;
+        };
+        let locate = self
+            .config
+            .screens
+            .iter()
+            .any(|sc| sc.name == session.name && sc.locate_on_enter);
+        if !locate {
+            return;
+        }
+        let tx = self.event_tx.clone();
+        supervisor::spawn("cursor-locate", async move {
+            for step in 0..LOCATE_SHAKE.len() {
+                tokio::time::sleep(LOCATE_STEP).await;
+                if tx
+                    .send(DaemonEvent::LocateStep {
+                        peer: peer_id,
+                        step,
+                    })
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        });
+    }
+
+    /// Send one move of a locate shake, unless control has moved on.
+    async fn locate_step(&mut self, peer_id: MachineId, step: usize) {
+        if self.controlling != Some(peer_id) {
+            return;
+        }
+        let Some(session) = self.sessions.get_mut(&peer_id) else {
+            return;
+        };
+        let pointer = self.local_devices.iter().find(|d| {
+            d.capabilities.contains(&DeviceCapability::RelativeMouse)
+                && session.shared_devices.contains(&d.id)
+        });
+        let (Some(pointer), Some(&offset)) = (pointer, LOCATE_SHAKE.get(step)) else {
+            return;
+        };
+        // Shake along the edge the cursor came in through: moving away
+        // from it and back would hit it again and hand control back.
+        let event = match session.entered_through {
+            Some(ScreenEdge::Top | ScreenEdge::Bottom) => {
+                InputEvent::MouseMove { dx: offset, dy: 0 }
+            }
+            _ => InputEvent::MouseMove { dx: 0, dy: offset },
+        };
+        let msg = InputMessage {
+            device_id: pointer.id,
+            timestamp_us: 0,
+            events: vec![event],
+        };
+        if let Err(e) = session.send_input(&msg).await {
+            debug!(peer = %session.name, error = %e, "failed to send locate shake");
+        }
+    }
+
+    /// Pause or resume all cross-control activity. Pausing releases control
+    /// in both directions; sessions stay connected.
+    async fn set_paused(&mut self, paused: bool) {
+        if paused == self.paused {
+            return;
+        }
+        self.paused = paused;
+        if !paused {
+            info!("cross-control resumed");
+            return;
+        }
+
+        info!("cross-control paused");
+        self.release_control().await;
+        self.return_control().await;
+    }
+
+    /// Keep local devices grabbed while we control a peer, or while a peer
+    /// controls us and `input.suppress_local_while_controlled` is set, and
+    /// released otherwise. A failed grab is undone, so no device is left
+    /// grabbed while others are free, and retried with backoff.
+    async fn sync_grab(&mut self) {
+        let suppress = self
+            .controlled_by
+            .filter(|_| self.config.input.suppress_local_while_controlled);
+        if self.controlling.is_none() && suppress.is_none() {
+            self.grab_retry = None;
+            if self.input_grab != InputGrab::Released {
+                self.release_input().await;
+                self.broadcast_status();
+            }
+            if self.local_input_grabbed.take().is_some() {
+                info!("local input restored");
+            }
+            return;
+        }
+        if self.input_grab != InputGrab::Grabbed {
+            let now = Instant::now();
+            if self.grab_retry.is_some_and(|(at, _)| now < at) {
+                return;
+            }
+            if let Err(e) = self.capture.grab().await {
+                self.release_input().await;
+                let failures = self.grab_retry.map_or(1, |(_, failures)| failures + 1);
+                let delay = grab_retry_delay(failures);
+                warn_throttled!(error = %e, retry_in = ?delay, "failed to grab local input");
+                self.grab_retry = Some((now + delay, failures));
+                self.input_grab = InputGrab::Retrying;
+                if failures == 1 {
+                    self.broadcast_status();
+                }
+                return;
+            }
+            self.grab_retry = None;
+            self.input_grab = InputGrab::Grabbed;
+            self.broadcast_status();
+        }
+        if suppress.is_some() && self.local_input_grabbed.is_none() {
+            info!("suppressing local input while controlled");
+        }
+        self.local_input_grabbed = suppress;
+    }
+
+    /// Release local devices, e.g. as control of a peer ends.
+    async fn release_input(&mut self) {
+        let _ = self.capture.release().await;
+        self.input_grab = InputGrab::Released;
+    }
+
+    /// Hand the cursor back to the peer controlling us, across the edge it
+    /// came in on.
+    async fn return_control(&mut self) {
+        if let Some(controller_id) = self.controlled_by.take() {
+            let edge = self.entry_edge.take().unwrap_or(ScreenEdge::Left);
+            let position = self.edge_position(edge);
+            if let Some(session) = self.sessions.get_mut(&controller_id) {
+                let _ = session.leave(edge, position).await;
+            }
+        }
+    }
+
+    /// Release control in both directions and tell every peer we are about
+    /// to sleep, so they stop routing to us without waiting for a timeout.
+    async fn prepare_for_sleep(&mut self) {
+        info!("system preparing to sleep");
+        self.flush_motion().await;
+        self.release_control().await;
+        self.return_control().await;
+        for session in self.sessions.values_mut() {
+            let _ = session
+                .control_tx
+                .send_tagged(&ControlMessage::Suspending)
+                .await;
+        }
+    }
+
+    /// Tell surviving peers we are back and redial screens whose sessions
+    /// were lost while asleep.
+    async fn handle_resumed(&mut self) {
+        info!("system resumed from sleep");
+        self.resumed_at = Some(Instant::now());
+        for session in self.sessions.values_mut() {
+            session.last_keepalive = Instant::now();
+            let _ = session
+                .control_tx
+                .send_tagged(&ControlMessage::Resumed)
+                .await;
+        }
+        self.connect_missing_peers();
+    }
+
+    /// Redial screens we have no session with, and check the Wi-Fi network
+    /// for profile selection.
+    fn handle_interfaces_changed(&mut self) {
+        self.network_changed_at = Some(Instant::now());
+        self.connect_missing_peers();
+
+        if self.config.profiles.values().any(|p| !p.ssids.is_empty()) {
+            let event_tx = self.event_tx.clone();
+            supervisor::spawn("ssid-check", async move {
+                if let Ok(ssid) = tokio::task::spawn_blocking(crate::network::current_ssid).await {
+                    let _ = event_tx.send(DaemonEvent::NetworkChanged { ssid }).await;
+                }
+            });
+        }
+    }
+
+    /// Dial every configured screen that has no live session.
+    fn connect_missing_peers(&self) {
+        let connected: Vec<&str> = self.sessions.values().map(|s| s.name.as_str()).collect();
+        for sc in &self.config.screens {
+            if !connected.contains(&sc.name.as_str()) {
+                self.connect_peer(sc);
             }
-        });
+        }
     }
 
-    async fn handle_captured_input(&mut self, captured: CapturedEvent) {
-        // Track hotkey state
-        self.update_hotkey_state(&captured.event);
+    /// Coalescing window for motion sent to `peer_id`: the battery window if
+    /// it is on battery, otherwise `performance.coalesce_ms`, if set.
+    fn coalesce_window(&self, peer_id: MachineId) -> Option<Duration> {
+        let ms = self.config.power.battery_coalesce_ms;
+        let on_battery = self.sessions.get(&peer_id)?.power.on_battery;
+        if on_battery && ms > 0 {
+            return Some(Duration::from_millis(ms));
+        }
+        self.config.performance.coalesce()
+    }
 
-        // Check release hotkey
-        if self.is_release_hotkey_pressed() && self.controlling.is_some() {
-            self.release_control().await;
+    /// Add motion to the pending batch for `peer` and the event's device,
+    /// scheduling a flush when a new batch starts.
+    fn coalesce_motion(
+        &mut self,
+        peer: MachineId,
+        captured: &CapturedEvent,
+        dx: i32,
+        dy: i32,
+        window: Duration,
+    ) {
+        if let Some(pending) = &mut self.pending_motion {
+            pending.dx += dx;
+            pending.dy += dy;
+            pending.timestamp_us = captured.timestamp_us;
             return;
         }
+        self.pending_motion = Some(PendingMotion {
+            peer,
+            device_id: captured.device_id,
+            timestamp_us: captured.timestamp_us,
+            dx,
+            dy,
+        });
+        let flush_tx = self.event_tx.clone();
+        supervisor::spawn("motion-flush", async move {
+            tokio::time::sleep(window).await;
+            let _ = flush_tx.send(DaemonEvent::FlushMotion).await;
+        });
+    }
 
-        // If we're controlling a remote, forward the event
-        if let Some(peer_id) = self.controlling {
-            if let Some(session) = self.sessions.get_mut(&peer_id) {
-                let msg = InputMessage {
-                    device_id: captured.device_id,
-                    timestamp_us: captured.timestamp_us,
-                    events: vec![captured.event],
+    /// Send any coalesced motion to the peer we are still controlling.
+    async fn flush_motion(&mut self) {
+        let Some(pending) = self.pending_motion.take() else {
+            return;
+        };
+        if self.controlling != Some(pending.peer) {
+            return;
+        }
+        if let Some(session) = self.sessions.get_mut(&pending.peer) {
+            let msg = InputMessage {
+                device_id: pending.device_id,
+                timestamp_us: pending.timestamp_us,
+                events: vec![InputEvent::MouseMove {
+                    dx: pending.dx,
+                    dy: pending.dy,
+                }],
+            };
+            if let Err(e) = session.send_input(&msg).await {
+                warn_throttled!(error = %e, "failed to send coalesced motion to peer");
+            } else {
+                self.counters.events_forwarded += 1;
+            }
+        }
+    }
+
+    /// Ping each peer whose keepalive interval has elapsed. The interval is
+    /// longer when either end is on battery.
+    async fn send_keepalives(&mut self) {
+        let normal = self.config.performance.keepalive(&self.config.daemon);
+        let battery = Duration::from_millis(self.config.power.battery_keepalive_ms);
+        let local_battery = self.power.is_some_and(|p| p.on_battery);
+        for session in self.sessions.values_mut() {
+            if session.suspended {
+                continue;
+            }
+            let interval = if local_battery || session.power.on_battery {
+                battery
+            } else {
+                normal
+            };
+            if session.last_keepalive.elapsed() < interval {
+                continue;
+            }
+            self.ping_seq += 1;
+            if let Err(e) = session.ping(self.ping_seq).await {
+                debug!(peer = %session.name, error = %e, "failed to send keepalive");
+            }
+        }
+    }
+
+    /// Act on discovery hints: dial a configured peer as soon as it appears,
+    /// and probe a connected peer as soon as it disappears.
+    async fn handle_discovery(&mut self, event: DiscoveryEvent) {
+        match event {
+            DiscoveryEvent::PeerFound(peer) => {
+                // Backends should filter by group already; don't rely on it.
+                if peer.group != self.config.daemon.group {
+                    debug!(peer = %peer.name, group = ?peer.group, "ignoring peer from another group");
+                    return;
+                }
+                let port = self.transport.local_addr().ok().map(|a| a.port());
+                let is_local = |addr: &SocketAddr| {
+                    Some(addr.port()) == port && crate::network::is_local_ip(addr.ip())
                 };
-                debug!(peer = %peer_id, device = ?msg.device_id, "forwarding input to peer");
-                if let Err(e) = session.send_input(&msg).await {
-                    warn!(error = %e, "failed to send input to peer");
-                    self.controlling = None;
-                    let _ = self.capture.release().await;
+                let pinned = self
+                    .config
+                    .screens
+                    .iter()
+                    .find(|sc| sc.name == peer.name)
+                    .and_then(|sc| sc.fingerprint.as_deref());
+                match self.directory.found(peer.clone(), is_local, pinned) {
+                    Sighting::Own => return,
+                    Sighting::Spoofed(description) => {
+                        warn_throttled!("ignoring spoofed advertisement: {description}");
+                        return;
+                    }
+                    Sighting::Conflict(description) => {
+                        warn!("identity conflict: {description}");
+                    }
+                    Sighting::Peer => {}
+                }
+                if self.directory.is_blocked(&peer.name) {
+                    debug!(peer = %peer.name, "not connecting while its identity is in conflict");
+                    return;
+                }
+                if self
+                    .sessions
+                    .values()
+                    .any(|s| s.machine_id == peer.machine_id || s.name == peer.name)
+                {
+                    return;
+                }
+                let Some(sc) = self.config.screens.iter().find(|sc| sc.name == peer.name) else {
+                    debug!(peer = %peer.name, "ignoring discovered peer without a screen");
+                    return;
+                };
+                let mut sc = sc.clone();
+                sc.address.get_or_insert_with(|| peer.address.to_string());
+                info!(peer = %peer.name, address = %peer.address, "peer discovered, connecting");
+                self.connect_peer(&sc);
+            }
+            DiscoveryEvent::PeerLost(machine_id) => {
+                self.directory.lost(machine_id);
+                let Some(session) = self.sessions.get_mut(&machine_id) else {
+                    return;
+                };
+                if session.suspended || session.liveness_probe.is_some() {
+                    return;
                 }
+                info!(peer = %session.name, "peer left discovery, probing");
+                self.ping_seq += 1;
+                session.liveness_probe = Some((self.ping_seq, Instant::now()));
+                if let Err(e) = session.ping(self.ping_seq).await {
+                    debug!(peer = %session.name, error = %e, "failed to send liveness probe");
+                }
+            }
+        }
+    }
+
+    /// Drop sessions whose liveness probe went unanswered. Returns `true` if
+    /// any were dropped.
+    async fn expire_liveness_probes(&mut self) -> bool {
+        let expired: Vec<MachineId> = self
+            .sessions
+            .values()
+            .filter(|s| {
+                s.liveness_probe
+                    .is_some_and(|(_, at)| at.elapsed() >= LIVENESS_PROBE_TIMEOUT)
+            })
+            .map(|s| s.machine_id)
+            .collect();
+        for id in &expired {
+            if let Some(session) = self.sessions.get_mut(id) {
+                warn!(peer = %session.name, "peer did not answer liveness probe");
+                session.connection.close();
             }
+            self.handle_peer_disconnected(*id).await;
+        }
+        !expired.is_empty()
+    }
+
+    /// Stop following a controller that has sent neither input nor a
+    /// keepalive for `input.controlled_timeout_secs`, e.g. after it lost
+    /// power, instead of waiting for the QUIC idle timeout.
+    fn expire_silent_controller(&mut self) -> bool {
+        let timeout = Duration::from_secs(self.config.input.controlled_timeout_secs);
+        let Some(controller) = self.controlled_by else {
+            return false;
+        };
+        if timeout.is_zero() || self.controller_seen.elapsed() < timeout {
+            return false;
+        }
+        if let Some(session) = self.sessions.get_mut(&controller) {
+            warn!(peer = %session.name, timeout_secs = timeout.as_secs(), "controller went silent, returning to idle");
+            session.controller_silent(timeout);
+        }
+        self.controlled_by = None;
+        self.draining = None;
+        self.silent_controller = Some(SilentController {
+            peer: controller,
+            episode: self.input_episode,
+            entry_edge: self.entry_edge.take(),
+        });
+        true
+    }
+
+    /// Re-baseline timers if the wall clock jumped since the last tick, so
+    /// the jump shows up neither as round-trip time nor as a timeout.
+    fn check_clock(&mut self) {
+        let Some(jump) = self.clock.check() else {
             return;
+        };
+        warn!(
+            direction = jump.as_str(),
+            jump_ms = u64::try_from(jump.amount().as_millis()).unwrap_or(u64::MAX),
+            "system clock jumped, re-baselining session timers"
+        );
+        self.counters.clock_jumps += 1;
+        for session in self.sessions.values_mut() {
+            session.clock_jumped(jump);
         }
+        self.controller_seen = Instant::now();
+        // Keep `status` reporting the start time on the new wall clock.
+        let (started, _) = self.started;
+        self.started.1 = SystemTime::now()
+            .checked_sub(started.elapsed())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+    }
 
-        // Track cursor position for barrier detection
-        if let InputEvent::MouseMove { dx, dy } = &captured.event {
-            self.cursor_x += dx;
-            self.cursor_y += dy;
+    /// Add the time since the last call to the peer we control, if any.
+    fn record_control_time(&mut self) {
+        let elapsed = self.metrics_tick.elapsed();
+        self.metrics_tick = Instant::now();
+        if let Some(session) = self.controlling.and_then(|id| self.sessions.get(&id)) {
+            self.metrics
+                .record_controlled(&session.name, elapsed, SystemTime::now());
+        }
+    }
 
-            // Clamp to screen bounds
-            let width = i32::try_from(self.screen.width).unwrap_or(i32::MAX);
-            let height = i32::try_from(self.screen.height).unwrap_or(i32::MAX);
-            self.cursor_x = self.cursor_x.clamp(0, width - 1);
-            self.cursor_y = self.cursor_y.clamp(0, height - 1);
+    /// Keep `metrics` current and save it every [`METRICS_SAVE_INTERVAL`].
+    fn update_metrics(&mut self) {
+        self.record_control_time();
+        if self.metrics_saved.elapsed() >= METRICS_SAVE_INTERVAL {
+            self.save_metrics();
+        }
+    }
 
-            // Check barrier crossings
-            if let Some((peer_id, edge, position)) = self.check_barrier_crossing() {
-                self.initiate_control(peer_id, edge, position).await;
-            }
+    /// Drop days past `limits.metrics_days` and write `metrics` to disk.
+    fn save_metrics(&mut self) {
+        self.metrics_saved = Instant::now();
+        let Some(path) = &self.metrics_path else {
+            return;
+        };
+        self.metrics
+            .prune(self.config.limits.metrics_days, SystemTime::now());
+        if let Err(e) = self.metrics.save(path) {
+            warn!(error = %e, "failed to save usage history");
         }
     }
 
-    fn check_barrier_crossing(&self) -> Option<(MachineId, ScreenEdge, u32)> {
-        for (peer_id, session) in &self.sessions {
-            // Find which screen config matches this peer
-            for screen_config in &self.config.screens {
-                if screen_config.name == session.name {
-                    let edge = screen_config.position.local_edge();
-                    if self.screen.is_at_edge(self.cursor_x, self.cursor_y, edge) {
-                        let position = match edge {
-                            ScreenEdge::Left | ScreenEdge::Right => {
-                                u32::try_from(self.cursor_y).unwrap_or(0)
-                            }
-                            ScreenEdge::Top | ScreenEdge::Bottom => {
-                                u32::try_from(self.cursor_x).unwrap_or(0)
-                            }
-                        };
-                        return Some((*peer_id, edge, position));
-                    }
+    /// Raise or clear each session's link alarm against the `[alarms]`
+    /// thresholds.
+    fn check_link_alarms(&mut self) {
+        let alarms = &self.config.alarms;
+        let hold = Duration::from_secs(alarms.for_secs);
+        let now = Instant::now();
+        for session in self.sessions.values_mut() {
+            let thresholds = alarms.thresholds_for(&session.name);
+            let breach = crate::alarms::breach(
+                &thresholds,
+                session.stats.rtt().last(),
+                session.stats.loss_percent(),
+            );
+            let Some(change) = session.alarm.update(breach, hold, now) else {
+                continue;
+            };
+            match &change {
+                AlarmChange::Raised(reason) => {
+                    warn!(peer = %session.name, %reason, "link degraded");
+                    session.record(format!("link alarm raised: {reason}"));
                 }
+                AlarmChange::Cleared => {
+                    info!(peer = %session.name, "link recovered");
+                    session.record("link alarm cleared");
+                }
+            }
+            if alarms.notify {
+                crate::alarms::notify(&session.name, &change);
             }
+            crate::alarms::run_command(&alarms.command, &session.name, &change);
         }
-        None
     }
 
-    async fn initiate_control(&mut self, peer_id: MachineId, edge: ScreenEdge, position: u32) {
-        info!(peer = %peer_id, ?edge, position, "initiating control");
+    /// Tell our controller where its input is landing, if that changed since
+    /// the last hint.
+    async fn send_focus_hint(&mut self) {
+        let Some(controller) = self.controlled_by else {
+            return;
+        };
+        let hint = FocusHint {
+            cursor_x: self.cursor_x,
+            cursor_y: self.cursor_y,
+            screen: self.screen_state,
+        };
+        if self.focus_hint_sent == Some((controller, hint)) {
+            return;
+        }
+        let Some(session) = self.sessions.get_mut(&controller) else {
+            return;
+        };
+        match session
+            .control_tx
+            .send_tagged(&ControlMessage::FocusHint(hint))
+            .await
+        {
+            Ok(()) => self.focus_hint_sent = Some((controller, hint)),
+            Err(e) => debug!(peer = %session.name, error = %e, "failed to send focus hint"),
+        }
+    }
 
-        if let Some(session) = self.sessions.get_mut(&peer_id) {
-            match session.send_enter(edge, position).await {
-                Ok(()) => {
-                    // Don't set controlling yet — wait for EnterAck via event loop
-                    info!(peer = %peer_id, "Enter sent, awaiting EnterAck");
-                }
-                Err(e) => {
-                    warn!(error = %e, "failed to initiate control");
+    /// Keep a peer's focus hint for `status`, warning when its screen can't
+    /// show what we type.
+    fn handle_focus_hint(&mut self, machine_id: MachineId, hint: FocusHint) {
+        let Some(session) = self.sessions.get_mut(&machine_id) else {
+            return;
+        };
+        let previous = session.focus.map_or(ScreenState::Active, |f| f.screen);
+        if hint.screen != previous {
+            match hint.screen {
+                ScreenState::Active => info!(peer = %session.name, "peer screen is active"),
+                ScreenState::Locked => {
+                    warn!(peer = %session.name, "peer is locked, input goes to its lock screen");
                 }
+                ScreenState::Asleep => warn!(peer = %session.name, "peer's displays are asleep"),
+            }
+            session.record(format!("screen {}", hint.screen.as_str()));
+        }
+        session.focus = Some(hint);
+    }
+
+    /// Record a local power change and tell every peer.
+    async fn handle_power_changed(&mut self, state: PowerState) {
+        info!(
+            on_battery = state.on_battery,
+            battery = ?state.battery_percent,
+            "local power source changed"
+        );
+        self.power = Some(state);
+        for session in self.sessions.values_mut() {
+            let _ = session
+                .control_tx
+                .send_tagged(&ControlMessage::PowerState(state))
+                .await;
+        }
+    }
+
+    /// Record a local keyboard layout change and tell every peer.
+    async fn handle_keyboard_layout_changed(&mut self, layout: KeyboardLayout) {
+        if self.keyboard_layout.as_ref() == Some(&layout) {
+            return;
+        }
+        info!(%layout, "local keyboard layout changed");
+        for session in self.sessions.values_mut() {
+            if let Err(e) = session.announce_keyboard_layout(&layout).await {
+                debug!(peer = %session.name, error = %e, "failed to send keyboard layout");
+            }
+        }
+        self.keyboard_layout = Some(layout);
+    }
+
+    /// Remember the last error a peer reported, forgetting the oldest
+    /// report once there are more than `limits.peer_errors`.
+    fn record_peer_error(&mut self, peer: String, code: ErrorCode, detail: String) {
+        self.peer_error_seq += 1;
+        self.peer_errors
+            .insert(peer, (code, detail, self.peer_error_seq));
+        while self.peer_errors.len() > self.config.limits.peer_errors.max(1) {
+            let oldest = self
+                .peer_errors
+                .iter()
+                .min_by_key(|(_, (_, _, seq))| *seq)
+                .map(|(peer, _)| peer.clone());
+            let Some(oldest) = oldest else {
+                break;
+            };
+            self.peer_errors.remove(&oldest);
+            self.peer_errors_evicted += 1;
+        }
+    }
+
+    /// Apply the `drag_scroll` rules to an event about to be forwarded.
+    fn drag_scroll(&mut self, captured: &CapturedEvent) -> Vec<InputEvent> {
+        let rules = &self.config.input.drag_scroll;
+        if rules.is_empty() {
+            return vec![captured.event.clone()];
+        }
+        let name = self
+            .local_devices
+            .iter()
+            .find(|d| d.id == captured.device_id)
+            .map_or("", |d| d.name.as_str());
+        self.drag_scroll
+            .translate(rules, captured.device_id, name, captured.event.clone())
+    }
+
+    /// Apply the controlled screen's `pointer_scale` to forwarded motion.
+    fn scale_pointer(&mut self, peer_id: MachineId, events: &mut [InputEvent]) {
+        let Some(session) = self.sessions.get(&peer_id) else {
+            return;
+        };
+        let Some(scale) = self
+            .config
+            .screens
+            .iter()
+            .find(|sc| sc.name == session.name)
+            .map(|sc| sc.pointer_scale)
+            .filter(|scale| !scale.is_identity())
+        else {
+            return;
+        };
+        for event in events {
+            if let InputEvent::MouseMove { dx, dy } = event {
+                (*dx, *dy) = scale.apply(*dx, *dy, &mut self.pointer_carry);
             }
         }
     }
 
     async fn release_control(&mut self) {
+        self.record_control_time();
         if let Some(peer_id) = self.controlling.take() {
-            info!(peer = %peer_id, "releasing control");
+            self.drag_scroll = DragScroll::default();
+            self.pointer_carry = (0.0, 0.0);
+            info!(machine_id = %peer_id, "releasing control");
             if let Some(session) = self.sessions.get_mut(&peer_id) {
                 let edge = ScreenEdge::Left; // Default edge for release
                 let _ = session.leave(edge, 0).await;
             }
-            let _ = self.capture.release().await;
-
-            // Reset cursor to center
-            self.cursor_x = i32::try_from(self.screen.width / 2).unwrap_or(960);
-            self.cursor_y = i32::try_from(self.screen.height / 2).unwrap_or(540);
+            self.release_input().await;
+            self.center_cursor();
         }
     }
 
+    /// Put the virtual cursor back in the middle of the local screen, away
+    /// from the edge it left through.
+    fn center_cursor(&mut self) {
+        self.cursor_x = i32::try_from(self.screen.width / 2).unwrap_or(960);
+        self.cursor_y = i32::try_from(self.screen.height / 2).unwrap_or(540);
+    }
+
     fn update_hotkey_state(&mut self, event: &InputEvent) {
         if let InputEvent::Key { code, state } = event {
             match state {
@@ -590,9 +3738,23 @@ impl Daemon {
         }
     }
 
-    fn is_release_hotkey_pressed(&self) -> bool {
-        let hotkey = &self.config.input.release_hotkey;
-        if hotkey.len() > self.hotkey_pressed.len() {
+    /// The release hotkey for the screen being controlled: its own, or
+    /// `input.release_hotkey`.
+    fn release_hotkey(&self) -> &[String] {
+        self.controlling
+            .and_then(|id| self.sessions.get(&id))
+            .and_then(|session| {
+                self.config
+                    .screens
+                    .iter()
+                    .find(|sc| sc.name == session.name)
+            })
+            .and_then(|sc| sc.release_hotkey.as_deref())
+            .unwrap_or(&self.config.input.release_hotkey)
+    }
+
+    fn is_hotkey_pressed(&self, hotkey: &[String]) -> bool {
+        if hotkey.is_empty() || hotkey.len() > self.hotkey_pressed.len() {
             return false;
         }
         hotkey.iter().all(|key_name| {
@@ -604,185 +3766,493 @@ impl Daemon {
 
     #[allow(clippy::too_many_lines)]
     async fn handle_peer_control(&mut self, machine_id: MachineId, msg: ControlMessage) {
+        if self.controlled_by == Some(machine_id) {
+            self.controller_seen = Instant::now();
+        }
         match msg {
             ControlMessage::Enter { edge, position } => {
-                info!(peer = %machine_id, ?edge, position, "peer entering");
+                info!(machine_id = %machine_id, ?edge, position, "peer entering");
+                let viewer = self.is_viewer(machine_id);
                 if let Some(session) = self.sessions.get_mut(&machine_id) {
+                    let denied = if viewer {
+                        Some(EnterDeniedReason::Viewer)
+                    } else if self.paused {
+                        Some(EnterDeniedReason::Paused)
+                    } else if !self.emulation_available {
+                        Some(EnterDeniedReason::NoEmulation)
+                    } else {
+                        None
+                    };
+                    if let Some(reason) = denied {
+                        if let Err(e) = session.deny_enter(reason).await {
+                            warn!(error = %e, "failed to send EnterDenied");
+                        }
+                        return;
+                    }
                     match session.handle_enter().await {
                         Ok(()) => {
                             self.controlled_by = Some(machine_id);
+                            self.local_user_until = None;
+                            self.controller_seen = Instant::now();
+                            self.input_episode += 1;
+                            self.draining = None;
+                            self.silent_controller = None;
                             // The edge in Enter is the exit edge on the controller's
                             // screen. We need the opposite edge — where the cursor
                             // enters our screen.
                             let entry_edge = edge.opposite();
                             self.entry_edge = Some(entry_edge);
-                            let pos = i32::try_from(position).unwrap_or(0);
-                            match entry_edge {
-                                ScreenEdge::Left => {
-                                    self.cursor_x = 0;
-                                    self.cursor_y = pos;
-                                }
-                                ScreenEdge::Right => {
-                                    let w = i32::try_from(self.screen.width).unwrap_or(1920);
-                                    self.cursor_x = w - 1;
-                                    self.cursor_y = pos;
-                                }
-                                ScreenEdge::Top => {
-                                    self.cursor_x = pos;
-                                    self.cursor_y = 0;
-                                }
-                                ScreenEdge::Bottom => {
-                                    let h = i32::try_from(self.screen.height).unwrap_or(1080);
-                                    self.cursor_x = pos;
-                                    self.cursor_y = h - 1;
-                                }
-                            }
+                            self.place_cursor_at_edge(entry_edge, position);
                             // Accept input stream asynchronously — the initiator
                             // opened a uni stream but QUIC may not have delivered
                             // the stream frame yet.
-                            self.spawn_accept_input_stream(machine_id);
+                            self.spawn_accept_input_stream(machine_id, self.input_episode);
+                            if self.config.input.verify_on_enter {
+                                self.spawn_input_check(machine_id, self.input_episode);
+                            }
+                            // Tell the new controller at once if it is about to
+                            // type into a lock screen.
+                            self.focus_hint_sent = None;
+                            self.send_focus_hint().await;
                         }
                         Err(e) => {
-                            warn!(error = %e, "failed to handle Enter");
+                            session
+                                .report_error(ErrorCode::EnterRefused, e.to_string())
+                                .await;
+                        }
+                    }
+                }
+            }
+            ControlMessage::EnterAck => {
+                info!(machine_id = %machine_id, "received EnterAck");
+                if let Some(session) = self.sessions.get_mut(&machine_id) {
+                    session.set_controlling();
+                    self.layout.record_crossing(&session.name);
+                }
+                self.counters.crossings += 1;
+                if let Some(session) = self.sessions.get(&machine_id) {
+                    self.metrics
+                        .record_crossing(&session.name, SystemTime::now());
+                }
+                self.record_control_time();
+                self.controlling = Some(machine_id);
+                self.controlling_since = Some(Instant::now());
+                self.wake_display(machine_id).await;
+                self.locate_cursor(machine_id);
+                if let Some(pending) = self.take_pending_entry(machine_id) {
+                    let response = self.perform(machine_id, pending.action).await;
+                    let _ = pending.reply.send(response);
+                }
+            }
+            ControlMessage::EnterDenied { reason } => {
+                if let Some(session) = self.sessions.get_mut(&machine_id) {
+                    session.handle_enter_denied(reason);
+                }
+                if let Some(pending) = self.take_pending_entry(machine_id) {
+                    let _ = pending.reply.send(IpcResponse::error(
+                        ErrorCode::PeerRefused,
+                        format!("peer refused control: {reason:?}"),
+                    ));
+                }
+            }
+            ControlMessage::Error { code, detail } => {
+                if let Some(session) = self.sessions.get_mut(&machine_id) {
+                    session.handle_error(code, &detail);
+                    let name = session.name.clone();
+                    self.record_peer_error(name, code, detail);
+                }
+                // The peer can't inject what we send it; take the cursor
+                // back rather than type into nothing.
+                if self.controlling == Some(machine_id)
+                    && matches!(code, ErrorCode::Inject | ErrorCode::VirtualDevice)
+                {
+                    warn!(machine_id = %machine_id, "peer cannot inject input, taking control back");
+                    self.release_control().await;
+                }
+            }
+            ControlMessage::Leave { edge, position } => {
+                if let Some(session) = self.sessions.get_mut(&machine_id) {
+                    session.handle_leave();
+                }
+                if self.controlled_by == Some(machine_id) {
+                    self.drain_input(machine_id);
+                }
+                // If we were controlling this peer, check adjacency map for
+                // multi-hop: maybe the cursor should go to another screen
+                // rather than returning to us.
+                if self.controlling == Some(machine_id) {
+                    info!(machine_id = %machine_id, ?edge, position, "peer sent Leave");
+                    self.record_control_time();
+                    self.controlling = None;
+                    self.release_input().await;
+
+                    // Check the edge table: where should the cursor go? No
+                    // entry means it comes back to us.
+                    if let Some(next_peer_id) = self.edges.next_hop(machine_id, edge) {
+                        // Multi-hop: transfer control to the next screen
+                        info!(
+                            next_peer = %next_peer_id,
+                            ?edge,
+                            position,
+                            "multi-hop: transferring control to next screen"
+                        );
+                        self.initiate_control(next_peer_id, edge, position).await;
+                    } else {
+                        // No multi-hop target — cursor returns to us.
+                        // Place cursor at the opposite edge.
+                        let bounced = self
+                            .controlling_since
+                            .is_some_and(|t| t.elapsed() < BOUNCE_WINDOW);
+                        if let (true, Some(session)) = (bounced, self.sessions.get(&machine_id)) {
+                            self.layout.record_bounce(&session.name);
+                        }
+                        self.place_cursor_at_edge(edge.opposite(), position);
+                    }
+                }
+            }
+            ControlMessage::DeviceAnnounce(info) => {
+                debug!(machine_id = %machine_id, device = %info.name, "device announced");
+                if !self.emulation_available {
+                    debug!(device = %info.name, "emulation unavailable, not mirroring device");
+                    return;
+                }
+                if self.is_viewer(machine_id) {
+                    debug!(device = %info.name, "viewer peer, not mirroring device");
+                    return;
+                }
+                let limit = self.config.limits.peer_devices;
+                if let Some(session) = self.sessions.get_mut(&machine_id) {
+                    if let Err(e) = info.validate() {
+                        session.reject_device(e).await;
+                        return;
+                    }
+                    let known = session.remote_devices.iter().any(|d| d.id == info.id);
+                    if !known && session.remote_devices.len() >= limit {
+                        session
+                            .reject_device(format!(
+                                "device {:?} is over the limit of {limit} devices per peer",
+                                info.name
+                            ))
+                            .await;
+                        return;
+                    }
+                    if known {
+                        session.remote_devices.retain(|d| d.id != info.id);
+                    }
+                    self.injector.add_device(machine_id, info.clone());
+                    session.record(format!("peer announced device {}", info.name));
+                    session.remote_devices.push(info);
+                }
+            }
+            ControlMessage::DeviceGone { device_id } => {
+                if let Some(session) = self.sessions.get_mut(&machine_id) {
+                    session.remote_devices.retain(|d| d.id != device_id);
+                    session.record(format!("peer removed device {device_id:?}"));
+                    self.injector.remove_device(machine_id, device_id);
+                }
+            }
+            ControlMessage::Clipboard(msg) => {
+                self.handle_peer_clipboard(machine_id, msg).await;
+            }
+            ControlMessage::PowerState(state) => {
+                if let Some(session) = self.sessions.get_mut(&machine_id) {
+                    info!(
+                        peer = %session.name,
+                        on_battery = state.on_battery,
+                        battery = ?state.battery_percent,
+                        "peer power source"
+                    );
+                    session.power = state;
+                }
+            }
+            ControlMessage::FocusHint(hint) => {
+                self.handle_focus_hint(machine_id, hint);
+            }
+            ControlMessage::RequestControl => {
+                let result = self.control_request_allowed(machine_id);
+                if let Err(e) = &result {
+                    info!(machine_id = %machine_id, reason = %e, "refused control request");
+                } else {
+                    // Hand the cursor back home first; the requester
+                    // enters once it has our answer.
+                    self.flush_motion().await;
+                    self.release_control().await;
+                }
+                if let Some(session) = self.sessions.get_mut(&machine_id) {
+                    session.record(match &result {
+                        Ok(()) => "granted control request".to_string(),
+                        Err(e) => format!("refused control request: {e}"),
+                    });
+                    let msg = ControlMessage::ControlRequestResult {
+                        error: result.err(),
+                    };
+                    let _ = session.control_tx.send_tagged(&msg).await;
+                }
+            }
+            ControlMessage::ControlRequestResult { error } => {
+                if let Some((reply, edge)) = self.pending_control_requests.remove(&machine_id) {
+                    let name = self
+                        .sessions
+                        .get(&machine_id)
+                        .map_or_else(|| machine_id.to_string(), |s| s.name.clone());
+                    let response = match error {
+                        None => {
+                            let position = self.edge_position(edge);
+                            self.initiate_control(machine_id, edge, position).await;
+                            IpcResponse::ControlGranted { screen: name }
                         }
-                    }
+                        Some(e) => IpcResponse::error(
+                            ErrorCode::PeerRefused,
+                            format!("{name} refused control: {e}"),
+                        ),
+                    };
+                    let _ = reply.send(response);
                 }
             }
-            ControlMessage::EnterAck => {
-                info!(peer = %machine_id, "received EnterAck");
+            ControlMessage::TakeControl => {
+                if self.controlled_by == Some(machine_id) {
+                    self.swap_control().await;
+                } else {
+                    debug!(machine_id = %machine_id, "ignoring TakeControl from a peer not controlling us");
+                }
+            }
+            ControlMessage::ConfigPush { layout } => {
+                let result = self.apply_pushed_config(machine_id, &layout);
+                if let Err(e) = &result {
+                    warn!(machine_id = %machine_id, error = %e, "refused pushed layout");
+                }
                 if let Some(session) = self.sessions.get_mut(&machine_id) {
-                    session.set_controlling();
+                    session.record(match &result {
+                        Ok(()) => "applied pushed layout".to_string(),
+                        Err(e) => format!("refused pushed layout: {e}"),
+                    });
+                    let msg = ControlMessage::ConfigPushResult {
+                        error: result.err(),
+                    };
+                    let _ = session.control_tx.send_tagged(&msg).await;
                 }
-                self.controlling = Some(machine_id);
             }
-            ControlMessage::Leave { edge, position } => {
+            ControlMessage::ConfigPushResult { error } => {
+                if let Some(reply) = self.pending_pushes.remove(&machine_id) {
+                    let name = self
+                        .sessions
+                        .get(&machine_id)
+                        .map_or_else(|| machine_id.to_string(), |s| s.name.clone());
+                    let response = match error {
+                        None => IpcResponse::ConfigPushed { name },
+                        Some(e) => IpcResponse::error(
+                            ErrorCode::PeerRefused,
+                            format!("{name} refused the layout: {e}"),
+                        ),
+                    };
+                    let _ = reply.send(response);
+                }
+            }
+            ControlMessage::RunCommand { name } => {
+                let result = self.run_allowed_command(machine_id, &name);
+                if let Err(e) = &result {
+                    warn!(machine_id = %machine_id, command = %name, error = %e, "refused command");
+                }
                 if let Some(session) = self.sessions.get_mut(&machine_id) {
-                    session.handle_leave();
+                    session.record(match &result {
+                        Ok(()) => format!("ran command {name}"),
+                        Err(e) => format!("refused command {name}: {e}"),
+                    });
+                    let msg = ControlMessage::CommandResult {
+                        name,
+                        error: result.err(),
+                    };
+                    let _ = session.control_tx.send_tagged(&msg).await;
+                }
+            }
+            ControlMessage::CommandResult {
+                name: command,
+                error,
+            } => {
+                if let Some(reply) = self.pending_commands.remove(&machine_id) {
+                    let name = self
+                        .sessions
+                        .get(&machine_id)
+                        .map_or_else(|| machine_id.to_string(), |s| s.name.clone());
+                    let response = match error {
+                        None => IpcResponse::CommandStarted { name, command },
+                        Some(e) => IpcResponse::error(
+                            ErrorCode::PeerRefused,
+                            format!("{name} refused the command: {e}"),
+                        ),
+                    };
+                    let _ = reply.send(response);
+                }
+            }
+            ControlMessage::Suspending => {
+                if let Some(session) = self.sessions.get_mut(&machine_id) {
+                    session.handle_suspending();
                 }
                 if self.controlled_by == Some(machine_id) {
                     self.controlled_by = None;
                     self.entry_edge = None;
                 }
-                // If we were controlling this peer, check adjacency map for
-                // multi-hop: maybe the cursor should go to another screen
-                // rather than returning to us.
                 if self.controlling == Some(machine_id) {
-                    info!(peer = %machine_id, ?edge, position, "peer sent Leave");
+                    // The cursor comes home; the sleeping peer can't send Leave.
+                    self.record_control_time();
                     self.controlling = None;
-                    let _ = self.capture.release().await;
-
-                    // Look up the leaving peer's name
-                    let peer_name = self.sessions.get(&machine_id).map(|s| s.name.clone());
-
-                    // Check adjacency map: where should the cursor go?
-                    let next_target = peer_name
-                        .as_ref()
-                        .and_then(|name| self.adjacency.get(&(name.clone(), edge)).cloned());
-
-                    // If the next target is us (local machine), fall through
-                    // to the default cursor-return behavior.
-                    let my_name = self.config.identity.name.clone();
-                    let next_target = next_target.filter(|t| *t != my_name);
-
-                    // Try to find the MachineId for the next target
-                    let next_peer = next_target.and_then(|target_name| {
-                        self.sessions
-                            .iter()
-                            .find(|(_, s)| s.name == target_name)
-                            .map(|(id, _)| *id)
-                    });
-
-                    if let Some(next_peer_id) = next_peer {
-                        // Multi-hop: transfer control to the next screen
-                        info!(
-                            next_peer = %next_peer_id,
-                            ?edge,
-                            position,
-                            "multi-hop: transferring control to next screen"
-                        );
-                        self.initiate_control(next_peer_id, edge, position).await;
+                    self.pending_motion = None;
+                    self.release_input().await;
+                    self.cursor_x = i32::try_from(self.screen.width / 2).unwrap_or(960);
+                    self.cursor_y = i32::try_from(self.screen.height / 2).unwrap_or(540);
+                }
+            }
+            ControlMessage::Resumed => {
+                if let Some(session) = self.sessions.get_mut(&machine_id) {
+                    info!(peer = %session.name, "peer resumed");
+                    session.record("peer resumed");
+                    session.suspended = false;
+                    session.last_keepalive = Instant::now();
+                }
+            }
+            ControlMessage::LocalInput { active } => {
+                if let Some(session) = self.sessions.get_mut(&machine_id) {
+                    if active {
+                        warn!(peer = %session.name, "someone is using the peer's own keyboard or pointer");
+                        session.record("peer's local user active");
                     } else {
-                        // No multi-hop target — cursor returns to us.
-                        // Place cursor at the opposite edge.
-                        let return_edge = edge.opposite();
-                        let pos = i32::try_from(position).unwrap_or(0);
-                        let width = i32::try_from(self.screen.width).unwrap_or(1920);
-                        let height = i32::try_from(self.screen.height).unwrap_or(1080);
-                        match return_edge {
-                            ScreenEdge::Left => {
-                                self.cursor_x = 0;
-                                self.cursor_y = pos;
-                            }
-                            ScreenEdge::Right => {
-                                self.cursor_x = width - 1;
-                                self.cursor_y = pos;
-                            }
-                            ScreenEdge::Top => {
-                                self.cursor_x = pos;
-                                self.cursor_y = 0;
-                            }
-                            ScreenEdge::Bottom => {
-                                self.cursor_x = pos;
-                                self.cursor_y = height - 1;
-                            }
-                        }
+                        info!(peer = %session.name, "peer's local user is idle again");
+                        session.record("peer's local user idle");
                     }
+                    session.local_user_active = active;
                 }
             }
-            ControlMessage::DeviceAnnounce(info) => {
-                debug!(peer = %machine_id, device = %info.name, "device announced");
+            ControlMessage::KeyboardLayout(layout) => {
                 if let Some(session) = self.sessions.get_mut(&machine_id) {
-                    match self.emulation.create_device(&info).await {
-                        Ok(virtual_id) => {
-                            session.device_map.insert(info.id, virtual_id);
-                            session.remote_devices.push(info);
-                        }
-                        Err(e) => {
-                            warn!(error = %e, "failed to create virtual device");
-                        }
-                    }
+                    info!(peer = %session.name, %layout, "peer keyboard layout");
+                    session.record(format!("keyboard layout {layout}"));
+                    session.keyboard_layout = Some(layout);
                 }
             }
-            ControlMessage::DeviceGone { device_id } => {
+            ControlMessage::Limits { control, input } => {
+                if let Some(session) = self.sessions.get_mut(&machine_id) {
+                    debug!(peer = %session.name, control, input, "peer announced message limits");
+                    session.apply_peer_limits(MessageLimits { control, input });
+                }
+            }
+            ControlMessage::BuildInfo { version, commit } => {
                 if let Some(session) = self.sessions.get_mut(&machine_id) {
-                    if let Some(virtual_id) = session.device_map.remove(&device_id) {
-                        let _ = self.emulation.destroy_device(virtual_id).await;
+                    session.record(format!("peer runs {version} ({commit})"));
+                    if version == crate::VERSION {
+                        debug!(peer = %session.name, %version, %commit, "peer build");
+                    } else {
+                        warn!(
+                            peer = %session.name,
+                            peer_version = %version,
+                            version = crate::VERSION,
+                            "peer runs a different cross-control version"
+                        );
                     }
+                    session.software = Some(version);
                 }
             }
             ControlMessage::Ping { seq } => {
                 if let Some(session) = self.sessions.get_mut(&machine_id) {
-                    let _ = session.control_tx.send(&ControlMessage::Pong { seq }).await;
+                    let _ = session
+                        .control_tx
+                        .send_tagged(&ControlMessage::Pong { seq })
+                        .await;
                 }
             }
             ControlMessage::Pong { seq } => {
-                debug!(peer = %machine_id, seq, "received pong");
+                debug!(machine_id = %machine_id, seq, "received pong");
+                if let Some(ping) = self.pending_pings.remove(&seq) {
+                    let screen = self
+                        .sessions
+                        .get(&machine_id)
+                        .map_or_else(|| machine_id.to_string(), |s| s.name.clone());
+                    let rtt_us = u64::try_from(ping.sent.elapsed().as_micros()).unwrap_or(u64::MAX);
+                    let _ = ping.reply.send(IpcResponse::Pong { screen, rtt_us });
+                }
+                if let Some(session) = self.sessions.get_mut(&machine_id) {
+                    if let Some(rtt) = session.handle_pong(seq) {
+                        self.metrics
+                            .record_rtt(&session.name, rtt, SystemTime::now());
+                    }
+                }
             }
             ControlMessage::Bye => {
-                info!(peer = %machine_id, "peer sent Bye");
+                info!(machine_id = %machine_id, "peer sent Bye");
                 self.handle_peer_disconnected(machine_id).await;
             }
             _ => {
-                debug!(peer = %machine_id, ?msg, "unhandled control message");
+                debug!(machine_id = %machine_id, ?msg, "unhandled control message");
             }
         }
     }
 
-    async fn handle_peer_input(&mut self, machine_id: MachineId, msg: InputMessage) {
+    #[instrument(level = "trace", name = "dispatch", skip_all, fields(machine_id = %machine_id))]
+    async fn handle_peer_input(
+        &mut self,
+        machine_id: MachineId,
+        episode: u64,
+        mut msg: InputMessage,
+    ) {
+        if let Some(session) = self.sessions.get_mut(&machine_id) {
+            session.stats.events_received += msg.events.len() as u64;
+        }
+        if episode != self.input_episode {
+            debug!(machine_id = %machine_id, episode, "dropping input from an earlier control episode");
+            self.count_dropped(machine_id, &msg);
+            return;
+        }
+        if let Some(silent) = self
+            .silent_controller
+            .filter(|s| s.peer == machine_id && s.episode == episode)
+            .filter(|_| self.controlled_by.is_none())
+        {
+            self.silent_controller = None;
+            self.controlled_by = Some(machine_id);
+            self.entry_edge = silent.entry_edge;
+            if let Some(session) = self.sessions.get_mut(&machine_id) {
+                info!(peer = %session.name, "silent controller is back, resuming control");
+                session.resume_controlled();
+            }
+        }
+        if self.controlled_by == Some(machine_id) {
+            self.controller_seen = Instant::now();
+        }
         if self.controlled_by != Some(machine_id) {
-            warn!(peer = %machine_id, controlled_by = ?self.controlled_by, "received input from non-controlling peer");
+            warn_throttled!(machine_id = %machine_id, controlled_by = ?self.controlled_by, "received input from non-controlling peer");
+            self.count_dropped(machine_id, &msg);
+            return;
+        }
+        if !self.hold_back_remote_input(machine_id, &mut msg) {
             return;
         }
 
-        // Track cursor position from remote input for barrier detection
-        for event in &msg.events {
-            if let InputEvent::MouseMove { dx, dy } = event {
-                self.cursor_x += dx;
-                self.cursor_y += dy;
-                let width = i32::try_from(self.screen.width).unwrap_or(i32::MAX);
-                let height = i32::try_from(self.screen.height).unwrap_or(i32::MAX);
-                self.cursor_x = self.cursor_x.clamp(0, width - 1);
-                self.cursor_y = self.cursor_y.clamp(0, height - 1);
+        // Track cursor position from remote input for barrier detection.
+        // Absolute moves become relative ones for a virtual pointer without
+        // absolute axes, measured from the tracked position.
+        let absolute =
+            self.injector
+                .supports(machine_id, msg.device_id, DeviceCapability::AbsoluteMouse);
+        let width = i32::try_from(self.screen.width).unwrap_or(i32::MAX);
+        let height = i32::try_from(self.screen.height).unwrap_or(i32::MAX);
+        for event in &mut msg.events {
+            match *event {
+                InputEvent::MouseMove { dx, dy } => {
+                    self.cursor_x = (self.cursor_x + dx).clamp(0, width - 1);
+                    self.cursor_y = (self.cursor_y + dy).clamp(0, height - 1);
+                }
+                InputEvent::MouseMoveAbsolute { x, y } => {
+                    let to_x = scale_unit(x, width);
+                    let to_y = scale_unit(y, height);
+                    if !absolute {
+                        *event = InputEvent::MouseMove {
+                            dx: to_x - self.cursor_x,
+                            dy: to_y - self.cursor_y,
+                        };
+                    }
+                    self.cursor_x = to_x;
+                    self.cursor_y = to_y;
+                }
+                _ => {}
             }
         }
 
@@ -796,11 +4266,18 @@ impl Daemon {
             }
         }
 
+        // A corner with an action is not an edge to leave through.
+        if self.check_corner_action().await {
+            self.inject_peer_input(machine_id, msg);
+            return;
+        }
+
         // Check if cursor has hit ANY screen edge — if so, send Leave to
         // the controller. The Leave message includes the exit edge so the
         // controller can decide where to route the cursor (multi-hop via
         // adjacency map, or return to itself).
-        if let Some(controller_id) = self.controlled_by {
+        // While draining after a Leave, the remaining input is only injected.
+        if let (Some(controller_id), None) = (self.controlled_by, self.draining) {
             for screen_config in &self.config.screens {
                 let edge = screen_config.position.local_edge();
                 // Skip the entry edge while cursor is still on it (suppression).
@@ -808,14 +4285,7 @@ impl Daemon {
                     continue;
                 }
                 if self.screen.is_at_edge(self.cursor_x, self.cursor_y, edge) {
-                    let position = match edge {
-                        ScreenEdge::Left | ScreenEdge::Right => {
-                            u32::try_from(self.cursor_y).unwrap_or(0)
-                        }
-                        ScreenEdge::Top | ScreenEdge::Bottom => {
-                            u32::try_from(self.cursor_x).unwrap_or(0)
-                        }
-                    };
+                    let position = self.edge_position(edge);
                     info!(
                         peer = %controller_id,
                         ?edge,
@@ -833,35 +4303,271 @@ impl Daemon {
             }
         }
 
-        if let Some(session) = self.sessions.get(&machine_id) {
-            if let Some(&virtual_id) = session.device_map.get(&msg.device_id) {
-                for event in &msg.events {
-                    if let Err(e) = self.emulation.inject(virtual_id, event.clone()).await {
-                        warn!(error = %e, "failed to inject event");
+        self.inject_peer_input(machine_id, msg);
+    }
+
+    fn inject_peer_input(&mut self, machine_id: MachineId, msg: InputMessage) {
+        let events = msg.events.len() as u64;
+        let device_id = msg.device_id;
+        if self.injector.inject(machine_id, device_id, msg.events) {
+            self.counters.events_injected += events;
+        } else {
+            debug!(machine_id = %machine_id, device_id = ?device_id, "no virtual device for input device");
+            if let Some(session) = self.sessions.get_mut(&machine_id) {
+                session.stats.events_dropped += events;
+            }
+        }
+    }
+
+    /// Count input from a peer that won't be injected.
+    fn count_dropped(&mut self, machine_id: MachineId, msg: &InputMessage) {
+        if let Some(session) = self.sessions.get_mut(&machine_id) {
+            session.stats.events_dropped += msg.events.len() as u64;
+        }
+    }
+
+    /// The peer controlling us sent Leave. Control is released once the
+    /// episode's input stream has ended, so events the peer sent before the
+    /// Leave are still injected; a peer that never ends the stream is given
+    /// `INPUT_DRAIN_TIMEOUT`.
+    fn drain_input(&mut self, machine_id: MachineId) {
+        let episode = self.input_episode;
+        if self.input_ended == Some(episode) {
+            self.controlled_by = None;
+            self.entry_edge = None;
+            return;
+        }
+        debug!(machine_id = %machine_id, episode, "waiting for input stream to end after Leave");
+        self.draining = Some((machine_id, episode));
+        let event_tx = self.event_tx.clone();
+        supervisor::spawn("input-drain-timeout", async move {
+            tokio::time::sleep(INPUT_DRAIN_TIMEOUT).await;
+            let _ = event_tx
+                .send(DaemonEvent::PeerInputEnded {
+                    machine_id,
+                    episode,
+                })
+                .await;
+        });
+    }
+
+    fn handle_peer_input_ended(&mut self, machine_id: MachineId, episode: u64) {
+        if episode == self.input_episode {
+            self.input_ended = Some(episode);
+        }
+        if self.draining != Some((machine_id, episode)) {
+            return;
+        }
+        self.draining = None;
+        if self.controlled_by == Some(machine_id) {
+            debug!(machine_id = %machine_id, episode, "input drained, releasing");
+            self.controlled_by = None;
+            self.entry_edge = None;
+        }
+    }
+
+    /// Whether clipboard content may be exchanged with a peer under the
+    /// configured scope. Viewer screens always share the clipboard, unless
+    /// their screen entry turns it off.
+    fn clipboard_allowed(&self, peer_id: MachineId) -> bool {
+        if self.paused || !self.config.clipboard.enabled {
+            return false;
+        }
+        let Some(session) = self.sessions.get(&peer_id) else {
+            return false;
+        };
+        if self
+            .config
+            .screens
+            .iter()
+            .any(|sc| sc.name == session.name && !sc.clipboard)
+        {
+            return false;
+        }
+        match self.config.clipboard.scope {
+            ClipboardScope::AllPeers => true,
+            ClipboardScope::ControlledOnly => {
+                self.controlling == Some(peer_id)
+                    || self.controlled_by == Some(peer_id)
+                    || self.is_viewer(peer_id)
+            }
+        }
+    }
+
+    async fn handle_clipboard_changed(&mut self, content: ClipboardContent) {
+        if self.clipboard_applied.as_ref() == Some(&content) {
+            // Our own write of a peer's clipboard; don't echo it back.
+            self.clipboard_applied = None;
+            return;
+        }
+        if content.size() > self.config.clipboard.max_size {
+            debug!(
+                size = content.size(),
+                "clipboard content too large to share"
+            );
+            return;
+        }
+
+        let size = content.size();
+        let offer = ControlMessage::Clipboard(ClipboardMessage::Offer {
+            formats: vec![content.format],
+            size_hint: size as u64,
+        });
+        self.clipboard_local = Some(content);
+
+        let peers: Vec<MachineId> = self
+            .sessions
+            .keys()
+            .copied()
+            .filter(|id| self.clipboard_allowed(*id))
+            .collect();
+        for peer_id in peers {
+            if let Some(session) = self.sessions.get_mut(&peer_id) {
+                if !fits(size, session.send_limits().control) {
+                    debug!(peer = %session.name, size, "clipboard content too large for peer");
+                    continue;
+                }
+                debug!(peer = %session.name, "offering clipboard");
+                if let Err(e) = session.control_tx.send_tagged(&offer).await {
+                    warn!(peer = %session.name, error = %e, "failed to send clipboard offer");
+                }
+            }
+        }
+    }
+
+    async fn handle_peer_clipboard(&mut self, machine_id: MachineId, msg: ClipboardMessage) {
+        if !self.clipboard_allowed(machine_id) {
+            debug!(machine_id = %machine_id, "clipboard message outside scope, ignoring");
+            return;
+        }
+        match msg {
+            ClipboardMessage::Offer { formats, size_hint } => {
+                let limit = self
+                    .sessions
+                    .get(&machine_id)
+                    .map_or(0, |s| s.limits().control);
+                let too_large = usize::try_from(size_hint).map_or(true, |size| {
+                    size > self.config.clipboard.max_size || !fits(size, limit)
+                });
+                if self.clipboard.is_none() || too_large {
+                    return;
+                }
+                let Some(format) = formats
+                    .iter()
+                    .copied()
+                    .find(|f| *f == ClipboardFormat::PlainText)
+                    .or_else(|| formats.first().copied())
+                else {
+                    return;
+                };
+                if let Some(session) = self.sessions.get_mut(&machine_id) {
+                    let request = ControlMessage::Clipboard(ClipboardMessage::Request { format });
+                    let _ = session.control_tx.send_tagged(&request).await;
+                }
+            }
+            ClipboardMessage::Request { format } => {
+                let Some(content) = self.clipboard_local.clone().filter(|c| c.format == format)
+                else {
+                    return;
+                };
+                if let Some(session) = self.sessions.get_mut(&machine_id) {
+                    let data = ControlMessage::Clipboard(ClipboardMessage::Data(content));
+                    if let Err(e) = session.control_tx.send_tagged(&data).await {
+                        warn!(peer = %session.name, error = %e, "failed to send clipboard data");
+                    }
+                }
+            }
+            ClipboardMessage::Data(content) => {
+                if content.size() > self.config.clipboard.max_size {
+                    return;
+                }
+                if let Some(clipboard) = &mut self.clipboard {
+                    self.clipboard_applied = Some(content.clone());
+                    self.clipboard_local = Some(content.clone());
+                    match clipboard.set(content).await {
+                        Ok(()) => debug!(machine_id = %machine_id, "clipboard updated from peer"),
+                        Err(e) => {
+                            self.clipboard_applied = None;
+                            warn!(error = %e, "failed to set clipboard");
+                        }
                     }
                 }
-            } else {
-                debug!(peer = %machine_id, device_id = ?msg.device_id, "no virtual device for input device");
             }
         }
     }
 
     async fn handle_peer_disconnected(&mut self, machine_id: MachineId) {
         if self.controlling == Some(machine_id) {
+            warn!(machine_id = %machine_id, "controlled peer disconnected, taking back input");
+            self.record_control_time();
             self.controlling = None;
-            let _ = self.capture.release().await;
+            self.drag_scroll = DragScroll::default();
+            self.pointer_carry = (0.0, 0.0);
+            self.release_input().await;
+            self.center_cursor();
         }
         if self.controlled_by == Some(machine_id) {
             self.controlled_by = None;
             self.entry_edge = None;
+            self.draining = None;
         }
 
-        if let Some(mut session) = self.sessions.remove(&machine_id) {
-            // Clean up virtual devices
-            for (_, virtual_id) in session.device_map.drain() {
-                let _ = self.emulation.destroy_device(virtual_id).await;
+        for reply in [
+            self.pending_pushes.remove(&machine_id),
+            self.pending_commands.remove(&machine_id),
+            self.pending_control_requests
+                .remove(&machine_id)
+                .map(|(reply, _)| reply),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            let _ = reply.send(IpcResponse::error(
+                ErrorCode::NotConnected,
+                "peer disconnected",
+            ));
+        }
+        let lost: Vec<u64> = self
+            .pending_pings
+            .iter()
+            .filter(|(_, p)| p.peer == machine_id)
+            .map(|(seq, _)| *seq)
+            .collect();
+        for seq in lost {
+            if let Some(ping) = self.pending_pings.remove(&seq) {
+                let _ = ping.reply.send(IpcResponse::error(
+                    ErrorCode::NotConnected,
+                    "peer disconnected",
+                ));
             }
+        }
+        if let Some(pending) = self.take_pending_entry(machine_id) {
+            let _ = pending.reply.send(IpcResponse::error(
+                ErrorCode::NotConnected,
+                "peer disconnected",
+            ));
+        }
+        if let Some(session) = self.sessions.remove(&machine_id) {
+            self.rebuild_edges();
+            // Clean up virtual devices
+            self.injector.remove_peer(machine_id);
             info!(peer = %session.name, "peer session removed");
+
+            let just_changed = [self.resumed_at, self.network_changed_at]
+                .into_iter()
+                .flatten()
+                .any(|t| t.elapsed() < RESUME_RECONNECT_WINDOW);
+            if just_changed {
+                if let Some(sc) = self
+                    .config
+                    .screens
+                    .iter()
+                    .find(|sc| sc.name == session.name)
+                {
+                    info!(peer = %session.name, "reconnecting");
+                    self.connect_peer(sc);
+                }
+            }
         }
     }
 
@@ -876,9 +4582,17 @@ impl Daemon {
             }
         }
 
+        if let Some(discovery) = &mut self.discovery {
+            let _ = discovery.stop_browsing().await;
+            let _ = discovery.stop_advertising().await;
+        }
+
+        self.record_control_time();
+        self.save_metrics();
+
         // Shut down capture and emulation
         self.capture.shutdown().await?;
-        self.emulation.shutdown().await?;
+        self.injector.shutdown().await?;
 
         // Close transport
         self.transport.close();
@@ -887,44 +4601,307 @@ impl Daemon {
         Ok(())
     }
 
+    /// Set the clipboard backend (called before run).
+    pub fn set_clipboard(&mut self, clipboard: Box<dyn ClipboardProvider>) {
+        self.clipboard = Some(clipboard);
+    }
+
+    /// Set the discovery backend (called before run). Ignored when
+    /// `daemon.discovery` is off.
+    pub fn set_discovery(&mut self, discovery: Box<dyn Discovery>) {
+        self.discovery = Some(discovery);
+    }
+
+    /// Ask `hook` whether to admit each inbound peer, instead of
+    /// `security.auth_command` (called before run).
+    pub fn set_auth_hook(&mut self, hook: Arc<dyn AuthHook>) {
+        self.auth = Some(hook);
+    }
+
+    /// Keep daily usage totals in the file at `path`, loading what it
+    /// already holds (called before run).
+    pub fn set_metrics_path(&mut self, path: PathBuf) {
+        self.metrics = MetricsHistory::load(&path);
+        self.metrics_path = Some(path);
+    }
+
+    /// Record where the config file lives, so layouts pushed by peers are
+    /// saved back to it.
+    pub fn set_config_path(&mut self, path: PathBuf) {
+        self.config_path = Some(path);
+    }
+
+    /// Record the directory holding the persistent `denylist`, so bans are
+    /// written where the next start loads them from (called before run).
+    pub fn set_denylist_dir(&mut self, dir: PathBuf) {
+        self.denylist_dir = Some(dir);
+    }
+
     /// Set the local device list (called before run, after enumeration).
     pub fn set_local_devices(&mut self, devices: Vec<DeviceInfo>) {
         self.local_devices = devices;
     }
+
+    fn session_history(&self, peer: &str) -> IpcResponse {
+        let Some(session) = self
+            .sessions
+            .values()
+            .find(|s| s.name == peer || s.machine_id.to_string() == peer)
+        else {
+            return IpcResponse::error(ErrorCode::UnknownPeer, format!("no session with {peer}"));
+        };
+        let events = session
+            .history()
+            .map(|entry| HistoryEvent {
+                at_ms: epoch_ms(entry.at),
+                state: entry.state.to_string(),
+                event: entry.event.clone(),
+            })
+            .collect();
+        IpcResponse::History(SessionHistory {
+            name: session.name.clone(),
+            machine_id: session.machine_id,
+            session: session.id.clone(),
+            state: session.state.to_string(),
+            events,
+        })
+    }
+
+    /// Report the traffic counters of `peer`'s session, or of all sessions,
+    /// then reset them if asked.
+    fn session_stats(&mut self, peer: Option<&str>, reset: bool) -> IpcResponse {
+        let mut reports = Vec::new();
+        for session in self.sessions.values_mut() {
+            if peer.is_some_and(|p| session.name != p && session.machine_id.to_string() != p) {
+                continue;
+            }
+            let stats = &session.stats;
+            let elapsed = stats.since.elapsed().unwrap_or_default();
+            reports.push(SessionStatsReport {
+                name: session.name.clone(),
+                machine_id: session.machine_id,
+                session: session.id.clone(),
+                since_ms: epoch_ms(stats.since),
+                elapsed_ms: u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX),
+                events_sent: stats.events_sent,
+                events_received: stats.events_received,
+                events_dropped: stats.events_dropped,
+                bytes_sent: stats.bytes_sent(),
+                bytes_received: stats.bytes_received(),
+                rtt_us: stats
+                    .rtt()
+                    .map(|rtt| u64::try_from(rtt.as_micros()).unwrap_or(u64::MAX))
+                    .collect(),
+            });
+            if reset {
+                session.stats.reset();
+                session.record("statistics reset");
+            }
+        }
+        if let (Some(peer), true) = (peer, reports.is_empty()) {
+            return IpcResponse::error(ErrorCode::UnknownPeer, format!("no session with {peer}"));
+        }
+        reports.sort_by(|a, b| a.name.cmp(&b.name));
+        IpcResponse::Stats(reports)
+    }
+
+    fn device_report(&self) -> DeviceReport {
+        let entry = |info: &DeviceInfo, events| DeviceEntry {
+            id: info.id,
+            name: info.name.clone(),
+            capabilities: info.capabilities.clone(),
+            events,
+        };
+        let captured = self
+            .local_devices
+            .iter()
+            .map(|info| {
+                let events = self.captured_events.get(&info.id).copied();
+                entry(info, events.unwrap_or(0))
+            })
+            .collect();
+
+        let mut remote: BTreeMap<String, (Vec<DeviceEntry>, u64)> = BTreeMap::new();
+        for (peer, info, events) in self.injector.devices() {
+            let name = self
+                .sessions
+                .get(&peer)
+                .map_or_else(|| peer.to_string(), |s| s.name.clone());
+            remote.entry(name).or_default().0.push(entry(info, events));
+        }
+        for session in self.sessions.values() {
+            if session.devices_rejected > 0 {
+                remote.entry(session.name.clone()).or_default().1 = session.devices_rejected;
+            }
+        }
+        DeviceReport {
+            captured,
+            remote: remote
+                .into_iter()
+                .map(|(peer, (mut devices, rejected))| {
+                    devices.sort_by_key(|d| d.id.0);
+                    PeerDevices {
+                        peer,
+                        devices,
+                        rejected,
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Whether `size` bytes of clipboard content fit in a control message of at
+/// most `limit` bytes, leaving room for the message's own fields.
+fn fits(size: usize, limit: u32) -> bool {
+    const OVERHEAD: usize = 64;
+    usize::try_from(limit).map_or(true, |limit| size + OVERHEAD <= limit)
+}
+
+/// Accept inbound connections until the transport closes, handshaking each
+/// in its own task so the event loop never blocks.
+async fn accept_loop(
+    transport: Arc<dyn Transport>,
+    event_tx: mpsc::Sender<DaemonEvent>,
+    ours: Identity,
+    auth: Option<Arc<dyn AuthHook>>,
+) {
+    loop {
+        match transport.accept().await {
+            Ok(conn) => {
+                let tx = event_tx.clone();
+                let ours = ours.clone();
+                let auth = auth.clone();
+                supervisor::spawn("inbound-handshake", async move {
+                    let remote = conn.remote_address();
+                    match PeerSession::establish(conn, Role::Responder, ours, auth.as_deref()).await
+                    {
+                        Ok(session) => {
+                            info!(
+                                peer = %session.name,
+                                session = %session.id,
+                                remote = %remote,
+                                "inbound handshake complete"
+                            );
+                            let _ = tx.send(DaemonEvent::SessionReady { session }).await;
+                        }
+                        Err(e) => {
+                            warn_throttled!(
+                                remote = %remote,
+                                error = %e,
+                                "inbound handshake failed"
+                            );
+                            if let Some((code, detail)) = remote_error(&e) {
+                                let peer = remote.to_string();
+                                let _ =
+                                    tx.send(DaemonEvent::PeerError { peer, code, detail }).await;
+                            }
+                        }
+                    }
+                });
+            }
+            Err(e) => {
+                debug!(error = %e, "accept loop ending");
+                break;
+            }
+        }
+    }
 }
 
-/// Perform a responder handshake in a background task (accept bidi stream,
-/// read Hello, send Welcome, announce devices).
-async fn perform_handshake_responder(
-    conn: cross_control_protocol::PeerConnection,
-    our_id: MachineId,
-    our_name: &str,
-    our_screen: &ScreenGeometry,
-    local_devices: &[DeviceInfo],
-) -> Result<PeerSession, DaemonError> {
-    let (control_tx, control_rx) = conn.accept_control_stream().await?;
-    let mut session = PeerSession::new(conn, control_tx, control_rx);
-    session
-        .handshake_responder(our_id, our_name, our_screen)
-        .await?;
-    session.announce_devices(local_devices).await?;
-    Ok(session)
+/// What a peer said when it rejected a handshake, if it said anything.
+fn remote_error(error: &DaemonError) -> Option<(ErrorCode, String)> {
+    match error {
+        DaemonError::Protocol(ProtocolError::Remote { code, detail }) => {
+            Some((*code, detail.clone()))
+        }
+        _ => None,
+    }
+}
+
+/// Refusal for a request that needs `feature`, sent to a peer whose
+/// protocol predates it.
+fn unsupported(session: &PeerSession, feature: ProtocolFeature) -> IpcResponse {
+    IpcResponse::error(
+        ErrorCode::VersionMismatch,
+        format!(
+            "{} speaks protocol {}, which has no {} (added in {})",
+            session.name,
+            session.version,
+            feature.description(),
+            feature.since()
+        ),
+    )
+}
+
+/// Features for a log line, e.g. `"focus hints, swap hotkey"`.
+fn describe_features(features: &[ProtocolFeature]) -> String {
+    if features.is_empty() {
+        return "none".to_string();
+    }
+    features
+        .iter()
+        .map(|feature| feature.description())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Remove the screen called `name` from a layout, with any adjacency
+/// naming it.
+fn remove_screen(
+    screens: &mut Vec<ScreenConfig>,
+    adjacency: &mut Vec<ScreenAdjacency>,
+    name: &str,
+) {
+    screens.retain(|sc| sc.name != name);
+    adjacency.retain(|adj| adj.screen != name && adj.neighbor != name);
+}
+
+/// Milliseconds since the Unix epoch, as reported over IPC.
+fn epoch_ms(at: SystemTime) -> u64 {
+    at.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| u64::try_from(d.as_millis()).unwrap_or(u64::MAX))
+}
+
+/// A normalised coordinate (0.0..1.0) in pixels along an axis of `length`.
+fn scale_unit(unit: f64, length: i32) -> i32 {
+    let unit = if unit.is_finite() {
+        unit.clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    // In range: `unit` is clamped and `length` fits an i32.
+    #[allow(clippy::cast_possible_truncation)]
+    let pixel = (unit * f64::from(length)).round() as i32;
+    pixel.clamp(0, length - 1)
+}
+
+/// Start a `[commands]` entry's `run` list without a shell, with its output
+/// discarded. A non-zero exit is only logged.
+fn spawn_command(name: &str, run: &[String]) -> Result<(), String> {
+    let (program, args) = run
+        .split_first()
+        .ok_or_else(|| format!("{name} has an empty run list"))?;
+    let mut child = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .map_err(|e| format!("failed to start {name}: {e}"))?;
+    let name = name.to_string();
+    supervisor::spawn("remote-command", async move {
+        match child.wait().await {
+            Ok(status) if status.success() => {}
+            Ok(status) => warn!(command = %name, %status, "command failed"),
+            Err(e) => warn!(command = %name, error = %e, "failed to wait for command"),
+        }
+    });
+    Ok(())
 }
 
-/// Perform an initiator handshake in a background task (open bidi stream,
-/// send Hello, read Welcome, announce devices).
-async fn perform_handshake_initiator(
-    conn: cross_control_protocol::PeerConnection,
-    our_id: MachineId,
-    our_name: &str,
-    our_screen: &ScreenGeometry,
-    local_devices: &[DeviceInfo],
-) -> Result<PeerSession, DaemonError> {
-    let (control_tx, control_rx) = conn.open_control_stream().await?;
-    let mut session = PeerSession::new(conn, control_tx, control_rx);
-    session
-        .handshake_initiator(our_id, our_name, our_screen)
-        .await?;
-    session.announce_devices(local_devices).await?;
-    Ok(session)
+/// How long to wait before grabbing again after `failures` failed grabs in
+/// a row.
+fn grab_retry_delay(failures: u32) -> Duration {
+    let doublings = failures.saturating_sub(1).min(16);
+    (GRAB_RETRY_MIN * 2u32.pow(doublings)).min(GRAB_RETRY_MAX)
 }
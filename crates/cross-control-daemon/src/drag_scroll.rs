@@ -0,0 +1,236 @@
+//! Button-held scrolling for input forwarded to a peer.
+//!
+//! Trackballs and some mice scroll by holding a button and moving the
+//! pointer, a mode that usually lives in the local driver or desktop
+//! settings and so never reaches the peer. An `[[input.drag_scroll]]` rule
+//! recreates it in the forwarding path: while the rule's button is held on a
+//! matching device, motion is sent as scroll steps instead. A press and
+//! release with no scrolling in between is sent as a plain click.
+
+use cross_control_types::{
+    ButtonState, DeviceId, InputEvent, MouseButton, ScrollAxis, ScrollDirection,
+};
+
+use crate::config::DragScrollRule;
+
+/// A drag in progress.
+struct Drag {
+    rule: DragScrollRule,
+    device_id: DeviceId,
+    /// Motion not yet turned into whole scroll steps.
+    x: i32,
+    y: i32,
+    scrolled: bool,
+}
+
+/// Applies [`DragScrollRule`]s to a stream of captured events.
+#[derive(Default)]
+pub struct DragScroll {
+    drag: Option<Drag>,
+}
+
+impl DragScroll {
+    /// Translate one event from `device_id` (named `device_name`), returning
+    /// the events to send in its place.
+    pub fn translate(
+        &mut self,
+        rules: &[DragScrollRule],
+        device_id: DeviceId,
+        device_name: &str,
+        event: InputEvent,
+    ) -> Vec<InputEvent> {
+        match event {
+            InputEvent::MouseButton {
+                button,
+                state: ButtonState::Pressed,
+            } => {
+                if let Some(rule) = rules
+                    .iter()
+                    .find(|rule| rule.button == button && rule.matches(device_name))
+                {
+                    self.drag = Some(Drag {
+                        rule: rule.clone(),
+                        device_id,
+                        x: 0,
+                        y: 0,
+                        scrolled: false,
+                    });
+                    return Vec::new();
+                }
+            }
+            InputEvent::MouseButton {
+                button,
+                state: ButtonState::Released,
+            } if self.is_dragging(device_id, Some(button)) => {
+                let drag = self.drag.take().expect("drag in progress");
+                return if drag.scrolled {
+                    Vec::new()
+                } else {
+                    click(button)
+                };
+            }
+            InputEvent::MouseMove { dx, dy } if self.is_dragging(device_id, None) => {
+                let drag = self.drag.as_mut().expect("drag in progress");
+                return drag.scroll(dx, dy);
+            }
+            _ => {}
+        }
+        vec![event]
+    }
+
+    fn is_dragging(&self, device_id: DeviceId, button: Option<MouseButton>) -> bool {
+        self.drag.as_ref().is_some_and(|drag| {
+            drag.device_id == device_id && button.map_or(true, |b| b == drag.rule.button)
+        })
+    }
+}
+
+impl Drag {
+    /// Accumulate motion and emit a scroll event per axis for each whole
+    /// step.
+    fn scroll(&mut self, dx: i32, dy: i32) -> Vec<InputEvent> {
+        let step = i32::try_from(self.rule.step.max(1)).unwrap_or(i32::MAX);
+        self.x = self.x.saturating_add(dx);
+        self.y = self.y.saturating_add(dy);
+        let steps_x = self.x / step;
+        let steps_y = self.y / step;
+        self.x -= steps_x * step;
+        self.y -= steps_y * step;
+
+        // Dragging down scrolls down, like the wheel; `natural` reverses it.
+        let sign = if self.rule.natural { -1 } else { 1 };
+        let mut events = Vec::new();
+        for (axis, steps) in [
+            (ScrollAxis::Vertical, -steps_y * sign),
+            (ScrollAxis::Horizontal, steps_x * sign),
+        ] {
+            if steps == 0 {
+                continue;
+            }
+            self.scrolled = true;
+            events.push(InputEvent::Scroll {
+                axis,
+                direction: if steps > 0 {
+                    ScrollDirection::Positive
+                } else {
+                    ScrollDirection::Negative
+                },
+                amount: f64::from(steps.unsigned_abs()),
+            });
+        }
+        events
+    }
+}
+
+fn click(button: MouseButton) -> Vec<InputEvent> {
+    [ButtonState::Pressed, ButtonState::Released]
+        .into_iter()
+        .map(|state| InputEvent::MouseButton { button, state })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACKBALL: DeviceId = DeviceId(3);
+
+    fn rule(natural: bool) -> DragScrollRule {
+        DragScrollRule {
+            button: MouseButton::Middle,
+            devices: vec!["trackball".to_string()],
+            step: 10,
+            natural,
+        }
+    }
+
+    fn button(state: ButtonState) -> InputEvent {
+        InputEvent::MouseButton {
+            button: MouseButton::Middle,
+            state,
+        }
+    }
+
+    fn scroll(axis: ScrollAxis, direction: ScrollDirection, amount: f64) -> InputEvent {
+        InputEvent::Scroll {
+            axis,
+            direction,
+            amount,
+        }
+    }
+
+    #[test]
+    fn drag_becomes_scroll_steps() {
+        let rules = [rule(false)];
+        let mut drag = DragScroll::default();
+        let mut send = |event| drag.translate(&rules, TRACKBALL, "Kensington Trackball", event);
+
+        assert!(send(button(ButtonState::Pressed)).is_empty());
+        assert!(send(InputEvent::MouseMove { dx: 0, dy: 6 }).is_empty());
+        assert_eq!(
+            send(InputEvent::MouseMove { dx: 4, dy: 18 }),
+            [scroll(ScrollAxis::Vertical, ScrollDirection::Negative, 2.0)]
+        );
+        assert_eq!(
+            send(InputEvent::MouseMove { dx: -25, dy: 0 }),
+            [scroll(
+                ScrollAxis::Horizontal,
+                ScrollDirection::Negative,
+                2.0
+            )]
+        );
+        assert!(send(button(ButtonState::Released)).is_empty());
+        assert_eq!(
+            send(InputEvent::MouseMove { dx: 5, dy: 5 }),
+            [InputEvent::MouseMove { dx: 5, dy: 5 }]
+        );
+    }
+
+    #[test]
+    fn natural_reverses_direction() {
+        let rules = [rule(true)];
+        let mut drag = DragScroll::default();
+        drag.translate(&rules, TRACKBALL, "trackball", button(ButtonState::Pressed));
+        assert_eq!(
+            drag.translate(
+                &rules,
+                TRACKBALL,
+                "trackball",
+                InputEvent::MouseMove { dx: 0, dy: 10 }
+            ),
+            [scroll(ScrollAxis::Vertical, ScrollDirection::Positive, 1.0)]
+        );
+    }
+
+    #[test]
+    fn press_without_scrolling_is_a_click() {
+        let rules = [rule(false)];
+        let mut drag = DragScroll::default();
+        let mut send = |event| drag.translate(&rules, TRACKBALL, "USB Trackball", event);
+
+        assert!(send(button(ButtonState::Pressed)).is_empty());
+        assert!(send(InputEvent::MouseMove { dx: 2, dy: -3 }).is_empty());
+        assert_eq!(
+            send(button(ButtonState::Released)),
+            [button(ButtonState::Pressed), button(ButtonState::Released)]
+        );
+    }
+
+    #[test]
+    fn other_devices_pass_through() {
+        let rules = [rule(false)];
+        let mut drag = DragScroll::default();
+        let pressed = button(ButtonState::Pressed);
+        assert_eq!(
+            drag.translate(&rules, DeviceId(1), "Office Mouse", pressed.clone()),
+            [pressed]
+        );
+
+        drag.translate(&rules, TRACKBALL, "trackball", button(ButtonState::Pressed));
+        let motion = InputEvent::MouseMove { dx: 30, dy: 30 };
+        assert_eq!(
+            drag.translate(&rules, DeviceId(1), "Office Mouse", motion.clone()),
+            [motion]
+        );
+    }
+}
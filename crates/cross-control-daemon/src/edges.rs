@@ -0,0 +1,136 @@
+//! Edge routing precomputed from the layout and the connected peers.
+//!
+//! The layout names screens, but the cursor moves between sessions. Matching
+//! names on every pointer event is wasted work, so [`EdgeTable`] resolves
+//! the layout to machine IDs once, whenever a session comes or goes or the
+//! layout changes, and the hot paths only look up edges.
+
+use std::collections::HashMap;
+
+use cross_control_types::{MachineId, ScreenEdge};
+
+use crate::config::{Config, ScreenMode};
+
+/// Where the cursor goes from each edge, by machine ID.
+#[derive(Debug, Default)]
+pub struct EdgeTable {
+    /// Peers entered by crossing one of our edges, in `screens` order.
+    /// Viewer screens are left out.
+    crossings: Vec<(ScreenEdge, MachineId)>,
+    /// The peer entered when the cursor leaves a peer through an edge.
+    /// Missing when it comes back to us or its neighbour isn't connected.
+    hops: HashMap<(MachineId, ScreenEdge), MachineId>,
+}
+
+impl EdgeTable {
+    /// Resolve `config`'s screens and adjacency against the connected
+    /// `peers`, given by machine ID and name.
+    pub fn build<'a>(
+        config: &Config,
+        peers: impl IntoIterator<Item = (MachineId, &'a str)>,
+    ) -> Self {
+        let mut ids: HashMap<&str, MachineId> = HashMap::new();
+        for (id, name) in peers {
+            ids.entry(name).or_insert(id);
+        }
+        // A peer using our own name is never a hop target.
+        ids.remove(config.identity.name.as_str());
+
+        let crossings = config
+            .screens
+            .iter()
+            .filter(|sc| sc.mode != ScreenMode::Viewer)
+            .filter_map(|sc| Some((sc.position.local_edge(), *ids.get(sc.name.as_str())?)))
+            .collect();
+        let hops = build_adjacency(config)
+            .into_iter()
+            .filter_map(|((screen, edge), neighbor)| {
+                let from = *ids.get(screen.as_str())?;
+                let to = *ids.get(neighbor.as_str())?;
+                Some(((from, edge), to))
+            })
+            .collect();
+        Self { crossings, hops }
+    }
+
+    /// Our edges and the peer beyond each, in `screens` order.
+    pub fn crossings(&self) -> impl Iterator<Item = (ScreenEdge, MachineId)> + '_ {
+        self.crossings.iter().copied()
+    }
+
+    /// The peer to enter when the cursor leaves `from` through `edge`, or
+    /// `None` if it comes back to us.
+    pub fn next_hop(&self, from: MachineId, edge: ScreenEdge) -> Option<MachineId> {
+        self.hops.get(&(from, edge)).copied()
+    }
+}
+
+/// Build the full adjacency map: `(screen_name, edge) → neighbor_name`.
+fn build_adjacency(config: &Config) -> HashMap<(String, ScreenEdge), String> {
+    // 1) From config.screens: our own direct neighbors.
+    let my_name = &config.identity.name;
+    let mut adjacency: HashMap<(String, ScreenEdge), String> = HashMap::new();
+    for sc in &config.screens {
+        let edge = sc.position.local_edge();
+        adjacency.insert((my_name.clone(), edge), sc.name.clone());
+        // Auto-generate inverse: neighbor → opposite edge → us
+        adjacency.insert((sc.name.clone(), edge.opposite()), my_name.clone());
+    }
+    // 2) From config.screen_adjacency: remote edges.
+    for adj in &config.screen_adjacency {
+        let edge = adj.position.local_edge();
+        adjacency.insert((adj.screen.clone(), edge), adj.neighbor.clone());
+        // Auto-generate inverse
+        adjacency.insert((adj.neighbor.clone(), edge.opposite()), adj.screen.clone());
+    }
+    adjacency
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config::from_toml(
+            r#"
+            [identity]
+            name = "desk"
+
+            [[screens]]
+            name = "laptop"
+            position = "Right"
+
+            [[screens]]
+            name = "tv"
+            position = "Left"
+            mode = "Viewer"
+
+            [[screen_adjacency]]
+            screen = "laptop"
+            position = "Right"
+            neighbor = "tablet"
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn resolves_connected_peers_only() {
+        let (laptop, tv, tablet) = (MachineId::new(), MachineId::new(), MachineId::new());
+        let table = EdgeTable::build(&config(), [(laptop, "laptop"), (tv, "tv")]);
+        assert_eq!(
+            table.crossings().collect::<Vec<_>>(),
+            [(ScreenEdge::Right, laptop)],
+            "viewers are never crossed into"
+        );
+        assert_eq!(table.next_hop(laptop, ScreenEdge::Right), None);
+        assert_eq!(table.next_hop(laptop, ScreenEdge::Left), None, "back to us");
+
+        let table = EdgeTable::build(
+            &config(),
+            [(laptop, "laptop"), (tv, "tv"), (tablet, "tablet")],
+        );
+        assert_eq!(table.next_hop(laptop, ScreenEdge::Right), Some(tablet));
+        assert_eq!(table.next_hop(tablet, ScreenEdge::Left), Some(laptop));
+    }
+}
@@ -1,5 +1,6 @@
 //! Daemon errors.
 
+use cross_control_types::ErrorCode;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -28,3 +29,20 @@ pub enum DaemonError {
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
+
+impl DaemonError {
+    /// Machine-readable cause, taken from the underlying protocol or input
+    /// error where there is one.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Config(_) => ErrorCode::Config,
+            Self::AlreadyRunning => ErrorCode::AlreadyRunning,
+            Self::NotRunning => ErrorCode::NotRunning,
+            Self::Protocol(e) => e.code(),
+            Self::Input(e) => e.code(),
+            Self::Clipboard(_) => ErrorCode::Clipboard,
+            Self::Discovery(_) => ErrorCode::Discovery,
+            Self::Other(_) => ErrorCode::Internal,
+        }
+    }
+}
@@ -0,0 +1,65 @@
+//! Returning input to the local user when the daemon dies.
+//!
+//! While this machine controls a peer its keyboard and mouse are grabbed, so
+//! a daemon that gets stuck at the wrong moment can leave the user locked
+//! out. Two things guard against that:
+//!
+//! - [`ReleaseOnDrop`] releases devices when the event loop unwinds from a
+//!   panic, not just when it returns normally.
+//! - The CLI treats a second SIGINT or SIGTERM as "exit now", for when the
+//!   event loop is too stuck to handle the first one.
+//!
+//! A daemon that is killed outright needs nothing more: the kernel drops
+//! evdev grabs when the process exits.
+
+use cross_control_input::ReleaseHandle;
+use tracing::{info, warn};
+
+/// Releases input devices when dropped, including during a panic unwind.
+pub struct ReleaseOnDrop(Option<ReleaseHandle>);
+
+impl ReleaseOnDrop {
+    pub fn new(release: Option<ReleaseHandle>) -> Self {
+        Self(release)
+    }
+}
+
+impl Drop for ReleaseOnDrop {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            warn!("event loop panicked, releasing input devices");
+        }
+        if let Some(release) = &self.0 {
+            release();
+            info!("input devices released");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn counting_release() -> (ReleaseHandle, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        let release: ReleaseHandle = Arc::new(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        (release, calls)
+    }
+
+    #[test]
+    fn panic_releases_devices() {
+        let (release, calls) = counting_release();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || {
+            let _guard = ReleaseOnDrop::new(Some(release));
+            panic!("event loop bug");
+        }));
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,269 @@
+//! Host firewall checks for `cross-control doctor`.
+//!
+//! Looks for the firewalls users most often trip over: ufw and firewalld on
+//! Linux, Windows Defender Firewall on Windows. Each one that is active is
+//! asked whether the daemon's UDP port is open, and a closed port comes with
+//! the command that opens it.
+
+use std::process::Command;
+
+/// A host firewall.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Firewall {
+    Ufw,
+    Firewalld,
+    Windows,
+}
+
+impl Firewall {
+    /// The command that opens UDP `port`.
+    pub fn allow_rule(self, port: u16) -> String {
+        match self {
+            Self::Ufw => format!("sudo ufw allow {port}/udp"),
+            Self::Firewalld => format!(
+                "sudo firewall-cmd --add-port={port}/udp --permanent && sudo firewall-cmd --reload"
+            ),
+            Self::Windows => format!(
+                "netsh advfirewall firewall add rule name=\"cross-control {port}\" \
+                 dir=in action=allow protocol=UDP localport={port}"
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for Firewall {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Ufw => "ufw",
+            Self::Firewalld => "firewalld",
+            Self::Windows => "Windows Firewall",
+        })
+    }
+}
+
+/// Whether an active firewall lets the port through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortStatus {
+    Open,
+    Blocked,
+    /// The firewall is active but its rules can't be read, e.g. `ufw
+    /// status` without root.
+    Unknown(String),
+}
+
+/// One active firewall's verdict on a port.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirewallCheck {
+    pub firewall: Firewall,
+    pub status: PortStatus,
+}
+
+/// Check every active firewall on this machine for UDP `port`.
+pub fn check_port(port: u16) -> Vec<FirewallCheck> {
+    let mut checks = Vec::new();
+    if cfg!(target_os = "linux") {
+        if let Some(status) = check_ufw(port) {
+            checks.push(FirewallCheck {
+                firewall: Firewall::Ufw,
+                status,
+            });
+        }
+        if let Some(status) = check_firewalld(port) {
+            checks.push(FirewallCheck {
+                firewall: Firewall::Firewalld,
+                status,
+            });
+        }
+    }
+    if cfg!(windows) {
+        if let Some(status) = check_windows(port) {
+            checks.push(FirewallCheck {
+                firewall: Firewall::Windows,
+                status,
+            });
+        }
+    }
+    checks
+}
+
+/// Run a program, returning whether it succeeded and its combined output.
+/// `None` if it isn't installed.
+fn run(program: &str, args: &[&str]) -> Option<(bool, String)> {
+    let output = Command::new(program).args(args).output().ok()?;
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    Some((output.status.success(), text))
+}
+
+fn check_ufw(port: u16) -> Option<PortStatus> {
+    let (ok, output) = run("ufw", &["status"])?;
+    if !ok {
+        return Some(PortStatus::Unknown(
+            "`ufw status` failed (it needs root)".to_string(),
+        ));
+    }
+    ufw_port_status(&output, port)
+}
+
+fn check_firewalld(port: u16) -> Option<PortStatus> {
+    let (_, state) = run("firewall-cmd", &["--state"])?;
+    if state.trim() != "running" {
+        return None;
+    }
+    let (_, answer) = run("firewall-cmd", &[&format!("--query-port={port}/udp")])?;
+    Some(match answer.trim() {
+        "yes" => PortStatus::Open,
+        "no" => PortStatus::Blocked,
+        other => PortStatus::Unknown(format!("`firewall-cmd --query-port` said {other:?}")),
+    })
+}
+
+fn check_windows(port: u16) -> Option<PortStatus> {
+    let (_, state) = run("netsh", &["advfirewall", "show", "currentprofile", "state"])?;
+    if !windows_firewall_on(&state) {
+        return None;
+    }
+    let (_, rules) = run(
+        "netsh",
+        &[
+            "advfirewall",
+            "firewall",
+            "show",
+            "rule",
+            "name=all",
+            "dir=in",
+        ],
+    )?;
+    Some(if windows_rules_allow(&rules, port) {
+        PortStatus::Open
+    } else {
+        PortStatus::Blocked
+    })
+}
+
+/// Parse `ufw status` output. `None` while ufw is inactive.
+pub fn ufw_port_status(output: &str, port: u16) -> Option<PortStatus> {
+    if !output.lines().any(|line| line.trim() == "Status: active") {
+        return None;
+    }
+    let open = output.lines().any(|line| {
+        let mut fields = line.split_whitespace();
+        let (Some(to), Some(action)) = (fields.next(), fields.next()) else {
+            return false;
+        };
+        let action = if action == "(v6)" {
+            fields.next().unwrap_or_default()
+        } else {
+            action
+        };
+        action == "ALLOW" && ufw_rule_covers(to, port)
+    });
+    Some(if open {
+        PortStatus::Open
+    } else {
+        PortStatus::Blocked
+    })
+}
+
+/// Whether a ufw "To" field (`24800`, `24800/udp`, `24000:25000/udp`)
+/// covers UDP `port`.
+fn ufw_rule_covers(to: &str, port: u16) -> bool {
+    let (ports, proto) = to.split_once('/').unwrap_or((to, "any"));
+    if proto != "udp" && proto != "any" {
+        return false;
+    }
+    ports.split(',').any(|range| {
+        let (low, high) = range.split_once(':').unwrap_or((range, range));
+        match (low.parse::<u16>(), high.parse::<u16>()) {
+            (Ok(low), Ok(high)) => (low..=high).contains(&port),
+            _ => false,
+        }
+    })
+}
+
+/// Parse `netsh advfirewall show currentprofile state`.
+fn windows_firewall_on(output: &str) -> bool {
+    output.lines().any(|line| {
+        let mut fields = line.split_whitespace();
+        fields.next() == Some("State") && fields.next() == Some("ON")
+    })
+}
+
+/// Whether `netsh advfirewall firewall show rule` output contains an
+/// enabled inbound allow rule for UDP `port`. Rules are blocks of
+/// `Key: Value` lines separated by blank lines.
+pub fn windows_rules_allow(output: &str, port: u16) -> bool {
+    output
+        .split("\r\n\r\n")
+        .flat_map(|b| b.split("\n\n"))
+        .any(|block| {
+            let field = |key: &str| {
+                block.lines().find_map(|line| {
+                    let (k, v) = line.split_once(':')?;
+                    (k.trim() == key).then(|| v.trim().to_string())
+                })
+            };
+            field("Enabled").as_deref() == Some("Yes")
+                && field("Action").as_deref() == Some("Allow")
+                && field("Protocol").as_deref() == Some("UDP")
+                && field("LocalPort").is_some_and(|ports| {
+                    ports == "Any" || ufw_rule_covers(&ports.replace('-', ":"), port)
+                })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UFW_ACTIVE: &str = "\
+Status: active
+
+To                         Action      From
+--                         ------      ----
+22/tcp                     ALLOW       Anywhere
+24000:24900/udp            ALLOW       192.168.1.0/24
+22/tcp (v6)                ALLOW       Anywhere (v6)
+";
+
+    #[test]
+    fn ufw_inactive_is_not_reported() {
+        assert_eq!(ufw_port_status("Status: inactive\n", 24800), None);
+    }
+
+    #[test]
+    fn ufw_port_ranges_and_protocols() {
+        assert_eq!(ufw_port_status(UFW_ACTIVE, 24800), Some(PortStatus::Open));
+        assert_eq!(
+            ufw_port_status(UFW_ACTIVE, 24901),
+            Some(PortStatus::Blocked)
+        );
+        assert_eq!(ufw_port_status(UFW_ACTIVE, 22), Some(PortStatus::Blocked));
+        assert!(ufw_rule_covers("24800", 24800));
+        assert!(!ufw_rule_covers("24800/tcp", 24800));
+    }
+
+    #[test]
+    fn windows_rule_must_be_enabled_inbound_udp() {
+        let rules = "\
+Rule Name:                            cross-control 24800
+----------------------------------------------------------------------
+Enabled:                              Yes
+Direction:                            In
+Protocol:                             UDP
+LocalPort:                            24800
+Action:                               Allow
+
+Rule Name:                            other
+Enabled:                              No
+Protocol:                             UDP
+LocalPort:                            24801
+Action:                               Allow
+";
+        assert!(windows_rules_allow(rules, 24800));
+        assert!(!windows_rules_allow(rules, 24801));
+        assert!(windows_firewall_on(
+            "State                                 ON\n"
+        ));
+    }
+}
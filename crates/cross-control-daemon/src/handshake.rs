@@ -0,0 +1,776 @@
+//! Connection handshake: the `Hello`/`Welcome` exchange and version check.
+//!
+//! The dialling side (initiator) sends `Hello` and waits for `Welcome`; the
+//! accepting side (responder) waits for `Hello` and answers with `Welcome`.
+//! [`Handshake`] is the state machine for both roles and does no I/O;
+//! [`exchange`] drives it over a control stream with a deadline, asking an
+//! authentication hook (see [`crate::auth`]) before answering a `Hello`.
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use cross_control_protocol::{MessageReceiver, MessageSender, ProtocolError};
+use cross_control_types::{
+    ControlMessage, ErrorCode, MachineId, ProtocolVersion, ScreenGeometry, PROTOCOL_VERSION,
+};
+use tracing::debug;
+
+use crate::auth::{AuthHook, PeerCandidate};
+use crate::error::DaemonError;
+
+/// How long the peer has to complete the handshake once connected.
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Which side of the connection we are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// We dialled the peer and speak first.
+    Initiator,
+    /// The peer dialled us.
+    Responder,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Initiator => write!(f, "initiator"),
+            Self::Responder => write!(f, "responder"),
+        }
+    }
+}
+
+/// What each side tells the other about itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Identity {
+    pub machine_id: MachineId,
+    pub name: String,
+    pub screen: ScreenGeometry,
+    /// Protocol version spoken; ours is always [`PROTOCOL_VERSION`].
+    pub version: ProtocolVersion,
+}
+
+/// Why a handshake did not complete.
+#[derive(Debug)]
+pub enum HandshakeError {
+    /// The peer sent `Error` instead of completing the handshake.
+    Remote { code: ErrorCode, detail: String },
+    /// We refused the peer. `code` and `detail` should be sent to it as an
+    /// `Error` before the connection is closed.
+    Refused {
+        code: ErrorCode,
+        detail: String,
+        /// The peer's name, if its message got far enough to say.
+        peer_name: Option<String>,
+        cause: ProtocolError,
+    },
+    /// The stream closed or failed, or the peer went quiet.
+    Transport(ProtocolError),
+}
+
+impl From<HandshakeError> for DaemonError {
+    fn from(error: HandshakeError) -> Self {
+        match error {
+            HandshakeError::Remote { code, detail } => {
+                ProtocolError::Remote { code, detail }.into()
+            }
+            HandshakeError::Refused { cause, .. } | HandshakeError::Transport(cause) => {
+                cause.into()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Initiator that has not sent `Hello` yet.
+    Start,
+    AwaitingHello,
+    AwaitingWelcome,
+    Complete,
+}
+
+/// The handshake state machine for one connection.
+#[derive(Debug)]
+pub struct Handshake {
+    role: Role,
+    ours: Identity,
+    state: State,
+}
+
+impl Handshake {
+    pub fn new(role: Role, ours: Identity) -> Self {
+        let state = match role {
+            Role::Initiator => State::Start,
+            Role::Responder => State::AwaitingHello,
+        };
+        Self { role, ours, state }
+    }
+
+    /// The message this side opens with: `Hello` for the initiator, nothing
+    /// for the responder.
+    pub fn opening(&mut self) -> Option<ControlMessage> {
+        if self.state != State::Start {
+            return None;
+        }
+        self.state = State::AwaitingWelcome;
+        Some(ControlMessage::Hello {
+            version: PROTOCOL_VERSION,
+            machine_id: self.ours.machine_id,
+            name: self.ours.name.clone(),
+            screen: self.ours.screen.clone(),
+        })
+    }
+
+    /// Name of the message we are waiting for.
+    pub fn expecting(&self) -> &'static str {
+        match self.state {
+            State::Start => "nothing before Hello is sent",
+            State::AwaitingHello => "Hello",
+            State::AwaitingWelcome => "Welcome",
+            State::Complete => "nothing after the handshake",
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.state == State::Complete
+    }
+
+    /// Feed the peer's message. On success the handshake is complete and
+    /// returns the peer's identity and, for the responder, the `Welcome` to
+    /// send back.
+    pub fn receive(
+        &mut self,
+        msg: ControlMessage,
+    ) -> Result<(Identity, Option<ControlMessage>), HandshakeError> {
+        let peer = match (self.state, msg) {
+            (_, ControlMessage::Error { code, detail }) => {
+                return Err(HandshakeError::Remote { code, detail });
+            }
+            (
+                State::AwaitingHello,
+                ControlMessage::Hello {
+                    version,
+                    machine_id,
+                    name,
+                    screen,
+                },
+            )
+            | (
+                State::AwaitingWelcome,
+                ControlMessage::Welcome {
+                    version,
+                    machine_id,
+                    name,
+                    screen,
+                },
+            ) => {
+                self.verify_version(version, &name)?;
+                Identity {
+                    machine_id,
+                    name,
+                    screen,
+                    version,
+                }
+            }
+            (_, other) => {
+                let detail = format!("expected {}, got {other:?}", self.expecting());
+                return Err(HandshakeError::Refused {
+                    code: ErrorCode::UnexpectedMessage,
+                    detail: detail.clone(),
+                    peer_name: None,
+                    cause: ProtocolError::Handshake(detail),
+                });
+            }
+        };
+
+        self.state = State::Complete;
+        let reply = (self.role == Role::Responder).then(|| ControlMessage::Welcome {
+            version: PROTOCOL_VERSION,
+            machine_id: self.ours.machine_id,
+            name: self.ours.name.clone(),
+            screen: self.ours.screen.clone(),
+        });
+        Ok((peer, reply))
+    }
+
+    fn verify_version(
+        &self,
+        remote: ProtocolVersion,
+        peer_name: &str,
+    ) -> Result<(), HandshakeError> {
+        if remote.major == PROTOCOL_VERSION.major {
+            return Ok(());
+        }
+        Err(HandshakeError::Refused {
+            code: ErrorCode::VersionMismatch,
+            detail: format!(
+                "{} speaks protocol {PROTOCOL_VERSION}, which is incompatible with {remote}",
+                self.ours.name
+            ),
+            peer_name: Some(peer_name.to_string()),
+            cause: ProtocolError::VersionMismatch {
+                remote: remote.to_string(),
+                local: PROTOCOL_VERSION.to_string(),
+            },
+        })
+    }
+}
+
+/// What the responder needs to ask an authentication hook about the peer.
+pub struct Admission<'a> {
+    pub hook: &'a dyn AuthHook,
+    /// Fingerprint of the certificate the peer presented during TLS.
+    pub fingerprint: Option<String>,
+    pub address: SocketAddr,
+}
+
+impl Admission<'_> {
+    /// Ask the hook about `peer`, refusing it with `Untrusted` if it says no.
+    async fn check(&self, peer: &Identity, our_name: &str) -> Result<(), HandshakeError> {
+        let candidate = PeerCandidate {
+            machine_id: peer.machine_id,
+            name: peer.name.clone(),
+            fingerprint: self.fingerprint.clone(),
+            address: self.address,
+        };
+        let reason = match self.hook.authorize(&candidate).await {
+            Ok(()) => return Ok(()),
+            Err(reason) => reason,
+        };
+        Err(HandshakeError::Refused {
+            code: ErrorCode::Untrusted,
+            detail: format!("{our_name} refused this machine: {reason}"),
+            peer_name: Some(peer.name.clone()),
+            cause: ProtocolError::Handshake(format!(
+                "{} refused by the authentication hook: {reason}",
+                peer.name
+            )),
+        })
+    }
+}
+
+/// Run the handshake over a control stream, giving up after `limit`. As
+/// responder, `admission` is consulted before `Welcome` is sent.
+pub async fn exchange(
+    tx: &mut MessageSender,
+    rx: &mut MessageReceiver,
+    role: Role,
+    ours: Identity,
+    admission: Option<Admission<'_>>,
+    limit: Duration,
+) -> Result<Identity, HandshakeError> {
+    let our_name = ours.name.clone();
+    let mut handshake = Handshake::new(role, ours);
+    let run = async {
+        if let Some(hello) = handshake.opening() {
+            tx.send_tagged(&hello)
+                .await
+                .map_err(HandshakeError::Transport)?;
+            debug!("sent Hello");
+        }
+        let msg: ControlMessage = match rx.recv_tagged().await {
+            Ok(Some(msg)) => msg,
+            Ok(None) => return Err(HandshakeError::Transport(ProtocolError::StreamClosed)),
+            Err(ProtocolError::VersionMismatch { remote, local }) => {
+                // The peer predates tagged framing, so tell it in its own.
+                let refusal = ControlMessage::Error {
+                    code: ErrorCode::VersionMismatch,
+                    detail: format!(
+                        "{our_name} speaks protocol {local}, which is incompatible with {remote}"
+                    ),
+                };
+                let _ = tx.send(&refusal).await;
+                return Err(HandshakeError::Transport(ProtocolError::VersionMismatch {
+                    remote,
+                    local,
+                }));
+            }
+            Err(e) => return Err(HandshakeError::Transport(e)),
+        };
+        let (peer, reply) = handshake.receive(msg)?;
+        if let (Some(admission), Role::Responder) = (&admission, role) {
+            admission.check(&peer, &our_name).await?;
+        }
+        if let Some(welcome) = reply {
+            tx.send_tagged(&welcome)
+                .await
+                .map_err(HandshakeError::Transport)?;
+        }
+        Ok(peer)
+    };
+    match tokio::time::timeout(limit, run).await {
+        Ok(result) => result,
+        Err(_) => Err(HandshakeError::Transport(ProtocolError::Handshake(
+            format!("no {} within {limit:?}", handshake.expecting()),
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cross_control_protocol::wire::encode_tagged;
+    use cross_control_types::{ScreenEdge, PROTOCOL_VERSION};
+    use tokio::io::AsyncWriteExt;
+
+    fn identity(name: &str) -> Identity {
+        Identity {
+            machine_id: MachineId::new(),
+            name: name.to_string(),
+            screen: ScreenGeometry::new(1920, 1080),
+            version: PROTOCOL_VERSION,
+        }
+    }
+
+    fn hello(version: ProtocolVersion, from: &Identity) -> ControlMessage {
+        ControlMessage::Hello {
+            version,
+            machine_id: from.machine_id,
+            name: from.name.clone(),
+            screen: from.screen.clone(),
+        }
+    }
+
+    fn welcome(version: ProtocolVersion, from: &Identity) -> ControlMessage {
+        ControlMessage::Welcome {
+            version,
+            machine_id: from.machine_id,
+            name: from.name.clone(),
+            screen: from.screen.clone(),
+        }
+    }
+
+    /// Messages that are never valid during a handshake.
+    fn stray_messages() -> Vec<ControlMessage> {
+        vec![
+            ControlMessage::Ping { seq: 1 },
+            ControlMessage::Bye,
+            ControlMessage::EnterAck,
+            ControlMessage::Enter {
+                edge: ScreenEdge::Left,
+                position: 0,
+            },
+        ]
+    }
+
+    fn assert_refused(
+        result: Result<(Identity, Option<ControlMessage>), HandshakeError>,
+        code: ErrorCode,
+    ) {
+        match result {
+            Err(HandshakeError::Refused { code: got, .. }) => assert_eq!(got, code),
+            other => panic!("expected refusal with {code:?}, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn initiator_sends_hello_and_accepts_welcome() {
+        let ours = identity("a");
+        let theirs = identity("b");
+        let mut handshake = Handshake::new(Role::Initiator, ours.clone());
+
+        let opening = handshake.opening().expect("initiator speaks first");
+        assert!(matches!(opening, ControlMessage::Hello { ref name, .. } if *name == ours.name));
+        assert!(handshake.opening().is_none(), "Hello is sent once");
+
+        let (peer, reply) = handshake
+            .receive(welcome(PROTOCOL_VERSION, &theirs))
+            .unwrap();
+        assert_eq!(peer, theirs);
+        assert!(reply.is_none());
+        assert!(handshake.is_complete());
+    }
+
+    #[test]
+    fn responder_answers_hello_with_welcome() {
+        let ours = identity("b");
+        let theirs = identity("a");
+        let mut handshake = Handshake::new(Role::Responder, ours.clone());
+        assert!(handshake.opening().is_none(), "responder waits for Hello");
+
+        let (peer, reply) = handshake.receive(hello(PROTOCOL_VERSION, &theirs)).unwrap();
+        assert_eq!(peer, theirs);
+        match reply {
+            Some(ControlMessage::Welcome {
+                machine_id, name, ..
+            }) => {
+                assert_eq!(machine_id, ours.machine_id);
+                assert_eq!(name, ours.name);
+            }
+            other => panic!("expected Welcome, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn newer_minor_version_is_accepted() {
+        let newer = ProtocolVersion {
+            minor: PROTOCOL_VERSION.minor + 1,
+            ..PROTOCOL_VERSION
+        };
+        let mut handshake = Handshake::new(Role::Responder, identity("b"));
+        let (peer, _) = handshake.receive(hello(newer, &identity("a"))).unwrap();
+        assert_eq!(peer.version, newer);
+    }
+
+    #[test]
+    fn other_major_version_is_refused_with_peer_name() {
+        let other = ProtocolVersion {
+            major: PROTOCOL_VERSION.major + 1,
+            ..PROTOCOL_VERSION
+        };
+        for role in [Role::Initiator, Role::Responder] {
+            let mut handshake = Handshake::new(role, identity("ours"));
+            handshake.opening();
+            let msg = match role {
+                Role::Initiator => welcome(other, &identity("theirs")),
+                Role::Responder => hello(other, &identity("theirs")),
+            };
+            match handshake.receive(msg) {
+                Err(HandshakeError::Refused {
+                    code,
+                    detail,
+                    peer_name,
+                    cause,
+                }) => {
+                    assert_eq!(code, ErrorCode::VersionMismatch);
+                    assert!(detail.starts_with("ours speaks protocol"), "{detail}");
+                    assert_eq!(peer_name.as_deref(), Some("theirs"));
+                    assert!(matches!(cause, ProtocolError::VersionMismatch { .. }));
+                }
+                other => panic!("{role}: expected refusal, got {other:?}"),
+            }
+            assert!(!handshake.is_complete());
+        }
+    }
+
+    #[test]
+    fn wrong_handshake_message_is_refused() {
+        // Each role receiving the message meant for the other.
+        let mut initiator = Handshake::new(Role::Initiator, identity("a"));
+        initiator.opening();
+        assert_refused(
+            initiator.receive(hello(PROTOCOL_VERSION, &identity("b"))),
+            ErrorCode::UnexpectedMessage,
+        );
+
+        let mut responder = Handshake::new(Role::Responder, identity("b"));
+        assert_refused(
+            responder.receive(welcome(PROTOCOL_VERSION, &identity("a"))),
+            ErrorCode::UnexpectedMessage,
+        );
+    }
+
+    #[test]
+    fn stray_messages_are_refused_in_every_state() {
+        for msg in stray_messages() {
+            let mut initiator = Handshake::new(Role::Initiator, identity("a"));
+            assert_refused(initiator.receive(msg.clone()), ErrorCode::UnexpectedMessage);
+            initiator.opening();
+            assert_refused(initiator.receive(msg.clone()), ErrorCode::UnexpectedMessage);
+
+            let mut responder = Handshake::new(Role::Responder, identity("b"));
+            assert_refused(responder.receive(msg), ErrorCode::UnexpectedMessage);
+        }
+    }
+
+    #[test]
+    fn welcome_before_hello_is_sent_is_refused() {
+        let mut initiator = Handshake::new(Role::Initiator, identity("a"));
+        assert_refused(
+            initiator.receive(welcome(PROTOCOL_VERSION, &identity("b"))),
+            ErrorCode::UnexpectedMessage,
+        );
+    }
+
+    #[test]
+    fn messages_after_completion_are_refused() {
+        let mut responder = Handshake::new(Role::Responder, identity("b"));
+        responder
+            .receive(hello(PROTOCOL_VERSION, &identity("a")))
+            .unwrap();
+        assert_refused(
+            responder.receive(hello(PROTOCOL_VERSION, &identity("a"))),
+            ErrorCode::UnexpectedMessage,
+        );
+    }
+
+    #[test]
+    fn peer_error_is_reported_as_remote() {
+        let mut initiator = Handshake::new(Role::Initiator, identity("a"));
+        initiator.opening();
+        let result = initiator.receive(ControlMessage::Error {
+            code: ErrorCode::VersionMismatch,
+            detail: "b speaks protocol 9.0".to_string(),
+        });
+        match result {
+            Err(HandshakeError::Remote { code, detail }) => {
+                assert_eq!(code, ErrorCode::VersionMismatch);
+                assert_eq!(detail, "b speaks protocol 9.0");
+            }
+            other => panic!("expected remote error, got {other:?}"),
+        }
+    }
+
+    /// A control stream pair over an in-memory pipe, plus the raw far end
+    /// for writing arbitrary bytes.
+    fn pipe() -> (MessageSender, MessageReceiver, tokio::io::DuplexStream) {
+        let (ours, theirs) = tokio::io::duplex(4096);
+        let (read, write) = tokio::io::split(ours);
+        (
+            MessageSender::new(Box::new(write)),
+            MessageReceiver::new(Box::new(read)),
+            theirs,
+        )
+    }
+
+    #[tokio::test]
+    async fn exchange_completes_between_both_roles() {
+        let (a, b) = tokio::io::duplex(4096);
+        let (a_read, a_write) = tokio::io::split(a);
+        let (b_read, b_write) = tokio::io::split(b);
+        let mut a_tx = MessageSender::new(Box::new(a_write));
+        let mut a_rx = MessageReceiver::new(Box::new(a_read));
+        let mut b_tx = MessageSender::new(Box::new(b_write));
+        let mut b_rx = MessageReceiver::new(Box::new(b_read));
+        let ours = identity("a");
+        let theirs = identity("b");
+
+        let (initiator, responder) = tokio::join!(
+            exchange(
+                &mut a_tx,
+                &mut a_rx,
+                Role::Initiator,
+                ours.clone(),
+                None,
+                HANDSHAKE_TIMEOUT,
+            ),
+            exchange(
+                &mut b_tx,
+                &mut b_rx,
+                Role::Responder,
+                theirs.clone(),
+                None,
+                HANDSHAKE_TIMEOUT,
+            ),
+        );
+        assert_eq!(initiator.unwrap(), theirs);
+        assert_eq!(responder.unwrap(), ours);
+    }
+
+    /// Refuses every peer, naming it.
+    struct DenyAll;
+
+    #[async_trait::async_trait]
+    impl AuthHook for DenyAll {
+        async fn authorize(&self, peer: &PeerCandidate) -> Result<(), String> {
+            Err(format!("{} is not in the inventory", peer.name))
+        }
+    }
+
+    #[tokio::test]
+    async fn refused_by_auth_hook_before_welcome() {
+        let (mut tx, mut rx, mut far) = pipe();
+        let theirs = identity("a");
+        let frame = encode_tagged(&hello(PROTOCOL_VERSION, &theirs)).unwrap();
+        far.write_all(&frame).await.unwrap();
+
+        let admission = Admission {
+            hook: &DenyAll,
+            fingerprint: None,
+            address: "127.0.0.1:24800".parse().unwrap(),
+        };
+        let result = exchange(
+            &mut tx,
+            &mut rx,
+            Role::Responder,
+            identity("b"),
+            Some(admission),
+            HANDSHAKE_TIMEOUT,
+        )
+        .await;
+        match result {
+            Err(HandshakeError::Refused {
+                code,
+                detail,
+                peer_name,
+                ..
+            }) => {
+                assert_eq!(code, ErrorCode::Untrusted);
+                assert_eq!(detail, "b refused this machine: a is not in the inventory");
+                assert_eq!(peer_name.as_deref(), Some("a"));
+            }
+            other => panic!("expected a refusal, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn stream_closed_before_any_message() {
+        let (mut tx, mut rx, far) = pipe();
+        drop(far);
+        let result = exchange(
+            &mut tx,
+            &mut rx,
+            Role::Responder,
+            identity("b"),
+            None,
+            HANDSHAKE_TIMEOUT,
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(HandshakeError::Transport(ProtocolError::StreamClosed))
+        ));
+    }
+
+    #[tokio::test]
+    async fn stream_truncated_mid_message() {
+        let (mut tx, mut rx, mut far) = pipe();
+        // A length prefix promising 64 bytes, then only 3 of them.
+        far.write_all(&64u32.to_be_bytes()).await.unwrap();
+        far.write_all(&[1, 2, 3]).await.unwrap();
+        drop(far);
+        let result = exchange(
+            &mut tx,
+            &mut rx,
+            Role::Responder,
+            identity("b"),
+            None,
+            HANDSHAKE_TIMEOUT,
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(HandshakeError::Transport(ProtocolError::StreamClosed))
+        ));
+    }
+
+    #[tokio::test]
+    async fn stream_truncated_mid_length_prefix() {
+        let (mut tx, mut rx, mut far) = pipe();
+        far.write_all(&[0, 0]).await.unwrap();
+        drop(far);
+        let result = exchange(
+            &mut tx,
+            &mut rx,
+            Role::Responder,
+            identity("b"),
+            None,
+            HANDSHAKE_TIMEOUT,
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(HandshakeError::Transport(ProtocolError::StreamClosed))
+        ));
+    }
+
+    #[tokio::test]
+    async fn garbage_payload_is_a_transport_error() {
+        let (mut tx, mut rx, mut far) = pipe();
+        // Tagged as a Hello, but not a Hello.
+        far.write_all(&6u32.to_be_bytes()).await.unwrap();
+        far.write_all(&[0, 0]).await.unwrap();
+        far.write_all(&[0xff; 4]).await.unwrap();
+        let result = exchange(
+            &mut tx,
+            &mut rx,
+            Role::Responder,
+            identity("b"),
+            None,
+            HANDSHAKE_TIMEOUT,
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(HandshakeError::Transport(ProtocolError::Deserialization(_)))
+        ));
+    }
+
+    /// A peer from before tagged framing gets a version mismatch it can
+    /// read, not a decode error.
+    #[tokio::test]
+    async fn untagged_legacy_hello_is_refused_in_its_framing() {
+        use cross_control_protocol::wire::{decode_message, encode_message};
+        use tokio::io::AsyncReadExt;
+
+        let (mut tx, mut rx, mut far) = pipe();
+        let legacy = ProtocolVersion { major: 0, minor: 1 };
+        let frame = encode_message(&hello(legacy, &identity("a"))).unwrap();
+        far.write_all(&frame).await.unwrap();
+
+        let result = exchange(
+            &mut tx,
+            &mut rx,
+            Role::Responder,
+            identity("b"),
+            None,
+            HANDSHAKE_TIMEOUT,
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(HandshakeError::Transport(
+                ProtocolError::VersionMismatch { .. }
+            ))
+        ));
+
+        let mut len = [0u8; 4];
+        far.read_exact(&mut len).await.unwrap();
+        let mut payload = vec![0u8; u32::from_be_bytes(len) as usize];
+        far.read_exact(&mut payload).await.unwrap();
+        match decode_message::<ControlMessage>(&payload).unwrap() {
+            ControlMessage::Error { code, detail } => {
+                assert_eq!(code, ErrorCode::VersionMismatch);
+                assert!(detail.contains("0.1"), "{detail}");
+            }
+            other => panic!("expected an untagged Error, got {other:?}"),
+        }
+    }
+
+    /// A message type from a newer peer is skipped rather than failing the
+    /// handshake.
+    #[tokio::test]
+    async fn unknown_message_type_is_skipped() {
+        let (mut tx, mut rx, mut far) = pipe();
+        far.write_all(&5u32.to_be_bytes()).await.unwrap();
+        far.write_all(&[0x01, 0x00]).await.unwrap();
+        far.write_all(&[1, 2, 3]).await.unwrap();
+        let theirs = identity("a");
+        let frame = encode_tagged(&hello(PROTOCOL_VERSION, &theirs)).unwrap();
+        far.write_all(&frame).await.unwrap();
+
+        let result = exchange(
+            &mut tx,
+            &mut rx,
+            Role::Responder,
+            identity("b"),
+            None,
+            HANDSHAKE_TIMEOUT,
+        )
+        .await;
+        assert_eq!(result.unwrap(), theirs);
+    }
+
+    #[tokio::test]
+    async fn silent_peer_times_out() {
+        let (mut tx, mut rx, _far) = pipe();
+        let limit = Duration::from_millis(50);
+        let result = exchange(
+            &mut tx,
+            &mut rx,
+            Role::Initiator,
+            identity("a"),
+            None,
+            limit,
+        )
+        .await;
+        match result {
+            Err(HandshakeError::Transport(ProtocolError::Handshake(detail))) => {
+                assert_eq!(detail, "no Welcome within 50ms");
+            }
+            other => panic!("expected timeout, got {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,241 @@
+//! Multi-step hotkeys.
+//!
+//! An `[[input.sequences]]` entry lists steps pressed one after another,
+//! each a key name or a `+`-joined combination such as `LeftCtrl+F9`. A step
+//! completes on the key press that leaves all of its keys held. Once the
+//! first step of some sequence completes, key presses are kept from peers
+//! until the sequence finishes; a press that fits no sequence, or a pause
+//! longer than `input.chord_timeout_ms`, abandons it.
+
+use std::time::{Duration, Instant};
+
+use cross_control_types::{ButtonState, KeyCode};
+
+use crate::config::{CornerAction, HotkeySequence};
+
+/// What the matcher made of a key event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SequenceKey {
+    /// Not part of a sequence; handle the event as usual.
+    Pass,
+    /// Part of a sequence in progress; drop the event.
+    Consumed,
+    /// Completed a sequence; drop the event and run the action.
+    Fired(CornerAction),
+}
+
+/// Tracks progress through [`HotkeySequence`]s.
+#[derive(Default)]
+pub struct SequenceMatcher {
+    /// Steps completed so far.
+    done: usize,
+    /// Sequences whose first `done` steps were the ones completed.
+    candidates: Vec<usize>,
+    last_step: Option<Instant>,
+    /// Keys whose press was consumed; their release is consumed too.
+    swallowed: Vec<KeyCode>,
+}
+
+impl SequenceMatcher {
+    /// Feed one key event. `held` is every key held down, including `code`
+    /// when it was just pressed.
+    pub fn key(
+        &mut self,
+        sequences: &[HotkeySequence],
+        timeout: Duration,
+        held: &[KeyCode],
+        code: KeyCode,
+        state: ButtonState,
+        now: Instant,
+    ) -> SequenceKey {
+        if state == ButtonState::Released {
+            let swallowed = self.swallowed.contains(&code);
+            self.swallowed.retain(|k| *k != code);
+            return if swallowed {
+                SequenceKey::Consumed
+            } else {
+                SequenceKey::Pass
+            };
+        }
+        if self
+            .last_step
+            .is_some_and(|at| now.duration_since(at) > timeout)
+        {
+            self.reset();
+        }
+
+        let candidates: Vec<usize> = if self.done == 0 {
+            (0..sequences.len())
+                .filter(|&i| !sequences[i].steps.is_empty())
+                .collect()
+        } else {
+            std::mem::take(&mut self.candidates)
+        };
+        let next_step = |i: usize| sequences[i].steps[self.done].as_str();
+        let advanced: Vec<usize> = candidates
+            .iter()
+            .copied()
+            .filter(|&i| completes_step(next_step(i), held, code))
+            .collect();
+
+        if advanced.is_empty() {
+            if self.done == 0 {
+                return SequenceKey::Pass;
+            }
+            // A modifier of the next step keeps the sequence going.
+            if candidates
+                .iter()
+                .any(|&i| step_keys(next_step(i)).any(|k| k == key_name(code)))
+            {
+                self.candidates = candidates;
+                self.swallow(code);
+                return SequenceKey::Consumed;
+            }
+            // The key may start another sequence.
+            self.reset();
+            return self.key(sequences, timeout, held, code, state, now);
+        }
+
+        self.swallow(code);
+        self.done += 1;
+        self.last_step = Some(now);
+        let finished = advanced
+            .iter()
+            .find(|&&i| sequences[i].steps.len() == self.done);
+        if let Some(&i) = finished {
+            self.reset();
+            return SequenceKey::Fired(sequences[i].action.clone());
+        }
+        self.candidates = advanced;
+        SequenceKey::Consumed
+    }
+
+    fn swallow(&mut self, code: KeyCode) {
+        if !self.swallowed.contains(&code) {
+            self.swallowed.push(code);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.done = 0;
+        self.candidates.clear();
+        self.last_step = None;
+    }
+}
+
+fn key_name(code: KeyCode) -> String {
+    format!("{code:?}")
+}
+
+fn step_keys(step: &str) -> impl Iterator<Item = &str> {
+    step.split('+').map(str::trim)
+}
+
+/// Whether pressing `code` completes `step`, given the keys `held`.
+fn completes_step(step: &str, held: &[KeyCode], code: KeyCode) -> bool {
+    let pressed = key_name(code);
+    step_keys(step).any(|k| k == pressed)
+        && step_keys(step).all(|k| held.iter().any(|h| key_name(*h) == k))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    fn sequence(steps: &[&str], screen: &str) -> HotkeySequence {
+        HotkeySequence {
+            steps: steps.iter().map(|s| (*s).to_string()).collect(),
+            action: CornerAction::Switch(screen.to_string()),
+        }
+    }
+
+    /// Press and release `code` with nothing else held.
+    fn tap(
+        matcher: &mut SequenceMatcher,
+        sequences: &[HotkeySequence],
+        code: KeyCode,
+        now: Instant,
+    ) -> SequenceKey {
+        let pressed = matcher.key(sequences, TIMEOUT, &[code], code, ButtonState::Pressed, now);
+        let released = matcher.key(sequences, TIMEOUT, &[], code, ButtonState::Released, now);
+        if pressed != SequenceKey::Pass {
+            assert_eq!(released, SequenceKey::Consumed);
+        }
+        pressed
+    }
+
+    #[test]
+    fn leader_then_number_picks_a_screen() {
+        let sequences = [
+            sequence(&["F9", "Digit1"], "laptop"),
+            sequence(&["F9", "Digit2"], "desktop"),
+        ];
+        let mut matcher = SequenceMatcher::default();
+        let now = Instant::now();
+        assert_eq!(
+            tap(&mut matcher, &sequences, KeyCode::F9, now),
+            SequenceKey::Consumed
+        );
+        assert_eq!(
+            tap(&mut matcher, &sequences, KeyCode::Digit2, now),
+            SequenceKey::Fired(CornerAction::Switch("desktop".to_string()))
+        );
+        // Back at the start: a plain number passes through.
+        assert_eq!(
+            tap(&mut matcher, &sequences, KeyCode::Digit1, now),
+            SequenceKey::Pass
+        );
+    }
+
+    #[test]
+    fn unrelated_key_or_timeout_abandons_the_sequence() {
+        let sequences = [sequence(&["F9", "Digit1"], "laptop")];
+        let mut matcher = SequenceMatcher::default();
+        let now = Instant::now();
+        tap(&mut matcher, &sequences, KeyCode::F9, now);
+        assert_eq!(
+            tap(&mut matcher, &sequences, KeyCode::KeyA, now),
+            SequenceKey::Pass
+        );
+        assert_eq!(
+            tap(&mut matcher, &sequences, KeyCode::Digit1, now),
+            SequenceKey::Pass
+        );
+
+        tap(&mut matcher, &sequences, KeyCode::F9, now);
+        let late = now + TIMEOUT + Duration::from_millis(1);
+        assert_eq!(
+            tap(&mut matcher, &sequences, KeyCode::Digit1, late),
+            SequenceKey::Pass
+        );
+    }
+
+    #[test]
+    fn combination_steps() {
+        let sequences = [sequence(&["LeftCtrl+F9", "LeftShift+Digit1"], "laptop")];
+        let mut matcher = SequenceMatcher::default();
+        let now = Instant::now();
+        let mut press = |held: &[KeyCode], code| {
+            matcher.key(&sequences, TIMEOUT, held, code, ButtonState::Pressed, now)
+        };
+        // The first step's modifier alone is ordinary input.
+        assert_eq!(
+            press(&[KeyCode::LeftCtrl], KeyCode::LeftCtrl),
+            SequenceKey::Pass
+        );
+        assert_eq!(
+            press(&[KeyCode::LeftCtrl, KeyCode::F9], KeyCode::F9),
+            SequenceKey::Consumed
+        );
+        assert_eq!(
+            press(&[KeyCode::LeftShift], KeyCode::LeftShift),
+            SequenceKey::Consumed
+        );
+        assert_eq!(
+            press(&[KeyCode::LeftShift, KeyCode::Digit1], KeyCode::Digit1),
+            SequenceKey::Fired(CornerAction::Switch("laptop".to_string()))
+        );
+    }
+}
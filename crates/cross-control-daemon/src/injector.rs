@@ -0,0 +1,641 @@
+//! Per-device injection queues for the controlled side.
+//!
+//! Each remote device gets its own queue and worker task, so events for one
+//! device are injected strictly in order without stalling the daemon event
+//! loop.
+//!
+//! The workers share one emulation backend. Each takes the backend lock for
+//! a single call only, and gives up on a call that outlasts
+//! [`INJECT_TIMEOUT`] (or [`CREATE_TIMEOUT`] for device creation), so a
+//! device whose backend call hangs delays the other queues by at most that
+//! long per call rather than blocking them for good.
+//!
+//! Queues are capped: when a device falls more than the limit behind, its
+//! oldest batches are dropped so a stuck backend can't grow the daemon
+//! without bound. Key and button releases in dropped batches are kept, and
+//! a release the backend failed to inject is retried before the device's
+//! next batch, so a press that got through is never left held.
+//!
+//! [`Injector::smoke_test`] checks a peer's devices without going through
+//! the queues, so a controller can be told straight away when its input
+//! has nowhere to go.
+
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use cross_control_input::{InputEmulation, InputError};
+use cross_control_types::{
+    warn_throttled, ButtonState, DeviceCapability, DeviceId, DeviceInfo, InputEvent, MachineId,
+    VirtualDeviceId,
+};
+use tokio::sync::{watch, Mutex, Notify};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, trace_span, warn, Instrument};
+
+type SharedBackend = Arc<Mutex<Box<dyn InputEmulation>>>;
+
+/// Longest a worker may hold the backend to inject one event.
+pub const INJECT_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// Longest a worker may hold the backend to create or destroy its device.
+pub const CREATE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Key for a device queue: the owning peer and its device ID.
+type QueueKey = (MachineId, DeviceId);
+
+/// Batches waiting for a device worker.
+#[derive(Default)]
+struct Backlog {
+    batches: std::sync::Mutex<VecDeque<Vec<InputEvent>>>,
+    closed: AtomicBool,
+    notify: Notify,
+}
+
+impl Backlog {
+    /// Queue a batch, dropping the oldest ones beyond `limit`. Returns how
+    /// many were dropped.
+    ///
+    /// The releases in dropped batches are kept, ahead of the oldest batch
+    /// left, since their presses may already have been injected.
+    fn push(&self, events: Vec<InputEvent>, limit: usize) -> usize {
+        let mut batches = self
+            .batches
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        batches.push_back(events);
+        let excess = batches.len().saturating_sub(limit.max(1));
+        let mut releases = Vec::new();
+        for event in batches.drain(..excess).flatten() {
+            if is_release(&event) && !releases.contains(&event) {
+                releases.push(event);
+            }
+        }
+        if let Some(front) = batches.front_mut() {
+            front.splice(..0, releases);
+        }
+        drop(batches);
+        self.notify.notify_one();
+        excess
+    }
+
+    /// The next batch, or `None` once closed and empty.
+    async fn pop(&self) -> Option<Vec<InputEvent>> {
+        loop {
+            let next = self
+                .batches
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .pop_front();
+            if next.is_some() {
+                return next;
+            }
+            if self.closed.load(Ordering::Acquire) {
+                return None;
+            }
+            self.notify.notified().await;
+        }
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.notify.notify_one();
+    }
+}
+
+/// Whether a worker has created its virtual device.
+#[derive(Debug, Clone)]
+enum Created {
+    Pending,
+    Ready(VirtualDeviceId),
+    Failed(String),
+}
+
+/// A queue feeding a single virtual device.
+struct DeviceQueue {
+    info: DeviceInfo,
+    backlog: Arc<Backlog>,
+    created: watch::Receiver<Created>,
+    task: JoinHandle<()>,
+    /// Events queued for the device so far.
+    events: AtomicU64,
+}
+
+/// Routes remote input to per-device worker tasks that own a virtual device.
+pub struct Injector {
+    backend: SharedBackend,
+    queues: HashMap<QueueKey, DeviceQueue>,
+    /// Workers whose queue was closed but may still be injecting.
+    draining: Vec<JoinHandle<()>>,
+    /// Batches each device may have waiting before the oldest are dropped.
+    queue_limit: usize,
+    /// Batches dropped so far.
+    evicted: AtomicU64,
+}
+
+impl Injector {
+    /// Wrap an emulation backend, allowing up to `queue_limit` batches to
+    /// wait per device.
+    pub fn new(backend: Box<dyn InputEmulation>, queue_limit: usize) -> Self {
+        Self {
+            backend: Arc::new(Mutex::new(backend)),
+            queues: HashMap::new(),
+            draining: Vec::new(),
+            queue_limit,
+            evicted: AtomicU64::new(0),
+        }
+    }
+
+    /// Number of batches dropped because a device queue was full.
+    pub fn evicted(&self) -> u64 {
+        self.evicted.load(Ordering::Relaxed)
+    }
+
+    /// Check that the backend can create virtual devices.
+    pub async fn probe(&self) -> Result<(), InputError> {
+        self.backend.lock().await.probe().await
+    }
+
+    /// Start a queue for a newly announced remote device.
+    ///
+    /// The virtual device is created by the worker task, so this never waits
+    /// on the backend. Re-announcing a known device replaces its queue.
+    pub fn add_device(&mut self, peer: MachineId, info: DeviceInfo) {
+        let key = (peer, info.id);
+        let backlog = Arc::new(Backlog::default());
+        let (created_tx, created) = watch::channel(Created::Pending);
+        let task = crate::supervisor::spawn(
+            "device-queue",
+            run_device_queue(
+                Arc::clone(&self.backend),
+                info.clone(),
+                Arc::clone(&backlog),
+                created_tx,
+            ),
+        );
+        let queue = DeviceQueue {
+            info,
+            backlog,
+            created,
+            task,
+            events: AtomicU64::new(0),
+        };
+        if let Some(old) = self.queues.insert(key, queue) {
+            self.retire(old);
+        }
+    }
+
+    /// Queue events for a remote device. Returns `false` if the device is unknown.
+    pub fn inject(&self, peer: MachineId, device: DeviceId, events: Vec<InputEvent>) -> bool {
+        let Some(queue) = self.queues.get(&(peer, device)) else {
+            return false;
+        };
+        queue
+            .events
+            .fetch_add(events.len() as u64, Ordering::Relaxed);
+        let dropped = queue.backlog.push(events, self.queue_limit);
+        if dropped > 0 {
+            self.evicted.fetch_add(dropped as u64, Ordering::Relaxed);
+            debug!(peer = %peer, device = device.0, dropped, "device queue full, dropped oldest input");
+        }
+        true
+    }
+
+    /// Whether a remote device has a queue and declares `capability`.
+    pub fn supports(
+        &self,
+        peer: MachineId,
+        device: DeviceId,
+        capability: DeviceCapability,
+    ) -> bool {
+        self.queues
+            .get(&(peer, device))
+            .is_some_and(|queue| queue.info.capabilities.contains(&capability))
+    }
+
+    /// Check that `peer`'s input can be injected: each of its virtual
+    /// devices exists, and pointer devices take a zero-length move. Devices
+    /// still being created are waited for up to `timeout`.
+    ///
+    /// The returned future doesn't borrow the injector, so it can run on
+    /// its own task.
+    pub fn smoke_test(
+        &self,
+        peer: MachineId,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<(), InputError>> + Send + 'static {
+        let backend = Arc::clone(&self.backend);
+        let devices: Vec<(DeviceInfo, watch::Receiver<Created>)> = self
+            .queues
+            .iter()
+            .filter(|((owner, _), _)| *owner == peer)
+            .map(|(_, queue)| (queue.info.clone(), queue.created.clone()))
+            .collect();
+        async move {
+            if devices.is_empty() {
+                return Err(InputError::VirtualDeviceCreate(
+                    "no input devices announced".to_string(),
+                ));
+            }
+            for (info, mut created) in devices {
+                let state = tokio::time::timeout(
+                    timeout,
+                    created.wait_for(|c| !matches!(c, Created::Pending)),
+                )
+                .await
+                .map_err(|_| {
+                    InputError::VirtualDeviceCreate(format!(
+                        "{} not created within {timeout:?}",
+                        info.name
+                    ))
+                })?
+                .map_err(|_| InputError::VirtualDeviceCreate(format!("{} was removed", info.name)))?
+                .clone();
+                let id = match state {
+                    Created::Ready(id) => id,
+                    Created::Failed(e) => {
+                        return Err(InputError::VirtualDeviceCreate(format!(
+                            "{}: {e}",
+                            info.name
+                        )));
+                    }
+                    Created::Pending => unreachable!("waited for creation"),
+                };
+                if info.capabilities.contains(&DeviceCapability::RelativeMouse) {
+                    let noop = InputEvent::MouseMove { dx: 0, dy: 0 };
+                    inject_one(&backend, id, noop).await?;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Each remote device with a queue, with the number of events queued
+    /// for it.
+    pub fn devices(&self) -> impl Iterator<Item = (MachineId, &DeviceInfo, u64)> {
+        self.queues
+            .iter()
+            .map(|((peer, _), queue)| (*peer, &queue.info, queue.events.load(Ordering::Relaxed)))
+    }
+
+    /// Stop the queue for a removed device; pending events are still injected.
+    pub fn remove_device(&mut self, peer: MachineId, device: DeviceId) {
+        if let Some(queue) = self.queues.remove(&(peer, device)) {
+            self.retire(queue);
+        }
+    }
+
+    /// Stop all queues belonging to a peer.
+    pub fn remove_peer(&mut self, peer: MachineId) {
+        let keys: Vec<QueueKey> = self
+            .queues
+            .keys()
+            .filter(|(owner, _)| *owner == peer)
+            .copied()
+            .collect();
+        for key in keys {
+            if let Some(queue) = self.queues.remove(&key) {
+                self.retire(queue);
+            }
+        }
+    }
+
+    /// Drain every queue, destroy all virtual devices, and shut down the backend.
+    pub async fn shutdown(&mut self) -> Result<(), InputError> {
+        let queues: Vec<DeviceQueue> = self.queues.drain().map(|(_, q)| q).collect();
+        for queue in queues {
+            self.retire(queue);
+        }
+        for task in self.draining.drain(..) {
+            let _ = task.await;
+        }
+        self.backend.lock().await.shutdown().await
+    }
+
+    /// Close a queue so its worker drains and exits, keeping the handle so
+    /// shutdown can wait for it.
+    fn retire(&mut self, queue: DeviceQueue) {
+        queue.backlog.close();
+        self.draining.retain(|task| !task.is_finished());
+        self.draining.push(queue.task);
+    }
+}
+
+/// Whether `event` lets go of a key or button.
+pub(crate) fn is_release(event: &InputEvent) -> bool {
+    matches!(
+        event,
+        InputEvent::Key {
+            state: ButtonState::Released,
+            ..
+        } | InputEvent::MouseButton {
+            state: ButtonState::Released,
+            ..
+        }
+    )
+}
+
+/// Inject one event, holding the backend for at most [`INJECT_TIMEOUT`].
+async fn inject_one(
+    backend: &SharedBackend,
+    device: VirtualDeviceId,
+    event: InputEvent,
+) -> Result<(), InputError> {
+    tokio::time::timeout(INJECT_TIMEOUT, async {
+        backend.lock().await.inject(device, event).await
+    })
+    .await
+    .unwrap_or_else(|_| {
+        Err(InputError::Inject(format!(
+            "backend busy for more than {INJECT_TIMEOUT:?}"
+        )))
+    })
+}
+
+/// Worker for a single remote device: create it, inject in order, destroy on close.
+async fn run_device_queue(
+    backend: SharedBackend,
+    info: DeviceInfo,
+    backlog: Arc<Backlog>,
+    created_tx: watch::Sender<Created>,
+) {
+    let created = tokio::time::timeout(CREATE_TIMEOUT, async {
+        backend.lock().await.create_device(&info).await
+    })
+    .await
+    .unwrap_or_else(|_| {
+        Err(InputError::VirtualDeviceCreate(format!(
+            "backend busy for more than {CREATE_TIMEOUT:?}"
+        )))
+    });
+    let virtual_id = match created {
+        Ok(id) => {
+            created_tx.send_replace(Created::Ready(id));
+            id
+        }
+        Err(e) => {
+            warn!(device = %info.name, error = %e, "failed to create virtual device");
+            created_tx.send_replace(Created::Failed(e.to_string()));
+            // Discard anything queued so senders don't pile up.
+            while backlog.pop().await.is_some() {}
+            return;
+        }
+    };
+    debug!(device = %info.name, id = virtual_id.0, "device queue started");
+
+    // Releases that failed, retried ahead of the next batch.
+    let mut unreleased: Vec<InputEvent> = Vec::new();
+    while let Some(events) = backlog.pop().await {
+        let span = trace_span!("inject", device = virtual_id.0, count = events.len());
+        async {
+            let retries = std::mem::take(&mut unreleased);
+            for event in retries.into_iter().chain(events) {
+                if let Err(e) = inject_one(&backend, virtual_id, event.clone()).await {
+                    warn_throttled!(device = %info.name, error = %e, "failed to inject event");
+                    if is_release(&event) && !unreleased.contains(&event) {
+                        unreleased.push(event);
+                    }
+                }
+            }
+        }
+        .instrument(span)
+        .await;
+    }
+
+    let destroyed = tokio::time::timeout(CREATE_TIMEOUT, async {
+        backend.lock().await.destroy_device(virtual_id).await
+    })
+    .await;
+    if destroyed.is_err() {
+        warn!(device = %info.name, "timed out destroying virtual device");
+    }
+    info!(device = %info.name, "device queue drained");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cross_control_input::mock::MockEmulation;
+    use cross_control_types::{ButtonState, DeviceCapability, KeyCode};
+
+    fn keyboard(id: u32) -> DeviceInfo {
+        DeviceInfo {
+            id: DeviceId(id),
+            name: format!("Keyboard {id}"),
+            capabilities: vec![DeviceCapability::Keyboard],
+            keys: Vec::new(),
+        }
+    }
+
+    fn key(code: KeyCode) -> InputEvent {
+        InputEvent::Key {
+            code,
+            state: ButtonState::Pressed,
+        }
+    }
+
+    #[tokio::test]
+    async fn events_are_injected_in_order_per_device() {
+        let emulation = MockEmulation::new();
+        let handle = emulation.handle();
+        let mut injector = Injector::new(Box::new(emulation), 64);
+        let peer = MachineId::new();
+
+        injector.add_device(peer, keyboard(1));
+        injector.add_device(peer, keyboard(2));
+        assert!(injector.inject(peer, DeviceId(1), vec![key(KeyCode::KeyA)]));
+        assert!(injector.inject(peer, DeviceId(2), vec![key(KeyCode::KeyX)]));
+        assert!(injector.inject(peer, DeviceId(1), vec![key(KeyCode::KeyB)]));
+        assert!(!injector.inject(peer, DeviceId(3), vec![key(KeyCode::KeyC)]));
+
+        injector.shutdown().await.unwrap();
+
+        let events = handle.injected_events();
+        assert_eq!(events.len(), 3);
+        let first_device = events
+            .iter()
+            .find(|e| e.event == key(KeyCode::KeyA))
+            .unwrap()
+            .device;
+        let order: Vec<_> = events
+            .iter()
+            .filter(|e| e.device == first_device)
+            .map(|e| e.event.clone())
+            .collect();
+        assert_eq!(order, vec![key(KeyCode::KeyA), key(KeyCode::KeyB)]);
+        assert!(handle.devices().is_empty());
+        assert!(handle.is_shutdown());
+    }
+
+    #[tokio::test]
+    async fn removing_peer_drains_its_queues() {
+        let emulation = MockEmulation::new();
+        let handle = emulation.handle();
+        let mut injector = Injector::new(Box::new(emulation), 64);
+        let peer = MachineId::new();
+
+        injector.add_device(peer, keyboard(1));
+        assert!(injector.inject(peer, DeviceId(1), vec![key(KeyCode::KeyA)]));
+        injector.remove_peer(peer);
+        assert!(!injector.inject(peer, DeviceId(1), vec![key(KeyCode::KeyB)]));
+
+        injector.shutdown().await.unwrap();
+        assert_eq!(handle.injected_events().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn smoke_test_checks_each_device() {
+        let emulation = MockEmulation::new();
+        let handle = emulation.handle();
+        let mut injector = Injector::new(Box::new(emulation), 64);
+        let peer = MachineId::new();
+        let timeout = Duration::from_secs(1);
+
+        assert!(injector.smoke_test(peer, timeout).await.is_err());
+
+        injector.add_device(peer, keyboard(1));
+        injector.add_device(
+            peer,
+            DeviceInfo {
+                id: DeviceId(2),
+                name: "Mouse".to_string(),
+                capabilities: vec![DeviceCapability::RelativeMouse],
+                keys: Vec::new(),
+            },
+        );
+        injector.smoke_test(peer, timeout).await.unwrap();
+        let events: Vec<_> = handle.take_injected_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, InputEvent::MouseMove { dx: 0, dy: 0 });
+
+        handle.fail_injects(true);
+        let err = injector.smoke_test(peer, timeout).await.unwrap_err();
+        assert!(matches!(err, InputError::Inject(_)), "{err}");
+    }
+
+    /// A mock backend whose `inject` never finishes for `KeyZ`.
+    struct StallingEmulation(MockEmulation);
+
+    #[async_trait::async_trait]
+    impl InputEmulation for StallingEmulation {
+        async fn create_device(
+            &mut self,
+            info: &DeviceInfo,
+        ) -> Result<VirtualDeviceId, InputError> {
+            self.0.create_device(info).await
+        }
+
+        async fn inject(
+            &mut self,
+            device: VirtualDeviceId,
+            event: InputEvent,
+        ) -> Result<(), InputError> {
+            if event == key(KeyCode::KeyZ) {
+                std::future::pending::<()>().await;
+            }
+            self.0.inject(device, event).await
+        }
+
+        async fn destroy_device(&mut self, device: VirtualDeviceId) -> Result<(), InputError> {
+            self.0.destroy_device(device).await
+        }
+
+        async fn shutdown(&mut self) -> Result<(), InputError> {
+            self.0.shutdown().await
+        }
+    }
+
+    #[tokio::test]
+    async fn hung_device_does_not_block_other_queues() {
+        let emulation = MockEmulation::new();
+        let handle = emulation.handle();
+        let mut injector = Injector::new(Box::new(StallingEmulation(emulation)), 64);
+        let peer = MachineId::new();
+
+        injector.add_device(peer, keyboard(1));
+        injector.add_device(peer, keyboard(2));
+        injector
+            .smoke_test(peer, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert!(injector.inject(peer, DeviceId(1), vec![key(KeyCode::KeyZ)]));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(injector.inject(peer, DeviceId(2), vec![key(KeyCode::KeyA)]));
+
+        let injected = tokio::time::timeout(INJECT_TIMEOUT * 4, async {
+            while handle.injected_events().is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await;
+        assert!(injected.is_ok(), "device 2 stayed blocked behind device 1");
+        assert_eq!(handle.injected_events()[0].event, key(KeyCode::KeyA));
+
+        injector.shutdown().await.unwrap();
+        assert!(handle.is_shutdown());
+    }
+
+    #[test]
+    fn full_backlog_drops_oldest_batches() {
+        let backlog = Backlog::default();
+        assert_eq!(backlog.push(vec![key(KeyCode::KeyA)], 2), 0);
+        assert_eq!(backlog.push(vec![key(KeyCode::KeyB)], 2), 0);
+        assert_eq!(backlog.push(vec![key(KeyCode::KeyC)], 2), 1);
+
+        let batches = backlog.batches.lock().unwrap();
+        assert_eq!(
+            batches.iter().flatten().cloned().collect::<Vec<_>>(),
+            vec![key(KeyCode::KeyB), key(KeyCode::KeyC)]
+        );
+    }
+
+    fn release(code: KeyCode) -> InputEvent {
+        InputEvent::Key {
+            code,
+            state: ButtonState::Released,
+        }
+    }
+
+    #[test]
+    fn full_backlog_keeps_dropped_releases() {
+        let backlog = Backlog::default();
+        backlog.push(vec![key(KeyCode::KeyA), release(KeyCode::KeyA)], 1);
+        backlog.push(vec![release(KeyCode::KeyA), key(KeyCode::KeyB)], 1);
+        assert_eq!(backlog.push(vec![key(KeyCode::KeyC)], 1), 1);
+
+        let batches = backlog.batches.lock().unwrap();
+        assert_eq!(
+            batches.iter().flatten().cloned().collect::<Vec<_>>(),
+            vec![release(KeyCode::KeyA), key(KeyCode::KeyC)]
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_release_is_retried() {
+        let emulation = MockEmulation::new();
+        let handle = emulation.handle();
+        let mut injector = Injector::new(Box::new(emulation), 64);
+        let peer = MachineId::new();
+
+        injector.add_device(peer, keyboard(1));
+        injector
+            .smoke_test(peer, Duration::from_secs(1))
+            .await
+            .unwrap();
+        handle.fail_injects(true);
+        assert!(injector.inject(peer, DeviceId(1), vec![release(KeyCode::KeyA)]));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        handle.fail_injects(false);
+        assert!(injector.inject(peer, DeviceId(1), vec![key(KeyCode::KeyB)]));
+        injector.shutdown().await.unwrap();
+
+        let events: Vec<_> = handle
+            .injected_events()
+            .into_iter()
+            .map(|e| e.event)
+            .collect();
+        assert_eq!(events, vec![release(KeyCode::KeyA), key(KeyCode::KeyB)]);
+    }
+}
@@ -0,0 +1,589 @@
+//! Local IPC between the CLI and a running daemon.
+//!
+//! The CLI connects to a Unix domain socket in the runtime directory and
+//! exchanges a single request/response pair per connection. Frames use the
+//! same length-prefixed bincode encoding as the peer wire protocol.
+
+use bincode::{Decode, Encode};
+use cross_control_types::{
+    DeviceCapability, DeviceId, ErrorCode, FocusHint, KeyboardLayout, MachineId, Position,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::error::DaemonError;
+use crate::layout::LayoutSuggestion;
+use crate::metrics::PeerDay;
+
+/// A request from the CLI to the daemon.
+#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+pub enum IpcRequest {
+    /// Short verification codes derived from each session's TLS secret.
+    SessionCodes,
+    /// Revoke trust in a peer (by name or machine ID), close its session and
+    /// refuse its certificate from now on.
+    Ban { peer: String },
+    /// Pause (`true`) or resume (`false`) all cross-control activity.
+    SetPaused(bool),
+    /// Summary of the daemon's runtime state.
+    Status,
+    /// The user switched keyboard layout, e.g. reported by a desktop hook;
+    /// peers are told straight away.
+    SetKeyboardLayout(KeyboardLayout),
+    /// Captured local devices and the virtual devices created for peers.
+    Devices,
+    /// Recent state transitions and messages of a peer's session (by name
+    /// or machine ID).
+    History { peer: String },
+    /// Traffic counters of a peer's session (by name or machine ID), or of
+    /// every session if `None`.
+    Stats { peer: Option<String> },
+    /// Like `Stats`, but start the counters again from zero once they are
+    /// read, e.g. before benchmarking a configuration change.
+    ResetStats { peer: Option<String> },
+    /// Daily usage totals for the last `days` days, optionally for one peer
+    /// (by name).
+    Trends { days: u64, peer: Option<String> },
+    /// Adjacency corrections suggested by edge and crossing usage.
+    LayoutSuggest,
+    /// Push layout TOML (`[[screens]]`, `[[screen_adjacency]]`) to a peer.
+    PushConfig { peer: String, layout: String },
+    /// Ask a peer to run a command from its `[commands]` allowlist.
+    RunCommand { peer: String, command: String },
+    /// Run a command on this machine and on every trusted, connected peer,
+    /// such as `lock-screen` when leaving the desk. This machine runs its
+    /// own `[commands]` entry last.
+    RunEverywhere { command: String },
+    /// Switch to a named profile (`default` for the top-level layout).
+    SwitchProfile { name: String },
+    /// Warp the cursor to pixel `(x, y)` on `screen`: this machine's name,
+    /// or a connected peer's name or machine ID. A peer that isn't being
+    /// controlled yet is entered first.
+    Teleport { screen: String, x: u32, y: u32 },
+    /// Type `text` into a peer (by name or machine ID) as key strokes, as
+    /// if on a US keyboard. The peer is entered first if needed.
+    TypeText { screen: String, text: String },
+    /// Ask a peer (by name or machine ID) to let this machine take control
+    /// of it. Control is taken once the peer agrees.
+    RequestControl { screen: String },
+    /// Send one application-level `Ping` to a peer (by name or machine ID)
+    /// and wait for its `Pong`, to measure the round trip through both
+    /// daemons rather than just the network.
+    Ping { screen: String },
+    /// Dial a machine to pair with it, using the daemon's endpoint. The
+    /// session is held until a matching `PairConfirm`.
+    PairConnect { address: String },
+    /// Accept or reject a connection made by `PairConnect`. `position` is
+    /// required unless the peer is already in the layout.
+    PairConfirm {
+        fingerprint: String,
+        accept: bool,
+        position: Option<Position>,
+    },
+}
+
+/// The daemon's reply to an [`IpcRequest`].
+#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+pub enum IpcResponse {
+    SessionCodes(Vec<SessionCode>),
+    /// The peer was banned and `fingerprint` added to the denylist.
+    Banned {
+        name: String,
+        fingerprint: String,
+    },
+    /// The pause state after a `SetPaused` request.
+    Paused(bool),
+    Status(Box<StatusReport>),
+    /// The keyboard layout was recorded and sent to `peers` peers.
+    KeyboardLayoutSet {
+        peers: usize,
+    },
+    Devices(DeviceReport),
+    History(SessionHistory),
+    /// Per-session counters, by peer name; for `ResetStats`, as they were
+    /// just before the reset.
+    Stats(Vec<SessionStatsReport>),
+    /// Usage totals per peer and day, ordered by peer and day.
+    Trends(Vec<PeerDay>),
+    LayoutSuggestions(Vec<LayoutSuggestion>),
+    /// The named peer applied a pushed layout.
+    ConfigPushed {
+        name: String,
+    },
+    /// The named peer started the command.
+    CommandStarted {
+        name: String,
+        command: String,
+    },
+    /// Per-machine outcome of a `RunEverywhere` request, this machine last.
+    CommandsRun(Vec<CommandOutcome>),
+    /// The active profile after a `SwitchProfile` request.
+    ProfileSwitched {
+        name: String,
+        screens: usize,
+    },
+    /// The cursor is on `screen` at `(x, y)`, clamped to the screen's size.
+    Teleported {
+        screen: String,
+        x: u32,
+        y: u32,
+    },
+    /// The peer agreed to a `RequestControl`; this machine is entering it.
+    ControlGranted {
+        screen: String,
+    },
+    /// The peer answered a `Ping` after `rtt_us` microseconds.
+    Pong {
+        screen: String,
+        rtt_us: u64,
+    },
+    /// `chars` characters were typed into `screen`.
+    Typed {
+        screen: String,
+        chars: usize,
+    },
+    /// A `PairConnect` reached the peer; its certificate fingerprint and
+    /// session code await confirmation.
+    PairPending {
+        name: String,
+        fingerprint: String,
+        code: String,
+    },
+    /// The peer was pinned and its session adopted.
+    Paired {
+        name: String,
+        position: Position,
+    },
+    /// The pairing was rejected and the connection closed.
+    PairCancelled,
+    /// The request could not be served.
+    Error {
+        code: ErrorCode,
+        message: String,
+    },
+}
+
+impl IpcResponse {
+    /// An error response.
+    pub fn error(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self::Error {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<DaemonError> for IpcResponse {
+    fn from(e: DaemonError) -> Self {
+        Self::error(e.code(), e.to_string())
+    }
+}
+
+/// Whether one machine started a command.
+#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+pub struct CommandOutcome {
+    pub name: String,
+    /// Why the command was not started; `None` if it was.
+    pub error: Option<String>,
+}
+
+/// Verification code for one live session.
+///
+/// Both ends derive the code from the TLS exporter, so matching codes on
+/// the two machines prove they share the same session secret.
+#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+pub struct SessionCode {
+    pub name: String,
+    pub machine_id: MachineId,
+    /// The session ID both machines log as `session`.
+    pub session: String,
+    pub code: String,
+}
+
+/// A session's recent history, reported to `cross-control history`.
+#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+pub struct SessionHistory {
+    pub name: String,
+    pub machine_id: MachineId,
+    /// The session ID both machines log as `session`.
+    pub session: String,
+    /// Current session state.
+    pub state: String,
+    /// Oldest first.
+    pub events: Vec<HistoryEvent>,
+}
+
+/// A session's traffic counters, reported to `cross-control stats`.
+#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+pub struct SessionStatsReport {
+    pub name: String,
+    pub machine_id: MachineId,
+    /// The session ID both machines log as `session`.
+    pub session: String,
+    /// When counting started (session start or last reset), in
+    /// milliseconds since the Unix epoch.
+    pub since_ms: u64,
+    /// Milliseconds counted over.
+    pub elapsed_ms: u64,
+    pub events_sent: u64,
+    pub events_received: u64,
+    /// Input events sent or received but not delivered.
+    pub events_dropped: u64,
+    /// Bytes on the session's streams, framing included.
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// Recent keepalive round-trip times in microseconds, oldest first.
+    pub rtt_us: Vec<u64>,
+}
+
+/// One state transition or significant message.
+#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+pub struct HistoryEvent {
+    /// Milliseconds since the Unix epoch.
+    pub at_ms: u64,
+    /// Session state after the event.
+    pub state: String,
+    pub event: String,
+}
+
+/// Runtime state reported to `cross-control status`.
+#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct StatusReport {
+    pub paused: bool,
+    /// Whether input from peers is only logged (`input.dry_run`).
+    pub dry_run: bool,
+    /// Whether keyboard input is kept from peers by the private hotkey.
+    pub private_input: bool,
+    pub session_count: usize,
+    /// Name of the peer we are controlling, if any.
+    pub controlling: Option<String>,
+    /// Where input to `controlling` is landing, as last hinted by it.
+    pub remote_focus: Option<FocusHint>,
+    /// Name of the peer controlling us, if any.
+    pub controlled_by: Option<String>,
+    /// Whether someone is using this machine's own input while
+    /// `controlled_by` drives it.
+    pub local_user_active: bool,
+    /// Whether `controlling` reported someone using its own input.
+    pub remote_user_active: bool,
+    /// Whether local input devices are grabbed.
+    pub input_grab: InputGrab,
+    /// `wait_on_start` screens the daemon is still waiting for before
+    /// reporting readiness.
+    pub waiting_on: Vec<String>,
+    /// Connected peers running a different version.
+    pub version_skew: Vec<VersionSkewReport>,
+    /// Active layout profile; `None` for the top-level layout.
+    pub profile: Option<String>,
+    /// Identity collisions seen by discovery.
+    pub conflicts: Vec<String>,
+    /// The last error each peer reported.
+    pub peer_errors: Vec<PeerErrorReport>,
+    /// Peers whose link is over an `[alarms]` threshold.
+    pub link_alarms: Vec<LinkAlarmReport>,
+    /// Keyboard layout of this machine, once known.
+    pub keyboard_layout: Option<KeyboardLayout>,
+    /// Keyboard layouts connected peers reported.
+    pub peer_keyboard_layouts: Vec<PeerKeyboardLayout>,
+    /// Entries dropped to stay within `[limits]`.
+    pub evictions: Evictions,
+    /// When the daemon started, in seconds since the Unix epoch.
+    pub started_at: u64,
+    pub uptime_secs: u64,
+    pub counters: Counters,
+    /// Daemon version and the git commit it was built from.
+    pub version: String,
+    pub commit: String,
+}
+
+/// Activity counts since startup.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
+pub struct Counters {
+    /// Times control crossed to a peer.
+    pub crossings: u64,
+    /// Input events sent to peers we controlled.
+    pub events_forwarded: u64,
+    /// Input events received from a controlling peer and queued for
+    /// injection.
+    pub events_injected: u64,
+    /// Sessions established with a machine that had connected before.
+    pub reconnects: u64,
+    /// Wall clock jumps the daemon re-baselined its timers after.
+    pub clock_jumps: u64,
+    /// Times someone started using this machine's own input while a peer
+    /// controlled it.
+    pub local_input_conflicts: u64,
+}
+
+/// Devices reported to `cross-control devices`.
+#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+pub struct DeviceReport {
+    /// Local devices whose input is captured and shared.
+    pub captured: Vec<DeviceEntry>,
+    /// Virtual devices created for each peer's devices, by peer name.
+    pub remote: Vec<PeerDevices>,
+}
+
+/// One input device and how many events it has carried.
+#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+pub struct DeviceEntry {
+    pub id: DeviceId,
+    pub name: String,
+    pub capabilities: Vec<DeviceCapability>,
+    /// Events captured from the device, or queued for injection into it,
+    /// since startup (or since the peer announced it).
+    pub events: u64,
+}
+
+/// The virtual devices created for one peer.
+#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+pub struct PeerDevices {
+    pub peer: String,
+    pub devices: Vec<DeviceEntry>,
+    /// Device announcements refused as malformed or over
+    /// `limits.peer_devices`.
+    pub rejected: u64,
+}
+
+/// Whether local input devices are grabbed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
+pub enum InputGrab {
+    #[default]
+    Released,
+    Grabbed,
+    /// Wanted, but the last grab failed; it is retried with backoff.
+    Retrying,
+}
+
+/// Counts of entries dropped to stay within `[limits]`, since startup.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
+pub struct Evictions {
+    /// Input batches dropped from full device queues.
+    pub input_batches: u64,
+    /// Peer error reports forgotten.
+    pub peer_errors: u64,
+    /// Discovered peers forgotten.
+    pub discovered_peers: u64,
+}
+
+impl Evictions {
+    /// Total entries dropped.
+    pub fn total(&self) -> u64 {
+        self.input_batches + self.peer_errors + self.discovered_peers
+    }
+}
+
+/// A connected peer whose protocol or software version differs from this
+/// daemon's.
+#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+pub struct VersionSkewReport {
+    pub peer: String,
+    /// The peer's software version, if it reported one.
+    pub version: Option<String>,
+    /// The protocol version the peer speaks.
+    pub protocol: String,
+    /// Features unavailable with this peer because it predates them.
+    pub disabled: Vec<String>,
+}
+
+/// An error a peer reported to this daemon.
+#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+pub struct PeerErrorReport {
+    /// Peer name, or its address if the handshake failed before the name
+    /// was known.
+    pub peer: String,
+    pub code: ErrorCode,
+    pub detail: String,
+}
+
+/// The keyboard layout a peer types with.
+#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+pub struct PeerKeyboardLayout {
+    pub peer: String,
+    pub layout: KeyboardLayout,
+}
+
+/// A raised link quality alarm.
+#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
+pub struct LinkAlarmReport {
+    pub peer: String,
+    /// What is over threshold, e.g. `RTT 55ms > 40ms`.
+    pub reason: String,
+}
+
+#[cfg(unix)]
+pub use unix::{request, serve};
+
+#[cfg(unix)]
+mod unix {
+    use std::fs::Permissions;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+    use std::path::Path;
+
+    use cross_control_protocol::wire::{decode_message, encode_message, MAX_MESSAGE_SIZE};
+    use cross_control_protocol::ProtocolError;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{UnixListener, UnixStream};
+    use tokio::sync::{mpsc, oneshot};
+    use tracing::{debug, info, warn};
+
+    use super::{IpcRequest, IpcResponse};
+    use crate::daemon::DaemonEvent;
+    use crate::error::DaemonError;
+
+    /// Serve IPC requests on `path`, forwarding each to the daemon event loop.
+    ///
+    /// Removes a stale socket file left by a previous run before binding.
+    /// The socket's directory must belong to this user and be closed to
+    /// everyone else, the socket itself is made `0600`, and connections
+    /// from other users are dropped before a request is read.
+    pub async fn serve(
+        path: &Path,
+        event_tx: mpsc::Sender<DaemonEvent>,
+    ) -> Result<(), DaemonError> {
+        // Check the directory before binding, so the socket never exists
+        // somewhere other users could reach it, whatever its own mode.
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        check_private_dir(dir)?;
+        let _ = std::fs::remove_file(path);
+        let listener = UnixListener::bind(path)
+            .map_err(|e| DaemonError::Config(format!("failed to bind IPC socket: {e}")))?;
+        let uid = match secure_socket(path, dir) {
+            Ok(uid) => uid,
+            Err(e) => {
+                let _ = std::fs::remove_file(path);
+                return Err(e);
+            }
+        };
+        info!(path = %path.display(), "IPC listening");
+
+        loop {
+            let (stream, _) = match listener.accept().await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    warn!(error = %e, "IPC accept failed");
+                    continue;
+                }
+            };
+            match stream.peer_cred() {
+                Ok(cred) if cred.uid() == uid => {}
+                Ok(cred) => {
+                    warn!(uid = cred.uid(), "rejected IPC client of another user");
+                    continue;
+                }
+                Err(e) => {
+                    warn!(error = %e, "rejected IPC client without credentials");
+                    continue;
+                }
+            }
+            let event_tx = event_tx.clone();
+            crate::supervisor::spawn("ipc-client", async move {
+                if let Err(e) = handle_client(stream, event_tx).await {
+                    debug!(error = %e, "IPC client error");
+                }
+            });
+        }
+    }
+
+    /// Restrict a freshly bound socket to its owner, and check that its
+    /// directory is private to the same user. Returns that user's uid.
+    /// Refuse to serve from a directory that anyone but its owner can enter.
+    fn check_private_dir(dir: &Path) -> Result<(), DaemonError> {
+        let mode = std::fs::metadata(dir)
+            .map_err(|e| {
+                DaemonError::Config(format!(
+                    "failed to inspect IPC socket directory {}: {e}",
+                    dir.display()
+                ))
+            })?
+            .mode();
+        if mode & 0o077 != 0 {
+            return Err(DaemonError::Config(format!(
+                "IPC socket directory {} must have mode 0700",
+                dir.display()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Restrict the freshly bound socket and confirm that its directory
+    /// belongs to us. Returns our uid.
+    fn secure_socket(path: &Path, dir: &Path) -> Result<u32, DaemonError> {
+        let config_err = |what: &str, e: std::io::Error| {
+            DaemonError::Config(format!("failed to {what} {}: {e}", path.display()))
+        };
+        std::fs::set_permissions(path, Permissions::from_mode(0o600))
+            .map_err(|e| config_err("restrict IPC socket", e))?;
+        // This process just created the socket, so it is owned by our uid.
+        let uid = std::fs::metadata(path)
+            .map_err(|e| config_err("inspect IPC socket", e))?
+            .uid();
+        let dir_meta = std::fs::metadata(dir).map_err(|e| config_err("inspect directory of", e))?;
+        if dir_meta.uid() != uid || dir_meta.mode() & 0o077 != 0 {
+            return Err(DaemonError::Config(format!(
+                "IPC socket directory {} must be owned by uid {uid} with mode 0700",
+                dir.display()
+            )));
+        }
+        Ok(uid)
+    }
+
+    async fn handle_client(
+        mut stream: UnixStream,
+        event_tx: mpsc::Sender<DaemonEvent>,
+    ) -> Result<(), DaemonError> {
+        let request: IpcRequest = read_frame(&mut stream).await?;
+        let (reply_tx, reply_rx) = oneshot::channel();
+        event_tx
+            .send(DaemonEvent::Ipc {
+                request,
+                reply: reply_tx,
+            })
+            .await
+            .map_err(|_| DaemonError::NotRunning)?;
+        let response = reply_rx.await.map_err(|_| DaemonError::NotRunning)?;
+        write_frame(&mut stream, &response).await
+    }
+
+    /// Send a single request to the daemon listening on `path`.
+    pub async fn request(path: &Path, request: &IpcRequest) -> Result<IpcResponse, DaemonError> {
+        let mut stream = UnixStream::connect(path)
+            .await
+            .map_err(|_| DaemonError::NotRunning)?;
+        write_frame(&mut stream, request).await?;
+        read_frame(&mut stream).await
+    }
+
+    async fn read_frame<T: bincode::Decode<()>>(stream: &mut UnixStream) -> Result<T, DaemonError> {
+        let mut len_buf = [0u8; 4];
+        stream
+            .read_exact(&mut len_buf)
+            .await
+            .map_err(|e| ProtocolError::Connection(e.to_string()))?;
+        let len = u32::from_be_bytes(len_buf);
+        if len > MAX_MESSAGE_SIZE {
+            return Err(ProtocolError::Deserialization(format!(
+                "IPC frame size {len} exceeds maximum {MAX_MESSAGE_SIZE}"
+            ))
+            .into());
+        }
+        let mut payload = vec![0u8; len as usize];
+        stream
+            .read_exact(&mut payload)
+            .await
+            .map_err(|e| ProtocolError::Connection(e.to_string()))?;
+        Ok(decode_message(&payload)?)
+    }
+
+    async fn write_frame<T: bincode::Encode>(
+        stream: &mut UnixStream,
+        msg: &T,
+    ) -> Result<(), DaemonError> {
+        let bytes = encode_message(msg)?;
+        stream
+            .write_all(&bytes)
+            .await
+            .map_err(|e| ProtocolError::Connection(e.to_string()))?;
+        Ok(())
+    }
+}
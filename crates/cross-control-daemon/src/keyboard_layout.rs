@@ -0,0 +1,124 @@
+//! Local keyboard layout detection.
+//!
+//! With the `linux` feature the daemon reads the keyboard layout from
+//! systemd-localed (`org.freedesktop.locale1`) and follows changes to its
+//! layout and variant, so peers hear about a `localectl set-x11-keymap`
+//! without a reconnect.
+//!
+//! localed only holds the configured layouts, not which XKB group is
+//! active, so the first group is reported. Switching groups, or input
+//! sources that only the desktop knows about, goes unseen and has to be
+//! reported with `cross-control keyboard-layout`. Without a supported
+//! backend the watcher returns immediately.
+//!
+//! The layout is informational: it is shown in `status`, and input is
+//! still forwarded as physical key codes, not translated by layout.
+
+use cross_control_types::KeyboardLayout;
+use tokio::sync::mpsc;
+
+use crate::daemon::DaemonEvent;
+
+/// Forward the local keyboard layout, and every change to it, to the daemon
+/// until the event channel closes.
+pub async fn watch(event_tx: mpsc::Sender<DaemonEvent>) {
+    #[cfg(all(feature = "linux", target_os = "linux"))]
+    if let Err(e) = localed::watch(&event_tx).await {
+        tracing::debug!(error = %e, "localed keyboard layout unavailable");
+    }
+    #[cfg(not(all(feature = "linux", target_os = "linux")))]
+    drop(event_tx);
+}
+
+/// The default layout from XKB's comma-separated `layout` and `variant`
+/// lists, which is the first group of each. `None` if no layout is set.
+pub fn first_group(layouts: &str, variants: &str) -> Option<KeyboardLayout> {
+    let layout = layouts.split(',').next()?.trim();
+    if layout.is_empty() {
+        return None;
+    }
+    Some(KeyboardLayout {
+        layout: layout.to_string(),
+        variant: variants
+            .split(',')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+    })
+}
+
+#[cfg(all(feature = "linux", target_os = "linux"))]
+mod localed {
+    use futures_lite::{stream, StreamExt};
+    use tokio::sync::mpsc;
+    use tracing::info;
+
+    use crate::daemon::DaemonEvent;
+
+    #[zbus::proxy(
+        interface = "org.freedesktop.locale1",
+        default_service = "org.freedesktop.locale1",
+        default_path = "/org/freedesktop/locale1"
+    )]
+    trait Locale {
+        #[zbus(property, name = "X11Layout")]
+        fn x11_layout(&self) -> zbus::Result<String>;
+
+        #[zbus(property, name = "X11Variant")]
+        fn x11_variant(&self) -> zbus::Result<String>;
+    }
+
+    pub async fn watch(event_tx: &mpsc::Sender<DaemonEvent>) -> zbus::Result<()> {
+        let connection = zbus::Connection::system().await?;
+        let locale = LocaleProxy::new(&connection).await?;
+        // Each change re-reads both, so one to either is enough to wake on.
+        let mut changes = stream::or(
+            locale.receive_x11_layout_changed().await,
+            locale.receive_x11_variant_changed().await,
+        );
+        info!("watching localed for keyboard layout changes");
+
+        loop {
+            let layouts = locale.x11_layout().await?;
+            let variants = locale.x11_variant().await.unwrap_or_default();
+            if let Some(layout) = super::first_group(&layouts, &variants) {
+                if event_tx
+                    .send(DaemonEvent::KeyboardLayoutChanged(layout))
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+            if changes.next().await.is_none() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_layout_is_first_group() {
+        assert_eq!(
+            first_group("us,gr", "dvorak,"),
+            Some(KeyboardLayout {
+                layout: "us".to_string(),
+                variant: "dvorak".to_string(),
+            })
+        );
+        assert_eq!(
+            first_group("gr", ""),
+            Some(KeyboardLayout {
+                layout: "gr".to_string(),
+                variant: String::new(),
+            })
+        );
+        assert_eq!(first_group("", ""), None);
+    }
+}
@@ -0,0 +1,230 @@
+//! Screen layout usage tracking and adjacency suggestions.
+//!
+//! A wrong `position` in `[[screens]]` is the most common setup mistake. It
+//! shows up in two ways: the user keeps pushing the cursor against an edge
+//! with no neighbour (where they expect the screen to be), and crossings into
+//! the configured screen bounce straight back.
+
+use std::collections::HashMap;
+
+use bincode::{Decode, Encode};
+use cross_control_types::{Position, ScreenEdge, ScreenGeometry};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{ScreenConfig, ScreenMode};
+
+/// Pushes or bounces needed before a layout change is suggested.
+const MIN_EVIDENCE: u32 = 5;
+
+/// A proposed correction to one configured screen.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
+pub struct LayoutSuggestion {
+    pub screen: String,
+    pub current: Position,
+    /// Where the screen probably is, if usage points at a specific edge.
+    pub suggested: Option<Position>,
+    pub reason: String,
+}
+
+/// Edge and crossing counters for the current daemon run.
+#[derive(Debug, Default)]
+pub struct LayoutStats {
+    /// Arrivals at local edges that have no configured neighbour.
+    edge_pushes: HashMap<ScreenEdge, u32>,
+    /// Completed crossings per screen name.
+    crossings: HashMap<String, u32>,
+    /// Crossings per screen name that returned almost immediately.
+    bounces: HashMap<String, u32>,
+    /// Edges the cursor was on after the previous move.
+    at_edges: Vec<ScreenEdge>,
+}
+
+impl LayoutStats {
+    /// Record a local cursor position, counting each arrival at an edge
+    /// outside `configured`.
+    pub fn record_cursor(
+        &mut self,
+        screen: &ScreenGeometry,
+        x: i32,
+        y: i32,
+        configured: &[ScreenEdge],
+    ) {
+        let at_edges: Vec<ScreenEdge> = [
+            ScreenEdge::Left,
+            ScreenEdge::Right,
+            ScreenEdge::Top,
+            ScreenEdge::Bottom,
+        ]
+        .into_iter()
+        .filter(|edge| screen.is_at_edge(x, y, *edge))
+        .collect();
+        for edge in &at_edges {
+            if !self.at_edges.contains(edge) && !configured.contains(edge) {
+                *self.edge_pushes.entry(*edge).or_default() += 1;
+            }
+        }
+        self.at_edges = at_edges;
+    }
+
+    /// Record a crossing into `screen`.
+    pub fn record_crossing(&mut self, screen: &str) {
+        *self.crossings.entry(screen.to_string()).or_default() += 1;
+    }
+
+    /// Record that a crossing into `screen` came straight back.
+    pub fn record_bounce(&mut self, screen: &str) {
+        *self.bounces.entry(screen.to_string()).or_default() += 1;
+    }
+
+    /// Propose adjacency corrections for `screens` from the recorded usage.
+    pub fn suggest(&self, screens: &[ScreenConfig]) -> Vec<LayoutSuggestion> {
+        let candidates: Vec<&ScreenConfig> = screens
+            .iter()
+            .filter(|sc| sc.mode == ScreenMode::Control)
+            .collect();
+        let count = |map: &HashMap<String, u32>, name: &str| map.get(name).copied().unwrap_or(0);
+        let mut suggestions: Vec<LayoutSuggestion> = Vec::new();
+
+        // Busiest unconfigured edge first, each matched to the screen that
+        // looks most misplaced: most bounces, then fewest crossings.
+        let mut pushes: Vec<(ScreenEdge, u32)> = self
+            .edge_pushes
+            .iter()
+            .filter(|(_, n)| **n >= MIN_EVIDENCE)
+            .map(|(edge, n)| (*edge, *n))
+            .collect();
+        pushes.sort_by_key(|(_, n)| std::cmp::Reverse(*n));
+        for (edge, n) in pushes {
+            let screen = candidates
+                .iter()
+                .filter(|sc| !suggestions.iter().any(|s| s.screen == sc.name))
+                .filter(|sc| count(&self.crossings, &sc.name) < n)
+                .max_by_key(|sc| {
+                    (
+                        count(&self.bounces, &sc.name),
+                        std::cmp::Reverse(count(&self.crossings, &sc.name)),
+                    )
+                });
+            let Some(screen) = screen else {
+                continue;
+            };
+            let suggested = Position::from_local_edge(edge);
+            suggestions.push(LayoutSuggestion {
+                screen: screen.name.clone(),
+                current: screen.position,
+                suggested: Some(suggested),
+                reason: format!(
+                    "you configured {} {:?} but pushed against the {edge:?} edge {n} times \
+                     (crossings into {}: {}) — did you mean {suggested:?}?",
+                    screen.name,
+                    screen.position,
+                    screen.name,
+                    count(&self.crossings, &screen.name),
+                ),
+            });
+        }
+
+        for screen in candidates {
+            if suggestions.iter().any(|s| s.screen == screen.name) {
+                continue;
+            }
+            let bounces = count(&self.bounces, &screen.name);
+            let crossings = count(&self.crossings, &screen.name);
+            if bounces >= MIN_EVIDENCE && bounces * 2 >= crossings {
+                suggestions.push(LayoutSuggestion {
+                    screen: screen.name.clone(),
+                    current: screen.position,
+                    suggested: None,
+                    reason: format!(
+                        "{bounces} of {crossings} crossings into {} came straight back — \
+                         check that it is really {:?} of this screen",
+                        screen.name, screen.position,
+                    ),
+                });
+            }
+        }
+        suggestions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PointerScale;
+
+    fn screen(name: &str, position: Position) -> ScreenConfig {
+        ScreenConfig {
+            name: name.to_string(),
+            group: None,
+            address: None,
+            position,
+            fingerprint: None,
+            mode: ScreenMode::Control,
+            wake_on_enter: true,
+            locate_on_enter: false,
+            pointer_scale: PointerScale::default(),
+            share_devices: None,
+            clipboard: true,
+            wait_on_start: false,
+            commands: Vec::new(),
+            release_hotkey: None,
+        }
+    }
+
+    fn push(stats: &mut LayoutStats, geometry: &ScreenGeometry, x: i32, configured: &[ScreenEdge]) {
+        stats.record_cursor(geometry, x, 500, configured);
+        stats.record_cursor(geometry, 960, 500, configured);
+    }
+
+    #[test]
+    fn pushes_against_unconfigured_edge_suggest_it() {
+        let geometry = ScreenGeometry::new(1920, 1080);
+        let screens = [screen("b", Position::Above)];
+        let mut stats = LayoutStats::default();
+        for _ in 0..MIN_EVIDENCE {
+            push(&mut stats, &geometry, 1919, &[ScreenEdge::Top]);
+        }
+        stats.record_crossing("b");
+
+        let suggestions = stats.suggest(&screens);
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].screen, "b");
+        assert_eq!(suggestions[0].current, Position::Above);
+        assert_eq!(suggestions[0].suggested, Some(Position::Right));
+    }
+
+    #[test]
+    fn resting_on_an_edge_counts_once() {
+        let geometry = ScreenGeometry::new(1920, 1080);
+        let mut stats = LayoutStats::default();
+        for y in 0..20 {
+            stats.record_cursor(&geometry, 0, 100 + y, &[]);
+        }
+        assert_eq!(stats.edge_pushes.get(&ScreenEdge::Left), Some(&1));
+    }
+
+    #[test]
+    fn configured_edges_and_sparse_usage_suggest_nothing() {
+        let geometry = ScreenGeometry::new(1920, 1080);
+        let screens = [screen("b", Position::Right)];
+        let mut stats = LayoutStats::default();
+        for _ in 0..10 {
+            push(&mut stats, &geometry, 1919, &[ScreenEdge::Right]);
+        }
+        push(&mut stats, &geometry, 0, &[ScreenEdge::Right]);
+        assert!(stats.suggest(&screens).is_empty());
+    }
+
+    #[test]
+    fn frequent_bounces_are_reported() {
+        let screens = [screen("b", Position::Right)];
+        let mut stats = LayoutStats::default();
+        for _ in 0..MIN_EVIDENCE {
+            stats.record_crossing("b");
+            stats.record_bounce("b");
+        }
+        let suggestions = stats.suggest(&screens);
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].suggested, None);
+    }
+}
@@ -3,12 +3,41 @@
 //! Implements the state machine for barrier logic, event routing, session
 //! management, and IPC server for the CLI to communicate with.
 
+pub mod alarms;
+pub mod auth;
+pub mod clock;
 pub mod config;
 pub mod daemon;
+pub mod drag_scroll;
+pub mod edges;
 pub mod error;
+pub mod failsafe;
+pub mod firewall;
+pub mod handshake;
+pub mod hotkey;
+pub mod injector;
+pub mod ipc;
+pub mod keyboard_layout;
+pub mod layout;
+pub mod metrics;
+pub mod network;
+pub mod pairing;
+pub mod power;
+pub mod presence;
+pub mod readiness;
+pub mod screen_state;
 pub mod session;
 pub mod setup;
+pub mod sleep;
 pub mod state;
+pub mod supervisor;
+pub mod watchdog;
+
+/// Version of the daemon crate.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Git commit the daemon was built from, or `unknown` outside a checkout.
+pub const COMMIT: &str = env!("CROSS_CONTROL_COMMIT");
 
 pub use config::Config;
 pub use daemon::{Daemon, DaemonEvent, DaemonStatus};
@@ -0,0 +1,204 @@
+//! Daily usage history.
+//!
+//! For each UTC day and peer, the daemon adds up how long it controlled the
+//! peer, how often the cursor crossed into it, and the keepalive round trips
+//! measured. The totals are kept in `metrics.toml` in the config directory
+//! for `limits.metrics_days` days, so `cross-control stats --since 7d` can
+//! show how much each machine is used across restarts.
+
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+use crate::error::DaemonError;
+
+/// One peer's usage on one UTC day.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
+pub struct PeerDay {
+    /// Days since the Unix epoch.
+    pub day: u64,
+    pub peer: String,
+    /// Time this machine controlled the peer, in milliseconds.
+    pub controlled_ms: u64,
+    /// Crossings into the peer.
+    pub crossings: u64,
+    /// Sum of the keepalive round trips measured, in microseconds.
+    pub rtt_total_us: u64,
+    /// Keepalive round trips measured.
+    pub rtt_samples: u64,
+}
+
+impl PeerDay {
+    /// Mean keepalive round trip, if any was measured.
+    pub fn mean_rtt_us(&self) -> Option<u64> {
+        self.rtt_total_us.checked_div(self.rtt_samples)
+    }
+
+    /// Add another day's (or peer's) totals to these.
+    pub fn add(&mut self, other: &PeerDay) {
+        self.controlled_ms += other.controlled_ms;
+        self.crossings += other.crossings;
+        self.rtt_total_us += other.rtt_total_us;
+        self.rtt_samples += other.rtt_samples;
+    }
+}
+
+/// The UTC day `at` falls on, in days since the Unix epoch.
+pub fn day_of(at: SystemTime) -> u64 {
+    at.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() / 86_400)
+}
+
+/// Usage totals per day and peer, as stored in `metrics.toml`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MetricsHistory {
+    #[serde(default, rename = "day")]
+    days: Vec<PeerDay>,
+    /// Whether anything changed since the last save.
+    #[serde(skip)]
+    dirty: bool,
+}
+
+impl MetricsHistory {
+    /// Read the history at `path`; empty if it is missing or unreadable.
+    pub fn load(path: &Path) -> Self {
+        let Ok(content) = std::fs::read_to_string(path) else {
+            return Self::default();
+        };
+        toml::from_str(&content).unwrap_or_else(|e| {
+            tracing::warn!(path = %path.display(), error = %e, "ignoring unreadable usage history");
+            Self::default()
+        })
+    }
+
+    /// Write the history to `path` if it changed since the last save.
+    pub fn save(&mut self, path: &Path) -> Result<(), DaemonError> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let content = toml::to_string(self)
+            .map_err(|e| DaemonError::Config(format!("failed to encode usage history: {e}")))?;
+        // Write beside the file and rename, so a crash can't truncate it.
+        let tmp = path.with_extension("toml.tmp");
+        std::fs::write(&tmp, content)
+            .and_then(|()| std::fs::rename(&tmp, path))
+            .map_err(|e| DaemonError::Config(format!("failed to write usage history: {e}")))?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    fn entry(&mut self, peer: &str, at: SystemTime) -> &mut PeerDay {
+        let day = day_of(at);
+        self.dirty = true;
+        let index = if let Some(index) = self
+            .days
+            .iter()
+            .position(|d| d.day == day && d.peer == peer)
+        {
+            index
+        } else {
+            self.days.push(PeerDay {
+                day,
+                peer: peer.to_string(),
+                ..PeerDay::default()
+            });
+            self.days.len() - 1
+        };
+        &mut self.days[index]
+    }
+
+    /// Add `elapsed` of control over `peer`.
+    pub fn record_controlled(&mut self, peer: &str, elapsed: Duration, at: SystemTime) {
+        let ms = u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX);
+        self.entry(peer, at).controlled_ms += ms;
+    }
+
+    /// Count a crossing into `peer`.
+    pub fn record_crossing(&mut self, peer: &str, at: SystemTime) {
+        self.entry(peer, at).crossings += 1;
+    }
+
+    /// Add a keepalive round trip to `peer`.
+    pub fn record_rtt(&mut self, peer: &str, rtt: Duration, at: SystemTime) {
+        let entry = self.entry(peer, at);
+        entry.rtt_total_us += u64::try_from(rtt.as_micros()).unwrap_or(u64::MAX);
+        entry.rtt_samples += 1;
+    }
+
+    /// Forget days more than `keep` days before the day of `now`.
+    pub fn prune(&mut self, keep: u64, now: SystemTime) {
+        let first = day_of(now).saturating_sub(keep.saturating_sub(1));
+        let before = self.days.len();
+        self.days.retain(|d| d.day >= first);
+        self.dirty |= self.days.len() != before;
+    }
+
+    /// Totals for the last `days` days up to the day of `now`, optionally
+    /// for one peer, ordered by peer and day.
+    pub fn since(&self, days: u64, peer: Option<&str>, now: SystemTime) -> Vec<PeerDay> {
+        let first = day_of(now).saturating_sub(days.saturating_sub(1));
+        let mut found: Vec<PeerDay> = self
+            .days
+            .iter()
+            .filter(|d| d.day >= first && peer.map_or(true, |p| d.peer == p))
+            .cloned()
+            .collect();
+        found.sort_by(|a, b| a.peer.cmp(&b.peer).then(a.day.cmp(&b.day)));
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(86_400);
+
+    #[test]
+    fn totals_are_kept_per_day_and_peer() {
+        let monday = SystemTime::UNIX_EPOCH + DAY * 20_000;
+        let tuesday = monday + DAY;
+        let mut history = MetricsHistory::default();
+        history.record_crossing("laptop", monday);
+        history.record_controlled("laptop", Duration::from_secs(90), monday);
+        history.record_rtt("laptop", Duration::from_millis(2), monday);
+        history.record_rtt("laptop", Duration::from_millis(4), monday);
+        history.record_crossing("laptop", tuesday);
+        history.record_crossing("desk", tuesday);
+
+        let week = history.since(7, None, tuesday);
+        let keys: Vec<(&str, u64)> = week.iter().map(|d| (d.peer.as_str(), d.day)).collect();
+        assert_eq!(
+            keys,
+            vec![("desk", 20_001), ("laptop", 20_000), ("laptop", 20_001)]
+        );
+        assert_eq!(week[1].controlled_ms, 90_000);
+        assert_eq!(week[1].mean_rtt_us(), Some(3000));
+        assert_eq!(week[2].mean_rtt_us(), None);
+
+        assert_eq!(history.since(1, Some("laptop"), tuesday).len(), 1);
+    }
+
+    #[test]
+    fn old_days_are_pruned_and_history_survives_a_save() {
+        let dir = std::env::temp_dir().join(format!("cc-metrics-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("metrics.toml");
+
+        let start = SystemTime::UNIX_EPOCH + DAY * 20_000;
+        let mut history = MetricsHistory::default();
+        for day in 0..10 {
+            history.record_crossing("laptop", start + DAY * day);
+        }
+        let now = start + DAY * 9;
+        history.prune(3, now);
+        history.save(&path).unwrap();
+
+        let loaded = MetricsHistory::load(&path);
+        let days: Vec<u64> = loaded.since(30, None, now).iter().map(|d| d.day).collect();
+        assert_eq!(days, vec![20_007, 20_008, 20_009]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
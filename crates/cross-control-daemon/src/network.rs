@@ -0,0 +1,242 @@
+//! Local network changes and identification.
+//!
+//! Interface address changes come from netlink on Linux and the platform's
+//! routing notifications elsewhere. On Linux the active Wi-Fi SSID is read
+//! with `nmcli`, falling back to `iwgetid`. Other platforms report no SSID,
+//! and profiles are only switched by hand.
+//!
+//! Routes to configured peers are found by connecting an unbound UDP socket
+//! and reading back the source address the OS picked; nothing is sent.
+
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+use futures_lite::StreamExt;
+use if_watch::{IfEvent, IpNet};
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use crate::config::Config;
+use crate::daemon::DaemonEvent;
+
+/// `daemon.bind` value that listens only on the address peers are reached
+/// through.
+pub const AUTO_BIND: &str = "auto";
+
+/// Quiet period after the last address change before it is reported, so a
+/// burst of changes (DHCP, VPN up) triggers a single re-evaluation.
+const SETTLE: Duration = Duration::from_secs(2);
+
+/// Forward local interface address changes to the daemon until the event
+/// channel closes.
+pub async fn watch_interfaces(event_tx: mpsc::Sender<DaemonEvent>) {
+    let mut watcher = match if_watch::tokio::IfWatcher::new() {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            warn!(error = %e, "interface change notifications unavailable");
+            return;
+        }
+    };
+    let mut addrs: HashSet<IpNet> = HashSet::new();
+    // The watcher starts by listing existing addresses; the first settled
+    // set is the baseline rather than a change.
+    let mut reported: Option<HashSet<IpNet>> = None;
+    let mut dirty = true;
+
+    loop {
+        let event = if dirty {
+            if let Ok(event) = tokio::time::timeout(SETTLE, watcher.next()).await {
+                event
+            } else {
+                dirty = false;
+                if reported.as_ref().is_some_and(|r| *r != addrs) {
+                    info!(addresses = addrs.len(), "network interfaces changed");
+                    if event_tx.send(DaemonEvent::InterfacesChanged).await.is_err() {
+                        return;
+                    }
+                }
+                reported = Some(addrs.clone());
+                continue;
+            }
+        } else {
+            watcher.next().await
+        };
+        match event {
+            Some(Ok(IfEvent::Up(net))) => {
+                debug!(address = %net, "address added");
+                addrs.insert(net);
+            }
+            Some(Ok(IfEvent::Down(net))) => {
+                debug!(address = %net, "address removed");
+                addrs.remove(&net);
+            }
+            Some(Err(e)) => {
+                warn!(error = %e, "interface watch failed");
+                return;
+            }
+            None => return,
+        }
+        dirty = true;
+    }
+}
+
+/// The SSID of the active Wi-Fi connection, if any.
+pub fn current_ssid() -> Option<String> {
+    #[cfg(target_os = "linux")]
+    {
+        linux::current_ssid()
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// Whether `ip` is assigned to this machine, i.e. a socket can bind to it.
+pub fn is_local_ip(ip: IpAddr) -> bool {
+    ip.is_loopback() || std::net::UdpSocket::bind((ip, 0)).is_ok()
+}
+
+/// How this machine reaches one configured screen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerRoute {
+    pub name: String,
+    /// The screen's address, if it resolved.
+    pub address: Option<SocketAddr>,
+    /// The local address used to reach it, if there is a route.
+    pub local: Option<IpAddr>,
+}
+
+/// The local address the OS would send from to reach `peer`.
+pub fn route_source(peer: SocketAddr) -> Option<IpAddr> {
+    let unspecified: IpAddr = if peer.is_ipv4() {
+        Ipv4Addr::UNSPECIFIED.into()
+    } else {
+        Ipv6Addr::UNSPECIFIED.into()
+    };
+    let socket = UdpSocket::bind((unspecified, 0)).ok()?;
+    socket.connect(peer).ok()?;
+    socket
+        .local_addr()
+        .ok()
+        .map(|addr| addr.ip())
+        .filter(|ip| !ip.is_unspecified())
+}
+
+/// Routes to every screen in `config` that has an address. Host names are
+/// resolved, so this may block.
+pub fn peer_routes(config: &Config) -> Vec<PeerRoute> {
+    config
+        .screens
+        .iter()
+        .filter_map(|sc| {
+            let address = sc.address.as_deref()?;
+            let address = if let Ok(ip) = address.parse::<IpAddr>() {
+                Some(SocketAddr::new(ip, config.daemon.peer_port()))
+            } else {
+                let with_port = if address.contains(':') {
+                    address.to_string()
+                } else {
+                    format!("{address}:{}", config.daemon.peer_port())
+                };
+                with_port
+                    .to_socket_addrs()
+                    .ok()
+                    .and_then(|mut addrs| addrs.next())
+            };
+            Some(PeerRoute {
+                name: sc.name.clone(),
+                address,
+                local: address.and_then(route_source),
+            })
+        })
+        .collect()
+}
+
+/// The listen address for `bind = "auto"`: the single local address all
+/// peers are reached through. `None` (listen everywhere) when there are no
+/// routable peers or they sit behind different interfaces.
+pub fn auto_listen_address(routes: &[PeerRoute]) -> Option<IpAddr> {
+    let mut locals = routes.iter().filter_map(|route| route.local);
+    let first = locals.next()?;
+    locals.all(|ip| ip == first).then_some(first)
+}
+
+/// Extract the active SSID from `nmcli -t -f active,ssid dev wifi` output.
+///
+/// Terse output escapes `:` and `\` inside fields with a backslash.
+pub fn ssid_from_nmcli(output: &str) -> Option<String> {
+    output
+        .lines()
+        .find_map(|line| line.strip_prefix("yes:"))
+        .map(|ssid| ssid.replace("\\:", ":").replace("\\\\", "\\"))
+        .filter(|ssid| !ssid.is_empty())
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::process::Command;
+
+    fn run(program: &str, args: &[&str]) -> Option<String> {
+        let output = Command::new(program).args(args).output().ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    pub fn current_ssid() -> Option<String> {
+        if let Some(output) = run("nmcli", &["-t", "-f", "active,ssid", "dev", "wifi"]) {
+            return super::ssid_from_nmcli(&output);
+        }
+        run("iwgetid", &["-r"])
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(local: Option<&str>) -> PeerRoute {
+        PeerRoute {
+            name: "peer".to_string(),
+            address: None,
+            local: local.map(|ip| ip.parse().unwrap()),
+        }
+    }
+
+    #[test]
+    fn auto_listen_address_needs_one_shared_interface() {
+        assert_eq!(auto_listen_address(&[]), None);
+        let lan = [route(Some("192.168.1.10")), route(None)];
+        assert_eq!(auto_listen_address(&lan), "192.168.1.10".parse().ok());
+        let split = [route(Some("192.168.1.10")), route(Some("10.8.0.3"))];
+        assert_eq!(auto_listen_address(&split), None);
+    }
+
+    #[test]
+    fn loopback_peer_routes_through_loopback() {
+        let local = route_source("127.0.0.1:24800".parse().unwrap());
+        assert!(local.is_some_and(|ip| ip.is_loopback()));
+    }
+
+    #[test]
+    fn nmcli_active_network_is_selected() {
+        let output = "no:neighbours\nyes:office\nno:cafe\n";
+        assert_eq!(ssid_from_nmcli(output).as_deref(), Some("office"));
+    }
+
+    #[test]
+    fn nmcli_escapes_are_removed() {
+        assert_eq!(ssid_from_nmcli("yes:lab\\:5G\n").as_deref(), Some("lab:5G"));
+    }
+
+    #[test]
+    fn nmcli_without_active_network_reports_nothing() {
+        assert_eq!(ssid_from_nmcli("no:office\n"), None);
+        assert_eq!(ssid_from_nmcli(""), None);
+    }
+}
@@ -0,0 +1,236 @@
+//! Guided two-machine setup (`cross-control setup`).
+//!
+//! One machine waits with [`accept`] while the other dials it with
+//! [`connect`]. Both show the TLS session code so the user can confirm they
+//! reached the right machine, then the initiator pushes the counterpart's
+//! half of the layout and each side writes its own config.
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use cross_control_protocol::{MessageReceiver, ProtocolError, Transport};
+use cross_control_types::{
+    ControlMessage, MachineId, Position, ScreenGeometry, SetupMessage, PROTOCOL_VERSION,
+};
+use tracing::debug;
+
+use crate::config::{Config, PointerScale, ScreenConfig, ScreenMode};
+use crate::error::DaemonError;
+use crate::handshake::{Identity, Role};
+use crate::session::PeerSession;
+
+/// Keepalive interval while waiting on the user, well inside QUIC's idle timeout.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long to wait for the other side to hang up once setup is done.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A connection to the other machine during guided setup.
+pub struct PairingSession {
+    session: PeerSession,
+    control_rx: MessageReceiver,
+    ping_seq: u64,
+}
+
+/// Dial `addr` and perform the handshake as initiator.
+pub async fn connect(
+    transport: &dyn Transport,
+    addr: SocketAddr,
+    our_id: MachineId,
+    our_name: &str,
+    our_screen: &ScreenGeometry,
+) -> Result<PairingSession, DaemonError> {
+    let conn = transport.connect(addr, "cross-control").await?;
+    let ours = identity(our_id, our_name, our_screen);
+    PairingSession::new(PeerSession::establish(conn, Role::Initiator, ours, None).await?)
+}
+
+/// Wait for the other machine to dial in and perform the handshake as responder.
+pub async fn accept(
+    transport: &dyn Transport,
+    our_id: MachineId,
+    our_name: &str,
+    our_screen: &ScreenGeometry,
+) -> Result<PairingSession, DaemonError> {
+    let conn = transport.accept().await?;
+    let ours = identity(our_id, our_name, our_screen);
+    PairingSession::new(PeerSession::establish(conn, Role::Responder, ours, None).await?)
+}
+
+fn identity(machine_id: MachineId, name: &str, screen: &ScreenGeometry) -> Identity {
+    Identity {
+        machine_id,
+        name: name.to_string(),
+        screen: screen.clone(),
+        version: PROTOCOL_VERSION,
+    }
+}
+
+impl PairingSession {
+    fn new(mut session: PeerSession) -> Result<Self, DaemonError> {
+        let control_rx = session
+            .take_control_rx()
+            .ok_or(DaemonError::Protocol(ProtocolError::StreamClosed))?;
+        Ok(Self {
+            session,
+            control_rx,
+            ping_seq: 0,
+        })
+    }
+
+    /// The other machine's configured name.
+    pub fn peer_name(&self) -> &str {
+        &self.session.name
+    }
+
+    /// The other machine's screen geometry.
+    pub fn peer_screen(&self) -> &ScreenGeometry {
+        &self.session.remote_screen
+    }
+
+    /// Fingerprint of the other machine's certificate.
+    pub fn peer_fingerprint(&self) -> Option<&str> {
+        self.session.fingerprint.as_deref()
+    }
+
+    /// The other machine's address as seen from here.
+    pub fn peer_address(&self) -> SocketAddr {
+        self.session.connection.remote_address()
+    }
+
+    /// Session code to compare with the one shown on the other machine.
+    pub fn verification_code(&self) -> Result<String, DaemonError> {
+        self.session.verification_code()
+    }
+
+    /// Send a setup message.
+    pub async fn send(&mut self, msg: SetupMessage) -> Result<(), DaemonError> {
+        self.session
+            .control_tx
+            .send_tagged(&ControlMessage::Setup(msg))
+            .await?;
+        Ok(())
+    }
+
+    /// Wait for the next setup message, answering keepalives meanwhile.
+    pub async fn recv(&mut self) -> Result<SetupMessage, DaemonError> {
+        loop {
+            let msg: ControlMessage = self
+                .control_rx
+                .recv_tagged()
+                .await?
+                .ok_or(DaemonError::Protocol(ProtocolError::StreamClosed))?;
+            match msg {
+                ControlMessage::Setup(msg) => return Ok(msg),
+                ControlMessage::Ping { seq } => {
+                    self.session
+                        .control_tx
+                        .send_tagged(&ControlMessage::Pong { seq })
+                        .await?;
+                }
+                ControlMessage::Bye => {
+                    return Err(DaemonError::Protocol(ProtocolError::StreamClosed));
+                }
+                other => debug!(?other, "ignoring message during setup"),
+            }
+        }
+    }
+
+    /// Run `fut` (typically a prompt for the user) while keeping the
+    /// connection alive.
+    pub async fn keep_alive_while<F: Future>(&mut self, fut: F) -> F::Output {
+        tokio::pin!(fut);
+        let mut interval = tokio::time::interval(KEEPALIVE_INTERVAL);
+        loop {
+            tokio::select! {
+                output = &mut fut => return output,
+                _ = interval.tick() => {
+                    self.ping_seq += 1;
+                    let ping = ControlMessage::Ping { seq: self.ping_seq };
+                    let _ = self.session.control_tx.send_tagged(&ping).await;
+                }
+            }
+        }
+    }
+
+    /// Close the connection.
+    pub async fn close(mut self) {
+        let _ = self.session.disconnect().await;
+    }
+
+    /// Wait (briefly) for the other side to close the connection, so the
+    /// last message sent is not cut off by closing it first.
+    pub async fn wait_closed(&mut self) {
+        let _ = tokio::time::timeout(CLOSE_TIMEOUT, self.recv()).await;
+    }
+}
+
+/// Record the paired machine in `config`, replacing any previous entry with
+/// the same name, and adopt the detected local screen size.
+pub fn apply_pairing(
+    config: &mut Config,
+    peer_name: &str,
+    address: String,
+    position: Position,
+    fingerprint: Option<String>,
+    screen_size: Option<(u32, u32)>,
+) {
+    if let Some((width, height)) = screen_size {
+        config.daemon.screen_width = width;
+        config.daemon.screen_height = height;
+    }
+    config.screens.retain(|sc| sc.name != peer_name);
+    config.screens.push(ScreenConfig {
+        name: peer_name.to_string(),
+        group: None,
+        address: Some(address),
+        position,
+        fingerprint,
+        mode: ScreenMode::Control,
+        wake_on_enter: true,
+        locate_on_enter: false,
+        pointer_scale: PointerScale::default(),
+        share_devices: None,
+        clipboard: true,
+        wait_on_start: false,
+        commands: Vec::new(),
+        release_hotkey: None,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_pairing_replaces_existing_entry() {
+        let mut config = Config::default();
+        apply_pairing(
+            &mut config,
+            "laptop",
+            "10.0.0.2:24800".to_string(),
+            Position::Left,
+            None,
+            None,
+        );
+        apply_pairing(
+            &mut config,
+            "laptop",
+            "10.0.0.3:24800".to_string(),
+            Position::Right,
+            Some("SHA256:ab".to_string()),
+            Some((2560, 1440)),
+        );
+        assert_eq!(config.screens.len(), 1);
+        assert_eq!(config.screens[0].position, Position::Right);
+        assert_eq!(config.screens[0].address.as_deref(), Some("10.0.0.3:24800"));
+        assert_eq!(config.daemon.screen_width, 2560);
+        assert_eq!(config.daemon.screen_height, 1440);
+
+        let saved = toml::to_string_pretty(&config).unwrap();
+        let loaded: Config = toml::from_str(&saved).unwrap();
+        assert_eq!(loaded.screens[0].name, "laptop");
+        assert_eq!(loaded.screens[0].fingerprint.as_deref(), Some("SHA256:ab"));
+    }
+}
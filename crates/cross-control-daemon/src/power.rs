@@ -0,0 +1,134 @@
+//! Local power-source detection.
+//!
+//! On Linux the state is read from `/sys/class/power_supply`. Other platforms
+//! report no state, and peers treat the machine as mains powered.
+
+use cross_control_types::PowerState;
+
+/// One entry from the platform's list of power supplies.
+#[derive(Debug, Clone, Default)]
+pub struct PowerSupply {
+    /// Mains adapter (`true`) or battery (`false`).
+    pub mains: bool,
+    /// Whether a mains adapter is plugged in.
+    pub online: bool,
+    /// Battery charge in percent.
+    pub capacity: Option<u8>,
+}
+
+/// Read the current local power state, if the platform exposes one.
+pub fn read_power_state() -> Option<PowerState> {
+    #[cfg(target_os = "linux")]
+    {
+        power_state_from_supplies(&linux::read_supplies())
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// Derive the power state from a list of supplies.
+///
+/// Machines without a battery report `None`. A machine is on battery when it
+/// has a battery and no mains adapter is online.
+pub fn power_state_from_supplies(supplies: &[PowerSupply]) -> Option<PowerState> {
+    let battery = supplies.iter().find(|s| !s.mains)?;
+    let on_mains = supplies.iter().any(|s| s.mains && s.online);
+    Some(PowerState {
+        on_battery: !on_mains,
+        battery_percent: battery.capacity,
+    })
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::path::Path;
+
+    use super::PowerSupply;
+
+    const POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+
+    pub fn read_supplies() -> Vec<PowerSupply> {
+        let Ok(entries) = std::fs::read_dir(POWER_SUPPLY_DIR) else {
+            return Vec::new();
+        };
+        entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| read_supply(&entry.path()))
+            .collect()
+    }
+
+    fn read_supply(dir: &Path) -> Option<PowerSupply> {
+        let read = |name: &str| {
+            std::fs::read_to_string(dir.join(name))
+                .ok()
+                .map(|s| s.trim().to_string())
+        };
+        let mains = match read("type")?.as_str() {
+            "Mains" | "USB" => true,
+            "Battery" => false,
+            _ => return None,
+        };
+        // Peripheral batteries (mice, headsets) don't power the machine.
+        if !mains && read("scope").as_deref() == Some("Device") {
+            return None;
+        }
+        Some(PowerSupply {
+            mains,
+            online: read("online").as_deref() == Some("1"),
+            capacity: read("capacity").and_then(|c| c.parse().ok()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn desktop_without_battery_reports_nothing() {
+        let supplies = [PowerSupply {
+            mains: true,
+            online: true,
+            capacity: None,
+        }];
+        assert_eq!(power_state_from_supplies(&supplies), None);
+    }
+
+    #[test]
+    fn laptop_unplugged_is_on_battery() {
+        let supplies = [
+            PowerSupply {
+                mains: true,
+                online: false,
+                capacity: None,
+            },
+            PowerSupply {
+                mains: false,
+                online: false,
+                capacity: Some(42),
+            },
+        ];
+        let state = power_state_from_supplies(&supplies).unwrap();
+        assert!(state.on_battery);
+        assert_eq!(state.battery_percent, Some(42));
+    }
+
+    #[test]
+    fn laptop_plugged_in_is_not_on_battery() {
+        let supplies = [
+            PowerSupply {
+                mains: true,
+                online: true,
+                capacity: None,
+            },
+            PowerSupply {
+                mains: false,
+                online: false,
+                capacity: Some(80),
+            },
+        ];
+        assert!(!power_state_from_supplies(&supplies).unwrap().on_battery);
+    }
+}
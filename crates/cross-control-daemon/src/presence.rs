@@ -0,0 +1,398 @@
+//! Peers currently seen by discovery, and identity collisions between them.
+//!
+//! Cloned VM images and copied config directories put two hosts on the
+//! network with the same machine ID or name. Connecting to either leads to
+//! sessions replacing each other, so colliding identities are reported and
+//! left alone until either party disappears.
+//!
+//! A true copy carries this machine's certificate along. An advertisement
+//! that claims our machine ID, or a name pinned in the config, without the
+//! matching certificate is an impostor and is ignored rather than allowed
+//! to block connections.
+
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+
+use cross_control_discovery::Peer;
+use cross_control_types::MachineId;
+
+/// What a discovery sighting turned out to be.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sighting {
+    /// Our own advertisement, seen by our own browser.
+    Own,
+    /// An ordinary peer.
+    Peer,
+    /// The sighting revealed a new identity collision.
+    Conflict(String),
+    /// The advertisement claims an identity whose certificate it doesn't
+    /// have, so it was ignored.
+    Spoofed(String),
+}
+
+/// An identity collision seen on the network.
+#[derive(Debug, Clone)]
+struct IdentityConflict {
+    /// The advertisements involved; losing any of them resolves the
+    /// collision.
+    machine_ids: Vec<MachineId>,
+    /// Names no longer connected to automatically. Empty when the collision
+    /// involves this machine, which blocks every automatic connection.
+    names: Vec<String>,
+    description: String,
+}
+
+/// Discovered peers, keyed by machine ID.
+pub struct PeerDirectory {
+    machine_id: MachineId,
+    name: String,
+    /// Our certificate fingerprint, which a true copy of this machine
+    /// advertises too.
+    fingerprint: Option<String>,
+    peers: HashMap<MachineId, Peer>,
+    /// Machine IDs in `peers`, oldest sighting first.
+    order: VecDeque<MachineId>,
+    conflicts: Vec<IdentityConflict>,
+    /// Peers kept before the oldest are forgotten.
+    limit: usize,
+    /// Peers forgotten because of `limit`.
+    evicted: u64,
+}
+
+impl PeerDirectory {
+    /// Create a directory for the local machine's identity.
+    pub fn new(machine_id: MachineId, name: impl Into<String>) -> Self {
+        Self {
+            machine_id,
+            name: name.into(),
+            fingerprint: None,
+            peers: HashMap::new(),
+            order: VecDeque::new(),
+            conflicts: Vec::new(),
+            limit: usize::MAX,
+            evicted: 0,
+        }
+    }
+
+    /// Remember at most `limit` peers, forgetting the oldest first.
+    #[must_use]
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit.max(1);
+        self
+    }
+
+    /// Set our certificate fingerprint. Without it, anything advertising
+    /// our machine ID or name counts as a copy of this machine.
+    #[must_use]
+    pub fn with_fingerprint(mut self, fingerprint: Option<String>) -> Self {
+        self.fingerprint = fingerprint;
+        self
+    }
+
+    /// Number of peers forgotten because of the limit.
+    pub fn evicted(&self) -> u64 {
+        self.evicted
+    }
+
+    /// Record a discovered peer. `is_local` tells whether an address belongs
+    /// to this machine, to recognise our own advertisement. `pinned` is the
+    /// fingerprint our config pins for the peer's name, if any; an
+    /// advertisement that doesn't match it is ignored.
+    pub fn found(
+        &mut self,
+        peer: Peer,
+        is_local: impl Fn(&SocketAddr) -> bool,
+        pinned: Option<&str>,
+    ) -> Sighting {
+        if peer.machine_id == self.machine_id && is_local(&peer.address) {
+            return Sighting::Own;
+        }
+        if let Some(description) = self.spoofed(&peer, pinned) {
+            return Sighting::Spoofed(description);
+        }
+        let conflict = self.conflict(&peer);
+
+        if peer.machine_id != self.machine_id && !self.peers.contains_key(&peer.machine_id) {
+            self.order.push_back(peer.machine_id);
+            self.peers.insert(peer.machine_id, peer);
+            while self.peers.len() > self.limit {
+                let Some(oldest) = self.order.pop_front() else {
+                    break;
+                };
+                self.lost(oldest);
+                self.evicted += 1;
+            }
+        }
+        match conflict {
+            Some(conflict)
+                if !self
+                    .conflicts
+                    .iter()
+                    .any(|c| c.description == conflict.description) =>
+            {
+                let description = conflict.description.clone();
+                self.conflicts.push(conflict);
+                Sighting::Conflict(description)
+            }
+            _ => Sighting::Peer,
+        }
+    }
+
+    /// Why `peer` is an impostor, if it is: it claims our machine ID
+    /// without our certificate, or a pinned name without the pinned one.
+    /// A copied config directory or cloned VM carries the certificate
+    /// along, so neither is a mere collision.
+    fn spoofed(&self, peer: &Peer, pinned: Option<&str>) -> Option<String> {
+        let advertised = peer.fingerprint.as_deref();
+        if peer.machine_id == self.machine_id {
+            let ours = self.fingerprint.as_deref()?;
+            return (advertised != Some(ours)).then(|| {
+                format!(
+                    "{} ({}) advertises this machine's ID {} without its certificate",
+                    peer.name, peer.address, peer.machine_id
+                )
+            });
+        }
+        let pinned = pinned?;
+        (advertised != Some(pinned)).then(|| {
+            format!(
+                "{} ({}) advertises the name {} without its pinned certificate",
+                peer.machine_id, peer.address, peer.name
+            )
+        })
+    }
+
+    /// The collision `peer` reveals, if any.
+    fn conflict(&self, peer: &Peer) -> Option<IdentityConflict> {
+        let is_copy = self.fingerprint.is_none() || peer.fingerprint == self.fingerprint;
+        if peer.machine_id == self.machine_id {
+            return Some(IdentityConflict {
+                machine_ids: vec![peer.machine_id],
+                names: Vec::new(),
+                description: format!(
+                    "{} ({}) advertises this machine's ID {}; was the config directory copied?",
+                    peer.name, peer.address, peer.machine_id
+                ),
+            });
+        }
+        if peer.name == self.name {
+            // Another machine with our name confuses peers, not us, so
+            // only a copy of this machine stops our own connections.
+            return Some(IdentityConflict {
+                machine_ids: vec![peer.machine_id],
+                names: if is_copy {
+                    Vec::new()
+                } else {
+                    vec![peer.name.clone()]
+                },
+                description: format!(
+                    "{} ({}) advertises this machine's name; rename one of them",
+                    peer.address, peer.machine_id
+                ),
+            });
+        }
+        if let Some(other) = self
+            .peers
+            .values()
+            .find(|p| p.name == peer.name && p.machine_id != peer.machine_id)
+        {
+            return Some(IdentityConflict {
+                machine_ids: vec![other.machine_id, peer.machine_id],
+                names: vec![peer.name.clone()],
+                description: format!(
+                    "{} is advertised by two machines: {} ({}) and {} ({})",
+                    peer.name, other.machine_id, other.address, peer.machine_id, peer.address
+                ),
+            });
+        }
+        self.peers
+            .get(&peer.machine_id)
+            .filter(|known| known.name != peer.name)
+            .map(|known| IdentityConflict {
+                machine_ids: vec![peer.machine_id],
+                names: vec![known.name.clone(), peer.name.clone()],
+                description: format!(
+                    "machine ID {} is advertised as both {} and {}; was a VM image cloned?",
+                    peer.machine_id, known.name, peer.name
+                ),
+            })
+    }
+
+    /// Forget a peer that stopped advertising, and the collisions it was
+    /// part of.
+    pub fn lost(&mut self, machine_id: MachineId) {
+        if self.peers.remove(&machine_id).is_some() {
+            self.order.retain(|id| *id != machine_id);
+        }
+        self.conflicts
+            .retain(|c| !c.machine_ids.contains(&machine_id));
+    }
+
+    /// Whether automatic connections to `name` are suspended by a collision.
+    pub fn is_blocked(&self, name: &str) -> bool {
+        self.conflicts
+            .iter()
+            .any(|c| c.names.is_empty() || c.names.iter().any(|n| n == name))
+    }
+
+    /// Descriptions of the current collisions.
+    pub fn conflicts(&self) -> Vec<String> {
+        self.conflicts
+            .iter()
+            .map(|c| c.description.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(machine_id: MachineId, name: &str, address: &str) -> Peer {
+        Peer {
+            machine_id,
+            name: name.to_string(),
+            address: address.parse().unwrap(),
+            fingerprint: None,
+            group: None,
+        }
+    }
+
+    #[test]
+    fn own_advertisement_is_ignored() {
+        let id = MachineId::new();
+        let mut directory = PeerDirectory::new(id, "desk");
+        let sighting = directory.found(peer(id, "desk", "10.0.0.1:24800"), |_| true, None);
+        assert_eq!(sighting, Sighting::Own);
+        assert!(directory.conflicts().is_empty());
+    }
+
+    #[test]
+    fn copied_machine_id_blocks_everything() {
+        let id = MachineId::new();
+        let mut directory = PeerDirectory::new(id, "desk");
+        let sighting = directory.found(peer(id, "desk-clone", "10.0.0.2:24800"), |_| false, None);
+        assert!(matches!(sighting, Sighting::Conflict(_)));
+        assert!(directory.is_blocked("laptop"));
+
+        // Seeing it again doesn't raise a second warning.
+        let sighting = directory.found(peer(id, "desk-clone", "10.0.0.2:24800"), |_| false, None);
+        assert_eq!(sighting, Sighting::Peer);
+
+        directory.lost(id);
+        assert!(!directory.is_blocked("laptop"));
+    }
+
+    #[test]
+    fn duplicate_peer_name_blocks_that_name() {
+        let mut directory = PeerDirectory::new(MachineId::new(), "desk");
+        let first = MachineId::new();
+        let second = MachineId::new();
+        assert_eq!(
+            directory.found(peer(first, "laptop", "10.0.0.3:24800"), |_| false, None),
+            Sighting::Peer
+        );
+        assert!(matches!(
+            directory.found(peer(second, "laptop", "10.0.0.4:24800"), |_| false, None),
+            Sighting::Conflict(_)
+        ));
+        assert!(directory.is_blocked("laptop"));
+        assert!(!directory.is_blocked("tablet"));
+
+        directory.lost(second);
+        assert!(!directory.is_blocked("laptop"));
+    }
+
+    #[test]
+    fn machine_id_under_two_names_blocks_both() {
+        let mut directory = PeerDirectory::new(MachineId::new(), "desk");
+        let id = MachineId::new();
+        directory.found(peer(id, "vm-1", "10.0.0.5:24800"), |_| false, None);
+        assert!(matches!(
+            directory.found(peer(id, "vm-2", "10.0.0.6:24800"), |_| false, None),
+            Sighting::Conflict(_)
+        ));
+        assert!(directory.is_blocked("vm-1"));
+        assert!(directory.is_blocked("vm-2"));
+    }
+
+    #[test]
+    fn losing_either_duplicate_resolves_the_conflict() {
+        let mut directory = PeerDirectory::new(MachineId::new(), "desk");
+        let first = MachineId::new();
+        directory.found(peer(first, "laptop", "10.0.0.3:24800"), |_| false, None);
+        directory.found(
+            peer(MachineId::new(), "laptop", "10.0.0.4:24800"),
+            |_| false,
+            None,
+        );
+        assert!(directory.is_blocked("laptop"));
+
+        directory.lost(first);
+        assert!(!directory.is_blocked("laptop"));
+    }
+
+    #[test]
+    fn impostors_are_ignored() {
+        let id = MachineId::new();
+        let mut directory =
+            PeerDirectory::new(id, "desk").with_fingerprint(Some("SHA256:desk".to_string()));
+
+        // Our machine ID without our certificate.
+        let sighting = directory.found(peer(id, "desk", "10.0.0.2:24800"), |_| false, None);
+        assert!(matches!(sighting, Sighting::Spoofed(_)), "{sighting:?}");
+        assert!(!directory.is_blocked("laptop"));
+
+        // A pinned name without the pinned certificate.
+        let sighting = directory.found(
+            peer(MachineId::new(), "laptop", "10.0.0.3:24800"),
+            |_| false,
+            Some("SHA256:laptop"),
+        );
+        assert!(matches!(sighting, Sighting::Spoofed(_)), "{sighting:?}");
+        assert!(directory.peers.is_empty());
+
+        // A real copy of this machine still blocks everything.
+        let copy = Peer {
+            fingerprint: Some("SHA256:desk".to_string()),
+            ..peer(id, "desk", "10.0.0.2:24800")
+        };
+        let sighting = directory.found(copy, |_| false, None);
+        assert!(matches!(sighting, Sighting::Conflict(_)), "{sighting:?}");
+        assert!(directory.is_blocked("laptop"));
+    }
+
+    #[test]
+    fn another_machine_with_our_name_blocks_only_that_name() {
+        let mut directory = PeerDirectory::new(MachineId::new(), "desk")
+            .with_fingerprint(Some("SHA256:desk".to_string()));
+        let sighting = directory.found(
+            peer(MachineId::new(), "desk", "10.0.0.2:24800"),
+            |_| false,
+            None,
+        );
+        assert!(matches!(sighting, Sighting::Conflict(_)), "{sighting:?}");
+        assert!(!directory.is_blocked("laptop"));
+    }
+
+    #[test]
+    fn oldest_peer_is_forgotten_past_the_limit() {
+        let mut directory = PeerDirectory::new(MachineId::new(), "desk").with_limit(2);
+        let first = MachineId::new();
+        directory.found(peer(first, "one", "10.0.0.7:24800"), |_| false, None);
+        directory.found(
+            peer(MachineId::new(), "two", "10.0.0.8:24800"),
+            |_| false,
+            None,
+        );
+        directory.found(
+            peer(MachineId::new(), "three", "10.0.0.9:24800"),
+            |_| false,
+            None,
+        );
+
+        assert_eq!(directory.evicted(), 1);
+        assert!(!directory.peers.contains_key(&first));
+        assert_eq!(directory.peers.len(), 2);
+    }
+}
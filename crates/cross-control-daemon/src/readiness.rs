@@ -0,0 +1,63 @@
+//! Startup readiness reporting.
+//!
+//! Under systemd with `Type=notify`, `READY=1` is sent to `$NOTIFY_SOCKET`
+//! once the daemon is ready, so units ordered after cross-control don't start
+//! while it is still connecting to peers. Elsewhere, or when the variable is
+//! unset, this does nothing.
+
+use tracing::debug;
+
+/// Tell the service manager the daemon is ready.
+pub fn notify_ready() {
+    let Some(socket) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    #[cfg(unix)]
+    if let Err(e) = send(&socket, "READY=1") {
+        tracing::warn!(error = %e, "failed to notify the service manager");
+        return;
+    }
+    #[cfg(not(unix))]
+    drop(socket);
+    debug!("reported readiness to the service manager");
+}
+
+#[cfg(unix)]
+fn send(socket: &std::ffi::OsStr, message: &str) -> std::io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::UnixDatagram;
+
+    let datagram = UnixDatagram::unbound()?;
+    // A leading `@` names a socket in Linux's abstract namespace.
+    #[cfg(target_os = "linux")]
+    if let Some(name) = socket.as_bytes().strip_prefix(b"@") {
+        use std::os::linux::net::SocketAddrExt;
+        let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+        datagram.send_to_addr(message.as_bytes(), &addr)?;
+        return Ok(());
+    }
+    datagram.send_to(message.as_bytes(), socket)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn sends_ready_to_socket_path() {
+        let dir = std::env::temp_dir().join(format!("cc-notify-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notify.sock");
+        let _ = std::fs::remove_file(&path);
+        let listener = std::os::unix::net::UnixDatagram::bind(&path).unwrap();
+
+        send(path.as_os_str(), "READY=1").unwrap();
+        let mut buf = [0u8; 32];
+        let n = listener.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,152 @@
+//! Local screen lock and display sleep detection.
+//!
+//! With the `linux` feature the lock state is logind's `LockedHint` for the
+//! user's session; on Linux the displays count as asleep when every
+//! connected DRM connector reports DPMS `Off`. Elsewhere the screen is
+//! always reported active.
+
+use std::time::Duration;
+
+use cross_control_types::ScreenState;
+use tokio::sync::mpsc;
+
+use crate::daemon::DaemonEvent;
+
+/// Poll the local screen state every `poll` and forward changes to the
+/// daemon until the event channel closes.
+pub async fn watch(event_tx: mpsc::Sender<DaemonEvent>, poll: Duration) {
+    #[cfg(all(feature = "linux", target_os = "linux"))]
+    let session = logind::session().await;
+    let mut last = ScreenState::Active;
+    loop {
+        tokio::time::sleep(poll).await;
+        #[cfg(all(feature = "linux", target_os = "linux"))]
+        let locked = logind::locked(session.as_ref()).await;
+        #[cfg(not(all(feature = "linux", target_os = "linux")))]
+        let locked = false;
+        let Ok(asleep) = tokio::task::spawn_blocking(displays_asleep).await else {
+            break;
+        };
+        let current = screen_state(locked, asleep);
+        if current == last {
+            continue;
+        }
+        last = current;
+        if event_tx
+            .send(DaemonEvent::ScreenStateChanged(current))
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+/// A locked screen is reported as locked even with its displays off: the
+/// lock screen is what input reaches once they wake.
+pub fn screen_state(locked: bool, asleep: bool) -> ScreenState {
+    if locked {
+        ScreenState::Locked
+    } else if asleep {
+        ScreenState::Asleep
+    } else {
+        ScreenState::Active
+    }
+}
+
+/// Whether every connected display is off. `connectors` holds each
+/// connector's `status` and `dpms` contents.
+pub fn all_displays_off(connectors: &[(String, String)]) -> bool {
+    let mut connected = connectors
+        .iter()
+        .filter(|(status, _)| status.trim() == "connected")
+        .peekable();
+    connected.peek().is_some() && connected.all(|(_, dpms)| dpms.trim() == "Off")
+}
+
+fn displays_asleep() -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        let Ok(entries) = std::fs::read_dir("/sys/class/drm") else {
+            return false;
+        };
+        let connectors: Vec<(String, String)> = entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let dir = entry.path();
+                let status = std::fs::read_to_string(dir.join("status")).ok()?;
+                let dpms = std::fs::read_to_string(dir.join("dpms")).ok()?;
+                Some((status, dpms))
+            })
+            .collect();
+        all_displays_off(&connectors)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        false
+    }
+}
+
+#[cfg(all(feature = "linux", target_os = "linux"))]
+mod logind {
+    use tracing::debug;
+
+    #[zbus::proxy(
+        interface = "org.freedesktop.login1.Session",
+        default_service = "org.freedesktop.login1",
+        default_path = "/org/freedesktop/login1/session/auto"
+    )]
+    pub trait Session {
+        #[zbus(property)]
+        fn locked_hint(&self) -> zbus::Result<bool>;
+    }
+
+    /// The user's logind session, or `None` if logind is unreachable.
+    pub async fn session() -> Option<SessionProxy<'static>> {
+        let connection = match zbus::Connection::system().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                debug!(error = %e, "logind unavailable, lock state unknown");
+                return None;
+            }
+        };
+        SessionProxy::new(&connection).await.ok()
+    }
+
+    pub async fn locked(session: Option<&SessionProxy<'static>>) -> bool {
+        match session {
+            Some(session) => session.locked_hint().await.unwrap_or(false),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connector(status: &str, dpms: &str) -> (String, String) {
+        (format!("{status}\n"), format!("{dpms}\n"))
+    }
+
+    #[test]
+    fn displays_asleep_only_when_every_connected_one_is_off() {
+        assert!(all_displays_off(&[
+            connector("connected", "Off"),
+            connector("disconnected", "On"),
+        ]));
+        assert!(!all_displays_off(&[
+            connector("connected", "Off"),
+            connector("connected", "On"),
+        ]));
+        assert!(!all_displays_off(&[connector("disconnected", "Off")]));
+        assert!(!all_displays_off(&[]));
+    }
+
+    #[test]
+    fn lock_wins_over_sleep() {
+        assert_eq!(screen_state(true, true), ScreenState::Locked);
+        assert_eq!(screen_state(false, true), ScreenState::Asleep);
+        assert_eq!(screen_state(false, false), ScreenState::Active);
+    }
+}
@@ -1,54 +1,417 @@
-//! Peer session management: handshake, enter/leave, device announce.
+//! Peer session management: enter/leave, device announce.
 
-use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
+use cross_control_protocol::wire::MAX_MESSAGE_SIZE;
 use cross_control_protocol::{MessageReceiver, MessageSender, PeerConnection};
 use cross_control_types::{
-    ControlMessage, DeviceId, DeviceInfo, InputMessage, MachineId, ProtocolVersion, ScreenGeometry,
-    VirtualDeviceId, PROTOCOL_VERSION,
+    ControlMessage, DeviceId, DeviceInfo, EnterDeniedReason, ErrorCode, FocusHint, InputMessage,
+    KeyboardLayout, MachineId, PowerState, ProtocolFeature, ProtocolVersion, ScreenGeometry,
+    PROTOCOL_VERSION,
 };
-use tracing::{debug, info, warn};
+use tracing::{debug, info, instrument, warn};
 
+use crate::alarms::LinkAlarm;
+use crate::auth::AuthHook;
+use crate::clock::ClockJump;
 use crate::error::DaemonError;
+use crate::handshake::{self, Admission, HandshakeError, Identity, Role, HANDSHAKE_TIMEOUT};
 use crate::state::SessionState;
 
+/// TLS exporter label for session verification codes.
+const VERIFY_LABEL: &[u8] = b"EXPORTER-cross-control-session-verify";
+
+/// TLS exporter label for session IDs.
+const SESSION_ID_LABEL: &[u8] = b"EXPORTER-cross-control-session-id";
+
+/// How long a fatal `Error` is given to reach the peer before closing.
+const ERROR_LINGER: Duration = Duration::from_millis(500);
+
+/// History entries kept until the daemon applies `limits.session_history`.
+const DEFAULT_HISTORY: usize = 64;
+
+/// A state transition or significant message in a session's history.
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub at: SystemTime,
+    /// The session state after the event.
+    pub state: SessionState,
+    pub event: String,
+}
+
+/// Keepalive round-trip times, and whether each keepalive was answered,
+/// kept per session.
+const RTT_HISTORY: usize = 32;
+
+/// Traffic counters for one session, since it was established or its
+/// statistics were last reset.
+#[derive(Debug)]
+pub struct SessionStats {
+    /// When counting started.
+    pub since: SystemTime,
+    /// Input events sent to the peer.
+    pub events_sent: u64,
+    /// Input events received from the peer.
+    pub events_received: u64,
+    /// Input events that never arrived: sends that failed, and input from
+    /// the peer that was stale or had nowhere to go.
+    pub events_dropped: u64,
+    bytes_sent: Arc<AtomicU64>,
+    bytes_received: Arc<AtomicU64>,
+    /// Keepalive round-trip times, oldest first.
+    rtt: VecDeque<Duration>,
+    /// Whether each recent keepalive was answered, oldest first.
+    answered: VecDeque<bool>,
+}
+
+impl SessionStats {
+    fn new() -> Self {
+        Self {
+            since: SystemTime::now(),
+            events_sent: 0,
+            events_received: 0,
+            events_dropped: 0,
+            bytes_sent: Arc::default(),
+            bytes_received: Arc::default(),
+            rtt: VecDeque::new(),
+            answered: VecDeque::new(),
+        }
+    }
+
+    /// Bytes sent on the session's streams, framing included.
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent.load(Ordering::Relaxed)
+    }
+
+    /// Bytes received on the session's streams, framing included.
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received.load(Ordering::Relaxed)
+    }
+
+    /// Counter for a receiver read by a background task, such as an input
+    /// stream reader.
+    pub fn received_counter(&self) -> Arc<AtomicU64> {
+        self.bytes_received.clone()
+    }
+
+    /// Recent keepalive round-trip times, oldest first.
+    pub fn rtt(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.rtt.iter().copied()
+    }
+
+    /// Share of recent keepalives that went unanswered, in percent; `None`
+    /// before any keepalive has been answered or missed.
+    pub fn loss_percent(&self) -> Option<f64> {
+        let missed = self.answered.iter().filter(|answered| !**answered).count();
+        // Both counts are at most `RTT_HISTORY`.
+        #[allow(clippy::cast_precision_loss)]
+        let percent = (missed * 100) as f64 / self.answered.len() as f64;
+        (!self.answered.is_empty()).then_some(percent)
+    }
+
+    fn record_rtt(&mut self, rtt: Duration) {
+        if self.rtt.len() >= RTT_HISTORY {
+            self.rtt.pop_front();
+        }
+        self.rtt.push_back(rtt);
+        self.record_answered(true);
+    }
+
+    fn record_answered(&mut self, answered: bool) {
+        if self.answered.len() >= RTT_HISTORY {
+            self.answered.pop_front();
+        }
+        self.answered.push_back(answered);
+    }
+
+    /// Start counting again from zero.
+    pub fn reset(&mut self) {
+        self.since = SystemTime::now();
+        self.events_sent = 0;
+        self.events_received = 0;
+        self.events_dropped = 0;
+        self.bytes_sent.store(0, Ordering::Relaxed);
+        self.bytes_received.store(0, Ordering::Relaxed);
+        self.rtt.clear();
+        self.answered.clear();
+    }
+}
+
+/// Largest messages, in bytes, accepted on each stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageLimits {
+    pub control: u32,
+    pub input: u32,
+}
+
+impl MessageLimits {
+    /// What a peer is assumed to accept until it announces its limits.
+    pub const DEFAULT: Self = Self {
+        control: MAX_MESSAGE_SIZE,
+        input: MAX_MESSAGE_SIZE,
+    };
+
+    /// The limits both ends accept.
+    #[must_use]
+    pub fn min(self, other: Self) -> Self {
+        Self {
+            control: self.control.min(other.control),
+            input: self.input.min(other.input),
+        }
+    }
+}
+
 /// A session with a single remote peer.
 pub struct PeerSession {
+    /// Identifier shared by both ends of the connection, logged as `session`
+    /// so the two machines' logs can be matched up.
+    pub id: String,
     pub machine_id: MachineId,
     pub name: String,
     pub remote_screen: ScreenGeometry,
+    /// Protocol version the peer speaks.
+    pub version: ProtocolVersion,
+    /// Software version the peer reported in `BuildInfo`; `None` until it
+    /// arrives, and always for peers that predate it.
+    pub software: Option<String>,
     pub state: SessionState,
     pub control_tx: MessageSender,
     control_rx: Option<MessageReceiver>,
     pub input_tx: Option<MessageSender>,
     input_rx: Option<MessageReceiver>,
-    /// Map from remote device ID to local virtual device ID.
-    pub device_map: HashMap<DeviceId, VirtualDeviceId>,
     /// Devices announced by the remote peer.
     pub remote_devices: Vec<DeviceInfo>,
+    /// Device announcements rejected as malformed or over
+    /// `limits.peer_devices`.
+    pub devices_rejected: u64,
+    /// Our devices announced to the peer; input from any other device is
+    /// not forwarded to it.
+    pub shared_devices: Vec<DeviceId>,
+    /// Set when the remote refused our Enter; barrier crossings towards it are
+    /// skipped until it reconnects.
+    pub enter_denied: Option<EnterDeniedReason>,
+    /// Our edge the last Enter we sent crossed.
+    pub entered_through: Option<cross_control_types::ScreenEdge>,
+    /// Set while the peer we control reports someone using its own input.
+    pub local_user_active: bool,
+    /// Fingerprint of the certificate the peer presented during TLS.
+    pub fingerprint: Option<String>,
+    /// Last power-source hint from the peer.
+    pub power: PowerState,
+    /// Keyboard layout the peer last reported typing with.
+    pub keyboard_layout: Option<KeyboardLayout>,
+    /// Last focus hint from the peer, sent while we controlled it.
+    pub focus: Option<FocusHint>,
+    /// Set when the peer announced it is going to sleep.
+    pub suspended: bool,
+    /// When we last sent the peer a keepalive ping.
+    pub last_keepalive: Instant,
+    /// The `Ping` we sent the peer after discovery reported it gone, and
+    /// when; cleared by the `Pong` answering it.
+    pub liveness_probe: Option<(u64, Instant)>,
+    /// The last `Ping` we sent and when, until its `Pong` arrives.
+    ping: Option<(u64, Instant)>,
+    pub stats: SessionStats,
+    /// Link quality alarm, checked against `[alarms]` each tick.
+    pub alarm: LinkAlarm,
     pub connection: PeerConnection,
+    /// Limits we announced to the peer.
+    limits: MessageLimits,
+    /// Limits for what we send the peer: the smaller of both ends' limits
+    /// once the peer has announced its own.
+    send_limits: MessageLimits,
+    /// Recent transitions and messages, oldest first.
+    history: VecDeque<HistoryEntry>,
+    history_limit: usize,
 }
 
 impl PeerSession {
     /// Create a new session from an accepted or initiated connection.
     pub fn new(
         connection: PeerConnection,
-        control_tx: MessageSender,
-        control_rx: MessageReceiver,
+        mut control_tx: MessageSender,
+        mut control_rx: MessageReceiver,
     ) -> Self {
-        Self {
+        let remote = connection.remote_address();
+        let stats = SessionStats::new();
+        control_tx.count_bytes(stats.bytes_sent.clone());
+        control_rx.count_bytes(stats.received_counter());
+        let mut session = Self {
+            id: session_id(&connection),
             machine_id: MachineId::default(),
             name: String::new(),
             remote_screen: ScreenGeometry::new(1920, 1080),
+            version: PROTOCOL_VERSION,
+            software: None,
             state: SessionState::Connected,
             control_tx,
             control_rx: Some(control_rx),
             input_tx: None,
             input_rx: None,
-            device_map: HashMap::new(),
             remote_devices: Vec::new(),
+            devices_rejected: 0,
+            shared_devices: Vec::new(),
+            enter_denied: None,
+            entered_through: None,
+            local_user_active: false,
+            fingerprint: connection.peer_fingerprint(),
+            power: PowerState::default(),
+            keyboard_layout: None,
+            focus: None,
+            suspended: false,
+            last_keepalive: Instant::now(),
+            liveness_probe: None,
+            ping: None,
+            stats,
+            alarm: LinkAlarm::default(),
             connection,
+            limits: MessageLimits::DEFAULT,
+            send_limits: MessageLimits::DEFAULT,
+            history: VecDeque::new(),
+            history_limit: DEFAULT_HISTORY,
+        };
+        session.record(format!("connected to {remote}"));
+        session
+    }
+
+    /// Add an event to the history under the current state.
+    pub fn record(&mut self, event: impl Into<String>) {
+        if self.history_limit == 0 {
+            return;
+        }
+        while self.history.len() >= self.history_limit {
+            self.history.pop_front();
+        }
+        self.history.push_back(HistoryEntry {
+            at: SystemTime::now(),
+            state: self.state,
+            event: event.into(),
+        });
+    }
+
+    /// Move to `state`, recording why.
+    fn transition(&mut self, state: SessionState, event: impl Into<String>) {
+        self.state = state;
+        self.record(event);
+    }
+
+    /// Recent transitions and messages, oldest first.
+    pub fn history(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.history.iter()
+    }
+
+    /// Keep at most `limit` history entries, dropping the oldest.
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history_limit = limit;
+        while self.history.len() > limit {
+            self.history.pop_front();
+        }
+    }
+
+    /// Set up the control stream on a fresh connection and handshake: the
+    /// initiator opens the stream, the responder accepts it and asks `auth`,
+    /// if given, whether to admit the peer.
+    pub async fn establish(
+        connection: PeerConnection,
+        role: Role,
+        ours: Identity,
+        auth: Option<&dyn AuthHook>,
+    ) -> Result<Self, DaemonError> {
+        let (control_tx, control_rx) = match role {
+            Role::Initiator => connection.open_control_stream().await?,
+            Role::Responder => connection.accept_control_stream().await?,
+        };
+        let mut session = Self::new(connection, control_tx, control_rx);
+        session.handshake(role, ours, auth).await?;
+        Ok(session)
+    }
+
+    /// Accept messages up to `limits` from the peer and tell it so.
+    ///
+    /// Must be called before `take_control_rx()`.
+    pub async fn announce_limits(&mut self, limits: MessageLimits) -> Result<(), DaemonError> {
+        self.limits = limits;
+        if let Some(control_rx) = &mut self.control_rx {
+            control_rx.set_max_size(limits.control);
+        }
+        self.control_tx
+            .send_tagged(&ControlMessage::Limits {
+                control: limits.control,
+                input: limits.input,
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Apply the limits the peer announced to what we send it.
+    pub fn apply_peer_limits(&mut self, theirs: MessageLimits) {
+        self.send_limits = self.limits.min(theirs);
+        self.control_tx.set_max_size(self.send_limits.control);
+        if let Some(input_tx) = &mut self.input_tx {
+            input_tx.set_max_size(self.send_limits.input);
+        }
+        self.record(format!(
+            "peer accepts {} byte control and {} byte input messages",
+            theirs.control, theirs.input
+        ));
+    }
+
+    /// Limits for what we send the peer.
+    pub fn send_limits(&self) -> MessageLimits {
+        self.send_limits
+    }
+
+    /// Limits we announced to the peer.
+    pub fn limits(&self) -> MessageLimits {
+        self.limits
+    }
+
+    /// Whether the peer's protocol version has `feature`.
+    pub fn supports(&self, feature: ProtocolFeature) -> bool {
+        self.version.supports(feature)
+    }
+
+    /// Tell the peer which software version we run, if it understands.
+    pub async fn announce_build(&mut self) -> Result<(), DaemonError> {
+        if !self.supports(ProtocolFeature::BuildInfo) {
+            return Ok(());
+        }
+        self.control_tx
+            .send_tagged(&ControlMessage::BuildInfo {
+                version: crate::VERSION.to_string(),
+                commit: crate::COMMIT.to_string(),
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Tell the peer which keyboard layout we type with, if it understands.
+    pub async fn announce_keyboard_layout(
+        &mut self,
+        layout: &KeyboardLayout,
+    ) -> Result<(), DaemonError> {
+        if !self.supports(ProtocolFeature::KeyboardLayout) {
+            return Ok(());
+        }
+        self.control_tx
+            .send_tagged(&ControlMessage::KeyboardLayout(layout.clone()))
+            .await?;
+        Ok(())
+    }
+
+    /// Tell the controlling peer someone started or stopped using our own
+    /// input, if it understands.
+    pub async fn announce_local_input(&mut self, active: bool) -> Result<(), DaemonError> {
+        if !self.supports(ProtocolFeature::LocalInput) {
+            return Ok(());
         }
+        self.control_tx
+            .send_tagged(&ControlMessage::LocalInput { active })
+            .await?;
+        Ok(())
     }
 
     /// Take ownership of the control receiver for spawning a reader task.
@@ -63,111 +426,83 @@ impl PeerSession {
         self.input_rx.take()
     }
 
-    /// Perform the initiator side of the handshake: send Hello, receive Welcome.
+    /// Exchange `Hello`/`Welcome` with the peer and record who it is. A
+    /// peer we refuse, including one `auth` refuses, is told why before the
+    /// connection is closed.
     ///
     /// Must be called before `take_control_rx()` — uses the `control_rx` directly.
-    pub async fn handshake_initiator(
+    #[instrument(name = "handshake", skip_all, fields(session = %self.id))]
+    pub async fn handshake(
         &mut self,
-        our_id: MachineId,
-        our_name: &str,
-        our_screen: &ScreenGeometry,
+        role: Role,
+        ours: Identity,
+        auth: Option<&dyn AuthHook>,
     ) -> Result<(), DaemonError> {
-        let hello = ControlMessage::Hello {
-            version: PROTOCOL_VERSION,
-            machine_id: our_id,
-            name: our_name.to_string(),
-            screen: our_screen.clone(),
-        };
-        self.control_tx.send(&hello).await?;
-        self.state = SessionState::HelloSent;
-        debug!("sent Hello");
-
+        if role == Role::Initiator {
+            self.transition(SessionState::HelloSent, "sending Hello");
+        }
         let rx = self
             .control_rx
             .as_mut()
             .expect("control_rx must exist during handshake");
-        let welcome: ControlMessage = rx.recv().await?.ok_or_else(|| {
-            DaemonError::Protocol(cross_control_protocol::ProtocolError::StreamClosed)
-        })?;
-
-        match welcome {
-            ControlMessage::Welcome {
-                version,
-                machine_id,
-                name,
-                screen,
-            } => {
-                verify_version(version)?;
-                self.machine_id = machine_id;
-                self.name.clone_from(&name);
-                self.remote_screen = screen;
-                self.state = SessionState::Idle;
-                info!(peer = %name, id = %machine_id, "handshake complete (initiator)");
+        let admission = auth.map(|hook| Admission {
+            hook,
+            fingerprint: self.fingerprint.clone(),
+            address: self.connection.remote_address(),
+        });
+        let exchange = handshake::exchange(
+            &mut self.control_tx,
+            rx,
+            role,
+            ours,
+            admission,
+            HANDSHAKE_TIMEOUT,
+        );
+        match exchange.await {
+            Ok(peer) => {
+                self.machine_id = peer.machine_id;
+                self.name = peer.name;
+                self.remote_screen = peer.screen;
+                self.version = peer.version;
+                self.transition(SessionState::Idle, format!("handshake complete as {role}"));
+                info!(peer = %self.name, machine_id = %self.machine_id, %role, "handshake complete");
                 Ok(())
             }
-            other => Err(DaemonError::Protocol(
-                cross_control_protocol::ProtocolError::Handshake(format!(
-                    "expected Welcome, got {other:?}"
-                )),
-            )),
+            Err(HandshakeError::Refused {
+                code,
+                detail,
+                peer_name,
+                cause,
+            }) => {
+                if let Some(name) = peer_name {
+                    self.name = name;
+                }
+                self.fail(code, detail).await;
+                Err(cause.into())
+            }
+            Err(e) => Err(e.into()),
         }
     }
 
-    /// Perform the responder side of the handshake: receive Hello, send Welcome.
-    ///
-    /// Must be called before `take_control_rx()` — uses the `control_rx` directly.
-    pub async fn handshake_responder(
+    /// Send a `DeviceAnnounce` for each of our devices that `share` allows,
+    /// recording them in `shared_devices`.
+    pub async fn announce_devices(
         &mut self,
-        our_id: MachineId,
-        our_name: &str,
-        our_screen: &ScreenGeometry,
+        devices: &[DeviceInfo],
+        share: impl Fn(&DeviceInfo) -> bool,
     ) -> Result<(), DaemonError> {
-        let rx = self
-            .control_rx
-            .as_mut()
-            .expect("control_rx must exist during handshake");
-        let hello: ControlMessage = rx.recv().await?.ok_or_else(|| {
-            DaemonError::Protocol(cross_control_protocol::ProtocolError::StreamClosed)
-        })?;
-
-        match hello {
-            ControlMessage::Hello {
-                version,
-                machine_id,
-                name,
-                screen,
-            } => {
-                verify_version(version)?;
-                self.machine_id = machine_id;
-                self.name.clone_from(&name);
-                self.remote_screen = screen;
-
-                let welcome = ControlMessage::Welcome {
-                    version: PROTOCOL_VERSION,
-                    machine_id: our_id,
-                    name: our_name.to_string(),
-                    screen: our_screen.clone(),
-                };
-                self.control_tx.send(&welcome).await?;
-                self.state = SessionState::Idle;
-                info!(peer = %name, id = %machine_id, "handshake complete (responder)");
-                Ok(())
-            }
-            other => Err(DaemonError::Protocol(
-                cross_control_protocol::ProtocolError::Handshake(format!(
-                    "expected Hello, got {other:?}"
-                )),
-            )),
-        }
-    }
-
-    /// Send a `DeviceAnnounce` for each of our devices.
-    pub async fn announce_devices(&mut self, devices: &[DeviceInfo]) -> Result<(), DaemonError> {
         for device in devices {
+            if !share(device) {
+                debug!(session = %self.id, device = %device.name, "device not shared with peer");
+                continue;
+            }
             let msg = ControlMessage::DeviceAnnounce(device.clone());
-            self.control_tx.send(&msg).await?;
-            debug!(device = %device.name, "announced device");
+            self.control_tx.send_tagged(&msg).await?;
+            self.shared_devices.push(device.id);
+            debug!(session = %self.id, device = %device.name, "announced device");
         }
+        let count = self.shared_devices.len();
+        self.record(format!("announced {count} devices"));
         Ok(())
     }
 
@@ -188,14 +523,21 @@ impl PeerSession {
 
         // Open input stream BEFORE sending Enter so it's available when
         // the remote calls accept_input_stream() upon receiving Enter.
-        let input_tx = self.connection.open_input_stream().await?;
+        let mut input_tx = self.connection.open_input_stream().await?;
+        input_tx.set_max_size(self.send_limits.input);
+        input_tx.count_bytes(self.stats.bytes_sent.clone());
         self.input_tx = Some(input_tx);
 
         let enter = ControlMessage::Enter { edge, position };
-        self.control_tx.send(&enter).await?;
+        self.control_tx.send_tagged(&enter).await?;
+        self.entered_through = Some(edge);
+        self.local_user_active = false;
 
         // Transition state so duplicate send_enter calls are rejected
-        self.state = SessionState::Controlling;
+        self.transition(
+            SessionState::Controlling,
+            format!("sent Enter at {edge:?} edge, position {position}"),
+        );
         debug!("sent Enter, waiting for EnterAck via event loop");
         Ok(())
     }
@@ -214,16 +556,84 @@ impl PeerSession {
             ));
         }
 
-        self.control_tx.send(&ControlMessage::EnterAck).await?;
-        self.state = SessionState::Controlled;
-        info!(peer = %self.name, "now being controlled by remote");
+        self.control_tx
+            .send_tagged(&ControlMessage::EnterAck)
+            .await?;
+        self.transition(SessionState::Controlled, "received Enter, sent EnterAck");
+        info!(peer = %self.name, session = %self.id, "now being controlled by remote");
         Ok(())
     }
 
+    /// Refuse an incoming Enter from the remote peer, staying Idle.
+    pub async fn deny_enter(&mut self, reason: EnterDeniedReason) -> Result<(), DaemonError> {
+        self.control_tx
+            .send_tagged(&ControlMessage::EnterDenied { reason })
+            .await?;
+        self.record(format!("refused Enter: {reason:?}"));
+        warn!(peer = %self.name, session = %self.id, ?reason, "refused Enter from remote");
+        Ok(())
+    }
+
+    /// Tell the peer why something it sent was rejected.
+    pub async fn report_error(&mut self, code: ErrorCode, detail: String) {
+        warn!(peer = %self.name, session = %self.id, ?code, "rejecting peer: {detail}");
+        self.record(format!("sent Error {code:?}: {detail}"));
+        let _ = self
+            .control_tx
+            .send_tagged(&ControlMessage::Error { code, detail })
+            .await;
+    }
+
+    /// Refuse a device the peer announced. Only the first refusal is
+    /// reported back, so a peer flooding us with announcements isn't
+    /// answered with a flood of errors.
+    pub async fn reject_device(&mut self, detail: String) {
+        self.devices_rejected += 1;
+        if self.devices_rejected == 1 {
+            self.report_error(ErrorCode::VirtualDevice, detail).await;
+        } else {
+            debug!(peer = %self.name, rejected = self.devices_rejected, "rejected device: {detail}");
+        }
+    }
+
+    /// Report a fatal error to the peer, give it a moment to arrive, and
+    /// close the connection.
+    pub async fn fail(&mut self, code: ErrorCode, detail: String) {
+        self.report_error(code, detail).await;
+        self.transition(SessionState::Disconnecting, "closing after error");
+        // The peer closes once it has read the error.
+        let _ = tokio::time::timeout(ERROR_LINGER, self.connection.closed()).await;
+        self.connection.close();
+    }
+
+    /// Handle an `Error` from the remote peer. A refused Enter is abandoned
+    /// like `EnterDenied`; fatal errors are followed by the peer closing.
+    pub fn handle_error(&mut self, code: ErrorCode, detail: &str) {
+        self.record(format!("peer reported {code:?}: {detail}"));
+        if code == ErrorCode::EnterRefused && self.state == SessionState::Controlling {
+            self.input_tx = None;
+            self.transition(SessionState::Idle, "abandoned Enter");
+        }
+        warn!(peer = %self.name, session = %self.id, ?code, "peer reported error: {detail}");
+    }
+
+    /// Handle an `EnterDenied` from the remote peer: abandon the pending Enter.
+    pub fn handle_enter_denied(&mut self, reason: EnterDeniedReason) {
+        self.input_tx = None;
+        self.transition(
+            SessionState::Idle,
+            format!("received EnterDenied: {reason:?}"),
+        );
+        if !reason.is_transient() {
+            self.enter_denied = Some(reason);
+        }
+        warn!(peer = %self.name, session = %self.id, ?reason, "remote refused to be controlled");
+    }
+
     /// Transition to Controlling state (called when `EnterAck` received via event loop).
     pub fn set_controlling(&mut self) {
-        self.state = SessionState::Controlling;
-        info!(peer = %self.name, "now controlling remote");
+        self.transition(SessionState::Controlling, "received EnterAck");
+        info!(peer = %self.name, session = %self.id, "now controlling remote");
     }
 
     /// Send Leave message and return to Idle.
@@ -232,50 +642,150 @@ impl PeerSession {
         edge: cross_control_types::ScreenEdge,
         position: u32,
     ) -> Result<(), DaemonError> {
+        // End the input stream first: the peer injects everything up to its
+        // end before treating the Leave as done.
+        if let Some(input_tx) = self.input_tx.take() {
+            if let Err(e) = input_tx.finish().await {
+                debug!(session = %self.id, error = %e, "failed to finish input stream");
+            }
+        }
         let leave = ControlMessage::Leave { edge, position };
-        self.control_tx.send(&leave).await?;
-        self.input_tx = None;
-        self.state = SessionState::Idle;
-        info!(peer = %self.name, "left remote control");
+        self.control_tx.send_tagged(&leave).await?;
+        self.transition(SessionState::Idle, format!("sent Leave at {edge:?} edge"));
+        info!(peer = %self.name, session = %self.id, "left remote control");
         Ok(())
     }
 
     /// Handle an incoming Leave from the remote peer.
     pub fn handle_leave(&mut self) {
+        self.input_tx = None;
         self.input_rx = None;
-        self.state = SessionState::Idle;
-        info!(peer = %self.name, "remote released control");
+        self.transition(SessionState::Idle, "received Leave");
+        info!(peer = %self.name, session = %self.id, "remote released control");
+    }
+
+    /// The peer controlling us went silent for `timeout`: stop treating it as
+    /// the controller.
+    pub fn controller_silent(&mut self, timeout: Duration) {
+        self.transition(
+            SessionState::Idle,
+            format!("controller silent for {}s", timeout.as_secs()),
+        );
+    }
+
+    /// Input arrived from a controller we gave up on: it is controlling
+    /// us again.
+    pub fn resume_controlled(&mut self) {
+        self.transition(SessionState::Controlled, "input resumed after silence");
+    }
+
+    /// The remote is going to sleep: drop any input streams in either direction.
+    pub fn handle_suspending(&mut self) {
+        self.suspended = true;
+        self.input_tx = None;
+        self.input_rx = None;
+        self.transition(SessionState::Idle, "peer suspending");
+        info!(peer = %self.name, session = %self.id, "peer suspending");
+    }
+
+    /// Send a keepalive `Ping`, timing it until the matching `Pong`.
+    pub async fn ping(&mut self, seq: u64) -> Result<(), DaemonError> {
+        let now = Instant::now();
+        self.last_keepalive = now;
+        // The previous ping is given up on once the next one goes out.
+        if self.ping.replace((seq, now)).is_some() {
+            self.stats.record_answered(false);
+        }
+        self.control_tx
+            .send_tagged(&ControlMessage::Ping { seq })
+            .await?;
+        Ok(())
+    }
+
+    /// Handle a `Pong`: if it answers the liveness probe, the peer is alive,
+    /// and if it answers our last `Ping`, the round trip is recorded and
+    /// returned.
+    pub fn handle_pong(&mut self, seq: u64) -> Option<Duration> {
+        if self.liveness_probe.is_some_and(|(probe, _)| probe == seq) {
+            self.liveness_probe = None;
+        }
+        let (_, at) = self.ping.filter(|(sent, _)| *sent == seq)?;
+        self.ping = None;
+        let rtt = at.elapsed();
+        self.stats.record_rtt(rtt);
+        Some(rtt)
+    }
+
+    /// The wall clock jumped (see [`crate::clock`]): restart the keepalive
+    /// and alarm timers, and forget the ping in flight rather than timing
+    /// its round trip across the jump or counting it as lost.
+    pub fn clock_jumped(&mut self, jump: ClockJump) {
+        let now = Instant::now();
+        self.last_keepalive = now;
+        self.ping = None;
+        if let Some((_, at)) = &mut self.liveness_probe {
+            *at = now;
+        }
+        self.alarm.restart_hold();
+        self.record(format!(
+            "clock jumped {} by {}s",
+            jump.as_str(),
+            jump.amount().as_secs()
+        ));
     }
 
     /// Send input events to the remote peer.
+    #[instrument(level = "trace", name = "send", skip_all)]
     pub async fn send_input(&mut self, msg: &InputMessage) -> Result<(), DaemonError> {
+        let events = msg.events.len() as u64;
         if let Some(tx) = &mut self.input_tx {
-            tx.send(msg).await?;
+            if let Err(e) = tx.send(msg).await {
+                self.stats.events_dropped += events;
+                return Err(e.into());
+            }
+            self.stats.events_sent += events;
             Ok(())
         } else {
-            warn!("attempted to send input without open input stream");
+            self.stats.events_dropped += events;
+            warn!(session = %self.id, "attempted to send input without open input stream");
             Ok(())
         }
     }
 
+    /// Short code derived from the TLS session secret, identical on both ends.
+    ///
+    /// Formatted as two groups of three digits (e.g. `042-917`) so users can
+    /// read it aloud and compare it across machines.
+    pub fn verification_code(&self) -> Result<String, DaemonError> {
+        let bytes = self
+            .connection
+            .export_keying_material(VERIFY_LABEL, &[], 8)?;
+        let mut raw = [0u8; 8];
+        raw.copy_from_slice(&bytes);
+        let n = u64::from_be_bytes(raw) % 1_000_000;
+        Ok(format!("{:03}-{:03}", n / 1000, n % 1000))
+    }
+
     /// Send Bye and close the connection.
     pub async fn disconnect(&mut self) -> Result<(), DaemonError> {
-        self.state = SessionState::Disconnecting;
-        let _ = self.control_tx.send(&ControlMessage::Bye).await;
+        self.transition(SessionState::Disconnecting, "sent Bye");
+        let _ = self.control_tx.send_tagged(&ControlMessage::Bye).await;
         self.connection.close();
-        info!(peer = %self.name, "disconnected");
+        info!(peer = %self.name, session = %self.id, "disconnected");
         Ok(())
     }
 }
 
-fn verify_version(remote: ProtocolVersion) -> Result<(), DaemonError> {
-    if remote.major != PROTOCOL_VERSION.major {
-        return Err(DaemonError::Protocol(
-            cross_control_protocol::ProtocolError::VersionMismatch {
-                remote: remote.to_string(),
-                local: PROTOCOL_VERSION.to_string(),
-            },
-        ));
+/// Hex session ID derived from the TLS session secret, identical on both ends.
+fn session_id(connection: &PeerConnection) -> String {
+    match connection.export_keying_material(SESSION_ID_LABEL, &[], 8) {
+        Ok(bytes) => {
+            let mut raw = [0u8; 8];
+            raw.copy_from_slice(&bytes);
+            format!("{:016x}", u64::from_be_bytes(raw))
+        }
+        // Only possible before the TLS handshake completes, which a
+        // `PeerConnection` never is.
+        Err(_) => String::from("unknown"),
     }
-    Ok(())
 }
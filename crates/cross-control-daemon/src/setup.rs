@@ -1,26 +1,29 @@
 //! Config loading, cert generation, and machine identity.
 
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
 use cross_control_certgen::GeneratedCert;
+use cross_control_clipboard::ClipboardProvider;
+use cross_control_discovery::{BroadcastDiscovery, Discovery};
 use cross_control_types::MachineId;
-use tracing::info;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
 use uuid::Uuid;
 
-use crate::config::Config;
+use crate::config::{Config, DiscoveryMethod};
 use crate::error::DaemonError;
+use crate::network;
 
 /// Load configuration from the given path, or the default location.
 pub fn load_config(path: Option<&str>) -> Result<Config, DaemonError> {
-    let config_path = match path {
-        Some(p) => PathBuf::from(p),
-        None => default_config_path(),
-    };
+    let config_path = config_file_path(path);
 
     if config_path.exists() {
         let content = std::fs::read_to_string(&config_path)
             .map_err(|e| DaemonError::Config(format!("failed to read config: {e}")))?;
-        let config: Config = toml::from_str(&content)
+        let config = Config::from_toml(&content)
             .map_err(|e| DaemonError::Config(format!("failed to parse config: {e}")))?;
         info!(path = %config_path.display(), "loaded config");
         Ok(config)
@@ -30,6 +33,120 @@ pub fn load_config(path: Option<&str>) -> Result<Config, DaemonError> {
     }
 }
 
+/// The discovery backend selected by `daemon.discovery_method`, or `None`
+/// when discovery is off or the backend can't be started.
+pub fn discovery_backend(
+    config: &Config,
+    cert_pem: &str,
+    key_pem: &str,
+) -> Option<Box<dyn Discovery>> {
+    if !config.daemon.discovery {
+        return None;
+    }
+    match config.daemon.discovery_method {
+        DiscoveryMethod::Mdns => {
+            info!("no mDNS backend in this build; connecting to configured addresses only");
+            None
+        }
+        DiscoveryMethod::Broadcast => {
+            match BroadcastDiscovery::new(config.daemon.broadcast_port(), cert_pem, key_pem) {
+                Ok(backend) => Some(Box::new(backend)),
+                Err(e) => {
+                    warn!(error = %e, "broadcast discovery unavailable");
+                    None
+                }
+            }
+        }
+    }
+}
+
+/// The platform clipboard backend, or `None` when `clipboard.enabled` is
+/// off or this build has no backend for the platform.
+pub fn clipboard_backend(config: &Config) -> Option<Box<dyn ClipboardProvider>> {
+    if !config.clipboard.enabled {
+        return None;
+    }
+    platform_clipboard()
+}
+
+#[cfg(all(windows, feature = "windows"))]
+fn platform_clipboard() -> Option<Box<dyn ClipboardProvider>> {
+    match cross_control_clipboard::windows::WindowsClipboard::new() {
+        Ok(backend) => Some(Box::new(backend)),
+        Err(e) => {
+            warn!(error = %e, "clipboard unavailable");
+            None
+        }
+    }
+}
+
+#[cfg(all(target_os = "macos", feature = "macos"))]
+fn platform_clipboard() -> Option<Box<dyn ClipboardProvider>> {
+    Some(Box::new(
+        cross_control_clipboard::macos::MacosClipboard::new(),
+    ))
+}
+
+#[cfg(not(any(
+    all(windows, feature = "windows"),
+    all(target_os = "macos", feature = "macos")
+)))]
+fn platform_clipboard() -> Option<Box<dyn ClipboardProvider>> {
+    info!("no clipboard backend for this platform in this build; clipboard sync is off");
+    None
+}
+
+/// Write configuration to the given path, or the default location.
+pub fn save_config(path: Option<&str>, config: &Config) -> Result<PathBuf, DaemonError> {
+    let config_path = config_file_path(path);
+    if let Some(parent) = config_path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| DaemonError::Config(format!("failed to create config dir: {e}")))?;
+    }
+    let content = config
+        .to_toml()
+        .map_err(|e| DaemonError::Config(format!("failed to serialize config: {e}")))?;
+    std::fs::write(&config_path, content)
+        .map_err(|e| DaemonError::Config(format!("failed to write config: {e}")))?;
+    info!(path = %config_path.display(), "saved config");
+    Ok(config_path)
+}
+
+/// Resolution of the first connected display, if the platform reports one.
+///
+/// On Linux this reads the preferred mode of each connected DRM connector.
+pub fn detect_screen_size() -> Option<(u32, u32)> {
+    #[cfg(target_os = "linux")]
+    {
+        let mut connectors: Vec<PathBuf> = std::fs::read_dir("/sys/class/drm")
+            .ok()?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .collect();
+        connectors.sort();
+        connectors.into_iter().find_map(|dir| {
+            let status = std::fs::read_to_string(dir.join("status")).ok()?;
+            if status.trim() != "connected" {
+                return None;
+            }
+            let modes = std::fs::read_to_string(dir.join("modes")).ok()?;
+            parse_mode(modes.lines().next()?)
+        })
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// Parse a DRM mode line such as `2560x1440`.
+fn parse_mode(mode: &str) -> Option<(u32, u32)> {
+    let (width, height) = mode.trim().split_once('x')?;
+    // Interlaced modes carry a trailing `i`.
+    let height = height.trim_end_matches('i');
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
 /// Load TLS cert and key from the config directory, or generate if missing.
 pub fn load_or_generate_certs(config_dir: &Path) -> Result<(String, String), DaemonError> {
     let cert_path = config_dir.join("cross-control.crt");
@@ -79,7 +196,7 @@ pub fn load_or_create_machine_id(config_dir: &Path) -> Result<MachineId, DaemonE
             .trim()
             .parse()
             .map_err(|e| DaemonError::Config(format!("invalid machine-id: {e}")))?;
-        info!(id = %uuid, "loaded machine ID");
+        info!(machine_id = %uuid, "loaded machine ID");
         Ok(MachineId::from_uuid(uuid))
     } else {
         std::fs::create_dir_all(config_dir)
@@ -89,16 +206,118 @@ pub fn load_or_create_machine_id(config_dir: &Path) -> Result<MachineId, DaemonE
         std::fs::write(&id_path, id.as_uuid().to_string())
             .map_err(|e| DaemonError::Config(format!("failed to write machine-id: {e}")))?;
 
-        info!(id = %id, "created new machine ID");
+        info!(machine_id = %id, "created new machine ID");
         Ok(id)
     }
 }
 
+/// The address to bind the QUIC endpoint to: `daemon.bind` on `port`, with
+/// [`network::AUTO_BIND`] resolved to the interface peers are reached
+/// through.
+pub fn listen_address(config: &Config, port: u16) -> Result<SocketAddr, DaemonError> {
+    if config.daemon.bind == network::AUTO_BIND {
+        let ip = network::auto_listen_address(&network::peer_routes(config));
+        if let Some(ip) = ip {
+            info!(address = %ip, "listening only on the interface peers use");
+        } else {
+            info!("peers use several interfaces or none, listening on all");
+        }
+        let ip = ip.unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        return Ok(SocketAddr::new(ip, port));
+    }
+    let ip: IpAddr = config
+        .daemon
+        .bind
+        .parse()
+        .map_err(|e| DaemonError::Config(format!("invalid bind address: {e}")))?;
+    Ok(SocketAddr::new(ip, port))
+}
+
+/// Load the denylisted certificate fingerprints, one per line.
+pub fn load_denylist(config_dir: &Path) -> Result<Vec<String>, DaemonError> {
+    let path = config_dir.join("denylist");
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| DaemonError::Config(format!("failed to read denylist: {e}")))?;
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Add a certificate fingerprint to the persistent denylist.
+pub fn append_denylist(config_dir: &Path, fingerprint: &str) -> Result<(), DaemonError> {
+    use std::io::Write;
+
+    if load_denylist(config_dir)?.iter().any(|f| f == fingerprint) {
+        return Ok(());
+    }
+    std::fs::create_dir_all(config_dir)
+        .map_err(|e| DaemonError::Config(format!("failed to create config dir: {e}")))?;
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(config_dir.join("denylist"))
+        .map_err(|e| DaemonError::Config(format!("failed to open denylist: {e}")))?;
+    writeln!(file, "{fingerprint}")
+        .map_err(|e| DaemonError::Config(format!("failed to write denylist: {e}")))?;
+    info!(fingerprint = %fingerprint, "added to denylist");
+    Ok(())
+}
+
+static INSTANCE: OnceLock<String> = OnceLock::new();
+
+/// Run as the named instance. The config directory, PID file, IPC socket
+/// and default machine name all carry the name, so daemons for separate
+/// meshes can share a host. Call before any path is looked up.
+pub fn set_instance(name: &str) -> Result<(), DaemonError> {
+    check_instance_name(name)?;
+    INSTANCE
+        .set(name.to_string())
+        .map_err(|_| DaemonError::Config("instance already set".to_string()))
+}
+
+/// The instance set with [`set_instance`], if any.
+pub fn instance() -> Option<&'static str> {
+    INSTANCE.get().map(String::as_str)
+}
+
+fn check_instance_name(name: &str) -> Result<(), DaemonError> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(DaemonError::Config(format!(
+            "invalid instance name {name:?}: use letters, digits, '-' and '_'"
+        )))
+    }
+}
+
+/// `base`, suffixed with `-<instance>` for a named instance.
+pub(crate) fn namespaced(base: &str, instance: Option<&str>) -> String {
+    match instance {
+        Some(instance) => format!("{base}-{instance}"),
+        None => base.to_string(),
+    }
+}
+
 /// Get the default config directory path.
 pub fn config_dir() -> PathBuf {
     dirs::config_dir()
         .unwrap_or_else(|| PathBuf::from("~/.config"))
-        .join("cross-control")
+        .join(namespaced("cross-control", instance()))
+}
+
+/// The config file at `path`, or the default location.
+pub fn config_file_path(path: Option<&str>) -> PathBuf {
+    path.map_or_else(default_config_path, PathBuf::from)
 }
 
 /// Get the default config file path.
@@ -106,10 +325,126 @@ fn default_config_path() -> PathBuf {
     config_dir().join("config.toml")
 }
 
+/// Get the path of the daily usage history.
+pub fn metrics_path() -> PathBuf {
+    config_dir().join("metrics.toml")
+}
+
 /// Get the PID file path.
 pub fn pid_file_path() -> PathBuf {
-    dirs::runtime_dir()
-        .or_else(dirs::state_dir)
-        .unwrap_or_else(|| PathBuf::from("/tmp"))
-        .join("cross-control.pid")
+    runtime_file("pid")
+}
+
+/// Get the IPC socket path.
+pub fn socket_path() -> PathBuf {
+    runtime_file("sock")
+}
+
+/// Get the path of the file describing the running daemon.
+pub fn runtime_info_path() -> PathBuf {
+    runtime_file("info")
+}
+
+/// What a running daemon publishes about itself for the CLI, e.g. the port
+/// it actually bound when `daemon.port` is 0.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuntimeInfo {
+    pub pid: u32,
+    /// The address the QUIC endpoint is bound to.
+    pub address: SocketAddr,
+}
+
+impl RuntimeInfo {
+    /// Write the info file at `path`.
+    pub fn write(&self, path: &Path) -> Result<(), DaemonError> {
+        let content = toml::to_string(self)
+            .map_err(|e| DaemonError::Config(format!("failed to encode runtime info: {e}")))?;
+        std::fs::write(path, content)
+            .map_err(|e| DaemonError::Config(format!("failed to write runtime info: {e}")))
+    }
+
+    /// Read the info file at `path`; `None` if it is missing or unreadable.
+    pub fn load(path: &Path) -> Option<Self> {
+        let content = std::fs::read_to_string(path).ok()?;
+        toml::from_str(&content).ok()
+    }
+}
+
+/// `cross-control.<extension>` in the runtime directory, namespaced by
+/// instance.
+fn runtime_file(extension: &str) -> PathBuf {
+    runtime_dir().join(format!(
+        "{}.{extension}",
+        namespaced("cross-control", instance())
+    ))
+}
+
+/// `$XDG_RUNTIME_DIR`, which is private to the user, or else a
+/// `cross-control` directory of the user's own. Never a shared directory
+/// such as `/tmp`, where other users could reach the IPC socket.
+fn runtime_dir() -> PathBuf {
+    dirs::runtime_dir().unwrap_or_else(|| {
+        dirs::state_dir()
+            .or_else(dirs::data_local_dir)
+            .or_else(|| dirs::home_dir().map(|home| home.join(".local/state")))
+            .unwrap_or_default()
+            .join("cross-control")
+    })
+}
+
+/// Create the runtime directory, readable only by the user, if it doesn't
+/// exist yet.
+pub fn create_runtime_dir() -> Result<PathBuf, DaemonError> {
+    let dir = runtime_dir();
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder
+        .create(&dir)
+        .map_err(|e| DaemonError::Config(format!("failed to create runtime dir: {e}")))?;
+    Ok(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_drm_modes() {
+        assert_eq!(parse_mode("2560x1440\n"), Some((2560, 1440)));
+        assert_eq!(parse_mode("1920x1080i"), Some((1920, 1080)));
+        assert_eq!(parse_mode("garbage"), None);
+    }
+
+    #[test]
+    fn instance_names() {
+        assert!(check_instance_name("work").is_ok());
+        assert!(check_instance_name("home_2").is_ok());
+        assert!(check_instance_name("").is_err());
+        assert!(check_instance_name("../etc").is_err());
+        assert_eq!(
+            namespaced("cross-control", Some("work")),
+            "cross-control-work"
+        );
+        assert_eq!(namespaced("cross-control", None), "cross-control");
+    }
+
+    #[test]
+    fn runtime_dir_is_not_a_literal_tilde() {
+        assert!(!runtime_dir().starts_with("~"));
+    }
+
+    #[test]
+    fn runtime_info_round_trip() {
+        let path = std::env::temp_dir().join(format!("cc-runtime-info-{}", std::process::id()));
+        let info = RuntimeInfo {
+            pid: 4242,
+            address: "127.0.0.1:50123".parse().unwrap(),
+        };
+        info.write(&path).unwrap();
+        assert_eq!(RuntimeInfo::load(&path), Some(info));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(RuntimeInfo::load(&path), None);
+    }
 }
@@ -0,0 +1,90 @@
+//! System sleep notifications.
+//!
+//! With the `linux` feature the daemon listens for logind's `PrepareForSleep`
+//! signal and holds a delay inhibitor, so peers hear `Suspending` before the
+//! machine actually sleeps. Without a supported backend the watcher returns
+//! immediately and peers fall back to keepalive timeouts.
+
+use tokio::sync::mpsc;
+
+use crate::daemon::DaemonEvent;
+
+/// Forward system sleep and resume notifications to the daemon until the
+/// event channel closes.
+pub async fn watch(event_tx: mpsc::Sender<DaemonEvent>) {
+    #[cfg(all(feature = "linux", target_os = "linux"))]
+    if let Err(e) = logind::watch(&event_tx).await {
+        tracing::warn!(error = %e, "logind sleep notifications unavailable");
+    }
+    #[cfg(not(all(feature = "linux", target_os = "linux")))]
+    drop(event_tx);
+}
+
+#[cfg(all(feature = "linux", target_os = "linux"))]
+mod logind {
+    use futures_lite::StreamExt;
+    use tokio::sync::{mpsc, oneshot};
+    use tracing::{debug, info};
+    use zbus::zvariant::OwnedFd;
+
+    use crate::daemon::DaemonEvent;
+
+    #[zbus::proxy(
+        interface = "org.freedesktop.login1.Manager",
+        default_service = "org.freedesktop.login1",
+        default_path = "/org/freedesktop/login1"
+    )]
+    trait Manager {
+        fn inhibit(&self, what: &str, who: &str, why: &str, mode: &str) -> zbus::Result<OwnedFd>;
+
+        #[zbus(signal)]
+        fn prepare_for_sleep(&self, start: bool) -> zbus::Result<()>;
+    }
+
+    /// Take a delay lock so logind waits (up to `InhibitDelayMaxSec`) for us
+    /// to notify peers before suspending.
+    async fn inhibit(manager: &ManagerProxy<'_>) -> Option<OwnedFd> {
+        match manager
+            .inhibit("sleep", "cross-control", "Notify peers", "delay")
+            .await
+        {
+            Ok(fd) => Some(fd),
+            Err(e) => {
+                debug!(error = %e, "failed to take sleep inhibitor");
+                None
+            }
+        }
+    }
+
+    pub async fn watch(event_tx: &mpsc::Sender<DaemonEvent>) -> zbus::Result<()> {
+        let connection = zbus::Connection::system().await?;
+        let manager = ManagerProxy::new(&connection).await?;
+        let mut signals = manager.receive_prepare_for_sleep().await?;
+        let mut lock = inhibit(&manager).await;
+        info!("watching logind for sleep");
+
+        while let Some(signal) = signals.next().await {
+            let start = signal.args()?.start;
+            if start {
+                let (done_tx, done_rx) = oneshot::channel();
+                if event_tx
+                    .send(DaemonEvent::PrepareForSleep { done: done_tx })
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+                let _ = done_rx.await;
+                // Releasing the lock lets the suspend proceed.
+                lock = None;
+            } else {
+                lock = inhibit(&manager).await;
+                if event_tx.send(DaemonEvent::Resumed).await.is_err() {
+                    break;
+                }
+            }
+        }
+        drop(lock);
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+//! Supervised background tasks.
+//!
+//! A panic in a bare `tokio::spawn` task is swallowed by the runtime, and
+//! whatever the task did (reading a peer's messages, accepting connections)
+//! silently stops. Tasks spawned here have their panics logged with the task
+//! name, can run a cleanup step so the daemon forgets state the task owned,
+//! and long-lived tasks are restarted.
+
+use std::any::Any;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::time::Duration;
+
+use futures_lite::FutureExt;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+/// Pause before restarting a task that panicked, so a task that panics
+/// immediately doesn't spin.
+pub const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// Spawn a task whose panic is logged.
+pub fn spawn<F>(name: &'static str, task: F) -> JoinHandle<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    spawn_with_cleanup(name, task, async {})
+}
+
+/// Spawn a task; if it panics, log the panic and run `cleanup`.
+pub fn spawn_with_cleanup<F, C>(name: &'static str, task: F, cleanup: C) -> JoinHandle<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+    C: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(async move {
+        if let Err(panic) = AssertUnwindSafe(task).catch_unwind().await {
+            log_panic(name, panic.as_ref());
+            cleanup.await;
+        }
+    })
+}
+
+/// Spawn a long-lived task made by `make`, starting a fresh one after
+/// [`RESTART_DELAY`] whenever it panics. Returns once a task finishes
+/// normally.
+pub fn spawn_restarting<M, F>(name: &'static str, mut make: M) -> JoinHandle<()>
+where
+    M: FnMut() -> F + Send + 'static,
+    F: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(async move {
+        loop {
+            match AssertUnwindSafe(make()).catch_unwind().await {
+                Ok(()) => return,
+                Err(panic) => log_panic(name, panic.as_ref()),
+            }
+            tokio::time::sleep(RESTART_DELAY).await;
+            info!(task = name, "restarting task");
+        }
+    })
+}
+
+fn log_panic(name: &str, panic: &(dyn Any + Send)) {
+    let message = panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("non-string panic payload");
+    error!(task = name, panic = %message, "background task panicked");
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn panic_runs_cleanup() {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        spawn_with_cleanup("test", async { panic!("boom") }, async move {
+            let _ = tx.send(());
+        })
+        .await
+        .unwrap();
+        assert!(rx.await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn cleanup_skipped_when_task_finishes() {
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        spawn_with_cleanup("test", async {}, async move {
+            let _ = tx.send(());
+        })
+        .await
+        .unwrap();
+        assert!(rx.await.is_err());
+    }
+
+    #[tokio::test]
+    async fn panicking_task_is_restarted() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&runs);
+        spawn_restarting("test", move || {
+            let run = counter.fetch_add(1, Ordering::SeqCst);
+            async move {
+                assert!(run > 0, "first run fails");
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+    }
+}
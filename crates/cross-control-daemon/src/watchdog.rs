@@ -0,0 +1,196 @@
+//! Stalled event loop detection.
+//!
+//! The event loop records when it starts and finishes handling each event.
+//! A plain OS thread, which keeps running even if the async runtime is
+//! blocked, checks that no single event takes too long. On a stall it logs
+//! the event being handled and the one before it, and releases grabbed input
+//! devices so a stuck daemon can't lock the user out of their own machine.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use cross_control_input::ReleaseHandle;
+use tokio::sync::mpsc;
+use tracing::{error, warn};
+
+use crate::daemon::DaemonEvent;
+
+/// How often the watchdog thread checks the heartbeat.
+const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// `busy_since` value while the loop is waiting for an event.
+const IDLE: u64 = u64::MAX;
+
+/// Progress of the event loop, shared with the watchdog thread.
+pub struct Heartbeat {
+    epoch: Instant,
+    /// Milliseconds since `epoch` when the current event started, or `IDLE`.
+    busy_since: AtomicU64,
+    /// The event being handled and the last one finished.
+    events: Mutex<(&'static str, &'static str)>,
+    stopped: AtomicBool,
+}
+
+/// An event that has been handled for too long.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stall {
+    pub event: &'static str,
+    pub previous: &'static str,
+    pub elapsed: Duration,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self {
+            epoch: Instant::now(),
+            busy_since: AtomicU64::new(IDLE),
+            events: Mutex::new(("", "none")),
+            stopped: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Heartbeat {
+    /// The loop started handling `event`.
+    pub fn begin(&self, event: &'static str) {
+        self.events
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .0 = event;
+        self.busy_since.store(self.now_ms(), Ordering::Release);
+    }
+
+    /// The loop finished the current event.
+    pub fn end(&self) {
+        self.busy_since.store(IDLE, Ordering::Release);
+        let mut events = self
+            .events
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        events.1 = events.0;
+    }
+
+    /// Stop the watchdog thread.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Release);
+    }
+
+    /// The event being handled, if it has taken longer than `limit`.
+    pub fn stalled(&self, limit: Duration) -> Option<Stall> {
+        let since = self.busy_since.load(Ordering::Acquire);
+        if since == IDLE {
+            return None;
+        }
+        let elapsed = Duration::from_millis(self.now_ms().saturating_sub(since));
+        if elapsed < limit {
+            return None;
+        }
+        let (event, previous) = *self
+            .events
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        Some(Stall {
+            event,
+            previous,
+            elapsed,
+        })
+    }
+
+    fn now_ms(&self) -> u64 {
+        u64::try_from(self.epoch.elapsed().as_millis()).unwrap_or(u64::MAX - 1)
+    }
+}
+
+/// Watch `heartbeat` from a dedicated thread until it is stopped. On a stall
+/// longer than `limit`, log it and call `release`; once the loop moves on,
+/// send [`DaemonEvent::WatchdogRecovered`] so the daemon can resync its state.
+pub fn spawn(
+    heartbeat: Arc<Heartbeat>,
+    limit: Duration,
+    release: Option<ReleaseHandle>,
+    event_tx: mpsc::Sender<DaemonEvent>,
+) {
+    let result = std::thread::Builder::new()
+        .name("watchdog".to_string())
+        .spawn(move || {
+            let mut stalled = false;
+            // Also stop once the daemon has been dropped.
+            while !heartbeat.stopped.load(Ordering::Acquire) && Arc::strong_count(&heartbeat) > 1 {
+                std::thread::sleep(CHECK_INTERVAL);
+                match heartbeat.stalled(limit) {
+                    Some(stall) if !stalled => {
+                        stalled = true;
+                        error!(
+                            event = stall.event,
+                            previous = stall.previous,
+                            elapsed_ms = stall.elapsed.as_millis(),
+                            "event loop stalled, releasing input devices"
+                        );
+                        if let Some(release) = &release {
+                            release();
+                        }
+                    }
+                    None if stalled => {
+                        stalled = false;
+                        warn!("event loop recovered");
+                        let _ = event_tx.try_send(DaemonEvent::WatchdogRecovered);
+                    }
+                    _ => {}
+                }
+            }
+        });
+    if let Err(e) = result {
+        warn!(error = %e, "failed to start watchdog");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idle_loop_never_stalls() {
+        let heartbeat = Heartbeat::default();
+        assert_eq!(heartbeat.stalled(Duration::ZERO), None);
+    }
+
+    #[test]
+    fn long_event_is_reported_with_previous() {
+        let heartbeat = Heartbeat::default();
+        heartbeat.begin("Tick");
+        heartbeat.end();
+        heartbeat.begin("Ipc");
+        assert_eq!(heartbeat.stalled(Duration::from_secs(60)), None);
+
+        let stall = heartbeat.stalled(Duration::ZERO).unwrap();
+        assert_eq!(stall.event, "Ipc");
+        assert_eq!(stall.previous, "Tick");
+
+        heartbeat.end();
+        assert_eq!(heartbeat.stalled(Duration::ZERO), None);
+    }
+
+    #[test]
+    fn stall_releases_devices_and_recovery_is_reported() {
+        let heartbeat = Arc::new(Heartbeat::default());
+        let released = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&released);
+        let (tx, mut rx) = mpsc::channel(1);
+
+        heartbeat.begin("PeerControl");
+        spawn(
+            Arc::clone(&heartbeat),
+            Duration::ZERO,
+            Some(Arc::new(move || flag.store(true, Ordering::SeqCst))),
+            tx,
+        );
+        std::thread::sleep(CHECK_INTERVAL * 2);
+        assert!(released.load(Ordering::SeqCst));
+
+        heartbeat.end();
+        std::thread::sleep(CHECK_INTERVAL * 2);
+        heartbeat.stop();
+        assert!(matches!(rx.try_recv(), Ok(DaemonEvent::WatchdogRecovered)));
+    }
+}
@@ -3,16 +3,21 @@
 use std::net::SocketAddr;
 use std::time::Duration;
 
+use cross_control_clipboard::mock::MockClipboardHandle;
 use cross_control_daemon::config::{
-    Config, DaemonConfig, IdentityConfig, ScreenAdjacency, ScreenConfig,
+    ClipboardScope, Config, ScreenAdjacency, ScreenConfig, ScreenMode,
+};
+use cross_control_daemon::{DaemonEvent, DaemonStatus};
+use cross_control_input::mock::{MockCaptureHandle, MockEmulation, MockEmulationHandle};
+use cross_control_protocol::Transport;
+use cross_control_test_support::{
+    config, screen, test_devices, wait_for_sessions, wait_for_status, DaemonBundle, Endpoint,
 };
-use cross_control_daemon::{Daemon, DaemonEvent, DaemonStatus};
-use cross_control_input::mock::{MockCapture, MockEmulation, MockEmulationHandle};
 use cross_control_types::{
-    ButtonState, CapturedEvent, DeviceCapability, DeviceId, DeviceInfo, InputEvent, KeyCode,
-    MachineId, Position,
+    ButtonState, CapturedEvent, ClipboardContent, DeviceId, InputEvent, KeyCode, MachineId,
+    Position, PowerState, ScreenState,
 };
-use tokio::sync::{mpsc, watch};
+use tokio::sync::{mpsc, oneshot, watch};
 use tracing_subscriber::EnvFilter;
 
 /// Everything needed to run a two-daemon test.
@@ -20,15 +25,23 @@ use tracing_subscriber::EnvFilter;
 struct TestPair {
     // Daemon A (initiator / left)
     feed_a: mpsc::Sender<CapturedEvent>,
+    capture_a: MockCaptureHandle,
+    addr_a: SocketAddr,
     emulation_a: MockEmulationHandle,
+    clipboard_a: MockClipboardHandle,
     status_a: watch::Receiver<DaemonStatus>,
     shutdown_a: mpsc::Sender<DaemonEvent>,
 
     // Daemon B (responder / right)
     feed_b: mpsc::Sender<CapturedEvent>,
+    capture_b: MockCaptureHandle,
     emulation_b: MockEmulationHandle,
+    clipboard_b: MockClipboardHandle,
     status_b: watch::Receiver<DaemonStatus>,
     shutdown_b: mpsc::Sender<DaemonEvent>,
+    /// B's certificate and config, for starting B again.
+    cert_b: cross_control_certgen::GeneratedCert,
+    config_b: Config,
 
     // Join handles
     handle_a: tokio::task::JoinHandle<()>,
@@ -44,21 +57,6 @@ impl TestPair {
     }
 }
 
-fn test_devices() -> Vec<DeviceInfo> {
-    vec![
-        DeviceInfo {
-            id: DeviceId(1),
-            name: "Test Keyboard".to_string(),
-            capabilities: vec![DeviceCapability::Keyboard],
-        },
-        DeviceInfo {
-            id: DeviceId(2),
-            name: "Test Mouse".to_string(),
-            capabilities: vec![DeviceCapability::RelativeMouse, DeviceCapability::Scroll],
-        },
-    ]
-}
-
 /// Set up two daemons on loopback.
 ///
 /// Daemon A has a screen "machine-b" at `Position::Right` pointing at B.
@@ -66,148 +64,73 @@ fn test_devices() -> Vec<DeviceInfo> {
 ///
 /// A initiates the outbound connection to B.
 async fn setup_pair() -> TestPair {
-    let _ = rustls::crypto::ring::default_provider().install_default();
-
-    let cert_a = cross_control_certgen::generate_certificate("localhost").unwrap();
-    let cert_b = cross_control_certgen::generate_certificate("localhost").unwrap();
-
-    let bind: SocketAddr = "127.0.0.1:0".parse().unwrap();
-    let transport_a =
-        cross_control_protocol::QuicTransport::bind(bind, &cert_a.cert_pem, &cert_a.key_pem)
-            .unwrap();
-    let transport_b =
-        cross_control_protocol::QuicTransport::bind(bind, &cert_b.cert_pem, &cert_b.key_pem)
-            .unwrap();
+    setup_pair_with_emulation_b(MockEmulation::new()).await
+}
 
-    let addr_b = transport_b.local_addr().unwrap();
+/// Like [`setup_pair`], but with a caller-supplied emulation backend for B.
+async fn setup_pair_with_emulation_b(backend_b: MockEmulation) -> TestPair {
+    setup_pair_with(backend_b, |_, _| {}).await
+}
 
-    let machine_id_a = MachineId::new();
-    let machine_id_b = MachineId::new();
+/// Like [`setup_pair`], but lets the caller adjust both configs before the
+/// daemons start.
+async fn setup_pair_with(
+    backend_b: MockEmulation,
+    configure: impl FnOnce(&mut Config, &mut Config),
+) -> TestPair {
+    let a = Endpoint::bind();
+    let b = Endpoint::bind();
 
     // Config for daemon A: knows about B at Position::Right
-    let config_a = Config {
-        daemon: DaemonConfig {
-            screen_width: 1920,
-            screen_height: 1080,
-            ..DaemonConfig::default()
-        },
-        identity: IdentityConfig {
-            name: "machine-a".to_string(),
-        },
-        screens: vec![ScreenConfig {
-            name: "machine-b".to_string(),
-            address: Some(addr_b.to_string()),
-            position: Position::Right,
-            fingerprint: None,
+    let mut config_a = config(
+        "machine-a",
+        vec![ScreenConfig {
+            fingerprint: Some(b.cert.fingerprint.clone()),
+            ..screen("machine-b", Position::Right, Some(b.addr))
         }],
-        ..Config::default()
-    };
+    );
 
     // Config for daemon B: knows about A at Position::Left (no address — A connects to B)
-    let config_b = Config {
-        daemon: DaemonConfig {
-            screen_width: 1920,
-            screen_height: 1080,
-            ..DaemonConfig::default()
-        },
-        identity: IdentityConfig {
-            name: "machine-b".to_string(),
-        },
-        screens: vec![ScreenConfig {
-            name: "machine-a".to_string(),
-            address: None,
-            position: Position::Left,
-            fingerprint: None,
+    let mut config_b = config(
+        "machine-b",
+        vec![ScreenConfig {
+            fingerprint: Some(a.cert.fingerprint.clone()),
+            ..screen("machine-a", Position::Left, None)
         }],
-        ..Config::default()
-    };
-
-    // Mock backends for A
-    let (capture_a, feed_a) = MockCapture::new();
-    let emulation_a_backend = MockEmulation::new();
-    let emulation_a = emulation_a_backend.handle();
-
-    // Mock backends for B
-    let (capture_b, feed_b) = MockCapture::new();
-    let emulation_b_backend = MockEmulation::new();
-    let emulation_b = emulation_b_backend.handle();
-
-    // Build daemons
-    let mut daemon_a = Daemon::new(
-        config_a,
-        machine_id_a,
-        transport_a,
-        Box::new(capture_a),
-        Box::new(emulation_a_backend),
     );
-    daemon_a.set_local_devices(test_devices());
-    let status_a = daemon_a.status_receiver();
-    let shutdown_a = daemon_a.event_sender();
 
-    let mut daemon_b = Daemon::new(
-        config_b,
-        machine_id_b,
-        transport_b,
-        Box::new(capture_b),
-        Box::new(emulation_b_backend),
-    );
-    daemon_b.set_local_devices(test_devices());
-    let status_b = daemon_b.status_receiver();
-    let shutdown_b = daemon_b.event_sender();
+    configure(&mut config_a, &mut config_b);
 
     // Spawn daemons — B first (it's the server), then A (connects to B)
-    let handle_b = tokio::spawn(async move {
-        if let Err(e) = daemon_b.run().await {
-            eprintln!("daemon B error: {e}");
-        }
-    });
+    let (daemon_b, handle_b) =
+        DaemonBundle::with_emulation(config_b.clone(), b.transport, backend_b).spawn();
 
     // Small delay to let B start accepting
     tokio::time::sleep(Duration::from_millis(50)).await;
 
-    let handle_a = tokio::spawn(async move {
-        if let Err(e) = daemon_a.run().await {
-            eprintln!("daemon A error: {e}");
-        }
-    });
+    let (daemon_a, handle_a) = DaemonBundle::new(config_a, a.transport).spawn();
 
     TestPair {
-        feed_a,
-        emulation_a,
-        status_a,
-        shutdown_a,
-        feed_b,
-        emulation_b,
-        status_b,
-        shutdown_b,
+        feed_a: daemon_a.feed,
+        capture_a: daemon_a.capture,
+        addr_a: a.addr,
+        emulation_a: daemon_a.emulation,
+        clipboard_a: daemon_a.clipboard,
+        status_a: daemon_a.status,
+        shutdown_a: daemon_a.events,
+        feed_b: daemon_b.feed,
+        capture_b: daemon_b.capture,
+        emulation_b: daemon_b.emulation,
+        clipboard_b: daemon_b.clipboard,
+        status_b: daemon_b.status,
+        shutdown_b: daemon_b.events,
+        cert_b: b.cert,
+        config_b,
         handle_a,
         handle_b,
     }
 }
 
-/// Wait for a condition on a status receiver with timeout.
-async fn wait_for_status(
-    rx: &mut watch::Receiver<DaemonStatus>,
-    timeout: Duration,
-    pred: impl Fn(&DaemonStatus) -> bool,
-) -> Result<DaemonStatus, &'static str> {
-    tokio::time::timeout(timeout, async {
-        loop {
-            {
-                let status = rx.borrow_and_update().clone();
-                if pred(&status) {
-                    return Ok(status);
-                }
-            }
-            if rx.changed().await.is_err() {
-                return Err("watch closed");
-            }
-        }
-    })
-    .await
-    .map_err(|_| "timeout")?
-}
-
 #[tokio::test]
 async fn test_handshake() {
     let mut pair = setup_pair().await;
@@ -272,6 +195,365 @@ async fn test_device_announce() {
     pair.shutdown().await;
 }
 
+#[tokio::test]
+async fn test_devices_report_lists_captured_and_virtual_devices() {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+
+    let mut pair = setup_pair().await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    for dx in [-10, 10] {
+        let event = CapturedEvent {
+            device_id: DeviceId(2),
+            timestamp_us: 1000,
+            event: InputEvent::MouseMove { dx, dy: 0 },
+        };
+        pair.feed_a.send(event).await.unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let IpcResponse::Devices(report) = ipc_request(&pair.shutdown_a, IpcRequest::Devices).await
+    else {
+        panic!("expected a device report");
+    };
+    let counts: Vec<(&str, u64)> = report
+        .captured
+        .iter()
+        .map(|d| (d.name.as_str(), d.events))
+        .collect();
+    assert_eq!(counts, [("Test Keyboard", 0), ("Test Mouse", 2)]);
+
+    let IpcResponse::Devices(report) = ipc_request(&pair.shutdown_b, IpcRequest::Devices).await
+    else {
+        panic!("expected a device report");
+    };
+    assert_eq!(report.remote.len(), 1);
+    assert_eq!(report.remote[0].peer, "machine-a");
+    let names: Vec<&str> = report.remote[0]
+        .devices
+        .iter()
+        .map(|d| d.name.as_str())
+        .collect();
+    assert_eq!(names, ["Test Keyboard", "Test Mouse"]);
+
+    pair.shutdown().await;
+}
+
+/// `History` lists the transitions of the session with a peer, and names an
+/// unknown peer as such.
+#[tokio::test]
+async fn test_session_history() {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+    use cross_control_types::ErrorCode;
+
+    let mut pair = setup_pair().await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    push_a_to_right_edge(&pair).await;
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.controlling.is_some()
+    })
+    .await
+    .expect("A should be controlling");
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let IpcResponse::History(history) = ipc_request(
+        &pair.shutdown_a,
+        IpcRequest::History {
+            peer: "machine-b".to_string(),
+        },
+    )
+    .await
+    else {
+        panic!("expected a session history");
+    };
+    assert_eq!(history.state, "Controlling");
+    let events: Vec<&str> = history.events.iter().map(|e| e.event.as_str()).collect();
+    assert!(events.iter().any(|e| e.starts_with("handshake complete")));
+    let enter = events.iter().position(|e| e.starts_with("sent Enter"));
+    let ack = events.iter().position(|e| *e == "received EnterAck");
+    assert!(
+        enter.is_some() && enter < ack,
+        "unexpected history: {events:?}"
+    );
+    assert!(history.events.windows(2).all(|w| w[0].at_ms <= w[1].at_ms));
+
+    let response = ipc_request(
+        &pair.shutdown_a,
+        IpcRequest::History {
+            peer: "nobody".to_string(),
+        },
+    )
+    .await;
+    assert!(matches!(
+        response,
+        IpcResponse::Error {
+            code: ErrorCode::UnknownPeer,
+            ..
+        }
+    ));
+
+    pair.shutdown().await;
+}
+
+/// With `locate_on_enter`, crossing shakes the peer's pointer along the
+/// entry edge and leaves it where it entered, without handing control back.
+#[tokio::test]
+async fn test_locate_on_enter_shakes_pointer_along_edge() {
+    let mut pair = setup_pair_with(MockEmulation::new(), |config_a, _| {
+        config_a.screens[0].wake_on_enter = false;
+        config_a.screens[0].locate_on_enter = true;
+    })
+    .await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    push_a_to_right_edge(&pair).await;
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.controlling.is_some()
+    })
+    .await
+    .expect("A should be controlling");
+    tokio::time::sleep(Duration::from_millis(600)).await;
+
+    let shake: Vec<i32> = pair
+        .emulation_b
+        .injected_events()
+        .into_iter()
+        .filter_map(|e| match e.event {
+            InputEvent::MouseMove { dx: 0, dy } => Some(dy),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(shake, vec![24, -48, 48, -48, 48, -48, 48, -24]);
+    assert!(pair.status_a.borrow().controlling.is_some());
+
+    pair.shutdown().await;
+}
+
+/// `Teleport` enters a peer and warps its cursor, falling back to relative
+/// motion for a pointer without absolute axes; teleporting to this machine
+/// takes control back.
+#[tokio::test]
+async fn test_teleport() {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+    use cross_control_types::ErrorCode;
+
+    let mut pair = setup_pair_with(MockEmulation::new(), |config_a, _| {
+        config_a.screens[0].wake_on_enter = false;
+    })
+    .await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let response = ipc_request(
+        &pair.shutdown_a,
+        IpcRequest::Teleport {
+            screen: "machine-b".to_string(),
+            x: 100,
+            y: 5000,
+        },
+    )
+    .await;
+    assert!(
+        matches!(response, IpcResponse::Teleported { ref screen, x: 100, y: 1079 } if screen == "machine-b"),
+        "unexpected response: {response:?}"
+    );
+    assert!(pair.status_a.borrow().controlling.is_some());
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.cursor_x == 100 && s.cursor_y == 1079
+    })
+    .await
+    .expect("B's cursor should move");
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    // A entered B's left edge at the height of A's cursor.
+    let moves: Vec<InputEvent> = pair
+        .emulation_b
+        .injected_events()
+        .into_iter()
+        .map(|e| e.event)
+        .collect();
+    assert_eq!(moves, vec![InputEvent::MouseMove { dx: 100, dy: 539 }]);
+
+    let response = ipc_request(
+        &pair.shutdown_a,
+        IpcRequest::Teleport {
+            screen: "machine-a".to_string(),
+            x: 10,
+            y: 20,
+        },
+    )
+    .await;
+    assert!(matches!(
+        response,
+        IpcResponse::Teleported { x: 10, y: 20, .. }
+    ));
+    let status = pair.status_a.borrow().clone();
+    assert_eq!(
+        (status.controlling, status.cursor_x, status.cursor_y),
+        (None, 10, 20)
+    );
+
+    let response = ipc_request(
+        &pair.shutdown_a,
+        IpcRequest::Teleport {
+            screen: "nobody".to_string(),
+            x: 0,
+            y: 0,
+        },
+    )
+    .await;
+    assert!(matches!(
+        response,
+        IpcResponse::Error {
+            code: ErrorCode::NotConnected,
+            ..
+        }
+    ));
+
+    pair.shutdown().await;
+}
+
+/// `TypeText` enters the peer and types with a shared keyboard, holding
+/// Shift for shifted characters.
+#[tokio::test]
+async fn test_type_text() {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+    use cross_control_types::ErrorCode;
+
+    let mut pair = setup_pair().await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let response = ipc_request(
+        &pair.shutdown_a,
+        IpcRequest::TypeText {
+            screen: "machine-é".to_string(),
+            text: "é".to_string(),
+        },
+    )
+    .await;
+    assert!(matches!(
+        response,
+        IpcResponse::Error {
+            code: ErrorCode::InvalidRequest,
+            ..
+        }
+    ));
+
+    let response = ipc_request(
+        &pair.shutdown_a,
+        IpcRequest::TypeText {
+            screen: "machine-b".to_string(),
+            text: "Hi!".to_string(),
+        },
+    )
+    .await;
+    assert!(
+        matches!(response, IpcResponse::Typed { chars: 3, .. }),
+        "unexpected response: {response:?}"
+    );
+    assert!(pair.status_a.borrow().controlling.is_some());
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let keys: Vec<(KeyCode, ButtonState)> = pair
+        .emulation_b
+        .injected_events()
+        .into_iter()
+        .filter_map(|e| match e.event {
+            InputEvent::Key { code, state } => Some((code, state)),
+            _ => None,
+        })
+        .collect();
+    let (down, up) = (ButtonState::Pressed, ButtonState::Released);
+    assert_eq!(
+        keys,
+        vec![
+            (KeyCode::LeftShift, down),
+            (KeyCode::KeyH, down),
+            (KeyCode::KeyH, up),
+            (KeyCode::LeftShift, up),
+            (KeyCode::KeyI, down),
+            (KeyCode::KeyI, up),
+            (KeyCode::LeftShift, down),
+            (KeyCode::Digit1, down),
+            (KeyCode::Digit1, up),
+            (KeyCode::LeftShift, up),
+        ]
+    );
+
+    pair.shutdown().await;
+}
+
+/// A corner bound to `switch` takes control of the named screen, and one
+/// bound to `release` on the controlled machine hands control back.
+#[tokio::test]
+async fn test_corner_actions() {
+    use cross_control_daemon::config::{CornerAction, CornerRule, ScreenCorner};
+
+    let mut pair = setup_pair_with(MockEmulation::new(), |config_a, config_b| {
+        config_a.input.corners.push(CornerRule {
+            corner: ScreenCorner::TopLeft,
+            action: CornerAction::Switch("machine-b".to_string()),
+        });
+        config_b.input.corners.push(CornerRule {
+            corner: ScreenCorner::BottomRight,
+            action: CornerAction::Release,
+        });
+    })
+    .await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mouse_move = |dx, dy| CapturedEvent {
+        device_id: DeviceId(2),
+        timestamp_us: 1000,
+        event: InputEvent::MouseMove { dx, dy },
+    };
+    pair.feed_a.send(mouse_move(-5000, -5000)).await.unwrap();
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.controlling.is_some()
+    })
+    .await
+    .expect("the top-left corner should switch to B");
+
+    pair.feed_a.send(mouse_move(5000, 5000)).await.unwrap();
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.controlling.is_none()
+    })
+    .await
+    .expect("B's bottom-right corner should release control");
+    assert!(pair.status_b.borrow().controlled_by.is_none());
+
+    pair.shutdown().await;
+}
+
 #[tokio::test]
 async fn test_enter_leave_flow() {
     let mut pair = setup_pair().await;
@@ -331,194 +613,3224 @@ async fn test_enter_leave_flow() {
             code: KeyCode::LeftShift,
             state: ButtonState::Pressed,
         },
-        InputEvent::Key {
-            code: KeyCode::Escape,
-            state: ButtonState::Pressed,
+        InputEvent::Key {
+            code: KeyCode::Escape,
+            state: ButtonState::Pressed,
+        },
+    ];
+    for event in hotkey_events {
+        let captured = CapturedEvent {
+            device_id: DeviceId(1),
+            timestamp_us: 2000,
+            event,
+        };
+        pair.feed_a.send(captured).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // Wait for daemon A to release control
+    let status = wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.controlling.is_none()
+    })
+    .await
+    .expect("daemon A should release control");
+
+    assert!(status.controlling.is_none());
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_input_forwarding() {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+
+    let _ = tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::new("debug"))
+        .with_test_writer()
+        .try_init();
+    let mut pair = setup_pair().await;
+
+    // Wait for handshake
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake A");
+
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Enter controlling state by pushing cursor right
+    for _ in 0..5 {
+        let event = CapturedEvent {
+            device_id: DeviceId(2),
+            timestamp_us: 1000,
+            event: InputEvent::MouseMove { dx: 500, dy: 0 },
+        };
+        pair.feed_a.send(event).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    // Wait for controlling state
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.controlling.is_some()
+    })
+    .await
+    .expect("should be controlling");
+
+    // Wait for B to confirm controlled_by
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.controlled_by.is_some()
+    })
+    .await
+    .expect("B should be controlled");
+
+    // Give the input reader time to be fully established
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Send multiple key events with delays to ensure delivery
+    for i in 0..5 {
+        let key_event = CapturedEvent {
+            device_id: DeviceId(1),
+            timestamp_us: 3000 + u64::try_from(i).unwrap_or(0),
+            event: InputEvent::Key {
+                code: KeyCode::KeyA,
+                state: ButtonState::Pressed,
+            },
+        };
+        pair.feed_a.send(key_event).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    // Wait for B's emulation to receive the injected event
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let events = pair.emulation_b.injected_events();
+            if events.iter().any(|e| {
+                matches!(
+                    &e.event,
+                    InputEvent::Key {
+                        code: KeyCode::KeyA,
+                        ..
+                    }
+                )
+            }) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("daemon B should receive KeyA injection");
+
+    let IpcResponse::Status(status_a) = ipc_request(&pair.shutdown_a, IpcRequest::Status).await
+    else {
+        panic!("expected status");
+    };
+    assert_eq!(status_a.counters.crossings, 1);
+    assert!(status_a.counters.events_forwarded >= 5);
+    assert_eq!(status_a.version, cross_control_daemon::VERSION);
+    let IpcResponse::Status(status_b) = ipc_request(&pair.shutdown_b, IpcRequest::Status).await
+    else {
+        panic!("expected status");
+    };
+    assert!(status_b.counters.events_injected >= 5);
+    assert_eq!(status_b.counters.reconnects, 0);
+
+    pair.shutdown().await;
+}
+
+/// Start a fresh daemon B with `config_b` and B's original certificate,
+/// returning its emulation handle.
+fn restart_b(pair: &mut TestPair, config_b: Config) -> MockEmulationHandle {
+    let b = Endpoint::bind_cert(pair.cert_b.clone());
+    let (daemon_b, handle_b) = DaemonBundle::new(config_b, b.transport).spawn();
+    pair.shutdown_b = daemon_b.events;
+    pair.handle_b = handle_b;
+    daemon_b.emulation
+}
+
+/// Kill the controlled daemon while input is flowing: the controller must
+/// take its input back, forget the crossing, and pair up again with the
+/// restarted peer.
+#[tokio::test]
+async fn test_controller_recovers_when_controlled_daemon_dies() {
+    let mut pair = setup_pair().await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    push_a_to_right_edge(&pair).await;
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.controlling.is_some()
+    })
+    .await
+    .expect("A should be controlling");
+    assert!(!pair.capture_a.was_released());
+
+    // Keep input flowing while B dies without any shutdown handshake.
+    let feed = pair.feed_a.clone();
+    let typing = tokio::spawn(async move {
+        loop {
+            let event = CapturedEvent {
+                device_id: DeviceId(1),
+                timestamp_us: 4000,
+                event: InputEvent::Key {
+                    code: KeyCode::KeyB,
+                    state: ButtonState::Pressed,
+                },
+            };
+            if feed.send(event).await.is_err() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    pair.handle_b.abort();
+    let _ = (&mut pair.handle_b).await;
+
+    let status = wait_for_status(&mut pair.status_a, Duration::from_secs(10), |s| {
+        s.controlling.is_none() && s.session_count == 0
+    })
+    .await
+    .expect("A should return to idle");
+    typing.abort();
+    assert!(pair.capture_a.was_released(), "A must ungrab its devices");
+    assert_eq!((status.cursor_x, status.cursor_y), (960, 540));
+
+    // A restarted daemon dials the screens it knows; this time B knows A's
+    // address.
+    let mut config_b = pair.config_b.clone();
+    config_b.screens[0].address = Some(pair.addr_a.to_string());
+    let injected_b = restart_b(&mut pair, config_b);
+
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count == 1
+    })
+    .await
+    .expect("A should reconnect to the restarted B");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // The cursor starts from the middle again, so crossing takes a push.
+    push_a_to_right_edge(&pair).await;
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.controlling.is_some()
+    })
+    .await
+    .expect("A should control the restarted B");
+    let event = CapturedEvent {
+        device_id: DeviceId(1),
+        timestamp_us: 5000,
+        event: InputEvent::Key {
+            code: KeyCode::KeyC,
+            state: ButtonState::Pressed,
+        },
+    };
+    pair.feed_a.send(event).await.unwrap();
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !injected_b.injected_events().iter().any(|e| {
+            matches!(
+                e.event,
+                InputEvent::Key {
+                    code: KeyCode::KeyC,
+                    ..
+                }
+            )
+        }) {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("restarted B should receive input");
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_hotkey_release() {
+    let mut pair = setup_pair().await;
+
+    // Wait for handshake
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake A");
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Enter controlling state
+    for _ in 0..5 {
+        let event = CapturedEvent {
+            device_id: DeviceId(2),
+            timestamp_us: 1000,
+            event: InputEvent::MouseMove { dx: 500, dy: 0 },
+        };
+        pair.feed_a.send(event).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.controlling.is_some()
+    })
+    .await
+    .expect("should be controlling");
+
+    // Send the release hotkey combo
+    let hotkey_events = [
+        InputEvent::Key {
+            code: KeyCode::LeftCtrl,
+            state: ButtonState::Pressed,
+        },
+        InputEvent::Key {
+            code: KeyCode::LeftShift,
+            state: ButtonState::Pressed,
+        },
+        InputEvent::Key {
+            code: KeyCode::Escape,
+            state: ButtonState::Pressed,
+        },
+    ];
+    for event in hotkey_events {
+        let captured = CapturedEvent {
+            device_id: DeviceId(1),
+            timestamp_us: 4000,
+            event,
+        };
+        pair.feed_a.send(captured).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // Daemon A should release control
+    let status_a = wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.controlling.is_none()
+    })
+    .await
+    .expect("daemon A should release");
+
+    assert!(status_a.controlling.is_none());
+
+    // Daemon B should return to idle
+    let status_b = wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.controlled_by.is_none()
+    })
+    .await
+    .expect("daemon B should return to idle");
+
+    assert!(status_b.controlled_by.is_none());
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_enter_denied_without_emulation() {
+    let mut pair = setup_pair_with_emulation_b(MockEmulation::unavailable()).await;
+
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake A");
+
+    let status_b = wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+    assert!(!status_b.emulation_available);
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Push cursor to the right edge — B must refuse the Enter.
+    for _ in 0..5 {
+        let event = CapturedEvent {
+            device_id: DeviceId(2),
+            timestamp_us: 1000,
+            event: InputEvent::MouseMove { dx: 500, dy: 0 },
+        };
+        pair.feed_a.send(event).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(pair.status_a.borrow().controlling.is_none());
+    assert!(pair.status_b.borrow().controlled_by.is_none());
+    assert!(pair.emulation_b.devices().is_empty());
+
+    pair.shutdown().await;
+}
+
+/// Send an IPC request straight into a daemon's event loop.
+async fn ipc_request(
+    events: &mpsc::Sender<DaemonEvent>,
+    request: cross_control_daemon::ipc::IpcRequest,
+) -> cross_control_daemon::ipc::IpcResponse {
+    let (reply, reply_rx) = oneshot::channel();
+    events
+        .send(DaemonEvent::Ipc { request, reply })
+        .await
+        .unwrap();
+    reply_rx.await.unwrap()
+}
+
+/// Push A's cursor against its right edge, towards B.
+async fn push_a_to_right_edge(pair: &TestPair) {
+    for _ in 0..5 {
+        let event = CapturedEvent {
+            device_id: DeviceId(2),
+            timestamp_us: 1000,
+            event: InputEvent::MouseMove { dx: 500, dy: 0 },
+        };
+        pair.feed_a.send(event).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    tokio::time::sleep(Duration::from_millis(300)).await;
+}
+
+#[tokio::test]
+async fn test_layout_suggests_frequently_pushed_edge() {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+
+    let mut pair = setup_pair().await;
+
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake A");
+
+    // B is configured to the right, but the user keeps pushing left.
+    for dx in [-5000, 500].repeat(5) {
+        let event = CapturedEvent {
+            device_id: DeviceId(2),
+            timestamp_us: 1000,
+            event: InputEvent::MouseMove { dx, dy: 0 },
+        };
+        pair.feed_a.send(event).await.unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let IpcResponse::LayoutSuggestions(suggestions) =
+        ipc_request(&pair.shutdown_a, IpcRequest::LayoutSuggest).await
+    else {
+        panic!("expected layout suggestions");
+    };
+    assert_eq!(suggestions.len(), 1);
+    assert_eq!(suggestions[0].screen, "machine-b");
+    assert_eq!(suggestions[0].current, Position::Right);
+    assert_eq!(suggestions[0].suggested, Some(Position::Left));
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_pushed_layout_is_applied_by_trusting_peer() {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+
+    let mut pair = setup_pair_with(MockEmulation::new(), |_, config_b| {
+        config_b.daemon.allow_remote_config = true;
+    })
+    .await;
+
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake A");
+
+    // Turn A into a viewer on B: B must then refuse A's Enter.
+    let layout = r#"
+[[screens]]
+name = "machine-a"
+position = "Left"
+mode = "Viewer"
+"#;
+    let request = IpcRequest::PushConfig {
+        peer: "machine-b".to_string(),
+        layout: layout.to_string(),
+    };
+    match ipc_request(&pair.shutdown_a, request).await {
+        IpcResponse::ConfigPushed { name } => assert_eq!(name, "machine-b"),
+        other => panic!("unexpected response: {other:?}"),
+    }
+
+    push_a_to_right_edge(&pair).await;
+    assert!(pair.status_a.borrow().controlling.is_none());
+    assert!(pair.status_b.borrow().controlled_by.is_none());
+
+    // The push carried no fingerprint, yet B still has A pinned and takes
+    // a second one.
+    let request = IpcRequest::PushConfig {
+        peer: "machine-b".to_string(),
+        layout: layout.to_string(),
+    };
+    match ipc_request(&pair.shutdown_a, request).await {
+        IpcResponse::ConfigPushed { name } => assert_eq!(name, "machine-b"),
+        other => panic!("unexpected response: {other:?}"),
+    }
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_pushed_layout_is_refused_by_default() {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+
+    let mut pair = setup_pair().await;
+
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake A");
+
+    let request = IpcRequest::PushConfig {
+        peer: "machine-b".to_string(),
+        layout: String::new(),
+    };
+    match ipc_request(&pair.shutdown_a, request).await {
+        IpcResponse::Error { code, message } => {
+            assert_eq!(code, cross_control_types::ErrorCode::PeerRefused);
+            assert!(message.contains("allow_remote_config"), "{message}");
+        }
+        other => panic!("unexpected response: {other:?}"),
+    }
+
+    // B still has A as a neighbour.
+    push_a_to_right_edge(&pair).await;
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.controlling.is_some()
+    })
+    .await
+    .expect("A should still control B");
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_pair_through_daemon_endpoint() {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+
+    // A starts out knowing nothing about B.
+    let mut addr_b = None;
+    let mut pair = setup_pair_with(MockEmulation::new(), |config_a, _| {
+        addr_b = config_a.screens.pop().and_then(|sc| sc.address);
+    })
+    .await;
+
+    let request = IpcRequest::PairConnect {
+        address: addr_b.unwrap(),
+    };
+    let fingerprint = match ipc_request(&pair.shutdown_a, request).await {
+        IpcResponse::PairPending {
+            name, fingerprint, ..
+        } => {
+            assert_eq!(name, "machine-b");
+            fingerprint
+        }
+        other => panic!("unexpected response: {other:?}"),
+    };
+    assert_eq!(pair.status_a.borrow().session_count, 0);
+
+    // B is new to A, so a position is required.
+    let request = IpcRequest::PairConfirm {
+        fingerprint: fingerprint.clone(),
+        accept: true,
+        position: None,
+    };
+    match ipc_request(&pair.shutdown_a, request).await {
+        IpcResponse::Error { code, .. } => {
+            assert_eq!(code, cross_control_types::ErrorCode::InvalidRequest);
+        }
+        other => panic!("unexpected response: {other:?}"),
+    }
+
+    let request = IpcRequest::PairConfirm {
+        fingerprint,
+        accept: true,
+        position: Some(Position::Right),
+    };
+    match ipc_request(&pair.shutdown_a, request).await {
+        IpcResponse::Paired { name, position } => {
+            assert_eq!(name, "machine-b");
+            assert_eq!(position, Position::Right);
+        }
+        other => panic!("unexpected response: {other:?}"),
+    }
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count == 1
+    })
+    .await
+    .expect("pairing session adopted");
+
+    push_a_to_right_edge(&pair).await;
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.controlling.is_some()
+    })
+    .await
+    .expect("A should control B");
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_profile_switch_reconnects_to_new_peer_set() {
+    use cross_control_daemon::config::ProfileConfig;
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+
+    let mut pair = setup_pair_with(MockEmulation::new(), |config_a, _| {
+        config_a.profiles.insert(
+            "travel".to_string(),
+            ProfileConfig {
+                ssids: vec!["cafe".to_string()],
+                ..ProfileConfig::default()
+            },
+        );
+    })
+    .await;
+
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake A");
+
+    // Joining the travel network drops B, which the profile doesn't list.
+    pair.shutdown_a
+        .send(DaemonEvent::NetworkChanged {
+            ssid: Some("cafe".to_string()),
+        })
+        .await
+        .unwrap();
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count == 0
+    })
+    .await
+    .expect("B should be disconnected");
+    match ipc_request(&pair.shutdown_a, IpcRequest::Status).await {
+        IpcResponse::Status(report) => assert_eq!(report.profile.as_deref(), Some("travel")),
+        other => panic!("unexpected response: {other:?}"),
+    }
+
+    let request = IpcRequest::SwitchProfile {
+        name: "default".to_string(),
+    };
+    match ipc_request(&pair.shutdown_a, request).await {
+        IpcResponse::ProfileSwitched { name, screens } => {
+            assert_eq!(name, "default");
+            assert_eq!(screens, 1);
+        }
+        other => panic!("unexpected response: {other:?}"),
+    }
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count == 1
+    })
+    .await
+    .expect("A should redial B");
+
+    let request = IpcRequest::SwitchProfile {
+        name: "docked".to_string(),
+    };
+    match ipc_request(&pair.shutdown_a, request).await {
+        IpcResponse::Error { code, message } => {
+            assert_eq!(code, cross_control_types::ErrorCode::Config);
+            assert!(message.contains("unknown profile"), "{message}");
+        }
+        other => panic!("unexpected response: {other:?}"),
+    }
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_interface_change_redials_lost_peers() {
+    use cross_control_daemon::config::ProfileConfig;
+    use cross_control_daemon::ipc::IpcRequest;
+
+    let mut pair = setup_pair_with(MockEmulation::new(), |_, config_b| {
+        config_b
+            .profiles
+            .insert("alone".to_string(), ProfileConfig::default());
+    })
+    .await;
+
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake A");
+
+    // B drops A and can't dial it back (it has no address for A).
+    for name in ["alone", "default"] {
+        let request = IpcRequest::SwitchProfile {
+            name: name.to_string(),
+        };
+        ipc_request(&pair.shutdown_b, request).await;
+    }
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count == 0
+    })
+    .await
+    .expect("B should close the session");
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(pair.status_a.borrow().session_count, 0);
+
+    pair.shutdown_a
+        .send(DaemonEvent::InterfacesChanged)
+        .await
+        .unwrap();
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count == 1
+    })
+    .await
+    .expect("A should redial B");
+
+    pair.shutdown().await;
+}
+
+/// Hand a daemon a discovery event, as its discovery backend would.
+async fn discover(
+    events: &mpsc::Sender<DaemonEvent>,
+    event: cross_control_discovery::DiscoveryEvent,
+) {
+    events.send(DaemonEvent::Discovery(event)).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_discovery_hints_probe_and_redial_peers() {
+    use cross_control_daemon::config::ProfileConfig;
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+    use cross_control_discovery::{DiscoveryEvent, Peer};
+
+    let mut pair = setup_pair_with(MockEmulation::new(), |_, config_b| {
+        config_b
+            .profiles
+            .insert("alone".to_string(), ProfileConfig::default());
+    })
+    .await;
+
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake A");
+    let IpcResponse::SessionCodes(codes) =
+        ipc_request(&pair.shutdown_a, IpcRequest::SessionCodes).await
+    else {
+        panic!("expected session codes");
+    };
+    let id_b = codes[0].machine_id;
+
+    // B is alive, so it answers the probe and keeps its session.
+    discover(&pair.shutdown_a, DiscoveryEvent::PeerLost(id_b)).await;
+    tokio::time::sleep(Duration::from_secs(4)).await;
+    assert_eq!(pair.status_a.borrow().session_count, 1);
+
+    // B drops A and can't dial back; seeing B on the network redials it.
+    for name in ["alone", "default"] {
+        let request = IpcRequest::SwitchProfile {
+            name: name.to_string(),
+        };
+        ipc_request(&pair.shutdown_b, request).await;
+    }
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count == 0
+    })
+    .await
+    .expect("B should close the session");
+
+    // A peer advertising another group is never dialled.
+    let mut peer = Peer {
+        machine_id: id_b,
+        name: "machine-b".to_string(),
+        address: "127.0.0.1:9".parse().unwrap(),
+        fingerprint: Some(pair.cert_b.fingerprint.clone()),
+        group: Some("other-office".to_string()),
+    };
+    discover(&pair.shutdown_a, DiscoveryEvent::PeerFound(peer.clone())).await;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(pair.status_a.borrow().session_count, 0);
+
+    // Nor is a host claiming B's name without B's pinned certificate.
+    peer.group = None;
+    let impostor = Peer {
+        machine_id: MachineId::new(),
+        fingerprint: None,
+        ..peer.clone()
+    };
+    discover(&pair.shutdown_a, DiscoveryEvent::PeerFound(impostor)).await;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(pair.status_a.borrow().session_count, 0);
+
+    // A copy of A, certificate and all, suspends automatic connections.
+    let clone_id = MachineId::new();
+    let clone = Peer {
+        machine_id: clone_id,
+        name: "machine-a".to_string(),
+        address: "10.255.0.1:24800".parse().unwrap(),
+        fingerprint: pair.config_b.screens[0].fingerprint.clone(),
+        group: None,
+    };
+    discover(&pair.shutdown_a, DiscoveryEvent::PeerFound(clone)).await;
+    match ipc_request(&pair.shutdown_a, IpcRequest::Status).await {
+        IpcResponse::Status(report) => assert_eq!(report.conflicts.len(), 1),
+        other => panic!("unexpected response: {other:?}"),
+    }
+    discover(&pair.shutdown_a, DiscoveryEvent::PeerFound(peer.clone())).await;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(pair.status_a.borrow().session_count, 0);
+
+    discover(&pair.shutdown_a, DiscoveryEvent::PeerLost(clone_id)).await;
+    discover(&pair.shutdown_a, DiscoveryEvent::PeerFound(peer)).await;
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count == 1
+    })
+    .await
+    .expect("A should redial B");
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_viewer_screen_is_never_controlled() {
+    // A treats B as a viewer, so crossing the edge must not send Enter.
+    let mut pair = setup_pair_with(MockEmulation::new(), |config_a, _| {
+        config_a.screens[0].mode = ScreenMode::Viewer;
+    })
+    .await;
+
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+
+    push_a_to_right_edge(&pair).await;
+    assert!(pair.status_a.borrow().controlling.is_none());
+    assert!(pair.status_b.borrow().controlled_by.is_none());
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_viewer_peer_is_denied_enter() {
+    // B treats A as a viewer, so it must refuse A's Enter.
+    let mut pair = setup_pair_with(MockEmulation::new(), |_, config_b| {
+        config_b.screens[0].mode = ScreenMode::Viewer;
+    })
+    .await;
+
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    push_a_to_right_edge(&pair).await;
+    assert!(pair.status_a.borrow().controlling.is_none());
+    assert!(pair.status_b.borrow().controlled_by.is_none());
+    assert!(pair.emulation_b.devices().is_empty());
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_pause_releases_control_and_blocks_enter() {
+    use cross_control_daemon::ipc::IpcRequest;
+
+    let mut pair = setup_pair().await;
+
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    push_a_to_right_edge(&pair).await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.controlled_by.is_some()
+    })
+    .await
+    .expect("B should be controlled");
+
+    // Pausing B hands the cursor back to A.
+    ipc_request(&pair.shutdown_b, IpcRequest::SetPaused(true)).await;
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.controlling.is_none()
+    })
+    .await
+    .expect("A should lose control");
+    let status_b = pair.status_b.borrow().clone();
+    assert!(status_b.paused);
+    assert!(status_b.controlled_by.is_none());
+    assert_eq!(status_b.session_count, 1);
+
+    // While paused, B refuses Enter.
+    push_a_to_right_edge(&pair).await;
+    assert!(pair.status_a.borrow().controlling.is_none());
+
+    // The pause hotkey on B resumes, and A can cross again.
+    for code in [KeyCode::LeftCtrl, KeyCode::LeftShift, KeyCode::Pause] {
+        let event = CapturedEvent {
+            device_id: DeviceId(1),
+            timestamp_us: 1000,
+            event: InputEvent::Key {
+                code,
+                state: ButtonState::Pressed,
+            },
+        };
+        pair.feed_b.send(event).await.unwrap();
+    }
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| !s.paused)
+        .await
+        .expect("B should resume");
+
+    push_a_to_right_edge(&pair).await;
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.controlling.is_some()
+    })
+    .await
+    .expect("A should control B after resume");
+
+    pair.shutdown().await;
+}
+
+/// Keys typed in private input mode never reach the controlled peer, but
+/// keys held when it was turned on are still released there.
+#[tokio::test]
+async fn test_hotkey_sequence_switches_screens() {
+    use cross_control_daemon::config::{CornerAction, HotkeySequence};
+
+    let mut pair = setup_pair_with(MockEmulation::new(), |config_a, _| {
+        config_a.input.sequences = vec![
+            HotkeySequence {
+                steps: vec!["F9".to_string(), "Digit2".to_string()],
+                action: CornerAction::Switch("machine-b".to_string()),
+            },
+            HotkeySequence {
+                steps: vec!["F9".to_string(), "Digit1".to_string()],
+                action: CornerAction::Switch("machine-a".to_string()),
+            },
+        ];
+    })
+    .await;
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake A");
+
+    let tap = |code| {
+        [ButtonState::Pressed, ButtonState::Released].map(|state| CapturedEvent {
+            device_id: DeviceId(1),
+            timestamp_us: 1000,
+            event: InputEvent::Key { code, state },
+        })
+    };
+    for event in tap(KeyCode::F9).into_iter().chain(tap(KeyCode::Digit2)) {
+        pair.feed_a.send(event).await.unwrap();
+    }
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.controlling.is_some()
+    })
+    .await
+    .expect("F9 then 2 should take control of B");
+
+    for event in tap(KeyCode::F9).into_iter().chain(tap(KeyCode::Digit1)) {
+        pair.feed_a.send(event).await.unwrap();
+    }
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.controlling.is_none()
+    })
+    .await
+    .expect("F9 then 1 should bring the cursor home");
+    // None of the sequence's keys reached B.
+    assert!(pair
+        .emulation_b
+        .injected_events()
+        .iter()
+        .all(|e| !matches!(e.event, InputEvent::Key { .. })));
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_swap_hotkey_reverses_control() {
+    let mut pair = setup_pair_with(MockEmulation::new(), |config_a, config_b| {
+        config_a.input.swap_hotkey = vec!["ScrollLock".to_string()];
+        config_b.input.swap_hotkey = vec!["ScrollLock".to_string()];
+    })
+    .await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+    let tap = |code| {
+        [ButtonState::Pressed, ButtonState::Released].map(|state| CapturedEvent {
+            device_id: DeviceId(1),
+            timestamp_us: 1000,
+            event: InputEvent::Key { code, state },
+        })
+    };
+
+    push_a_to_right_edge(&pair).await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.controlled_by.is_some()
+    })
+    .await
+    .expect("A should control B");
+
+    // Pressed on the controller: B takes over.
+    for event in tap(KeyCode::ScrollLock) {
+        pair.feed_a.send(event).await.unwrap();
+    }
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.controlling.is_some() && s.controlled_by.is_none()
+    })
+    .await
+    .expect("B should control A");
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.controlled_by.is_some() && s.controlling.is_none()
+    })
+    .await
+    .expect("A should be controlled by B");
+
+    // Pressed on the machine being controlled: A takes back over.
+    for event in tap(KeyCode::ScrollLock) {
+        pair.feed_a.send(event).await.unwrap();
+    }
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.controlling.is_some() && s.controlled_by.is_none()
+    })
+    .await
+    .expect("A should control B again");
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.controlled_by.is_some() && s.controlling.is_none()
+    })
+    .await
+    .expect("B should be controlled again");
+    assert!(pair.emulation_b.injected_events().iter().all(|e| !matches!(
+        e.event,
+        InputEvent::Key {
+            code: KeyCode::ScrollLock,
+            ..
+        }
+    )));
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_control_request_follows_policy() {
+    use cross_control_daemon::config::ControlRequestPolicy;
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+    use cross_control_types::ErrorCode;
+
+    let request = || IpcRequest::RequestControl {
+        screen: "machine-a".to_string(),
+    };
+
+    // By default a request is refused.
+    let mut pair = setup_pair().await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+    let response = ipc_request(&pair.shutdown_b, request()).await;
+    assert!(
+        matches!(
+            response,
+            IpcResponse::Error {
+                code: ErrorCode::PeerRefused,
+                ..
+            }
+        ),
+        "{response:?}"
+    );
+    pair.shutdown().await;
+
+    // With A accepting pinned peers, B takes over while A controls it.
+    let mut pair = setup_pair_with(MockEmulation::new(), |config_a, _| {
+        config_a.daemon.control_requests = ControlRequestPolicy::Pinned;
+    })
+    .await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+    push_a_to_right_edge(&pair).await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.controlled_by.is_some()
+    })
+    .await
+    .expect("A should control B");
+
+    let response = ipc_request(&pair.shutdown_b, request()).await;
+    assert!(
+        matches!(response, IpcResponse::ControlGranted { ref screen } if screen == "machine-a"),
+        "{response:?}"
+    );
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.controlled_by.is_some() && s.controlling.is_none()
+    })
+    .await
+    .expect("B should control A");
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.controlling.is_some() && s.controlled_by.is_none()
+    })
+    .await
+    .expect("B should no longer be controlled");
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_private_input_keeps_keys_local() {
+    let mut pair = setup_pair().await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    push_a_to_right_edge(&pair).await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.controlled_by.is_some()
+    })
+    .await
+    .expect("B should be controlled");
+
+    let (down, up) = (ButtonState::Pressed, ButtonState::Released);
+    let hotkey = [
+        (KeyCode::LeftCtrl, down),
+        (KeyCode::LeftShift, down),
+        (KeyCode::F12, down),
+    ];
+    let typed = [
+        (KeyCode::F12, up),
+        (KeyCode::LeftCtrl, up),
+        (KeyCode::LeftShift, up),
+        (KeyCode::KeyP, down),
+        (KeyCode::KeyP, up),
+    ];
+    let after = [(KeyCode::KeyB, down)];
+    for (i, (code, state)) in hotkey
+        .iter()
+        .chain(&typed)
+        .chain(&hotkey)
+        .chain(&after)
+        .enumerate()
+    {
+        let event = CapturedEvent {
+            device_id: DeviceId(1),
+            timestamp_us: 1000,
+            event: InputEvent::Key {
+                code: *code,
+                state: *state,
+            },
+        };
+        pair.feed_a.send(event).await.unwrap();
+        if i == 2 {
+            wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+                s.private_input
+            })
+            .await
+            .expect("the hotkey should turn private input on");
+        }
+    }
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        !s.private_input
+    })
+    .await
+    .expect("the hotkey should turn private input off");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let keys: Vec<(KeyCode, ButtonState)> = pair
+        .emulation_b
+        .injected_events()
+        .into_iter()
+        .filter_map(|e| match e.event {
+            InputEvent::Key { code, state } => Some((code, state)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        keys,
+        vec![
+            (KeyCode::LeftCtrl, down),
+            (KeyCode::LeftShift, down),
+            (KeyCode::LeftCtrl, up),
+            (KeyCode::LeftShift, up),
+            (KeyCode::KeyB, down),
+        ]
+    );
+
+    pair.shutdown().await;
+}
+
+/// A peer runs a command only if its `[commands]` entry allows the sender.
+#[cfg(unix)]
+#[tokio::test]
+async fn test_run_command_respects_allowlist() {
+    use cross_control_daemon::config::CommandConfig;
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+    use cross_control_types::ErrorCode;
+
+    let marker = std::env::temp_dir().join(format!("cc-test-{}.ran", MachineId::new()));
+    let mut pair = setup_pair_with(MockEmulation::new(), |_, config_b| {
+        config_b.commands.insert(
+            "mark".to_string(),
+            CommandConfig {
+                run: vec!["touch".to_string(), marker.display().to_string()],
+                peers: vec!["machine-a".to_string()],
+            },
+        );
+        config_b.commands.insert(
+            "nobody".to_string(),
+            CommandConfig {
+                run: vec!["touch".to_string(), marker.display().to_string()],
+                peers: Vec::new(),
+            },
+        );
+    })
+    .await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    for command in ["nobody", "rm"] {
+        let response = ipc_request(
+            &pair.shutdown_a,
+            IpcRequest::RunCommand {
+                peer: "machine-b".to_string(),
+                command: command.to_string(),
+            },
+        )
+        .await;
+        assert!(
+            matches!(
+                response,
+                IpcResponse::Error {
+                    code: ErrorCode::PeerRefused,
+                    ..
+                }
+            ),
+            "{command}: {response:?}"
+        );
+    }
+    assert!(!marker.exists());
+
+    let response = ipc_request(
+        &pair.shutdown_a,
+        IpcRequest::RunCommand {
+            peer: "machine-b".to_string(),
+            command: "mark".to_string(),
+        },
+    )
+    .await;
+    assert!(
+        matches!(response, IpcResponse::CommandStarted { ref command, .. } if command == "mark"),
+        "{response:?}"
+    );
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while !marker.exists() && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(marker.exists(), "the command should have run on B");
+    let _ = std::fs::remove_file(&marker);
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_run_everywhere_reports_each_machine() {
+    use cross_control_daemon::config::CommandConfig;
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+
+    let id = MachineId::new();
+    let marker_a = std::env::temp_dir().join(format!("cc-test-{id}.a"));
+    let marker_b = std::env::temp_dir().join(format!("cc-test-{id}.b"));
+    let mut pair = setup_pair_with(MockEmulation::new(), |config_a, config_b| {
+        config_a.commands.insert(
+            "lock-screen".to_string(),
+            CommandConfig {
+                run: vec!["touch".to_string(), marker_a.display().to_string()],
+                peers: Vec::new(),
+            },
+        );
+        config_b.commands.insert(
+            "lock-screen".to_string(),
+            CommandConfig {
+                run: vec!["touch".to_string(), marker_b.display().to_string()],
+                peers: vec!["machine-a".to_string()],
+            },
+        );
+    })
+    .await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let response = ipc_request(
+        &pair.shutdown_a,
+        IpcRequest::RunEverywhere {
+            command: "lock-screen".to_string(),
+        },
+    )
+    .await;
+    let IpcResponse::CommandsRun(outcomes) = response else {
+        panic!("unexpected response: {response:?}");
+    };
+    let outcomes: Vec<_> = outcomes.into_iter().map(|o| (o.name, o.error)).collect();
+    assert_eq!(
+        outcomes,
+        [
+            ("machine-b".to_string(), None),
+            ("machine-a".to_string(), None)
+        ]
+    );
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while !(marker_a.exists() && marker_b.exists()) && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(marker_a.exists(), "the command should have run on A");
+    assert!(marker_b.exists(), "the command should have run on B");
+    let _ = std::fs::remove_file(&marker_a);
+    let _ = std::fs::remove_file(&marker_b);
+
+    // B hasn't defined `suspend`, and neither has A.
+    let response = ipc_request(
+        &pair.shutdown_a,
+        IpcRequest::RunEverywhere {
+            command: "suspend".to_string(),
+        },
+    )
+    .await;
+    let IpcResponse::CommandsRun(outcomes) = response else {
+        panic!("unexpected response: {response:?}");
+    };
+    assert_eq!(outcomes.len(), 2);
+    assert!(outcomes.iter().all(|o| o.error.is_some()), "{outcomes:?}");
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_local_input_suppressed_while_controlled() {
+    let mut pair = setup_pair_with(MockEmulation::new(), |_, config_b| {
+        config_b.input.suppress_local_while_controlled = true;
+    })
+    .await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(!pair.capture_b.is_grabbed());
+
+    push_a_to_right_edge(&pair).await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.controlled_by.is_some()
+    })
+    .await
+    .expect("B should be controlled");
+    assert!(pair.capture_b.is_grabbed(), "B grabs its own devices");
+
+    // B's local pointer goes nowhere, even towards A.
+    let before = pair.status_b.borrow().clone();
+    pair.feed_b
+        .send(CapturedEvent {
+            device_id: DeviceId(2),
+            timestamp_us: 1000,
+            event: InputEvent::MouseMove { dx: -5000, dy: 0 },
+        })
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let after = pair.status_b.borrow().clone();
+    assert_eq!(
+        (after.cursor_x, after.controlling),
+        (before.cursor_x, before.controlling)
+    );
+
+    // The release hotkey on B hands the cursor back to A.
+    for code in [KeyCode::LeftCtrl, KeyCode::LeftShift, KeyCode::Escape] {
+        pair.feed_b
+            .send(CapturedEvent {
+                device_id: DeviceId(1),
+                timestamp_us: 2000,
+                event: InputEvent::Key {
+                    code,
+                    state: ButtonState::Pressed,
+                },
+            })
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.controlling.is_none()
+    })
+    .await
+    .expect("A should get its cursor back");
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.controlled_by.is_none()
+    })
+    .await
+    .expect("B should return to idle");
+    assert!(!pair.capture_b.is_grabbed(), "B releases its devices");
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_failed_grab_is_rolled_back_and_retried() {
+    use cross_control_daemon::ipc::{InputGrab, IpcRequest, IpcResponse};
+
+    let mut pair = setup_pair().await;
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake A");
+    pair.capture_a.fail_grabs(2);
+
+    push_a_to_right_edge(&pair).await;
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.controlling.is_some()
+    })
+    .await
+    .expect("A should control B");
+    assert_eq!(pair.status_a.borrow().input_grab, InputGrab::Retrying);
+    assert!(
+        pair.capture_a.was_released(),
+        "a failed grab must release everything"
+    );
+
+    // Two failures back off 250ms, then 500ms; the third attempt holds.
+    let status = wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.input_grab == InputGrab::Grabbed
+    })
+    .await
+    .expect("A should grab its devices on retry");
+    assert!(status.controlling.is_some());
+    assert!(pair.capture_a.is_grabbed());
+
+    let response = ipc_request(&pair.shutdown_a, IpcRequest::Status).await;
+    let IpcResponse::Status(report) = response else {
+        panic!("unexpected response: {response:?}");
+    };
+    assert_eq!(report.input_grab, InputGrab::Grabbed);
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_local_input_policy_returns_cursor() {
+    use cross_control_daemon::config::LocalInputPolicy;
+
+    for policy in [LocalInputPolicy::Release, LocalInputPolicy::PauseRemote] {
+        let mut pair = setup_pair_with(MockEmulation::new(), |_, config_b| {
+            config_b.controlled.local_input_policy = policy;
+        })
+        .await;
+        wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+            s.session_count >= 1
+        })
+        .await
+        .expect("handshake B");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        push_a_to_right_edge(&pair).await;
+        wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+            s.controlled_by.is_some()
+        })
+        .await
+        .expect("B should be controlled");
+
+        // Someone at B presses a key.
+        pair.feed_b
+            .send(CapturedEvent {
+                device_id: DeviceId(1),
+                timestamp_us: 2000,
+                event: InputEvent::Key {
+                    code: KeyCode::KeyA,
+                    state: ButtonState::Pressed,
+                },
+            })
+            .await
+            .unwrap();
+        wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+            s.controlling.is_none()
+        })
+        .await
+        .expect("A should get its cursor back");
+        let status_b = wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+            s.controlled_by.is_none()
+        })
+        .await
+        .expect("B should return to idle");
+        assert_eq!(
+            status_b.paused,
+            policy == LocalInputPolicy::PauseRemote,
+            "{policy:?}"
+        );
+
+        pair.shutdown().await;
+    }
+}
+
+/// Under the `hold` policy, someone using B's own mouse holds back A's
+/// input until they have been idle for `local_input_secs`, and A is told.
+#[tokio::test]
+async fn test_local_input_hold_pauses_remote_input() {
+    use cross_control_daemon::config::LocalInputPolicy;
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+
+    let mut pair = setup_pair_with(MockEmulation::new(), |_, config_b| {
+        config_b.controlled.local_input_policy = LocalInputPolicy::Hold;
+        config_b.controlled.local_input_secs = 1;
+    })
+    .await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    push_a_to_right_edge(&pair).await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.controlled_by.is_some()
+    })
+    .await
+    .expect("B should be controlled");
+
+    // Someone at B moves B's own mouse.
+    pair.feed_b
+        .send(CapturedEvent {
+            device_id: DeviceId(2),
+            timestamp_us: 2000,
+            event: InputEvent::MouseMove { dx: 3, dy: 0 },
+        })
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let key = |state| CapturedEvent {
+        device_id: DeviceId(1),
+        timestamp_us: 3000,
+        event: InputEvent::Key {
+            code: KeyCode::KeyA,
+            state,
+        },
+    };
+    let key_events = |pair: &TestPair| {
+        pair.emulation_b
+            .injected_events()
+            .into_iter()
+            .filter(|e| matches!(e.event, InputEvent::Key { .. }))
+            .map(|e| e.event)
+            .collect::<Vec<_>>()
+    };
+    pair.feed_a.send(key(ButtonState::Pressed)).await.unwrap();
+    pair.feed_a.send(key(ButtonState::Released)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    // Only the release got through.
+    assert_eq!(
+        key_events(&pair),
+        vec![InputEvent::Key {
+            code: KeyCode::KeyA,
+            state: ButtonState::Released,
+        }]
+    );
+    let IpcResponse::Status(status_a) = ipc_request(&pair.shutdown_a, IpcRequest::Status).await
+    else {
+        panic!("expected status");
+    };
+    assert!(status_a.remote_user_active);
+    let IpcResponse::Status(status_b) = ipc_request(&pair.shutdown_b, IpcRequest::Status).await
+    else {
+        panic!("expected status");
+    };
+    assert!(status_b.local_user_active);
+    assert_eq!(status_b.counters.local_input_conflicts, 1);
+
+    // Once B's user is idle, A has B to itself again.
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    let IpcResponse::Status(status_a) = ipc_request(&pair.shutdown_a, IpcRequest::Status).await
+    else {
+        panic!("expected status");
+    };
+    assert!(!status_a.remote_user_active);
+    pair.feed_a.send(key(ButtonState::Pressed)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(key_events(&pair).len(), 2);
+    assert!(pair.status_a.borrow().controlling.is_some());
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_controller_sees_remote_lock_screen() {
+    let mut pair = setup_pair().await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    push_a_to_right_edge(&pair).await;
+    let status_a = wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.remote_focus.is_some()
+    })
+    .await
+    .expect("B should hint where input lands");
+    assert_eq!(status_a.remote_focus.unwrap().screen, ScreenState::Active);
+
+    pair.shutdown_b
+        .send(DaemonEvent::ScreenStateChanged(ScreenState::Locked))
+        .await
+        .unwrap();
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.remote_focus
+            .is_some_and(|f| f.screen == ScreenState::Locked)
+    })
+    .await
+    .expect("A should learn B is locked");
+
+    pair.shutdown().await;
+}
+
+/// The grab that suppresses B's own input still lets the daemon see it, so
+/// the pause hotkey works too, and pausing hands the cursor back.
+#[tokio::test]
+async fn test_pause_hotkey_works_while_local_input_suppressed() {
+    let mut pair = setup_pair_with(MockEmulation::new(), |_, config_b| {
+        config_b.input.suppress_local_while_controlled = true;
+    })
+    .await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+
+    push_a_to_right_edge(&pair).await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.controlled_by.is_some()
+    })
+    .await
+    .expect("B should be controlled");
+    assert!(pair.capture_b.is_grabbed());
+
+    for code in [KeyCode::LeftCtrl, KeyCode::LeftShift, KeyCode::Pause] {
+        pair.feed_b
+            .send(CapturedEvent {
+                device_id: DeviceId(1),
+                timestamp_us: 2000,
+                event: InputEvent::Key {
+                    code,
+                    state: ButtonState::Pressed,
+                },
+            })
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.paused && s.controlled_by.is_none()
+    })
+    .await
+    .expect("B should pause and give the cursor back");
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.controlling.is_none()
+    })
+    .await
+    .expect("A should get its cursor back");
+    assert!(!pair.capture_b.is_grabbed(), "B releases its devices");
+
+    pair.shutdown().await;
+}
+
+/// Net-zero pointer nudges injected on B.
+fn wake_nudges(handle: &MockEmulationHandle) -> usize {
+    handle
+        .injected_events()
+        .iter()
+        .filter(|e| matches!(e.event, InputEvent::MouseMove { dx: 1, dy: 0 }))
+        .count()
+}
+
+#[tokio::test]
+async fn test_enter_wakes_remote_display() {
+    let mut pair = setup_pair().await;
+
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    push_a_to_right_edge(&pair).await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.controlled_by.is_some()
+    })
+    .await
+    .expect("B should be controlled");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    assert_eq!(wake_nudges(&pair.emulation_b), 1);
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_display_wake_can_be_disabled_per_screen() {
+    let mut pair = setup_pair_with(MockEmulation::new(), |config_a, _| {
+        config_a.screens[0].wake_on_enter = false;
+    })
+    .await;
+
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    push_a_to_right_edge(&pair).await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.controlled_by.is_some()
+    })
+    .await
+    .expect("B should be controlled");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    assert_eq!(wake_nudges(&pair.emulation_b), 0);
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_unshared_device_is_not_announced_or_forwarded() {
+    let mut pair = setup_pair_with(MockEmulation::new(), |config_a, _| {
+        config_a.screens[0].share_devices = Some(vec!["*keyboard".to_string()]);
+    })
+    .await;
+
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let names: Vec<String> = pair
+        .emulation_b
+        .devices()
+        .into_values()
+        .map(|d| d.name)
+        .collect();
+    assert_eq!(names, ["Test Keyboard"]);
+
+    push_a_to_right_edge(&pair).await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.controlled_by.is_some()
+    })
+    .await
+    .expect("B should be controlled");
+
+    for event in [
+        InputEvent::MouseMove { dx: 5, dy: 5 },
+        InputEvent::Key {
+            code: KeyCode::KeyA,
+            state: ButtonState::Pressed,
+        },
+    ] {
+        let device_id = match event {
+            InputEvent::Key { .. } => DeviceId(1),
+            _ => DeviceId(2),
+        };
+        let captured = CapturedEvent {
+            device_id,
+            timestamp_us: 2000,
+            event,
+        };
+        pair.feed_a.send(captured).await.unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let injected = pair.emulation_b.injected_events();
+    assert!(injected
+        .iter()
+        .any(|e| matches!(e.event, InputEvent::Key { .. })));
+    assert!(!injected
+        .iter()
+        .any(|e| matches!(e.event, InputEvent::MouseMove { .. })));
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_sleeping_peer_is_unavailable_until_resumed() {
+    let mut pair = setup_pair().await;
+
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    push_a_to_right_edge(&pair).await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.controlled_by.is_some()
+    })
+    .await
+    .expect("B should be controlled");
+
+    // B is about to sleep: control returns to A before the ack.
+    let (done_tx, done_rx) = oneshot::channel();
+    pair.shutdown_b
+        .send(DaemonEvent::PrepareForSleep { done: done_tx })
+        .await
+        .unwrap();
+    tokio::time::timeout(Duration::from_secs(5), done_rx)
+        .await
+        .expect("sleep should be acknowledged")
+        .unwrap();
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.controlling.is_none()
+    })
+    .await
+    .expect("A should lose control");
+    assert_eq!(pair.status_a.borrow().session_count, 1);
+
+    // A no longer crosses into the sleeping peer.
+    push_a_to_right_edge(&pair).await;
+    assert!(pair.status_a.borrow().controlling.is_none());
+    assert!(pair.status_b.borrow().controlled_by.is_none());
+
+    pair.shutdown_b.send(DaemonEvent::Resumed).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    push_a_to_right_edge(&pair).await;
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.controlling.is_some()
+    })
+    .await
+    .expect("A should control B after it resumes");
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_motion_to_battery_peer_is_coalesced() {
+    let mut pair = setup_pair().await;
+
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+
+    // B reports running on battery; A learns it over the control stream.
+    pair.shutdown_b
+        .send(DaemonEvent::PowerChanged(PowerState {
+            on_battery: true,
+            battery_percent: Some(50),
+        }))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    push_a_to_right_edge(&pair).await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.controlled_by.is_some()
+    })
+    .await
+    .expect("B should be controlled");
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    for _ in 0..10 {
+        let event = CapturedEvent {
+            device_id: DeviceId(2),
+            timestamp_us: 2000,
+            event: InputEvent::MouseMove { dx: 0, dy: 1 },
+        };
+        pair.feed_a.send(event).await.unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let moves: Vec<i32> = pair
+        .emulation_b
+        .injected_events()
+        .into_iter()
+        .filter_map(|e| match e.event {
+            InputEvent::MouseMove { dy, .. } => Some(dy),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(moves.iter().sum::<i32>(), 10);
+    assert!(moves.len() < 10, "motion was not coalesced: {moves:?}");
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_clipboard_syncs_between_idle_peers_with_all_peers_scope() {
+    let mut pair = setup_pair_with(MockEmulation::new(), |config_a, config_b| {
+        config_a.clipboard.scope = ClipboardScope::AllPeers;
+        config_b.clipboard.scope = ClipboardScope::AllPeers;
+    })
+    .await;
+
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake A");
+
+    pair.clipboard_a.copy(ClipboardContent::text("copied on A"));
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(
+        pair.clipboard_b.contents(),
+        Some(ClipboardContent::text("copied on A"))
+    );
+
+    pair.clipboard_b.copy(ClipboardContent::text("copied on B"));
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(
+        pair.clipboard_a.contents(),
+        Some(ClipboardContent::text("copied on B"))
+    );
+
+    pair.shutdown().await;
+}
+
+/// Clipboard contents larger than either end's control message limit stay
+/// local in both directions, and the session survives.
+#[tokio::test]
+async fn test_clipboard_respects_negotiated_message_limit() {
+    let mut pair = setup_pair_with(MockEmulation::new(), |config_a, config_b| {
+        config_a.clipboard.scope = ClipboardScope::AllPeers;
+        config_b.clipboard.scope = ClipboardScope::AllPeers;
+        config_b.limits.control_message_size = 4096;
+    })
+    .await;
+
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake A");
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let large = "x".repeat(8192);
+    pair.clipboard_a.copy(ClipboardContent::text(&large));
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(pair.clipboard_b.contents(), None);
+
+    pair.clipboard_b
+        .copy(ClipboardContent::text(&"y".repeat(8192)));
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(
+        pair.clipboard_a.contents(),
+        Some(ClipboardContent::text(&large))
+    );
+
+    pair.clipboard_a
+        .copy(ClipboardContent::text("small enough"));
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(
+        pair.clipboard_b.contents(),
+        Some(ClipboardContent::text("small enough"))
+    );
+    assert_eq!(pair.status_a.borrow().session_count, 1);
+
+    pair.shutdown().await;
+}
+
+/// A screen entry with `clipboard = false` keeps that peer out of
+/// clipboard sync even when every peer shares it.
+#[tokio::test]
+async fn test_clipboard_off_for_screen() {
+    let mut pair = setup_pair_with(MockEmulation::new(), |config_a, config_b| {
+        config_a.clipboard.scope = ClipboardScope::AllPeers;
+        config_a.screens[0].clipboard = false;
+        config_b.clipboard.scope = ClipboardScope::AllPeers;
+    })
+    .await;
+
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake A");
+
+    pair.clipboard_a.copy(ClipboardContent::text("copied on A"));
+    pair.clipboard_b.copy(ClipboardContent::text("copied on B"));
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(
+        pair.clipboard_a.contents(),
+        Some(ClipboardContent::text("copied on A"))
+    );
+    assert_eq!(
+        pair.clipboard_b.contents(),
+        Some(ClipboardContent::text("copied on B"))
+    );
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_clipboard_not_synced_between_idle_peers_by_default() {
+    let mut pair = setup_pair().await;
+
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+
+    pair.clipboard_a.copy(ClipboardContent::text("copied on A"));
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(pair.clipboard_b.contents(), None);
+
+    pair.shutdown().await;
+}
+
+/// A fresh `0700` directory for IPC sockets.
+#[cfg(unix)]
+fn private_socket_dir() -> std::path::PathBuf {
+    use std::os::unix::fs::DirBuilderExt;
+
+    let dir = std::env::temp_dir().join(format!("cc-ipc-{}", MachineId::new()));
+    std::fs::DirBuilder::new().mode(0o700).create(&dir).unwrap();
+    dir
+}
+
+/// The IPC socket is `0600` in a private directory, and isn't bound at all
+/// in a directory other users can reach.
+#[cfg(unix)]
+#[tokio::test]
+async fn test_ipc_socket_is_private() {
+    use std::os::unix::fs::PermissionsExt;
+
+    use cross_control_daemon::ipc::{self, IpcRequest, IpcResponse};
+
+    let (events, mut requests) = mpsc::channel(4);
+    let dir = private_socket_dir();
+    let socket = dir.join("cc.sock");
+    let server = tokio::spawn({
+        let (path, tx) = (socket.clone(), events.clone());
+        async move { ipc::serve(&path, tx).await }
+    });
+    tokio::spawn(async move {
+        while let Some(event) = requests.recv().await {
+            if let DaemonEvent::Ipc { reply, .. } = event {
+                let _ = reply.send(IpcResponse::Paused(true));
+            }
+        }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    let response = ipc::request(&socket, &IpcRequest::Status).await.unwrap();
+    assert!(
+        matches!(response, IpcResponse::Paused(true)),
+        "{response:?}"
+    );
+    server.abort();
+
+    std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let shared = dir.join("shared.sock");
+    let err = ipc::serve(&shared, events).await.unwrap_err();
+    assert!(err.to_string().contains("mode 0700"), "{err}");
+    assert!(!shared.exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_session_codes_match_over_ipc() {
+    use cross_control_daemon::ipc::{self, IpcRequest, IpcResponse};
+
+    let mut pair = setup_pair().await;
+
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake A");
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+
+    let dir = private_socket_dir();
+    let socket_a = dir.join(format!("cc-test-{}.sock", MachineId::new()));
+    let socket_b = dir.join(format!("cc-test-{}.sock", MachineId::new()));
+    let server_a = tokio::spawn({
+        let (path, tx) = (socket_a.clone(), pair.shutdown_a.clone());
+        async move { ipc::serve(&path, tx).await }
+    });
+    let server_b = tokio::spawn({
+        let (path, tx) = (socket_b.clone(), pair.shutdown_b.clone());
+        async move { ipc::serve(&path, tx).await }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let codes = |response| match response {
+        IpcResponse::SessionCodes(codes) => codes,
+        other => panic!("unexpected response: {other:?}"),
+    };
+    let codes_a = codes(
+        ipc::request(&socket_a, &IpcRequest::SessionCodes)
+            .await
+            .unwrap(),
+    );
+    let codes_b = codes(
+        ipc::request(&socket_b, &IpcRequest::SessionCodes)
+            .await
+            .unwrap(),
+    );
+
+    assert_eq!(codes_a.len(), 1);
+    assert_eq!(codes_b.len(), 1);
+    assert_eq!(codes_a[0].name, "machine-b");
+    assert_eq!(codes_b[0].name, "machine-a");
+    assert_eq!(codes_a[0].code, codes_b[0].code);
+    assert_eq!(codes_a[0].session, codes_b[0].session);
+    assert_ne!(codes_a[0].session, "unknown");
+
+    server_a.abort();
+    server_b.abort();
+    let _ = std::fs::remove_dir_all(&dir);
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_ban_peer_closes_session() {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+
+    let mut pair = setup_pair().await;
+
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake A");
+
+    let request = IpcRequest::Ban {
+        peer: "machine-b".to_string(),
+    };
+    match ipc_request(&pair.shutdown_a, request).await {
+        IpcResponse::Banned { name, fingerprint } => {
+            assert_eq!(name, "machine-b");
+            assert!(fingerprint.starts_with("SHA256:"));
+        }
+        other => panic!("unexpected response: {other:?}"),
+    }
+
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count == 0
+    })
+    .await
+    .expect("A should drop the banned session");
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count == 0
+    })
+    .await
+    .expect("B should see the session close");
+
+    pair.shutdown().await;
+}
+
+/// A ban is written to the daemon's own denylist and config file, so it
+/// survives a restart, and adjacency naming the banned screen goes too.
+#[tokio::test]
+async fn test_ban_peer_persists_denylist_and_layout() {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+    use cross_control_daemon::setup;
+
+    let dir = std::env::temp_dir().join(format!("cc-ban-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let config_path = dir.join("config.toml");
+
+    let a = Endpoint::bind();
+    let config_a = Config {
+        screen_adjacency: vec![
+            ScreenAdjacency {
+                screen: "machine-b".to_string(),
+                neighbor: "machine-c".to_string(),
+                position: Position::Right,
+            },
+            ScreenAdjacency {
+                screen: "machine-c".to_string(),
+                neighbor: "machine-d".to_string(),
+                position: Position::Right,
+            },
+        ],
+        ..config(
+            "machine-a",
+            vec![ScreenConfig {
+                fingerprint: Some("SHA256:banned".to_string()),
+                ..screen("machine-b", Position::Right, None)
+            }],
+        )
+    };
+    setup::save_config(config_path.to_str(), &config_a).unwrap();
+
+    let mut bundle = DaemonBundle::new(config_a, a.transport);
+    bundle.daemon.set_config_path(config_path.clone());
+    bundle.daemon.set_denylist_dir(dir.clone());
+    let (daemon_a, handle_a) = bundle.spawn();
+
+    let request = IpcRequest::Ban {
+        peer: "machine-b".to_string(),
+    };
+    let response = ipc_request(&daemon_a.events, request).await;
+    assert!(
+        matches!(&response, IpcResponse::Banned { fingerprint, .. } if fingerprint == "SHA256:banned"),
+        "{response:?}"
+    );
+
+    assert_eq!(setup::load_denylist(&dir).unwrap(), vec!["SHA256:banned"]);
+    let on_disk = setup::load_config(config_path.to_str()).unwrap();
+    assert!(on_disk.screens.is_empty());
+    let adjacency: Vec<(&str, &str)> = on_disk
+        .screen_adjacency
+        .iter()
+        .map(|adj| (adj.screen.as_str(), adj.neighbor.as_str()))
+        .collect();
+    assert_eq!(adjacency, vec![("machine-c", "machine-d")]);
+
+    daemon_a.shutdown().await;
+    let _ = handle_a.await;
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// With a profile active, a ban is saved to that profile's section and
+/// also leaves the top-level layout, which is otherwise kept as it was.
+#[tokio::test]
+async fn test_ban_peer_with_profile_active_updates_every_layout() {
+    use cross_control_daemon::config::ProfileConfig;
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+    use cross_control_daemon::setup;
+
+    let dir = std::env::temp_dir().join(format!("cc-ban-profile-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let config_path = dir.join("config.toml");
+
+    let banned = ScreenConfig {
+        fingerprint: Some("SHA256:banned".to_string()),
+        ..screen("machine-b", Position::Right, None)
+    };
+    let a = Endpoint::bind();
+    let mut config_a = config(
+        "machine-a",
+        vec![banned.clone(), screen("machine-c", Position::Left, None)],
+    );
+    config_a.profiles.insert(
+        "travel".to_string(),
+        ProfileConfig {
+            screens: vec![banned, screen("machine-d", Position::Above, None)],
+            ..ProfileConfig::default()
+        },
+    );
+    config_a.daemon.profile = Some("travel".to_string());
+    setup::save_config(config_path.to_str(), &config_a).unwrap();
+
+    let mut bundle = DaemonBundle::new(config_a, a.transport);
+    bundle.daemon.set_config_path(config_path.clone());
+    bundle.daemon.set_denylist_dir(dir.clone());
+    let (daemon_a, handle_a) = bundle.spawn();
+
+    let request = IpcRequest::Ban {
+        peer: "machine-b".to_string(),
+    };
+    let response = ipc_request(&daemon_a.events, request).await;
+    assert!(
+        matches!(&response, IpcResponse::Banned { .. }),
+        "{response:?}"
+    );
+
+    let names = |screens: &[ScreenConfig]| -> Vec<String> {
+        screens.iter().map(|sc| sc.name.clone()).collect()
+    };
+    let on_disk = setup::load_config(config_path.to_str()).unwrap();
+    assert_eq!(names(&on_disk.screens), vec!["machine-c"]);
+    assert_eq!(
+        names(&on_disk.profiles["travel"].screens),
+        vec!["machine-d"]
+    );
+
+    // Switching back doesn't restore the banned screen either.
+    let request = IpcRequest::SwitchProfile {
+        name: "default".to_string(),
+    };
+    match ipc_request(&daemon_a.events, request).await {
+        IpcResponse::ProfileSwitched { screens, .. } => assert_eq!(screens, 1),
+        other => panic!("unexpected response: {other:?}"),
+    }
+
+    daemon_a.shutdown().await;
+    let _ = handle_a.await;
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// A bare transport standing in for a peer that speaks another protocol
+/// version. A's screen for B is pointed at it.
+fn bind_fake_peer(config_a: &mut Config) -> cross_control_protocol::QuicTransport {
+    let fake = Endpoint::bind();
+    config_a.screens[0].address = Some(fake.addr.to_string());
+    config_a.screens[0].fingerprint = None;
+    fake.transport
+}
+
+#[tokio::test]
+async fn test_version_mismatch_is_reported_to_peer() {
+    use cross_control_types::{
+        ControlMessage, ErrorCode, ProtocolVersion, ScreenGeometry, PROTOCOL_VERSION,
+    };
+
+    let mut fake = None;
+    let pair = setup_pair_with(MockEmulation::new(), |config_a, _| {
+        fake = Some(bind_fake_peer(config_a));
+    })
+    .await;
+    let fake = fake.unwrap();
+
+    let conn = tokio::time::timeout(Duration::from_secs(5), fake.accept())
+        .await
+        .expect("A should dial the fake peer")
+        .unwrap();
+    let (mut tx, mut rx) = conn.accept_control_stream().await.unwrap();
+    let hello: ControlMessage = rx.recv_tagged().await.unwrap().unwrap();
+    assert!(matches!(hello, ControlMessage::Hello { .. }));
+    tx.send_tagged(&ControlMessage::Welcome {
+        version: ProtocolVersion { major: 9, minor: 0 },
+        machine_id: MachineId::new(),
+        name: "machine-b".to_string(),
+        screen: ScreenGeometry::new(1920, 1080),
+    })
+    .await
+    .unwrap();
+
+    let reply: ControlMessage = tokio::time::timeout(Duration::from_secs(5), rx.recv_tagged())
+        .await
+        .expect("A should explain the rejection")
+        .unwrap()
+        .unwrap();
+    match reply {
+        ControlMessage::Error { code, detail } => {
+            assert_eq!(code, ErrorCode::VersionMismatch);
+            assert!(detail.contains(&PROTOCOL_VERSION.to_string()), "{detail}");
+            assert!(detail.contains("9.0"), "{detail}");
+        }
+        other => panic!("unexpected message: {other:?}"),
+    }
+
+    pair.shutdown().await;
+}
+
+/// Handshake with A as "machine-b" speaking `version`, returning the
+/// control stream.
+async fn fake_peer_handshake(
+    fake: &cross_control_protocol::QuicTransport,
+    version: cross_control_types::ProtocolVersion,
+) -> (
+    cross_control_protocol::PeerConnection,
+    cross_control_protocol::MessageSender,
+    cross_control_protocol::MessageReceiver,
+) {
+    use cross_control_types::{ControlMessage, ScreenGeometry};
+
+    let conn = tokio::time::timeout(Duration::from_secs(5), fake.accept())
+        .await
+        .expect("A should dial the fake peer")
+        .unwrap();
+    let (mut tx, mut rx) = conn.accept_control_stream().await.unwrap();
+    let _hello: ControlMessage = rx.recv_tagged().await.unwrap().unwrap();
+    tx.send_tagged(&ControlMessage::Welcome {
+        version,
+        machine_id: MachineId::new(),
+        name: "machine-b".to_string(),
+        screen: ScreenGeometry::new(1920, 1080),
+    })
+    .await
+    .unwrap();
+    (conn, tx, rx)
+}
+
+/// A peer on an older minor version shows up in `status` with the features
+/// it lacks, and requests needing them are refused without asking it.
+#[tokio::test]
+async fn test_older_peer_version_is_reported_in_status() {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+    use cross_control_types::{ErrorCode, ProtocolVersion};
+
+    let mut fake = None;
+    let mut pair = setup_pair_with(MockEmulation::new(), |config_a, _| {
+        fake = Some(bind_fake_peer(config_a));
+    })
+    .await;
+    let fake = fake.unwrap();
+    let older = ProtocolVersion { major: 0, minor: 5 };
+    let _peer = fake_peer_handshake(&fake, older).await;
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("A should accept the older peer");
+
+    let IpcResponse::Status(status) = ipc_request(&pair.shutdown_a, IpcRequest::Status).await
+    else {
+        panic!("expected status");
+    };
+    assert_eq!(status.version_skew.len(), 1);
+    let skew = &status.version_skew[0];
+    assert_eq!(skew.peer, "machine-b");
+    assert_eq!(skew.protocol, "0.5");
+    assert_eq!(skew.version, None);
+    assert!(
+        skew.disabled.iter().any(|f| f == "control requests"),
+        "{skew:?}"
+    );
+    assert!(
+        !skew.disabled.iter().any(|f| f == "focus hints"),
+        "{skew:?}"
+    );
+
+    let response = ipc_request(
+        &pair.shutdown_a,
+        IpcRequest::RequestControl {
+            screen: "machine-b".to_string(),
+        },
+    )
+    .await;
+    match response {
+        IpcResponse::Error { code, message } => {
+            assert_eq!(code, ErrorCode::VersionMismatch);
+            assert!(message.contains("0.5"), "{message}");
+        }
+        other => panic!("unexpected response: {other:?}"),
+    }
+
+    pair.shutdown().await;
+}
+
+/// A peer on the same protocol but another release is listed by the
+/// version it reports in `BuildInfo`, with nothing disabled.
+#[tokio::test]
+async fn test_peer_build_version_is_reported_in_status() {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+    use cross_control_types::{ControlMessage, PROTOCOL_VERSION};
+
+    let mut fake = None;
+    let mut pair = setup_pair_with(MockEmulation::new(), |config_a, _| {
+        fake = Some(bind_fake_peer(config_a));
+    })
+    .await;
+    let fake = fake.unwrap();
+    let (_conn, mut tx, mut rx) = fake_peer_handshake(&fake, PROTOCOL_VERSION).await;
+    tx.send_tagged(&ControlMessage::BuildInfo {
+        version: "0.0.1".to_string(),
+        commit: "abc1234".to_string(),
+    })
+    .await
+    .unwrap();
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("A should accept the peer");
+
+    // A announces its own build after the handshake.
+    let version = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let msg: ControlMessage = rx.recv_tagged().await.unwrap().unwrap();
+            if let ControlMessage::BuildInfo { version, .. } = msg {
+                return version;
+            }
+        }
+    })
+    .await
+    .expect("A should send BuildInfo");
+    assert_eq!(version, cross_control_daemon::VERSION);
+
+    let skew = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let IpcResponse::Status(status) =
+                ipc_request(&pair.shutdown_a, IpcRequest::Status).await
+            {
+                if !status.version_skew.is_empty() {
+                    return status.version_skew;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("A should record the peer's version");
+    assert_eq!(skew[0].version.as_deref(), Some("0.0.1"));
+    assert!(skew[0].disabled.is_empty());
+
+    pair.shutdown().await;
+}
+
+/// A peer announcing more devices than `limits.peer_devices`, or malformed
+/// ones, gets virtual devices only for the valid ones within the limit and
+/// a single error back.
+#[tokio::test]
+async fn test_device_announce_storm_is_capped() {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+    use cross_control_types::{
+        ControlMessage, DeviceCapability, DeviceInfo, ErrorCode, PROTOCOL_VERSION,
+    };
+
+    let mut fake = None;
+    let mut pair = setup_pair_with(MockEmulation::new(), |config_a, _| {
+        fake = Some(bind_fake_peer(config_a));
+        config_a.limits.peer_devices = 2;
+    })
+    .await;
+    let fake = fake.unwrap();
+    let (_conn, mut tx, mut rx) = fake_peer_handshake(&fake, PROTOCOL_VERSION).await;
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("A should accept the peer");
+
+    let mouse = |id| DeviceInfo {
+        id: DeviceId(id),
+        name: format!("Mouse {id}"),
+        capabilities: vec![DeviceCapability::RelativeMouse],
+        keys: Vec::new(),
+    };
+    let malformed = DeviceInfo {
+        capabilities: Vec::new(),
+        ..mouse(99)
+    };
+    for device in [mouse(1), malformed, mouse(2), mouse(1), mouse(3), mouse(4)] {
+        tx.send_tagged(&ControlMessage::DeviceAnnounce(device))
+            .await
+            .unwrap();
+    }
+
+    let (code, errors) = tokio::time::timeout(Duration::from_secs(5), async {
+        let mut first = None;
+        let mut errors = 0;
+        loop {
+            let msg = tokio::time::timeout(Duration::from_millis(500), rx.recv_tagged());
+            match msg.await {
+                Ok(Ok(Some(ControlMessage::Error { code, .. }))) => {
+                    first.get_or_insert(code);
+                    errors += 1;
+                }
+                Ok(Ok(Some(_))) => {}
+                _ => return (first, errors),
+            }
+        }
+    })
+    .await
+    .expect("A should stay quiet after rejecting");
+    assert_eq!(code, Some(ErrorCode::VirtualDevice));
+    assert_eq!(errors, 1);
+
+    let IpcResponse::Devices(report) = ipc_request(&pair.shutdown_a, IpcRequest::Devices).await
+    else {
+        panic!("expected a device report");
+    };
+    let peer = report
+        .remote
+        .iter()
+        .find(|p| p.peer == "machine-b")
+        .expect("devices for the fake peer");
+    let ids: Vec<u32> = peer.devices.iter().map(|d| d.id.0).collect();
+    assert_eq!(ids, vec![1, 2]);
+    assert_eq!(peer.rejected, 3);
+
+    pair.shutdown().await;
+}
+
+/// A fake peer that has taken control of daemon A.
+#[allow(dead_code)]
+struct FakeController {
+    conn: cross_control_protocol::PeerConnection,
+    tx: cross_control_protocol::MessageSender,
+    rx: cross_control_protocol::MessageReceiver,
+    input_tx: cross_control_protocol::MessageSender,
+}
+
+/// Handshake with A as "machine-b", announce a keyboard and Enter A.
+async fn fake_controller_enters(fake: &cross_control_protocol::QuicTransport) -> FakeController {
+    use cross_control_types::{ControlMessage, ScreenEdge, ScreenGeometry, PROTOCOL_VERSION};
+
+    let conn = tokio::time::timeout(Duration::from_secs(5), fake.accept())
+        .await
+        .expect("A should dial the fake peer")
+        .unwrap();
+    let (mut tx, mut rx) = conn.accept_control_stream().await.unwrap();
+    let _hello: ControlMessage = rx.recv_tagged().await.unwrap().unwrap();
+    tx.send_tagged(&ControlMessage::Welcome {
+        version: PROTOCOL_VERSION,
+        machine_id: MachineId::new(),
+        name: "machine-b".to_string(),
+        screen: ScreenGeometry::new(1920, 1080),
+    })
+    .await
+    .unwrap();
+    tx.send_tagged(&ControlMessage::DeviceAnnounce(test_devices().remove(0)))
+        .await
+        .unwrap();
+
+    let input_tx = conn.open_input_stream().await.unwrap();
+    tx.send_tagged(&ControlMessage::Enter {
+        edge: ScreenEdge::Left,
+        position: 500,
+    })
+    .await
+    .unwrap();
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let msg: ControlMessage = rx.recv_tagged().await.unwrap().unwrap();
+            if matches!(msg, ControlMessage::EnterAck) {
+                break;
+            }
+        }
+    })
+    .await
+    .expect("A should accept Enter");
+    FakeController {
+        conn,
+        tx,
+        rx,
+        input_tx,
+    }
+}
+
+/// Input the controller sends before ending its input stream is injected
+/// even when its Leave overtakes it, and control is released only after.
+#[tokio::test]
+async fn test_input_stream_is_drained_after_leave() {
+    use cross_control_types::{ControlMessage, InputMessage, ScreenEdge};
+
+    let mut fake = None;
+    let mut pair = setup_pair_with(MockEmulation::new(), |config_a, _| {
+        fake = Some(bind_fake_peer(config_a));
+    })
+    .await;
+
+    let mut controller = fake_controller_enters(&fake.unwrap()).await;
+
+    // The Leave arrives first; the input it follows is still in flight.
+    controller
+        .tx
+        .send_tagged(&ControlMessage::Leave {
+            edge: ScreenEdge::Left,
+            position: 500,
+        })
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(pair.status_a.borrow().controlled_by.is_some());
+    for i in 0..10 {
+        let msg = InputMessage {
+            device_id: DeviceId(1),
+            timestamp_us: i,
+            events: vec![InputEvent::Key {
+                code: KeyCode::KeyA,
+                state: ButtonState::Pressed,
+            }],
+        };
+        controller.input_tx.send(&msg).await.unwrap();
+    }
+    controller.input_tx.finish().await.unwrap();
+
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.controlled_by.is_none()
+    })
+    .await
+    .expect("A should be released");
+    let injected = pair.emulation_a.injected_events();
+    assert_eq!(injected.len(), 10, "{injected:?}");
+
+    pair.shutdown().await;
+}
+
+/// A controller that goes silent is given up on after
+/// `input.controlled_timeout_secs`; input on the same episode resumes it.
+#[tokio::test]
+async fn test_silent_controller_times_out() {
+    use cross_control_types::InputMessage;
+
+    let mut fake = None;
+    let mut pair = setup_pair_with(MockEmulation::new(), |config_a, _| {
+        fake = Some(bind_fake_peer(config_a));
+        config_a.input.controlled_timeout_secs = 1;
+    })
+    .await;
+    let mut controller = fake_controller_enters(&fake.unwrap()).await;
+    assert!(pair.status_a.borrow().controlled_by.is_some());
+
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.controlled_by.is_none()
+    })
+    .await
+    .expect("A should give up on the silent controller");
+
+    let msg = InputMessage {
+        device_id: DeviceId(1),
+        timestamp_us: 1,
+        events: vec![InputEvent::Key {
+            code: KeyCode::KeyA,
+            state: ButtonState::Pressed,
+        }],
+    };
+    controller.input_tx.send(&msg).await.unwrap();
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.controlled_by.is_some()
+    })
+    .await
+    .expect("input should resume control");
+    assert_eq!(pair.emulation_a.injected_events().len(), 1);
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_peer_error_appears_in_status() {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+    use cross_control_types::{ControlMessage, ErrorCode};
+
+    let mut fake = None;
+    let pair = setup_pair_with(MockEmulation::new(), |config_a, _| {
+        fake = Some(bind_fake_peer(config_a));
+    })
+    .await;
+    let fake = fake.unwrap();
+
+    let conn = tokio::time::timeout(Duration::from_secs(5), fake.accept())
+        .await
+        .expect("A should dial the fake peer")
+        .unwrap();
+    let (mut tx, mut rx) = conn.accept_control_stream().await.unwrap();
+    let _hello: ControlMessage = rx.recv_tagged().await.unwrap().unwrap();
+    tx.send_tagged(&ControlMessage::Error {
+        code: ErrorCode::VersionMismatch,
+        detail: "machine-b speaks protocol 9.0".to_string(),
+    })
+    .await
+    .unwrap();
+
+    let errors = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let IpcResponse::Status(report) =
+                ipc_request(&pair.shutdown_a, IpcRequest::Status).await
+            {
+                if !report.peer_errors.is_empty() {
+                    return report.peer_errors;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("A should record the peer's error");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].peer, "machine-b");
+    assert_eq!(errors[0].code, ErrorCode::VersionMismatch);
+    assert_eq!(errors[0].detail, "machine-b speaks protocol 9.0");
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_input_check_on_enter_injects_zero_move() {
+    let mut pair = setup_pair_with(MockEmulation::new(), |_, config_b| {
+        config_b.input.verify_on_enter = true;
+    })
+    .await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+
+    push_a_to_right_edge(&pair).await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.controlled_by.is_some()
+    })
+    .await
+    .expect("B should be controlled");
+
+    let checked = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if pair
+                .emulation_b
+                .injected_events()
+                .iter()
+                .any(|e| e.event == InputEvent::MouseMove { dx: 0, dy: 0 })
+            {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await;
+    assert!(checked.is_ok(), "B should inject a zero move after Enter");
+    assert!(pair.status_a.borrow().controlling.is_some());
+
+    pair.shutdown().await;
+}
+
+/// With `input.dry_run`, B accepts control but creates no virtual devices
+/// and injects nothing.
+#[tokio::test]
+async fn test_dry_run_accepts_control_without_injecting() {
+    let mut pair = setup_pair_with(MockEmulation::new(), |_, config_b| {
+        config_b.input.dry_run = true;
+    })
+    .await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    push_a_to_right_edge(&pair).await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.controlled_by.is_some()
+    })
+    .await
+    .expect("B should accept control");
+    let event = CapturedEvent {
+        device_id: DeviceId(1),
+        timestamp_us: 1000,
+        event: InputEvent::Key {
+            code: KeyCode::KeyA,
+            state: ButtonState::Pressed,
+        },
+    };
+    pair.feed_a.send(event).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    assert!(pair.emulation_b.devices().is_empty());
+    assert!(pair.emulation_b.injected_events().is_empty());
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_failed_input_check_returns_control() {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+    use cross_control_types::ErrorCode;
+
+    let mut pair = setup_pair_with(MockEmulation::new(), |_, config_b| {
+        config_b.input.verify_on_enter = true;
+    })
+    .await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+    pair.emulation_b.fail_injects(true);
+
+    // B accepts the Enter, fails the check and tells A, which takes the
+    // cursor back.
+    push_a_to_right_edge(&pair).await;
+    let report = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let IpcResponse::Status(report) =
+                ipc_request(&pair.shutdown_a, IpcRequest::Status).await
+            {
+                if !report.peer_errors.is_empty() {
+                    return report;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("A should record B's error");
+    assert!(report.controlling.is_none(), "A should take control back");
+    assert_eq!(report.peer_errors.len(), 1, "{:?}", report.peer_errors);
+    assert_eq!(report.peer_errors[0].peer, "machine-b");
+    assert_eq!(report.peer_errors[0].code, ErrorCode::Inject);
+
+    pair.shutdown().await;
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_auth_command_refuses_inbound_peer() {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+    use cross_control_types::ErrorCode;
+
+    let pair = setup_pair_with(MockEmulation::new(), |_, config_b| {
+        config_b.security.auth_command = vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            r#"echo "$CROSS_CONTROL_PEER_NAME is not in the inventory"; exit 1"#.to_string(),
+        ];
+    })
+    .await;
+
+    let errors = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let IpcResponse::Status(report) =
+                ipc_request(&pair.shutdown_a, IpcRequest::Status).await
+            {
+                if !report.peer_errors.is_empty() {
+                    return report.peer_errors;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("A should be told why it was refused");
+    assert_eq!(errors[0].code, ErrorCode::Untrusted);
+    assert_eq!(
+        errors[0].detail,
+        "machine-b refused this machine: machine-a is not in the inventory"
+    );
+    assert_eq!(pair.status_a.borrow().session_count, 0);
+    assert_eq!(pair.status_b.borrow().session_count, 0);
+
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_session_stats_snapshot_and_reset() {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse, SessionStatsReport};
+
+    async fn stats(
+        events: &mpsc::Sender<DaemonEvent>,
+        request: IpcRequest,
+    ) -> Vec<SessionStatsReport> {
+        match ipc_request(events, request).await {
+            IpcResponse::Stats(reports) => reports,
+            other => panic!("unexpected response: {other:?}"),
+        }
+    }
+
+    let mut pair = setup_pair_with(MockEmulation::new(), |config_a, _| {
+        config_a.performance.tick_ms = Some(20);
+        config_a.performance.keepalive_ms = Some(50);
+    })
+    .await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.session_count >= 1
+    })
+    .await
+    .expect("handshake B");
+
+    push_a_to_right_edge(&pair).await;
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
+        s.controlled_by.is_some()
+    })
+    .await
+    .expect("B should be controlled");
+    for _ in 0..3 {
+        pair.feed_a
+            .send(CapturedEvent {
+                device_id: DeviceId(2),
+                timestamp_us: 2000,
+                event: InputEvent::MouseMove { dx: 10, dy: 0 },
+            })
+            .await
+            .unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let received = stats(&pair.shutdown_b, IpcRequest::Stats { peer: None }).await;
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].name, "machine-a");
+    assert!(received[0].events_received >= 3, "{received:?}");
+    assert!(received[0].bytes_received > 0);
+
+    let before = stats(
+        &pair.shutdown_a,
+        IpcRequest::ResetStats {
+            peer: Some("machine-b".to_string()),
+        },
+    )
+    .await;
+    assert_eq!(before.len(), 1);
+    assert!(before[0].events_sent >= 3, "{before:?}");
+    assert!(before[0].bytes_sent > 0);
+    assert!(!before[0].rtt_us.is_empty(), "keepalives should be timed");
+
+    let after = stats(&pair.shutdown_a, IpcRequest::Stats { peer: None }).await;
+    assert_eq!(after[0].events_sent, 0);
+    assert!(after[0].since_ms >= before[0].since_ms);
+
+    let unknown = ipc_request(
+        &pair.shutdown_a,
+        IpcRequest::Stats {
+            peer: Some("nobody".to_string()),
         },
-    ];
-    for event in hotkey_events {
-        let captured = CapturedEvent {
-            device_id: DeviceId(1),
-            timestamp_us: 2000,
-            event,
-        };
-        pair.feed_a.send(captured).await.unwrap();
-        tokio::time::sleep(Duration::from_millis(10)).await;
-    }
+    )
+    .await;
+    assert!(
+        matches!(unknown, IpcResponse::Error { code, .. } if code == cross_control_types::ErrorCode::UnknownPeer),
+        "{unknown:?}"
+    );
 
-    // Wait for daemon A to release control
-    let status = wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
-        s.controlling.is_none()
+    pair.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_cli_ping_measures_round_trip() {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+    use cross_control_types::ErrorCode;
+
+    let mut pair = setup_pair_with(MockEmulation::new(), |_, _| {}).await;
+    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
+        s.session_count >= 1
     })
     .await
-    .expect("daemon A should release control");
+    .expect("handshake A");
 
-    assert!(status.controlling.is_none());
+    let response = ipc_request(
+        &pair.shutdown_a,
+        IpcRequest::Ping {
+            screen: "machine-b".to_string(),
+        },
+    )
+    .await;
+    assert!(
+        matches!(&response, IpcResponse::Pong { screen, .. } if screen == "machine-b"),
+        "{response:?}"
+    );
+
+    let response = ipc_request(
+        &pair.shutdown_a,
+        IpcRequest::Ping {
+            screen: "nowhere".to_string(),
+        },
+    )
+    .await;
+    assert!(
+        matches!(
+            response,
+            IpcResponse::Error {
+                code: ErrorCode::NotConnected,
+                ..
+            }
+        ),
+        "{response:?}"
+    );
 
     pair.shutdown().await;
 }
 
 #[tokio::test]
-async fn test_input_forwarding() {
-    let _ = tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::new("debug"))
-        .with_test_writer()
-        .try_init();
-    let mut pair = setup_pair().await;
+async fn test_trends_count_crossings_per_day() {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
 
-    // Wait for handshake
+    let mut pair = setup_pair_with(MockEmulation::new(), |_, _| {}).await;
     wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
         s.session_count >= 1
     })
     .await
     .expect("handshake A");
-
     wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
         s.session_count >= 1
     })
     .await
     .expect("handshake B");
-
     tokio::time::sleep(Duration::from_millis(200)).await;
 
-    // Enter controlling state by pushing cursor right
-    for _ in 0..5 {
-        let event = CapturedEvent {
-            device_id: DeviceId(2),
-            timestamp_us: 1000,
-            event: InputEvent::MouseMove { dx: 500, dy: 0 },
-        };
-        pair.feed_a.send(event).await.unwrap();
-        tokio::time::sleep(Duration::from_millis(20)).await;
-    }
-
-    // Wait for controlling state
+    push_a_to_right_edge(&pair).await;
     wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
         s.controlling.is_some()
     })
     .await
-    .expect("should be controlling");
-
-    // Wait for B to confirm controlled_by
-    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
-        s.controlled_by.is_some()
-    })
-    .await
-    .expect("B should be controlled");
+    .expect("A should be controlling");
 
-    // Give the input reader time to be fully established
-    tokio::time::sleep(Duration::from_millis(100)).await;
-
-    // Send multiple key events with delays to ensure delivery
-    for i in 0..5 {
-        let key_event = CapturedEvent {
-            device_id: DeviceId(1),
-            timestamp_us: 3000 + u64::try_from(i).unwrap_or(0),
-            event: InputEvent::Key {
-                code: KeyCode::KeyA,
-                state: ButtonState::Pressed,
-            },
-        };
-        pair.feed_a.send(key_event).await.unwrap();
-        tokio::time::sleep(Duration::from_millis(50)).await;
-    }
+    let response = ipc_request(
+        &pair.shutdown_a,
+        IpcRequest::Trends {
+            days: 7,
+            peer: Some("machine-b".to_string()),
+        },
+    )
+    .await;
+    let IpcResponse::Trends(days) = response else {
+        panic!("unexpected response: {response:?}");
+    };
+    assert_eq!(days.len(), 1, "{days:?}");
+    assert_eq!(days[0].peer, "machine-b");
+    assert_eq!(days[0].crossings, 1);
 
-    // Wait for B's emulation to receive the injected event
-    tokio::time::timeout(Duration::from_secs(5), async {
-        loop {
-            let events = pair.emulation_b.injected_events();
-            if events.iter().any(|e| {
-                matches!(
-                    &e.event,
-                    InputEvent::Key {
-                        code: KeyCode::KeyA,
-                        ..
-                    }
-                )
-            }) {
-                return;
-            }
-            tokio::time::sleep(Duration::from_millis(50)).await;
-        }
-    })
-    .await
-    .expect("daemon B should receive KeyA injection");
+    let response = ipc_request(
+        &pair.shutdown_a,
+        IpcRequest::Trends {
+            days: 7,
+            peer: Some("nowhere".to_string()),
+        },
+    )
+    .await;
+    assert!(
+        matches!(&response, IpcResponse::Trends(days) if days.is_empty()),
+        "{response:?}"
+    );
 
     pair.shutdown().await;
 }
 
 #[tokio::test]
-async fn test_hotkey_release() {
-    let mut pair = setup_pair().await;
+async fn test_keyboard_layout_change_reaches_peer() {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
+    use cross_control_types::KeyboardLayout;
 
-    // Wait for handshake
+    let mut pair = setup_pair_with(MockEmulation::new(), |_, _| {}).await;
     wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
         s.session_count >= 1
     })
     .await
     .expect("handshake A");
 
-    tokio::time::sleep(Duration::from_millis(200)).await;
-
-    // Enter controlling state
-    for _ in 0..5 {
-        let event = CapturedEvent {
-            device_id: DeviceId(2),
-            timestamp_us: 1000,
-            event: InputEvent::MouseMove { dx: 500, dy: 0 },
-        };
-        pair.feed_a.send(event).await.unwrap();
-        tokio::time::sleep(Duration::from_millis(20)).await;
-    }
+    let greek = KeyboardLayout {
+        layout: "gr".to_string(),
+        variant: String::new(),
+    };
+    let response = ipc_request(
+        &pair.shutdown_a,
+        IpcRequest::SetKeyboardLayout(greek.clone()),
+    )
+    .await;
+    assert!(
+        matches!(response, IpcResponse::KeyboardLayoutSet { peers: 1 }),
+        "{response:?}"
+    );
 
-    wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
-        s.controlling.is_some()
+    let layouts = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let IpcResponse::Status(report) =
+                ipc_request(&pair.shutdown_b, IpcRequest::Status).await
+            {
+                if !report.peer_keyboard_layouts.is_empty() {
+                    return report.peer_keyboard_layouts;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
     })
     .await
-    .expect("should be controlling");
+    .expect("B should hear A's layout");
+    assert_eq!(layouts[0].peer, "machine-a");
+    assert_eq!(layouts[0].layout, greek);
 
-    // Send the release hotkey combo
-    let hotkey_events = [
-        InputEvent::Key {
-            code: KeyCode::LeftCtrl,
-            state: ButtonState::Pressed,
-        },
-        InputEvent::Key {
-            code: KeyCode::LeftShift,
-            state: ButtonState::Pressed,
-        },
-        InputEvent::Key {
-            code: KeyCode::Escape,
-            state: ButtonState::Pressed,
-        },
-    ];
-    for event in hotkey_events {
-        let captured = CapturedEvent {
-            device_id: DeviceId(1),
-            timestamp_us: 4000,
-            event,
-        };
-        pair.feed_a.send(captured).await.unwrap();
-        tokio::time::sleep(Duration::from_millis(10)).await;
-    }
+    let IpcResponse::Status(report) = ipc_request(&pair.shutdown_a, IpcRequest::Status).await
+    else {
+        panic!("expected status");
+    };
+    assert_eq!(report.keyboard_layout, Some(greek));
 
-    // Daemon A should release control
-    let status_a = wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| {
-        s.controlling.is_none()
-    })
-    .await
-    .expect("daemon A should release");
+    pair.shutdown().await;
+}
 
-    assert!(status_a.controlling.is_none());
+#[cfg(unix)]
+#[tokio::test]
+async fn test_link_alarm_raised_over_rtt_threshold() {
+    use cross_control_daemon::ipc::{IpcRequest, IpcResponse};
 
-    // Daemon B should return to idle
-    let status_b = wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| {
-        s.controlled_by.is_none()
+    let log = std::env::temp_dir().join(format!("cc-alarm-{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&log);
+    let script = format!(
+        r#"echo "$CROSS_CONTROL_PEER_NAME $CROSS_CONTROL_ALARM" >> {}"#,
+        log.display()
+    );
+    let pair = setup_pair_with(MockEmulation::new(), |config_a, _| {
+        config_a.performance.tick_ms = Some(20);
+        config_a.performance.keepalive_ms = Some(50);
+        config_a.alarms.notify = false;
+        config_a.alarms.for_secs = 0;
+        config_a.alarms.command = vec!["sh".to_string(), "-c".to_string(), script];
+        // Any measured round trip is over a zero threshold.
+        config_a.alarms.peers.insert(
+            "machine-b".to_string(),
+            cross_control_daemon::config::AlarmThresholds {
+                rtt_ms: Some(0),
+                loss_percent: None,
+            },
+        );
+    })
+    .await;
+
+    let alarms = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let IpcResponse::Status(report) =
+                ipc_request(&pair.shutdown_a, IpcRequest::Status).await
+            {
+                if !report.link_alarms.is_empty() {
+                    return report.link_alarms;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
     })
     .await
-    .expect("daemon B should return to idle");
+    .expect("A should raise a link alarm");
+    assert_eq!(alarms[0].peer, "machine-b");
+    assert!(alarms[0].reason.starts_with("RTT "), "{alarms:?}");
 
-    assert!(status_b.controlled_by.is_none());
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !std::fs::read_to_string(&log).is_ok_and(|s| s.contains("machine-b raised")) {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("the alarm command should run");
+    let _ = std::fs::remove_file(&log);
 
     pair.shutdown().await;
 }
@@ -566,26 +3878,64 @@ struct DaemonSpec {
     screen_adjacency: Vec<ScreenAdjacency>,
 }
 
+/// A daemon holds back readiness until its `wait_on_start` peer has a
+/// session; one with nothing to wait for is ready straight away.
+#[tokio::test]
+async fn test_wait_on_start_ready_after_session() {
+    let mut pair = setup_pair_with(MockEmulation::new(), |config_a, _| {
+        config_a.screens[0].wait_on_start = true;
+    })
+    .await;
+
+    let status_a = wait_for_status(&mut pair.status_a, Duration::from_secs(5), |s| s.ready)
+        .await
+        .expect("A ready");
+    assert!(status_a.session_count >= 1);
+    wait_for_status(&mut pair.status_b, Duration::from_secs(5), |s| s.ready)
+        .await
+        .expect("B ready");
+
+    pair.shutdown().await;
+}
+
+/// A `wait_on_start` peer that never answers delays readiness only until
+/// `daemon.wait_on_start_secs` passes.
+#[tokio::test]
+async fn test_wait_on_start_gives_up_after_timeout() {
+    let a = Endpoint::bind();
+    let absent = Endpoint::bind();
+    let mut config_a = config(
+        "machine-a",
+        vec![ScreenConfig {
+            wait_on_start: true,
+            ..screen("machine-b", Position::Right, Some(absent.addr))
+        }],
+    );
+    config_a.daemon.wait_on_start_secs = 1;
+    drop(absent);
+
+    let (mut daemon_a, handle_a) = DaemonBundle::new(config_a, a.transport).spawn();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(!daemon_a.status.borrow().ready);
+
+    wait_for_status(&mut daemon_a.status, Duration::from_secs(5), |s| s.ready)
+        .await
+        .expect("A ready after the timeout");
+    assert_eq!(daemon_a.status.borrow().session_count, 0);
+
+    daemon_a.shutdown().await;
+    let _ = handle_a.await;
+}
+
 /// Set up N daemons on loopback. Returns the cluster and addresses.
 /// `build_specs` receives the bound addresses and returns a spec per daemon.
 async fn setup_cluster<F>(n: usize, build_specs: F) -> TestCluster
 where
     F: FnOnce(&[SocketAddr]) -> Vec<DaemonSpec>,
 {
-    let _ = rustls::crypto::ring::default_provider().install_default();
-
     // Bind all transports first so we know the addresses.
-    let mut transports = Vec::new();
-    let mut addrs = Vec::new();
-    for _ in 0..n {
-        let cert = cross_control_certgen::generate_certificate("localhost").unwrap();
-        let bind: SocketAddr = "127.0.0.1:0".parse().unwrap();
-        let transport =
-            cross_control_protocol::QuicTransport::bind(bind, &cert.cert_pem, &cert.key_pem)
-                .unwrap();
-        addrs.push(transport.local_addr().unwrap());
-        transports.push(transport);
-    }
+    let endpoints: Vec<Endpoint> = (0..n).map(|_| Endpoint::bind()).collect();
+    let addrs: Vec<SocketAddr> = endpoints.iter().map(|e| e.addr).collect();
 
     let specs = build_specs(&addrs);
     assert_eq!(specs.len(), n);
@@ -595,61 +3945,23 @@ where
     let mut shutdowns = Vec::new();
     let mut handles = Vec::new();
 
-    for (i, (transport, spec)) in transports.into_iter().zip(specs).enumerate() {
-        let (capture, feed) = MockCapture::new();
-        let emu = MockEmulation::new();
-
+    for (endpoint, spec) in endpoints.into_iter().zip(specs) {
         let config = Config {
-            daemon: DaemonConfig {
-                screen_width: 1920,
-                screen_height: 1080,
-                ..DaemonConfig::default()
-            },
-            identity: IdentityConfig {
-                name: spec.name.clone(),
-            },
-            screens: spec.screens,
             screen_adjacency: spec.screen_adjacency,
-            ..Config::default()
+            ..config(&spec.name, spec.screens)
         };
-
-        let mut daemon = Daemon::new(
-            config,
-            MachineId::new(),
-            transport,
-            Box::new(capture),
-            Box::new(emu),
-        );
-        daemon.set_local_devices(test_devices());
-        statuses.push(daemon.status_receiver());
-        shutdowns.push(daemon.event_sender());
-        feeds.push(feed);
-
-        let name = spec.name;
-        let handle = tokio::spawn(async move {
-            if let Err(e) = daemon.run().await {
-                eprintln!("daemon {name} (idx {i}) error: {e}");
-            }
-        });
+        let (daemon, handle) = DaemonBundle::new(config, endpoint.transport).spawn();
+        feeds.push(daemon.feed);
+        statuses.push(daemon.status);
+        shutdowns.push(daemon.events);
         handles.push(handle);
     }
 
-    // Wait for all daemons to reach expected session counts.
-    // Each daemon with outbound addresses will connect; each accept completes.
-    // Give a generous timeout.
-    // We don't know expected counts here, so just wait for at least 1 session each.
-    // The caller can do more specific waits.
-    tokio::time::timeout(Duration::from_secs(5), async {
-        loop {
-            let all_connected = statuses.iter().all(|s| s.borrow().session_count >= 1);
-            if all_connected {
-                break;
-            }
-            tokio::time::sleep(Duration::from_millis(50)).await;
-        }
-    })
-    .await
-    .expect("all daemons should establish at least 1 session");
+    // We don't know expected session counts here, so just wait for at
+    // least 1 each. The caller can do more specific waits.
+    wait_for_sessions(&statuses, 1, Duration::from_secs(5))
+        .await
+        .expect("all daemons should establish at least 1 session");
 
     // Let device announcements propagate.
     tokio::time::sleep(Duration::from_millis(200)).await;
@@ -667,7 +3979,7 @@ where
 // ---------------------------------------------------------------------------
 
 /// Set up: A connects to B (above) and C (right).
-/// A knows the full graph via screen_adjacency.
+/// A knows the full graph via `screen_adjacency`.
 ///
 ///        B
 ///        |
@@ -678,39 +3990,19 @@ async fn setup_three_screens() -> TestCluster {
             DaemonSpec {
                 name: "A".into(),
                 screens: vec![
-                    ScreenConfig {
-                        name: "B".into(),
-                        address: Some(addrs[1].to_string()),
-                        position: Position::Above,
-                        fingerprint: None,
-                    },
-                    ScreenConfig {
-                        name: "C".into(),
-                        address: Some(addrs[2].to_string()),
-                        position: Position::Right,
-                        fingerprint: None,
-                    },
+                    screen("B", Position::Above, Some(addrs[1])),
+                    screen("C", Position::Right, Some(addrs[2])),
                 ],
                 screen_adjacency: vec![],
             },
             DaemonSpec {
                 name: "B".into(),
-                screens: vec![ScreenConfig {
-                    name: "A".into(),
-                    address: None,
-                    position: Position::Below,
-                    fingerprint: None,
-                }],
+                screens: vec![screen("A", Position::Below, None)],
                 screen_adjacency: vec![],
             },
             DaemonSpec {
                 name: "C".into(),
-                screens: vec![ScreenConfig {
-                    name: "A".into(),
-                    address: None,
-                    position: Position::Left,
-                    fingerprint: None,
-                }],
+                screens: vec![screen("A", Position::Left, None)],
                 screen_adjacency: vec![],
             },
         ]
@@ -853,18 +4145,8 @@ async fn test_multi_hop_a_to_b_to_c() {
             DaemonSpec {
                 name: "A".into(),
                 screens: vec![
-                    ScreenConfig {
-                        name: "B".into(),
-                        address: Some(addrs[1].to_string()),
-                        position: Position::Right,
-                        fingerprint: None,
-                    },
-                    ScreenConfig {
-                        name: "C".into(),
-                        address: Some(addrs[2].to_string()),
-                        position: Position::Below,
-                        fingerprint: None,
-                    },
+                    screen("B", Position::Right, Some(addrs[1])),
+                    screen("C", Position::Below, Some(addrs[2])),
                 ],
                 // A knows that below B is C (for multi-hop routing).
                 screen_adjacency: vec![ScreenAdjacency {
@@ -876,36 +4158,16 @@ async fn test_multi_hop_a_to_b_to_c() {
             DaemonSpec {
                 name: "B".into(),
                 screens: vec![
-                    ScreenConfig {
-                        name: "A".into(),
-                        address: None,
-                        position: Position::Left,
-                        fingerprint: None,
-                    },
-                    ScreenConfig {
-                        name: "C".into(),
-                        address: Some(addrs[2].to_string()),
-                        position: Position::Below,
-                        fingerprint: None,
-                    },
+                    screen("A", Position::Left, None),
+                    screen("C", Position::Below, Some(addrs[2])),
                 ],
                 screen_adjacency: vec![],
             },
             DaemonSpec {
                 name: "C".into(),
                 screens: vec![
-                    ScreenConfig {
-                        name: "B".into(),
-                        address: None,
-                        position: Position::Above,
-                        fingerprint: None,
-                    },
-                    ScreenConfig {
-                        name: "A".into(),
-                        address: None,
-                        position: Position::Left,
-                        fingerprint: None,
-                    },
+                    screen("B", Position::Above, None),
+                    screen("A", Position::Left, None),
                 ],
                 screen_adjacency: vec![],
             },
@@ -980,3 +4242,75 @@ async fn test_multi_hop_a_to_b_to_c() {
 
     cluster.shutdown().await;
 }
+
+#[tokio::test]
+async fn test_guided_setup_exchanges_layout() {
+    use cross_control_daemon::pairing;
+    use cross_control_types::{ScreenGeometry, SetupMessage};
+
+    let Endpoint {
+        cert: cert_a,
+        transport: transport_a,
+        ..
+    } = Endpoint::bind();
+    let Endpoint {
+        cert: cert_b,
+        transport: transport_b,
+        addr: addr_b,
+    } = Endpoint::bind();
+
+    let responder = tokio::spawn(async move {
+        let mut session = pairing::accept(
+            &transport_b,
+            MachineId::new(),
+            "machine-b",
+            &ScreenGeometry::new(2560, 1440),
+        )
+        .await
+        .unwrap();
+        let code = session.verification_code().unwrap();
+        let layout = session.recv().await.unwrap();
+        session.send(SetupMessage::Accepted).await.unwrap();
+        session.wait_closed().await;
+        (code, layout, session.peer_fingerprint().map(str::to_string))
+    });
+
+    let mut session = pairing::connect(
+        &transport_a,
+        addr_b,
+        MachineId::new(),
+        "machine-a",
+        &ScreenGeometry::new(1920, 1080),
+    )
+    .await
+    .unwrap();
+    assert_eq!(session.peer_name(), "machine-b");
+    assert_eq!(session.peer_screen().width, 2560);
+    assert_eq!(
+        session.peer_fingerprint(),
+        Some(cert_b.fingerprint.as_str())
+    );
+    let code = session.verification_code().unwrap();
+
+    // B is to the right of A, so A is to the left of B.
+    session
+        .send(SetupMessage::Layout {
+            position: Position::Right.opposite(),
+            port: 24800,
+        })
+        .await
+        .unwrap();
+    assert_eq!(session.recv().await.unwrap(), SetupMessage::Accepted);
+    session.close().await;
+
+    let (code_b, layout, fingerprint_a) = responder.await.unwrap();
+    assert_eq!(code, code_b);
+    assert_eq!(
+        layout,
+        SetupMessage::Layout {
+            position: Position::Left,
+            port: 24800,
+        }
+    );
+    assert_eq!(fingerprint_a.as_deref(), Some(cert_a.fingerprint.as_str()));
+}
@@ -0,0 +1,749 @@
+//! UDP broadcast discovery for networks that block multicast.
+//!
+//! Every advertiser broadcasts a signed announcement every few seconds and
+//! answers probes from browsers that have just started. An announcement
+//! carries the [`TxtRecord`] properties (including the daemon port), the
+//! sender's address, a timestamp and a sequence number, the sender's
+//! certificate, and an ECDSA P-256 signature over all but the last two made
+//! with its TLS key. The fingerprint reported for a peer is therefore that
+//! of the key that signed the packet.
+//!
+//! Receivers drop announcements that are too old or too far in the future,
+//! and any whose sequence number doesn't increase on the last one from the
+//! same key, so a captured packet can't be replayed to bring back a peer or
+//! point it somewhere else. A machine ID stays bound to the key it was
+//! first announced with until the peer is lost, so another host can't
+//! announce it leaving or moving. Probes are unauthenticated, so they are answered
+//! at most once per [`PROBE_REPLY_INTERVAL`].
+//!
+//! The QUIC endpoint owns the daemon port, so broadcasts use a port of their
+//! own, by default the one after the daemon port.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use bincode::{Decode, Encode};
+use cross_control_types::{warn_throttled, MachineId};
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+use rustls_pki_types::CertificateDer;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, info};
+
+use crate::{Discovery, DiscoveryError, DiscoveryEvent, Peer, TxtRecord};
+
+/// How often an advertiser repeats its announcement.
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Peers not heard from for this long are reported lost.
+const PEER_EXPIRY: Duration = Duration::from_secs(15);
+
+/// How far an announcement's timestamp may be from the receiver's clock,
+/// either way, before it is dropped as stale.
+const MAX_CLOCK_SKEW: Duration = Duration::from_secs(30);
+
+/// Shortest gap between two answers to probes.
+const PROBE_REPLY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Prefix of the signed bytes, so the signature can't be replayed elsewhere.
+const SIGNATURE_CONTEXT: &[u8] = b"cross-control broadcast announcement v2";
+
+/// Largest packet accepted; an announcement with a certificate is well below.
+const MAX_PACKET: usize = 4096;
+
+#[derive(Debug, Encode, Decode)]
+enum Packet {
+    /// A browser started and asks advertisers to announce now.
+    Probe,
+    Announce(Announcement),
+}
+
+#[derive(Debug, Clone, Encode, Decode)]
+struct Announcement {
+    properties: Vec<(String, String)>,
+    /// The sender stopped advertising.
+    leaving: bool,
+    /// Address the sender is reachable at, if it could tell. Receivers
+    /// fall back to the packet's source address.
+    address: Option<IpAddr>,
+    /// Sender's clock when signed, in milliseconds since the Unix epoch.
+    timestamp: u64,
+    /// Increases with every announcement from the same key.
+    sequence: u64,
+    /// DER certificate of the sender.
+    certificate: Vec<u8>,
+    /// Signature over [`signed_bytes`] with the certificate's key.
+    signature: Vec<u8>,
+}
+
+/// Signs announcements with the daemon's TLS key.
+struct Signer {
+    certificate: Vec<u8>,
+    key: EcdsaKeyPair,
+    /// Next sequence number. Starts at the current time so a restarted
+    /// daemon carries on above the numbers it used before.
+    sequence: AtomicU64,
+}
+
+impl Signer {
+    /// An encoded, freshly signed announcement of `record`, sent towards
+    /// `destination`.
+    fn announcement(
+        &self,
+        record: &TxtRecord,
+        leaving: bool,
+        destination: SocketAddr,
+    ) -> Result<Vec<u8>, DiscoveryError> {
+        let mut announcement = Announcement {
+            properties: record.to_properties(),
+            leaving,
+            address: local_ip(destination),
+            timestamp: unix_millis(),
+            sequence: self.sequence.fetch_add(1, Ordering::Relaxed),
+            certificate: self.certificate.clone(),
+            signature: Vec::new(),
+        };
+        let signature = self
+            .key
+            .sign(&SystemRandom::new(), &signed_bytes(&announcement))
+            .map_err(|_| anyhow::anyhow!("failed to sign announcement"))?;
+        announcement.signature = signature.as_ref().to_vec();
+        encode(&Packet::Announce(announcement))
+    }
+}
+
+/// State shared with the socket task.
+#[derive(Default)]
+struct Shared {
+    /// The record being advertised, re-signed for every announcement.
+    announcement: Option<TxtRecord>,
+    /// Browse output and the group it is filtered on.
+    browse: Option<(mpsc::Sender<DiscoveryEvent>, Option<String>)>,
+}
+
+/// Last sequence number accepted from each signing key, by fingerprint.
+#[derive(Default)]
+struct Sequences(HashMap<String, (u64, Instant)>);
+
+impl Sequences {
+    /// Accept an announcement signed by `fingerprint` if it is recent and
+    /// newer than the last one from that key.
+    fn check(
+        &mut self,
+        fingerprint: &str,
+        announcement: &Announcement,
+        now: u64,
+    ) -> Result<(), &'static str> {
+        let skew = u64::try_from(MAX_CLOCK_SKEW.as_millis()).unwrap_or(u64::MAX);
+        if announcement.timestamp.abs_diff(now) > skew {
+            return Err("stale announcement");
+        }
+        if let Some((last, _)) = self.0.get(fingerprint) {
+            if announcement.sequence <= *last {
+                return Err("replayed announcement");
+            }
+        }
+        self.0.insert(
+            fingerprint.to_string(),
+            (announcement.sequence, Instant::now()),
+        );
+        Ok(())
+    }
+
+    /// Forget keys whose last announcement is past the clock skew window
+    /// on either side; anything replayed from them is stale by now.
+    fn prune(&mut self) {
+        self.0
+            .retain(|_, (_, at)| at.elapsed() < MAX_CLOCK_SKEW * 2);
+    }
+}
+
+/// Discovery over UDP broadcast.
+pub struct BroadcastDiscovery {
+    port: u16,
+    destination: SocketAddr,
+    signer: Arc<Signer>,
+    shared: Arc<Mutex<Shared>>,
+    socket: Option<Arc<UdpSocket>>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl BroadcastDiscovery {
+    /// Create a backend listening on `port`, signing with the daemon's TLS
+    /// certificate and key.
+    pub fn new(port: u16, cert_pem: &str, key_pem: &str) -> Result<Self, DiscoveryError> {
+        let mut reader = std::io::BufReader::new(cert_pem.as_bytes());
+        let certificate = rustls_pemfile::certs(&mut reader)
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("no certificate found in PEM"))?
+            .map_err(|e| anyhow::anyhow!("failed to parse certificate PEM: {e}"))?
+            .to_vec();
+
+        let mut reader = std::io::BufReader::new(key_pem.as_bytes());
+        let key = loop {
+            match rustls_pemfile::read_one(&mut reader)
+                .map_err(|e| anyhow::anyhow!("failed to parse key PEM: {e}"))?
+            {
+                Some(rustls_pemfile::Item::Pkcs8Key(key)) => break key,
+                Some(_) => {}
+                None => return Err(anyhow::anyhow!("no PKCS#8 key found in PEM").into()),
+            }
+        };
+        let key = EcdsaKeyPair::from_pkcs8(
+            &ECDSA_P256_SHA256_ASN1_SIGNING,
+            key.secret_pkcs8_der(),
+            &SystemRandom::new(),
+        )
+        .map_err(|e| anyhow::anyhow!("broadcast discovery needs an ECDSA P-256 key: {e}"))?;
+
+        Ok(Self {
+            port,
+            destination: SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::BROADCAST, port)),
+            signer: Arc::new(Signer {
+                certificate,
+                key,
+                sequence: AtomicU64::new(unix_millis()),
+            }),
+            shared: Arc::default(),
+            socket: None,
+            task: None,
+        })
+    }
+
+    /// Send packets to `destination` instead of the limited broadcast
+    /// address, e.g. a subnet's directed broadcast address.
+    #[must_use]
+    pub fn with_destination(mut self, destination: SocketAddr) -> Self {
+        self.destination = destination;
+        self
+    }
+
+    /// Bind the socket and start the receive task on first use.
+    fn socket(&mut self) -> Result<Arc<UdpSocket>, DiscoveryError> {
+        if let Some(socket) = &self.socket {
+            return Ok(Arc::clone(socket));
+        }
+        let socket = bind(self.port)
+            .map_err(|e| anyhow::anyhow!("failed to bind broadcast port {}: {e}", self.port))?;
+        let socket = Arc::new(socket);
+        info!(port = self.port, "broadcast discovery listening");
+        self.task = Some(tokio::spawn(run(
+            Arc::clone(&socket),
+            self.destination,
+            Arc::clone(&self.signer),
+            Arc::clone(&self.shared),
+        )));
+        self.socket = Some(Arc::clone(&socket));
+        Ok(socket)
+    }
+
+    async fn send(&mut self, packet: &[u8]) -> Result<(), DiscoveryError> {
+        let socket = self.socket()?;
+        socket
+            .send_to(packet, self.destination)
+            .await
+            .map_err(|e| anyhow::anyhow!("broadcast failed: {e}"))?;
+        Ok(())
+    }
+}
+
+impl Drop for BroadcastDiscovery {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+}
+
+#[async_trait]
+impl Discovery for BroadcastDiscovery {
+    async fn advertise(&mut self, record: &TxtRecord) -> Result<(), DiscoveryError> {
+        let packet = self.signer.announcement(record, false, self.destination)?;
+        lock(&self.shared).announcement = Some(record.clone());
+        self.send(&packet).await
+    }
+
+    async fn stop_advertising(&mut self) -> Result<(), DiscoveryError> {
+        let Some(record) = lock(&self.shared).announcement.take() else {
+            return Ok(());
+        };
+        let goodbye = self.signer.announcement(&record, true, self.destination)?;
+        self.send(&goodbye).await
+    }
+
+    async fn browse(
+        &mut self,
+        group: Option<&str>,
+    ) -> Result<mpsc::Receiver<DiscoveryEvent>, DiscoveryError> {
+        let (tx, rx) = mpsc::channel(64);
+        lock(&self.shared).browse = Some((tx, group.map(str::to_string)));
+        self.send(&encode(&Packet::Probe)?).await?;
+        Ok(rx)
+    }
+
+    async fn stop_browsing(&mut self) -> Result<(), DiscoveryError> {
+        lock(&self.shared).browse = None;
+        Ok(())
+    }
+}
+
+/// Receive packets, answer probes, repeat our announcement, and report
+/// peers coming and going.
+async fn run(
+    socket: Arc<UdpSocket>,
+    destination: SocketAddr,
+    signer: Arc<Signer>,
+    shared: Arc<Mutex<Shared>>,
+) {
+    let mut seen: HashMap<MachineId, (Peer, Instant)> = HashMap::new();
+    let mut sequences = Sequences::default();
+    let mut last_probe_reply: Option<Instant> = None;
+    let mut ticker = tokio::time::interval(ANNOUNCE_INTERVAL);
+    let mut buf = vec![0u8; MAX_PACKET];
+
+    let announce = |signer: &Signer| {
+        let record = lock(&shared).announcement.clone()?;
+        match signer.announcement(&record, false, destination) {
+            Ok(packet) => Some(packet),
+            Err(e) => {
+                debug!(error = %e, "failed to sign announcement");
+                None
+            }
+        }
+    };
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                if let Some(packet) = announce(&signer) {
+                    if let Err(e) = socket.send_to(&packet, destination).await {
+                        debug!(error = %e, "announcement failed");
+                    }
+                }
+                let expired: Vec<MachineId> = seen
+                    .iter()
+                    .filter(|(_, (_, at))| at.elapsed() >= PEER_EXPIRY)
+                    .map(|(id, _)| *id)
+                    .collect();
+                for id in expired {
+                    seen.remove(&id);
+                    emit(&shared, DiscoveryEvent::PeerLost(id)).await;
+                }
+                sequences.prune();
+            }
+            received = socket.recv_from(&mut buf) => {
+                let (len, from) = match received {
+                    Ok(received) => received,
+                    Err(e) => {
+                        debug!(error = %e, "broadcast receive failed");
+                        continue;
+                    }
+                };
+                match decode(&buf[..len]) {
+                    Ok(Packet::Probe) => {
+                        if last_probe_reply
+                            .is_some_and(|at| at.elapsed() < PROBE_REPLY_INTERVAL)
+                        {
+                            debug!(from = %from, "probe reply rate limited");
+                            continue;
+                        }
+                        if let Some(packet) = announce(&signer) {
+                            last_probe_reply = Some(Instant::now());
+                            let _ = socket.send_to(&packet, destination).await;
+                        }
+                    }
+                    Ok(Packet::Announce(announcement)) => {
+                        handle_announcement(
+                            &shared,
+                            &mut seen,
+                            &mut sequences,
+                            &announcement,
+                            from,
+                        )
+                        .await;
+                    }
+                    Err(e) => debug!(from = %from, error = %e, "ignoring malformed packet"),
+                }
+            }
+        }
+    }
+}
+
+async fn handle_announcement(
+    shared: &Mutex<Shared>,
+    seen: &mut HashMap<MachineId, (Peer, Instant)>,
+    sequences: &mut Sequences,
+    announcement: &Announcement,
+    from: SocketAddr,
+) {
+    let record = match verify(announcement) {
+        Ok(record) => record,
+        Err(e) => {
+            warn_throttled!(from = %from, error = %e, "ignoring unverified announcement");
+            return;
+        }
+    };
+    let fingerprint = record.fingerprint.as_deref().unwrap_or_default();
+    if let Err(reason) = sequences.check(fingerprint, announcement, unix_millis()) {
+        debug!(from = %from, sequence = announcement.sequence, "ignoring {reason}");
+        return;
+    }
+    let group = match &lock(shared).browse {
+        Some((_, group)) => group.clone(),
+        None => return,
+    };
+    if record.group != group {
+        return;
+    }
+
+    let machine_id = record.machine_id;
+    // A machine ID belongs to the key first heard announcing it, until it
+    // expires, so another host can't make it leave or move.
+    if let Some((known, _)) = seen.get(&machine_id) {
+        if known.fingerprint != record.fingerprint {
+            warn_throttled!(
+                from = %from,
+                machine_id = %machine_id,
+                "ignoring announcement signed by another key"
+            );
+            return;
+        }
+    }
+    if announcement.leaving {
+        if seen.remove(&machine_id).is_some() {
+            emit(shared, DiscoveryEvent::PeerLost(machine_id)).await;
+        }
+        return;
+    }
+    let peer = record.into_peer(announcement.address.unwrap_or(from.ip()));
+    let changed = seen.get(&machine_id).map_or(true, |(known, _)| {
+        known.address != peer.address || known.name != peer.name
+    });
+    seen.insert(machine_id, (peer.clone(), Instant::now()));
+    if changed {
+        emit(shared, DiscoveryEvent::PeerFound(peer)).await;
+    }
+}
+
+/// Check the signature and parse the record. The record's fingerprint must
+/// match the signing certificate; a missing one is filled in.
+fn verify(announcement: &Announcement) -> Result<TxtRecord, DiscoveryError> {
+    let der = CertificateDer::from(announcement.certificate.as_slice());
+    let cert = webpki::EndEntityCert::try_from(&der)
+        .map_err(|e| DiscoveryError::InvalidTxt(format!("bad certificate: {e}")))?;
+    cert.verify_signature(
+        webpki::ring::ECDSA_P256_SHA256,
+        &signed_bytes(announcement),
+        &announcement.signature,
+    )
+    .map_err(|e| DiscoveryError::InvalidTxt(format!("bad signature: {e}")))?;
+
+    let props = announcement
+        .properties
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()));
+    let mut record = TxtRecord::from_properties(props)?;
+    let fingerprint = cross_control_certgen::fingerprint_from_der(&announcement.certificate);
+    match &record.fingerprint {
+        Some(advertised) if *advertised != fingerprint => {
+            return Err(DiscoveryError::InvalidTxt(
+                "fingerprint does not match the signing certificate".to_string(),
+            ));
+        }
+        _ => record.fingerprint = Some(fingerprint),
+    }
+    Ok(record)
+}
+
+/// Everything in an announcement but the certificate and signature.
+fn signed_bytes(announcement: &Announcement) -> Vec<u8> {
+    let mut bytes = SIGNATURE_CONTEXT.to_vec();
+    for (key, value) in &announcement.properties {
+        for part in [key, value] {
+            bytes.extend_from_slice(&u32::try_from(part.len()).unwrap_or(u32::MAX).to_be_bytes());
+            bytes.extend_from_slice(part.as_bytes());
+        }
+    }
+    bytes.push(u8::from(announcement.leaving));
+    match announcement.address {
+        None => bytes.push(0),
+        Some(IpAddr::V4(ip)) => {
+            bytes.push(4);
+            bytes.extend_from_slice(&ip.octets());
+        }
+        Some(IpAddr::V6(ip)) => {
+            bytes.push(6);
+            bytes.extend_from_slice(&ip.octets());
+        }
+    }
+    bytes.extend_from_slice(&announcement.timestamp.to_be_bytes());
+    bytes.extend_from_slice(&announcement.sequence.to_be_bytes());
+    bytes
+}
+
+/// The local address packets to `destination` leave from, found by
+/// connecting an unbound UDP socket (nothing is sent).
+fn local_ip(destination: SocketAddr) -> Option<IpAddr> {
+    let socket = std::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.set_broadcast(true).ok()?;
+    socket.connect(destination).ok()?;
+    let ip = socket.local_addr().ok()?.ip();
+    (!ip.is_unspecified()).then_some(ip)
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| u64::try_from(d.as_millis()).unwrap_or(u64::MAX))
+}
+
+async fn emit(shared: &Mutex<Shared>, event: DiscoveryEvent) {
+    let tx = lock(shared).browse.as_ref().map(|(tx, _)| tx.clone());
+    if let Some(tx) = tx {
+        let _ = tx.send(event).await;
+    }
+}
+
+fn bind(port: u16) -> std::io::Result<UdpSocket> {
+    use socket2::{Domain, Protocol, Socket, Type};
+
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    // Several daemons on one host (or a restart) can share the port; every
+    // socket bound to it receives each broadcast.
+    socket.set_reuse_address(true)?;
+    socket.set_broadcast(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port)).into())?;
+    UdpSocket::from_std(socket.into())
+}
+
+fn encode(packet: &Packet) -> Result<Vec<u8>, DiscoveryError> {
+    bincode::encode_to_vec(packet, bincode::config::standard())
+        .map_err(|e| anyhow::anyhow!("failed to encode packet: {e}").into())
+}
+
+fn decode(bytes: &[u8]) -> Result<Packet, bincode::error::DecodeError> {
+    let config = bincode::config::standard().with_limit::<MAX_PACKET>();
+    bincode::decode_from_slice(bytes, config).map(|(packet, _)| packet)
+}
+
+fn lock(shared: &Mutex<Shared>) -> std::sync::MutexGuard<'_, Shared> {
+    shared
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cross_control_types::PROTOCOL_VERSION;
+
+    fn record(machine_id: MachineId, name: &str) -> TxtRecord {
+        TxtRecord {
+            version: PROTOCOL_VERSION,
+            machine_id,
+            name: name.to_string(),
+            port: 24800,
+            fingerprint: None,
+            capabilities: Vec::new(),
+            group: None,
+        }
+    }
+
+    fn backend(port: u16, peer_port: u16) -> (BroadcastDiscovery, String) {
+        let cert = cross_control_certgen::generate_certificate("localhost").unwrap();
+        let backend = BroadcastDiscovery::new(port, &cert.cert_pem, &cert.key_pem)
+            .unwrap()
+            .with_destination(SocketAddr::from((Ipv4Addr::LOCALHOST, peer_port)));
+        (backend, cert.fingerprint)
+    }
+
+    fn free_port() -> u16 {
+        std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    #[test]
+    fn tampered_announcement_is_rejected() {
+        let (backend, fingerprint) = backend(0, 0);
+        let packet = backend
+            .signer
+            .announcement(
+                &record(MachineId::new(), "desk"),
+                false,
+                backend.destination,
+            )
+            .unwrap();
+        let Ok(Packet::Announce(mut announcement)) = decode(&packet) else {
+            panic!("expected an announcement");
+        };
+        let verified = verify(&announcement).unwrap();
+        assert_eq!(verified.fingerprint, Some(fingerprint));
+
+        for (key, value) in &mut announcement.properties {
+            if key == "name" {
+                *value = "impostor".to_string();
+            }
+        }
+        assert!(verify(&announcement).is_err());
+    }
+
+    #[test]
+    fn signed_address_cannot_be_changed() {
+        let (backend, _) = backend(0, 0);
+        let packet = backend
+            .signer
+            .announcement(
+                &record(MachineId::new(), "desk"),
+                false,
+                backend.destination,
+            )
+            .unwrap();
+        let Ok(Packet::Announce(mut announcement)) = decode(&packet) else {
+            panic!("expected an announcement");
+        };
+        assert_eq!(
+            announcement.address,
+            Some(IpAddr::from(Ipv4Addr::LOCALHOST))
+        );
+
+        announcement.address = Some(IpAddr::from([192, 0, 2, 1]));
+        assert!(verify(&announcement).is_err());
+    }
+
+    #[test]
+    fn replayed_and_stale_announcements_are_rejected() {
+        let (backend, fingerprint) = backend(0, 0);
+        let announce = || {
+            let packet = backend
+                .signer
+                .announcement(
+                    &record(MachineId::new(), "desk"),
+                    false,
+                    backend.destination,
+                )
+                .unwrap();
+            let Ok(Packet::Announce(announcement)) = decode(&packet) else {
+                panic!("expected an announcement");
+            };
+            announcement
+        };
+        let (first, second) = (announce(), announce());
+        let mut sequences = Sequences::default();
+
+        let skew = u64::try_from(MAX_CLOCK_SKEW.as_millis()).unwrap();
+        let late = second.timestamp + skew + 1;
+        assert!(sequences.check(&fingerprint, &second, late).is_err());
+
+        let now = unix_millis();
+        sequences.check(&fingerprint, &second, now).unwrap();
+        assert!(sequences.check(&fingerprint, &second, now).is_err());
+        assert!(sequences.check(&fingerprint, &first, now).is_err());
+        sequences.check(&fingerprint, &announce(), now).unwrap();
+    }
+
+    #[tokio::test]
+    async fn machine_id_stays_with_its_first_key() {
+        let (owner, _) = backend(0, 0);
+        let (impostor, _) = backend(0, 0);
+        let id = MachineId::new();
+        let announce = |backend: &BroadcastDiscovery, leaving: bool| {
+            let packet = backend
+                .signer
+                .announcement(&record(id, "desk"), leaving, backend.destination)
+                .unwrap();
+            let Ok(Packet::Announce(announcement)) = decode(&packet) else {
+                panic!("expected an announcement");
+            };
+            announcement
+        };
+        let (tx, mut events) = mpsc::channel(8);
+        let shared = Mutex::new(Shared {
+            announcement: None,
+            browse: Some((tx, None)),
+        });
+        let mut seen = HashMap::new();
+        let mut sequences = Sequences::default();
+        let from = SocketAddr::from((Ipv4Addr::LOCALHOST, 1));
+
+        let first = announce(&owner, false);
+        handle_announcement(&shared, &mut seen, &mut sequences, &first, from).await;
+        assert!(matches!(
+            events.try_recv(),
+            Ok(DiscoveryEvent::PeerFound(_))
+        ));
+
+        let renamed = {
+            let packet = impostor
+                .signer
+                .announcement(&record(id, "evil"), false, impostor.destination)
+                .unwrap();
+            let Ok(Packet::Announce(announcement)) = decode(&packet) else {
+                panic!("expected an announcement");
+            };
+            announcement
+        };
+        let leaving = announce(&impostor, true);
+        for spoofed in [&renamed, &leaving] {
+            handle_announcement(&shared, &mut seen, &mut sequences, spoofed, from).await;
+        }
+        assert!(events.try_recv().is_err(), "impostor changed the peer");
+        assert!(seen.contains_key(&id));
+
+        let goodbye = announce(&owner, true);
+        handle_announcement(&shared, &mut seen, &mut sequences, &goodbye, from).await;
+        assert!(matches!(events.try_recv(), Ok(DiscoveryEvent::PeerLost(lost)) if lost == id));
+    }
+
+    #[tokio::test]
+    async fn announcements_are_found_and_lost() {
+        let (port_a, port_b) = (free_port(), free_port());
+        let (mut a, fingerprint_a) = backend(port_a, port_b);
+        let (mut b, _) = backend(port_b, port_a);
+        let id_a = MachineId::new();
+
+        let mut events = b.browse(None).await.unwrap();
+        a.advertise(&record(id_a, "desk")).await.unwrap();
+
+        let event = tokio::time::timeout(Duration::from_secs(5), events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let DiscoveryEvent::PeerFound(peer) = event else {
+            panic!("expected PeerFound, got {event:?}");
+        };
+        assert_eq!(peer.machine_id, id_a);
+        assert_eq!(peer.fingerprint, Some(fingerprint_a));
+        assert_eq!(peer.address.port(), 24800);
+
+        a.stop_advertising().await.unwrap();
+        let event = tokio::time::timeout(Duration::from_secs(5), events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(event, DiscoveryEvent::PeerLost(id) if id == id_a));
+    }
+
+    #[tokio::test]
+    async fn other_groups_are_ignored() {
+        let (port_a, port_b) = (free_port(), free_port());
+        let (mut a, _) = backend(port_a, port_b);
+        let (mut b, _) = backend(port_b, port_a);
+
+        let mut events = b.browse(Some("design")).await.unwrap();
+        a.advertise(&record(MachineId::new(), "desk"))
+            .await
+            .unwrap();
+        let found = tokio::time::timeout(Duration::from_millis(500), events.recv()).await;
+        assert!(found.is_err(), "unexpected event: {found:?}");
+    }
+}
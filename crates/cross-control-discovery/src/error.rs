@@ -10,6 +10,9 @@ pub enum DiscoveryError {
     #[error("mDNS browse failed: {0}")]
     Browse(String),
 
+    #[error("invalid TXT record: {0}")]
+    InvalidTxt(String),
+
     #[error("backend not available on this platform")]
     Unavailable,
 
@@ -1,14 +1,44 @@
 //! mDNS/DNS-SD zero-config discovery for cross-control.
 //!
 //! Defines the [`Discovery`] trait for advertising and browsing cross-control
-//! peers on the local network. The mdns-sd backend will be added in Phase 2.
+//! peers on the local network, and [`BroadcastDiscovery`], a UDP broadcast
+//! backend for networks that block multicast. The mdns-sd backend will be
+//! added in Phase 2.
 
 use async_trait::async_trait;
 use cross_control_types::MachineId;
 
+pub mod broadcast;
 pub mod error;
+pub mod txt;
 
+pub use broadcast::BroadcastDiscovery;
 pub use error::DiscoveryError;
+pub use txt::{ServiceCapability, TxtRecord};
+
+/// DNS-SD service type advertised by every cross-control daemon.
+pub const SERVICE_TYPE: &str = "_cross-control._udp.local.";
+
+/// DNS-SD subtype under which members of `group` advertise, so a browser
+/// can ask for its own group only.
+///
+/// The group is lowercased, characters outside `[a-z0-9-]` become `-`, and
+/// the label, leading `_` included, is cut to the 63 bytes DNS allows.
+pub fn group_subtype(group: &str) -> String {
+    let label: String = group
+        .chars()
+        .map(|c| c.to_ascii_lowercase())
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .take(62)
+        .collect();
+    format!("_{label}._sub.{SERVICE_TYPE}")
+}
 
 /// A discovered peer on the network.
 #[derive(Debug, Clone)]
@@ -21,25 +51,29 @@ pub struct Peer {
     pub address: std::net::SocketAddr,
     /// TLS certificate fingerprint (SHA-256).
     pub fingerprint: Option<String>,
+    /// Discovery group the peer advertises, if any.
+    pub group: Option<String>,
 }
 
 /// Network discovery for cross-control peers.
 #[async_trait]
 pub trait Discovery: Send + 'static {
-    /// Start advertising this machine on the network.
-    async fn advertise(
-        &mut self,
-        machine_id: MachineId,
-        name: &str,
-        port: u16,
-    ) -> Result<(), DiscoveryError>;
+    /// Start advertising this machine on the network, with `record` as the
+    /// service's TXT record.
+    ///
+    /// With a `group` in the record, the service is also registered under
+    /// [`group_subtype`].
+    async fn advertise(&mut self, record: &TxtRecord) -> Result<(), DiscoveryError>;
 
     /// Stop advertising.
     async fn stop_advertising(&mut self) -> Result<(), DiscoveryError>;
 
     /// Start browsing for peers, sending discoveries to the returned receiver.
+    ///
+    /// With a `group`, only peers advertising the same group are reported.
     async fn browse(
         &mut self,
+        group: Option<&str>,
     ) -> Result<tokio::sync::mpsc::Receiver<DiscoveryEvent>, DiscoveryError>;
 
     /// Stop browsing.
@@ -54,3 +88,19 @@ pub enum DiscoveryEvent {
     /// A previously known peer disappeared.
     PeerLost(MachineId),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group_subtype_is_a_dns_label() {
+        assert_eq!(
+            group_subtype("Design Team"),
+            "_design-team._sub._cross-control._udp.local."
+        );
+        let long = "x".repeat(100);
+        let subtype = group_subtype(&long);
+        assert_eq!(subtype.split('.').next().unwrap().len(), 63);
+    }
+}
@@ -0,0 +1,246 @@
+//! The TXT record advertised with the DNS-SD service.
+//!
+//! Keys are kept short so the record fits in a single mDNS packet:
+//!
+//! | Key     | Value                                        |
+//! |---------|----------------------------------------------|
+//! | `v`     | Protocol version, `major.minor`              |
+//! | `id`    | Machine ID                                   |
+//! | `name`  | Machine name                                 |
+//! | `port`  | QUIC port                                    |
+//! | `fp`    | Certificate fingerprint (optional)           |
+//! | `caps`  | Comma-separated capabilities (optional)      |
+//! | `group` | Discovery group (optional)                   |
+//!
+//! Unknown keys and capabilities are ignored, so newer peers can add fields
+//! without breaking older browsers.
+
+use std::net::{IpAddr, SocketAddr};
+
+use cross_control_types::{MachineId, ProtocolVersion};
+
+use crate::{DiscoveryError, Peer};
+
+/// Something an advertised daemon can do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceCapability {
+    /// It can inject input, so it can be controlled.
+    Control,
+    /// It shares its clipboard.
+    Clipboard,
+}
+
+impl ServiceCapability {
+    /// The name used in the `caps` key.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Control => "control",
+            Self::Clipboard => "clipboard",
+        }
+    }
+
+    /// Parse a `caps` entry; unknown names yield `None`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "control" => Some(Self::Control),
+            "clipboard" => Some(Self::Clipboard),
+            _ => None,
+        }
+    }
+}
+
+/// Typed contents of the advertised TXT record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxtRecord {
+    pub version: ProtocolVersion,
+    pub machine_id: MachineId,
+    pub name: String,
+    pub port: u16,
+    pub fingerprint: Option<String>,
+    pub capabilities: Vec<ServiceCapability>,
+    pub group: Option<String>,
+}
+
+impl TxtRecord {
+    /// Key/value pairs to register with the mDNS responder.
+    pub fn to_properties(&self) -> Vec<(String, String)> {
+        let mut props = vec![
+            ("v".to_string(), self.version.to_string()),
+            ("id".to_string(), self.machine_id.to_string()),
+            ("name".to_string(), self.name.clone()),
+            ("port".to_string(), self.port.to_string()),
+        ];
+        if let Some(fingerprint) = &self.fingerprint {
+            props.push(("fp".to_string(), fingerprint.clone()));
+        }
+        if !self.capabilities.is_empty() {
+            let caps: Vec<&str> = self.capabilities.iter().map(|c| c.as_str()).collect();
+            props.push(("caps".to_string(), caps.join(",")));
+        }
+        if let Some(group) = &self.group {
+            props.push(("group".to_string(), group.clone()));
+        }
+        props
+    }
+
+    /// Parse the key/value pairs of a resolved service.
+    pub fn from_properties<'a>(
+        props: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Result<Self, DiscoveryError> {
+        let mut version = None;
+        let mut machine_id = None;
+        let mut name = None;
+        let mut port = None;
+        let mut fingerprint = None;
+        let mut capabilities = Vec::new();
+        let mut group = None;
+
+        for (key, value) in props {
+            match key {
+                "v" => version = Some(parse_version(value)?),
+                "id" => {
+                    let uuid = value
+                        .parse()
+                        .map_err(|e| invalid(format!("bad machine id {value:?}: {e}")))?;
+                    machine_id = Some(MachineId::from_uuid(uuid));
+                }
+                "name" => name = Some(value.to_string()),
+                "port" => {
+                    port = Some(
+                        value
+                            .parse()
+                            .map_err(|_| invalid(format!("bad port {value:?}")))?,
+                    );
+                }
+                "fp" => fingerprint = Some(value.to_string()),
+                "caps" => {
+                    capabilities = value
+                        .split(',')
+                        .filter_map(ServiceCapability::parse)
+                        .collect();
+                }
+                "group" => group = Some(value.to_string()),
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            version: version.ok_or_else(|| invalid("missing v".to_string()))?,
+            machine_id: machine_id.ok_or_else(|| invalid("missing id".to_string()))?,
+            name: name.ok_or_else(|| invalid("missing name".to_string()))?,
+            port: port.ok_or_else(|| invalid("missing port".to_string()))?,
+            fingerprint,
+            capabilities,
+            group,
+        })
+    }
+
+    /// The peer this record describes, reachable at `ip`.
+    pub fn into_peer(self, ip: IpAddr) -> Peer {
+        Peer {
+            machine_id: self.machine_id,
+            name: self.name,
+            address: SocketAddr::new(ip, self.port),
+            fingerprint: self.fingerprint,
+            group: self.group,
+        }
+    }
+}
+
+fn parse_version(value: &str) -> Result<ProtocolVersion, DiscoveryError> {
+    let (major, minor) = value
+        .split_once('.')
+        .ok_or_else(|| invalid(format!("bad version {value:?}")))?;
+    let parse = |part: &str| {
+        part.parse()
+            .map_err(|_| invalid(format!("bad version {value:?}")))
+    };
+    Ok(ProtocolVersion {
+        major: parse(major)?,
+        minor: parse(minor)?,
+    })
+}
+
+fn invalid(reason: String) -> DiscoveryError {
+    DiscoveryError::InvalidTxt(reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cross_control_types::PROTOCOL_VERSION;
+
+    fn record() -> TxtRecord {
+        TxtRecord {
+            version: PROTOCOL_VERSION,
+            machine_id: MachineId::new(),
+            name: "desk".to_string(),
+            port: 24800,
+            fingerprint: Some("SHA256:ab:cd".to_string()),
+            capabilities: vec![ServiceCapability::Control, ServiceCapability::Clipboard],
+            group: Some("design".to_string()),
+        }
+    }
+
+    fn parse(props: &[(String, String)]) -> Result<TxtRecord, DiscoveryError> {
+        TxtRecord::from_properties(props.iter().map(|(k, v)| (k.as_str(), v.as_str())))
+    }
+
+    #[test]
+    fn full_record_round_trips() {
+        let record = record();
+        assert_eq!(parse(&record.to_properties()).unwrap(), record);
+    }
+
+    #[test]
+    fn minimal_record_round_trips() {
+        let record = TxtRecord {
+            fingerprint: None,
+            capabilities: Vec::new(),
+            group: None,
+            ..record()
+        };
+        let props = record.to_properties();
+        assert_eq!(props.len(), 4);
+        assert_eq!(parse(&props).unwrap(), record);
+    }
+
+    #[test]
+    fn unknown_keys_and_capabilities_are_ignored() {
+        let mut props = record().to_properties();
+        props.push(("future".to_string(), "yes".to_string()));
+        for (key, value) in &mut props {
+            if key == "caps" {
+                *value = "control,teleport".to_string();
+            }
+        }
+        let parsed = parse(&props).unwrap();
+        assert_eq!(parsed.capabilities, vec![ServiceCapability::Control]);
+    }
+
+    #[test]
+    fn missing_or_malformed_fields_are_rejected() {
+        let props = record().to_properties();
+        let without_id: Vec<_> = props.iter().filter(|(k, _)| k != "id").cloned().collect();
+        assert!(parse(&without_id).is_err());
+
+        let mut bad_port = props.clone();
+        bad_port.retain(|(k, _)| k != "port");
+        bad_port.push(("port".to_string(), "99999".to_string()));
+        assert!(parse(&bad_port).is_err());
+
+        let mut bad_version = props;
+        bad_version.retain(|(k, _)| k != "v");
+        bad_version.push(("v".to_string(), "1".to_string()));
+        assert!(parse(&bad_version).is_err());
+    }
+
+    #[test]
+    fn record_becomes_peer() {
+        let record = record();
+        let peer = record.clone().into_peer("10.0.0.7".parse().unwrap());
+        assert_eq!(peer.machine_id, record.machine_id);
+        assert_eq!(peer.address, "10.0.0.7:24800".parse().unwrap());
+        assert_eq!(peer.group.as_deref(), Some("design"));
+    }
+}
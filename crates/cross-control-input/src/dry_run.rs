@@ -0,0 +1,92 @@
+//! Emulation backend that only logs.
+//!
+//! [`DryRunEmulation`] accepts every device and event a controlling peer
+//! sends and logs what would have been injected, without touching the
+//! machine. It backs `input.dry_run`, for trying out a new peer's
+//! configuration or a demo on a machine that mustn't be typed into.
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use cross_control_types::{DeviceInfo, InputEvent, VirtualDeviceId};
+use tracing::info;
+
+use crate::error::InputError;
+use crate::InputEmulation;
+
+/// Logs virtual devices and injected events instead of emitting them.
+#[derive(Debug, Default)]
+pub struct DryRunEmulation {
+    /// Names of the devices that would exist, by virtual device ID.
+    devices: HashMap<VirtualDeviceId, String>,
+    next_id: u32,
+}
+
+impl DryRunEmulation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn name(&self, device: VirtualDeviceId) -> &str {
+        self.devices.get(&device).map_or("unknown", String::as_str)
+    }
+}
+
+#[async_trait]
+impl InputEmulation for DryRunEmulation {
+    async fn create_device(&mut self, info: &DeviceInfo) -> Result<VirtualDeviceId, InputError> {
+        self.next_id += 1;
+        let id = VirtualDeviceId(self.next_id);
+        info!(device = %info.name, capabilities = ?info.capabilities, "dry run: would create virtual device");
+        self.devices.insert(id, info.name.clone());
+        Ok(id)
+    }
+
+    async fn inject(
+        &mut self,
+        device: VirtualDeviceId,
+        event: InputEvent,
+    ) -> Result<(), InputError> {
+        if !self.devices.contains_key(&device) {
+            return Err(InputError::Inject(format!("unknown device {device:?}")));
+        }
+        info!(device = self.name(device), ?event, "dry run: would inject");
+        Ok(())
+    }
+
+    async fn destroy_device(&mut self, device: VirtualDeviceId) -> Result<(), InputError> {
+        if let Some(name) = self.devices.remove(&device) {
+            info!(device = %name, "dry run: would destroy virtual device");
+        }
+        Ok(())
+    }
+
+    async fn shutdown(&mut self) -> Result<(), InputError> {
+        self.devices.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cross_control_types::{DeviceCapability, DeviceId};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn accepts_input_for_devices_it_created() {
+        let mut emulation = DryRunEmulation::new();
+        let info = DeviceInfo {
+            id: DeviceId(1),
+            name: "Office Mouse".to_string(),
+            capabilities: vec![DeviceCapability::RelativeMouse],
+            keys: Vec::new(),
+        };
+        let device = emulation.create_device(&info).await.unwrap();
+        let event = InputEvent::MouseMove { dx: 5, dy: 0 };
+        emulation.inject(device, event.clone()).await.unwrap();
+
+        emulation.destroy_device(device).await.unwrap();
+        assert!(emulation.inject(device, event).await.is_err());
+    }
+}
@@ -1,5 +1,6 @@
 //! Input subsystem errors.
 
+use cross_control_types::ErrorCode;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -25,3 +26,18 @@ pub enum InputError {
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
+
+impl InputError {
+    /// Machine-readable cause.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::DeviceOpen(_) => ErrorCode::DeviceOpen,
+            Self::DeviceGrab(_) => ErrorCode::DeviceGrab,
+            Self::VirtualDeviceCreate(_) => ErrorCode::VirtualDevice,
+            Self::Inject(_) => ErrorCode::Inject,
+            Self::BarrierNotFound(_) => ErrorCode::BarrierNotFound,
+            Self::Unavailable => ErrorCode::InputUnavailable,
+            Self::Other(_) => ErrorCode::Internal,
+        }
+    }
+}
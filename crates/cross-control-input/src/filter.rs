@@ -0,0 +1,84 @@
+//! Name-based overrides for which local devices are captured.
+
+/// Devices to capture or skip regardless of what a backend's own
+/// classification says. Patterns are matched against device names with
+/// [`wildcard_match`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceFilter {
+    /// Capture these even if they look virtual or are neither a keyboard
+    /// nor a mouse, e.g. a key remapper's output device or a macro pad.
+    pub include: Vec<String>,
+    /// Never capture these. Wins over `include`.
+    pub exclude: Vec<String>,
+}
+
+impl DeviceFilter {
+    /// Whether the device called `name` is forced in.
+    pub fn includes(&self, name: &str) -> bool {
+        !self.excludes(name)
+            && self
+                .include
+                .iter()
+                .any(|pattern| wildcard_match(pattern, name))
+    }
+
+    /// Whether the device called `name` is left out.
+    pub fn excludes(&self, name: &str) -> bool {
+        self.exclude
+            .iter()
+            .any(|pattern| wildcard_match(pattern, name))
+    }
+}
+
+/// Case-insensitive match of `text` against `pattern`, where `*` matches
+/// any run of characters.
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` seen, and where in `text` it started matching.
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star_p, star_t)) = star {
+            // Let the last `*` swallow one more character and retry.
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        assert!(wildcard_match("a*b*c", "aXXbYbZc"));
+        assert!(!wildcard_match("a*b*c", "aXXbYbZ"));
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("*KEYBOARD*", "Logitech USB Keyboard"));
+    }
+
+    #[test]
+    fn exclude_wins() {
+        let filter = DeviceFilter {
+            include: vec!["keyd*".to_string(), "*pad*".to_string()],
+            exclude: vec!["*touchpad*".to_string()],
+        };
+        assert!(filter.includes("keyd virtual keyboard"));
+        assert!(filter.includes("Macro Pad"));
+        assert!(!filter.includes("ELAN Touchpad"));
+        assert!(filter.excludes("ELAN Touchpad"));
+        assert!(!filter.excludes("Macro Pad"));
+    }
+}
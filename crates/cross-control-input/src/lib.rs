@@ -4,13 +4,17 @@
 //! platform-specific backends must implement. The evdev/uinput (Linux) and
 //! Raw Input/SendInput (Windows) backends will be added in later phases.
 
+use std::sync::Arc;
+
 use async_trait::async_trait;
 use cross_control_types::{
     Barrier, BarrierId, CapturedEvent, DeviceInfo, InputEvent, VirtualDeviceId,
 };
 use tokio::sync::mpsc;
 
+pub mod dry_run;
 pub mod error;
+pub mod filter;
 
 #[cfg(feature = "linux")]
 pub mod linux;
@@ -18,7 +22,12 @@ pub mod linux;
 #[cfg(any(test, feature = "mock"))]
 pub mod mock;
 
+pub use dry_run::DryRunEmulation;
 pub use error::InputError;
+pub use filter::{wildcard_match, DeviceFilter};
+
+/// Releases grabbed devices without going through the owning task.
+pub type ReleaseHandle = Arc<dyn Fn() + Send + Sync>;
 
 /// Captures physical input devices and detects barrier crossings.
 ///
@@ -35,9 +44,20 @@ pub trait InputCapture: Send + 'static {
     /// Remove a previously registered barrier.
     async fn remove_barrier(&mut self, id: BarrierId) -> Result<(), InputError>;
 
+    /// Grab all devices exclusively, so the local desktop stops receiving
+    /// their input until [`release`](Self::release).
+    async fn grab(&mut self) -> Result<(), InputError>;
+
     /// Release all grabbed devices (give control back to local machine).
     async fn release(&mut self) -> Result<(), InputError>;
 
+    /// A synchronous way to release grabbed devices from another thread,
+    /// for when the task owning the backend is stuck. Called after
+    /// [`start`](Self::start); `None` if the backend has nothing to release.
+    fn release_handle(&self) -> Option<ReleaseHandle> {
+        None
+    }
+
     /// Shut down the capture backend and release all resources.
     async fn shutdown(&mut self) -> Result<(), InputError>;
 }
@@ -45,6 +65,14 @@ pub trait InputCapture: Send + 'static {
 /// Creates virtual input devices and injects events on the controlled machine.
 #[async_trait]
 pub trait InputEmulation: Send + 'static {
+    /// Check that the backend can create virtual devices on this system.
+    ///
+    /// Called once at daemon startup so an unusable backend is detected
+    /// before any peer tries to take control.
+    async fn probe(&mut self) -> Result<(), InputError> {
+        Ok(())
+    }
+
     /// Create a virtual device mirroring the given physical device info.
     async fn create_device(&mut self, info: &DeviceInfo) -> Result<VirtualDeviceId, InputError>;
 
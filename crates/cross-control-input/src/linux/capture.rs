@@ -1,21 +1,26 @@
 //! evdev-based input capture for Linux.
 
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::os::fd::AsFd;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
 
 use async_trait::async_trait;
 use cross_control_types::{
-    Barrier, BarrierId, CapturedEvent, DeviceCapability, DeviceId, DeviceInfo, InputEvent,
+    Barrier, BarrierId, CapturedEvent, DeviceCapability, DeviceId, DeviceInfo, InputEvent, KeyCode,
     ScrollDirection,
 };
-use evdev::{Device, EventSummary, EventType, KeyCode as EvdevKey, RelativeAxisCode};
+use evdev::{
+    AttributeSetRef, BusType, Device, EventSummary, EventType, KeyCode as EvdevKey,
+    RelativeAxisCode,
+};
 use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
-use tracing::{debug, info, warn};
+use tracing::{debug, info, trace_span, warn};
 
 use super::keymap;
 use crate::error::InputError;
-use crate::InputCapture;
+use crate::{DeviceFilter, InputCapture, ReleaseHandle};
 
 /// Linux input capture using evdev.
 ///
@@ -23,17 +28,85 @@ use crate::InputCapture;
 /// by default). The daemon calls [`grab`] when switching to remote control
 /// and [`release`] when returning.
 pub struct EvdevCapture {
-    devices: HashMap<DeviceId, DeviceEntry>,
     barriers: HashMap<BarrierId, Barrier>,
     next_barrier_id: u32,
     task: Option<JoinHandle<()>>,
     shutdown_tx: Option<tokio::sync::watch::Sender<bool>>,
+    filter: DeviceFilter,
+    /// Where each reader task's device is grabbed, shared with the
+    /// [`release_handle`](InputCapture::release_handle).
+    grabs: Grabs<Device>,
+}
+
+/// Per-device handles for the exclusive grab, keyed by device.
+///
+/// An evdev grab belongs to the open file that took it, and the kernel then
+/// delivers the device's events to that file only. Each handle is therefore
+/// a duplicate of the reader task's own descriptor, sharing its open file:
+/// grabbing through it keeps events flowing to the reader while the rest of
+/// the desktop stops seeing them, and it can be released without going
+/// through the reader.
+type Grabs<D> = Arc<Mutex<HashMap<DeviceId, GrabHandle<D>>>>;
+
+struct GrabHandle<D> {
+    name: String,
+    device: D,
+    grabbed: bool,
+}
+
+/// A device that can be grabbed exclusively and given up again.
+trait Grab {
+    fn grab(&mut self) -> std::io::Result<()>;
+    fn ungrab(&mut self) -> std::io::Result<()>;
+}
+
+impl Grab for Device {
+    fn grab(&mut self) -> std::io::Result<()> {
+        Device::grab(self)
+    }
+
+    fn ungrab(&mut self) -> std::io::Result<()> {
+        Device::ungrab(self)
+    }
+}
+
+/// Grab every device in `grabs`, or none: if one fails, those already
+/// grabbed are released again.
+fn grab_all<D: Grab>(grabs: &Mutex<HashMap<DeviceId, GrabHandle<D>>>) -> Result<(), InputError> {
+    let failed = lock(grabs)
+        .values_mut()
+        .filter(|handle| !handle.grabbed)
+        .find_map(|handle| match handle.device.grab() {
+            Ok(()) => {
+                handle.grabbed = true;
+                None
+            }
+            Err(e) => Some(InputError::DeviceGrab(format!("{}: {e}", handle.name))),
+        });
+    if let Some(e) = failed {
+        // Don't leave the keyboard grabbed and the mouse free, or the
+        // other way round: undo the grabs made so far.
+        warn!(error = %e, "grab failed, releasing devices already grabbed");
+        release_grabs(grabs);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Ungrab every grabbed device in `grabs`.
+fn release_grabs<D: Grab>(grabs: &Mutex<HashMap<DeviceId, GrabHandle<D>>>) {
+    for handle in lock(grabs).values_mut().filter(|handle| handle.grabbed) {
+        if let Err(e) = handle.device.ungrab() {
+            debug!(device = %handle.name, error = %e, "failed to ungrab device");
+        }
+        handle.grabbed = false;
+    }
 }
 
-struct DeviceEntry {
-    path: PathBuf,
-    #[allow(dead_code)]
-    info: DeviceInfo,
+/// A [`ReleaseHandle`] that ungrabs the devices in `grabs`.
+fn grabs_release_handle<D: Grab + Send + 'static>(grabs: &Grabs<D>) -> ReleaseHandle {
+    let grabs = Arc::clone(grabs);
+    Arc::new(move || release_grabs(&grabs))
 }
 
 impl Default for EvdevCapture {
@@ -44,94 +117,74 @@ impl Default for EvdevCapture {
 
 impl EvdevCapture {
     pub fn new() -> Self {
+        Self::with_filter(DeviceFilter::default())
+    }
+
+    /// Capture the devices [`enumerate_devices`](Self::enumerate_devices)
+    /// picks with `filter`.
+    pub fn with_filter(filter: DeviceFilter) -> Self {
         Self {
-            devices: HashMap::new(),
             barriers: HashMap::new(),
             next_barrier_id: 1,
             task: None,
             shutdown_tx: None,
+            filter,
+            grabs: Grabs::default(),
         }
     }
 
     /// Enumerate input devices and return info about keyboards and mice.
-    pub fn enumerate_devices() -> Vec<(PathBuf, DeviceInfo)> {
+    ///
+    /// Virtual devices, such as other programs' uinput devices, are
+    /// skipped unless `filter` includes them.
+    pub fn enumerate_devices(filter: &DeviceFilter) -> Vec<(PathBuf, DeviceInfo)> {
         let mut result = Vec::new();
         let mut dev_id: u32 = 0;
 
         for (path, device) in evdev::enumerate() {
-            let supported = device.supported_events();
-            let mut capabilities = Vec::new();
-
-            if supported.contains(EventType::KEY) {
-                if let Some(keys) = device.supported_keys() {
-                    // Check for keyboard keys
-                    let has_keyboard_keys = keys.contains(EvdevKey::KEY_A)
-                        && keys.contains(EvdevKey::KEY_Z)
-                        && keys.contains(EvdevKey::KEY_ENTER);
-
-                    if has_keyboard_keys {
-                        capabilities.push(DeviceCapability::Keyboard);
-                    }
-
-                    // Check for mouse buttons
-                    if keys.contains(EvdevKey::BTN_LEFT) {
-                        capabilities.push(DeviceCapability::RelativeMouse);
-                    }
-                }
+            let name = device.name().unwrap_or("Unknown Device").to_string();
+            if filter.excludes(&name) {
+                debug!(device = %name, "skipping excluded device");
+                continue;
             }
-
-            if supported.contains(EventType::RELATIVE) {
-                if let Some(rel) = device.supported_relative_axes() {
-                    if rel.contains(RelativeAxisCode::REL_WHEEL)
-                        || rel.contains(RelativeAxisCode::REL_HWHEEL)
-                    {
-                        capabilities.push(DeviceCapability::Scroll);
-                    }
-                    // Also add RelativeMouse if REL_X/REL_Y present but not already added
-                    if (rel.contains(RelativeAxisCode::REL_X)
-                        || rel.contains(RelativeAxisCode::REL_Y))
-                        && !capabilities.contains(&DeviceCapability::RelativeMouse)
-                    {
-                        capabilities.push(DeviceCapability::RelativeMouse);
-                    }
-                }
+            let forced = filter.includes(&name);
+            if !forced && is_virtual(&path, &device) {
+                debug!(device = %name, "skipping virtual device");
+                continue;
             }
 
+            let supported = device.supported_events();
+            let keys = device
+                .supported_keys()
+                .filter(|_| supported.contains(EventType::KEY));
+            let rel = device
+                .supported_relative_axes()
+                .filter(|_| supported.contains(EventType::RELATIVE));
+            let (capabilities, key_set) = classify(keys, rel, forced);
+
             if capabilities.is_empty() {
                 continue;
             }
 
-            let name = device.name().unwrap_or("Unknown Device").to_string();
             let info = DeviceInfo {
                 id: DeviceId(dev_id),
                 name,
                 capabilities,
+                keys: key_set,
             };
+            debug!(device = %info.name, capabilities = ?info.capabilities, "classified device");
             result.push((path, info));
             dev_id += 1;
         }
 
         result
     }
-
-    /// Grab all tracked devices exclusively (prevents local desktop from receiving input).
-    pub fn grab(&mut self) -> Result<(), InputError> {
-        for entry in self.devices.values() {
-            if let Ok(mut device) = Device::open(&entry.path) {
-                device
-                    .grab()
-                    .map_err(|e| InputError::DeviceGrab(e.to_string()))?;
-            }
-        }
-        info!("grabbed all input devices");
-        Ok(())
-    }
 }
 
 #[async_trait]
 impl InputCapture for EvdevCapture {
     async fn start(&mut self, tx: mpsc::Sender<CapturedEvent>) -> Result<(), InputError> {
-        let device_list = Self::enumerate_devices();
+        let device_list = Self::enumerate_devices(&self.filter);
 
         if device_list.is_empty() {
             return Err(diagnose_no_devices());
@@ -142,67 +195,30 @@ impl InputCapture for EvdevCapture {
 
         for (path, info) in &device_list {
             info!(device = %info.name, path = %path.display(), "tracking device");
-            self.devices.insert(
-                info.id,
-                DeviceEntry {
-                    path: path.clone(),
-                    info: info.clone(),
-                },
-            );
         }
 
         // Spawn reader tasks for each device
         let mut handles = Vec::new();
         for (path, info) in device_list {
-            let tx = tx.clone();
-            let device_id = info.id;
-            let mut shutdown_rx = shutdown_rx.clone();
-
-            let handle: JoinHandle<()> = tokio::spawn(async move {
-                let device = match Device::open(&path) {
-                    Ok(d) => d,
-                    Err(e) => {
-                        warn!(path = %path.display(), error = %e, "failed to open device");
-                        return;
-                    }
-                };
-                let mut stream = match device.into_event_stream() {
-                    Ok(s) => s,
-                    Err(e) => {
-                        warn!(path = %path.display(), error = %e, "failed to create event stream");
-                        return;
-                    }
-                };
-
-                loop {
-                    tokio::select! {
-                        _ = shutdown_rx.changed() => {
-                            break;
-                        }
-                        result = stream.next_event() => {
-                            match result {
-                                Ok(ev) => {
-                                    if let Some(input_event) = convert_evdev_event(&ev) {
-                                        let captured = CapturedEvent {
-                                            device_id,
-                                            timestamp_us: ev.timestamp().duration_since(std::time::SystemTime::UNIX_EPOCH).ok().and_then(|d| u64::try_from(d.as_micros()).ok()).unwrap_or(0),
-                                            event: input_event,
-                                        };
-                                        if tx.send(captured).await.is_err() {
-                                            break;
-                                        }
-                                    }
-                                }
-                                Err(e) => {
-                                    warn!(error = %e, "device read error");
-                                    break;
-                                }
-                            }
-                        }
-                    }
-                }
-            });
-            handles.push(handle);
+            let Some((grab, handle)) =
+                spawn_reader(&path, info.id, tx.clone(), shutdown_rx.clone())
+            else {
+                continue;
+            };
+            let grabs = Arc::clone(&self.grabs);
+            lock(&grabs).insert(
+                info.id,
+                GrabHandle {
+                    name: info.name,
+                    device: grab,
+                    grabbed: false,
+                },
+            );
+            handles.push(tokio::spawn(async move {
+                let _ = handle.await;
+                // The device is gone; its grab went with the reader's descriptor.
+                lock(&grabs).remove(&info.id);
+            }));
         }
 
         // Spawn a supervisor that waits for all reader tasks
@@ -232,17 +248,22 @@ impl InputCapture for EvdevCapture {
         Ok(())
     }
 
+    async fn grab(&mut self) -> Result<(), InputError> {
+        grab_all(&self.grabs)?;
+        info!("grabbed all input devices");
+        Ok(())
+    }
+
     async fn release(&mut self) -> Result<(), InputError> {
-        // Re-open devices without grab to release exclusive access
-        for entry in self.devices.values() {
-            if let Ok(mut device) = Device::open(&entry.path) {
-                let _ = device.ungrab();
-            }
-        }
+        release_grabs(&self.grabs);
         info!("released all input devices");
         Ok(())
     }
 
+    fn release_handle(&self) -> Option<ReleaseHandle> {
+        Some(grabs_release_handle(&self.grabs))
+    }
+
     async fn shutdown(&mut self) -> Result<(), InputError> {
         if let Some(tx) = self.shutdown_tx.take() {
             let _ = tx.send(true);
@@ -251,12 +272,155 @@ impl InputCapture for EvdevCapture {
             let _ = task.await;
         }
         self.release().await?;
-        self.devices.clear();
+        lock(&self.grabs).clear();
         info!("input capture shut down");
         Ok(())
     }
 }
 
+fn lock<D>(
+    grabs: &Mutex<HashMap<DeviceId, GrabHandle<D>>>,
+) -> std::sync::MutexGuard<'_, HashMap<DeviceId, GrabHandle<D>>> {
+    grabs.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Open the device at `path` and spawn a task forwarding its events to
+/// `tx` until `shutdown` fires. Returns the task and a duplicate of its
+/// descriptor to grab the device through.
+fn spawn_reader(
+    path: &Path,
+    device_id: DeviceId,
+    tx: mpsc::Sender<CapturedEvent>,
+    mut shutdown: tokio::sync::watch::Receiver<bool>,
+) -> Option<(Device, JoinHandle<()>)> {
+    let device = match Device::open(path) {
+        Ok(d) => d,
+        Err(e) => {
+            warn!(path = %path.display(), error = %e, "failed to open device");
+            return None;
+        }
+    };
+    let grab = match device
+        .as_fd()
+        .try_clone_to_owned()
+        .and_then(Device::from_fd)
+    {
+        Ok(d) => d,
+        Err(e) => {
+            warn!(path = %path.display(), error = %e, "failed to duplicate device");
+            return None;
+        }
+    };
+    let mut stream = match device.into_event_stream() {
+        Ok(s) => s,
+        Err(e) => {
+            warn!(path = %path.display(), error = %e, "failed to create event stream");
+            return None;
+        }
+    };
+
+    let handle = tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = shutdown.changed() => {
+                    break;
+                }
+                result = stream.next_event() => {
+                    match result {
+                        Ok(ev) => {
+                            let converted = {
+                                let _span = trace_span!("capture").entered();
+                                convert_evdev_event(&ev)
+                            };
+                            if let Some(input_event) = converted {
+                                let captured = CapturedEvent {
+                                    device_id,
+                                    timestamp_us: ev.timestamp().duration_since(std::time::SystemTime::UNIX_EPOCH).ok().and_then(|d| u64::try_from(d.as_micros()).ok()).unwrap_or(0),
+                                    event: input_event,
+                                };
+                                if tx.send(captured).await.is_err() {
+                                    break;
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            warn!(error = %e, "device read error");
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    });
+    Some((grab, handle))
+}
+
+/// Whether the node at `path` belongs to a virtual device, such as one
+/// another program created through uinput.
+fn is_virtual(path: &Path, device: &Device) -> bool {
+    if device.input_id().bus_type() == BusType::BUS_VIRTUAL {
+        return true;
+    }
+    path.file_name()
+        .map(|node| Path::new("/sys/class/input").join(node))
+        .and_then(|link| std::fs::canonicalize(link).ok())
+        .is_some_and(|sys_path| is_uinput_sys_path(&sys_path))
+}
+
+/// Whether a node's sysfs path is that of a uinput device. These have no
+/// parent device and live under /sys/devices/virtual/input, whatever bus
+/// type their creator claimed. uhid devices, such as Bluetooth LE
+/// keyboards, also live under /sys/devices/virtual, but under misc/uhid,
+/// and are real hardware.
+fn is_uinput_sys_path(sys_path: &Path) -> bool {
+    sys_path.starts_with("/sys/devices/virtual/input")
+}
+
+/// Work out what a device can do from its supported keys and relative
+/// axes, and which keyboard keys it has.
+///
+/// A node is checked for every capability, so a wireless receiver that
+/// exposes keyboard and mouse on one node comes out as a single device with
+/// both. Capabilities are listed in a fixed order without duplicates.
+///
+/// Power buttons, lid switches and media-key-only nodes have no keyboard
+/// or mouse profile and come out empty, unless `forced`, which counts any
+/// key other than a mouse button as a keyboard.
+fn classify(
+    keys: Option<&AttributeSetRef<EvdevKey>>,
+    rel: Option<&AttributeSetRef<RelativeAxisCode>>,
+    forced: bool,
+) -> (Vec<DeviceCapability>, Vec<KeyCode>) {
+    let has_key = |key| keys.is_some_and(|keys| keys.contains(key));
+    let has_rel = |axis| rel.is_some_and(|rel| rel.contains(axis));
+    let keyboard_keys = || {
+        keys.into_iter()
+            .flat_map(AttributeSetRef::iter)
+            .filter(|k| keymap::evdev_key_to_mouse_button(*k).is_none())
+    };
+
+    let keyboard =
+        (has_key(EvdevKey::KEY_A) && has_key(EvdevKey::KEY_Z) && has_key(EvdevKey::KEY_ENTER))
+            || (forced && keyboard_keys().next().is_some());
+    let pointer = has_key(EvdevKey::BTN_LEFT)
+        || (has_rel(RelativeAxisCode::REL_X) && has_rel(RelativeAxisCode::REL_Y));
+    let scroll = has_rel(RelativeAxisCode::REL_WHEEL) || has_rel(RelativeAxisCode::REL_HWHEEL);
+
+    let mut capabilities = Vec::new();
+    let mut key_set = Vec::new();
+    if keyboard {
+        capabilities.push(DeviceCapability::Keyboard);
+        key_set = keyboard_keys().map(keymap::evdev_key_to_keycode).collect();
+    }
+    if pointer {
+        capabilities.push(DeviceCapability::RelativeMouse);
+    }
+    if scroll {
+        capabilities.push(DeviceCapability::Scroll);
+    }
+    (capabilities, key_set)
+}
+
 /// Diagnose why no input devices were found — permissions vs genuinely empty.
 fn diagnose_no_devices() -> InputError {
     use std::fs;
@@ -301,8 +465,7 @@ fn diagnose_no_devices() -> InputError {
             // Event files exist — check if we can read any
             let any_readable = event_files.iter().any(|f| {
                 fs::metadata(f.path())
-                    .map(|m| m.mode() & 0o004 != 0) // world-readable
-                    .unwrap_or(false)
+                    .is_ok_and(|m| m.mode() & 0o004 != 0) // world-readable
                     || fs::File::open(f.path()).is_ok()
             });
 
@@ -362,3 +525,219 @@ fn convert_evdev_event(ev: &evdev::InputEvent) -> Option<InputEvent> {
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use evdev::AttributeSet;
+
+    use super::*;
+
+    /// Stands in for a device descriptor, recording whether it is grabbed.
+    struct FakeGrab {
+        grabbed: Arc<AtomicBool>,
+        fails: bool,
+    }
+
+    impl Grab for FakeGrab {
+        fn grab(&mut self) -> std::io::Result<()> {
+            if self.fails {
+                return Err(std::io::Error::other("busy"));
+            }
+            self.grabbed.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn ungrab(&mut self) -> std::io::Result<()> {
+            self.grabbed.store(false, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    fn fake_grabs(fails: &[bool]) -> (Grabs<FakeGrab>, Vec<Arc<AtomicBool>>) {
+        let grabs: Grabs<FakeGrab> = Grabs::default();
+        let flags: Vec<_> = fails
+            .iter()
+            .map(|_| Arc::new(AtomicBool::new(false)))
+            .collect();
+        for (id, (&fails, grabbed)) in (0..).zip(fails.iter().zip(&flags)) {
+            lock(&grabs).insert(
+                DeviceId(id),
+                GrabHandle {
+                    name: format!("device {id}"),
+                    device: FakeGrab {
+                        grabbed: Arc::clone(grabbed),
+                        fails,
+                    },
+                    grabbed: false,
+                },
+            );
+        }
+        (grabs, flags)
+    }
+
+    #[test]
+    fn release_handle_ungrabs_the_grabbed_devices() {
+        let (grabs, flags) = fake_grabs(&[false, false]);
+        let handle = grabs_release_handle(&grabs);
+
+        grab_all(&grabs).unwrap();
+        assert!(flags.iter().all(|f| f.load(Ordering::SeqCst)));
+        handle();
+        assert!(flags.iter().all(|f| !f.load(Ordering::SeqCst)));
+        // The handles stay, so the devices can be grabbed again.
+        grab_all(&grabs).unwrap();
+        assert!(flags.iter().all(|f| f.load(Ordering::SeqCst)));
+    }
+
+    #[test]
+    fn failed_grab_releases_the_others() {
+        let (grabs, flags) = fake_grabs(&[false, true, false]);
+        assert!(matches!(grab_all(&grabs), Err(InputError::DeviceGrab(_))));
+        assert!(flags.iter().all(|f| !f.load(Ordering::SeqCst)));
+        assert!(lock(&grabs).values().all(|handle| !handle.grabbed));
+    }
+
+    /// The reader keeps receiving a grabbed device's events. Needs
+    /// `/dev/uinput`, and is skipped without it.
+    #[tokio::test]
+    async fn grabbed_device_still_reaches_its_reader() {
+        use evdev::uinput::VirtualDevice;
+
+        let Ok(builder) = VirtualDevice::builder() else {
+            eprintln!("skipping: /dev/uinput unavailable");
+            return;
+        };
+        let mut virtual_keys = AttributeSet::<EvdevKey>::new();
+        virtual_keys.insert(EvdevKey::KEY_A);
+        let mut virt = builder
+            .name("cross-control grab test")
+            .with_keys(&virtual_keys)
+            .unwrap()
+            .build()
+            .unwrap();
+        let path = virt
+            .enumerate_dev_nodes_blocking()
+            .unwrap()
+            .find_map(Result::ok)
+            .unwrap();
+
+        let (tx, mut rx) = mpsc::channel(16);
+        let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+        let (mut grab, reader) = spawn_reader(&path, DeviceId(0), tx, shutdown_rx).unwrap();
+        grab.grab().unwrap();
+
+        let press = evdev::InputEvent::new(EventType::KEY.0, EvdevKey::KEY_A.code(), 1);
+        virt.emit(&[press]).unwrap();
+        let event = tokio::time::timeout(std::time::Duration::from_secs(2), rx.recv())
+            .await
+            .expect("no event while grabbed")
+            .unwrap();
+        assert!(matches!(
+            event.event,
+            InputEvent::Key {
+                code: KeyCode::KeyA,
+                ..
+            }
+        ));
+
+        grab.ungrab().unwrap();
+        let _ = shutdown_tx.send(true);
+        let _ = reader.await;
+    }
+
+    fn keys(keys: &[EvdevKey]) -> AttributeSet<EvdevKey> {
+        keys.iter().copied().collect()
+    }
+
+    fn axes(axes: &[RelativeAxisCode]) -> AttributeSet<RelativeAxisCode> {
+        axes.iter().copied().collect()
+    }
+
+    #[test]
+    fn combo_receiver_is_one_device_with_everything() {
+        let keys = keys(&[
+            EvdevKey::KEY_A,
+            EvdevKey::KEY_Z,
+            EvdevKey::KEY_ENTER,
+            EvdevKey::BTN_LEFT,
+            EvdevKey::BTN_RIGHT,
+        ]);
+        let rel = axes(&[
+            RelativeAxisCode::REL_X,
+            RelativeAxisCode::REL_Y,
+            RelativeAxisCode::REL_WHEEL,
+        ]);
+        let (capabilities, key_set) = classify(Some(&keys), Some(&rel), false);
+        assert_eq!(
+            capabilities,
+            vec![
+                DeviceCapability::Keyboard,
+                DeviceCapability::RelativeMouse,
+                DeviceCapability::Scroll,
+            ]
+        );
+        assert_eq!(key_set, vec![KeyCode::Enter, KeyCode::KeyA, KeyCode::KeyZ]);
+    }
+
+    #[test]
+    fn plain_devices() {
+        let keyboard = keys(&[EvdevKey::KEY_A, EvdevKey::KEY_Z, EvdevKey::KEY_ENTER]);
+        assert_eq!(
+            classify(Some(&keyboard), None, false).0,
+            vec![DeviceCapability::Keyboard]
+        );
+
+        let buttons = keys(&[EvdevKey::BTN_LEFT]);
+        let rel = axes(&[RelativeAxisCode::REL_X, RelativeAxisCode::REL_HWHEEL]);
+        assert_eq!(
+            classify(Some(&buttons), Some(&rel), false),
+            (
+                vec![DeviceCapability::RelativeMouse, DeviceCapability::Scroll],
+                Vec::new()
+            )
+        );
+
+        // Power buttons and media keys alone are not a keyboard.
+        let power = keys(&[EvdevKey::KEY_POWER]);
+        assert!(classify(Some(&power), None, false).0.is_empty());
+        let media = keys(&[EvdevKey::KEY_VOLUMEUP, EvdevKey::KEY_MUTE]);
+        assert!(classify(Some(&media), None, false).0.is_empty());
+        // Nor is a lone motion axis a mouse.
+        let rel = axes(&[RelativeAxisCode::REL_X]);
+        assert!(classify(None, Some(&rel), false).0.is_empty());
+    }
+
+    #[test]
+    fn forced_devices_count_any_key_as_a_keyboard() {
+        let media = keys(&[EvdevKey::KEY_VOLUMEUP, EvdevKey::KEY_MUTE]);
+        assert_eq!(
+            classify(Some(&media), None, true),
+            (
+                vec![DeviceCapability::Keyboard],
+                vec![KeyCode::Mute, KeyCode::VolumeUp]
+            )
+        );
+        // Mouse buttons still make a mouse, not a keyboard.
+        let buttons = keys(&[EvdevKey::BTN_LEFT]);
+        assert_eq!(
+            classify(Some(&buttons), None, true).0,
+            vec![DeviceCapability::RelativeMouse]
+        );
+    }
+
+    #[test]
+    fn only_uinput_devices_are_virtual() {
+        assert!(is_uinput_sys_path(Path::new(
+            "/sys/devices/virtual/input/input42/event7"
+        )));
+        // A Bluetooth LE keyboard, delivered through uhid.
+        assert!(!is_uinput_sys_path(Path::new(
+            "/sys/devices/virtual/misc/uhid/0005:046D:B35B.0004/input/input31/event12"
+        )));
+        assert!(!is_uinput_sys_path(Path::new(
+            "/sys/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.0/0003:046D:C52B.0001/input/input5/event4"
+        )));
+    }
+}
@@ -4,7 +4,7 @@ use std::collections::HashMap;
 
 use async_trait::async_trait;
 use cross_control_types::{
-    DeviceCapability, DeviceInfo, InputEvent, ScrollDirection, VirtualDeviceId,
+    DeviceCapability, DeviceInfo, InputEvent, KeyCode, ScrollDirection, VirtualDeviceId,
 };
 use evdev::uinput::VirtualDevice;
 use evdev::{AttributeSet, EventType, KeyCode as EvdevKey, RelativeAxisCode};
@@ -39,49 +39,16 @@ impl UinputEmulation {
             .map_err(|e| InputError::VirtualDeviceCreate(e.to_string()))?
             .name(&info.name);
 
-        for cap in &info.capabilities {
-            match cap {
-                DeviceCapability::Keyboard => {
-                    let mut keys = AttributeSet::<EvdevKey>::new();
-                    // Register all standard keys
-                    for code in 1..=248 {
-                        keys.insert(EvdevKey(code));
-                    }
-                    builder = builder
-                        .with_keys(&keys)
-                        .map_err(|e| InputError::VirtualDeviceCreate(e.to_string()))?;
-                }
-                DeviceCapability::RelativeMouse => {
-                    let mut keys = AttributeSet::<EvdevKey>::new();
-                    // Mouse buttons
-                    keys.insert(EvdevKey::BTN_LEFT);
-                    keys.insert(EvdevKey::BTN_RIGHT);
-                    keys.insert(EvdevKey::BTN_MIDDLE);
-                    keys.insert(EvdevKey::BTN_SIDE);
-                    keys.insert(EvdevKey::BTN_EXTRA);
-                    builder = builder
-                        .with_keys(&keys)
-                        .map_err(|e| InputError::VirtualDeviceCreate(e.to_string()))?;
-
-                    let mut rel = AttributeSet::<RelativeAxisCode>::new();
-                    rel.insert(RelativeAxisCode::REL_X);
-                    rel.insert(RelativeAxisCode::REL_Y);
-                    builder = builder
-                        .with_relative_axes(&rel)
-                        .map_err(|e| InputError::VirtualDeviceCreate(e.to_string()))?;
-                }
-                DeviceCapability::AbsoluteMouse => {
-                    // Not needed for MVP, relative mouse covers Linux
-                }
-                DeviceCapability::Scroll => {
-                    let mut rel = AttributeSet::<RelativeAxisCode>::new();
-                    rel.insert(RelativeAxisCode::REL_WHEEL);
-                    rel.insert(RelativeAxisCode::REL_HWHEEL);
-                    builder = builder
-                        .with_relative_axes(&rel)
-                        .map_err(|e| InputError::VirtualDeviceCreate(e.to_string()))?;
-                }
-            }
+        let (keys, rel) = virtual_capabilities(info);
+        if keys.iter().next().is_some() {
+            builder = builder
+                .with_keys(&keys)
+                .map_err(|e| InputError::VirtualDeviceCreate(e.to_string()))?;
+        }
+        if rel.iter().next().is_some() {
+            builder = builder
+                .with_relative_axes(&rel)
+                .map_err(|e| InputError::VirtualDeviceCreate(e.to_string()))?;
         }
 
         builder
@@ -92,6 +59,13 @@ impl UinputEmulation {
 
 #[async_trait]
 impl InputEmulation for UinputEmulation {
+    async fn probe(&mut self) -> Result<(), InputError> {
+        // Opening the builder opens /dev/uinput; nothing is created.
+        VirtualDevice::builder()
+            .map(|_| ())
+            .map_err(|e| InputError::VirtualDeviceCreate(format!("/dev/uinput unavailable: {e}")))
+    }
+
     async fn create_device(&mut self, info: &DeviceInfo) -> Result<VirtualDeviceId, InputError> {
         let device = Self::build_virtual_device(info)?;
         let id = VirtualDeviceId(self.next_id);
@@ -140,6 +114,63 @@ impl InputEmulation for UinputEmulation {
     }
 }
 
+/// Build the key set for a virtual keyboard from the keys announced by the
+/// capturing side. Falls back to all standard keys (1..=248) when the peer
+/// did not report a key set.
+fn virtual_key_set(keys: &[KeyCode]) -> AttributeSet<EvdevKey> {
+    let mut set = AttributeSet::<EvdevKey>::new();
+    if keys.is_empty() {
+        for code in 1..=248 {
+            set.insert(EvdevKey(code));
+        }
+    } else {
+        for code in keys {
+            set.insert(keymap::keycode_to_evdev_key(*code));
+        }
+    }
+    set
+}
+
+/// The keys and relative axes a virtual device for `info` needs, merged
+/// across all of its capabilities so that a keyboard-and-mouse combo becomes
+/// one device that can emit both.
+fn virtual_capabilities(
+    info: &DeviceInfo,
+) -> (AttributeSet<EvdevKey>, AttributeSet<RelativeAxisCode>) {
+    let mut keys = AttributeSet::<EvdevKey>::new();
+    let mut rel = AttributeSet::<RelativeAxisCode>::new();
+    for cap in &info.capabilities {
+        match cap {
+            DeviceCapability::Keyboard => {
+                for key in &virtual_key_set(&info.keys) {
+                    keys.insert(key);
+                }
+            }
+            DeviceCapability::RelativeMouse => {
+                for button in [
+                    EvdevKey::BTN_LEFT,
+                    EvdevKey::BTN_RIGHT,
+                    EvdevKey::BTN_MIDDLE,
+                    EvdevKey::BTN_SIDE,
+                    EvdevKey::BTN_EXTRA,
+                ] {
+                    keys.insert(button);
+                }
+                rel.insert(RelativeAxisCode::REL_X);
+                rel.insert(RelativeAxisCode::REL_Y);
+            }
+            DeviceCapability::AbsoluteMouse => {
+                // Not needed for MVP, relative mouse covers Linux
+            }
+            DeviceCapability::Scroll => {
+                rel.insert(RelativeAxisCode::REL_WHEEL);
+                rel.insert(RelativeAxisCode::REL_HWHEEL);
+            }
+        }
+    }
+    (keys, rel)
+}
+
 /// Convert our `InputEvent` to a list of evdev `InputEvent`s.
 fn input_event_to_evdev(event: &InputEvent) -> Vec<evdev::InputEvent> {
     match event {
@@ -182,3 +213,46 @@ fn input_event_to_evdev(event: &InputEvent) -> Vec<evdev::InputEvent> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn virtual_key_set_mirrors_announced_keys() {
+        let set = virtual_key_set(&[KeyCode::KeyA, KeyCode::Enter, KeyCode::Unknown(0x1d0)]);
+        assert_eq!(set.iter().count(), 3);
+        assert!(set.contains(EvdevKey::KEY_A));
+        assert!(set.contains(EvdevKey::KEY_ENTER));
+        assert!(set.contains(EvdevKey(0x1d0)));
+        assert!(!set.contains(EvdevKey::KEY_B));
+    }
+
+    #[test]
+    fn combo_device_gets_keys_buttons_and_axes() {
+        let info = DeviceInfo {
+            id: cross_control_types::DeviceId(3),
+            name: "Wireless Receiver".to_string(),
+            capabilities: vec![
+                DeviceCapability::Scroll,
+                DeviceCapability::RelativeMouse,
+                DeviceCapability::Keyboard,
+            ],
+            keys: vec![KeyCode::KeyA, KeyCode::Enter],
+        };
+        let (keys, rel) = virtual_capabilities(&info);
+        assert_eq!(keys.iter().count(), 7);
+        assert!(keys.contains(EvdevKey::KEY_A));
+        assert!(keys.contains(EvdevKey::BTN_LEFT));
+        assert_eq!(rel.iter().count(), 4);
+        assert!(rel.contains(RelativeAxisCode::REL_X));
+        assert!(rel.contains(RelativeAxisCode::REL_WHEEL));
+    }
+
+    #[test]
+    fn virtual_key_set_falls_back_to_standard_keys() {
+        let set = virtual_key_set(&[]);
+        assert_eq!(set.iter().count(), 248);
+        assert!(set.contains(EvdevKey::KEY_ESC));
+    }
+}
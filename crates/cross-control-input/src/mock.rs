@@ -11,7 +11,7 @@ use cross_control_types::{
 use tokio::sync::mpsc;
 
 use crate::error::InputError;
-use crate::{InputCapture, InputEmulation};
+use crate::{InputCapture, InputEmulation, ReleaseHandle};
 
 // ---------------------------------------------------------------------------
 // MockCapture
@@ -25,7 +25,9 @@ use crate::{InputCapture, InputEmulation};
 pub struct MockCapture {
     feed_rx: Option<mpsc::Receiver<CapturedEvent>>,
     barriers: Arc<Mutex<HashMap<BarrierId, Barrier>>>,
+    grabbed: Arc<AtomicBool>,
     released: Arc<AtomicBool>,
+    failing_grabs: Arc<AtomicU32>,
     next_barrier: AtomicU32,
     shutdown: Arc<AtomicBool>,
 }
@@ -37,7 +39,9 @@ impl MockCapture {
         let capture = Self {
             feed_rx: Some(feed_rx),
             barriers: Arc::new(Mutex::new(HashMap::new())),
+            grabbed: Arc::new(AtomicBool::new(false)),
             released: Arc::new(AtomicBool::new(false)),
+            failing_grabs: Arc::new(AtomicU32::new(0)),
             next_barrier: AtomicU32::new(1),
             shutdown: Arc::new(AtomicBool::new(false)),
         };
@@ -48,6 +52,42 @@ impl MockCapture {
     pub fn was_released(&self) -> bool {
         self.released.load(Ordering::SeqCst)
     }
+
+    /// Get a handle for observing the capture after it moves into a daemon.
+    pub fn handle(&self) -> MockCaptureHandle {
+        MockCaptureHandle {
+            grabbed: Arc::clone(&self.grabbed),
+            released: Arc::clone(&self.released),
+            failing_grabs: Arc::clone(&self.failing_grabs),
+        }
+    }
+}
+
+/// Clonable observer handle for `MockCapture`.
+#[derive(Clone)]
+pub struct MockCaptureHandle {
+    grabbed: Arc<AtomicBool>,
+    released: Arc<AtomicBool>,
+    failing_grabs: Arc<AtomicU32>,
+}
+
+impl MockCaptureHandle {
+    /// Check if devices are grabbed: `grab()` was called and `release()`
+    /// hasn't been since.
+    pub fn is_grabbed(&self) -> bool {
+        self.grabbed.load(Ordering::SeqCst)
+    }
+
+    /// Check if `release()` was called.
+    pub fn was_released(&self) -> bool {
+        self.released.load(Ordering::SeqCst)
+    }
+
+    /// Make the next `count` calls to `grab()` fail, as when another
+    /// program holds one of the devices.
+    pub fn fail_grabs(&self, count: u32) {
+        self.failing_grabs.store(count, Ordering::SeqCst);
+    }
 }
 
 #[async_trait]
@@ -88,11 +128,32 @@ impl InputCapture for MockCapture {
         Ok(())
     }
 
+    async fn grab(&mut self) -> Result<(), InputError> {
+        let failing = self
+            .failing_grabs
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+        if failing.is_ok() {
+            return Err(InputError::DeviceGrab("mock grab failure".to_string()));
+        }
+        self.grabbed.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
     async fn release(&mut self) -> Result<(), InputError> {
+        self.grabbed.store(false, Ordering::SeqCst);
         self.released.store(true, Ordering::SeqCst);
         Ok(())
     }
 
+    fn release_handle(&self) -> Option<ReleaseHandle> {
+        let grabbed = Arc::clone(&self.grabbed);
+        let released = Arc::clone(&self.released);
+        Some(Arc::new(move || {
+            grabbed.store(false, Ordering::SeqCst);
+            released.store(true, Ordering::SeqCst);
+        }))
+    }
+
     async fn shutdown(&mut self) -> Result<(), InputError> {
         self.shutdown.store(true, Ordering::SeqCst);
         Ok(())
@@ -117,6 +178,8 @@ struct MockEmulationState {
     injected: Vec<InjectedEvent>,
     next_id: u32,
     shutdown: bool,
+    unavailable: bool,
+    failing_injects: bool,
 }
 
 /// Mock input emulation backend for testing.
@@ -138,6 +201,14 @@ impl MockEmulation {
         }
     }
 
+    /// Create a mock backend that behaves as if virtual devices cannot be
+    /// created (e.g. no `/dev/uinput`).
+    pub fn unavailable() -> Self {
+        let emulation = Self::new();
+        emulation.state.lock().unwrap().unavailable = true;
+        emulation
+    }
+
     /// Get a clonable handle for observing the emulation state from tests.
     pub fn handle(&self) -> MockEmulationHandle {
         MockEmulationHandle {
@@ -165,16 +236,37 @@ impl MockEmulationHandle {
         self.state.lock().unwrap().injected.clone()
     }
 
+    /// Take all injected events recorded so far, clearing the record.
+    pub fn take_injected_events(&self) -> Vec<InjectedEvent> {
+        std::mem::take(&mut self.state.lock().unwrap().injected)
+    }
+
     /// Check if shutdown was called.
     pub fn is_shutdown(&self) -> bool {
         self.state.lock().unwrap().shutdown
     }
+
+    /// Make `inject` fail while `fail` is set, as when a virtual device has
+    /// vanished from under the backend.
+    pub fn fail_injects(&self, fail: bool) {
+        self.state.lock().unwrap().failing_injects = fail;
+    }
 }
 
 #[async_trait]
 impl InputEmulation for MockEmulation {
+    async fn probe(&mut self) -> Result<(), InputError> {
+        if self.state.lock().unwrap().unavailable {
+            return Err(InputError::Unavailable);
+        }
+        Ok(())
+    }
+
     async fn create_device(&mut self, info: &DeviceInfo) -> Result<VirtualDeviceId, InputError> {
         let mut state = self.state.lock().unwrap();
+        if state.unavailable {
+            return Err(InputError::Unavailable);
+        }
         state.next_id += 1;
         let id = VirtualDeviceId(state.next_id);
         state.devices.insert(id, info.clone());
@@ -187,6 +279,9 @@ impl InputEmulation for MockEmulation {
         event: InputEvent,
     ) -> Result<(), InputError> {
         let mut state = self.state.lock().unwrap();
+        if state.failing_injects {
+            return Err(InputError::Inject("mock inject failure".to_string()));
+        }
         state.injected.push(InjectedEvent { device, event });
         Ok(())
     }
@@ -0,0 +1,158 @@
+//! Source-address filtering for inbound connections.
+
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+use serde::{Deserialize, Serialize};
+
+/// An address range in CIDR notation, e.g. `192.168.1.0/24`. A bare
+/// address is a single-host range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Subnet {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Subnet {
+    /// Whether `addr` falls inside this range. IPv4-mapped IPv6 addresses,
+    /// as seen on dual-stack sockets, match IPv4 ranges.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, addr.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(addr)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(net) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(addr)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u128::from(net) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Subnet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr
+            .parse()
+            .map_err(|_| format!("invalid subnet {s:?}: bad address"))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|&p| p <= max)
+                .ok_or_else(|| format!("invalid subnet {s:?}: prefix must be 0-{max}"))?,
+            None => max,
+        };
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl TryFrom<String> for Subnet {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Subnet> for String {
+    fn from(subnet: Subnet) -> Self {
+        subnet.to_string()
+    }
+}
+
+impl fmt::Display for Subnet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// Subnets inbound connections may come from.
+///
+/// Checked against the source address before the transport answers a
+/// connection attempt, so connections from elsewhere never reach the TLS
+/// handshake. Empty (the default) admits every address. Outbound
+/// connections are not affected.
+#[derive(Debug, Clone, Default)]
+pub struct Allowlist(Arc<RwLock<Vec<Subnet>>>);
+
+impl Allowlist {
+    /// Replace the allowed subnets.
+    pub fn set(&self, subnets: Vec<Subnet>) {
+        *self
+            .0
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) = subnets;
+    }
+
+    /// Whether a connection from `addr` may proceed.
+    pub fn permits(&self, addr: IpAddr) -> bool {
+        let subnets = self
+            .0
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        subnets.is_empty() || subnets.iter().any(|subnet| subnet.contains(addr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn subnet_matches_by_prefix() {
+        let lan: Subnet = "192.168.1.0/24".parse().unwrap();
+        assert!(lan.contains(ip("192.168.1.77")));
+        assert!(!lan.contains(ip("192.168.2.1")));
+        assert!(lan.contains(ip("::ffff:192.168.1.5")));
+
+        let host: Subnet = "10.8.0.1".parse().unwrap();
+        assert_eq!(host.to_string(), "10.8.0.1/32");
+        assert!(host.contains(ip("10.8.0.1")));
+        assert!(!host.contains(ip("10.8.0.2")));
+
+        let any: Subnet = "0.0.0.0/0".parse().unwrap();
+        assert!(any.contains(ip("203.0.113.9")));
+        assert!(!any.contains(ip("2001:db8::1")));
+
+        let v6: Subnet = "fd00::/8".parse().unwrap();
+        assert!(v6.contains(ip("fd12::1")));
+        assert!(!v6.contains(ip("fe80::1")));
+    }
+
+    #[test]
+    fn invalid_subnets_are_rejected() {
+        assert!("192.168.1.0/33".parse::<Subnet>().is_err());
+        assert!("lan/24".parse::<Subnet>().is_err());
+        assert!("192.168.1.0/".parse::<Subnet>().is_err());
+    }
+
+    #[test]
+    fn empty_allowlist_permits_everything() {
+        let allowlist = Allowlist::default();
+        assert!(allowlist.permits(ip("203.0.113.9")));
+
+        allowlist.set(vec!["192.168.1.0/24".parse().unwrap()]);
+        assert!(allowlist.permits(ip("192.168.1.20")));
+        assert!(!allowlist.permits(ip("203.0.113.9")));
+    }
+}
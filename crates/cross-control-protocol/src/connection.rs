@@ -1,23 +1,145 @@
-//! QUIC connection and stream framing.
+//! Peer connections and stream framing.
+//!
+//! [`Connection`] is the boundary between the daemon and a concrete
+//! transport: anything that can hand out ordered byte streams to a peer and
+//! export keying material from its session can carry cross-control. QUIC
+//! (`quinn::Connection`) is the implementation the daemon ships with.
 
+use std::io::ErrorKind;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 
+use async_trait::async_trait;
 use bincode::{Decode, Encode};
-use quinn::{Connection, RecvStream, SendStream};
-use tracing::trace;
+use cross_control_types::PROTOCOL_VERSION;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::{debug, trace, trace_span, Instrument};
 
 use crate::error::ProtocolError;
-use crate::wire::MAX_MESSAGE_SIZE;
+use crate::wire::{decode_message, decode_tagged, Tagged, MAX_MESSAGE_SIZE, TAG_SIZE};
+
+/// Sending half of a stream handed out by a [`Connection`].
+pub type SendHalf = Box<dyn AsyncWrite + Send + Unpin>;
+
+/// Receiving half of a stream handed out by a [`Connection`].
+pub type RecvHalf = Box<dyn AsyncRead + Send + Unpin>;
+
+/// An authenticated, encrypted connection to one peer.
+///
+/// Bidirectional streams carry control messages and unidirectional streams
+/// carry input; each must deliver bytes in order. Implementations must
+/// authenticate the peer before the connection is handed out.
+#[async_trait]
+pub trait Connection: Send + Sync + 'static {
+    /// Remote address of the peer.
+    fn remote_address(&self) -> SocketAddr;
+
+    /// SHA-256 fingerprint of the certificate the peer presented, if any.
+    fn peer_fingerprint(&self) -> Option<String>;
+
+    /// Open a bidirectional stream.
+    async fn open_bi(&self) -> Result<(SendHalf, RecvHalf), ProtocolError>;
+
+    /// Accept a bidirectional stream opened by the peer.
+    async fn accept_bi(&self) -> Result<(SendHalf, RecvHalf), ProtocolError>;
+
+    /// Open a unidirectional stream.
+    async fn open_uni(&self) -> Result<SendHalf, ProtocolError>;
+
+    /// Accept a unidirectional stream opened by the peer.
+    async fn accept_uni(&self) -> Result<RecvHalf, ProtocolError>;
+
+    /// Fill `output` with keying material derived from the session secret.
+    /// Both ends must derive the same bytes for the same label and context.
+    fn export_keying_material(
+        &self,
+        output: &mut [u8],
+        label: &[u8],
+        context: &[u8],
+    ) -> Result<(), ProtocolError>;
+
+    /// Close the connection gracefully.
+    fn close(&self);
+
+    /// Wait until either side closes the connection.
+    async fn closed(&self);
+}
+
+#[async_trait]
+impl Connection for quinn::Connection {
+    fn remote_address(&self) -> SocketAddr {
+        quinn::Connection::remote_address(self)
+    }
+
+    fn peer_fingerprint(&self) -> Option<String> {
+        let certs = self
+            .peer_identity()?
+            .downcast::<Vec<rustls::pki_types::CertificateDer<'static>>>()
+            .ok()?;
+        certs
+            .first()
+            .map(|cert| cross_control_certgen::fingerprint_from_der(cert))
+    }
+
+    async fn open_bi(&self) -> Result<(SendHalf, RecvHalf), ProtocolError> {
+        let (send, recv) = quinn::Connection::open_bi(self)
+            .await
+            .map_err(|e| ProtocolError::Connection(e.to_string()))?;
+        Ok((Box::new(send), Box::new(recv)))
+    }
+
+    async fn accept_bi(&self) -> Result<(SendHalf, RecvHalf), ProtocolError> {
+        let (send, recv) = quinn::Connection::accept_bi(self)
+            .await
+            .map_err(|e| ProtocolError::Connection(e.to_string()))?;
+        Ok((Box::new(send), Box::new(recv)))
+    }
+
+    async fn open_uni(&self) -> Result<SendHalf, ProtocolError> {
+        let send = quinn::Connection::open_uni(self)
+            .await
+            .map_err(|e| ProtocolError::Connection(e.to_string()))?;
+        Ok(Box::new(send))
+    }
+
+    async fn accept_uni(&self) -> Result<RecvHalf, ProtocolError> {
+        let recv = quinn::Connection::accept_uni(self)
+            .await
+            .map_err(|e| ProtocolError::Connection(e.to_string()))?;
+        Ok(Box::new(recv))
+    }
+
+    fn export_keying_material(
+        &self,
+        output: &mut [u8],
+        label: &[u8],
+        context: &[u8],
+    ) -> Result<(), ProtocolError> {
+        quinn::Connection::export_keying_material(self, output, label, context)
+            .map_err(|_| ProtocolError::Tls("keying material export failed".to_string()))
+    }
+
+    fn close(&self) {
+        quinn::Connection::close(self, quinn::VarInt::from_u32(0), b"bye");
+    }
+
+    async fn closed(&self) {
+        quinn::Connection::closed(self).await;
+    }
+}
 
 /// A connection to a remote cross-control peer.
 #[derive(Clone)]
 pub struct PeerConnection {
-    connection: Connection,
+    connection: Arc<dyn Connection>,
 }
 
 impl PeerConnection {
-    pub fn new(connection: Connection) -> Self {
-        Self { connection }
+    pub fn new(connection: impl Connection) -> Self {
+        Self {
+            connection: Arc::new(connection),
+        }
     }
 
     /// Get the remote address of this connection.
@@ -25,15 +147,17 @@ impl PeerConnection {
         self.connection.remote_address()
     }
 
-    /// Open a bidirectional stream (for control messages).
+    /// SHA-256 fingerprint of the certificate the peer presented, if any.
+    pub fn peer_fingerprint(&self) -> Option<String> {
+        self.connection.peer_fingerprint()
+    }
+
+    /// Open a bidirectional stream (for control messages, sent and received
+    /// as [`Tagged`] frames).
     pub async fn open_control_stream(
         &self,
     ) -> Result<(MessageSender, MessageReceiver), ProtocolError> {
-        let (send, recv) = self
-            .connection
-            .open_bi()
-            .await
-            .map_err(|e| ProtocolError::Connection(e.to_string()))?;
+        let (send, recv) = self.connection.open_bi().await?;
         Ok((MessageSender::new(send), MessageReceiver::new(recv)))
     }
 
@@ -41,133 +165,247 @@ impl PeerConnection {
     pub async fn accept_control_stream(
         &self,
     ) -> Result<(MessageSender, MessageReceiver), ProtocolError> {
-        let (send, recv) = self
-            .connection
-            .accept_bi()
-            .await
-            .map_err(|e| ProtocolError::Connection(e.to_string()))?;
+        let (send, recv) = self.connection.accept_bi().await?;
         Ok((MessageSender::new(send), MessageReceiver::new(recv)))
     }
 
     /// Open a unidirectional stream (for input events, controller -> controlled).
     pub async fn open_input_stream(&self) -> Result<MessageSender, ProtocolError> {
-        let send = self
-            .connection
-            .open_uni()
-            .await
-            .map_err(|e| ProtocolError::Connection(e.to_string()))?;
+        let send = self.connection.open_uni().await?;
         Ok(MessageSender::new(send))
     }
 
     /// Accept a unidirectional stream (for input events, controller -> controlled).
     pub async fn accept_input_stream(&self) -> Result<MessageReceiver, ProtocolError> {
-        let recv = self
-            .connection
-            .accept_uni()
-            .await
-            .map_err(|e| ProtocolError::Connection(e.to_string()))?;
+        let recv = self.connection.accept_uni().await?;
         Ok(MessageReceiver::new(recv))
     }
 
+    /// Derive `len` bytes of keying material from the TLS session (RFC 5705).
+    ///
+    /// Both ends of a connection derive the same value for the same label and
+    /// context, so it can be compared out of band to detect interception.
+    pub fn export_keying_material(
+        &self,
+        label: &[u8],
+        context: &[u8],
+        len: usize,
+    ) -> Result<Vec<u8>, ProtocolError> {
+        let mut output = vec![0u8; len];
+        self.connection
+            .export_keying_material(&mut output, label, context)?;
+        Ok(output)
+    }
+
     /// Close the connection gracefully.
     pub fn close(&self) {
-        self.connection.close(quinn::VarInt::from_u32(0), b"bye");
+        self.connection.close();
+    }
+
+    /// Wait until either side closes the connection.
+    pub async fn closed(&self) {
+        self.connection.closed().await;
     }
 }
 
-/// Sends length-prefixed bincode messages over a QUIC send stream.
+/// Sends length-prefixed bincode messages over a stream.
 pub struct MessageSender {
-    stream: SendStream,
+    stream: SendHalf,
+    max_size: u32,
+    counter: Option<Arc<AtomicU64>>,
 }
 
 impl MessageSender {
-    fn new(stream: SendStream) -> Self {
-        Self { stream }
+    /// Frame messages over a raw stream, e.g. one from a custom [`Connection`].
+    pub fn new(stream: SendHalf) -> Self {
+        Self {
+            stream,
+            max_size: MAX_MESSAGE_SIZE,
+            counter: None,
+        }
+    }
+
+    /// Largest frame this sender will write, in bytes.
+    pub fn max_size(&self) -> u32 {
+        self.max_size
+    }
+
+    /// Refuse to send frames larger than `max_size` bytes, e.g. the limit
+    /// the peer announced.
+    pub fn set_max_size(&mut self, max_size: u32) {
+        self.max_size = max_size;
+    }
+
+    /// Add the size of every frame sent from now on, length prefix
+    /// included, to `counter`. Several streams may share one counter.
+    pub fn count_bytes(&mut self, counter: Arc<AtomicU64>) {
+        self.counter = Some(counter);
     }
 
     /// Send a message, encoding it as length-prefixed bincode.
     pub async fn send<T: Encode>(&mut self, msg: &T) -> Result<(), ProtocolError> {
-        let config = bincode::config::standard();
-        let payload = bincode::encode_to_vec(msg, config)
-            .map_err(|e| ProtocolError::Serialization(e.to_string()))?;
+        let payload = encode_payload(msg)?;
+        self.write_frame(None, &payload).await
+    }
 
-        let len = u32::try_from(payload.len())
+    /// Send a message as a tagged frame (see [`Tagged`]). Control messages
+    /// are always sent this way.
+    pub async fn send_tagged<T: Tagged>(&mut self, msg: &T) -> Result<(), ProtocolError> {
+        let payload = encode_payload(msg)?;
+        self.write_frame(Some(msg.tag()), &payload).await
+    }
+
+    async fn write_frame(&mut self, tag: Option<u16>, payload: &[u8]) -> Result<(), ProtocolError> {
+        let tag_len = if tag.is_some() { TAG_SIZE } else { 0 };
+        let len = u32::try_from(tag_len + payload.len())
             .map_err(|_| ProtocolError::Serialization("message too large".to_string()))?;
 
-        if len > MAX_MESSAGE_SIZE {
+        if len > self.max_size {
             return Err(ProtocolError::Serialization(format!(
-                "message size {len} exceeds maximum {MAX_MESSAGE_SIZE}"
+                "message size {len} exceeds maximum {}",
+                self.max_size
             )));
         }
 
-        self.stream
-            .write_all(&len.to_be_bytes())
-            .await
-            .map_err(|e| ProtocolError::Connection(e.to_string()))?;
-        self.stream
-            .write_all(&payload)
-            .await
-            .map_err(|e| ProtocolError::Connection(e.to_string()))?;
+        async {
+            self.stream.write_all(&len.to_be_bytes()).await?;
+            if let Some(tag) = tag {
+                self.stream.write_all(&tag.to_be_bytes()).await?;
+            }
+            self.stream.write_all(payload).await
+        }
+        .instrument(trace_span!("write", len))
+        .await
+        .map_err(|e| ProtocolError::Connection(e.to_string()))?;
 
-        trace!(len, "sent message");
+        if let Some(counter) = &self.counter {
+            counter.fetch_add(u64::from(len) + 4, Ordering::Relaxed);
+        }
+        trace!(len, ?tag, "sent message");
         Ok(())
     }
 
     /// Finish the stream (signal no more data).
-    pub fn finish(mut self) -> Result<(), ProtocolError> {
+    pub async fn finish(mut self) -> Result<(), ProtocolError> {
         self.stream
-            .finish()
+            .shutdown()
+            .await
             .map_err(|e| ProtocolError::Connection(e.to_string()))
     }
 }
 
-/// Receives length-prefixed bincode messages from a QUIC recv stream.
+fn encode_payload<T: Encode>(msg: &T) -> Result<Vec<u8>, ProtocolError> {
+    let _span = trace_span!("encode").entered();
+    let config = bincode::config::standard();
+    bincode::encode_to_vec(msg, config).map_err(|e| ProtocolError::Serialization(e.to_string()))
+}
+
+/// Receives length-prefixed bincode messages from a stream.
 pub struct MessageReceiver {
-    stream: RecvStream,
+    stream: RecvHalf,
+    max_size: u32,
+    counter: Option<Arc<AtomicU64>>,
 }
 
 impl MessageReceiver {
-    fn new(stream: RecvStream) -> Self {
-        Self { stream }
+    /// Frame messages over a raw stream, e.g. one from a custom [`Connection`].
+    pub fn new(stream: RecvHalf) -> Self {
+        Self {
+            stream,
+            max_size: MAX_MESSAGE_SIZE,
+            counter: None,
+        }
+    }
+
+    /// Largest frame this receiver accepts, in bytes.
+    pub fn max_size(&self) -> u32 {
+        self.max_size
+    }
+
+    /// Reject frames larger than `max_size` bytes.
+    pub fn set_max_size(&mut self, max_size: u32) {
+        self.max_size = max_size;
+    }
+
+    /// Add the size of every frame received from now on, length prefix
+    /// included, to `counter`. Several streams may share one counter.
+    pub fn count_bytes(&mut self, counter: Arc<AtomicU64>) {
+        self.counter = Some(counter);
     }
 
     /// Receive and decode a message.
     ///
     /// Returns `None` if the stream has been cleanly closed by the peer.
     pub async fn recv<T: Decode<()>>(&mut self) -> Result<Option<T>, ProtocolError> {
+        let Some(payload) = self.read_frame().await? else {
+            return Ok(None);
+        };
+
+        let _span = trace_span!("decode", len = payload.len()).entered();
+        let msg = decode_message(&payload)?;
+        trace!(len = payload.len(), "received message");
+        Ok(Some(msg))
+    }
+
+    /// Receive and decode a tagged message (see [`Tagged`]), skipping any
+    /// frames whose tag this build does not know. An untagged handshake
+    /// message from a peer that predates tagging is a `VersionMismatch`.
+    ///
+    /// Returns `None` if the stream has been cleanly closed by the peer.
+    pub async fn recv_tagged<T: Tagged>(&mut self) -> Result<Option<T>, ProtocolError> {
+        loop {
+            let Some(frame) = self.read_frame().await? else {
+                return Ok(None);
+            };
+
+            let _span = trace_span!("decode", len = frame.len()).entered();
+            let decoded = decode_tagged(&frame);
+            if !matches!(decoded, Ok(Some(_))) {
+                if let Some(remote) = T::legacy_version(&frame) {
+                    return Err(ProtocolError::VersionMismatch {
+                        remote: remote.to_string(),
+                        local: PROTOCOL_VERSION.to_string(),
+                    });
+                }
+            }
+            if let Some((tag, msg)) = decoded? {
+                trace!(len = frame.len(), tag, "received message");
+                return Ok(Some(msg));
+            }
+            let tag = u16::from_be_bytes([frame[0], frame[1]]);
+            debug!(len = frame.len(), tag, "skipped message with unknown tag");
+        }
+    }
+
+    /// Read one length-prefixed frame. `None` on a clean end of stream.
+    async fn read_frame(&mut self) -> Result<Option<Vec<u8>>, ProtocolError> {
         // Read 4-byte length prefix
         let mut len_buf = [0u8; 4];
         match self.stream.read_exact(&mut len_buf).await {
-            Ok(()) => {}
-            Err(quinn::ReadExactError::FinishedEarly(_)) => return Ok(None),
-            Err(quinn::ReadExactError::ReadError(e)) => {
-                return Err(ProtocolError::Connection(e.to_string()));
-            }
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(ProtocolError::Connection(e.to_string())),
         }
 
         let len = u32::from_be_bytes(len_buf);
-        if len > MAX_MESSAGE_SIZE {
+        if len > self.max_size {
             return Err(ProtocolError::Deserialization(format!(
-                "message size {len} exceeds maximum {MAX_MESSAGE_SIZE}"
+                "message size {len} exceeds maximum {}",
+                self.max_size
             )));
         }
 
         let mut payload = vec![0u8; len as usize];
         match self.stream.read_exact(&mut payload).await {
-            Ok(()) => {}
-            Err(quinn::ReadExactError::FinishedEarly(_)) => {
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
                 return Err(ProtocolError::StreamClosed);
             }
-            Err(quinn::ReadExactError::ReadError(e)) => {
-                return Err(ProtocolError::Connection(e.to_string()));
-            }
+            Err(e) => return Err(ProtocolError::Connection(e.to_string())),
         }
-
-        let config = bincode::config::standard();
-        let (msg, _) = bincode::decode_from_slice(&payload, config)
-            .map_err(|e| ProtocolError::Deserialization(e.to_string()))?;
-
-        trace!(len, "received message");
-        Ok(Some(msg))
+        if let Some(counter) = &self.counter {
+            counter.fetch_add(u64::from(len) + 4, Ordering::Relaxed);
+        }
+        Ok(Some(payload))
     }
 }
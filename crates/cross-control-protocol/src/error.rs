@@ -1,5 +1,6 @@
 //! Protocol and transport errors.
 
+use cross_control_types::ErrorCode;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -19,6 +20,9 @@ pub enum ProtocolError {
     #[error("deserialisation error: {0}")]
     Deserialization(String),
 
+    #[error("peer reported {code}: {detail}")]
+    Remote { code: ErrorCode, detail: String },
+
     #[error("stream closed unexpectedly")]
     StreamClosed,
 
@@ -31,3 +35,20 @@ pub enum ProtocolError {
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
+
+impl ProtocolError {
+    /// Machine-readable cause. For [`ProtocolError::Remote`] this is the
+    /// code the peer sent.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Connection(_) | Self::Quinn(_) => ErrorCode::Connection,
+            Self::Handshake(_) => ErrorCode::Handshake,
+            Self::VersionMismatch { .. } => ErrorCode::VersionMismatch,
+            Self::Serialization(_) | Self::Deserialization(_) => ErrorCode::Serialization,
+            Self::StreamClosed => ErrorCode::StreamClosed,
+            Self::Tls(_) => ErrorCode::Tls,
+            Self::Remote { code, .. } => *code,
+            Self::Other(_) => ErrorCode::Internal,
+        }
+    }
+}
@@ -2,14 +2,22 @@
 //!
 //! This crate handles QUIC connection management (via quinn), message
 //! serialisation/deserialisation (via bincode v2), and the protocol state
-//! machine for handshake and stream management.
+//! machine for handshake and stream management. The daemon reaches peers
+//! through the [`Transport`] and [`Connection`] traits, with
+//! [`QuicTransport`] as the shipped implementation.
 
+pub mod allowlist;
 pub mod connection;
 pub mod error;
 pub mod tls;
 pub mod transport;
 pub mod wire;
 
-pub use connection::{MessageReceiver, MessageSender, PeerConnection};
+pub use allowlist::{Allowlist, Subnet};
+pub use connection::{
+    Connection, MessageReceiver, MessageSender, PeerConnection, RecvHalf, SendHalf,
+};
 pub use error::ProtocolError;
-pub use transport::QuicTransport;
+pub use tls::Denylist;
+pub use transport::{QuicTransport, Transport};
+pub use wire::Tagged;
@@ -1,19 +1,67 @@
 //! TLS configuration for QUIC connections.
 
-use std::sync::Arc;
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
 
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::WebPkiSupportedAlgorithms;
 use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
-use tracing::debug;
+use rustls::server::danger::{ClientCertVerified, ClientCertVerifier};
+use rustls::{DigitallySignedStruct, DistinguishedName, SignatureScheme};
+use tracing::{debug, warn};
 
 use crate::error::ProtocolError;
 
+/// Certificate fingerprints refused during the TLS handshake.
+///
+/// Shared between the server and client configs of a transport, so entries
+/// added at runtime apply to every subsequent handshake in both directions.
+#[derive(Debug, Clone, Default)]
+pub struct Denylist(Arc<RwLock<HashSet<String>>>);
+
+impl Denylist {
+    /// Refuse a fingerprint. Returns `false` if it was already denied.
+    pub fn insert(&self, fingerprint: String) -> bool {
+        self.0
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .insert(fingerprint)
+    }
+
+    /// Whether a fingerprint is refused.
+    pub fn contains(&self, fingerprint: &str) -> bool {
+        self.0
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .contains(fingerprint)
+    }
+
+    /// Reject `cert` if its fingerprint is denied.
+    fn check(&self, cert: &CertificateDer<'_>) -> Result<(), rustls::Error> {
+        let fingerprint = cross_control_certgen::fingerprint_from_der(cert);
+        if self.contains(&fingerprint) {
+            warn!(fingerprint = %fingerprint, "refused denylisted certificate");
+            return Err(rustls::Error::General(format!(
+                "certificate {fingerprint} is denylisted"
+            )));
+        }
+        Ok(())
+    }
+}
+
 /// Build a quinn `ServerConfig` from PEM-encoded cert and key.
-pub fn server_config(cert_pem: &str, key_pem: &str) -> Result<quinn::ServerConfig, ProtocolError> {
+///
+/// Clients must present a certificate so that denylisted peers can be refused.
+pub fn server_config(
+    cert_pem: &str,
+    key_pem: &str,
+    denylist: Denylist,
+) -> Result<quinn::ServerConfig, ProtocolError> {
     let certs = parse_certs(cert_pem)?;
     let key = parse_key(key_pem)?;
 
     let mut tls_config = rustls::ServerConfig::builder()
-        .with_no_client_auth()
+        .with_client_cert_verifier(Arc::new(DenylistVerifier::new(denylist)))
         .with_single_cert(certs, key)
         .map_err(|e| ProtocolError::Tls(e.to_string()))?;
 
@@ -27,14 +75,23 @@ pub fn server_config(cert_pem: &str, key_pem: &str) -> Result<quinn::ServerConfi
     Ok(config)
 }
 
-/// Build a quinn `ClientConfig` that skips certificate verification (MVP).
+/// Build a quinn `ClientConfig` that presents our certificate and accepts any
+/// server certificate not on the denylist (MVP).
 ///
 /// In Phase 2 this will be replaced with fingerprint-pinning verification.
-pub fn client_config_skip_verification() -> Result<quinn::ClientConfig, ProtocolError> {
+pub fn client_config(
+    cert_pem: &str,
+    key_pem: &str,
+    denylist: Denylist,
+) -> Result<quinn::ClientConfig, ProtocolError> {
+    let certs = parse_certs(cert_pem)?;
+    let key = parse_key(key_pem)?;
+
     let mut tls_config = rustls::ClientConfig::builder()
         .dangerous()
-        .with_custom_certificate_verifier(Arc::new(SkipServerVerification))
-        .with_no_client_auth();
+        .with_custom_certificate_verifier(Arc::new(DenylistVerifier::new(denylist)))
+        .with_client_auth_cert(certs, key)
+        .map_err(|e| ProtocolError::Tls(e.to_string()))?;
 
     tls_config.alpn_protocols = vec![b"cross-control/0.1".to_vec()];
 
@@ -42,7 +99,7 @@ pub fn client_config_skip_verification() -> Result<quinn::ClientConfig, Protocol
         quinn::crypto::rustls::QuicClientConfig::try_from(tls_config)
             .map_err(|e| ProtocolError::Tls(e.to_string()))?,
     ));
-    debug!("built client TLS config (skip verification)");
+    debug!("built client TLS config (denylist verification)");
     Ok(config)
 }
 
@@ -66,51 +123,94 @@ fn parse_key(pem: &str) -> Result<PrivateKeyDer<'static>, ProtocolError> {
         .ok_or_else(|| ProtocolError::Tls("no private key found in PEM".to_string()))
 }
 
-/// Certificate verifier that accepts all server certificates (MVP only).
+/// Certificate verifier that accepts any self-signed peer certificate unless
+/// its fingerprint is denylisted (MVP only). Handshake signatures are still
+/// checked so a peer must hold the private key for the certificate it shows.
 #[derive(Debug)]
-struct SkipServerVerification;
+struct DenylistVerifier {
+    denylist: Denylist,
+    algorithms: WebPkiSupportedAlgorithms,
+}
 
-impl rustls::client::danger::ServerCertVerifier for SkipServerVerification {
+impl DenylistVerifier {
+    fn new(denylist: Denylist) -> Self {
+        Self {
+            denylist,
+            algorithms: rustls::crypto::ring::default_provider().signature_verification_algorithms,
+        }
+    }
+}
+
+impl ServerCertVerifier for DenylistVerifier {
     fn verify_server_cert(
         &self,
-        _end_entity: &CertificateDer<'_>,
+        end_entity: &CertificateDer<'_>,
         _intermediates: &[CertificateDer<'_>],
         _server_name: &ServerName<'_>,
         _ocsp_response: &[u8],
         _now: UnixTime,
-    ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
-        Ok(rustls::client::danger::ServerCertVerified::assertion())
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        self.denylist.check(end_entity)?;
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+impl ClientCertVerifier for DenylistVerifier {
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _now: UnixTime,
+    ) -> Result<ClientCertVerified, rustls::Error> {
+        self.denylist.check(end_entity)?;
+        Ok(ClientCertVerified::assertion())
     }
 
     fn verify_tls12_signature(
         &self,
-        _message: &[u8],
-        _cert: &CertificateDer<'_>,
-        _dss: &rustls::DigitallySignedStruct,
-    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
     }
 
     fn verify_tls13_signature(
         &self,
-        _message: &[u8],
-        _cert: &CertificateDer<'_>,
-        _dss: &rustls::DigitallySignedStruct,
-    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
-        Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
     }
 
-    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
-        vec![
-            rustls::SignatureScheme::ECDSA_NISTP256_SHA256,
-            rustls::SignatureScheme::ECDSA_NISTP384_SHA384,
-            rustls::SignatureScheme::ED25519,
-            rustls::SignatureScheme::RSA_PSS_SHA256,
-            rustls::SignatureScheme::RSA_PSS_SHA384,
-            rustls::SignatureScheme::RSA_PSS_SHA512,
-            rustls::SignatureScheme::RSA_PKCS1_SHA256,
-            rustls::SignatureScheme::RSA_PKCS1_SHA384,
-            rustls::SignatureScheme::RSA_PKCS1_SHA512,
-        ]
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
     }
 }
@@ -1,13 +1,50 @@
-//! QUIC transport: bind, accept, and connect.
+//! Transports: bind, accept, and connect.
 
 use std::net::SocketAddr;
 
+use async_trait::async_trait;
 use quinn::Endpoint;
 use tracing::{debug, info};
 
+use crate::allowlist::Allowlist;
+
 use crate::connection::PeerConnection;
 use crate::error::ProtocolError;
-use crate::tls;
+use crate::tls::{self, Denylist};
+
+/// An endpoint that accepts connections from peers and dials them.
+///
+/// The daemon only talks to peers through this trait, so a transport other
+/// than QUIC (or an in-memory one in tests) can be swapped in. Connections
+/// must be authenticated before they are returned, and refused in either
+/// direction for fingerprints on the [`Denylist`].
+#[async_trait]
+pub trait Transport: Send + Sync + 'static {
+    /// Accept the next incoming connection.
+    async fn accept(&self) -> Result<PeerConnection, ProtocolError>;
+
+    /// Connect to the peer at `addr`.
+    async fn connect(
+        &self,
+        addr: SocketAddr,
+        server_name: &str,
+    ) -> Result<PeerConnection, ProtocolError>;
+
+    /// The local address connections are accepted on.
+    fn local_addr(&self) -> Result<SocketAddr, ProtocolError>;
+
+    /// Subnets inbound connections are accepted from.
+    fn allowlist(&self) -> &Allowlist;
+
+    /// Certificate fingerprints refused in either direction.
+    fn denylist(&self) -> &Denylist;
+
+    /// Fingerprint of the certificate this transport presents.
+    fn fingerprint(&self) -> Option<&str>;
+
+    /// Stop accepting and close all connections.
+    fn close(&self);
+}
 
 /// QUIC transport layer for cross-control.
 ///
@@ -16,6 +53,9 @@ use crate::tls;
 #[derive(Clone)]
 pub struct QuicTransport {
     endpoint: Endpoint,
+    allowlist: Allowlist,
+    denylist: Denylist,
+    fingerprint: Option<String>,
 }
 
 impl QuicTransport {
@@ -24,24 +64,54 @@ impl QuicTransport {
         // Install the default crypto provider if not already done
         let _ = rustls::crypto::ring::default_provider().install_default();
 
-        let server_config = tls::server_config(cert_pem, key_pem)?;
-        let client_config = tls::client_config_skip_verification()?;
+        let denylist = Denylist::default();
+        let server_config = tls::server_config(cert_pem, key_pem, denylist.clone())?;
+        let client_config = tls::client_config(cert_pem, key_pem, denylist.clone())?;
 
         let mut endpoint = Endpoint::server(server_config, addr)
             .map_err(|e| ProtocolError::Connection(e.to_string()))?;
         endpoint.set_default_client_config(client_config);
 
         info!(addr = %addr, "QUIC transport bound");
-        Ok(Self { endpoint })
+        Ok(Self {
+            endpoint,
+            allowlist: Allowlist::default(),
+            denylist,
+            fingerprint: cross_control_certgen::fingerprint_from_pem(cert_pem).ok(),
+        })
     }
+}
 
-    /// Accept an incoming connection.
-    pub async fn accept(&self) -> Result<PeerConnection, ProtocolError> {
-        let incoming = self
-            .endpoint
-            .accept()
-            .await
-            .ok_or_else(|| ProtocolError::Connection("endpoint closed".to_string()))?;
+#[async_trait]
+impl Transport for QuicTransport {
+    fn allowlist(&self) -> &Allowlist {
+        &self.allowlist
+    }
+
+    fn denylist(&self) -> &Denylist {
+        &self.denylist
+    }
+
+    fn fingerprint(&self) -> Option<&str> {
+        self.fingerprint.as_deref()
+    }
+
+    /// Accept an incoming connection. Attempts from outside the allowlist
+    /// are dropped without a reply.
+    async fn accept(&self) -> Result<PeerConnection, ProtocolError> {
+        let incoming = loop {
+            let incoming = self
+                .endpoint
+                .accept()
+                .await
+                .ok_or_else(|| ProtocolError::Connection("endpoint closed".to_string()))?;
+            let remote = incoming.remote_address();
+            if self.allowlist.permits(remote.ip()) {
+                break incoming;
+            }
+            debug!(remote = %remote, "ignored connection from outside allowed subnets");
+            incoming.ignore();
+        };
 
         let connection = incoming
             .await
@@ -52,8 +122,7 @@ impl QuicTransport {
         Ok(PeerConnection::new(connection))
     }
 
-    /// Connect to a remote peer.
-    pub async fn connect(
+    async fn connect(
         &self,
         addr: SocketAddr,
         server_name: &str,
@@ -69,15 +138,14 @@ impl QuicTransport {
         Ok(PeerConnection::new(connection))
     }
 
-    /// Get the local address this transport is bound to.
-    pub fn local_addr(&self) -> Result<SocketAddr, ProtocolError> {
+    fn local_addr(&self) -> Result<SocketAddr, ProtocolError> {
         self.endpoint
             .local_addr()
             .map_err(|e| ProtocolError::Connection(e.to_string()))
     }
 
     /// Gracefully shut down the transport.
-    pub fn close(&self) {
+    fn close(&self) {
         self.endpoint.close(quinn::VarInt::from_u32(0), b"shutdown");
         info!("QUIC transport closed");
     }
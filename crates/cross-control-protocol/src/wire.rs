@@ -2,14 +2,109 @@
 //!
 //! Each message on the wire is:
 //!   [4 bytes big-endian length][bincode v2 payload]
+//!
+//! Messages that implement [`Tagged`] (the control stream) carry their
+//! variant's tag ahead of the payload, and the length covers both:
+//!   [4 bytes big-endian length][2 bytes big-endian tag][bincode v2 payload]
+//!
+//! A receiver skips frames whose tag it does not know, so a newer peer can
+//! add message types without breaking the session.
+//!
+//! Peers older than [`TAGGED_SINCE`] send the control stream untagged. Their
+//! `Hello` or `Welcome` is recognised by [`Tagged::legacy_version`], so the
+//! handshake can fail with a version mismatch instead of a decode error.
 
 use bincode::{Decode, Encode};
+use cross_control_types::{ControlMessage, ProtocolVersion};
 
 use crate::error::ProtocolError;
 
-/// Maximum message size (1 MiB). Prevents allocation bombs.
+/// Default maximum message size (1 MiB), on both streams until the peer
+/// announces its own limits. Prevents allocation bombs.
 pub const MAX_MESSAGE_SIZE: u32 = 1024 * 1024;
 
+/// Length of the tag in a [`Tagged`] frame.
+pub const TAG_SIZE: usize = 2;
+
+/// The first protocol version with a tagged control stream.
+pub const TAGGED_SINCE: ProtocolVersion = ProtocolVersion { major: 0, minor: 2 };
+
+/// A message enum framed with a per-variant tag, so receivers can skip
+/// variants they do not know.
+///
+/// New variants must be added with a new tag; tags are never renumbered or
+/// reused. Changing the fields of an existing variant still needs a major
+/// protocol version bump.
+pub trait Tagged: Encode + Decode<()> {
+    /// Tag of this message's variant.
+    fn tag(&self) -> u16;
+
+    /// Whether this build can decode frames carrying `tag`.
+    fn is_known(tag: u16) -> bool;
+
+    /// The version announced by `frame`, if it is an untagged handshake
+    /// message from a peer older than [`TAGGED_SINCE`].
+    fn legacy_version(_frame: &[u8]) -> Option<ProtocolVersion> {
+        None
+    }
+}
+
+impl Tagged for ControlMessage {
+    fn tag(&self) -> u16 {
+        // Matches the declaration order, which is also bincode's variant
+        // index, so the tag and payload can never disagree.
+        match self {
+            Self::Hello { .. } => 0,
+            Self::Welcome { .. } => 1,
+            Self::DeviceAnnounce(_) => 2,
+            Self::DeviceGone { .. } => 3,
+            Self::ScreenUpdate(_) => 4,
+            Self::Enter { .. } => 5,
+            Self::EnterAck => 6,
+            Self::EnterDenied { .. } => 7,
+            Self::Leave { .. } => 8,
+            Self::PowerState(_) => 9,
+            Self::Suspending => 10,
+            Self::Resumed => 11,
+            Self::Clipboard(_) => 12,
+            Self::ConfigPush { .. } => 13,
+            Self::ConfigPushResult { .. } => 14,
+            Self::Setup(_) => 15,
+            Self::Ping { .. } => 16,
+            Self::Pong { .. } => 17,
+            Self::Bye => 18,
+            Self::Error { .. } => 19,
+            Self::Limits { .. } => 20,
+            Self::RunCommand { .. } => 21,
+            Self::CommandResult { .. } => 22,
+            Self::FocusHint(_) => 23,
+            Self::TakeControl => 24,
+            Self::RequestControl => 25,
+            Self::ControlRequestResult { .. } => 26,
+            Self::BuildInfo { .. } => 27,
+            Self::KeyboardLayout(_) => 28,
+            Self::LocalInput { .. } => 29,
+        }
+    }
+
+    fn is_known(tag: u16) -> bool {
+        tag <= 29
+    }
+
+    fn legacy_version(frame: &[u8]) -> Option<ProtocolVersion> {
+        // Variant indexes and fields of Hello and Welcome are unchanged
+        // since 0.1, so the untagged frame still decodes.
+        match decode_message(frame).ok()? {
+            Self::Hello { version, .. } | Self::Welcome { version, .. }
+                if version.major == TAGGED_SINCE.major && version.minor < TAGGED_SINCE.minor =>
+            {
+                Some(version)
+            }
+            _ => None,
+        }
+    }
+}
+
 /// Encode a message to a length-prefixed byte vector.
 pub fn encode_message<T: Encode>(msg: &T) -> Result<Vec<u8>, ProtocolError> {
     let config = bincode::config::standard();
@@ -25,6 +120,47 @@ pub fn encode_message<T: Encode>(msg: &T) -> Result<Vec<u8>, ProtocolError> {
     Ok(buf)
 }
 
+/// Encode a message to a length-prefixed, tagged byte vector.
+pub fn encode_tagged<T: Tagged>(msg: &T) -> Result<Vec<u8>, ProtocolError> {
+    let config = bincode::config::standard();
+    let payload = bincode::encode_to_vec(msg, config)
+        .map_err(|e| ProtocolError::Serialization(e.to_string()))?;
+
+    let len = u32::try_from(TAG_SIZE + payload.len())
+        .map_err(|_| ProtocolError::Serialization("message too large".to_string()))?;
+
+    let mut buf = Vec::with_capacity(4 + TAG_SIZE + payload.len());
+    buf.extend_from_slice(&len.to_be_bytes());
+    buf.extend_from_slice(&msg.tag().to_be_bytes());
+    buf.extend_from_slice(&payload);
+    Ok(buf)
+}
+
+/// Decode a tagged frame (without the length prefix).
+///
+/// Returns `None` for a tag this build does not know; the frame should be
+/// skipped.
+pub fn decode_tagged<T: Tagged>(frame: &[u8]) -> Result<Option<(u16, T)>, ProtocolError> {
+    if frame.len() < TAG_SIZE {
+        return Err(ProtocolError::Deserialization(
+            "frame shorter than its tag".to_string(),
+        ));
+    }
+    let (tag, payload) = frame.split_at(TAG_SIZE);
+    let tag = u16::from_be_bytes([tag[0], tag[1]]);
+    if !T::is_known(tag) {
+        return Ok(None);
+    }
+    let msg: T = decode_message(payload)?;
+    if msg.tag() != tag {
+        return Err(ProtocolError::Deserialization(format!(
+            "frame tagged {tag} holds message tagged {}",
+            msg.tag()
+        )));
+    }
+    Ok(Some((tag, msg)))
+}
+
 /// Decode a message from a bincode v2 payload (without the length prefix).
 pub fn decode_message<T: Decode<()>>(payload: &[u8]) -> Result<T, ProtocolError> {
     let config = bincode::config::standard();
@@ -74,4 +210,97 @@ mod tests {
             _ => panic!("unexpected message type"),
         }
     }
+
+    #[test]
+    fn tags_follow_variant_index() {
+        let config = bincode::config::standard();
+        let messages = [
+            ControlMessage::EnterAck,
+            ControlMessage::Ping { seq: 1 },
+            ControlMessage::Bye,
+            ControlMessage::Limits {
+                control: 1,
+                input: 2,
+            },
+            ControlMessage::Error {
+                code: cross_control_types::ErrorCode::Internal,
+                detail: String::new(),
+            },
+            ControlMessage::CommandResult {
+                name: "lock-screen".to_string(),
+                error: None,
+            },
+            ControlMessage::FocusHint(cross_control_types::FocusHint::default()),
+            ControlMessage::TakeControl,
+            ControlMessage::RequestControl,
+            ControlMessage::ControlRequestResult {
+                error: Some("control requests are not accepted".to_string()),
+            },
+            ControlMessage::BuildInfo {
+                version: "0.4.0".to_string(),
+                commit: "abc1234".to_string(),
+            },
+            ControlMessage::KeyboardLayout(cross_control_types::KeyboardLayout {
+                layout: "gr".to_string(),
+                variant: String::new(),
+            }),
+            ControlMessage::LocalInput { active: true },
+        ];
+        for msg in messages {
+            let payload = bincode::encode_to_vec(&msg, config).unwrap();
+            assert_eq!(u16::from(payload[0]), msg.tag(), "{msg:?}");
+            assert!(ControlMessage::is_known(msg.tag()));
+        }
+        assert!(!ControlMessage::is_known(30));
+    }
+
+    #[test]
+    fn tagged_roundtrip() {
+        let bytes = encode_tagged(&ControlMessage::Pong { seq: 9 }).unwrap();
+        let len = u32::from_be_bytes(bytes[..4].try_into().unwrap());
+        assert_eq!(len as usize, bytes.len() - 4);
+        assert_eq!(&bytes[4..6], &17u16.to_be_bytes());
+
+        let (tag, msg) = decode_tagged::<ControlMessage>(&bytes[4..])
+            .unwrap()
+            .unwrap();
+        assert_eq!(tag, 17);
+        assert!(matches!(msg, ControlMessage::Pong { seq: 9 }));
+    }
+
+    #[test]
+    fn unknown_tag_is_skipped() {
+        let mut frame = 400u16.to_be_bytes().to_vec();
+        frame.extend_from_slice(&[1, 2, 3]);
+        assert!(decode_tagged::<ControlMessage>(&frame).unwrap().is_none());
+    }
+
+    #[test]
+    fn untagged_legacy_hello_is_recognised() {
+        let legacy = ProtocolVersion { major: 0, minor: 1 };
+        let hello = ControlMessage::Hello {
+            version: legacy,
+            machine_id: cross_control_types::MachineId::new(),
+            name: "old".to_string(),
+            screen: cross_control_types::ScreenGeometry::new(1920, 1080),
+        };
+        let bytes = encode_message(&hello).unwrap();
+        assert!(!matches!(
+            decode_tagged::<ControlMessage>(&bytes[4..]),
+            Ok(Some(_))
+        ));
+        assert_eq!(ControlMessage::legacy_version(&bytes[4..]), Some(legacy));
+
+        let tagged = encode_tagged(&hello).unwrap();
+        assert_eq!(ControlMessage::legacy_version(&tagged[4..]), None);
+    }
+
+    #[test]
+    fn mismatched_tag_is_an_error() {
+        let bytes = encode_tagged(&ControlMessage::Bye).unwrap();
+        let mut frame = bytes[4..].to_vec();
+        frame[..2].copy_from_slice(&6u16.to_be_bytes());
+        assert!(decode_tagged::<ControlMessage>(&frame).is_err());
+        assert!(decode_tagged::<ControlMessage>(&[0]).is_err());
+    }
 }
@@ -0,0 +1,204 @@
+//! Integration test: message framing over a non-QUIC `Connection`.
+
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use cross_control_protocol::{Connection, PeerConnection, ProtocolError, RecvHalf, SendHalf};
+use cross_control_types::{
+    ButtonState, ControlMessage, DeviceId, InputEvent, InputMessage, KeyCode,
+};
+use tokio::sync::{mpsc, watch, Mutex};
+
+type BiStream = (SendHalf, RecvHalf);
+
+/// One end of an in-memory connection built from `tokio::io::duplex` pipes.
+struct MemoryConnection {
+    bi_out: mpsc::UnboundedSender<BiStream>,
+    bi_in: Mutex<mpsc::UnboundedReceiver<BiStream>>,
+    uni_out: mpsc::UnboundedSender<RecvHalf>,
+    uni_in: Mutex<mpsc::UnboundedReceiver<RecvHalf>>,
+    closed: watch::Sender<bool>,
+}
+
+fn memory_pair() -> (MemoryConnection, MemoryConnection) {
+    let (bi_a, bi_from_a) = mpsc::unbounded_channel();
+    let (bi_b, bi_from_b) = mpsc::unbounded_channel();
+    let (uni_a, uni_from_a) = mpsc::unbounded_channel();
+    let (uni_b, uni_from_b) = mpsc::unbounded_channel();
+    let (closed, _) = watch::channel(false);
+    let a = MemoryConnection {
+        bi_out: bi_a,
+        bi_in: Mutex::new(bi_from_b),
+        uni_out: uni_a,
+        uni_in: Mutex::new(uni_from_b),
+        closed: closed.clone(),
+    };
+    let b = MemoryConnection {
+        bi_out: bi_b,
+        bi_in: Mutex::new(bi_from_a),
+        uni_out: uni_b,
+        uni_in: Mutex::new(uni_from_a),
+        closed,
+    };
+    (a, b)
+}
+
+fn gone() -> ProtocolError {
+    ProtocolError::Connection("peer gone".to_string())
+}
+
+#[async_trait]
+impl Connection for MemoryConnection {
+    fn remote_address(&self) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], 0))
+    }
+
+    fn peer_fingerprint(&self) -> Option<String> {
+        None
+    }
+
+    async fn open_bi(&self) -> Result<BiStream, ProtocolError> {
+        let (ours, theirs) = tokio::io::duplex(64 * 1024);
+        let (their_read, their_write) = tokio::io::split(theirs);
+        self.bi_out
+            .send((Box::new(their_write), Box::new(their_read)))
+            .map_err(|_| gone())?;
+        let (our_read, our_write) = tokio::io::split(ours);
+        Ok((Box::new(our_write), Box::new(our_read)))
+    }
+
+    async fn accept_bi(&self) -> Result<BiStream, ProtocolError> {
+        self.bi_in.lock().await.recv().await.ok_or_else(gone)
+    }
+
+    async fn open_uni(&self) -> Result<SendHalf, ProtocolError> {
+        let (ours, theirs) = tokio::io::duplex(64 * 1024);
+        self.uni_out.send(Box::new(theirs)).map_err(|_| gone())?;
+        Ok(Box::new(ours))
+    }
+
+    async fn accept_uni(&self) -> Result<RecvHalf, ProtocolError> {
+        self.uni_in.lock().await.recv().await.ok_or_else(gone)
+    }
+
+    fn export_keying_material(
+        &self,
+        output: &mut [u8],
+        _label: &[u8],
+        _context: &[u8],
+    ) -> Result<(), ProtocolError> {
+        output.fill(0x5a);
+        Ok(())
+    }
+
+    fn close(&self) {
+        self.closed.send_replace(true);
+    }
+
+    async fn closed(&self) {
+        let mut closed = self.closed.subscribe();
+        let _ = closed.wait_for(|closed| *closed).await;
+    }
+}
+
+#[tokio::test]
+async fn control_and_input_streams_over_memory_connection() {
+    let (a, b) = memory_pair();
+    let a = PeerConnection::new(a);
+    let b = PeerConnection::new(b);
+
+    let (mut a_tx, mut a_rx) = a.open_control_stream().await.unwrap();
+    let (mut b_tx, mut b_rx) = b.accept_control_stream().await.unwrap();
+
+    a_tx.send_tagged(&ControlMessage::Ping { seq: 7 })
+        .await
+        .unwrap();
+    let request: ControlMessage = b_rx.recv_tagged().await.unwrap().unwrap();
+    assert!(matches!(request, ControlMessage::Ping { seq: 7 }));
+
+    b_tx.send_tagged(&ControlMessage::Pong { seq: 7 })
+        .await
+        .unwrap();
+    let reply: ControlMessage = a_rx.recv_tagged().await.unwrap().unwrap();
+    assert!(matches!(reply, ControlMessage::Pong { seq: 7 }));
+
+    let mut input_tx = a.open_input_stream().await.unwrap();
+    let mut input_rx = b.accept_input_stream().await.unwrap();
+    let msg = InputMessage {
+        device_id: DeviceId(1),
+        timestamp_us: 1,
+        events: vec![InputEvent::Key {
+            code: KeyCode::KeyA,
+            state: ButtonState::Pressed,
+        }],
+    };
+    input_tx.send(&msg).await.unwrap();
+    let received: InputMessage = input_rx.recv().await.unwrap().unwrap();
+    assert_eq!(received.events, msg.events);
+
+    // Finishing the stream reads as a clean end, not an error.
+    input_tx.finish().await.unwrap();
+    assert!(input_rx.recv::<InputMessage>().await.unwrap().is_none());
+
+    assert_eq!(
+        a.export_keying_material(b"label", &[], 4).unwrap(),
+        b.export_keying_material(b"label", &[], 4).unwrap()
+    );
+
+    a.close();
+    tokio::time::timeout(std::time::Duration::from_secs(1), b.closed())
+        .await
+        .expect("close is visible to the other end");
+}
+
+#[tokio::test]
+async fn message_size_limits_apply_to_both_ends() {
+    let (a, b) = memory_pair();
+    let a = PeerConnection::new(a);
+    let b = PeerConnection::new(b);
+    let (mut a_tx, _a_rx) = a.open_control_stream().await.unwrap();
+    let (_b_tx, mut b_rx) = b.accept_control_stream().await.unwrap();
+    let large = ControlMessage::ConfigPush {
+        layout: "x".repeat(256),
+    };
+
+    a_tx.set_max_size(128);
+    assert!(matches!(
+        a_tx.send_tagged(&large).await,
+        Err(ProtocolError::Serialization(_))
+    ));
+
+    a_tx.set_max_size(1024);
+    b_rx.set_max_size(128);
+    a_tx.send_tagged(&large).await.unwrap();
+    assert!(matches!(
+        b_rx.recv_tagged::<ControlMessage>().await,
+        Err(ProtocolError::Deserialization(_))
+    ));
+}
+
+#[tokio::test]
+async fn byte_counters_include_framing() {
+    let (a, b) = memory_pair();
+    let a = PeerConnection::new(a);
+    let b = PeerConnection::new(b);
+    let (mut a_tx, _a_rx) = a.open_control_stream().await.unwrap();
+    let (_b_tx, mut b_rx) = b.accept_control_stream().await.unwrap();
+    let sent = Arc::new(AtomicU64::new(0));
+    let received = Arc::new(AtomicU64::new(0));
+    a_tx.count_bytes(sent.clone());
+    b_rx.count_bytes(received.clone());
+
+    let ping = ControlMessage::Ping { seq: 7 };
+    let frame = cross_control_protocol::wire::encode_tagged(&ping)
+        .unwrap()
+        .len() as u64;
+    for _ in 0..3 {
+        a_tx.send_tagged(&ping).await.unwrap();
+        b_rx.recv_tagged::<ControlMessage>().await.unwrap().unwrap();
+    }
+    assert_eq!(sent.load(Ordering::Relaxed), 3 * frame);
+    assert_eq!(received.load(Ordering::Relaxed), 3 * frame);
+}
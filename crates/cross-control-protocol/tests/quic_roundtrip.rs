@@ -2,6 +2,8 @@
 
 use std::net::SocketAddr;
 
+use cross_control_protocol::Transport;
+
 use cross_control_types::{
     ControlMessage, DeviceCapability, DeviceId, DeviceInfo, InputEvent, InputMessage, KeyCode,
     MachineId, ScreenGeometry, PROTOCOL_VERSION,
@@ -29,7 +31,7 @@ async fn hello_welcome_handshake_on_loopback() {
         let (mut tx, mut rx) = conn.accept_control_stream().await.unwrap();
 
         // Receive Hello
-        let hello: ControlMessage = rx.recv().await.unwrap().unwrap();
+        let hello: ControlMessage = rx.recv_tagged().await.unwrap().unwrap();
         match hello {
             ControlMessage::Hello {
                 version,
@@ -50,10 +52,10 @@ async fn hello_welcome_handshake_on_loopback() {
             name: "test-server".to_string(),
             screen: ScreenGeometry::new(2560, 1440),
         };
-        tx.send(&welcome).await.unwrap();
+        tx.send_tagged(&welcome).await.unwrap();
 
         // Receive DeviceAnnounce
-        let announce: ControlMessage = rx.recv().await.unwrap().unwrap();
+        let announce: ControlMessage = rx.recv_tagged().await.unwrap().unwrap();
         match announce {
             ControlMessage::DeviceAnnounce(info) => {
                 assert_eq!(info.name, "Test Keyboard");
@@ -98,10 +100,10 @@ async fn hello_welcome_handshake_on_loopback() {
         name: "test-client".to_string(),
         screen: ScreenGeometry::new(1920, 1080),
     };
-    tx.send(&hello).await.unwrap();
+    tx.send_tagged(&hello).await.unwrap();
 
     // Receive Welcome
-    let welcome: ControlMessage = rx.recv().await.unwrap().unwrap();
+    let welcome: ControlMessage = rx.recv_tagged().await.unwrap().unwrap();
     match welcome {
         ControlMessage::Welcome { name, .. } => {
             assert_eq!(name, "test-server");
@@ -114,8 +116,9 @@ async fn hello_welcome_handshake_on_loopback() {
         id: DeviceId(1),
         name: "Test Keyboard".to_string(),
         capabilities: vec![DeviceCapability::Keyboard],
+        keys: Vec::new(),
     };
-    tx.send(&ControlMessage::DeviceAnnounce(device))
+    tx.send_tagged(&ControlMessage::DeviceAnnounce(device))
         .await
         .unwrap();
 
@@ -157,10 +160,10 @@ async fn ping_pong_roundtrip() {
         let conn = transport.accept().await.unwrap();
         let (mut tx, mut rx) = conn.accept_control_stream().await.unwrap();
 
-        let msg: ControlMessage = rx.recv().await.unwrap().unwrap();
+        let msg: ControlMessage = rx.recv_tagged().await.unwrap().unwrap();
         match msg {
             ControlMessage::Ping { seq } => {
-                tx.send(&ControlMessage::Pong { seq }).await.unwrap();
+                tx.send_tagged(&ControlMessage::Pong { seq }).await.unwrap();
             }
             other => panic!("expected Ping, got {other:?}"),
         }
@@ -180,8 +183,10 @@ async fn ping_pong_roundtrip() {
     let conn = client.connect(server_addr, "localhost").await.unwrap();
     let (mut tx, mut rx) = conn.open_control_stream().await.unwrap();
 
-    tx.send(&ControlMessage::Ping { seq: 42 }).await.unwrap();
-    let reply: ControlMessage = rx.recv().await.unwrap().unwrap();
+    tx.send_tagged(&ControlMessage::Ping { seq: 42 })
+        .await
+        .unwrap();
+    let reply: ControlMessage = rx.recv_tagged().await.unwrap().unwrap();
     match reply {
         ControlMessage::Pong { seq } => assert_eq!(seq, 42),
         other => panic!("expected Pong, got {other:?}"),
@@ -191,3 +196,121 @@ async fn ping_pong_roundtrip() {
     let _ = done_tx.send(());
     server.await.unwrap();
 }
+
+#[tokio::test]
+async fn denylisted_certificates_are_refused() {
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    let server_cert = cross_control_certgen::generate_certificate("localhost").unwrap();
+    let server = cross_control_protocol::QuicTransport::bind(
+        "127.0.0.1:0".parse().unwrap(),
+        &server_cert.cert_pem,
+        &server_cert.key_pem,
+    )
+    .unwrap();
+    let server_addr = server.local_addr().unwrap();
+
+    // Keep accepting so every handshake gets a response from the server.
+    let (accepted_tx, mut accepted) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn({
+        let server = server.clone();
+        async move {
+            loop {
+                let result = server.accept().await;
+                if accepted_tx.send(result).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    let client_cert = cross_control_certgen::generate_certificate("localhost").unwrap();
+    let client = cross_control_protocol::QuicTransport::bind(
+        "127.0.0.1:0".parse().unwrap(),
+        &client_cert.cert_pem,
+        &client_cert.key_pem,
+    )
+    .unwrap();
+
+    // Both ends see the other's certificate fingerprint.
+    let conn = client.connect(server_addr, "localhost").await.unwrap();
+    assert_eq!(
+        conn.peer_fingerprint(),
+        Some(server_cert.fingerprint.clone())
+    );
+    let inbound = accepted.recv().await.unwrap().unwrap();
+    assert_eq!(
+        inbound.peer_fingerprint(),
+        Some(client_cert.fingerprint.clone())
+    );
+
+    // The client refuses a denylisted server.
+    client.denylist().insert(server_cert.fingerprint.clone());
+    assert!(client.connect(server_addr, "localhost").await.is_err());
+    assert!(accepted.recv().await.unwrap().is_err());
+
+    // The server refuses a denylisted client.
+    let other_cert = cross_control_certgen::generate_certificate("localhost").unwrap();
+    let other = cross_control_protocol::QuicTransport::bind(
+        "127.0.0.1:0".parse().unwrap(),
+        &other_cert.cert_pem,
+        &other_cert.key_pem,
+    )
+    .unwrap();
+    server.denylist().insert(other_cert.fingerprint.clone());
+    let _ = other.connect(server_addr, "localhost").await;
+    assert!(accepted.recv().await.unwrap().is_err());
+}
+
+#[tokio::test]
+async fn connections_from_outside_allowed_subnets_are_ignored() {
+    use std::time::Duration;
+
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    let server_cert = cross_control_certgen::generate_certificate("localhost").unwrap();
+    let server = cross_control_protocol::QuicTransport::bind(
+        "127.0.0.1:0".parse().unwrap(),
+        &server_cert.cert_pem,
+        &server_cert.key_pem,
+    )
+    .unwrap();
+    let server_addr = server.local_addr().unwrap();
+    server.allowlist().set(vec!["127.0.0.0/8".parse().unwrap()]);
+
+    let (accepted_tx, mut accepted) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn({
+        let server = server.clone();
+        async move {
+            loop {
+                let result = server.accept().await;
+                if accepted_tx.send(result).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    let client_cert = cross_control_certgen::generate_certificate("localhost").unwrap();
+    let client = cross_control_protocol::QuicTransport::bind(
+        "127.0.0.1:0".parse().unwrap(),
+        &client_cert.cert_pem,
+        &client_cert.key_pem,
+    )
+    .unwrap();
+    client.connect(server_addr, "localhost").await.unwrap();
+    accepted.recv().await.unwrap().unwrap();
+
+    // Once loopback is no longer allowed the server never answers, so the
+    // client just times out.
+    server
+        .allowlist()
+        .set(vec!["192.168.1.0/24".parse().unwrap()]);
+    let attempt = tokio::time::timeout(
+        Duration::from_millis(500),
+        client.connect(server_addr, "localhost"),
+    )
+    .await;
+    assert!(attempt.is_err(), "connection should get no reply");
+    assert!(accepted.try_recv().is_err());
+}
@@ -0,0 +1,255 @@
+//! Loopback daemons with mock backends, for integration tests, examples and
+//! the TUI harness.
+//!
+//! A typical test binds an [`Endpoint`] per machine, builds each machine's
+//! [`Config`] with [`config`] and [`screen`], spawns a [`DaemonBundle`] and
+//! waits on the returned status receivers:
+//!
+//! ```no_run
+//! # async fn example() {
+//! use cross_control_test_support::{config, screen, wait_for_status, DaemonBundle, Endpoint};
+//! use cross_control_types::Position;
+//! use std::time::Duration;
+//!
+//! let a = Endpoint::bind();
+//! let b = Endpoint::bind();
+//! let config_a = config("a", vec![screen("b", Position::Right, Some(b.addr))]);
+//! let config_b = config("b", vec![screen("a", Position::Left, None)]);
+//! let (_b, _) = DaemonBundle::new(config_b, b.transport).spawn();
+//! let (mut a, _) = DaemonBundle::new(config_a, a.transport).spawn();
+//! wait_for_status(&mut a.status, Duration::from_secs(5), |s| s.session_count == 1)
+//!     .await
+//!     .unwrap();
+//! # }
+//! ```
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use cross_control_certgen::GeneratedCert;
+use cross_control_clipboard::mock::{MockClipboard, MockClipboardHandle};
+use cross_control_daemon::config::{
+    Config, DaemonConfig, IdentityConfig, PointerScale, ScreenConfig, ScreenMode,
+};
+use cross_control_daemon::{Daemon, DaemonEvent, DaemonStatus};
+use cross_control_input::mock::{
+    MockCapture, MockCaptureHandle, MockEmulation, MockEmulationHandle,
+};
+use cross_control_protocol::{QuicTransport, Transport};
+use cross_control_types::{
+    CapturedEvent, DeviceCapability, DeviceId, DeviceInfo, MachineId, Position,
+};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+
+/// Screen size of every test machine.
+pub const SCREEN_WIDTH: u32 = 1920;
+pub const SCREEN_HEIGHT: u32 = 1080;
+
+/// The local devices of every test machine: a keyboard (`DeviceId(1)`) and
+/// a mouse with a scroll wheel (`DeviceId(2)`).
+pub fn test_devices() -> Vec<DeviceInfo> {
+    vec![
+        DeviceInfo {
+            id: DeviceId(1),
+            name: "Test Keyboard".to_string(),
+            capabilities: vec![DeviceCapability::Keyboard],
+            keys: Vec::new(),
+        },
+        DeviceInfo {
+            id: DeviceId(2),
+            name: "Test Mouse".to_string(),
+            capabilities: vec![DeviceCapability::RelativeMouse, DeviceCapability::Scroll],
+            keys: Vec::new(),
+        },
+    ]
+}
+
+/// A certificate and a QUIC transport bound to a free loopback port.
+pub struct Endpoint {
+    pub cert: GeneratedCert,
+    pub transport: QuicTransport,
+    pub addr: SocketAddr,
+}
+
+impl Endpoint {
+    /// Generate a certificate and bind to it.
+    pub fn bind() -> Self {
+        Self::bind_cert(cross_control_certgen::generate_certificate("localhost").unwrap())
+    }
+
+    /// Bind with an existing certificate, e.g. to restart a machine under
+    /// the identity its peers pinned.
+    pub fn bind_cert(cert: GeneratedCert) -> Self {
+        let bind: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let transport = QuicTransport::bind(bind, &cert.cert_pem, &cert.key_pem).unwrap();
+        let addr = transport.local_addr().unwrap();
+        Self {
+            cert,
+            transport,
+            addr,
+        }
+    }
+}
+
+/// A control-mode screen entry for `name`, dialled at `address` if given.
+pub fn screen(name: &str, position: Position, address: Option<SocketAddr>) -> ScreenConfig {
+    ScreenConfig {
+        name: name.to_string(),
+        group: None,
+        address: address.map(|a| a.to_string()),
+        position,
+        fingerprint: None,
+        mode: ScreenMode::Control,
+        wake_on_enter: true,
+        locate_on_enter: false,
+        pointer_scale: PointerScale::default(),
+        share_devices: None,
+        clipboard: true,
+        wait_on_start: false,
+        commands: Vec::new(),
+        release_hotkey: None,
+    }
+}
+
+/// The config of a test machine named `name`, with a
+/// [`SCREEN_WIDTH`]x[`SCREEN_HEIGHT`] screen and defaults elsewhere.
+pub fn config(name: &str, screens: Vec<ScreenConfig>) -> Config {
+    Config {
+        daemon: DaemonConfig {
+            screen_width: SCREEN_WIDTH,
+            screen_height: SCREEN_HEIGHT,
+            ..DaemonConfig::default()
+        },
+        identity: IdentityConfig {
+            name: name.to_string(),
+        },
+        screens,
+        ..Config::default()
+    }
+}
+
+/// A daemon with mock backends and [`test_devices`], not yet running.
+pub struct DaemonBundle {
+    pub daemon: Daemon,
+    pub handles: DaemonHandles,
+}
+
+/// Ways to drive and observe a daemon built by [`DaemonBundle`].
+#[derive(Clone)]
+pub struct DaemonHandles {
+    pub name: String,
+    pub machine_id: MachineId,
+    /// Feeds captured local input to the daemon.
+    pub feed: mpsc::Sender<CapturedEvent>,
+    pub capture: MockCaptureHandle,
+    pub emulation: MockEmulationHandle,
+    pub clipboard: MockClipboardHandle,
+    pub status: watch::Receiver<DaemonStatus>,
+    /// The daemon's event channel, for IPC requests and `Shutdown`.
+    pub events: mpsc::Sender<DaemonEvent>,
+}
+
+impl DaemonHandles {
+    /// Ask the daemon to stop.
+    pub async fn shutdown(&self) {
+        let _ = self.events.send(DaemonEvent::Shutdown).await;
+    }
+}
+
+impl DaemonBundle {
+    pub fn new(config: Config, transport: QuicTransport) -> Self {
+        Self::with_emulation(config, transport, MockEmulation::new())
+    }
+
+    /// Like [`new`](Self::new), with a caller-configured emulation backend.
+    pub fn with_emulation(
+        config: Config,
+        transport: QuicTransport,
+        emulation: MockEmulation,
+    ) -> Self {
+        let name = config.identity.name.clone();
+        let machine_id = MachineId::new();
+        let (capture, feed) = MockCapture::new();
+        let capture_handle = capture.handle();
+        let emulation_handle = emulation.handle();
+        let clipboard = MockClipboard::new();
+        let clipboard_handle = clipboard.handle();
+
+        let mut daemon = Daemon::new(
+            config,
+            machine_id,
+            transport,
+            Box::new(capture),
+            Box::new(emulation),
+        );
+        daemon.set_local_devices(test_devices());
+        daemon.set_clipboard(Box::new(clipboard));
+        let handles = DaemonHandles {
+            name,
+            machine_id,
+            feed,
+            capture: capture_handle,
+            emulation: emulation_handle,
+            clipboard: clipboard_handle,
+            status: daemon.status_receiver(),
+            events: daemon.event_sender(),
+        };
+        Self { daemon, handles }
+    }
+
+    /// Run the daemon on a task; errors are printed to stderr.
+    pub fn spawn(self) -> (DaemonHandles, JoinHandle<()>) {
+        let Self {
+            mut daemon,
+            handles,
+        } = self;
+        let name = handles.name.clone();
+        let task = tokio::spawn(async move {
+            if let Err(e) = daemon.run().await {
+                eprintln!("daemon {name} error: {e}");
+            }
+        });
+        (handles, task)
+    }
+}
+
+/// Wait for a condition on a status receiver with timeout.
+pub async fn wait_for_status(
+    rx: &mut watch::Receiver<DaemonStatus>,
+    timeout: Duration,
+    pred: impl Fn(&DaemonStatus) -> bool,
+) -> Result<DaemonStatus, &'static str> {
+    tokio::time::timeout(timeout, async {
+        loop {
+            {
+                let status = rx.borrow_and_update().clone();
+                if pred(&status) {
+                    return Ok(status);
+                }
+            }
+            if rx.changed().await.is_err() {
+                return Err("watch closed");
+            }
+        }
+    })
+    .await
+    .map_err(|_| "timeout")?
+}
+
+/// Wait until every daemon has at least `sessions` sessions. On timeout,
+/// returns each daemon's session count.
+pub async fn wait_for_sessions(
+    statuses: &[watch::Receiver<DaemonStatus>],
+    sessions: usize,
+    timeout: Duration,
+) -> Result<(), Vec<usize>> {
+    let counts = || -> Vec<usize> { statuses.iter().map(|s| s.borrow().session_count).collect() };
+    let connected = tokio::time::timeout(timeout, async {
+        while !counts().iter().all(|&count| count >= sessions) {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await;
+    connected.map_err(|_| counts())
+}
@@ -6,7 +6,9 @@ use ratatui::text::{Line, Span};
 use ratatui::widgets::{Block, Borders, List, ListItem, Paragraph};
 use ratatui::Frame;
 
+use cross_control_daemon::ipc::InputGrab;
 use cross_control_daemon::DaemonStatus;
+use cross_control_types::ScreenState;
 
 use crate::app::AppState;
 
@@ -172,15 +174,31 @@ fn draw_screen(
     let inner = block.inner(area);
     f.render_widget(block, area);
 
-    // Draw state label
-    let state_line = Paragraph::new(Line::from(vec![
+    // Draw state label, and warnings when the controlled screen can't
+    // show what is typed into it or local input still reaches this one
+    let mut spans = vec![
         Span::raw("State: "),
         Span::styled(
             label,
             Style::default().fg(color).add_modifier(Modifier::BOLD),
         ),
         Span::raw(format!("  Sessions: {}", status.session_count)),
-    ]));
+    ];
+    if let Some(focus) = status.remote_focus {
+        if focus.screen != ScreenState::Active {
+            spans.push(Span::styled(
+                format!("  Remote {}", focus.screen.as_str()),
+                Style::default().fg(Color::Red),
+            ));
+        }
+    }
+    if status.input_grab == InputGrab::Retrying {
+        spans.push(Span::styled(
+            "  Input not grabbed",
+            Style::default().fg(Color::Red),
+        ));
+    }
+    let state_line = Paragraph::new(Line::from(spans));
     if inner.height > 0 {
         f.render_widget(
             state_line,
@@ -3,6 +3,8 @@
 use bincode::{Decode, Encode};
 use serde::{Deserialize, Serialize};
 
+use crate::event::KeyCode;
+
 /// Opaque ID for a physical input device on the source machine.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Encode, Decode)]
 pub struct DeviceId(pub u32);
@@ -20,6 +22,52 @@ pub struct DeviceInfo {
     pub name: String,
     /// What this device can do.
     pub capabilities: Vec<DeviceCapability>,
+    /// Keys this device can emit (keyboards only). The controlled side
+    /// registers exactly this set on the virtual device; empty means the
+    /// capturing side did not report one.
+    pub keys: Vec<KeyCode>,
+}
+
+/// Longest device name accepted from a peer, in bytes.
+pub const MAX_DEVICE_NAME_LEN: usize = 256;
+
+/// Most keys a device announced by a peer may list: one per Linux key code
+/// (`KEY_CNT`).
+pub const MAX_DEVICE_KEYS: usize = 768;
+
+impl DeviceInfo {
+    /// Check a device announced by a peer before creating a virtual device
+    /// for it: a name of at most [`MAX_DEVICE_NAME_LEN`] bytes, at least one
+    /// capability with none repeated, and at most [`MAX_DEVICE_KEYS`] keys.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.len() > MAX_DEVICE_NAME_LEN {
+            return Err(format!(
+                "device name is {} bytes, at most {MAX_DEVICE_NAME_LEN} allowed",
+                self.name.len()
+            ));
+        }
+        if self.capabilities.is_empty() {
+            return Err(format!("device {:?} has no capabilities", self.name));
+        }
+        let mut seen = Vec::with_capacity(self.capabilities.len());
+        for capability in &self.capabilities {
+            if seen.contains(capability) {
+                return Err(format!(
+                    "device {:?} lists {capability:?} more than once",
+                    self.name
+                ));
+            }
+            seen.push(*capability);
+        }
+        if self.keys.len() > MAX_DEVICE_KEYS {
+            return Err(format!(
+                "device {:?} lists {} keys, at most {MAX_DEVICE_KEYS} allowed",
+                self.name,
+                self.keys.len()
+            ));
+        }
+        Ok(())
+    }
 }
 
 /// What kind of input a device supports.
@@ -41,6 +89,7 @@ mod tests {
             id: DeviceId(7),
             name: "Test Keyboard".to_string(),
             capabilities: vec![DeviceCapability::Keyboard],
+            keys: vec![KeyCode::KeyA, KeyCode::Enter, KeyCode::Unknown(0x1d0)],
         };
         let config = bincode::config::standard();
         let bytes = bincode::encode_to_vec(&info, config).unwrap();
@@ -48,12 +97,45 @@ mod tests {
         assert_eq!(info, decoded);
     }
 
+    #[test]
+    fn validate_rejects_oversized_or_malformed_devices() {
+        let device = DeviceInfo {
+            id: DeviceId(1),
+            name: "Office Mouse".to_string(),
+            capabilities: vec![DeviceCapability::RelativeMouse, DeviceCapability::Scroll],
+            keys: Vec::new(),
+        };
+        assert_eq!(device.validate(), Ok(()));
+
+        let long_name = DeviceInfo {
+            name: "x".repeat(MAX_DEVICE_NAME_LEN + 1),
+            ..device.clone()
+        };
+        assert!(long_name.validate().is_err());
+        let no_capabilities = DeviceInfo {
+            capabilities: Vec::new(),
+            ..device.clone()
+        };
+        assert!(no_capabilities.validate().is_err());
+        let repeated = DeviceInfo {
+            capabilities: vec![DeviceCapability::Scroll; 1000],
+            ..device.clone()
+        };
+        assert!(repeated.validate().is_err());
+        let too_many_keys = DeviceInfo {
+            keys: vec![KeyCode::KeyA; MAX_DEVICE_KEYS + 1],
+            ..device
+        };
+        assert!(too_many_keys.validate().is_err());
+    }
+
     #[test]
     fn device_info_multi_capability() {
         let info = DeviceInfo {
             id: DeviceId(1),
             name: "Gaming Mouse".to_string(),
             capabilities: vec![DeviceCapability::RelativeMouse, DeviceCapability::Scroll],
+            keys: Vec::new(),
         };
         let config = bincode::config::standard();
         let bytes = bincode::encode_to_vec(&info, config).unwrap();
@@ -0,0 +1,229 @@
+//! Machine-readable error codes.
+//!
+//! Every error the daemon can report carries an [`ErrorCode`], so the CLI,
+//! hooks and monitoring can branch on the cause without parsing messages.
+//! Numeric codes and names are stable: codes are never renumbered or
+//! reused, and new ones are appended. Numbers are grouped by subsystem:
+//!
+//! | Range | Subsystem         |
+//! |-------|-------------------|
+//! | 1xx   | Protocol and peers |
+//! | 2xx   | Input             |
+//! | 3xx   | Daemon and IPC    |
+//! | 9xx   | Unclassified      |
+
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+/// Stable identifier for the cause of an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Encode, Decode)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorCode {
+    /// The protocol versions are incompatible.
+    VersionMismatch,
+    /// The peer's certificate is not trusted.
+    Untrusted,
+    /// An `Enter` could not be accepted in the current state.
+    EnterRefused,
+    /// A message arrived that wasn't expected at this point.
+    UnexpectedMessage,
+    /// The connection could not be established or was lost.
+    Connection,
+    /// The handshake failed.
+    Handshake,
+    /// A message could not be encoded or decoded.
+    Serialization,
+    /// A stream ended unexpectedly.
+    StreamClosed,
+    /// TLS setup or verification failed.
+    Tls,
+    /// An input device could not be opened.
+    DeviceOpen,
+    /// An input device could not be grabbed.
+    DeviceGrab,
+    /// A virtual input device could not be created.
+    VirtualDevice,
+    /// An event could not be injected.
+    Inject,
+    /// A barrier does not exist.
+    BarrierNotFound,
+    /// No input backend is available on this platform.
+    InputUnavailable,
+    /// The configuration is invalid or could not be read or written.
+    Config,
+    /// A daemon is already running.
+    AlreadyRunning,
+    /// No daemon is running.
+    NotRunning,
+    /// No peer matches the given name or ID.
+    UnknownPeer,
+    /// The peer is known but not connected.
+    NotConnected,
+    /// The request can't be served as given.
+    InvalidRequest,
+    /// The peer refused the request.
+    PeerRefused,
+    /// The request was replaced by a newer one.
+    Superseded,
+    /// The clipboard backend failed.
+    Clipboard,
+    /// The discovery backend failed.
+    Discovery,
+    /// Anything not covered by a more specific code.
+    Internal,
+}
+
+impl ErrorCode {
+    /// Every code, in declaration order.
+    pub const ALL: &'static [Self] = &[
+        Self::VersionMismatch,
+        Self::Untrusted,
+        Self::EnterRefused,
+        Self::UnexpectedMessage,
+        Self::Connection,
+        Self::Handshake,
+        Self::Serialization,
+        Self::StreamClosed,
+        Self::Tls,
+        Self::DeviceOpen,
+        Self::DeviceGrab,
+        Self::VirtualDevice,
+        Self::Inject,
+        Self::BarrierNotFound,
+        Self::InputUnavailable,
+        Self::Config,
+        Self::AlreadyRunning,
+        Self::NotRunning,
+        Self::UnknownPeer,
+        Self::NotConnected,
+        Self::InvalidRequest,
+        Self::PeerRefused,
+        Self::Superseded,
+        Self::Clipboard,
+        Self::Discovery,
+        Self::Internal,
+    ];
+
+    /// Stable numeric code.
+    #[must_use]
+    pub fn as_u16(self) -> u16 {
+        match self {
+            Self::Connection => 100,
+            Self::Handshake => 101,
+            Self::VersionMismatch => 102,
+            Self::Serialization => 103,
+            Self::StreamClosed => 104,
+            Self::Tls => 105,
+            Self::Untrusted => 106,
+            Self::UnexpectedMessage => 107,
+            Self::EnterRefused => 108,
+            Self::DeviceOpen => 200,
+            Self::DeviceGrab => 201,
+            Self::VirtualDevice => 202,
+            Self::Inject => 203,
+            Self::BarrierNotFound => 204,
+            Self::InputUnavailable => 205,
+            Self::Config => 300,
+            Self::AlreadyRunning => 301,
+            Self::NotRunning => 302,
+            Self::UnknownPeer => 303,
+            Self::NotConnected => 304,
+            Self::InvalidRequest => 305,
+            Self::PeerRefused => 306,
+            Self::Superseded => 307,
+            Self::Clipboard => 308,
+            Self::Discovery => 309,
+            Self::Internal => 900,
+        }
+    }
+
+    /// Stable name, as used in JSON and CLI output.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::VersionMismatch => "version-mismatch",
+            Self::Untrusted => "untrusted",
+            Self::EnterRefused => "enter-refused",
+            Self::UnexpectedMessage => "unexpected-message",
+            Self::Connection => "connection",
+            Self::Handshake => "handshake",
+            Self::Serialization => "serialization",
+            Self::StreamClosed => "stream-closed",
+            Self::Tls => "tls",
+            Self::DeviceOpen => "device-open",
+            Self::DeviceGrab => "device-grab",
+            Self::VirtualDevice => "virtual-device",
+            Self::Inject => "inject",
+            Self::BarrierNotFound => "barrier-not-found",
+            Self::InputUnavailable => "input-unavailable",
+            Self::Config => "config",
+            Self::AlreadyRunning => "already-running",
+            Self::NotRunning => "not-running",
+            Self::UnknownPeer => "unknown-peer",
+            Self::NotConnected => "not-connected",
+            Self::InvalidRequest => "invalid-request",
+            Self::PeerRefused => "peer-refused",
+            Self::Superseded => "superseded",
+            Self::Clipboard => "clipboard",
+            Self::Discovery => "discovery",
+            Self::Internal => "internal",
+        }
+    }
+
+    /// Look up a code by number.
+    #[must_use]
+    pub fn from_u16(code: u16) -> Option<Self> {
+        Self::ALL.iter().copied().find(|c| c.as_u16() == code)
+    }
+
+    /// Look up a code by name.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|c| c.as_str() == name)
+    }
+
+    /// Whether a peer that reports this code closes the connection after it.
+    #[must_use]
+    pub fn is_fatal(self) -> bool {
+        !matches!(self, Self::EnterRefused)
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.as_str(), self.as_u16())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn codes_and_names_are_unique_and_round_trip() {
+        let mut numbers = HashSet::new();
+        let mut names = HashSet::new();
+        for &code in ErrorCode::ALL {
+            assert!(
+                numbers.insert(code.as_u16()),
+                "duplicate number for {code:?}"
+            );
+            assert!(names.insert(code.as_str()), "duplicate name for {code:?}");
+            assert_eq!(ErrorCode::from_u16(code.as_u16()), Some(code));
+            assert_eq!(ErrorCode::from_name(code.as_str()), Some(code));
+        }
+        assert_eq!(ErrorCode::from_u16(1), None);
+    }
+
+    #[test]
+    fn serde_uses_the_stable_name() {
+        let json = serde_json::to_string(&ErrorCode::VersionMismatch).unwrap();
+        assert_eq!(json, "\"version-mismatch\"");
+    }
+
+    #[test]
+    fn display_shows_name_and_number() {
+        assert_eq!(ErrorCode::UnknownPeer.to_string(), "unknown-peer (303)");
+    }
+}
@@ -186,6 +186,61 @@ pub enum KeyCode {
     Unknown(u32),
 }
 
+impl KeyCode {
+    /// The key that types `c` on a US layout, and whether Shift is held for
+    /// it. `None` if no key on that layout types `c`.
+    pub fn for_char(c: char) -> Option<(KeyCode, bool)> {
+        use KeyCode::{
+            Backquote, Backslash, BracketLeft, BracketRight, Comma, Digit0, Digit1, Digit2, Digit3,
+            Digit4, Digit5, Digit6, Digit7, Digit8, Digit9, Enter, Equal, KeyA, KeyB, KeyC, KeyD,
+            KeyE, KeyF, KeyG, KeyH, KeyI, KeyJ, KeyK, KeyL, KeyM, KeyN, KeyO, KeyP, KeyQ, KeyR,
+            KeyS, KeyT, KeyU, KeyV, KeyW, KeyX, KeyY, KeyZ, Minus, Period, Quote, Semicolon, Slash,
+            Space, Tab,
+        };
+        const LETTERS: [KeyCode; 26] = [
+            KeyA, KeyB, KeyC, KeyD, KeyE, KeyF, KeyG, KeyH, KeyI, KeyJ, KeyK, KeyL, KeyM, KeyN,
+            KeyO, KeyP, KeyQ, KeyR, KeyS, KeyT, KeyU, KeyV, KeyW, KeyX, KeyY, KeyZ,
+        ];
+        const DIGITS: [KeyCode; 10] = [
+            Digit0, Digit1, Digit2, Digit3, Digit4, Digit5, Digit6, Digit7, Digit8, Digit9,
+        ];
+        const SHIFTED_DIGITS: &str = ")!@#$%^&*(";
+        const PUNCTUATION: [(char, char, KeyCode); 11] = [
+            ('-', '_', Minus),
+            ('=', '+', Equal),
+            ('[', '{', BracketLeft),
+            (']', '}', BracketRight),
+            ('\\', '|', Backslash),
+            (';', ':', Semicolon),
+            ('\'', '"', Quote),
+            ('`', '~', Backquote),
+            (',', '<', Comma),
+            ('.', '>', Period),
+            ('/', '?', Slash),
+        ];
+
+        let key = match c {
+            'a'..='z' => (LETTERS[c as usize - 'a' as usize], false),
+            'A'..='Z' => (LETTERS[c as usize - 'A' as usize], true),
+            '0'..='9' => (DIGITS[c as usize - '0' as usize], false),
+            ' ' => (Space, false),
+            '\n' => (Enter, false),
+            '\t' => (Tab, false),
+            _ => {
+                if let Some(digit) = SHIFTED_DIGITS.find(c) {
+                    (DIGITS[digit], true)
+                } else {
+                    let &(plain, _, code) = PUNCTUATION
+                        .iter()
+                        .find(|(plain, shifted, _)| c == *plain || c == *shifted)?;
+                    (code, c != plain)
+                }
+            }
+        };
+        Some(key)
+    }
+}
+
 /// Mouse button identifier.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Encode, Decode)]
 pub enum MouseButton {
@@ -218,6 +273,18 @@ pub enum ScrollDirection {
 mod tests {
     use super::*;
 
+    #[test]
+    fn keys_for_us_layout_characters() {
+        assert_eq!(KeyCode::for_char('q'), Some((KeyCode::KeyQ, false)));
+        assert_eq!(KeyCode::for_char('Z'), Some((KeyCode::KeyZ, true)));
+        assert_eq!(KeyCode::for_char('7'), Some((KeyCode::Digit7, false)));
+        assert_eq!(KeyCode::for_char('('), Some((KeyCode::Digit9, true)));
+        assert_eq!(KeyCode::for_char('/'), Some((KeyCode::Slash, false)));
+        assert_eq!(KeyCode::for_char('"'), Some((KeyCode::Quote, true)));
+        assert_eq!(KeyCode::for_char('\n'), Some((KeyCode::Enter, false)));
+        assert_eq!(KeyCode::for_char('é'), None);
+    }
+
     #[test]
     fn input_event_key_roundtrip() {
         let event = InputEvent::Key {
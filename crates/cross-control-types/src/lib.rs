@@ -2,22 +2,29 @@
 //!
 //! This crate contains all types shared across the cross-control workspace:
 //! input events, device descriptors, screen geometry, machine identity,
-//! barrier definitions, and protocol messages.
+//! barrier definitions, protocol messages, and error codes. It also holds
+//! [`warn_throttled!`], the rate-limited warning used across the workspace.
 
 pub mod clipboard;
 pub mod device;
+pub mod error;
 pub mod event;
 pub mod machine;
 pub mod message;
 pub mod screen;
+pub mod throttle;
 
 pub use clipboard::{ClipboardContent, ClipboardFormat};
 pub use device::{DeviceCapability, DeviceId, DeviceInfo, VirtualDeviceId};
+pub use error::ErrorCode;
 pub use event::{
     ButtonState, CapturedEvent, InputEvent, KeyCode, MouseButton, ScrollAxis, ScrollDirection,
 };
-pub use machine::MachineId;
+pub use machine::{KeyboardLayout, MachineId, PowerState};
 pub use message::{
-    ClipboardMessage, ControlMessage, InputMessage, Message, ProtocolVersion, PROTOCOL_VERSION,
+    ClipboardMessage, ControlMessage, EnterDeniedReason, InputMessage, Message, ProtocolFeature,
+    ProtocolVersion, SetupMessage, PROTOCOL_VERSION,
+};
+pub use screen::{
+    Barrier, BarrierId, FocusHint, Position, ScreenEdge, ScreenGeometry, ScreenState,
 };
-pub use screen::{Barrier, BarrierId, Position, ScreenEdge, ScreenGeometry};
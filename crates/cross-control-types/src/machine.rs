@@ -42,10 +42,69 @@ impl std::fmt::Display for MachineId {
     }
 }
 
+/// Power-source hint exchanged between peers.
+///
+/// Peers on battery get less frequent keepalives and coalesced pointer
+/// motion to save power.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
+pub struct PowerState {
+    /// Running on battery rather than mains power.
+    pub on_battery: bool,
+    /// Remaining battery charge in percent, if the machine has a battery.
+    pub battery_percent: Option<u8>,
+}
+
+impl PowerState {
+    /// Whether the machine is on battery with charge at or below `threshold` percent.
+    #[must_use]
+    pub fn is_low(&self, threshold: u8) -> bool {
+        self.on_battery && self.battery_percent.is_some_and(|p| p <= threshold)
+    }
+}
+
+/// The keyboard layout a machine is typing with, as XKB names it (e.g.
+/// `gr`, or `us` with variant `dvorak`).
+///
+/// Sent after the handshake and again whenever the user switches layout,
+/// so peers always know which symbols the key codes they receive stand for.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
+pub struct KeyboardLayout {
+    pub layout: String,
+    /// Empty for the layout's default variant.
+    pub variant: String,
+}
+
+impl std::fmt::Display for KeyboardLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.variant.is_empty() {
+            write!(f, "{}", self.layout)
+        } else {
+            write!(f, "{} ({})", self.layout, self.variant)
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn power_state_low_only_on_battery() {
+        let battery = PowerState {
+            on_battery: true,
+            battery_percent: Some(10),
+        };
+        assert!(battery.is_low(15));
+        assert!(!battery.is_low(5));
+
+        let charging = PowerState {
+            on_battery: false,
+            ..battery
+        };
+        assert!(!charging.is_low(15));
+        assert!(!PowerState::default().is_low(15));
+    }
+
     #[test]
     fn machine_id_unique() {
         let a = MachineId::new();
@@ -63,6 +122,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::needless_borrows_for_generic_args)]
     fn machine_id_bincode_roundtrip() {
         let id = MachineId::new();
         let config = bincode::config::standard();
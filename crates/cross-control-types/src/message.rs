@@ -7,12 +7,16 @@ use serde::{Deserialize, Serialize};
 
 use crate::clipboard::{ClipboardContent, ClipboardFormat};
 use crate::device::{DeviceId, DeviceInfo};
+use crate::error::ErrorCode;
 use crate::event::InputEvent;
-use crate::machine::MachineId;
-use crate::screen::{ScreenEdge, ScreenGeometry};
+use crate::machine::{KeyboardLayout, MachineId, PowerState};
+use crate::screen::{FocusHint, Position, ScreenEdge, ScreenGeometry};
 
 /// Current protocol version.
-pub const PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion { major: 0, minor: 1 };
+pub const PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion {
+    major: 0,
+    minor: 10,
+};
 
 /// Protocol version for compatibility negotiation.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
@@ -21,12 +25,100 @@ pub struct ProtocolVersion {
     pub minor: u16,
 }
 
+impl ProtocolVersion {
+    /// Whether a peer speaking this version understands `feature`.
+    #[must_use]
+    pub fn supports(self, feature: ProtocolFeature) -> bool {
+        let since = feature.since();
+        self.major > since.major || (self.major == since.major && self.minor >= since.minor)
+    }
+
+    /// Features `self` supports that a peer speaking `peer` does not, so
+    /// they are unavailable in a session with it.
+    #[must_use]
+    pub fn missing_from(self, peer: ProtocolVersion) -> Vec<ProtocolFeature> {
+        ProtocolFeature::ALL
+            .iter()
+            .copied()
+            .filter(|feature| self.supports(*feature) && !peer.supports(*feature))
+            .collect()
+    }
+}
+
 impl std::fmt::Display for ProtocolVersion {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}.{}", self.major, self.minor)
     }
 }
 
+/// Protocol features added in a minor version. Peers with different minor
+/// versions still talk, but the older one skips messages for features it
+/// predates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolFeature {
+    /// Per-session message size limits (`Limits`).
+    MessageLimits,
+    /// Allowlisted remote commands (`RunCommand`).
+    RunCommand,
+    /// Cursor and screen state hints to the controller (`FocusHint`).
+    FocusHint,
+    /// Swapping who drives (`TakeControl`).
+    TakeControl,
+    /// Asking a peer for control (`RequestControl`).
+    RequestControl,
+    /// Reporting the software version (`BuildInfo`).
+    BuildInfo,
+    /// Reporting the active keyboard layout (`KeyboardLayout`).
+    KeyboardLayout,
+    /// Telling the controller its user is not alone (`LocalInput`).
+    LocalInput,
+}
+
+impl ProtocolFeature {
+    /// Every feature, oldest first.
+    pub const ALL: &'static [Self] = &[
+        Self::MessageLimits,
+        Self::RunCommand,
+        Self::FocusHint,
+        Self::TakeControl,
+        Self::RequestControl,
+        Self::BuildInfo,
+        Self::KeyboardLayout,
+        Self::LocalInput,
+    ];
+
+    /// The first protocol version with this feature.
+    #[must_use]
+    pub fn since(self) -> ProtocolVersion {
+        let minor = match self {
+            Self::MessageLimits => 3,
+            Self::RunCommand => 4,
+            Self::FocusHint => 5,
+            Self::TakeControl => 6,
+            Self::RequestControl => 7,
+            Self::BuildInfo => 8,
+            Self::KeyboardLayout => 9,
+            Self::LocalInput => 10,
+        };
+        ProtocolVersion { major: 0, minor }
+    }
+
+    /// What the feature is, for status output.
+    #[must_use]
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::MessageLimits => "message size limits",
+            Self::RunCommand => "remote commands",
+            Self::FocusHint => "focus hints",
+            Self::TakeControl => "swap hotkey",
+            Self::RequestControl => "control requests",
+            Self::BuildInfo => "version reporting",
+            Self::KeyboardLayout => "keyboard layout reporting",
+            Self::LocalInput => "local input notices",
+        }
+    }
+}
+
 /// Top-level message envelope.
 #[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
 pub enum Message {
@@ -74,6 +166,9 @@ pub enum ControlMessage {
     /// Acknowledge an Enter; remote is ready to receive input.
     EnterAck,
 
+    /// Refuse an Enter; the cursor stays on the sender's screen.
+    EnterDenied { reason: EnterDeniedReason },
+
     /// Cursor is returning to the local machine.
     Leave {
         /// Which edge the cursor is entering on.
@@ -82,6 +177,29 @@ pub enum ControlMessage {
         position: u32,
     },
 
+    /// The sender's power source changed (also sent once after the handshake).
+    PowerState(PowerState),
+
+    /// The sender is about to sleep; treat it as unavailable until `Resumed`.
+    Suspending,
+
+    /// The sender woke from sleep.
+    Resumed,
+
+    /// Clipboard synchronisation, carried on the control stream.
+    Clipboard(ClipboardMessage),
+
+    /// Replace the receiver's `[[screens]]` and `[[screen_adjacency]]` with
+    /// the given TOML. Only honoured from trusted peers when the receiver
+    /// sets `allow_remote_config`.
+    ConfigPush { layout: String },
+
+    /// Reply to `ConfigPush`: `None` if applied, otherwise why not.
+    ConfigPushResult { error: Option<String> },
+
+    /// Guided setup between two machines (`cross-control setup`).
+    Setup(SetupMessage),
+
     /// Keepalive ping.
     Ping {
         /// Sequence number for RTT measurement.
@@ -96,6 +214,88 @@ pub enum ControlMessage {
 
     /// Graceful disconnect.
     Bye,
+
+    /// The sender rejected something the receiver sent. Sent before the
+    /// sender closes the connection when `code` is fatal, so the reason
+    /// shows up on both machines.
+    Error { code: ErrorCode, detail: String },
+
+    /// The largest messages, in bytes, the sender accepts on the control and
+    /// input streams. Sent once after the handshake; until it arrives, 1 MiB
+    /// is assumed for both.
+    Limits { control: u32, input: u32 },
+
+    /// Ask the receiver to run a command by name. The receiver only runs
+    /// commands its config allows the sender to run.
+    RunCommand { name: String },
+
+    /// Reply to `RunCommand`: `None` if the command was started, otherwise
+    /// why not.
+    CommandResult { name: String, error: Option<String> },
+
+    /// Sent by a controlled machine to its controller whenever its cursor
+    /// or screen state changed since the last hint.
+    FocusHint(FocusHint),
+
+    /// Sent by a controller to the machine it controls: take control of
+    /// the sender, so the receiver's keyboard and mouse drive both.
+    TakeControl,
+
+    /// Asks the receiver to let the sender take control of it, e.g. because
+    /// the sender's user wants their own keyboard to drive. Answered with
+    /// `ControlRequestResult`.
+    RequestControl,
+
+    /// Reply to `RequestControl`: `None` if the receiver gave up control of
+    /// any peer and awaits the sender's `Enter`, otherwise why not.
+    ControlRequestResult { error: Option<String> },
+
+    /// The sender's software version and the commit it was built from.
+    /// Sent once after the handshake, so version skew can be reported.
+    BuildInfo { version: String, commit: String },
+
+    /// The keyboard layout the sender types with. Sent after the handshake
+    /// and whenever it changes.
+    KeyboardLayout(KeyboardLayout),
+
+    /// Sent by a controlled machine when someone starts (`active`) or stops
+    /// using its own keyboard or pointer while the receiver controls it.
+    LocalInput { active: bool },
+}
+
+/// Guided setup messages, exchanged before either machine has a config
+/// entry for the other.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
+pub enum SetupMessage {
+    /// The counterpart's half of the layout: where the sender's screen sits
+    /// relative to the receiver, and the port the sender's daemon listens on.
+    Layout { position: Position, port: u16 },
+    /// The receiver confirmed the session code and saved the layout.
+    Accepted,
+    /// The receiver declined; neither side should save anything.
+    Declined,
+}
+
+/// Why a peer refused to be controlled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
+pub enum EnterDeniedReason {
+    /// The input emulation backend (e.g. uinput) is unavailable, so injected
+    /// input would be silently dropped.
+    NoEmulation,
+    /// The controller is configured as a viewer on the remote: the session
+    /// carries clipboard and presence only.
+    Viewer,
+    /// The remote has paused cross-control; it may accept a later Enter.
+    Paused,
+}
+
+impl EnterDeniedReason {
+    /// Whether the refusal may clear without reconnecting, so later barrier
+    /// crossings should still try to Enter.
+    #[must_use]
+    pub fn is_transient(self) -> bool {
+        matches!(self, Self::Paused)
+    }
 }
 
 /// Input data messages (unidirectional, controller -> controlled).
@@ -164,6 +364,7 @@ mod tests {
             id: DeviceId(1),
             name: "Keyboard".to_string(),
             capabilities: vec![DeviceCapability::Keyboard],
+            keys: Vec::new(),
         }));
         let _decoded = bincode_roundtrip(&msg);
     }
@@ -183,6 +384,19 @@ mod tests {
         let _decoded = bincode_roundtrip(&leave);
     }
 
+    #[test]
+    fn enter_denied_roundtrip() {
+        let msg = Message::Control(ControlMessage::EnterDenied {
+            reason: EnterDeniedReason::NoEmulation,
+        });
+        match bincode_roundtrip(&msg) {
+            Message::Control(ControlMessage::EnterDenied { reason }) => {
+                assert_eq!(reason, EnterDeniedReason::NoEmulation);
+            }
+            other => panic!("unexpected message: {other:?}"),
+        }
+    }
+
     #[test]
     fn input_message_roundtrip() {
         use crate::event::{ButtonState, KeyCode};
@@ -221,6 +435,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::similar_names)]
     fn ping_pong_roundtrip() {
         let ping = Message::Control(ControlMessage::Ping { seq: 42 });
         let _decoded = bincode_roundtrip(&ping);
@@ -235,8 +450,45 @@ mod tests {
         let _decoded = bincode_roundtrip(&msg);
     }
 
+    #[test]
+    fn error_roundtrip() {
+        let msg = Message::Control(ControlMessage::Error {
+            code: ErrorCode::VersionMismatch,
+            detail: "desk speaks protocol 0.1".to_string(),
+        });
+        match bincode_roundtrip(&msg) {
+            Message::Control(ControlMessage::Error { code, detail }) => {
+                assert_eq!(code, ErrorCode::VersionMismatch);
+                assert_eq!(detail, "desk speaks protocol 0.1");
+            }
+            other => panic!("unexpected message: {other:?}"),
+        }
+    }
+
     #[test]
     fn protocol_version_display() {
-        assert_eq!(PROTOCOL_VERSION.to_string(), "0.1");
+        assert_eq!(PROTOCOL_VERSION.to_string(), "0.10");
+    }
+
+    #[test]
+    fn features_missing_from_older_peer() {
+        let older = ProtocolVersion { major: 0, minor: 5 };
+        assert!(older.supports(ProtocolFeature::FocusHint));
+        assert!(!older.supports(ProtocolFeature::TakeControl));
+        assert_eq!(
+            PROTOCOL_VERSION.missing_from(older),
+            vec![
+                ProtocolFeature::TakeControl,
+                ProtocolFeature::RequestControl,
+                ProtocolFeature::BuildInfo,
+                ProtocolFeature::KeyboardLayout,
+                ProtocolFeature::LocalInput,
+            ]
+        );
+        assert!(older.missing_from(PROTOCOL_VERSION).is_empty());
+        assert!(PROTOCOL_VERSION.missing_from(PROTOCOL_VERSION).is_empty());
+        for feature in ProtocolFeature::ALL {
+            assert!(PROTOCOL_VERSION.supports(*feature), "{feature:?}");
+        }
     }
 }
@@ -3,6 +3,43 @@
 use bincode::{Decode, Encode};
 use serde::{Deserialize, Serialize};
 
+/// Whether a machine's screen can show what is typed into it.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, Encode, Decode,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScreenState {
+    /// Unlocked with the display on.
+    #[default]
+    Active,
+    /// The session is locked; input goes to the lock screen.
+    Locked,
+    /// Every display is off (power-saving sleep).
+    Asleep,
+}
+
+impl ScreenState {
+    /// Lowercase name, as used in config and logs.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Active => "active",
+            Self::Locked => "locked",
+            Self::Asleep => "asleep",
+        }
+    }
+}
+
+/// Where a controlled machine's input is landing, hinted to its controller
+/// so it doesn't type blind.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
+pub struct FocusHint {
+    /// Cursor position after the injected input, in pixels.
+    pub cursor_x: i32,
+    pub cursor_y: i32,
+    pub screen: ScreenState,
+}
+
 /// Screen geometry for a machine's display.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
 pub struct ScreenGeometry {
@@ -14,6 +51,11 @@ pub struct ScreenGeometry {
     pub x: i32,
     /// Y offset for multi-monitor setups.
     pub y: i32,
+    /// Display scale factor in percent (100 = 1x, 200 = 2x for a high-DPI panel).
+    ///
+    /// Edge positions exchanged between peers are in logical pixels, i.e.
+    /// physical pixels divided by this factor.
+    pub scale: u32,
 }
 
 impl ScreenGeometry {
@@ -24,9 +66,31 @@ impl ScreenGeometry {
             height,
             x: 0,
             y: 0,
+            scale: 100,
         }
     }
 
+    /// Set the display scale factor (in percent).
+    #[must_use]
+    pub fn with_scale(mut self, scale: u32) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Convert a physical pixel coordinate to logical pixels.
+    #[must_use]
+    pub fn to_logical(&self, physical: u32) -> u32 {
+        let scale = u64::from(self.scale.max(1));
+        u32::try_from(u64::from(physical) * 100 / scale).unwrap_or(u32::MAX)
+    }
+
+    /// Convert a logical pixel coordinate to physical pixels.
+    #[must_use]
+    pub fn to_physical(&self, logical: u32) -> u32 {
+        let scale = u64::from(self.scale.max(1));
+        u32::try_from(u64::from(logical) * scale / 100).unwrap_or(u32::MAX)
+    }
+
     /// Check whether a pixel coordinate is on a given screen edge.
     #[must_use]
     pub fn is_at_edge(&self, px: i32, py: i32, edge: ScreenEdge) -> bool {
@@ -106,6 +170,28 @@ impl Position {
         }
     }
 
+    /// The position whose local edge is `edge`.
+    #[must_use]
+    pub fn from_local_edge(edge: ScreenEdge) -> Self {
+        match edge {
+            ScreenEdge::Left => Self::Left,
+            ScreenEdge::Right => Self::Right,
+            ScreenEdge::Top => Self::Above,
+            ScreenEdge::Bottom => Self::Below,
+        }
+    }
+
+    /// Where the local screen sits as seen from a screen at this position.
+    #[must_use]
+    pub fn opposite(&self) -> Self {
+        match self {
+            Self::Left => Self::Right,
+            Self::Right => Self::Left,
+            Self::Above => Self::Below,
+            Self::Below => Self::Above,
+        }
+    }
+
     /// The screen edge on the remote machine where the cursor enters.
     #[must_use]
     pub fn remote_entry_edge(&self) -> ScreenEdge {
@@ -129,6 +215,7 @@ mod tests {
             height: 1080,
             x: 0,
             y: 0,
+            scale: 200,
         };
         let config = bincode::config::standard();
         let bytes = bincode::encode_to_vec(&geo, config).unwrap();
@@ -136,6 +223,21 @@ mod tests {
         assert_eq!(geo, decoded);
     }
 
+    #[test]
+    fn logical_physical_conversion() {
+        let hidpi = ScreenGeometry::new(2880, 1800).with_scale(200);
+        assert_eq!(hidpi.to_logical(1800), 900);
+        assert_eq!(hidpi.to_physical(900), 1800);
+
+        let standard = ScreenGeometry::new(1920, 1080);
+        assert_eq!(standard.to_logical(540), 540);
+        assert_eq!(standard.to_physical(540), 540);
+
+        let fractional = ScreenGeometry::new(2560, 1440).with_scale(125);
+        assert_eq!(fractional.to_logical(1000), 800);
+        assert_eq!(fractional.to_physical(800), 1000);
+    }
+
     #[test]
     fn is_at_edge_left() {
         let geo = ScreenGeometry::new(1920, 1080);
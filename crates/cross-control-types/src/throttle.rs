@@ -0,0 +1,150 @@
+//! Rate limiting for log messages that can repeat once per event.
+//!
+//! Some failures (a broken uinput device, a peer sending input out of turn)
+//! happen for every input event until they are fixed, and logging each one
+//! floods the journal. [`warn_throttled!`](crate::warn_throttled) logs the
+//! first few occurrences from a call site in each window, drops the rest,
+//! and reports how many it dropped the next time it logs. A burst that
+//! stops is reported too: the daemon logs [`take_suppressed`] on its tick.
+
+use std::sync::{Mutex, MutexGuard, Once, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Messages logged per call site in each window before suppression starts.
+pub const BURST: u32 = 5;
+
+/// Length of a throttling window.
+pub const WINDOW: Duration = Duration::from_secs(10);
+
+/// Every throttle that has been checked, for [`take_suppressed`].
+static THROTTLES: Mutex<Vec<&'static LogThrottle>> = Mutex::new(Vec::new());
+
+/// Throttling state for one call site.
+pub struct LogThrottle {
+    /// Where the throttled message is logged from, e.g. `crate::module:42`.
+    site: &'static str,
+    registered: Once,
+    state: Mutex<State>,
+}
+
+struct State {
+    window_start: Option<Instant>,
+    logged: u32,
+    suppressed: u64,
+}
+
+impl LogThrottle {
+    pub const fn new(site: &'static str) -> Self {
+        Self {
+            site,
+            registered: Once::new(),
+            state: Mutex::new(State {
+                window_start: None,
+                logged: 0,
+                suppressed: 0,
+            }),
+        }
+    }
+
+    /// Whether to log this occurrence. `Some(n)` means log it, after
+    /// reporting the `n` occurrences suppressed since the last one logged.
+    pub fn check(&'static self) -> Option<u64> {
+        self.registered.call_once(|| lock(&THROTTLES).push(self));
+        self.check_at(Instant::now())
+    }
+
+    fn check_at(&self, now: Instant) -> Option<u64> {
+        let mut state = lock(&self.state);
+        let expired = state
+            .window_start
+            .map_or(true, |start| now.duration_since(start) >= WINDOW);
+        if expired {
+            state.window_start = Some(now);
+            state.logged = 0;
+        }
+        if state.logged >= BURST {
+            state.suppressed += 1;
+            return None;
+        }
+        state.logged += 1;
+        Some(std::mem::take(&mut state.suppressed))
+    }
+}
+
+/// Take the counts suppressed at call sites whose window has ended, so a
+/// burst is reported even if the message never fires again. Returns each
+/// call site with its count.
+pub fn take_suppressed() -> Vec<(&'static str, u64)> {
+    take_suppressed_at(Instant::now())
+}
+
+fn take_suppressed_at(now: Instant) -> Vec<(&'static str, u64)> {
+    let throttles = lock(&THROTTLES).clone();
+    throttles
+        .into_iter()
+        .filter_map(|throttle| {
+            let mut state = lock(&throttle.state);
+            let ended = state
+                .window_start
+                .is_some_and(|start| now.duration_since(start) >= WINDOW);
+            (ended && state.suppressed > 0)
+                .then(|| (throttle.site, std::mem::take(&mut state.suppressed)))
+        })
+        .collect()
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Like `tracing::warn!`, but throttled per call site: at most
+/// [`BURST`](crate::throttle::BURST) messages per
+/// [`WINDOW`](crate::throttle::WINDOW), with a count of the messages
+/// suppressed in between. The calling crate must depend on `tracing`.
+#[macro_export]
+macro_rules! warn_throttled {
+    ($($arg:tt)+) => {{
+        static THROTTLE: $crate::throttle::LogThrottle =
+            $crate::throttle::LogThrottle::new(concat!(module_path!(), ":", line!()));
+        if let Some(suppressed) = THROTTLE.check() {
+            if suppressed > 0 {
+                ::tracing::warn!(suppressed, "suppressed {suppressed} similar messages");
+            }
+            ::tracing::warn!($($arg)+);
+        }
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn burst_then_suppress_then_summarise() {
+        let throttle = LogThrottle::new("test");
+        let start = Instant::now();
+        for _ in 0..BURST {
+            assert_eq!(throttle.check_at(start), Some(0));
+        }
+        for _ in 0..12_441 {
+            assert_eq!(throttle.check_at(start + WINDOW / 2), None);
+        }
+        assert_eq!(throttle.check_at(start + WINDOW), Some(12_441));
+        assert_eq!(throttle.check_at(start + WINDOW), Some(0));
+    }
+
+    #[test]
+    fn ended_bursts_are_taken_once() {
+        static THROTTLE: LogThrottle = LogThrottle::new("burst");
+        for _ in 0..BURST + 3 {
+            THROTTLE.check();
+        }
+        let later = Instant::now() + WINDOW;
+        assert!(!take_suppressed_at(Instant::now()).contains(&("burst", 3)));
+        assert!(take_suppressed_at(later).contains(&("burst", 3)));
+        assert!(!take_suppressed_at(later)
+            .iter()
+            .any(|(site, _)| *site == "burst"));
+        assert_eq!(THROTTLE.check_at(later), Some(0));
+    }
+}
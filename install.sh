@@ -107,7 +107,7 @@ Description=cross-control virtual KVM daemon
 After=network.target
 
 [Service]
-Type=simple
+Type=notify
 ExecStart=${install_dir}/${BIN_NAME} start
 Restart=on-failure
 RestartSec=5